@@ -25,13 +25,18 @@ pub use proto::file::node::load_node_from_file;
 pub use proto::file::relay::load_relay_from_file;
 pub use proto::file::ser_string;
 
-pub use proto::app_server::messages::{AppPermissions, NamedRelayAddress, RelayAddress};
-pub use proto::funder::messages::Receipt;
+pub use proto::app_server::messages::{
+    AppPermissions, ChannelAddress, NamedRelayAddress, RelayAddress,
+};
+pub use proto::funder::messages::{
+    Currency, CurrencyBalance, DebtPolicy, FreezePolicy, Rate, Receipt, TokenBalance,
+};
 pub use proto::funder::signature_buff::verify_receipt;
 pub use proto::index_server::messages::NamedIndexServerAddress;
+pub use proto::net::messages::NetAddress;
 pub use proto::report::signature_buff::verify_move_token_hashed_report;
 
-pub use node::connect::{AppConfig, AppReport, AppRoutes, AppSendFunds, NodeConnection};
+pub use node::connect::{AppConfig, AppHistory, AppReport, AppRoutes, AppSendFunds, NodeConnection};
 
 pub use self::connect::{connect, ConnectError};
 pub use self::identity::{identity_from_file, IdentityFromFileError};
@@ -40,10 +45,11 @@ pub use self::identity::{identity_from_file, IdentityFromFileError};
 pub mod report {
     pub use proto::report::messages::{
         AddFriendReport, ChannelInconsistentReport, ChannelStatusReport, DirectionReport,
-        FriendLivenessReport, FriendReport, FriendReportMutation, FriendStatusReport, FunderReport,
-        FunderReportMutateError, FunderReportMutation, FunderReportMutations, McBalanceReport,
-        McRequestsStatusReport, MoveTokenHashedReport, RequestsStatusReport, ResetTermsReport,
-        SentLocalRelaysReport, TcReport,
+        FriendLivenessReport, FriendReport, FriendReportMutation, FriendStatusReport,
+        FrozenCreditsReport, FunderReport, FunderReportMutateError, FunderReportMutation,
+        FunderReportMutations, HeldPaymentReport, McBalanceReport, McRequestsStatusReport,
+        MoveTokenHashedReport, RequestsStatusReport, ResetTermsReport, SentLocalRelaysReport,
+        SettleStatusReport, TcReport,
     };
 
     pub use proto::app_server::messages::{NodeReport, NodeReportMutation};
@@ -52,11 +58,17 @@ pub mod report {
     };
 }
 
+pub mod history {
+    pub use proto::funder::messages::{PaymentDirection, PaymentRecord, PaymentStatus};
+}
+
 pub mod invoice {
     pub use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+    pub use proto::funder::messages::HeldPayment;
 }
 
 pub mod route {
+    pub use proto::consts::DEFAULT_MAX_ROUTES;
     pub use proto::funder::messages::FriendsRoute;
     pub use proto::index_server::messages::RouteWithCapacity;
 
@@ -9,20 +9,26 @@ use futures::{future, stream, Sink, SinkExt, Stream, StreamExt};
 use common::conn::ConnPair;
 use common::select_streams::{select_streams, BoxStream};
 // use common::mutable_state::MutableState;
+use crypto::crypto_rand::CryptoRandom;
+use crypto::identity::PublicKey;
 use crypto::uid::Uid;
 
+use proto::consts::DEFAULT_MAX_ROUTES;
 use proto::funder::messages::{
-    FriendStatus, FunderControl, FunderIncomingControl, FunderOutgoingControl, RemoveFriend,
-    RequestsStatus, SetFriendStatus, SetRequestsStatus,
+    Currency, FriendStatus, FriendsRoute, FunderControl, FunderIncomingControl,
+    FunderOutgoingControl, RemoveFriend, RequestsStatus, ResponseReceived, ResponseSendFundsResult,
+    SetFriendStatus, SetRequestsStatus, UserRequestSendFunds,
 };
-use proto::report::convert::funder_report_mutation_to_index_mutation;
+use proto::report::convert::{calc_friend_capacities, funder_report_mutation_to_index_mutation};
+use proto::report::messages::FunderReport;
 
 use proto::app_server::messages::{
     AppPermissions, AppRequest, AppServerToApp, AppToAppServer, NodeReport, NodeReportMutation,
-    ReportMutations,
+    ReportMutations, RequestRebalance,
 };
 use proto::index_client::messages::{
-    AppServerToIndexClient, IndexClientRequest, IndexClientToAppServer,
+    AppServerToIndexClient, IndexClientRequest, IndexClientToAppServer, RequestRoutes,
+    RequestRoutesMode, ResponseRoutesResult,
 };
 
 pub type IncomingAppConnection<B> = (
@@ -56,6 +62,12 @@ pub struct App<B: Clone> {
     opt_sender: Option<mpsc::Sender<AppServerToApp<B>>>,
     open_route_requests: HashSet<Uid>,
     open_send_funds_requests: HashSet<Uid>,
+    open_payment_history_requests: HashSet<Uid>,
+    /// Rebalance requests that are waiting for a route, together with the app request id that
+    /// issued them. Keyed by the request_id of the routes request.
+    open_rebalance_requests: HashMap<Uid, (Uid, RequestRebalance)>,
+    /// Payments sent for rebalance requests: payment request_id -> rebalance request_id
+    open_rebalance_payments: HashMap<Uid, Uid>,
 }
 
 impl<B> App<B>
@@ -68,6 +80,9 @@ where
             opt_sender: Some(sender),
             open_route_requests: HashSet::new(),
             open_send_funds_requests: HashSet::new(),
+            open_payment_history_requests: HashSet::new(),
+            open_rebalance_requests: HashMap::new(),
+            open_rebalance_payments: HashMap::new(),
         }
     }
 
@@ -80,7 +95,7 @@ where
     }
 }
 
-pub struct AppServer<B: Clone, TF, TIC, S> {
+pub struct AppServer<B: Clone, TF, TIC, R, S> {
     to_funder: TF,
     to_index_client: TIC,
     from_app_sender: mpsc::Sender<(u128, Option<AppToAppServer<B>>)>,
@@ -91,6 +106,8 @@ pub struct AppServer<B: Clone, TF, TIC, S> {
     /// Required because an app (with one public key) might have multiple connections.
     app_counter: u128,
     apps: HashMap<u128, App<B>>,
+    /// Used to generate request ids for requests we issue on behalf of apps
+    rng: R,
     spawner: S,
 }
 
@@ -99,7 +116,9 @@ fn check_permissions<B>(app_permissions: &AppPermissions, app_request: &AppReque
     match app_request {
         AppRequest::AddRelay(_) => app_permissions.config,
         AppRequest::RemoveRelay(_) => app_permissions.config,
+        AppRequest::SetDirectAddress(_) => app_permissions.config,
         AppRequest::RequestSendFunds(_) => app_permissions.send_funds,
+        AppRequest::CancelRequestSendFunds(_) => app_permissions.send_funds,
         AppRequest::ReceiptAck(_) => app_permissions.send_funds,
         AppRequest::AddFriend(_) => app_permissions.config,
         AppRequest::SetFriendRelays(_) => app_permissions.config,
@@ -110,18 +129,82 @@ fn check_permissions<B>(app_permissions: &AppPermissions, app_request: &AppReque
         AppRequest::OpenFriend(_) => app_permissions.config,
         AppRequest::CloseFriend(_) => app_permissions.config,
         AppRequest::SetFriendRemoteMaxDebt(_) => app_permissions.config,
+        AppRequest::SetFriendRate(_) => app_permissions.config,
+        AppRequest::SetFriendDebtPolicy(_) => app_permissions.config,
+        AppRequest::ClearFriendDebtPolicy(_) => app_permissions.config,
+        AppRequest::SettleFriend(_) => app_permissions.config,
         AppRequest::ResetFriendChannel(_) => app_permissions.config,
+        AppRequest::SetFreezePolicy(_) => app_permissions.config,
+        AppRequest::SetHoldIncomingPayments(_) => app_permissions.config,
+        AppRequest::AcceptHeldPayment(_) => app_permissions.send_funds,
+        AppRequest::RejectHeldPayment(_) => app_permissions.send_funds,
+        // Like the node report, the payment history is available to all apps:
+        AppRequest::RequestPaymentHistory(_) => true,
+        AppRequest::RequestRebalance(_) => app_permissions.send_funds,
         AppRequest::RequestRoutes(_) => app_permissions.routes,
         AppRequest::AddIndexServer(_) => app_permissions.config,
         AppRequest::RemoveIndexServer(_) => app_permissions.config,
     }
 }
 
-impl<B, TF, TIC, S> AppServer<B, TF, TIC, S>
+/// Build a cycle for a rebalance self payment, out of the routes we got from the index client.
+/// The routes we get lead from the out friend to the in friend. We pick the first route that forms
+/// a valid cycle when we add ourselves at both ends.
+fn rebalance_route(
+    local_public_key: &PublicKey,
+    request_rebalance: &RequestRebalance,
+    response_routes_result: &ResponseRoutesResult,
+) -> Option<FriendsRoute> {
+    let routes_with_capacity = match response_routes_result {
+        ResponseRoutesResult::Success(routes_with_capacity) => routes_with_capacity,
+        ResponseRoutesResult::Failure => return None,
+    };
+
+    for route_with_capacity in routes_with_capacity {
+        let inner_keys = &route_with_capacity.route.public_keys;
+        if inner_keys.first() != Some(&request_rebalance.out_friend_public_key)
+            || inner_keys.last() != Some(&request_rebalance.in_friend_public_key)
+        {
+            continue;
+        }
+
+        let mut public_keys = vec![local_public_key.clone()];
+        public_keys.extend(inner_keys.iter().cloned());
+        public_keys.push(local_public_key.clone());
+        let route = FriendsRoute { public_keys };
+
+        // Make sure that we show up only at the two ends of the cycle:
+        if route.is_valid() {
+            return Some(route);
+        }
+    }
+    None
+}
+
+/// Send and receive capacities of our channel with a friend, in a certain currency.
+/// A friend we can not route through has no capacity.
+fn friend_capacities<B>(
+    funder_report: &FunderReport<B>,
+    friend_public_key: &PublicKey,
+    currency: &Currency,
+) -> (u128, u128)
+where
+    B: Clone,
+{
+    funder_report
+        .friends
+        .get(friend_public_key)
+        .and_then(|friend_report| calc_friend_capacities(friend_report).remove(currency))
+        .map(|(send_capacity, recv_capacity, _rate)| (send_capacity, recv_capacity))
+        .unwrap_or((0, 0))
+}
+
+impl<B, TF, TIC, R, S> AppServer<B, TF, TIC, R, S>
 where
     B: Clone + PartialEq + Eq + Debug + Send + Sync + 'static,
     TF: Sink<SinkItem = FunderIncomingControl<B>> + Unpin + Sync + Send,
     TIC: Sink<SinkItem = AppServerToIndexClient<B>> + Unpin,
+    R: CryptoRandom,
     S: Spawn,
 {
     pub fn new(
@@ -129,6 +212,7 @@ where
         to_index_client: TIC,
         from_app_sender: mpsc::Sender<(u128, Option<AppToAppServer<B>>)>,
         node_report: NodeReport<B>,
+        rng: R,
         spawner: S,
     ) -> Self {
         AppServer {
@@ -139,6 +223,7 @@ where
             incoming_connections_closed: false,
             app_counter: 0,
             apps: HashMap::new(),
+            rng,
             spawner,
         }
     }
@@ -211,6 +296,18 @@ where
                             app.send(AppServerToApp::ResponseReceived(response_received.clone()))
                         );
                     }
+                    // A payment sent for a rebalance request is reported using the request_id
+                    // of the rebalance request:
+                    if let Some(rebalance_request_id) = app
+                        .open_rebalance_payments
+                        .remove(&response_received.request_id)
+                    {
+                        let response_received = ResponseReceived {
+                            request_id: rebalance_request_id,
+                            result: response_received.result.clone(),
+                        };
+                        await!(app.send(AppServerToApp::ResponseReceived(response_received)));
+                    }
                 }
             }
             FunderOutgoingControl::ReportMutations(funder_report_mutations) => {
@@ -218,14 +315,10 @@ where
                 for funder_report_mutation in &funder_report_mutations.mutations {
                     // Transform the funder report mutation to index mutations
                     // and send it to IndexClient
-                    let opt_index_mutation = funder_report_mutation_to_index_mutation(
+                    index_mutations.extend(funder_report_mutation_to_index_mutation(
                         &self.node_report.funder_report,
                         funder_report_mutation,
-                    );
-
-                    if let Some(index_mutation) = opt_index_mutation {
-                        index_mutations.push(index_mutation);
-                    }
+                    ));
                 }
 
                 // Send index mutations:
@@ -249,6 +342,27 @@ where
 
                 await!(self.broadcast_node_report_mutations(report_mutations));
             }
+            FunderOutgoingControl::ResponsePaymentHistory(response_payment_history) => {
+                // Find the app that issued the request, and forward the response to this app:
+                for app in self.apps.values_mut() {
+                    if app
+                        .open_payment_history_requests
+                        .remove(&response_payment_history.request_id)
+                    {
+                        await!(app.send(AppServerToApp::ResponsePaymentHistory(
+                            response_payment_history.clone()
+                        )));
+                    }
+                }
+            }
+            FunderOutgoingControl::IncomingPaymentHeld(held_payment) => {
+                // Any app that may send funds may also accept or reject the held payment:
+                for app in self.apps.values_mut() {
+                    if app.permissions.send_funds {
+                        await!(app.send(AppServerToApp::IncomingPaymentHeld(held_payment.clone())));
+                    }
+                }
+            }
         }
         Ok(())
     }
@@ -273,9 +387,60 @@ where
                 await!(self.broadcast_node_report_mutations(report_mutations));
             }
             IndexClientToAppServer::ResponseRoutes(client_response_routes) => {
+                let local_public_key = &self.node_report.funder_report.local_public_key;
                 // We search for the app that issued the request, and send it the response.
                 // TODO: Should we break the loop if we found one originating app?
                 for app in self.apps.values_mut() {
+                    if let Some((app_request_id, request_rebalance)) = app
+                        .open_rebalance_requests
+                        .remove(&client_response_routes.request_id)
+                    {
+                        let opt_route = rebalance_route(
+                            local_public_key,
+                            &request_rebalance,
+                            &client_response_routes.result,
+                        );
+                        let route = match opt_route {
+                            Some(route) => route,
+                            None => {
+                                // No suitable cycle was found:
+                                let response_received = ResponseReceived {
+                                    request_id: request_rebalance.request_id,
+                                    result: ResponseSendFundsResult::Failure(
+                                        local_public_key.clone(),
+                                    ),
+                                };
+                                await!(
+                                    app.send(AppServerToApp::ResponseReceived(response_received))
+                                );
+                                continue;
+                            }
+                        };
+
+                        // Pay ourselves along the cycle. Any fees left unused by the mediators
+                        // will arrive back to us together with the payment.
+                        // The payment gets its own request_id, so that it can not collide with
+                        // the request_id of the routes request:
+                        let user_request_send_funds = UserRequestSendFunds {
+                            request_id: Uid::new(&self.rng),
+                            route,
+                            currency: request_rebalance.currency.clone(),
+                            invoice_id: request_rebalance.invoice_id,
+                            dest_payment: request_rebalance.dest_payment,
+                            total_dest_payment: request_rebalance.dest_payment,
+                            fees: request_rebalance.max_fees,
+                            timeout_ticks: request_rebalance.timeout_ticks,
+                        };
+                        app.open_rebalance_payments.insert(
+                            user_request_send_funds.request_id,
+                            request_rebalance.request_id,
+                        );
+                        await!(self.to_funder.send(FunderIncomingControl::new(
+                            app_request_id,
+                            FunderControl::RequestSendFunds(user_request_send_funds)
+                        )))
+                        .map_err(|_| AppServerError::SendToFunderError)?;
+                    }
                     if app
                         .open_route_requests
                         .remove(&client_response_routes.request_id)
@@ -327,6 +492,13 @@ where
                 FunderIncomingControl::new(app_request_id, FunderControl::RemoveRelay(public_key))
             ))
             .map_err(|_| AppServerError::SendToFunderError),
+            AppRequest::SetDirectAddress(opt_direct_address) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::SetDirectAddress(opt_direct_address)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
             AppRequest::RequestSendFunds(user_request_send_funds) => {
                 // Keep track of which application issued this request:
                 app.open_send_funds_requests
@@ -337,6 +509,21 @@ where
                 )))
                 .map_err(|_| AppServerError::SendToFunderError)
             }
+            AppRequest::CancelRequestSendFunds(request_id) => {
+                // The payment of a rebalance request is cancelled using the request_id of the
+                // rebalance request:
+                let request_id = app
+                    .open_rebalance_payments
+                    .iter()
+                    .find(|(_, rebalance_request_id)| **rebalance_request_id == request_id)
+                    .map(|(payment_request_id, _)| *payment_request_id)
+                    .unwrap_or(request_id);
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::CancelRequestSendFunds(request_id)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
             AppRequest::ReceiptAck(receipt_ack) => await!(self.to_funder.send(
                 FunderIncomingControl::new(app_request_id, FunderControl::ReceiptAck(receipt_ack))
             ))
@@ -418,6 +605,34 @@ where
                 )))
                 .map_err(|_| AppServerError::SendToFunderError)
             }
+            AppRequest::SetFriendRate(set_friend_rate) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::SetFriendRate(set_friend_rate)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::SetFriendDebtPolicy(set_friend_debt_policy) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::SetFriendDebtPolicy(set_friend_debt_policy)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::ClearFriendDebtPolicy(friend_public_key) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::ClearFriendDebtPolicy(friend_public_key)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::SettleFriend(friend_public_key) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::SettleFriend(friend_public_key)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
             AppRequest::ResetFriendChannel(reset_friend_channel) => {
                 await!(self.to_funder.send(FunderIncomingControl::new(
                     app_request_id,
@@ -425,6 +640,130 @@ where
                 )))
                 .map_err(|_| AppServerError::SendToFunderError)
             }
+            AppRequest::SetFreezePolicy(freeze_policy) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::SetFreezePolicy(freeze_policy)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::SetHoldIncomingPayments(hold_incoming_payments) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::SetHoldIncomingPayments(hold_incoming_payments)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::AcceptHeldPayment(invoice_id) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::AcceptHeldPayment(invoice_id)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::RejectHeldPayment(invoice_id) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::RejectHeldPayment(invoice_id)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::RequestPaymentHistory(request_payment_history) => {
+                // Keep track of which application issued this request:
+                app.open_payment_history_requests
+                    .insert(request_payment_history.request_id);
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::RequestPaymentHistory(request_payment_history)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::RequestRebalance(request_rebalance) => {
+                let local_public_key = &self.node_report.funder_report.local_public_key;
+                if request_rebalance.out_friend_public_key == request_rebalance.in_friend_public_key
+                {
+                    // Nothing to move between a friend and itself:
+                    let response_received = ResponseReceived {
+                        request_id: request_rebalance.request_id,
+                        result: ResponseSendFundsResult::Failure(local_public_key.clone()),
+                    };
+                    await!(app.send(AppServerToApp::ResponseReceived(response_received)));
+                    return Ok(());
+                }
+
+                // The routes we get from the index client only cover the way from the out friend
+                // to the in friend. Our own channels are checked here: The first hop carries the
+                // payment together with the fees, and the last hop carries at least the payment.
+                let funder_report = &self.node_report.funder_report;
+                let (first_hop_capacity, _) = friend_capacities(
+                    funder_report,
+                    &request_rebalance.out_friend_public_key,
+                    &request_rebalance.currency,
+                );
+                let (_, last_hop_capacity) = friend_capacities(
+                    funder_report,
+                    &request_rebalance.in_friend_public_key,
+                    &request_rebalance.currency,
+                );
+                let first_hop_payment = request_rebalance
+                    .dest_payment
+                    .saturating_add(request_rebalance.max_fees);
+                let opt_rejected_hop = if first_hop_capacity < first_hop_payment {
+                    Some(("first", first_hop_capacity, first_hop_payment))
+                } else if last_hop_capacity < request_rebalance.dest_payment {
+                    Some(("last", last_hop_capacity, request_rebalance.dest_payment))
+                } else {
+                    None
+                };
+                if let Some((hop, capacity, payment)) = opt_rejected_hop {
+                    warn!(
+                        "Rebalance request {:?} rejected: The {} hop can carry only {} out of {} credits",
+                        request_rebalance.request_id, hop, capacity, payment
+                    );
+                    let response_received = ResponseReceived {
+                        request_id: request_rebalance.request_id,
+                        result: ResponseSendFundsResult::Failure(local_public_key.clone()),
+                    };
+                    await!(app.send(AppServerToApp::ResponseReceived(response_received)));
+                    return Ok(());
+                }
+
+                // We first ask for a route from the out friend to the in friend.
+                // The payment is sent when the route arrives (See handle_from_index_client()).
+                // The mediators will need the payment together with the fees:
+                let request_routes = RequestRoutes {
+                    request_id: Uid::new(&self.rng),
+                    capacity: request_rebalance
+                        .dest_payment
+                        .saturating_add(request_rebalance.max_fees),
+                    source: request_rebalance.out_friend_public_key.clone(),
+                    destination: request_rebalance.in_friend_public_key.clone(),
+                    // The route should not return to us directly from the out friend:
+                    opt_exclude: Some((
+                        request_rebalance.out_friend_public_key.clone(),
+                        local_public_key.clone(),
+                    )),
+                    currency: request_rebalance.currency.clone(),
+                    // Some of the routes might not form a valid cycle:
+                    max_routes: DEFAULT_MAX_ROUTES,
+                    mode: RequestRoutesMode::Routes,
+                    exclude_nodes: Vec::new(),
+                    exclude_edges: Vec::new(),
+                    waypoints: Vec::new(),
+                    opt_max_route_len: None,
+                };
+                app.open_rebalance_requests.insert(
+                    request_routes.request_id,
+                    (app_request_id, request_rebalance),
+                );
+                await!(self
+                    .to_index_client
+                    .send(AppServerToIndexClient::AppRequest((
+                        app_request_id,
+                        IndexClientRequest::RequestRoutes(request_routes)
+                    ))))
+                .map_err(|_| AppServerError::SendToIndexClientError)
+            }
             AppRequest::RequestRoutes(request_routes) => {
                 // Keep track of which application issued this request:
                 app.open_route_requests.insert(request_routes.request_id);
@@ -474,13 +813,14 @@ where
 }
 
 #[allow(unused)]
-pub async fn app_server_loop<B, FF, TF, FIC, TIC, IC, S>(
+pub async fn app_server_loop<B, FF, TF, FIC, TIC, IC, R, S>(
     from_funder: FF,
     to_funder: TF,
     from_index_client: FIC,
     to_index_client: TIC,
     incoming_connections: IC,
     initial_node_report: NodeReport<B>,
+    rng: R,
     mut spawner: S,
 ) -> Result<(), AppServerError>
 where
@@ -490,6 +830,7 @@ where
     FIC: Stream<Item = IndexClientToAppServer<B>> + Unpin + Send,
     TIC: Sink<SinkItem = AppServerToIndexClient<B>> + Unpin,
     IC: Stream<Item = IncomingAppConnection<B>> + Unpin + Send,
+    R: CryptoRandom,
     S: Spawn,
 {
     let (from_app_sender, from_app_receiver) = mpsc::channel(0);
@@ -498,6 +839,7 @@ where
         to_index_client,
         from_app_sender,
         initial_node_report,
+        rng,
         spawner,
     );
 
@@ -0,0 +1,105 @@
+use std::convert::TryFrom;
+
+use futures::channel::mpsc;
+use futures::executor::ThreadPool;
+use futures::task::Spawn;
+use futures::{SinkExt, StreamExt};
+
+use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::app_server::messages::{AppPermissions, AppRequest, AppServerToApp, AppToAppServer};
+use proto::funder::messages::{Currency, FunderControl, FunderOutgoingControl, HeldPayment};
+
+use super::utils::spawn_dummy_app_server;
+
+async fn task_app_server_loop_held_payment<S>(spawner: S)
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let (
+        mut funder_sender,
+        mut funder_receiver,
+        _index_client_sender,
+        _index_client_receiver,
+        mut connections_sender,
+        _initial_node_report,
+    ) = spawn_dummy_app_server(spawner.clone());
+
+    // Connect two apps. Only app1 may send funds:
+    let (mut app_sender0, app_server_receiver) = mpsc::channel(0);
+    let (app_server_sender, mut app_receiver0) = mpsc::channel(0);
+    let app_server_conn_pair = (app_server_sender, app_server_receiver);
+    let app_permissions = AppPermissions {
+        routes: true,
+        send_funds: false,
+        config: true,
+    };
+    await!(connections_sender.send((app_permissions, app_server_conn_pair))).unwrap();
+
+    let (mut app_sender1, app_server_receiver) = mpsc::channel(0);
+    let (app_server_sender, mut app_receiver1) = mpsc::channel(0);
+    let app_server_conn_pair = (app_server_sender, app_server_receiver);
+    let app_permissions = AppPermissions {
+        routes: false,
+        send_funds: true,
+        config: false,
+    };
+    await!(connections_sender.send((app_permissions, app_server_conn_pair))).unwrap();
+
+    // The apps should receive the current node report as the first message:
+    let _to_app_message = await!(app_receiver0.next()).unwrap();
+    let _to_app_message = await!(app_receiver1.next()).unwrap();
+
+    // The Funder holds an incoming payment. Only app1 should be notified:
+    let held_payment = HeldPayment {
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        currency: Currency::try_from("FST".to_owned()).unwrap(),
+        total_dest_payment: 100,
+        left_ticks: 8,
+    };
+    await!(
+        funder_sender.send(FunderOutgoingControl::IncomingPaymentHeld(
+            held_payment.clone()
+        ))
+    )
+    .unwrap();
+
+    let to_app_message = await!(app_receiver1.next()).unwrap();
+    match to_app_message {
+        AppServerToApp::IncomingPaymentHeld(received_held_payment) => {
+            assert_eq!(received_held_payment, held_payment);
+        }
+        _ => unreachable!(),
+    }
+    assert!(app_receiver0.try_next().is_err());
+
+    // app0 may not reject the payment. The request should be discarded:
+    let to_app_server = AppToAppServer::new(
+        Uid::from(&[21; UID_LEN]),
+        AppRequest::RejectHeldPayment(InvoiceId::from(&[1; INVOICE_ID_LEN])),
+    );
+    await!(app_sender0.send(to_app_server)).unwrap();
+
+    // app1 accepts the payment:
+    let to_app_server = AppToAppServer::new(
+        Uid::from(&[22; UID_LEN]),
+        AppRequest::AcceptHeldPayment(InvoiceId::from(&[1; INVOICE_ID_LEN])),
+    );
+    await!(app_sender1.send(to_app_server)).unwrap();
+
+    let to_funder_message = await!(funder_receiver.next()).unwrap();
+    assert_eq!(to_funder_message.app_request_id, Uid::from(&[22; UID_LEN]));
+    match to_funder_message.funder_control {
+        FunderControl::AcceptHeldPayment(invoice_id) => {
+            assert_eq!(invoice_id, InvoiceId::from(&[1; INVOICE_ID_LEN]));
+        }
+        _ => unreachable!(),
+    };
+}
+
+#[test]
+fn test_app_server_loop_held_payment() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_app_server_loop_held_payment(thread_pool.clone()));
+}
@@ -1,6 +1,9 @@
 mod all_apps_closed;
 mod funder_command;
+mod held_payment;
 mod index_client_command;
+mod payment_history;
+mod rebalance;
 mod request_routes;
 mod request_send_funds;
 mod two_apps;
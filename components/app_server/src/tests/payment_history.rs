@@ -0,0 +1,116 @@
+use futures::channel::mpsc;
+use futures::executor::ThreadPool;
+use futures::task::Spawn;
+use futures::{SinkExt, StreamExt};
+
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::app_server::messages::{AppPermissions, AppRequest, AppServerToApp, AppToAppServer};
+use proto::funder::messages::{
+    FunderControl, FunderOutgoingControl, PaymentStatus, RequestPaymentHistory,
+    ResponsePaymentHistory,
+};
+
+use super::utils::spawn_dummy_app_server;
+
+async fn task_app_server_loop_payment_history<S>(spawner: S)
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let (
+        mut funder_sender,
+        mut funder_receiver,
+        _index_client_sender,
+        _index_client_receiver,
+        mut connections_sender,
+        _initial_node_report,
+    ) = spawn_dummy_app_server(spawner.clone());
+
+    // Connect two apps. app0 has no permissions at all:
+    let (mut app_sender0, app_server_receiver) = mpsc::channel(0);
+    let (app_server_sender, mut app_receiver0) = mpsc::channel(0);
+    let app_server_conn_pair = (app_server_sender, app_server_receiver);
+    let app_permissions = AppPermissions {
+        routes: false,
+        send_funds: false,
+        config: false,
+    };
+    await!(connections_sender.send((app_permissions, app_server_conn_pair))).unwrap();
+
+    let (_app_sender1, app_server_receiver) = mpsc::channel(0);
+    let (app_server_sender, mut app_receiver1) = mpsc::channel(0);
+    let app_server_conn_pair = (app_server_sender, app_server_receiver);
+    let app_permissions = AppPermissions {
+        routes: true,
+        send_funds: true,
+        config: true,
+    };
+    await!(connections_sender.send((app_permissions, app_server_conn_pair))).unwrap();
+
+    // The apps should receive the current node report as the first message:
+    let _to_app_message = await!(app_receiver0.next()).unwrap();
+    let _to_app_message = await!(app_receiver1.next()).unwrap();
+
+    // Query the payment history through app0:
+    let request_payment_history = RequestPaymentHistory {
+        request_id: Uid::from(&[3; UID_LEN]),
+        opt_direction: None,
+        opt_status: Some(PaymentStatus::Success),
+        opt_invoice_id: None,
+        offset: 0,
+        max_records: 16,
+    };
+    let to_app_server = AppToAppServer::new(
+        Uid::from(&[22; UID_LEN]),
+        AppRequest::RequestPaymentHistory(request_payment_history.clone()),
+    );
+    await!(app_sender0.send(to_app_server)).unwrap();
+
+    // The request should be forwarded to the Funder:
+    let to_funder_message = await!(funder_receiver.next()).unwrap();
+    assert_eq!(to_funder_message.app_request_id, Uid::from(&[22; UID_LEN]));
+    match to_funder_message.funder_control {
+        FunderControl::RequestPaymentHistory(received_request_payment_history) => {
+            assert_eq!(received_request_payment_history, request_payment_history);
+        }
+        _ => unreachable!(),
+    };
+
+    // The Funder returns a response. It should arrive only at app0:
+    let response_payment_history = ResponsePaymentHistory {
+        request_id: Uid::from(&[3; UID_LEN]),
+        records: Vec::new(),
+    };
+    await!(
+        funder_sender.send(FunderOutgoingControl::ResponsePaymentHistory(
+            response_payment_history.clone()
+        ))
+    )
+    .unwrap();
+
+    let to_app_message = await!(app_receiver0.next()).unwrap();
+    match to_app_message {
+        AppServerToApp::ResponsePaymentHistory(received_response_payment_history) => {
+            assert_eq!(received_response_payment_history, response_payment_history);
+        }
+        _ => unreachable!(),
+    }
+    assert!(app_receiver1.try_next().is_err());
+
+    // The same response again should be discarded, as it does not match any open request:
+    await!(
+        funder_sender.send(FunderOutgoingControl::ResponsePaymentHistory(
+            response_payment_history
+        ))
+    )
+    .unwrap();
+
+    assert!(app_receiver0.try_next().is_err());
+    assert!(app_receiver1.try_next().is_err());
+}
+
+#[test]
+fn test_app_server_loop_payment_history() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_app_server_loop_payment_history(thread_pool.clone()));
+}
@@ -0,0 +1,344 @@
+use std::convert::TryFrom;
+
+use futures::channel::mpsc;
+use futures::executor::ThreadPool;
+use futures::task::Spawn;
+use futures::{SinkExt, StreamExt};
+
+use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
+use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::app_server::messages::{
+    AppPermissions, AppRequest, AppServerToApp, AppToAppServer, RequestRebalance,
+};
+use proto::consts::DEFAULT_MAX_ROUTES;
+use proto::funder::messages::{
+    Currency, CurrencyBalance, FriendsRoute, FunderControl, FunderOutgoingControl,
+    ResponseReceived, ResponseSendFundsResult,
+};
+use proto::index_client::messages::{
+    AppServerToIndexClient, ClientResponseRoutes, IndexClientRequest, IndexClientToAppServer,
+    ResponseRoutesResult,
+};
+use proto::index_server::messages::RouteWithCapacity;
+use proto::report::messages::{
+    AddFriendReport, ChannelStatusReport, DirectionReport, FriendLivenessReport,
+    FriendReportMutation, FriendStatusReport, FunderReportMutation, FunderReportMutations,
+    McBalanceReport, McRequestsStatusReport, RequestsStatusReport, TcReport,
+};
+
+use super::utils::spawn_dummy_app_server;
+
+/// Report mutations adding an enabled and online friend, with a consistent channel in
+/// `currency`. Both sides may owe up to 200 credits.
+fn add_friend_mutations(
+    friend_public_key: &PublicKey,
+    currency: &Currency,
+) -> Vec<FunderReportMutation<u32>> {
+    let tc_report = TcReport {
+        direction: DirectionReport::Incoming,
+        balances: vec![McBalanceReport {
+            currency: currency.clone(),
+            balance: 0,
+            local_max_debt: 200,
+            remote_max_debt: 200,
+            local_pending_debt: 0,
+            remote_pending_debt: 0,
+        }],
+        requests_status: McRequestsStatusReport {
+            local: RequestsStatusReport::Open,
+            remote: RequestsStatusReport::Open,
+        },
+        num_local_pending_requests: 0,
+        num_remote_pending_requests: 0,
+        frozen_credits: Vec::new(),
+    };
+    let add_friend_report = AddFriendReport {
+        friend_public_key: friend_public_key.clone(),
+        name: "friend".to_owned(),
+        relays: Vec::new(),
+        balances: vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 0,
+        }],
+        opt_last_incoming_move_token: None,
+        channel_status: ChannelStatusReport::Consistent(tc_report),
+    };
+    vec![
+        FunderReportMutation::AddFriend(add_friend_report),
+        FunderReportMutation::FriendReportMutation((
+            friend_public_key.clone(),
+            FriendReportMutation::SetStatus(FriendStatusReport::Enabled),
+        )),
+        FunderReportMutation::FriendReportMutation((
+            friend_public_key.clone(),
+            FriendReportMutation::SetLiveness(FriendLivenessReport::Online),
+        )),
+    ]
+}
+
+async fn task_app_server_loop_rebalance<S>(spawner: S)
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let (
+        mut funder_sender,
+        mut funder_receiver,
+        mut index_client_sender,
+        mut index_client_receiver,
+        mut connections_sender,
+        _initial_node_report,
+    ) = spawn_dummy_app_server(spawner.clone());
+
+    // Connect an app:
+    let (mut app_sender, app_server_receiver) = mpsc::channel(0);
+    let (app_server_sender, mut app_receiver) = mpsc::channel(0);
+    let app_server_conn_pair = (app_server_sender, app_server_receiver);
+    let app_permissions = AppPermissions {
+        routes: false,
+        send_funds: true,
+        config: false,
+    };
+    await!(connections_sender.send((app_permissions, app_server_conn_pair))).unwrap();
+
+    // The app should receive the current node report as the first message:
+    let _to_app_message = await!(app_receiver.next()).unwrap();
+
+    let currency = Currency::try_from("FST".to_owned()).unwrap();
+    let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+    let out_friend_public_key = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+    let in_friend_public_key = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+
+    // The Funder reports our channels with the two friends:
+    let mut mutations = add_friend_mutations(&out_friend_public_key, &currency);
+    mutations.extend(add_friend_mutations(&in_friend_public_key, &currency));
+    let funder_report_mutations = FunderReportMutations {
+        opt_app_request_id: None,
+        mutations,
+    };
+    await!(funder_sender.send(FunderOutgoingControl::ReportMutations(
+        funder_report_mutations
+    )))
+    .unwrap();
+    match await!(index_client_receiver.next()).unwrap() {
+        AppServerToIndexClient::ApplyMutations(_index_mutations) => {}
+        _ => unreachable!(),
+    };
+    match await!(app_receiver.next()).unwrap() {
+        AppServerToApp::ReportMutations(_report_mutations) => {}
+        _ => unreachable!(),
+    };
+
+    // Our channel with the out friend can not carry the payment together with the fees. The
+    // request is rejected right away:
+    let request_rebalance = RequestRebalance {
+        request_id: Uid::from(&[2; UID_LEN]),
+        invoice_id: InvoiceId::from(&[2; INVOICE_ID_LEN]),
+        out_friend_public_key: out_friend_public_key.clone(),
+        in_friend_public_key: in_friend_public_key.clone(),
+        dest_payment: 198,
+        max_fees: 5,
+        timeout_ticks: 8,
+        currency: currency.clone(),
+    };
+    let to_app_server = AppToAppServer::new(
+        Uid::from(&[21; UID_LEN]),
+        AppRequest::RequestRebalance(request_rebalance),
+    );
+    await!(app_sender.send(to_app_server)).unwrap();
+
+    let to_app_message = await!(app_receiver.next()).unwrap();
+    match to_app_message {
+        AppServerToApp::ResponseReceived(received_response) => {
+            assert_eq!(received_response.request_id, Uid::from(&[2; UID_LEN]));
+            assert_eq!(
+                received_response.result,
+                ResponseSendFundsResult::Failure(local_public_key.clone())
+            );
+        }
+        _ => unreachable!(),
+    };
+    assert!(index_client_receiver.try_next().is_err());
+
+    let request_rebalance = RequestRebalance {
+        request_id: Uid::from(&[3; UID_LEN]),
+        invoice_id: InvoiceId::from(&[4; INVOICE_ID_LEN]),
+        out_friend_public_key: out_friend_public_key.clone(),
+        in_friend_public_key: in_friend_public_key.clone(),
+        dest_payment: 100,
+        max_fees: 5,
+        timeout_ticks: 8,
+        currency: currency.clone(),
+    };
+    let to_app_server = AppToAppServer::new(
+        Uid::from(&[22; UID_LEN]),
+        AppRequest::RequestRebalance(request_rebalance),
+    );
+    await!(app_sender.send(to_app_server)).unwrap();
+
+    // A request for routes between the two friends should be sent to the IndexClient:
+    // The routes request gets its own request_id:
+    let to_index_client_message = await!(index_client_receiver.next()).unwrap();
+    let routes_request_id = match to_index_client_message {
+        AppServerToIndexClient::AppRequest((
+            app_request_id,
+            IndexClientRequest::RequestRoutes(request_routes),
+        )) => {
+            assert_eq!(app_request_id, Uid::from(&[22; UID_LEN]));
+            assert_ne!(request_routes.request_id, Uid::from(&[3; UID_LEN]));
+            assert_eq!(request_routes.capacity, 105);
+            assert_eq!(request_routes.source, out_friend_public_key);
+            assert_eq!(request_routes.destination, in_friend_public_key);
+            assert_eq!(
+                request_routes.opt_exclude,
+                Some((out_friend_public_key.clone(), local_public_key.clone()))
+            );
+            assert_eq!(request_routes.currency, currency);
+            assert_eq!(request_routes.max_routes, DEFAULT_MAX_ROUTES);
+            request_routes.request_id
+        }
+        _ => unreachable!(),
+    };
+
+    // The first route passes through us, and can not be used to build a cycle:
+    let mediator_public_key = PublicKey::from(&[0xdd; PUBLIC_KEY_LEN]);
+    let routes = vec![
+        RouteWithCapacity {
+            route: FriendsRoute {
+                public_keys: vec![
+                    out_friend_public_key.clone(),
+                    local_public_key.clone(),
+                    in_friend_public_key.clone(),
+                ],
+            },
+            capacity: 200,
+            fees: 1,
+        },
+        RouteWithCapacity {
+            route: FriendsRoute {
+                public_keys: vec![
+                    out_friend_public_key.clone(),
+                    mediator_public_key.clone(),
+                    in_friend_public_key.clone(),
+                ],
+            },
+            capacity: 200,
+            fees: 1,
+        },
+    ];
+    let client_response_routes = ClientResponseRoutes {
+        request_id: routes_request_id,
+        result: ResponseRoutesResult::Success(routes),
+    };
+    await!(
+        index_client_sender.send(IndexClientToAppServer::ResponseRoutes(
+            client_response_routes
+        ))
+    )
+    .unwrap();
+
+    // The app should not see the routes. Instead, a self payment is sent to the Funder.
+    // The payment gets a request_id of its own:
+    let to_funder_message = await!(funder_receiver.next()).unwrap();
+    assert_eq!(to_funder_message.app_request_id, Uid::from(&[22; UID_LEN]));
+    let payment_request_id = match to_funder_message.funder_control {
+        FunderControl::RequestSendFunds(user_request_send_funds) => {
+            assert_ne!(user_request_send_funds.request_id, Uid::from(&[3; UID_LEN]));
+            assert_ne!(user_request_send_funds.request_id, routes_request_id);
+            assert_eq!(
+                user_request_send_funds.route.public_keys,
+                vec![
+                    local_public_key.clone(),
+                    out_friend_public_key.clone(),
+                    mediator_public_key.clone(),
+                    in_friend_public_key.clone(),
+                    local_public_key.clone(),
+                ]
+            );
+            assert_eq!(user_request_send_funds.currency, currency);
+            assert_eq!(user_request_send_funds.dest_payment, 100);
+            assert_eq!(user_request_send_funds.total_dest_payment, 100);
+            assert_eq!(user_request_send_funds.fees, 5);
+            user_request_send_funds.request_id
+        }
+        _ => unreachable!(),
+    };
+    assert!(app_receiver.try_next().is_err());
+
+    // The response from the Funder should arrive at the app, using the request_id of the
+    // rebalance request:
+    let response_received = ResponseReceived {
+        request_id: payment_request_id,
+        result: ResponseSendFundsResult::Failure(mediator_public_key.clone()),
+    };
+    await!(funder_sender.send(FunderOutgoingControl::ResponseReceived(response_received))).unwrap();
+
+    let to_app_message = await!(app_receiver.next()).unwrap();
+    match to_app_message {
+        AppServerToApp::ResponseReceived(received_response) => {
+            assert_eq!(received_response.request_id, Uid::from(&[3; UID_LEN]));
+            assert_eq!(
+                received_response.result,
+                ResponseSendFundsResult::Failure(mediator_public_key)
+            );
+        }
+        _ => unreachable!(),
+    };
+
+    // Another rebalance request, for which no route is found:
+    let request_rebalance = RequestRebalance {
+        request_id: Uid::from(&[5; UID_LEN]),
+        invoice_id: InvoiceId::from(&[6; INVOICE_ID_LEN]),
+        out_friend_public_key,
+        in_friend_public_key,
+        dest_payment: 100,
+        max_fees: 5,
+        timeout_ticks: 8,
+        currency: currency.clone(),
+    };
+    let to_app_server = AppToAppServer::new(
+        Uid::from(&[23; UID_LEN]),
+        AppRequest::RequestRebalance(request_rebalance),
+    );
+    await!(app_sender.send(to_app_server)).unwrap();
+    let to_index_client_message = await!(index_client_receiver.next()).unwrap();
+    let routes_request_id = match to_index_client_message {
+        AppServerToIndexClient::AppRequest((
+            _app_request_id,
+            IndexClientRequest::RequestRoutes(request_routes),
+        )) => request_routes.request_id,
+        _ => unreachable!(),
+    };
+
+    let client_response_routes = ClientResponseRoutes {
+        request_id: routes_request_id,
+        result: ResponseRoutesResult::Failure,
+    };
+    await!(
+        index_client_sender.send(IndexClientToAppServer::ResponseRoutes(
+            client_response_routes
+        ))
+    )
+    .unwrap();
+
+    // The app gets a failure, reported by the local node:
+    let to_app_message = await!(app_receiver.next()).unwrap();
+    match to_app_message {
+        AppServerToApp::ResponseReceived(received_response) => {
+            assert_eq!(received_response.request_id, Uid::from(&[5; UID_LEN]));
+            assert_eq!(
+                received_response.result,
+                ResponseSendFundsResult::Failure(local_public_key)
+            );
+        }
+        _ => unreachable!(),
+    };
+    assert!(funder_receiver.try_next().is_err());
+}
+
+#[test]
+fn test_app_server_loop_rebalance() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_app_server_loop_rebalance(thread_pool.clone()));
+}
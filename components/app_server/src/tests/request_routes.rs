@@ -1,3 +1,5 @@
+use std::convert::TryFrom;
+
 use futures::channel::mpsc;
 use futures::executor::ThreadPool;
 use futures::task::Spawn;
@@ -8,9 +10,10 @@ use crypto::uid::Uid;
 use crypto::uid::UID_LEN;
 
 use proto::app_server::messages::{AppPermissions, AppRequest, AppServerToApp, AppToAppServer};
+use proto::funder::messages::Currency;
 use proto::index_client::messages::{
     AppServerToIndexClient, ClientResponseRoutes, IndexClientRequest, IndexClientToAppServer,
-    RequestRoutes, ResponseRoutesResult,
+    RequestRoutes, RequestRoutesMode, ResponseRoutesResult,
 };
 
 use super::utils::spawn_dummy_app_server;
@@ -60,6 +63,13 @@ where
         source: PublicKey::from(&[0xee; PUBLIC_KEY_LEN]),
         destination: PublicKey::from(&[0xff; PUBLIC_KEY_LEN]),
         opt_exclude: None,
+        currency: Currency::try_from("FST".to_owned()).unwrap(),
+        max_routes: 4,
+        mode: RequestRoutesMode::Routes,
+        exclude_nodes: Vec::new(),
+        exclude_edges: Vec::new(),
+        waypoints: Vec::new(),
+        opt_max_route_len: None,
     };
 
     let to_app_server = AppToAppServer::new(
@@ -1,3 +1,5 @@
+use std::convert::TryFrom;
+
 use futures::channel::mpsc;
 use futures::executor::ThreadPool;
 use futures::task::Spawn;
@@ -9,8 +11,8 @@ use crypto::uid::{Uid, UID_LEN};
 
 use proto::app_server::messages::{AppPermissions, AppRequest, AppServerToApp, AppToAppServer};
 use proto::funder::messages::{
-    FriendsRoute, FunderControl, FunderOutgoingControl, ResponseReceived, ResponseSendFundsResult,
-    UserRequestSendFunds,
+    Currency, FriendsRoute, FunderControl, FunderOutgoingControl, ResponseReceived,
+    ResponseSendFundsResult, UserRequestSendFunds,
 };
 
 use super::utils::spawn_dummy_app_server;
@@ -61,8 +63,12 @@ where
         route: FriendsRoute {
             public_keys: vec![pk_e.clone(), pk_f.clone()],
         },
+        currency: Currency::try_from("FST".to_owned()).unwrap(),
         invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
         dest_payment: 20,
+        total_dest_payment: 20,
+        fees: 0,
+        timeout_ticks: 0x100,
     };
 
     let to_app_server = AppToAppServer::new(
@@ -3,11 +3,13 @@ use futures::task::{Spawn, SpawnExt};
 use futures::{FutureExt, TryFutureExt};
 
 use im::hashmap::HashMap as ImHashMap;
+use im::vector::Vector as ImVec;
 
 use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
+use crypto::test_utils::DummyRandom;
 
 use proto::app_server::messages::{NamedRelayAddress, NodeReport};
-use proto::funder::messages::{FunderIncomingControl, FunderOutgoingControl};
+use proto::funder::messages::{FreezePolicy, FunderIncomingControl, FunderOutgoingControl};
 use proto::index_client::messages::{
     AppServerToIndexClient, IndexClientReport, IndexClientToAppServer,
 };
@@ -62,8 +64,12 @@ where
         relays: vec![dummy_named_relay_address(0), dummy_named_relay_address(1)]
             .into_iter()
             .collect(),
+        opt_direct_address: None,
         friends: ImHashMap::new(),
         num_ready_receipts: 0,
+        freeze_policy: FreezePolicy::Unlimited,
+        hold_incoming_payments: false,
+        held_payments: ImVec::new(),
     };
 
     let server100 = NamedIndexServerAddress {
@@ -95,6 +101,7 @@ where
         to_index_client,
         incoming_connections,
         initial_node_report.clone(),
+        DummyRandom::new(&[1u8]),
         spawner.clone(),
     )
     .map_err(|e| error!("app_server_loop() error: {:?}", e))
@@ -0,0 +1,33 @@
+#![feature(futures_api, async_await, await_macro, arbitrary_self_types)]
+#![feature(nll)]
+#![feature(generators)]
+#![feature(never_type)]
+#![deny(trivial_numeric_casts, warnings)]
+#![allow(intra_doc_link_resolution_failure)]
+#![allow(
+    clippy::too_many_arguments,
+    clippy::implicit_hasher,
+    clippy::module_inception,
+    clippy::new_without_default
+)]
+
+#[macro_use]
+extern crate log;
+
+use std::io;
+use structopt::StructOpt;
+
+use bin::stindexadmlib::{stindexadm, IndexAdmError, StIndexAdmCmd};
+
+fn run() -> Result<(), IndexAdmError> {
+    env_logger::init();
+
+    let st_index_adm_cmd = StIndexAdmCmd::from_args();
+    stindexadm(st_index_adm_cmd, &mut io::stdout())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        error!("run() error: {:?}", e);
+    }
+}
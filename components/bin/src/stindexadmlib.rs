@@ -0,0 +1,276 @@
+use std::convert::TryInto;
+use std::io;
+
+use futures::executor::ThreadPool;
+use futures::{SinkExt, StreamExt};
+
+use structopt::StructOpt;
+
+use common::conn::FutTransform;
+
+use net::NetConnector;
+use version::VersionPrefix;
+
+use proto::consts::{MAX_FRAME_LENGTH, PROTOCOL_VERSION};
+use proto::file::ser_string::{public_key_to_string, string_to_public_key};
+use proto::funder::messages::CurrencyError;
+use proto::index_server::messages::{
+    IndexAdminToServer, IndexServerStats, IndexServerToAdmin, RequestNeighbors, ResponseNeighbors,
+};
+use proto::index_server::serialize::{
+    deserialize_index_server_to_admin, serialize_index_admin_to_server,
+};
+use proto::net::messages::{NetAddress, NetAddressError};
+
+#[derive(Clone, Debug, StructOpt)]
+pub struct NeighborsCmd {
+    /// Public key of the node
+    #[structopt(short = "n", long = "node")]
+    pub node: String,
+    /// Currency of the edges
+    #[structopt(short = "c", long = "currency")]
+    pub currency: String,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum AdmQueryCmd {
+    /// Show index server statistics
+    #[structopt(name = "stats")]
+    Stats,
+    /// Show all the edges reported by a node
+    #[structopt(name = "neighbors")]
+    Neighbors(NeighborsCmd),
+}
+
+/// stindexadm: Offst Index Server ADMin
+/// Query a running index server through its admin listening address
+#[derive(Clone, Debug, StructOpt)]
+#[structopt(name = "stindexadm")]
+pub struct StIndexAdmCmd {
+    /// Admin listening address of the index server (See stindex --ladmin)
+    #[structopt(short = "a", long = "address")]
+    pub address: String,
+    #[structopt(subcommand)]
+    pub query: AdmQueryCmd,
+}
+
+#[derive(Debug)]
+pub enum IndexAdmError {
+    CreateThreadPoolError,
+    NetAddressError(NetAddressError),
+    InvalidPublicKey,
+    CurrencyError(CurrencyError),
+    ConnectError,
+    SendRequestError,
+    ConnectionClosed,
+    DeserializeResponseError,
+    WriteError,
+}
+
+impl From<NetAddressError> for IndexAdmError {
+    fn from(e: NetAddressError) -> Self {
+        IndexAdmError::NetAddressError(e)
+    }
+}
+
+impl From<CurrencyError> for IndexAdmError {
+    fn from(e: CurrencyError) -> Self {
+        IndexAdmError::CurrencyError(e)
+    }
+}
+
+fn print_stats(stats: &IndexServerStats, writer: &mut impl io::Write) -> Result<(), io::Error> {
+    writeln!(writer, "Graphs:")?;
+    for graph_stats in &stats.graphs {
+        writeln!(
+            writer,
+            "  {}: {} nodes, {} edges",
+            graph_stats.currency.as_str(),
+            graph_stats.num_nodes,
+            graph_stats.num_edges
+        )?;
+    }
+    writeln!(writer, "Connected clients: {}", stats.num_clients)?;
+    writeln!(writer, "Trusted servers: {}", stats.num_trusted_servers)?;
+    writeln!(writer, "Connected servers:")?;
+    for public_key in &stats.connected_servers {
+        writeln!(writer, "  {}", public_key_to_string(public_key))?;
+    }
+    writeln!(
+        writer,
+        "Verifier: {} neighbors, {} ticks, {} nodes",
+        stats.verifier.num_neighbors, stats.verifier.num_ticks, stats.verifier.num_nodes
+    )?;
+    let routes_requests = &stats.routes_requests;
+    writeln!(
+        writer,
+        "Routes requests: {} handled, {} rejected, {} open",
+        routes_requests.num_handled, routes_requests.num_rejected, routes_requests.num_open
+    )?;
+    writeln!(
+        writer,
+        "Routes requests latency: {}[us] average, {}[us] maximum",
+        routes_requests.avg_latency_micros, routes_requests.max_latency_micros
+    )?;
+    Ok(())
+}
+
+fn print_neighbors(
+    response_neighbors: &ResponseNeighbors,
+    writer: &mut impl io::Write,
+) -> Result<(), io::Error> {
+    writeln!(
+        writer,
+        "Neighbors of {} ({}):",
+        public_key_to_string(&response_neighbors.node_public_key),
+        response_neighbors.currency.as_str()
+    )?;
+    for neighbor in &response_neighbors.neighbors {
+        writeln!(
+            writer,
+            "  {}: send_capacity={}, recv_capacity={}, rate=(mul={}, add={})",
+            public_key_to_string(&neighbor.public_key),
+            neighbor.send_capacity,
+            neighbor.recv_capacity,
+            neighbor.rate.mul,
+            neighbor.rate.add
+        )?;
+    }
+    Ok(())
+}
+
+pub fn stindexadm(
+    st_index_adm_cmd: StIndexAdmCmd,
+    writer: &mut impl io::Write,
+) -> Result<(), IndexAdmError> {
+    let StIndexAdmCmd { address, query } = st_index_adm_cmd;
+
+    let net_address: NetAddress = address.try_into()?;
+
+    let request = match query {
+        AdmQueryCmd::Stats => IndexAdminToServer::RequestStats,
+        AdmQueryCmd::Neighbors(NeighborsCmd { node, currency }) => {
+            IndexAdminToServer::RequestNeighbors(RequestNeighbors {
+                currency: currency.try_into()?,
+                node_public_key: string_to_public_key(&node)
+                    .map_err(|_| IndexAdmError::InvalidPublicKey)?,
+            })
+        }
+    };
+
+    // Create a ThreadPool:
+    let mut thread_pool = ThreadPool::new().map_err(|_| IndexAdmError::CreateThreadPoolError)?;
+
+    // A thread pool for resolving network addresses:
+    let resolve_thread_pool =
+        ThreadPool::new().map_err(|_| IndexAdmError::CreateThreadPoolError)?;
+
+    let mut net_connector =
+        NetConnector::new(MAX_FRAME_LENGTH, resolve_thread_pool, thread_pool.clone());
+    let mut version_transform = VersionPrefix::new(PROTOCOL_VERSION, thread_pool.clone());
+
+    let response = thread_pool.run(
+        async move {
+            let conn_pair = await!(net_connector.transform(net_address))
+                .ok_or(IndexAdmError::ConnectError)?;
+            let (mut sender, mut receiver) = await!(version_transform.transform(conn_pair));
+
+            await!(sender.send(serialize_index_admin_to_server(&request)))
+                .map_err(|_| IndexAdmError::SendRequestError)?;
+
+            let data = await!(receiver.next()).ok_or(IndexAdmError::ConnectionClosed)?;
+            deserialize_index_server_to_admin(&data)
+                .map_err(|_| IndexAdmError::DeserializeResponseError)
+        },
+    )?;
+
+    match response {
+        IndexServerToAdmin::Stats(stats) => print_stats(&stats, writer),
+        IndexServerToAdmin::ResponseNeighbors(response_neighbors) => {
+            print_neighbors(&response_neighbors, writer)
+        }
+    }
+    .map_err(|_| IndexAdmError::WriteError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::TryFrom;
+    use std::str;
+
+    use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
+
+    use proto::funder::messages::{Currency, Rate};
+    use proto::index_server::messages::{
+        CurrencyGraphStats, NeighborEdge, RoutesRequestsStats, VerifierStats,
+    };
+
+    #[test]
+    fn test_print_stats() {
+        let stats = IndexServerStats {
+            graphs: vec![CurrencyGraphStats {
+                currency: Currency::try_from("FST".to_owned()).unwrap(),
+                num_nodes: 3,
+                num_edges: 4,
+            }],
+            num_clients: 5,
+            num_trusted_servers: 2,
+            connected_servers: vec![PublicKey::from(&[0xaa; PUBLIC_KEY_LEN])],
+            verifier: VerifierStats {
+                num_neighbors: 1,
+                num_ticks: 8,
+                num_nodes: 3,
+            },
+            routes_requests: RoutesRequestsStats {
+                num_handled: 10,
+                num_rejected: 1,
+                num_open: 0,
+                avg_latency_micros: 150,
+                max_latency_micros: 400,
+            },
+        };
+
+        let mut output = Vec::new();
+        print_stats(&stats, &mut output).unwrap();
+        let output_str = str::from_utf8(&output).unwrap();
+
+        assert!(output_str.contains("  FST: 3 nodes, 4 edges\n"));
+        assert!(output_str.contains("Connected clients: 5\n"));
+        assert!(output_str.contains("Trusted servers: 2\n"));
+        assert!(output_str.contains(&format!(
+            "Connected servers:\n  {}\n",
+            public_key_to_string(&PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]))
+        )));
+        assert!(output_str.contains("Verifier: 1 neighbors, 8 ticks, 3 nodes\n"));
+        assert!(output_str.contains("Routes requests: 10 handled, 1 rejected, 0 open\n"));
+        assert!(output_str.contains("Routes requests latency: 150[us] average, 400[us] maximum\n"));
+    }
+
+    #[test]
+    fn test_print_neighbors() {
+        let node_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let neighbor_public_key = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let response_neighbors = ResponseNeighbors {
+            currency: Currency::try_from("FST".to_owned()).unwrap(),
+            node_public_key: node_public_key.clone(),
+            neighbors: vec![NeighborEdge {
+                public_key: neighbor_public_key.clone(),
+                send_capacity: 100,
+                recv_capacity: 20,
+                rate: Rate { mul: 0, add: 1 },
+            }],
+        };
+
+        let mut output = Vec::new();
+        print_neighbors(&response_neighbors, &mut output).unwrap();
+
+        let expected = format!(
+            "Neighbors of {} (FST):\n  {}: send_capacity=100, recv_capacity=20, rate=(mul=0, add=1)\n",
+            public_key_to_string(&node_public_key),
+            public_key_to_string(&neighbor_public_key)
+        );
+        assert_eq!(str::from_utf8(&output).unwrap(), expected);
+    }
+}
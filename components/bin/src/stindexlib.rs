@@ -4,8 +4,10 @@ use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+use futures::channel::mpsc;
 use futures::executor::ThreadPool;
 use futures::task::SpawnExt;
+use futures::{FutureExt, TryFutureExt};
 
 use structopt::StructOpt;
 
@@ -13,10 +15,13 @@ use common::conn::Listener;
 use common::int_convert::usize_to_u64;
 
 use crypto::crypto_rand::system_random;
+use crypto::identity::PublicKey;
 
 use identity::{create_identity, IdentityClient};
 
-use index_server::{net_index_server, NetIndexServerError};
+use index_server::{
+    net_index_server, trusted_servers_watcher, ClientLimits, GraphBackend, NetIndexServerError,
+};
 use proto::consts::{MAX_FRAME_LENGTH, TICK_MS};
 use timer::create_timer;
 
@@ -24,6 +29,7 @@ use net::{NetConnector, TcpListener};
 
 use proto::file::identity::load_identity_from_file;
 use proto::file::index_server::{load_trusted_servers, IndexServerDirectoryError};
+use proto::net::messages::NetAddress;
 
 // TODO; Maybe take as a command line argument in the future?
 /// Maximum amount of concurrent encrypted channel set-ups.
@@ -31,6 +37,19 @@ use proto::file::index_server::{load_trusted_servers, IndexServerDirectoryError}
 pub const MAX_CONCURRENT_ENCRYPT: usize = 0x200;
 /// Amount of ticks we wait before attempting to reconnect to a remote index server.
 pub const BACKOFF_TICKS: usize = 0x8;
+/// Amount of ticks between two scans of the trusted servers directory.
+pub const TRUSTED_SCAN_TICKS: usize = 0x10;
+/// Maximum amount of route requests a client may send in a burst.
+pub const MAX_CLIENT_REQUEST_TOKENS: usize = 0x10;
+/// Amount of route requests a client may send every tick.
+pub const CLIENT_REQUEST_REFILL_TOKENS: usize = 0x1;
+/// Maximum amount of mutations updates a client may send in a burst.
+pub const MAX_CLIENT_MUTATIONS_TOKENS: usize = 0x40;
+/// Amount of mutations updates a client may send every tick.
+pub const CLIENT_MUTATIONS_REFILL_TOKENS: usize = 0x4;
+/// Maximum amount of route requests handled at the same time.
+/// We set this number to avoid DoS from many clients requesting routes at the same time.
+pub const MAX_OPEN_REQUESTS: usize = 0x40;
 
 /// stindex: Offst Index Server
 /// A server used to index the Offst network. Collects topology information from nodes, and serves
@@ -47,9 +66,26 @@ pub struct StIndexCmd {
     /// Listening address for servers
     #[structopt(short = "s", long = "lserver")]
     pub lserver: SocketAddr,
-    /// Directory path of trusted index servers
+    /// Directory path of trusted index servers. Changes to this directory are applied while
+    /// the server is running
     #[structopt(parse(from_os_str), short = "t", long = "trusted")]
     pub trusted: PathBuf,
+    /// Graph snapshot file path. The graph is periodically saved to this file, and restored
+    /// from it on startup
+    #[structopt(parse(from_os_str), short = "g", long = "snapshot")]
+    pub snapshot: Option<PathBuf>,
+    /// Graph implementation: simple, or compact (Optimized for large networks)
+    #[structopt(long = "graph", default_value = "simple")]
+    pub graph: GraphBackend,
+    /// Listening address for admin queries (See stindexadm). Admin connections are neither
+    /// encrypted nor authenticated, therefore only loopback addresses are allowed (See
+    /// --admin-remote)
+    #[structopt(long = "ladmin")]
+    pub ladmin: Option<SocketAddr>,
+    /// Allow a non loopback admin listening address. Anyone who can reach this address will be
+    /// able to query the server
+    #[structopt(long = "admin-remote")]
+    pub admin_remote: bool,
 }
 
 #[allow(clippy::enum_variant_names)]
@@ -61,6 +97,23 @@ pub enum IndexServerBinError {
     LoadIdentityError,
     CreateIdentityError,
     LoadTrustedServersError(IndexServerDirectoryError),
+    SpawnTrustedWatcherError,
+    NonLoopbackAdminAddress,
+}
+
+/// Load the trusted servers directory into a map of public_key -> address
+fn load_trusted_servers_map(
+    trusted: &Path,
+) -> Result<HashMap<PublicKey, NetAddress>, IndexServerDirectoryError> {
+    Ok(load_trusted_servers(trusted)?
+        .into_iter()
+        .map(|index_server_address| {
+            (
+                index_server_address.public_key,
+                index_server_address.address,
+            )
+        })
+        .collect::<HashMap<_, _>>())
 }
 
 pub fn stindex(st_index_cmd: StIndexCmd) -> Result<(), IndexServerBinError> {
@@ -69,21 +122,23 @@ pub fn stindex(st_index_cmd: StIndexCmd) -> Result<(), IndexServerBinError> {
         lclient,
         lserver,
         trusted,
+        snapshot,
+        graph,
+        ladmin,
+        admin_remote,
     } = st_index_cmd;
 
+    if let Some(ladmin) = &ladmin {
+        if !ladmin.ip().is_loopback() && !admin_remote {
+            return Err(IndexServerBinError::NonLoopbackAdminAddress);
+        }
+    }
+
     let identity = load_identity_from_file(Path::new(&idfile))
         .map_err(|_| IndexServerBinError::LoadIdentityError)?;
 
-    let trusted_servers = load_trusted_servers(Path::new(&trusted))
-        .map_err(IndexServerBinError::LoadTrustedServersError)?
-        .into_iter()
-        .map(|index_server_address| {
-            (
-                index_server_address.public_key,
-                index_server_address.address,
-            )
-        })
-        .collect::<HashMap<_, _>>();
+    let trusted_servers = load_trusted_servers_map(Path::new(&trusted))
+        .map_err(IndexServerBinError::LoadTrustedServersError)?;
 
     // Create a ThreadPool:
     let mut thread_pool =
@@ -117,22 +172,64 @@ pub fn stindex(st_index_cmd: StIndexCmd) -> Result<(), IndexServerBinError> {
     let server_tcp_listener = TcpListener::new(MAX_FRAME_LENGTH, thread_pool.clone());
     let (_config_sender, incoming_server_raw_conns) = server_tcp_listener.listen(lserver);
 
+    // Start listening to admins (If requested):
+    let incoming_admin_raw_conns = match ladmin {
+        Some(ladmin) => {
+            let admin_tcp_listener = TcpListener::new(MAX_FRAME_LENGTH, thread_pool.clone());
+            let (_config_sender, incoming_admin_raw_conns) = admin_tcp_listener.listen(ladmin);
+            incoming_admin_raw_conns
+        }
+        None => {
+            // No admin connections will ever arrive:
+            let (_admin_conns_sender, incoming_admin_raw_conns) = mpsc::channel(0);
+            incoming_admin_raw_conns
+        }
+    };
+
     // A tcp connector, Used to connect to remote servers:
     let raw_server_net_connector =
         NetConnector::new(MAX_FRAME_LENGTH, resolve_thread_pool, thread_pool.clone());
 
+    // Watch the trusted servers directory for changes:
+    let (trusted_mutations_sender, incoming_trusted_mutations) = mpsc::channel(0);
+    let watcher_fut = trusted_servers_watcher(
+        move || load_trusted_servers_map(&trusted),
+        trusted_servers.clone(),
+        timer_client.clone(),
+        TRUSTED_SCAN_TICKS,
+        trusted_mutations_sender,
+    )
+    .map_err(|e| error!("trusted_servers_watcher() error: {:?}", e))
+    .map(|_| ());
+    thread_pool
+        .spawn(watcher_fut)
+        .map_err(|_| IndexServerBinError::SpawnTrustedWatcherError)?;
+
+    let client_limits = ClientLimits {
+        max_request_tokens: MAX_CLIENT_REQUEST_TOKENS,
+        request_refill_tokens: CLIENT_REQUEST_REFILL_TOKENS,
+        max_mutations_tokens: MAX_CLIENT_MUTATIONS_TOKENS,
+        mutations_refill_tokens: CLIENT_MUTATIONS_REFILL_TOKENS,
+        max_open_requests: MAX_OPEN_REQUESTS,
+    };
+
     let rng = system_random();
 
     let index_server_fut = net_index_server(
         incoming_client_raw_conns,
         incoming_server_raw_conns,
+        incoming_admin_raw_conns,
         raw_server_net_connector,
         identity_client,
         timer_client,
         rng,
         trusted_servers,
+        incoming_trusted_mutations,
         MAX_CONCURRENT_ENCRYPT,
         BACKOFF_TICKS,
+        client_limits,
+        snapshot,
+        graph,
         graph_service_thread_pool,
         thread_pool.clone(),
     );
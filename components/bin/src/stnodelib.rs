@@ -21,7 +21,7 @@ use node::{net_node, NetNodeError, NodeConfig, NodeState};
 
 use database::file_db::FileDb;
 
-use net::{NetConnector, TcpListener};
+use net::{NetConnector, NetListener, TcpListener};
 use proto::consts::{
     KEEPALIVE_TICKS, MAX_FRAME_LENGTH, MAX_NODE_RELAYS, MAX_OPERATIONS_IN_BATCH, TICKS_TO_REKEY,
     TICK_MS,
@@ -74,20 +74,30 @@ pub struct StNodeCmd {
     /// Listening address (Used for communication with apps)
     #[structopt(short = "l", long = "laddr")]
     pub laddr: SocketAddr,
+    /// Listening address for direct connections from friends (For example: "[::]:9600").
+    /// By default we listen on all IPv4 interfaces, at the port of our direct address.
+    #[structopt(long = "ldirect")]
+    pub ldirect: Option<SocketAddr>,
     /// Database file path
     #[structopt(parse(from_os_str), short = "d", long = "database")]
     pub database: PathBuf,
     /// Directory path of trusted applications
     #[structopt(parse(from_os_str), short = "t", long = "trusted")]
     pub trusted: PathBuf,
+    /// Maximum amount of records kept in the payment history. When the history is full, the
+    /// oldest records are removed. By default all the records are kept.
+    #[structopt(long = "max-history")]
+    pub max_history: Option<usize>,
 }
 
 pub fn stnode(st_node_cmd: StNodeCmd) -> Result<(), NodeBinError> {
     let StNodeCmd {
         idfile,
         laddr,
+        ldirect,
         database,
         trusted,
+        max_history,
     } = st_node_cmd;
 
     // Parse identity file:
@@ -135,6 +145,8 @@ pub fn stnode(st_node_cmd: StNodeCmd) -> Result<(), NodeBinError> {
         max_operations_in_batch: MAX_OPERATIONS_IN_BATCH,
         /// The size we allocate for the user send funds requests queue.
         max_pending_user_requests: MAX_PENDING_USER_REQUESTS,
+        /// Maximum amount of records kept in the payment history.
+        opt_max_payment_history: max_history,
         /// Maximum amount of concurrent index client requests:
         max_open_index_client_requests: MAX_OPEN_INDEX_CLIENT_REQUESTS,
         /// Maximum amount of relays a node may use.
@@ -158,6 +170,9 @@ pub fn stnode(st_node_cmd: StNodeCmd) -> Result<(), NodeBinError> {
     let app_tcp_listener = TcpListener::new(MAX_FRAME_LENGTH, thread_pool.clone());
     let (_config_sender, incoming_app_raw_conns) = app_tcp_listener.listen(laddr);
 
+    // Used to listen for direct connections from friends:
+    let net_listener = NetListener::new(ldirect, MAX_FRAME_LENGTH, thread_pool.clone());
+
     // Create a closure for loading trusted apps map:
     let get_trusted_apps = move || -> Option<_> {
         Some(
@@ -172,6 +187,7 @@ pub fn stnode(st_node_cmd: StNodeCmd) -> Result<(), NodeBinError> {
     let node_fut = net_node(
         incoming_app_raw_conns,
         net_connector,
+        net_listener,
         timer_client,
         identity_client,
         rng,
@@ -1,9 +1,12 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::time::Duration;
 
+use futures::channel::mpsc;
 use futures::executor::ThreadPool;
 use futures::task::SpawnExt;
+use futures::{future, SinkExt, StreamExt};
 
 use structopt::StructOpt;
 
@@ -15,17 +18,25 @@ use identity::{create_identity, IdentityClient};
 use proto::consts::{MAX_FRAME_LENGTH, TICK_MS};
 
 use common::int_convert::usize_to_u64;
+use common::watcher::watch_changes;
 
-use net::TcpListener;
-use relay::{net_relay_server, NetRelayServerError};
+use net::{NetConnector, TcpListener};
+use relay::{log_relay_stats, net_relay_server, NetRelayServerError, RelayLimits};
 use timer::create_timer;
 
 use proto::file::identity::load_identity_from_file;
+use proto::file::relay::{
+    load_allowed_nodes, load_peer_relays, AllowedNodesDirectoryError, PeerRelaysDirectoryError,
+};
 
 // TODO; Maybe take as a command line argument in the future?
 /// Maximum amount of concurrent encrypted channel set-ups.
 /// We set this number to avoid DoS from half finished encrypted channel negotiations.
 pub const MAX_CONCURRENT_ENCRYPT: usize = 0x200;
+/// Amount of ticks between two scans of the allowed nodes directory.
+pub const ALLOWED_SCAN_TICKS: usize = 0x10;
+/// Amount of ticks to wait before reconnecting to a peer relay.
+pub const BACKOFF_TICKS: usize = 0x8;
 
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
@@ -34,6 +45,12 @@ pub enum RelayServerBinError {
     LoadIdentityError,
     CreateIdentityError,
     CreateTimerError,
+    InvalidTunnelBandwidth,
+    InvalidStatsInterval,
+    LoadAllowedNodesError(AllowedNodesDirectoryError),
+    LoadPeerRelaysError(PeerRelaysDirectoryError),
+    SpawnAllowedWatcherError,
+    SpawnStatsLoggerError,
     NetRelayServerError(NetRelayServerError),
 }
 
@@ -48,19 +65,83 @@ pub struct StRelayCmd {
     /// Listening address (Example: 0.0.0.0:1337)
     #[structopt(short = "l", long = "laddr")]
     pub laddr: SocketAddr,
+    /// Maximum amount of open connections
+    #[structopt(long = "max-conns", default_value = "16384")]
+    pub max_conns: usize,
+    /// Maximum amount of open connections from a single identity. A node opens a connection for
+    /// every friend it communicates with through the relay. Peer relays are not limited
+    #[structopt(long = "max-conns-per-key", default_value = "256")]
+    pub max_conns_per_key: usize,
+    /// Maximum amount of connections waiting to be accepted by a single listening node
+    #[structopt(long = "max-half-tunnels", default_value = "64")]
+    pub max_half_tunnels: usize,
+    /// Maximum bandwidth of a single tunnel in every direction, in bytes per second
+    #[structopt(long = "max-tunnel-bandwidth", default_value = "1048576")]
+    pub max_tunnel_bandwidth: usize,
+    /// Directory of friend or node files. If provided, only those nodes may use the relay.
+    /// Changes to this directory are applied while the relay is running
+    #[structopt(parse(from_os_str), short = "a", long = "allowed")]
+    pub allowed: Option<PathBuf>,
+    /// Directory of relay files, describing peer relays. Nodes listening on a peer relay are
+    /// reachable through this relay too. Peer relays should list this relay as a peer as well
+    #[structopt(parse(from_os_str), short = "p", long = "peers")]
+    pub peers: Option<PathBuf>,
+    /// Interval between two statistics reports written to the log, in seconds. Reports include
+    /// the listening nodes, byte counters and lifetimes of tunnels and rejected connections counts
+    #[structopt(long = "stats-interval", default_value = "60")]
+    pub stats_interval: usize,
 }
 
 pub fn strelay(st_relay_cmd: StRelayCmd) -> Result<(), RelayServerBinError> {
-    let StRelayCmd { idfile, laddr } = st_relay_cmd;
+    let StRelayCmd {
+        idfile,
+        laddr,
+        max_conns,
+        max_conns_per_key,
+        max_half_tunnels,
+        max_tunnel_bandwidth,
+        allowed,
+        peers,
+        stats_interval,
+    } = st_relay_cmd;
+
+    let max_tunnel_bytes_per_tick = max_tunnel_bandwidth.saturating_mul(TICK_MS) / 1000;
+    if max_tunnel_bytes_per_tick == 0 {
+        return Err(RelayServerBinError::InvalidTunnelBandwidth);
+    }
+    let relay_limits = RelayLimits {
+        max_conns,
+        max_conns_per_public_key: max_conns_per_key,
+        max_half_tunnels,
+        max_tunnel_bytes_per_tick,
+    };
+
+    let stats_ticks = stats_interval.saturating_mul(1000) / TICK_MS;
+    if stats_ticks == 0 {
+        return Err(RelayServerBinError::InvalidStatsInterval);
+    }
 
     // Parse identity file:
     let identity =
         load_identity_from_file(&idfile).map_err(|_| RelayServerBinError::LoadIdentityError)?;
 
+    let peer_relays = match peers {
+        Some(peers) => load_peer_relays(&peers)
+            .map_err(RelayServerBinError::LoadPeerRelaysError)?
+            .into_iter()
+            .map(|relay_address| (relay_address.public_key, relay_address.address))
+            .collect(),
+        None => HashMap::new(),
+    };
+
     // Create a ThreadPool:
     let mut thread_pool =
         ThreadPool::new().map_err(|_| RelayServerBinError::CreateThreadPoolError)?;
 
+    // A thread pool for blocking computations:
+    let resolve_thread_pool =
+        ThreadPool::new().map_err(|_| RelayServerBinError::CreateThreadPoolError)?;
+
     // Spawn identity service:
     let (sender, identity_loop) = create_identity(identity);
     thread_pool
@@ -72,17 +153,71 @@ pub fn strelay(st_relay_cmd: StRelayCmd) -> Result<(), RelayServerBinError> {
     let timer_client = create_timer(dur, thread_pool.clone())
         .map_err(|_| RelayServerBinError::CreateTimerError)?;
 
+    // Watch the allowed nodes directory for changes (If provided):
+    let (mut allowed_sender, incoming_allowed_nodes) = mpsc::channel(0);
+    let opt_allowed_nodes = match allowed {
+        Some(allowed) => {
+            let allowed_nodes =
+                load_allowed_nodes(&allowed).map_err(RelayServerBinError::LoadAllowedNodesError)?;
+            let mut c_timer_client = timer_client.clone();
+            let c_allowed_nodes = allowed_nodes.clone();
+            let watcher_fut = async move {
+                let timer_stream = match await!(c_timer_client.request_timer_stream()) {
+                    Ok(timer_stream) => timer_stream,
+                    Err(e) => {
+                        error!("Failed requesting a timer stream: {:?}", e);
+                        return;
+                    }
+                };
+                let mut allowed_nodes_changes = watch_changes(
+                    move || load_allowed_nodes(&allowed),
+                    c_allowed_nodes,
+                    timer_stream,
+                    ALLOWED_SCAN_TICKS,
+                );
+                let _ = await!(allowed_sender.send_all(&mut allowed_nodes_changes));
+            };
+            thread_pool
+                .spawn(watcher_fut)
+                .map_err(|_| RelayServerBinError::SpawnAllowedWatcherError)?;
+            Some(allowed_nodes)
+        }
+        None => None,
+    };
+
+    // Write relay statistics reports to the log:
+    let (stats_sender, stats_receiver) = mpsc::channel(0);
+    let stats_logger_fut = stats_receiver.for_each(|relay_stats| {
+        log_relay_stats(&relay_stats);
+        future::ready(())
+    });
+    thread_pool
+        .spawn(stats_logger_fut)
+        .map_err(|_| RelayServerBinError::SpawnStatsLoggerError)?;
+
     let rng = system_random();
 
     let tcp_listener = TcpListener::new(MAX_FRAME_LENGTH, thread_pool.clone());
     let (_config_sender, incoming_raw_conns) = tcp_listener.listen(laddr);
 
+    // A tcp connector, Used to connect to peer relays:
+    let raw_peer_connector =
+        NetConnector::new(MAX_FRAME_LENGTH, resolve_thread_pool, thread_pool.clone());
+
     let relay_server_fut = net_relay_server(
         incoming_raw_conns,
+        raw_peer_connector,
+        opt_allowed_nodes,
+        incoming_allowed_nodes,
+        peer_relays,
         identity_client,
         timer_client,
         rng,
         MAX_CONCURRENT_ENCRYPT,
+        relay_limits,
+        BACKOFF_TICKS,
+        stats_ticks,
+        stats_sender,
         thread_pool.clone(),
     );
 
@@ -12,7 +12,7 @@ use common::conn::{BoxFuture, FutTransform};
 use common::select_streams::{select_streams, BoxStream};
 use timer::TimerClient;
 
-use crate::types::RawConn;
+use crate::types::{DirectAddress, RawConn};
 use crypto::identity::PublicKey;
 
 #[derive(Debug)]
@@ -121,7 +121,7 @@ where
 
 impl<RA, C, ET, S> ConnectPool<RA, C, ET, S>
 where
-    RA: Hash + Clone + Eq + Send + Debug + 'static,
+    RA: Hash + Clone + Eq + DirectAddress + Send + Debug + 'static,
     S: Spawn,
     ET: FutTransform<Input = (PublicKey, RawConn), Output = Option<RawConn>>
         + Clone
@@ -179,6 +179,15 @@ where
         Ok(cancel_sender)
     }
 
+    /// Take the next address for a new connection attempt.
+    /// A direct address is attempted first. Other addresses are attempted cyclically.
+    fn pop_address_direct_first(&mut self) -> Option<RA> {
+        match self.addresses.iter().position(RA::is_direct) {
+            Some(index) => self.addresses.remove(index),
+            None => self.addresses.pop_front(),
+        }
+    }
+
     pub fn handle_connect_request(
         &mut self,
         connect_request: CpConnectRequest,
@@ -188,7 +197,7 @@ where
             return Err(ConnectPoolError::MultipleConnectRequests);
         }
 
-        let address = match self.addresses.pop_front() {
+        let address = match self.pop_address_direct_first() {
             None => {
                 // We can't connect yet, because we don't know of any address.
                 self.status = CpStatus::Waiting((0, connect_request.response_sender));
@@ -211,7 +220,7 @@ where
         let status = mem::replace(&mut self.status, CpStatus::NoRequest);
         match (was_empty, status) {
             (true, CpStatus::Waiting((_remaining_ticks, response_sender))) => {
-                let address = self.addresses.pop_front().unwrap();
+                let address = self.pop_address_direct_first().unwrap();
                 let canceler = self.create_conn_attempt(address.clone())?;
                 self.status = CpStatus::Connecting((address, canceler, response_sender));
             }
@@ -321,7 +330,7 @@ async fn connect_pool_loop<RA, ET, TS, C, S>(
     mut opt_event_sender: Option<mpsc::Sender<()>>,
 ) -> Result<(), ConnectPoolError>
 where
-    RA: Hash + Clone + Eq + Send + Debug + 'static,
+    RA: Hash + Clone + Eq + DirectAddress + Send + Debug + 'static,
     C: FutTransform<Input = (RA, PublicKey), Output = Option<RawConn>> + Clone + Send + 'static,
     TS: Stream + Unpin + Send,
     ET: FutTransform<Input = (PublicKey, RawConn), Output = Option<RawConn>>
@@ -403,7 +412,7 @@ pub fn create_connect_pool<RA, ET, TS, C, S>(
     mut spawner: S,
 ) -> Result<ConnectPoolControl<RA>, ConnectPoolError>
 where
-    RA: Hash + Clone + Eq + Send + Debug + 'static,
+    RA: Hash + Clone + Eq + DirectAddress + Send + Debug + 'static,
     C: FutTransform<Input = (RA, PublicKey), Output = Option<RawConn>> + Clone + Send + 'static,
     TS: Stream + Unpin + Send + 'static,
     ET: FutTransform<Input = (PublicKey, RawConn), Output = Option<RawConn>>
@@ -451,7 +460,7 @@ pub struct PoolConnector<RA, C, ET, S> {
 
 impl<RA, C, ET, S> PoolConnector<RA, C, ET, S>
 where
-    RA: Hash + Clone + Eq + Send + 'static,
+    RA: Hash + Clone + Eq + DirectAddress + Send + 'static,
     C: FutTransform<Input = (RA, PublicKey), Output = Option<RawConn>> + Clone + Send + 'static,
     ET: FutTransform<Input = (PublicKey, RawConn), Output = Option<RawConn>>
         + Clone
@@ -479,7 +488,7 @@ where
 
 impl<RA, C, ET, S> FutTransform for PoolConnector<RA, C, ET, S>
 where
-    RA: Hash + Clone + Eq + Send + Debug + 'static,
+    RA: Hash + Clone + Eq + DirectAddress + Send + Debug + 'static,
     C: FutTransform<Input = (RA, PublicKey), Output = Option<RawConn>> + Clone + Send + 'static,
     ET: FutTransform<Input = (PublicKey, RawConn), Output = Option<RawConn>>
         + Clone
@@ -517,9 +526,16 @@ mod tests {
     use common::conn::FuncFutTransform;
     use common::dummy_connector::DummyConnector;
     use crypto::identity::PUBLIC_KEY_LEN;
+    use proto::app_server::messages::{ChannelAddress, RelayAddress};
 
     use timer::{dummy_timer_multi_sender, TimerTick};
 
+    impl DirectAddress for u32 {
+        fn is_direct(&self) -> bool {
+            false
+        }
+    }
+
     async fn task_pool_connector_cyclic_connect<S>(spawner: S)
     where
         S: Spawn + Clone + Send + 'static,
@@ -762,4 +778,127 @@ mod tests {
         let mut thread_pool = ThreadPool::new().unwrap();
         thread_pool.run(task_pool_connector_backoff_ticks(thread_pool.clone()));
     }
+
+    async fn task_pool_connector_direct_first<S>(mut spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        // Create a mock time service:
+        let (mut tick_sender_receiver, mut timer_client) =
+            dummy_timer_multi_sender(spawner.clone());
+
+        let backoff_ticks = 2;
+
+        let (conn_request_sender, mut conn_request_receiver) = mpsc::channel(0);
+        let client_connector = DummyConnector::new(conn_request_sender);
+
+        // We don't need encryption for this test:
+        let encrypt_transform = FuncFutTransform::new(|(_public_key, conn_pair)| {
+            Box::pin(future::ready(Some(conn_pair)))
+        });
+
+        let timer_stream = await!(timer_client.request_timer_stream()).unwrap();
+        let mut tick_sender = await!(tick_sender_receiver.next()).unwrap();
+
+        // Used for debugging the loop:
+        let (event_sender, mut event_receiver) = mpsc::channel(0);
+
+        let (request_sender, incoming_requests) = mpsc::channel(0);
+        let (config_sender, incoming_config) = mpsc::channel(0);
+
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+
+        let loop_fut = connect_pool_loop(
+            incoming_requests,
+            incoming_config,
+            timer_stream,
+            encrypt_transform,
+            pk_b.clone(), // friend_public_key
+            backoff_ticks,
+            client_connector,
+            spawner.clone(),
+            Some(event_sender),
+        )
+        .map_err(|e| error!("connect_pool_loop() error: {:?}", e))
+        .map(|_| ());
+
+        spawner.spawn(loop_fut).unwrap();
+
+        let mut connect_client = CpConnectClient::new(request_sender);
+        let mut config_client = CpConfigClient::new(config_sender);
+
+        let direct_address = ChannelAddress::Direct(0x2u32);
+        let addresses = vec![
+            ChannelAddress::Relay(RelayAddress {
+                public_key: PublicKey::from(&[0x0; PUBLIC_KEY_LEN]),
+                address: 0x0u32,
+            }),
+            ChannelAddress::Relay(RelayAddress {
+                public_key: PublicKey::from(&[0x1; PUBLIC_KEY_LEN]),
+                address: 0x1u32,
+            }),
+            direct_address.clone(),
+        ];
+        await!(config_client.config(addresses.clone())).unwrap();
+        await!(event_receiver.next()).unwrap();
+
+        // The direct address is attempted first.
+        // If it fails, we fall back to a relay:
+        let connect_fut = connect_client.connect();
+        let handle_connect_fut = async {
+            await!(event_receiver.next()).unwrap(); // Connection request event
+            let conn_request = await!(conn_request_receiver.next()).unwrap();
+            let (address, pk) = &conn_request.address;
+            assert_eq!(address, &direct_address);
+            assert_eq!(pk, &pk_b);
+
+            // Connection attempt failed:
+            conn_request.reply(None);
+            await!(event_receiver.next()).unwrap(); // connection attempt done event
+
+            // Wait backoff_ticks:
+            for _ in 0..backoff_ticks {
+                await!(tick_sender.send(TimerTick)).unwrap();
+                await!(event_receiver.next()).unwrap(); // timer tick event
+            }
+
+            let conn_request = await!(conn_request_receiver.next()).unwrap();
+            let (address, pk) = &conn_request.address;
+            assert!(!address.is_direct());
+            assert_eq!(pk, &pk_b);
+
+            let (local_sender, remote_receiver) = mpsc::channel(0);
+            let (remote_sender, local_receiver) = mpsc::channel(0);
+            conn_request.reply(Some((local_sender, local_receiver)));
+            await!(event_receiver.next()).unwrap(); // connection attempt done event
+            (remote_sender, remote_receiver)
+        };
+        let (local_conn, _remote_conn) = await!(connect_fut.join(handle_connect_fut));
+
+        // Drop the connection:
+        drop(local_conn);
+
+        // A new connection attempts the direct address first again:
+        let connect_fut = connect_client.connect();
+        let handle_connect_fut = async {
+            await!(event_receiver.next()).unwrap(); // Connection request event
+            let conn_request = await!(conn_request_receiver.next()).unwrap();
+            let (address, pk) = &conn_request.address;
+            assert_eq!(address, &direct_address);
+            assert_eq!(pk, &pk_b);
+
+            let (local_sender, remote_receiver) = mpsc::channel(0);
+            let (remote_sender, local_receiver) = mpsc::channel(0);
+            conn_request.reply(Some((local_sender, local_receiver)));
+            await!(event_receiver.next()).unwrap(); // connection attempt done event
+            (remote_sender, remote_receiver)
+        };
+        let (_local_conn, _remote_conn) = await!(connect_fut.join(handle_connect_fut));
+    }
+
+    #[test]
+    fn test_pool_connector_direct_first() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_pool_connector_direct_first(thread_pool.clone()));
+    }
 }
@@ -0,0 +1,431 @@
+use futures::channel::mpsc;
+use futures::task::{Spawn, SpawnExt};
+use futures::{future, stream, FutureExt, SinkExt, Stream, StreamExt, TryFutureExt};
+
+use common::conn::{BoxFuture, ConnPairVec, FutTransform, Listener};
+use common::select_streams::{select_streams, BoxStream};
+use common::transform_pool::transform_pool_loop;
+
+use crypto::identity::PublicKey;
+
+use proto::app_server::messages::{ChannelAddress, RelayAddress};
+
+use crate::types::{AccessControlOpPk, AccessControlPk, RawConn};
+
+/// Connect directly to a friend, without going through a relay.
+/// The connection is authenticated against the friend's public key.
+#[derive(Clone)]
+pub struct DirectConnector<C, ET, KT> {
+    connector: C,
+    encrypt_transform: ET,
+    keepalive_transform: KT,
+}
+
+impl<C, ET, KT> DirectConnector<C, ET, KT> {
+    pub fn new(connector: C, encrypt_transform: ET, keepalive_transform: KT) -> Self {
+        DirectConnector {
+            connector,
+            encrypt_transform,
+            keepalive_transform,
+        }
+    }
+}
+
+impl<B, C, ET, KT> FutTransform for DirectConnector<C, ET, KT>
+where
+    B: Send + 'static,
+    C: FutTransform<Input = B, Output = Option<ConnPairVec>> + Send,
+    ET: FutTransform<
+            Input = (Option<PublicKey>, ConnPairVec),
+            Output = Option<(PublicKey, ConnPairVec)>,
+        > + Send,
+    KT: FutTransform<Input = ConnPairVec, Output = ConnPairVec> + Send,
+{
+    type Input = (B, PublicKey);
+    type Output = Option<RawConn>;
+
+    fn transform(&mut self, input: Self::Input) -> BoxFuture<'_, Self::Output> {
+        let (address, friend_public_key) = input;
+
+        Box::pin(
+            async move {
+                let conn_pair = await!(self.connector.transform(address))?;
+                let (_public_key, conn_pair) = await!(self
+                    .encrypt_transform
+                    .transform((Some(friend_public_key), conn_pair)))?;
+                Some(await!(self.keepalive_transform.transform(conn_pair)))
+            },
+        )
+    }
+}
+
+/// Set up an incoming direct connection.
+/// Returns the public key of the remote side, because we can not predict it.
+#[derive(Clone)]
+struct DirectHandshakeTransform<VT, ET> {
+    version_transform: VT,
+    encrypt_transform: ET,
+}
+
+impl<VT, ET> DirectHandshakeTransform<VT, ET> {
+    pub fn new(version_transform: VT, encrypt_transform: ET) -> Self {
+        DirectHandshakeTransform {
+            version_transform,
+            encrypt_transform,
+        }
+    }
+}
+
+impl<VT, ET> FutTransform for DirectHandshakeTransform<VT, ET>
+where
+    VT: FutTransform<Input = ConnPairVec, Output = ConnPairVec> + Send,
+    ET: FutTransform<
+            Input = (Option<PublicKey>, ConnPairVec),
+            Output = Option<(PublicKey, ConnPairVec)>,
+        > + Send,
+{
+    type Input = ConnPairVec;
+    type Output = Option<(PublicKey, ConnPairVec)>;
+
+    fn transform(&mut self, conn_pair: Self::Input) -> BoxFuture<'_, Self::Output> {
+        Box::pin(
+            async move {
+                let conn_pair = await!(self.version_transform.transform(conn_pair));
+                await!(self.encrypt_transform.transform((None, conn_pair)))
+            },
+        )
+    }
+}
+
+enum DirectListenerEvent {
+    AccessControlOp(AccessControlOpPk),
+    AccessControlClosed,
+    Connection((PublicKey, ConnPairVec)),
+    ConnectionsClosed,
+}
+
+async fn inner_direct_listener<IC, IAC, KT>(
+    incoming_conns: IC,
+    incoming_access_control: IAC,
+    mut access_control: AccessControlPk,
+    mut connections_sender: mpsc::Sender<(PublicKey, RawConn)>,
+    mut keepalive_transform: KT,
+) where
+    IC: Stream<Item = (PublicKey, ConnPairVec)> + Send + 'static,
+    IAC: Stream<Item = AccessControlOpPk> + Send + 'static,
+    KT: FutTransform<Input = ConnPairVec, Output = ConnPairVec>,
+{
+    let incoming_access_control = incoming_access_control
+        .map(DirectListenerEvent::AccessControlOp)
+        .chain(stream::once(future::ready(
+            DirectListenerEvent::AccessControlClosed,
+        )));
+
+    let incoming_conns = incoming_conns
+        .map(DirectListenerEvent::Connection)
+        .chain(stream::once(future::ready(
+            DirectListenerEvent::ConnectionsClosed,
+        )));
+
+    let mut events = select_streams![incoming_access_control, incoming_conns];
+
+    while let Some(event) = await!(events.next()) {
+        match event {
+            DirectListenerEvent::AccessControlOp(access_control_op) => {
+                access_control.apply_op(access_control_op)
+            }
+            DirectListenerEvent::Connection((public_key, conn_pair)) => {
+                if !access_control.is_allowed(&public_key) {
+                    // The connection is closed when conn_pair is dropped:
+                    warn!(
+                        "inner_direct_listener(): Rejected connection from {:?}",
+                        public_key
+                    );
+                    continue;
+                }
+                let conn_pair = await!(keepalive_transform.transform(conn_pair));
+                if await!(connections_sender.send((public_key, conn_pair))).is_err() {
+                    break;
+                }
+            }
+            DirectListenerEvent::AccessControlClosed => break,
+            DirectListenerEvent::ConnectionsClosed => break,
+        }
+    }
+}
+
+/// Listen for direct connections from friends, without going through a relay.
+/// Only connections from remote nodes allowed by the access control are passed on.
+#[derive(Clone)]
+pub struct DirectListener<L, VT, ET, KT, S> {
+    raw_listener: L,
+    version_transform: VT,
+    encrypt_transform: ET,
+    keepalive_transform: KT,
+    max_concurrent_encrypt: usize,
+    spawner: S,
+}
+
+impl<L, VT, ET, KT, S> DirectListener<L, VT, ET, KT, S> {
+    pub fn new(
+        raw_listener: L,
+        version_transform: VT,
+        encrypt_transform: ET,
+        keepalive_transform: KT,
+        max_concurrent_encrypt: usize,
+        spawner: S,
+    ) -> Self {
+        DirectListener {
+            raw_listener,
+            version_transform,
+            encrypt_transform,
+            keepalive_transform,
+            max_concurrent_encrypt,
+            spawner,
+        }
+    }
+}
+
+impl<B, L, VT, ET, KT, S> Listener for DirectListener<L, VT, ET, KT, S>
+where
+    L: Listener<Connection = ConnPairVec, Config = (), Arg = B>,
+    VT: FutTransform<Input = ConnPairVec, Output = ConnPairVec> + Clone + Send + 'static,
+    ET: FutTransform<
+            Input = (Option<PublicKey>, ConnPairVec),
+            Output = Option<(PublicKey, ConnPairVec)>,
+        > + Clone
+        + Send
+        + 'static,
+    KT: FutTransform<Input = ConnPairVec, Output = ConnPairVec> + Clone + Send + 'static,
+    S: Spawn + Clone + Send + 'static,
+{
+    type Connection = (PublicKey, RawConn);
+    type Config = AccessControlOpPk;
+    type Arg = (B, AccessControlPk);
+
+    fn listen(
+        self,
+        arg: Self::Arg,
+    ) -> (mpsc::Sender<Self::Config>, mpsc::Receiver<Self::Connection>) {
+        let (address, access_control) = arg;
+        let DirectListener {
+            raw_listener,
+            version_transform,
+            encrypt_transform,
+            keepalive_transform,
+            max_concurrent_encrypt,
+            mut spawner,
+        } = self;
+
+        let (access_control_sender, incoming_access_control) = mpsc::channel(0);
+        let (connections_sender, connections_receiver) = mpsc::channel(0);
+
+        let (raw_config_sender, incoming_raw_conns) = raw_listener.listen(address);
+
+        // Set up encryption for incoming connections:
+        let (enc_conns_sender, incoming_enc_conns) = mpsc::channel(0);
+        let handshake_transform =
+            DirectHandshakeTransform::new(version_transform, encrypt_transform);
+        let enc_pool_fut = transform_pool_loop(
+            incoming_raw_conns,
+            enc_conns_sender,
+            handshake_transform,
+            max_concurrent_encrypt,
+            spawner.clone(),
+        )
+        .map_err(|e| error!("transform_pool_loop() error: {:?}", e))
+        .map(|_| ());
+
+        if spawner.spawn(enc_pool_fut).is_err() {
+            return (access_control_sender, connections_receiver);
+        }
+
+        let listener_fut = async move {
+            // We keep the raw listener open as long as we are listening:
+            let _raw_config_sender = raw_config_sender;
+            await!(inner_direct_listener(
+                incoming_enc_conns,
+                incoming_access_control,
+                access_control,
+                connections_sender,
+                keepalive_transform
+            ))
+        };
+        let _ = spawner.spawn(listener_fut);
+
+        (access_control_sender, connections_receiver)
+    }
+}
+
+/// Connect to a friend through a relay or directly, according to the address.
+#[derive(Clone)]
+pub struct ChannelConnector<RC, DC> {
+    relay_connector: RC,
+    direct_connector: DC,
+}
+
+impl<RC, DC> ChannelConnector<RC, DC> {
+    pub fn new(relay_connector: RC, direct_connector: DC) -> Self {
+        ChannelConnector {
+            relay_connector,
+            direct_connector,
+        }
+    }
+}
+
+impl<B, RC, DC> FutTransform for ChannelConnector<RC, DC>
+where
+    RC: FutTransform<Input = (RelayAddress<B>, PublicKey), Output = Option<RawConn>>,
+    DC: FutTransform<Input = (B, PublicKey), Output = Option<RawConn>>,
+{
+    type Input = (ChannelAddress<B>, PublicKey);
+    type Output = Option<RawConn>;
+
+    fn transform(&mut self, input: Self::Input) -> BoxFuture<'_, Self::Output> {
+        let (channel_address, friend_public_key) = input;
+        match channel_address {
+            ChannelAddress::Relay(relay_address) => self
+                .relay_connector
+                .transform((relay_address, friend_public_key)),
+            ChannelAddress::Direct(address) => self
+                .direct_connector
+                .transform((address, friend_public_key)),
+        }
+    }
+}
+
+/// Listen for connections from friends on a relay or directly, according to the address.
+#[derive(Clone)]
+pub struct ChannelListener<RL, DL> {
+    relay_listener: RL,
+    direct_listener: DL,
+}
+
+impl<RL, DL> ChannelListener<RL, DL> {
+    pub fn new(relay_listener: RL, direct_listener: DL) -> Self {
+        ChannelListener {
+            relay_listener,
+            direct_listener,
+        }
+    }
+}
+
+impl<B, RL, DL> Listener for ChannelListener<RL, DL>
+where
+    RL: Listener<
+        Connection = (PublicKey, RawConn),
+        Config = AccessControlOpPk,
+        Arg = (RelayAddress<B>, AccessControlPk),
+    >,
+    DL: Listener<
+        Connection = (PublicKey, RawConn),
+        Config = AccessControlOpPk,
+        Arg = (B, AccessControlPk),
+    >,
+{
+    type Connection = (PublicKey, RawConn);
+    type Config = AccessControlOpPk;
+    type Arg = (ChannelAddress<B>, AccessControlPk);
+
+    fn listen(
+        self,
+        arg: Self::Arg,
+    ) -> (mpsc::Sender<Self::Config>, mpsc::Receiver<Self::Connection>) {
+        let (channel_address, access_control) = arg;
+        match channel_address {
+            ChannelAddress::Relay(relay_address) => {
+                self.relay_listener.listen((relay_address, access_control))
+            }
+            ChannelAddress::Direct(address) => {
+                self.direct_listener.listen((address, access_control))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::ThreadPool;
+
+    use common::access_control::AccessControlOp;
+    use common::conn::FuncFutTransform;
+    use common::dummy_listener::DummyListener;
+    use crypto::identity::PUBLIC_KEY_LEN;
+
+    async fn task_direct_listener_access_control<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (req_sender, mut req_receiver) = mpsc::channel(0);
+        let raw_listener = DummyListener::new(req_sender, spawner.clone());
+
+        // We don't need a version prefix and keepalives for this test:
+        let version_transform =
+            FuncFutTransform::new(|conn_pair| Box::pin(future::ready(conn_pair)));
+        let keepalive_transform =
+            FuncFutTransform::new(|conn_pair| Box::pin(future::ready(conn_pair)));
+
+        // A mock encryption: The remote side identifies itself by sending one byte:
+        let encrypt_transform =
+            FuncFutTransform::new(|(_opt_public_key, conn_pair): (_, ConnPairVec)| {
+                Box::pin(
+                    async move {
+                        let (sender, mut receiver) = conn_pair;
+                        let data = await!(receiver.next())?;
+                        let public_key = PublicKey::from(&[data[0]; PUBLIC_KEY_LEN]);
+                        Some((public_key, (sender, receiver)))
+                    },
+                )
+            });
+
+        let direct_listener = DirectListener::new(
+            raw_listener,
+            version_transform,
+            encrypt_transform,
+            keepalive_transform,
+            2, // max_concurrent_encrypt
+            spawner.clone(),
+        );
+
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+
+        let mut access_control = AccessControlPk::new();
+        access_control.apply_op(AccessControlOp::Add(pk_a.clone()));
+
+        let (_access_control_sender, mut connections_receiver) =
+            direct_listener.listen((0x1337u32, access_control));
+
+        let listen_request = await!(req_receiver.next()).unwrap();
+        assert_eq!(listen_request.arg, 0x1337u32);
+        let mut conn_sender = listen_request.conn_sender;
+
+        // A connection from a node that is not allowed is closed:
+        let (local_sender, mut remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (mut remote_sender, local_receiver) = mpsc::channel::<Vec<u8>>(0);
+        await!(conn_sender.send((local_sender, local_receiver))).unwrap();
+        await!(remote_sender.send(vec![0xbb])).unwrap();
+        assert!(await!(remote_receiver.next()).is_none());
+
+        // A connection from an allowed node is passed on:
+        let (local_sender, mut remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (mut remote_sender, local_receiver) = mpsc::channel::<Vec<u8>>(0);
+        await!(conn_sender.send((local_sender, local_receiver))).unwrap();
+        await!(remote_sender.send(vec![0xaa])).unwrap();
+
+        let (public_key, (mut sender, mut receiver)) = await!(connections_receiver.next()).unwrap();
+        assert_eq!(public_key, pk_a);
+
+        await!(sender.send(vec![1, 2, 3])).unwrap();
+        assert_eq!(await!(remote_receiver.next()).unwrap(), vec![1, 2, 3]);
+        await!(remote_sender.send(vec![3, 2, 1])).unwrap();
+        assert_eq!(await!(receiver.next()).unwrap(), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_direct_listener_access_control() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_direct_listener_access_control(thread_pool.clone()));
+    }
+}
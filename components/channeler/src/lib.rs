@@ -21,6 +21,7 @@ extern crate common;
 mod channeler;
 mod connect_pool;
 mod connector_utils;
+mod direct;
 mod listen_pool;
 mod listen_pool_state;
 mod overwrite_channel;
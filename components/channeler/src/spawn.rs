@@ -4,7 +4,7 @@ use std::hash::Hash;
 use futures::channel::mpsc;
 use futures::task::Spawn;
 
-use common::conn::{BoxFuture, ConnPairVec, FutTransform};
+use common::conn::{BoxFuture, ConnPairVec, FutTransform, Listener};
 use timer::TimerClient;
 
 use crypto::identity::PublicKey;
@@ -13,7 +13,9 @@ use relay::{ClientConnector, ClientListener};
 
 use crate::channeler::{channeler_loop, ChannelerError};
 use crate::connect_pool::PoolConnector;
+use crate::direct::{ChannelConnector, ChannelListener, DirectConnector, DirectListener};
 use crate::listen_pool::PoolListener;
+use proto::app_server::messages::{ChannelAddress, RelayAddress};
 use proto::funder::messages::{ChannelerToFunder, FunderToChanneler};
 
 /// A connection style encrypt transform.
@@ -96,22 +98,35 @@ pub enum SpawnChannelerError {
 
 // TODO: Possibly rename this function and module, as the channeler future
 // is not spawned here.
-pub async fn spawn_channeler<RA, C, ET, KT, S>(
+/// Friends are reached through relays (Using `enc_relay_connector`), or directly.
+/// Direct connections are opened using `direct_connector`, and received from `raw_listener`.
+/// `version_transform` is applied to incoming direct connections.
+pub async fn spawn_channeler<B, C, DC, L, VT, ET, KT, S>(
     local_public_key: PublicKey,
     timer_client: TimerClient,
     backoff_ticks: usize,
     conn_timeout_ticks: usize,
     max_concurrent_encrypt: usize,
     enc_relay_connector: C,
+    direct_connector: DC,
+    raw_listener: L,
+    version_transform: VT,
     encrypt_transform: ET,
     keepalive_transform: KT,
-    from_funder: mpsc::Receiver<FunderToChanneler<RA>>,
+    from_funder: mpsc::Receiver<FunderToChanneler<ChannelAddress<B>>>,
     to_funder: mpsc::Sender<ChannelerToFunder>,
     spawner: S,
 ) -> Result<(), ChannelerError>
 where
-    RA: Eq + Hash + Clone + Send + Sync + Debug + 'static,
-    C: FutTransform<Input = RA, Output = Option<ConnPairVec>> + Clone + Send + Sync + 'static,
+    B: Eq + Hash + Clone + Send + Sync + Debug + 'static,
+    C: FutTransform<Input = RelayAddress<B>, Output = Option<ConnPairVec>>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    DC: FutTransform<Input = B, Output = Option<ConnPairVec>> + Clone + Send + Sync + 'static,
+    L: Listener<Connection = ConnPairVec, Config = (), Arg = B> + Clone + Send + Sync + 'static,
+    VT: FutTransform<Input = ConnPairVec, Output = ConnPairVec> + Clone + Send + Sync + 'static,
     ET: FutTransform<
             Input = (Option<PublicKey>, ConnPairVec),
             Output = Option<(PublicKey, ConnPairVec)>,
@@ -125,11 +140,19 @@ where
     let client_connector =
         ClientConnector::new(enc_relay_connector.clone(), keepalive_transform.clone());
 
+    let direct_connector = DirectConnector::new(
+        direct_connector,
+        encrypt_transform.clone(),
+        keepalive_transform.clone(),
+    );
+
+    let channel_connector = ChannelConnector::new(client_connector, direct_connector);
+
     let connect_encrypt_transform = ConnectEncryptTransform::new(encrypt_transform.clone());
 
     let pool_connector = PoolConnector::new(
         timer_client.clone(),
-        client_connector.clone(),
+        channel_connector,
         connect_encrypt_transform,
         backoff_ticks,
         spawner.clone(),
@@ -143,10 +166,21 @@ where
         spawner.clone(),
     );
 
+    let direct_listener = DirectListener::new(
+        raw_listener,
+        version_transform,
+        encrypt_transform.clone(),
+        keepalive_transform.clone(),
+        max_concurrent_encrypt,
+        spawner.clone(),
+    );
+
+    let channel_listener = ChannelListener::new(client_listener, direct_listener);
+
     let listen_encrypt_transform = ListenEncryptTransform::new(encrypt_transform.clone());
 
-    let pool_listener = PoolListener::<RA, _, _, _>::new(
-        client_listener,
+    let pool_listener = PoolListener::<ChannelAddress<B>, _, _, _>::new(
+        channel_listener,
         listen_encrypt_transform,
         max_concurrent_encrypt,
         backoff_ticks,
@@ -2,7 +2,24 @@ use common::access_control::{AccessControl, AccessControlOp};
 use common::conn::ConnPair;
 use crypto::identity::PublicKey;
 
+use proto::app_server::messages::ChannelAddress;
+
 pub type RawConn = ConnPair<Vec<u8>, Vec<u8>>;
 
 pub type AccessControlPk = AccessControl<PublicKey>;
 pub type AccessControlOpPk = AccessControlOp<PublicKey>;
+
+/// An address we can connect to a friend through.
+/// Direct addresses are preferred over addresses of relays.
+pub trait DirectAddress {
+    fn is_direct(&self) -> bool;
+}
+
+impl<B> DirectAddress for ChannelAddress<B> {
+    fn is_direct(&self) -> bool {
+        match self {
+            ChannelAddress::Direct(_) => true,
+            ChannelAddress::Relay(_) => false,
+        }
+    }
+}
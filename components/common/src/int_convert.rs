@@ -1,4 +1,5 @@
 use std::u32;
+use std::usize;
 
 #[cfg(any(
     target_pointer_width = "8",
@@ -35,6 +36,24 @@ pub fn u32_to_usize(num: u32) -> Option<usize> {
     Some(num as usize)
 }
 
+#[cfg(any(
+    target_pointer_width = "8",
+    target_pointer_width = "16",
+    target_pointer_width = "32"
+))]
+pub fn u64_to_usize(num: u64) -> Option<usize> {
+    if num > usize::MAX as u64 {
+        None
+    } else {
+        Some(num as usize)
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+pub fn u64_to_usize(num: u64) -> Option<usize> {
+    Some(num as usize)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,4 +82,14 @@ mod tests {
         assert_eq!(u32_to_usize(1u32), Some(1usize));
         assert_eq!(u32_to_usize(0xffff_ffff_u32), Some(0xffff_ffff_usize));
     }
+
+    #[test]
+    fn test_u64_to_usize() {
+        assert_eq!(u64_to_usize(0u64), Some(0usize));
+        assert_eq!(u64_to_usize(1u64), Some(1usize));
+        assert_eq!(
+            u64_to_usize(0xffff_ffff_ffff_ffff_u64),
+            Some(0xffff_ffff_ffff_ffff_usize)
+        );
+    }
 }
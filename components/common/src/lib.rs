@@ -35,5 +35,6 @@ pub mod mutable_state;
 pub mod select_streams;
 pub mod state_service;
 pub mod transform_pool;
+pub mod watcher;
 // pub mod wait_spawner;
 pub mod test_executor;
@@ -0,0 +1,68 @@
+use std::fmt::Debug;
+
+use futures::{future, Stream, StreamExt};
+
+/// Reload a value every `scan_ticks` ticks of `timer_stream` using `load`, and yield the new value
+/// whenever it changes. Used for watching configuration directories.
+///
+/// `value` is the value loaded before the watching started.
+/// If loading fails, the error is logged and the value is left unchanged until the next
+/// successful load.
+pub fn watch_changes<T, F, E, TS>(
+    mut load: F,
+    mut value: T,
+    timer_stream: TS,
+    scan_ticks: usize,
+) -> impl Stream<Item = T> + Unpin
+where
+    T: Clone + PartialEq,
+    F: FnMut() -> Result<T, E>,
+    E: Debug,
+    TS: Stream + Unpin,
+{
+    assert!(scan_ticks > 0);
+
+    let mut ticks_to_scan = scan_ticks;
+    timer_stream.filter_map(move |_| {
+        ticks_to_scan -= 1;
+        if ticks_to_scan > 0 {
+            return future::ready(None);
+        }
+        ticks_to_scan = scan_ticks;
+
+        let new_value = match load() {
+            Ok(new_value) => new_value,
+            Err(e) => {
+                warn!("watch_changes(): Failed loading: {:?}", e);
+                return future::ready(None);
+            }
+        };
+
+        if new_value == value {
+            return future::ready(None);
+        }
+        value = new_value.clone();
+        future::ready(Some(new_value))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::stream;
+
+    #[test]
+    fn test_watch_changes_basic() {
+        // Results of consecutive loads:
+        let mut load_results = vec![Ok(1u32), Err(()), Ok(2u32), Ok(2u32), Ok(3u32)].into_iter();
+        let load = move || load_results.next().unwrap();
+
+        let scan_ticks = 4;
+        let timer_stream = stream::iter(vec![(); 5 * scan_ticks + 2]);
+        let changes = watch_changes(load, 1u32, timer_stream, scan_ticks);
+
+        // Nothing changes, the load fails, and then only changes are reported:
+        assert_eq!(block_on(changes.collect::<Vec<_>>()), vec![2u32, 3u32]);
+    }
+}
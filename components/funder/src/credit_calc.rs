@@ -1,27 +1,21 @@
 #![warn(unused)]
 
-use std::convert::TryFrom;
-// use utils::int_convert::usize_to_u32;
+use proto::funder::messages::Rate;
 
-// TODO: Why do we take node_index and route_len as u32?
-// Possibly change this in the future?
-
-/// Amount of credit paid to a node that sent a valid Response
+/// Amount of credits paid to a node that sent a valid Response
 ///
 /// ```text
 ///    B  --  (C)  --   D   --   E   --   F
 /// ```
 ///
-/// In the example above, num_nodes = 5, node_index = 1 for the node C.
+/// In the example above, C will be paid `dest_payment` together with all the fees left to be
+/// paid to the nodes after B on the route (`left_fees`). C will keep its own fee and pass the rest
+/// of the credits to D.
+///
 /// Upon any overflow (u128) this function will return None.
 ///
-pub fn credits_on_success(node_index: u32, route_len: u32, dest_payment: u128) -> Option<u128> {
-    if node_index == 0 {
-        None
-    } else {
-        let dist = route_len.checked_sub(node_index)?.checked_sub(1)?;
-        u128::try_from(dist).ok()?.checked_add(dest_payment)
-    }
+pub fn credits_on_success(dest_payment: u128, left_fees: u128) -> Option<u128> {
+    dest_payment.checked_add(left_fees)
 }
 
 /// The amount of credits paid to a node in case of failure.
@@ -30,56 +24,61 @@ pub fn credits_on_failure() -> Option<u128> {
     Some(0)
 }
 
-/// Compute the amount of credits we need to freeze when sending a request to a node which is
-/// nodes_to_dest nodes from the destination.
+/// Compute the amount of credits we need to freeze when sending a request to a node,
+/// given the amount of fees left to be paid to the remaining nodes on the route.
 ///
 /// ```text
 ///                           req      req      req
 ///                           res      res      res      res
 ///                    B  --   C   --  (D)   --   E   --   F
-///
-/// node_index:        0       1        2         3        4
+/// ```
 ///
 /// In the above example, if we plan to send a message from C to D,
-/// we should have node_index = 2 in order to calculate the amount of credits C should freeze.
-/// ```
+/// we should use the `left_fees` value of the request sent from C to D.
 ///
-pub fn credits_to_freeze(node_index: u32, route_len: u32, dest_payment: u128) -> Option<u128> {
-    credits_on_success(node_index, route_len, dest_payment)
+pub fn credits_to_freeze(dest_payment: u128, left_fees: u128) -> Option<u128> {
+    credits_on_success(dest_payment, left_fees)
+}
+
+/// Calculate the amount of fees a mediator with the given rate takes for forwarding a request.
+/// Returns None if the request does not contain enough fees, or upon overflow.
+/// Otherwise, returns the pair (own_fee, new_left_fees).
+pub fn take_fee(rate: &Rate, dest_payment: u128, left_fees: u128) -> Option<(u128, u128)> {
+    let own_fee = rate.calc_fee(dest_payment)?;
+    let new_left_fees = left_fees.checked_sub(own_fee)?;
+    Some((own_fee, new_left_fees))
 }
 
-/// A credit calculator object that is wired to work with a specific request.
+/// A credit calculator object that is wired to work with a specific request,
+/// as it is sent between two adjacent nodes on the route.
 pub struct CreditCalculator {
-    route_len: u32,
     dest_payment: u128,
+    left_fees: u128,
 }
 
 impl CreditCalculator {
-    pub fn new(route_len: u32, dest_payment: u128) -> Self {
+    pub fn new(dest_payment: u128, left_fees: u128) -> Self {
         CreditCalculator {
-            route_len,
             dest_payment,
+            left_fees,
         }
     }
 
-    /// Amount of credits node <index-1> should freeze when sending
-    /// a request message to node <index>
-    /// Source node has index 0. Destination node has index route_len - 1.
-    pub fn credits_to_freeze(&self, node_index: u32) -> Option<u128> {
-        credits_to_freeze(node_index, self.route_len, self.dest_payment)
+    /// Amount of credits the sending node should freeze when sending
+    /// the request message to the next node.
+    pub fn credits_to_freeze(&self) -> Option<u128> {
+        credits_to_freeze(self.dest_payment, self.left_fees)
     }
 
-    /// Amount of credits to be paid to node <index> when it sends a valid response to node
-    /// <index-1>
-    /// Source node has index 0. Destination node has index route_len - 1.
-    pub fn credits_on_success(&self, node_index: u32) -> Option<u128> {
-        credits_on_success(node_index, self.route_len, self.dest_payment)
+    /// Amount of credits to be paid to the receiving node when it sends a valid response back to
+    /// the sending node.
+    pub fn credits_on_success(&self) -> Option<u128> {
+        credits_on_success(self.dest_payment, self.left_fees)
     }
 
-    /// Amount of credits to be paid to node <index> when it sends a failure message to node
-    /// <index-1>
-    /// Source node has index 0. Destination node has index route_len - 1.
-    pub fn credits_on_failure(&self, _node_index: u32, _reporting_node_index: u32) -> Option<u128> {
+    /// Amount of credits to be paid to the receiving node when it sends a failure message back
+    /// to the sending node.
+    pub fn credits_on_failure(&self) -> Option<u128> {
         credits_on_failure()
     }
 }
@@ -114,37 +113,68 @@ mod tests {
     }
     */
 
+    /// Simulate sending a request along a route of mediators with the given rates.
+    /// Returns the amount of credits paid on success for every hop.
+    fn route_success_credits(rates: &[Rate], dest_payment: u128, fees: u128) -> Vec<u128> {
+        let mut left_fees = fees;
+        let mut credits = vec![credits_on_success(dest_payment, left_fees).unwrap()];
+        for rate in rates {
+            let (_own_fee, new_left_fees) = take_fee(rate, dest_payment, left_fees).unwrap();
+            left_fees = new_left_fees;
+            credits.push(credits_on_success(dest_payment, left_fees).unwrap());
+        }
+        credits
+    }
+
     #[test]
     fn test_credits_on_success_monotone() {
-        let route_len = 40;
         let dest_payment = 100;
-        let mut opt_old_credits = None;
-        // Zero index doesn't get anything:
-        assert_eq!(credits_on_success(0, route_len, dest_payment), None);
-
-        // First index is paid the most, and the last node on the route gets the least. Payment is
+        let rates = vec![
+            Rate { mul: 0, add: 1 },
+            Rate {
+                mul: 1 << 31,
+                add: 0,
+            },
+            Rate { mul: 0, add: 0 },
+            Rate {
+                mul: 1 << 30,
+                add: 3,
+            },
+        ];
+        // 1 + 50 + 0 + 28:
+        let fees = 79;
+
+        // First hop is paid the most, and the last hop on the route gets the least. Payment is
         // telescopic:
-        for node_index in 1..route_len {
-            let credits = credits_on_success(node_index, route_len, dest_payment);
-            match opt_old_credits {
-                None => {}
-                Some(old_credits) => assert!(old_credits > credits),
-            };
-            opt_old_credits = Some(credits);
+        let credits = route_success_credits(&rates, dest_payment, fees);
+        assert_eq!(credits, vec![179, 178, 128, 128, 100]);
+        for pair in credits.windows(2) {
+            assert!(pair[0] >= pair[1]);
         }
     }
 
     #[test]
     fn test_credits_to_freeze_bigger_than_success() {
-        let route_len = 40;
         let dest_payment = 100;
-
-        // First index is paid the most, and the last node on the route gets the least. Payment is
-        // telescopic:
-        for node_index in 1..route_len {
-            let success_credits = credits_on_success(node_index, route_len, dest_payment);
-            let freeze_credits = credits_to_freeze(node_index, route_len, dest_payment);
+        for left_fees in 0..40 {
+            let success_credits = credits_on_success(dest_payment, left_fees);
+            let freeze_credits = credits_to_freeze(dest_payment, left_fees);
             assert!(freeze_credits >= success_credits);
+            assert!(freeze_credits >= credits_on_failure());
         }
     }
+
+    #[test]
+    fn test_take_fee_insufficient() {
+        let rate = Rate { mul: 0, add: 5 };
+        assert_eq!(take_fee(&rate, 100, 5), Some((5, 0)));
+        assert_eq!(take_fee(&rate, 100, 4), None);
+
+        // Overflow:
+        let rate = Rate {
+            mul: u32::max_value(),
+            add: 0,
+        };
+        assert_eq!(take_fee(&rate, u128::max_value(), u128::max_value()), None);
+    }
 }
@@ -0,0 +1,126 @@
+use std::cmp;
+
+use proto::funder::messages::DebtPolicy;
+
+/// Bookkeeping we keep for a friend that has a debt policy.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DebtPolicyStatus {
+    /// The balance with the friend the last time the policy was applied.
+    pub last_balance: i128,
+    /// Total amount of debt the friend has repaid us since the policy was set.
+    pub total_repaid: u128,
+    /// Were requests from the friend closed by the policy?
+    /// We only open requests that were closed by the policy.
+    pub closed_requests: bool,
+}
+
+impl DebtPolicyStatus {
+    pub fn new(balance: i128) -> Self {
+        DebtPolicyStatus {
+            last_balance: balance,
+            total_repaid: 0,
+            closed_requests: false,
+        }
+    }
+}
+
+/// The debt of the friend to us, given our balance with the friend.
+fn remote_debt(balance: i128) -> u128 {
+    if balance > 0 {
+        balance as u128
+    } else {
+        0
+    }
+}
+
+/// Account for debt repaid by the friend since the last time the policy was applied.
+/// A repayment is any decrease in the debt of the friend to us.
+pub fn update_repaid(status: &DebtPolicyStatus, balance: i128) -> DebtPolicyStatus {
+    let repaid = remote_debt(status.last_balance).saturating_sub(remote_debt(balance));
+    DebtPolicyStatus {
+        last_balance: balance,
+        total_repaid: status.total_repaid.saturating_add(repaid),
+        closed_requests: status.closed_requests,
+    }
+}
+
+/// Calculate the remote max debt a policy grants a friend that has repaid `total_repaid` credits.
+pub fn policy_remote_max_debt(debt_policy: &DebtPolicy, total_repaid: u128) -> u128 {
+    let bonus = total_repaid
+        .checked_mul(u128::from(debt_policy.repaid_percent))
+        .map(|bonus| bonus / 100)
+        .unwrap_or(u128::max_value());
+    cmp::min(
+        debt_policy.base_max_debt.saturating_add(bonus),
+        debt_policy.max_debt_cap,
+    )
+}
+
+/// Is the debt of the friend above the soft threshold of the policy?
+pub fn is_over_soft_threshold(debt_policy: &DebtPolicy, balance: i128) -> bool {
+    match debt_policy.opt_soft_threshold {
+        Some(soft_threshold) => remote_debt(balance) > soft_threshold,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use proto::funder::messages::Currency;
+
+    fn example_debt_policy() -> DebtPolicy {
+        DebtPolicy {
+            currency: Currency::try_from("USD".to_owned()).unwrap(),
+            base_max_debt: 100,
+            repaid_percent: 50,
+            max_debt_cap: 200,
+            opt_soft_threshold: Some(80),
+        }
+    }
+
+    #[test]
+    fn test_update_repaid() {
+        let status = DebtPolicyStatus::new(0);
+
+        // The friend gets into debt:
+        let status = update_repaid(&status, 60);
+        assert_eq!(status.last_balance, 60);
+        assert_eq!(status.total_repaid, 0);
+
+        // The friend repays some of the debt:
+        let status = update_repaid(&status, 20);
+        assert_eq!(status.total_repaid, 40);
+
+        // Only the debt counts as repaid, and not credits the friend gives us beyond its debt:
+        let status = update_repaid(&status, -30);
+        assert_eq!(status.last_balance, -30);
+        assert_eq!(status.total_repaid, 60);
+
+        let status = update_repaid(&status, -50);
+        assert_eq!(status.total_repaid, 60);
+    }
+
+    #[test]
+    fn test_policy_remote_max_debt() {
+        let debt_policy = example_debt_policy();
+        assert_eq!(policy_remote_max_debt(&debt_policy, 0), 100);
+        assert_eq!(policy_remote_max_debt(&debt_policy, 60), 130);
+        assert_eq!(policy_remote_max_debt(&debt_policy, 200), 200);
+        assert_eq!(policy_remote_max_debt(&debt_policy, 1000), 200);
+        assert_eq!(policy_remote_max_debt(&debt_policy, u128::max_value()), 200);
+    }
+
+    #[test]
+    fn test_is_over_soft_threshold() {
+        let mut debt_policy = example_debt_policy();
+        assert!(!is_over_soft_threshold(&debt_policy, -100));
+        assert!(!is_over_soft_threshold(&debt_policy, 80));
+        assert!(is_over_soft_threshold(&debt_policy, 81));
+
+        debt_policy.opt_soft_threshold = None;
+        assert!(!is_over_soft_threshold(&debt_policy, 1000));
+    }
+}
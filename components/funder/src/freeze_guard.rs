@@ -0,0 +1,439 @@
+use im::hashmap::HashMap as ImHashMap;
+
+use num_bigint::BigUint;
+
+use common::canonical_serialize::CanonicalSerialize;
+use crypto::identity::PublicKey;
+use crypto::uid::Uid;
+
+use proto::funder::messages::{Currency, FreezePolicy, PendingRequest, RequestSendFunds};
+
+use crate::credit_calc::credits_to_freeze;
+use crate::friend::ChannelStatus;
+use crate::state::FunderState;
+
+/// Get the next node on the route of a request, after the local node.
+/// Returns None if we are the destination of the request.
+fn next_on_route<'a>(
+    local_public_key: &PublicKey,
+    pending_request: &'a PendingRequest,
+) -> Option<&'a PublicKey> {
+    let local_index = pending_request.route.pk_to_index(local_public_key)?;
+    pending_request
+        .route
+        .index_to_pk(local_index.checked_add(1).unwrap())
+}
+
+/// Sum the credits frozen by a friend for requests it has sent us, grouped by currency and by the
+/// next friend on the route.
+///
+/// ```text
+/// J --> (A) --> I
+/// ```
+/// A is the local node, `pending_remote_requests` are the requests J has sent A that were not
+/// yet answered.
+pub fn frozen_credits_by_next(
+    local_public_key: &PublicKey,
+    pending_remote_requests: &ImHashMap<Uid, PendingRequest>,
+) -> ImHashMap<(Currency, PublicKey), u128> {
+    let mut frozen_credits = ImHashMap::new();
+    for pending_request in pending_remote_requests.values() {
+        let next_public_key = match next_on_route(local_public_key, pending_request) {
+            Some(next_public_key) => next_public_key,
+            // We are the destination of this request:
+            None => continue,
+        };
+        let credits =
+            credits_to_freeze(pending_request.dest_payment, pending_request.left_fees).unwrap();
+        let entry = frozen_credits
+            .entry((pending_request.currency.clone(), next_public_key.clone()))
+            .or_insert(0);
+        *entry = entry.checked_add(credits).unwrap();
+    }
+    frozen_credits
+}
+
+/// Was the request forwarded to the friend `next_public_key`?
+/// A forwarded request is either waiting in the friend's queue, or already pending in the token
+/// channel with this friend.
+fn is_forwarded<B>(state: &FunderState<B>, next_public_key: &PublicKey, request_id: &Uid) -> bool
+where
+    B: Clone + CanonicalSerialize,
+{
+    let friend = match state.friends.get(next_public_key) {
+        Some(friend) => friend,
+        None => return false,
+    };
+
+    if friend
+        .pending_requests
+        .iter()
+        .any(|request_send_funds| &request_send_funds.request_id == request_id)
+    {
+        return true;
+    }
+
+    match &friend.channel_status {
+        ChannelStatus::Consistent(token_channel) => token_channel
+            .get_mutual_credit()
+            .state()
+            .pending_requests
+            .pending_local_requests
+            .contains_key(request_id),
+        ChannelStatus::Inconsistent(_) => false,
+    }
+}
+
+/// Get the amount of credits (Of the given currency) `origin_public_key` has frozen for requests
+/// we have forwarded to `next_public_key`.
+fn get_frozen<B>(
+    state: &FunderState<B>,
+    origin_public_key: &PublicKey,
+    next_public_key: &PublicKey,
+    currency: &Currency,
+) -> u128
+where
+    B: Clone + CanonicalSerialize,
+{
+    let token_channel = match state.friends.get(origin_public_key) {
+        Some(friend) => match &friend.channel_status {
+            ChannelStatus::Consistent(token_channel) => token_channel,
+            ChannelStatus::Inconsistent(_) => return 0,
+        },
+        None => return 0,
+    };
+
+    let pending_remote_requests = &token_channel
+        .get_mutual_credit()
+        .state()
+        .pending_requests
+        .pending_remote_requests;
+
+    let mut frozen = 0u128;
+    for (request_id, pending_request) in pending_remote_requests {
+        if &pending_request.currency != currency {
+            continue;
+        }
+        if next_on_route(&state.local_public_key, pending_request) != Some(next_public_key) {
+            continue;
+        }
+        if !is_forwarded(state, next_public_key, request_id) {
+            continue;
+        }
+        let credits =
+            credits_to_freeze(pending_request.dest_payment, pending_request.left_fees).unwrap();
+        frozen = frozen.checked_add(credits).unwrap();
+    }
+    frozen
+}
+
+/// The maximum debt we allow a friend in a given currency.
+/// A friend with an inconsistent channel can not freeze credits, so we count it as zero.
+fn get_remote_max_debt<B>(
+    state: &FunderState<B>,
+    friend_public_key: &PublicKey,
+    currency: &Currency,
+) -> u128
+where
+    B: Clone + CanonicalSerialize,
+{
+    match state.friends.get(friend_public_key) {
+        Some(friend) => match &friend.channel_status {
+            ChannelStatus::Consistent(token_channel) => token_channel.get_remote_max_debt(currency),
+            ChannelStatus::Inconsistent(_) => 0,
+        },
+        None => 0,
+    }
+}
+
+/// The maximum amount of credits `origin_public_key` (J) may freeze for requests forwarded
+/// to `next_public_key` (I), according to the proportional credit freezing allocation:
+///
+/// `F{JA}_I <= md{AI} * md{AJ} / (md{A} - md{AJ})`
+///
+/// Where `md{AX}` is the maximum debt we (A) allow the friend X, and `md{A}` is the sum of the
+/// maximum debts we allow all of our friends. All the debts are of the same currency, as every
+/// currency has its own credit lines.
+fn max_frozen_proportional<B>(
+    state: &FunderState<B>,
+    origin_public_key: &PublicKey,
+    next_public_key: &PublicKey,
+    currency: &Currency,
+) -> BigUint
+where
+    B: Clone + CanonicalSerialize,
+{
+    let md_i = BigUint::from(get_remote_max_debt(state, next_public_key, currency));
+    let md_j = BigUint::from(get_remote_max_debt(state, origin_public_key, currency));
+    let md = state
+        .friends
+        .keys()
+        .map(|friend_public_key| {
+            BigUint::from(get_remote_max_debt(state, friend_public_key, currency))
+        })
+        .fold(BigUint::from(0u128), |acc, x| acc + x);
+
+    // origin_public_key is one of our friends, therefore md >= md_j:
+    let denominator = md - &md_j;
+    if denominator == BigUint::from(0u128) {
+        // We don't trust any other friend. Nothing may be frozen:
+        return BigUint::from(0u128);
+    }
+    md_i * md_j / denominator
+}
+
+/// Check if we may forward a request received from `origin_public_key` to `next_public_key`
+/// without letting `origin_public_key` freeze more credits than allowed by our freeze policy.
+///
+/// Returns None if forwarding the request is not allowed.
+pub fn verify_freeze<B>(
+    state: &FunderState<B>,
+    origin_public_key: &PublicKey,
+    next_public_key: &PublicKey,
+    request_send_funds: &RequestSendFunds,
+) -> Option<()>
+where
+    B: Clone + CanonicalSerialize,
+{
+    match state.freeze_policy {
+        FreezePolicy::Unlimited => Some(()),
+        FreezePolicy::Proportional => {
+            let credits = credits_to_freeze(
+                request_send_funds.dest_payment,
+                request_send_funds.left_fees,
+            )?;
+            let currency = &request_send_funds.currency;
+            let new_frozen = get_frozen(state, origin_public_key, next_public_key, currency)
+                .checked_add(credits)?;
+
+            if BigUint::from(new_frozen)
+                <= max_frozen_proportional(state, origin_public_key, next_public_key, currency)
+            {
+                Some(())
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crypto::identity::PUBLIC_KEY_LEN;
+    use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+    use crypto::uid::UID_LEN;
+
+    use std::convert::TryFrom;
+
+    use proto::funder::messages::{AddFriend, FriendsRoute};
+
+    use crate::friend::FriendMutation;
+    use crate::mutual_credit::types::McMutation;
+    use crate::state::FunderMutation;
+    use crate::token_channel::TcMutation;
+    use crate::types::create_pending_request;
+
+    fn usd() -> Currency {
+        Currency::try_from("USD".to_owned()).unwrap()
+    }
+
+    fn add_friend(
+        state: &mut FunderState<u32>,
+        friend_public_key: &PublicKey,
+        currency: &Currency,
+        max_debt: u128,
+    ) {
+        if !state.friends.contains_key(friend_public_key) {
+            let add_friend = AddFriend {
+                friend_public_key: friend_public_key.clone(),
+                relays: Vec::new(),
+                name: "friend".into(),
+                balances: Vec::new(),
+            };
+            state.mutate(&FunderMutation::AddFriend(add_friend));
+        }
+
+        let mc_mutation = McMutation::SetRemoteMaxDebt((currency.clone(), max_debt));
+        let friend_mutation = FriendMutation::TcMutation(TcMutation::McMutation(mc_mutation));
+        state.mutate(&FunderMutation::FriendMutation((
+            friend_public_key.clone(),
+            friend_mutation,
+        )));
+    }
+
+    fn dummy_request(
+        index: u8,
+        route: &[PublicKey],
+        currency: &Currency,
+        dest_payment: u128,
+    ) -> RequestSendFunds {
+        RequestSendFunds {
+            request_id: Uid::from(&[index; UID_LEN]),
+            route: FriendsRoute {
+                public_keys: route.to_vec(),
+            },
+            currency: currency.clone(),
+            dest_payment,
+            total_dest_payment: dest_payment,
+            left_fees: 0,
+            invoice_id: InvoiceId::from(&[index; INVOICE_ID_LEN]),
+            left_ticks: 0x10,
+        }
+    }
+
+    /// Simulate receiving a request from `origin_public_key`, and forwarding it to the next
+    /// friend on the route.
+    fn forward_request(
+        state: &mut FunderState<u32>,
+        origin_public_key: &PublicKey,
+        next_public_key: &PublicKey,
+        request_send_funds: &RequestSendFunds,
+    ) {
+        let pending_request = create_pending_request(request_send_funds);
+        let mc_mutation = McMutation::InsertRemotePendingRequest(pending_request);
+        let friend_mutation = FriendMutation::TcMutation(TcMutation::McMutation(mc_mutation));
+        state.mutate(&FunderMutation::FriendMutation((
+            origin_public_key.clone(),
+            friend_mutation,
+        )));
+
+        let friend_mutation = FriendMutation::PushBackPendingRequest(request_send_funds.clone());
+        state.mutate(&FunderMutation::FriendMutation((
+            next_public_key.clone(),
+            friend_mutation,
+        )));
+    }
+
+    #[test]
+    fn test_frozen_credits_by_next() {
+        /*
+         * j -- (a) -- i
+         *             |
+         *             k
+         */
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_i = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let pk_j = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+        let pk_k = PublicKey::from(&[0xdd; PUBLIC_KEY_LEN]);
+        let hours = Currency::try_from("HOURS".to_owned()).unwrap();
+
+        let mut pending_remote_requests = ImHashMap::new();
+        let requests = vec![
+            dummy_request(0, &[pk_j.clone(), pk_a.clone(), pk_i.clone()], &usd(), 10),
+            dummy_request(
+                1,
+                &[pk_j.clone(), pk_a.clone(), pk_i.clone(), pk_k.clone()],
+                &usd(),
+                20,
+            ),
+            // We are the destination:
+            dummy_request(2, &[pk_j.clone(), pk_a.clone()], &usd(), 40),
+            dummy_request(3, &[pk_j.clone(), pk_a.clone(), pk_i.clone()], &hours, 30),
+        ];
+        for request_send_funds in &requests {
+            let pending_request = create_pending_request(request_send_funds);
+            pending_remote_requests.insert(pending_request.request_id, pending_request);
+        }
+
+        let frozen_credits = frozen_credits_by_next(&pk_a, &pending_remote_requests);
+        assert_eq!(frozen_credits.len(), 2);
+        assert_eq!(
+            frozen_credits.get(&(usd(), pk_i.clone())).cloned(),
+            Some(credits_to_freeze(10, 0).unwrap() + credits_to_freeze(20, 0).unwrap())
+        );
+        assert_eq!(
+            frozen_credits.get(&(hours, pk_i.clone())).cloned(),
+            Some(credits_to_freeze(30, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_verify_freeze_proportional() {
+        /*
+         * j -- (a) -- i
+         *       |
+         *       k
+         */
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_i = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let pk_j = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+        let pk_k = PublicKey::from(&[0xdd; PUBLIC_KEY_LEN]);
+
+        let mut state = FunderState::<u32>::new(pk_a.clone(), Vec::new());
+        add_friend(&mut state, &pk_i, &usd(), 100);
+        add_friend(&mut state, &pk_j, &usd(), 50);
+        add_friend(&mut state, &pk_k, &usd(), 100);
+
+        // F{JA}_I <= md{AI} * md{AJ} / (md{A} - md{AJ}) = 100 * 50 / (250 - 50) = 25
+        assert_eq!(
+            max_frozen_proportional(&state, &pk_j, &pk_i, &usd()),
+            BigUint::from(25u128)
+        );
+
+        let route = [pk_j.clone(), pk_a.clone(), pk_i.clone()];
+        let request_send_funds = dummy_request(0, &route, &usd(), 26);
+
+        // The default policy does not limit frozen credits:
+        assert_eq!(
+            verify_freeze(&state, &pk_j, &pk_i, &request_send_funds),
+            Some(())
+        );
+
+        state.mutate(&FunderMutation::SetFreezePolicy(FreezePolicy::Proportional));
+        assert_eq!(
+            verify_freeze(&state, &pk_j, &pk_i, &request_send_funds),
+            None
+        );
+
+        let request_send_funds = dummy_request(1, &route, &usd(), 15);
+        assert_eq!(
+            verify_freeze(&state, &pk_j, &pk_i, &request_send_funds),
+            Some(())
+        );
+        forward_request(&mut state, &pk_j, &pk_i, &request_send_funds);
+        assert_eq!(
+            get_frozen(&state, &pk_j, &pk_i, &usd()),
+            credits_to_freeze(15, 0).unwrap()
+        );
+
+        // Credits frozen towards i were already used:
+        let request_send_funds = dummy_request(2, &route, &usd(), 15);
+        assert_eq!(
+            verify_freeze(&state, &pk_j, &pk_i, &request_send_funds),
+            None
+        );
+
+        // We allow no debt in other currencies:
+        let hours = Currency::try_from("HOURS".to_owned()).unwrap();
+        let request_send_funds = dummy_request(3, &route, &hours, 1);
+        assert_eq!(
+            verify_freeze(&state, &pk_j, &pk_i, &request_send_funds),
+            None
+        );
+
+        // Credits frozen towards k are accounted separately:
+        let route = [pk_j.clone(), pk_a.clone(), pk_k.clone()];
+        let request_send_funds = dummy_request(4, &route, &usd(), 15);
+        assert_eq!(
+            verify_freeze(&state, &pk_j, &pk_k, &request_send_funds),
+            Some(())
+        );
+    }
+
+    #[test]
+    fn test_max_frozen_proportional_single_friend() {
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_j = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+
+        let mut state = FunderState::<u32>::new(pk_a.clone(), Vec::new());
+        add_friend(&mut state, &pk_j, &usd(), 50);
+
+        // We trust no other friend, so nothing may be frozen:
+        assert_eq!(
+            max_frozen_proportional(&state, &pk_j, &pk_j, &usd()),
+            BigUint::from(0u128)
+        );
+    }
+}
@@ -1,17 +1,21 @@
+use im::hashmap::HashMap as ImHashMap;
 use im::vector::Vector as ImVec;
 use std::fmt::Debug;
 
 use crypto::identity::PublicKey;
+use crypto::uid::Uid;
 
 use common::canonical_serialize::CanonicalSerialize;
 use common::safe_arithmetic::SafeUnsignedArithmetic;
 
-use proto::app_server::messages::{NamedRelayAddress, RelayAddress};
+use proto::app_server::messages::ChannelAddress;
+use proto::consts::DEFAULT_RATE_ADD;
 use proto::funder::messages::{
-    FailureSendFunds, FriendStatus, PendingRequest, RequestSendFunds, RequestsStatus, ResetTerms,
-    ResponseSendFunds,
+    Currency, CurrencyBalance, DebtPolicy, FailureSendFunds, FriendStatus, PendingRequest, Rate,
+    RequestSendFunds, RequestsStatus, ResetTerms, ResponseSendFunds, SettleStatus,
 };
 
+use crate::debt_policy::DebtPolicyStatus;
 use crate::token_channel::{TcMutation, TokenChannel};
 use crate::types::MoveTokenHashed;
 
@@ -23,42 +27,55 @@ pub enum ResponseOp {
     UnsignedFailure(PendingRequest),
 }
 
+impl ResponseOp {
+    /// The request this operation answers.
+    pub fn request_id(&self) -> &Uid {
+        match self {
+            ResponseOp::Response(response_send_funds) => &response_send_funds.request_id,
+            ResponseOp::UnsignedResponse(pending_request) => &pending_request.request_id,
+            ResponseOp::Failure(failure_send_funds) => &failure_send_funds.request_id,
+            ResponseOp::UnsignedFailure(pending_request) => &pending_request.request_id,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SentLocalRelays<B>
 where
     B: Clone,
 {
     NeverSent,
-    Transition((ImVec<NamedRelayAddress<B>>, ImVec<NamedRelayAddress<B>>)), // (last sent, before last sent)
-    LastSent(ImVec<NamedRelayAddress<B>>),
+    Transition((ImVec<ChannelAddress<B>>, ImVec<ChannelAddress<B>>)), // (last sent, before last sent)
+    LastSent(ImVec<ChannelAddress<B>>),
 }
 
 impl<B> SentLocalRelays<B>
 where
-    B: Clone + Debug,
+    B: Clone + PartialEq + Debug,
 {
-    pub fn to_vec(&self) -> Vec<RelayAddress<B>> {
+    pub fn to_vec(&self) -> Vec<ChannelAddress<B>> {
         match self {
             SentLocalRelays::NeverSent => Vec::new(),
             SentLocalRelays::Transition((last_relays, prev_last_relays)) => {
-                // Create a unique list of all relay public keys:
-                let mut relays: Vec<RelayAddress<B>> = Vec::new();
-                for relay in last_relays {
-                    relays.push(relay.clone().into());
+                // Create a unique list of all relay public keys and direct addresses:
+                let mut relays: Vec<ChannelAddress<B>> = Vec::new();
+                for relay in last_relays.iter().chain(prev_last_relays.iter()) {
+                    let is_duplicate = relays.iter().any(|cur_relay| match (cur_relay, relay) {
+                        (ChannelAddress::Relay(cur), ChannelAddress::Relay(new)) => {
+                            cur.public_key == new.public_key
+                        }
+                        (ChannelAddress::Direct(cur), ChannelAddress::Direct(new)) => cur == new,
+                        _ => false,
+                    });
+                    if !is_duplicate {
+                        relays.push(relay.clone());
+                    }
                 }
-                for relay in prev_last_relays {
-                    relays.push(relay.clone().into());
-                }
-                // Note: a vector must be sorted in order to use dedup_by_key()!
-                relays.sort_by_key(|relay_address| relay_address.public_key.clone());
-                relays.dedup_by_key(|relay_address| relay_address.public_key.clone());
                 relays
             }
-            SentLocalRelays::LastSent(last_address) => last_address
-                .iter()
-                .cloned()
-                .map(Into::into)
-                .collect::<Vec<_>>(),
+            SentLocalRelays::LastSent(last_address) => {
+                last_address.iter().cloned().collect::<Vec<_>>()
+            }
         }
     }
 }
@@ -69,18 +86,24 @@ pub enum FriendMutation<B: Clone> {
     TcMutation(TcMutation<B>),
     SetInconsistent(ChannelInconsistent),
     SetConsistent(TokenChannel<B>),
-    SetWantedRemoteMaxDebt(u128),
+    SetWantedRemoteMaxDebt((Currency, u128)),
     SetWantedLocalRequestsStatus(RequestsStatus),
     PushBackPendingRequest(RequestSendFunds),
     PopFrontPendingRequest,
+    RemovePendingRequest(Uid),
     PushBackPendingResponse(ResponseOp),
     PopFrontPendingResponse,
     PushBackPendingUserRequest(RequestSendFunds),
     PopFrontPendingUserRequest,
+    RemovePendingUserRequest(Uid),
     SetStatus(FriendStatus),
-    SetRemoteRelays(Vec<RelayAddress<B>>),
+    SetRemoteRelays(Vec<ChannelAddress<B>>),
     SetName(String),
     SetSentLocalRelays(SentLocalRelays<B>),
+    SetRate(Rate),
+    SetDebtPolicy(Option<DebtPolicy>),
+    SetDebtPolicyStatus(DebtPolicyStatus),
+    SetSettleStatus(SettleStatus),
 }
 
 #[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
@@ -117,11 +140,11 @@ where
 pub struct FriendState<B: Clone> {
     pub local_public_key: PublicKey,
     pub remote_public_key: PublicKey,
-    pub remote_relays: Vec<RelayAddress<B>>,
+    pub remote_relays: Vec<ChannelAddress<B>>,
     pub sent_local_relays: SentLocalRelays<B>,
     pub name: String,
     pub channel_status: ChannelStatus<B>,
-    pub wanted_remote_max_debt: u128,
+    pub wanted_remote_max_debt: ImHashMap<Currency, u128>,
     pub wanted_local_requests_status: RequestsStatus,
     pub pending_requests: ImVec<RequestSendFunds>,
     pub pending_responses: ImVec<ResponseOp>,
@@ -130,6 +153,14 @@ pub struct FriendState<B: Clone> {
     pub pending_user_requests: ImVec<RequestSendFunds>,
     // Request that the user has sent to this neighbor,
     // but have not been processed yet. Bounded in size.
+    pub rate: Rate,
+    // The fee we charge this friend for forwarding its requests.
+    pub opt_debt_policy: Option<DebtPolicy>,
+    // Rules for automatically managing the remote max debt of this friend.
+    pub debt_policy_status: DebtPolicyStatus,
+    // Bookkeeping for the debt policy.
+    pub settle_status: SettleStatus,
+    // Settlement of the mutual credit, before closing the channel.
 }
 
 impl<B> FriendState<B>
@@ -139,11 +170,11 @@ where
     pub fn new(
         local_public_key: &PublicKey,
         remote_public_key: &PublicKey,
-        remote_relays: Vec<RelayAddress<B>>,
+        remote_relays: Vec<ChannelAddress<B>>,
         name: String,
-        balance: i128,
+        balances: &[CurrencyBalance],
     ) -> Self {
-        let token_channel = TokenChannel::new(local_public_key, remote_public_key, balance);
+        let token_channel = TokenChannel::new(local_public_key, remote_public_key, balances);
 
         FriendState {
             local_public_key: local_public_key.clone(),
@@ -153,9 +184,9 @@ where
             name,
             channel_status: ChannelStatus::Consistent(token_channel),
 
-            // The remote_max_debt we want to have. When possible, this will be sent to the remote
-            // side.
-            wanted_remote_max_debt: 0,
+            // The remote_max_debt we want to have for every currency. When possible, this will be
+            // sent to the remote side.
+            wanted_remote_max_debt: ImHashMap::new(),
             wanted_local_requests_status: RequestsStatus::Closed,
             // The local_send_price we want to have (Or possibly close requests, by having an empty
             // send price). When possible, this will be updated with the TokenChannel.
@@ -163,11 +194,21 @@ where
             pending_responses: ImVec::new(),
             status: FriendStatus::Disabled,
             pending_user_requests: ImVec::new(),
+            // Initially we charge a constant fee for every forwarded request:
+            rate: Rate {
+                mul: 0,
+                add: DEFAULT_RATE_ADD,
+            },
+            // Initially the remote max debt is managed manually:
+            opt_debt_policy: None,
+            // The status is initialized when a debt policy is set:
+            debt_policy_status: DebtPolicyStatus::new(0),
+            settle_status: SettleStatus::Active,
         }
     }
 
     // TODO: Do we use this function somewhere?
-    /// Find the shared credits we have with this friend.
+    /// Find the shared credits we have with this friend in a given currency.
     /// This value is used for freeze guard calculations.
     /// This value is the capacity shared between the rest of the friends.
     ///
@@ -181,13 +222,20 @@ where
     /// In the picture above, the shared credits between O and A will be shared between the nodes
     /// B, C and D.
     ///
-    pub fn get_shared_credits(&self) -> u128 {
-        let balance = match &self.channel_status {
-            ChannelStatus::Consistent(token_channel) => {
-                &token_channel.get_mutual_credit().state().balance
-            }
+    pub fn get_shared_credits(&self, currency: &Currency) -> u128 {
+        let token_channel = match &self.channel_status {
+            ChannelStatus::Consistent(token_channel) => token_channel,
             ChannelStatus::Inconsistent(_channel_inconsistent) => return 0,
         };
+        let balance = match token_channel
+            .get_mutual_credit()
+            .state()
+            .balances
+            .get(currency)
+        {
+            Some(balance) => balance,
+            None => return 0,
+        };
         balance
             .local_max_debt
             .saturating_add_signed(balance.balance)
@@ -207,8 +255,9 @@ where
             FriendMutation::SetConsistent(token_channel) => {
                 self.channel_status = ChannelStatus::Consistent(token_channel.clone());
             }
-            FriendMutation::SetWantedRemoteMaxDebt(wanted_remote_max_debt) => {
-                self.wanted_remote_max_debt = *wanted_remote_max_debt;
+            FriendMutation::SetWantedRemoteMaxDebt((currency, wanted_remote_max_debt)) => {
+                self.wanted_remote_max_debt
+                    .insert(currency.clone(), *wanted_remote_max_debt);
             }
             FriendMutation::SetWantedLocalRequestsStatus(wanted_local_requests_status) => {
                 self.wanted_local_requests_status = wanted_local_requests_status.clone();
@@ -219,6 +268,10 @@ where
             FriendMutation::PopFrontPendingRequest => {
                 let _ = self.pending_requests.pop_front();
             }
+            FriendMutation::RemovePendingRequest(request_id) => {
+                self.pending_requests
+                    .retain(|request_send_funds| &request_send_funds.request_id != request_id);
+            }
             FriendMutation::PushBackPendingResponse(response_op) => {
                 self.pending_responses.push_back(response_op.clone());
             }
@@ -232,6 +285,10 @@ where
             FriendMutation::PopFrontPendingUserRequest => {
                 let _ = self.pending_user_requests.pop_front();
             }
+            FriendMutation::RemovePendingUserRequest(request_id) => {
+                self.pending_user_requests
+                    .retain(|request_send_funds| &request_send_funds.request_id != request_id);
+            }
             FriendMutation::SetStatus(friend_status) => {
                 self.status = friend_status.clone();
             }
@@ -244,6 +301,18 @@ where
             FriendMutation::SetSentLocalRelays(sent_local_relays) => {
                 self.sent_local_relays = sent_local_relays.clone();
             }
+            FriendMutation::SetRate(rate) => {
+                self.rate = rate.clone();
+            }
+            FriendMutation::SetDebtPolicy(opt_debt_policy) => {
+                self.opt_debt_policy = opt_debt_policy.clone();
+            }
+            FriendMutation::SetDebtPolicyStatus(debt_policy_status) => {
+                self.debt_policy_status = debt_policy_status.clone();
+            }
+            FriendMutation::SetSettleStatus(settle_status) => {
+                self.settle_status = settle_status.clone();
+            }
         }
     }
 }
@@ -1,7 +1,8 @@
 use std::fmt::Debug;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use futures::channel::mpsc;
-use futures::{future, stream, SinkExt, StreamExt};
+use futures::{future, stream, SinkExt, Stream, StreamExt};
 
 use common::canonical_serialize::CanonicalSerialize;
 
@@ -28,6 +29,14 @@ pub enum FunderError {
     SendCommError,
 }
 
+/// Current time, in seconds since the Unix epoch.
+fn now_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
 #[derive(Debug, Clone)]
 pub enum FunderEvent<B> {
     FunderIncoming(FunderIncoming<B>),
@@ -35,11 +44,12 @@ pub enum FunderEvent<B> {
     IncomingCommClosed,
 }
 
-pub async fn inner_funder_loop<B, R>(
+pub async fn inner_funder_loop<B, R, TS>(
     mut identity_client: IdentityClient,
     rng: R,
     incoming_control: mpsc::Receiver<FunderIncomingControl<B>>,
     incoming_comm: mpsc::Receiver<FunderIncomingComm<B>>,
+    timer_stream: TS,
     control_sender: mpsc::Sender<FunderOutgoingControl<B>>,
     comm_sender: mpsc::Sender<FunderOutgoingComm<B>>,
     mut funder_state: FunderState<B>,
@@ -47,11 +57,13 @@ pub async fn inner_funder_loop<B, R>(
     max_operations_in_batch: usize,
     max_node_relays: usize,
     max_pending_user_requests: usize,
+    opt_max_payment_history: Option<usize>,
     mut opt_event_sender: Option<mpsc::Sender<FunderEvent<B>>>,
 ) -> Result<(), FunderError>
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
     R: CryptoRandom + 'static,
+    TS: Stream + Unpin,
 {
     // Transform error type:
     let mut comm_sender = comm_sender.sink_map_err(|_| ());
@@ -73,11 +85,13 @@ where
             FunderEvent::FunderIncoming(FunderIncoming::Comm(incoming_comm_msg))
         })
         .chain(stream::once(future::ready(FunderEvent::IncomingCommClosed)));
+    let timer_stream =
+        timer_stream.map(|_| FunderEvent::FunderIncoming(FunderIncoming::TimerTick));
     // Chain the Init message first:
     let mut incoming_messages = stream::once(future::ready(FunderEvent::FunderIncoming(
         FunderIncoming::Init,
     )))
-    .chain(incoming_control.select(incoming_comm));
+    .chain(incoming_control.select(incoming_comm).select(timer_stream));
 
     while let Some(funder_event) = await!(incoming_messages.next()) {
         // For testing:
@@ -96,6 +110,8 @@ where
             max_node_relays,
             max_operations_in_batch,
             max_pending_user_requests,
+            opt_max_payment_history,
+            now_timestamp(),
             funder_incoming
         ));
 
@@ -140,28 +156,32 @@ where
     Ok(())
 }
 
-pub async fn funder_loop<B, R>(
+pub async fn funder_loop<B, R, TS>(
     identity_client: IdentityClient,
     rng: R,
     incoming_control: mpsc::Receiver<FunderIncomingControl<B>>,
     incoming_comm: mpsc::Receiver<FunderIncomingComm<B>>,
+    timer_stream: TS,
     control_sender: mpsc::Sender<FunderOutgoingControl<B>>,
     comm_sender: mpsc::Sender<FunderOutgoingComm<B>>,
     max_operations_in_batch: usize,
     max_node_relays: usize,
     max_pending_user_requests: usize,
+    opt_max_payment_history: Option<usize>,
     funder_state: FunderState<B>,
     db_client: DatabaseClient<FunderMutation<B>>,
 ) -> Result<(), FunderError>
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
     R: CryptoRandom + 'static,
+    TS: Stream + Unpin,
 {
     await!(inner_funder_loop(
         identity_client,
         rng,
         incoming_control,
         incoming_comm,
+        timer_stream,
         control_sender,
         comm_sender,
         funder_state,
@@ -169,6 +189,7 @@ where
         max_operations_in_batch,
         max_node_relays,
         max_pending_user_requests,
+        opt_max_payment_history,
         None
     ))
 }
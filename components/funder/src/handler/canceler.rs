@@ -1,16 +1,23 @@
 use common::canonical_serialize::CanonicalSerialize;
 use crypto::identity::PublicKey;
+use crypto::invoice_id::InvoiceId;
+use crypto::uid::Uid;
 use std::fmt::Debug;
 
-use proto::funder::messages::{
-    FunderOutgoingControl, RequestSendFunds, ResponseReceived, ResponseSendFundsResult,
-};
+use common::int_convert::usize_to_u64;
+
+use proto::consts::REQUEST_TIMEOUT_HOP_TICKS;
+use proto::funder::messages::{FunderOutgoingControl, RequestSendFunds};
 
-use crate::handler::handler::{find_request_origin, MutableFunderState};
+use crate::handler::handler::{
+    find_mediated_origin, find_request_origin, is_response_queued, MutableFunderState,
+};
+use crate::handler::history::fail_user_request;
 use crate::handler::sender::SendCommands;
 
 use crate::friend::{ChannelStatus, FriendMutation, ResponseOp};
-use crate::state::FunderMutation;
+use crate::state::{FunderMutation, FunderState};
+use crate::timeouts::{PendingOrigin, TimeoutsMutation};
 use crate::types::create_pending_request;
 
 /*
@@ -56,6 +63,66 @@ pub fn reply_with_failure<B>(
     send_commands.set_try_send(remote_public_key);
 }
 
+/// Cancel a payment split over multiple routes (where we are the destination).
+/// A failure is sent back for every part of the payment that arrived so far, except for parts
+/// that arrived from `opt_skip_public_key`.
+pub fn cancel_incoming_payment<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    invoice_id: &InvoiceId,
+    opt_skip_public_key: Option<&PublicKey>,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let incoming_payment = match m_state.state().incoming_payments.get(invoice_id) {
+        Some(incoming_payment) => incoming_payment.clone(),
+        None => return,
+    };
+
+    for (friend_public_key, pending_request) in incoming_payment.parts {
+        if Some(&friend_public_key) == opt_skip_public_key {
+            continue;
+        }
+        let u_failure_op = ResponseOp::UnsignedFailure(pending_request);
+        let friend_mutation = FriendMutation::PushBackPendingResponse(u_failure_op);
+        let funder_mutation =
+            FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+        m_state.mutate(funder_mutation);
+        send_commands.set_try_send(&friend_public_key);
+    }
+
+    let funder_mutation = FunderMutation::RemoveIncomingPayment(invoice_id.clone());
+    m_state.mutate(funder_mutation);
+}
+
+/// Cancel all the incoming payments that have a part that arrived from a given friend.
+/// This is required when the token channel with the friend is reset or the friend is removed:
+/// The parts that arrived from this friend can not be answered anymore.
+pub fn cancel_friend_incoming_payments<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    friend_public_key: &PublicKey,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let invoice_ids: Vec<InvoiceId> = m_state
+        .state()
+        .incoming_payments
+        .iter()
+        .filter(|(_, incoming_payment)| {
+            incoming_payment
+                .parts
+                .iter()
+                .any(|(part_public_key, _)| part_public_key == friend_public_key)
+        })
+        .map(|(invoice_id, _)| invoice_id.clone())
+        .collect();
+
+    for invoice_id in invoice_ids {
+        cancel_incoming_payment(m_state, send_commands, &invoice_id, Some(friend_public_key));
+    }
+}
+
 /// Cancel outgoing local requests that are already inside the token channel (Possibly already
 /// communicated to the remote side).
 pub fn cancel_local_pending_requests<B>(
@@ -84,9 +151,8 @@ pub fn cancel_local_pending_requests<B>(
         .clone();
 
     // Prepare a list of all remote requests that we need to cancel:
-    for (local_request_id, pending_local_request) in pending_local_requests {
-        let opt_origin_public_key =
-            find_request_origin(m_state.state(), &local_request_id).cloned();
+    for (_, pending_local_request) in pending_local_requests {
+        let opt_origin_public_key = find_mediated_origin(m_state.state(), &pending_local_request);
         match opt_origin_public_key {
             Some(origin_public_key) => {
                 // We have found the friend that is the origin of this request.
@@ -99,15 +165,17 @@ pub fn cancel_local_pending_requests<B>(
                 send_commands.set_try_send(&origin_public_key);
             }
             None => {
+                let request_id = &pending_local_request.request_id;
+                if m_state.state().timeouts.unbacked.contains_key(request_id) {
+                    // We have already failed this request back to its origin, and we will never
+                    // obtain a response for it:
+                    let timeouts_mutation = TimeoutsMutation::RemoveUnbacked(request_id.clone());
+                    m_state.mutate(FunderMutation::TimeoutsMutation(timeouts_mutation));
+                    continue;
+                }
                 // We are the origin of this request.
                 // We send a failure response through the control:
-                let response_received = ResponseReceived {
-                    request_id: pending_local_request.request_id,
-                    result: ResponseSendFundsResult::Failure(
-                        m_state.state().local_public_key.clone(),
-                    ),
-                };
-                outgoing_control.push(FunderOutgoingControl::ResponseReceived(response_received));
+                fail_user_request(m_state, outgoing_control, &pending_local_request);
             }
         };
     }
@@ -144,13 +212,8 @@ pub fn cancel_pending_requests<B>(
             }
             None => {
                 // We are the origin of this request:
-                let response_received = ResponseReceived {
-                    request_id: pending_request.request_id,
-                    result: ResponseSendFundsResult::Failure(
-                        m_state.state().local_public_key.clone(),
-                    ),
-                };
-                outgoing_control.push(FunderOutgoingControl::ResponseReceived(response_received));
+                let local_pending_request = create_pending_request(&pending_request);
+                fail_user_request(m_state, outgoing_control, &local_pending_request);
             }
         };
     }
@@ -173,10 +236,181 @@ pub fn cancel_pending_user_requests<B>(
         m_state.mutate(funder_mutation);
 
         // We are the origin of this request:
-        let response_received = ResponseReceived {
-            request_id: pending_user_request.request_id,
-            result: ResponseSendFundsResult::Failure(m_state.state().local_public_key.clone()),
-        };
-        outgoing_control.push(FunderOutgoingControl::ResponseReceived(response_received));
+        let pending_request = create_pending_request(&pending_user_request);
+        fail_user_request(m_state, outgoing_control, &pending_request);
+    }
+}
+
+/// Check if a request was already sent to a friend, and is waiting for the friend to answer.
+fn is_request_forwarded<B>(
+    state: &FunderState<B>,
+    friend_public_key: &PublicKey,
+    request_id: &Uid,
+) -> bool
+where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let friend = match state.friends.get(friend_public_key) {
+        Some(friend) => friend,
+        None => return false,
+    };
+
+    match &friend.channel_status {
+        ChannelStatus::Inconsistent(_) => false,
+        ChannelStatus::Consistent(token_channel) => token_channel
+            .get_mutual_credit()
+            .state()
+            .pending_requests
+            .pending_local_requests
+            .contains_key(request_id),
     }
 }
+
+/// Cancel a request that was received from a friend and was not answered in time.
+/// A failure is sent back to the friend, which unfreezes the credits pending in the token
+/// channel with that friend.
+///
+/// If the request was already forwarded to the next friend on the route, the next friend gets
+/// a few more ticks to answer, according to the amount of hops left on the route (See
+/// `PendingOrigin::Forwarded`). `is_forwarded_deadline` is true once these extra ticks have
+/// passed too. In that case the request is failed back to the friend, and the request pending
+/// with the next friend is recorded as unbacked.
+pub fn cancel_remote_request<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    remote_public_key: &PublicKey,
+    request_id: &Uid,
+    is_forwarded_deadline: bool,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let friend = match m_state.state().friends.get(remote_public_key) {
+        Some(friend) => friend,
+        None => return,
+    };
+
+    let token_channel = match &friend.channel_status {
+        ChannelStatus::Inconsistent(_) => return,
+        ChannelStatus::Consistent(token_channel) => token_channel,
+    };
+
+    let pending_request = match token_channel
+        .get_mutual_credit()
+        .state()
+        .pending_requests
+        .pending_remote_requests
+        .get(request_id)
+    {
+        Some(pending_request) => pending_request.clone(),
+        // The request was already answered:
+        None => return,
+    };
+
+    if is_response_queued(m_state.state(), remote_public_key, request_id) {
+        // An answer is already on its way:
+        return;
+    }
+
+    // If we are the destination, the request might be a part of a payment split over multiple
+    // routes. In that case we cancel the whole payment:
+    let is_incoming_part = m_state
+        .state()
+        .incoming_payments
+        .get(&pending_request.invoice_id)
+        .map(|incoming_payment| {
+            incoming_payment
+                .parts
+                .iter()
+                .any(|(_, part)| &part.request_id == request_id)
+        })
+        .unwrap_or(false);
+
+    if is_incoming_part {
+        cancel_incoming_payment(m_state, send_commands, &pending_request.invoice_id, None);
+        return;
+    }
+
+    // If we are a mediator, the request might still wait to be sent to the next friend on the
+    // route. We make sure it will not be sent:
+    let opt_next_index = pending_request
+        .route
+        .find_pk_pair(remote_public_key, &m_state.state().local_public_key)
+        .and_then(|remote_index| remote_index.checked_add(2));
+    let opt_next_public_key = opt_next_index
+        .and_then(|next_index| pending_request.route.index_to_pk(next_index))
+        .cloned();
+
+    if let (Some(next_index), Some(next_public_key)) = (opt_next_index, opt_next_public_key) {
+        if is_request_forwarded(m_state.state(), &next_public_key, request_id) {
+            if !is_forwarded_deadline {
+                // The next friend might still answer the request. If we fail the request now
+                // and a response arrives later, we will have to pay the next friend without
+                // being paid by the remote friend. Every node after us on the route has an
+                // earlier deadline, so we give the failure some extra ticks to travel back:
+                let hops_left = pending_request.route.len().saturating_sub(next_index);
+                let left_ticks =
+                    REQUEST_TIMEOUT_HOP_TICKS.saturating_mul(usize_to_u64(hops_left).unwrap());
+                let timeouts_mutation = TimeoutsMutation::SetDeadline((
+                    request_id.clone(),
+                    left_ticks,
+                    PendingOrigin::Forwarded(remote_public_key.clone()),
+                ));
+                m_state.mutate(FunderMutation::TimeoutsMutation(timeouts_mutation));
+                return;
+            }
+            // The next friend did not answer in time. We do not keep the credits of the remote
+            // friend frozen any longer. The credits frozen with the next friend are released
+            // once the next friend answers:
+            let timeouts_mutation =
+                TimeoutsMutation::AddUnbacked((request_id.clone(), next_public_key));
+            m_state.mutate(FunderMutation::TimeoutsMutation(timeouts_mutation));
+        } else if m_state.state().friends.contains_key(&next_public_key) {
+            let friend_mutation = FriendMutation::RemovePendingRequest(request_id.clone());
+            let funder_mutation =
+                FunderMutation::FriendMutation((next_public_key, friend_mutation));
+            m_state.mutate(funder_mutation);
+        }
+    }
+
+    let u_failure_op = ResponseOp::UnsignedFailure(pending_request);
+    let friend_mutation = FriendMutation::PushBackPendingResponse(u_failure_op);
+    let funder_mutation =
+        FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+    send_commands.set_try_send(remote_public_key);
+}
+
+/// Cancel a request issued by the user, if it is still waiting in the queue of a friend.
+/// Requests that were already sent to the friend can not be cancelled.
+/// Returns true if the request was cancelled.
+pub fn cancel_pending_user_request<B>(
+    m_state: &mut MutableFunderState<B>,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    friend_public_key: &PublicKey,
+    request_id: &Uid,
+) -> bool
+where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let friend = match m_state.state().friends.get(friend_public_key) {
+        Some(friend) => friend,
+        None => return false,
+    };
+
+    let pending_request = match friend
+        .pending_user_requests
+        .iter()
+        .find(|pending_user_request| &pending_user_request.request_id == request_id)
+    {
+        Some(pending_user_request) => create_pending_request(pending_user_request),
+        None => return false,
+    };
+
+    let friend_mutation = FriendMutation::RemovePendingUserRequest(request_id.clone());
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+
+    fail_user_request(m_state, outgoing_control, &pending_request);
+    true
+}
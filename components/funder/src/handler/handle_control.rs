@@ -1,27 +1,40 @@
+use std::cmp;
 use std::fmt::Debug;
+use std::usize;
 
 use common::canonical_serialize::CanonicalSerialize;
+use common::int_convert::u64_to_usize;
 
 use crypto::identity::PublicKey;
+use crypto::invoice_id::InvoiceId;
+use crypto::uid::Uid;
 
+use crate::debt_policy::DebtPolicyStatus;
 use crate::friend::{ChannelStatus, FriendMutation};
 use crate::state::FunderMutation;
 
-use proto::app_server::messages::{NamedRelayAddress, RelayAddress};
+use proto::app_server::messages::{ChannelAddress, NamedRelayAddress};
+use proto::consts::MAX_PAYMENT_HISTORY_RECORDS;
 use proto::funder::messages::{
-    AddFriend, ChannelerUpdateFriend, FriendStatus, FunderControl, FunderOutgoingControl,
-    ReceiptAck, RemoveFriend, ResetFriendChannel, ResponseReceived, ResponseSendFundsResult,
-    SetFriendName, SetFriendRelays, SetFriendRemoteMaxDebt, SetFriendStatus, SetRequestsStatus,
-    UserRequestSendFunds,
+    AddFriend, ChannelerUpdateFriend, FreezePolicy, FriendStatus, FunderControl,
+    FunderOutgoingControl, ReceiptAck, RemoveFriend, RequestPaymentHistory, ResetFriendChannel,
+    ResponsePaymentHistory, ResponseReceived, ResponseSendFundsResult, SetFriendDebtPolicy,
+    SetFriendName, SetFriendRate, SetFriendRelays, SetFriendRemoteMaxDebt, SetFriendStatus,
+    SetRequestsStatus, SettleStatus, UserRequestSendFunds,
 };
 
-use crate::ephemeral::Ephemeral;
 use crate::handler::canceler::{
-    cancel_local_pending_requests, cancel_pending_requests, cancel_pending_user_requests,
+    cancel_friend_incoming_payments, cancel_incoming_payment, cancel_local_pending_requests,
+    cancel_pending_requests, cancel_pending_user_request, cancel_pending_user_requests,
 };
+use crate::handler::handle_friend::respond_to_incoming_payment;
 use crate::handler::handler::{is_friend_ready, MutableEphemeral, MutableFunderState};
+use crate::handler::history::fail_user_request;
+use crate::handler::policy_engine::apply_debt_policy;
 use crate::handler::sender::SendCommands;
+use crate::handler::settle::begin_settle;
 
+use crate::timeouts::{PendingOrigin, TimeoutsMutation};
 use crate::types::ChannelerConfig;
 
 #[derive(Debug)]
@@ -38,8 +51,18 @@ pub enum HandleControlError {
     UserRequestInvalid,
     FriendNotReady,
     MaxNodeRelaysReached,
+    RequestDoesNotExist,
+    RequestNotCancellable,
+    ChannelInconsistent,
+    FriendSettling,
+    PaymentNotHeld,
+    DebtPolicyActive,
 }
 
+/// Set the remote max debt of a friend manually.
+/// If the remote max debt of the currency is managed by a debt policy, the request is rejected.
+/// The policy has to be cleared first (See control_clear_friend_debt_policy()), otherwise it would
+/// overwrite the manual value on the next time tick.
 fn control_set_friend_remote_max_debt<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
@@ -55,7 +78,17 @@ where
         .get(&set_friend_remote_max_debt.friend_public_key)
         .ok_or(HandleControlError::FriendDoesNotExist)?;
 
-    if friend.wanted_remote_max_debt == set_friend_remote_max_debt.remote_max_debt {
+    if let Some(debt_policy) = &friend.opt_debt_policy {
+        if debt_policy.currency == set_friend_remote_max_debt.currency {
+            return Err(HandleControlError::DebtPolicyActive);
+        }
+    }
+
+    if friend
+        .wanted_remote_max_debt
+        .get(&set_friend_remote_max_debt.currency)
+        == Some(&set_friend_remote_max_debt.remote_max_debt)
+    {
         // Wanted remote max debt is already set to this value. Nothing to do here.
         return Ok(());
     }
@@ -63,8 +96,10 @@ where
     // We only set the wanted remote max debt here. The actual remote max debt will be changed
     // only when we manage to send a move token message containing the SetRemoteMaxDebt
     // operation.
-    let friend_mutation =
-        FriendMutation::SetWantedRemoteMaxDebt(set_friend_remote_max_debt.remote_max_debt);
+    let friend_mutation = FriendMutation::SetWantedRemoteMaxDebt((
+        set_friend_remote_max_debt.currency.clone(),
+        set_friend_remote_max_debt.remote_max_debt,
+    ));
     let m_mutation = FunderMutation::FriendMutation((
         set_friend_remote_max_debt.friend_public_key.clone(),
         friend_mutation,
@@ -114,9 +149,9 @@ where
 
 fn enable_friend<B>(
     m_state: &mut MutableFunderState<B>,
-    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
     friend_public_key: &PublicKey,
-    friend_relays: &[RelayAddress<B>],
+    friend_relays: &[ChannelAddress<B>],
 ) where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
@@ -136,7 +171,7 @@ fn disable_friend<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
-    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
     friend_public_key: &PublicKey,
 ) where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
@@ -154,7 +189,7 @@ fn disable_friend<B>(
 fn control_add_relay<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
-    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
     max_node_relays: usize,
     named_relay_address: NamedRelayAddress<B>,
 ) -> Result<(), HandleControlError>
@@ -169,31 +204,14 @@ where
     let funder_mutation = FunderMutation::AddRelay(named_relay_address);
     m_state.mutate(funder_mutation);
 
-    let relays = m_state
-        .state()
-        .relays
-        .iter()
-        .cloned()
-        .map(RelayAddress::from)
-        .collect::<Vec<_>>();
-
-    // Notify Channeler about relay address change:
-    let channeler_config = ChannelerConfig::SetRelays(relays);
-    outgoing_channeler_config.push(channeler_config);
-
-    // We might need to update all friends about the address change:
-    let friend_public_keys = m_state.state().friends.keys().cloned().collect::<Vec<_>>();
-
-    for friend_public_key in &friend_public_keys {
-        send_commands.set_try_send(friend_public_key);
-    }
+    update_local_addresses(m_state, send_commands, outgoing_channeler_config);
     Ok(())
 }
 
 fn control_remove_relay<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
-    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
     public_key: PublicKey,
 ) where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
@@ -201,16 +219,43 @@ fn control_remove_relay<B>(
     let funder_mutation = FunderMutation::RemoveRelay(public_key);
     m_state.mutate(funder_mutation);
 
-    let relays = m_state
+    update_local_addresses(m_state, send_commands, outgoing_channeler_config);
+}
+
+fn control_set_direct_address<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
+    opt_direct_address: Option<B>,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    if m_state.state().opt_direct_address == opt_direct_address {
+        return;
+    }
+
+    let funder_mutation = FunderMutation::SetDirectAddress(opt_direct_address);
+    m_state.mutate(funder_mutation);
+
+    update_local_addresses(m_state, send_commands, outgoing_channeler_config);
+}
+
+/// Notify the Channeler and all friends about a change in our local addresses.
+fn update_local_addresses<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let local_addresses = m_state
         .state()
-        .relays
-        .iter()
-        .cloned()
-        .map(RelayAddress::from)
+        .local_addresses()
+        .into_iter()
         .collect::<Vec<_>>();
 
-    // Notify Channeler about relay address change:
-    let channeler_config = ChannelerConfig::SetRelays(relays);
+    // Notify Channeler about local address change:
+    let channeler_config = ChannelerConfig::SetRelays(local_addresses);
     outgoing_channeler_config.push(channeler_config);
 
     // We might need to update all friends about the address change:
@@ -235,7 +280,7 @@ fn control_remove_friend<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
-    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
     remove_friend: RemoveFriend,
 ) -> Result<(), HandleControlError>
 where
@@ -263,6 +308,8 @@ where
         &remove_friend.friend_public_key,
     );
 
+    cancel_friend_incoming_payments(m_state, send_commands, &remove_friend.friend_public_key);
+
     let funder_mutation = FunderMutation::RemoveFriend(remove_friend.friend_public_key.clone());
     m_state.mutate(funder_mutation);
 
@@ -273,7 +320,7 @@ fn control_set_friend_status<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
-    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
     set_friend_status: SetFriendStatus,
 ) -> Result<(), HandleControlError>
 where
@@ -330,12 +377,17 @@ where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
     // Make sure that friend exists:
-    let _friend = m_state
+    let friend = m_state
         .state()
         .friends
         .get(&set_requests_status.friend_public_key)
         .ok_or(HandleControlError::FriendDoesNotExist)?;
 
+    // Requests stay closed once we started settling with the friend:
+    if friend.settle_status != SettleStatus::Active && set_requests_status.status.is_open() {
+        return Err(HandleControlError::FriendSettling);
+    }
+
     let friend_mutation = FriendMutation::SetWantedLocalRequestsStatus(set_requests_status.status);
     let funder_mutation = FunderMutation::FriendMutation((
         set_requests_status.friend_public_key.clone(),
@@ -349,7 +401,7 @@ where
 
 fn control_set_friend_relays<B>(
     m_state: &mut MutableFunderState<B>,
-    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
     set_friend_relays: SetFriendRelays<B>,
 ) -> Result<(), HandleControlError>
 where
@@ -421,16 +473,250 @@ where
     Ok(())
 }
 
+fn control_set_friend_rate<B>(
+    m_state: &mut MutableFunderState<B>,
+    set_friend_rate: SetFriendRate,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    // Make sure that friend exists:
+    let friend = m_state
+        .state()
+        .friends
+        .get(&set_friend_rate.friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    // If the newly proposed rate is the same as the old one, we do nothing:
+    if friend.rate == set_friend_rate.rate {
+        return Ok(());
+    }
+
+    // Note that the new rate only applies to requests arriving from now on.
+    let friend_mutation = FriendMutation::SetRate(set_friend_rate.rate);
+    let funder_mutation = FunderMutation::FriendMutation((
+        set_friend_rate.friend_public_key.clone(),
+        friend_mutation,
+    ));
+    m_state.mutate(funder_mutation);
+
+    Ok(())
+}
+
+fn control_set_friend_debt_policy<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    set_friend_debt_policy: SetFriendDebtPolicy,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    // Make sure that friend exists:
+    let friend = m_state
+        .state()
+        .friends
+        .get(&set_friend_debt_policy.friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    if friend.opt_debt_policy.as_ref() == Some(&set_friend_debt_policy.debt_policy) {
+        return Ok(());
+    }
+
+    let friend_public_key = set_friend_debt_policy.friend_public_key;
+    let currency = &set_friend_debt_policy.debt_policy.currency;
+    let same_currency = friend
+        .opt_debt_policy
+        .as_ref()
+        .map(|debt_policy| &debt_policy.currency == currency)
+        .unwrap_or(false);
+
+    if !same_currency {
+        // Repayments are only counted from the moment a policy is set for a currency.
+        // A currency without a credit line has zero balance:
+        let balance = match &friend.channel_status {
+            ChannelStatus::Consistent(token_channel) => token_channel
+                .get_mutual_credit()
+                .state()
+                .balances
+                .get(currency)
+                .map(|mc_balance| mc_balance.balance)
+                .unwrap_or(0),
+            ChannelStatus::Inconsistent(channel_inconsistent) => channel_inconsistent
+                .local_reset_terms
+                .balance_for_reset
+                .iter()
+                .find(|currency_balance| &currency_balance.currency == currency)
+                .map(|currency_balance| currency_balance.balance)
+                .unwrap_or(0),
+        };
+        let friend_mutation = FriendMutation::SetDebtPolicyStatus(DebtPolicyStatus::new(balance));
+        let funder_mutation =
+            FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+        m_state.mutate(funder_mutation);
+    }
+
+    let friend_mutation = FriendMutation::SetDebtPolicy(Some(set_friend_debt_policy.debt_policy));
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+
+    // Apply the new policy right away:
+    apply_debt_policy(m_state, send_commands, &friend_public_key);
+    Ok(())
+}
+
+fn control_clear_friend_debt_policy<B>(
+    m_state: &mut MutableFunderState<B>,
+    friend_public_key: PublicKey,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    // Make sure that friend exists:
+    let friend = m_state
+        .state()
+        .friends
+        .get(&friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    if friend.opt_debt_policy.is_none() {
+        return Ok(());
+    }
+
+    // The current remote max debt and requests status are left as they are:
+    let friend_mutation = FriendMutation::SetDebtPolicy(None);
+    let funder_mutation = FunderMutation::FriendMutation((friend_public_key, friend_mutation));
+    m_state.mutate(funder_mutation);
+
+    Ok(())
+}
+
+fn control_settle_friend<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    friend_public_key: PublicKey,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    // Make sure that friend exists:
+    let friend = m_state
+        .state()
+        .friends
+        .get(&friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    // An inconsistent channel has to be reset before it can be settled:
+    if let ChannelStatus::Inconsistent(_) = &friend.channel_status {
+        return Err(HandleControlError::ChannelInconsistent);
+    }
+
+    if friend.settle_status != SettleStatus::Active {
+        // Settlement is already in progress. Nothing to do here.
+        return Ok(());
+    }
+
+    begin_settle(m_state, send_commands, outgoing_control, &friend_public_key);
+    Ok(())
+}
+
+fn control_set_freeze_policy<B>(
+    m_state: &mut MutableFunderState<B>,
+    freeze_policy: FreezePolicy,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    if m_state.state().freeze_policy == freeze_policy {
+        return Ok(());
+    }
+
+    // Note that requests that were already forwarded are not affected by the new policy.
+    let funder_mutation = FunderMutation::SetFreezePolicy(freeze_policy);
+    m_state.mutate(funder_mutation);
+
+    Ok(())
+}
+
+fn control_set_hold_incoming_payments<B>(
+    m_state: &mut MutableFunderState<B>,
+    hold_incoming_payments: bool,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    if m_state.state().hold_incoming_payments == hold_incoming_payments {
+        return Ok(());
+    }
+
+    // Note that payments that are already held stay held until they are accepted or rejected.
+    let funder_mutation = FunderMutation::SetHoldIncomingPayments(hold_incoming_payments);
+    m_state.mutate(funder_mutation);
+
+    Ok(())
+}
+
+/// Make sure that the total payment for `invoice_id` has arrived, and waits for the user.
+fn check_payment_held<B>(
+    m_state: &MutableFunderState<B>,
+    invoice_id: &InvoiceId,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    match m_state.state().incoming_payments.get(invoice_id) {
+        Some(incoming_payment) if incoming_payment.held => Ok(()),
+        // The payment might have already timed out:
+        _ => Err(HandleControlError::PaymentNotHeld),
+    }
+}
+
+fn control_accept_held_payment<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    invoice_id: InvoiceId,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    check_payment_held(m_state, &invoice_id)?;
+    // Receipts will be issued for all the parts of the payment:
+    respond_to_incoming_payment(m_state, send_commands, &invoice_id);
+    Ok(())
+}
+
+fn control_reject_held_payment<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    invoice_id: InvoiceId,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    check_payment_held(m_state, &invoice_id)?;
+    cancel_incoming_payment(m_state, send_commands, &invoice_id, None);
+    Ok(())
+}
+
 fn check_user_request_valid(user_request_send_funds: &UserRequestSendFunds) -> Option<()> {
     if !user_request_send_funds.route.is_valid() {
         return None;
     }
+    // The part we send can not be larger than the total payment:
+    if user_request_send_funds.total_dest_payment < user_request_send_funds.dest_payment {
+        return None;
+    }
+    // We must allow some time for the request to be answered:
+    if user_request_send_funds.timeout_ticks == 0 {
+        return None;
+    }
     Some(())
 }
 
 fn control_request_send_funds_inner<B>(
     m_state: &mut MutableFunderState<B>,
-    ephemeral: &Ephemeral,
+    m_ephemeral: &mut MutableEphemeral,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
     send_commands: &mut SendCommands,
     max_pending_user_requests: usize,
@@ -478,7 +764,7 @@ where
         None => Err(HandleControlError::FriendDoesNotExist),
     }?;
 
-    if !is_friend_ready(m_state.state(), ephemeral, &friend_public_key) {
+    if !is_friend_ready(m_state.state(), m_ephemeral.ephemeral(), &friend_public_key) {
         return Err(HandleControlError::FriendNotReady);
     }
 
@@ -511,6 +797,15 @@ where
         return Err(HandleControlError::PendingUserRequestsFull);
     }
 
+    // If the request is still waiting in the queue when the deadline passes, it will be
+    // cancelled:
+    let timeouts_mutation = TimeoutsMutation::SetDeadline((
+        user_request_send_funds.request_id.clone(),
+        user_request_send_funds.timeout_ticks,
+        PendingOrigin::User(friend_public_key.clone()),
+    ));
+    m_state.mutate(FunderMutation::TimeoutsMutation(timeouts_mutation));
+
     let request_send_funds = user_request_send_funds.into_request();
     let friend_mutation = FriendMutation::PushBackPendingUserRequest(request_send_funds);
     let funder_mutation =
@@ -523,7 +818,7 @@ where
 
 fn control_request_send_funds<B>(
     m_state: &mut MutableFunderState<B>,
-    ephemeral: &Ephemeral,
+    m_ephemeral: &mut MutableEphemeral,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
     send_commands: &mut SendCommands,
     max_pending_user_requests: usize,
@@ -536,19 +831,15 @@ where
     // Otherwise, we return the internal error and return a response failure message.
     if let Err(e) = control_request_send_funds_inner(
         m_state,
-        ephemeral,
+        m_ephemeral,
         outgoing_control,
         send_commands,
         max_pending_user_requests,
         user_request_send_funds.clone(),
     ) {
         error!("control_request_send_funds_inner() failed: {:?}", e);
-        let response_received = ResponseReceived {
-            request_id: user_request_send_funds.request_id,
-            result: ResponseSendFundsResult::Failure(m_state.state().local_public_key.clone()),
-        };
-
-        outgoing_control.push(FunderOutgoingControl::ResponseReceived(response_received));
+        let pending_request = user_request_send_funds.create_pending_request();
+        fail_user_request(m_state, outgoing_control, &pending_request);
     }
 
     // Every RequestSendFunds must have a matching response. Therefore we don't return an error
@@ -556,6 +847,43 @@ where
     Ok(())
 }
 
+/// Cancel a request that was issued by the user.
+/// Only requests that are still waiting in the queue of a friend can be cancelled. Requests that
+/// were already sent to a friend will be answered by the friend (In the worst case, after their
+/// deadline passes).
+fn control_cancel_request_send_funds<B>(
+    m_state: &mut MutableFunderState<B>,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    request_id: Uid,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend_public_keys: Vec<PublicKey> = m_state.state().friends.keys().cloned().collect();
+    for friend_public_key in &friend_public_keys {
+        if cancel_pending_user_request(m_state, outgoing_control, friend_public_key, &request_id) {
+            return Ok(());
+        }
+    }
+
+    // Check if the request was already sent to a friend:
+    for friend in m_state.state().friends.values() {
+        if let ChannelStatus::Consistent(token_channel) = &friend.channel_status {
+            if token_channel
+                .get_mutual_credit()
+                .state()
+                .pending_requests
+                .pending_local_requests
+                .contains_key(&request_id)
+            {
+                return Err(HandleControlError::RequestNotCancellable);
+            }
+        }
+    }
+
+    Err(HandleControlError::RequestDoesNotExist)
+}
+
 /// Handle an incoming receipt ack message
 fn control_receipt_ack<B>(
     m_state: &mut MutableFunderState<B>,
@@ -583,12 +911,63 @@ where
     Ok(())
 }
 
+/// Query the payment history.
+/// Matching records are returned from the most recent to the oldest.
+fn control_request_payment_history<B>(
+    m_state: &MutableFunderState<B>,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    request_payment_history: RequestPaymentHistory,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let RequestPaymentHistory {
+        request_id,
+        opt_direction,
+        opt_status,
+        opt_invoice_id,
+        offset,
+        max_records,
+    } = request_payment_history;
+
+    // Values that do not fit into a usize can not be reached anyways:
+    let offset = u64_to_usize(offset).unwrap_or(usize::MAX);
+    let max_records = u64_to_usize(cmp::min(max_records, MAX_PAYMENT_HISTORY_RECORDS)).unwrap();
+
+    let records = m_state
+        .state()
+        .payment_history
+        .iter()
+        .rev()
+        .filter(|record| opt_direction.map_or(true, |direction| record.direction == direction))
+        .filter(|record| opt_status.map_or(true, |status| record.status == status))
+        .filter(|record| {
+            opt_invoice_id
+                .as_ref()
+                .map_or(true, |invoice_id| &record.invoice_id == invoice_id)
+        })
+        .skip(offset)
+        .take(max_records)
+        .cloned()
+        .collect();
+
+    let response_payment_history = ResponsePaymentHistory {
+        request_id,
+        records,
+    };
+    outgoing_control.push(FunderOutgoingControl::ResponsePaymentHistory(
+        response_payment_history,
+    ));
+
+    Ok(())
+}
+
 pub fn handle_control_message<B>(
     m_state: &mut MutableFunderState<B>,
     m_ephemeral: &mut MutableEphemeral,
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
-    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
     max_node_relays: usize,
     max_pending_user_requests: usize,
     incoming_control: FunderControl<B>,
@@ -623,6 +1002,16 @@ where
             Ok(())
         }
 
+        FunderControl::SetDirectAddress(opt_direct_address) => {
+            control_set_direct_address(
+                m_state,
+                send_commands,
+                outgoing_channeler_config,
+                opt_direct_address,
+            );
+            Ok(())
+        }
+
         FunderControl::AddFriend(add_friend) => {
             control_add_friend(m_state, add_friend);
             Ok(())
@@ -656,15 +1045,55 @@ where
             control_set_friend_name(m_state, set_friend_name)
         }
 
+        FunderControl::SetFriendRate(set_friend_rate) => {
+            control_set_friend_rate(m_state, set_friend_rate)
+        }
+
+        FunderControl::SetFriendDebtPolicy(set_friend_debt_policy) => {
+            control_set_friend_debt_policy(m_state, send_commands, set_friend_debt_policy)
+        }
+
+        FunderControl::ClearFriendDebtPolicy(friend_public_key) => {
+            control_clear_friend_debt_policy(m_state, friend_public_key)
+        }
+
+        FunderControl::SettleFriend(friend_public_key) => {
+            control_settle_friend(m_state, send_commands, outgoing_control, friend_public_key)
+        }
+
         FunderControl::RequestSendFunds(user_request_send_funds) => control_request_send_funds(
             m_state,
-            m_ephemeral.ephemeral(),
+            m_ephemeral,
             outgoing_control,
             send_commands,
             max_pending_user_requests,
             user_request_send_funds,
         ),
 
+        FunderControl::CancelRequestSendFunds(request_id) => {
+            control_cancel_request_send_funds(m_state, outgoing_control, request_id)
+        }
+
         FunderControl::ReceiptAck(receipt_ack) => control_receipt_ack(m_state, receipt_ack),
+
+        FunderControl::SetFreezePolicy(freeze_policy) => {
+            control_set_freeze_policy(m_state, freeze_policy)
+        }
+
+        FunderControl::RequestPaymentHistory(request_payment_history) => {
+            control_request_payment_history(m_state, outgoing_control, request_payment_history)
+        }
+
+        FunderControl::SetHoldIncomingPayments(hold_incoming_payments) => {
+            control_set_hold_incoming_payments(m_state, hold_incoming_payments)
+        }
+
+        FunderControl::AcceptHeldPayment(invoice_id) => {
+            control_accept_held_payment(m_state, send_commands, invoice_id)
+        }
+
+        FunderControl::RejectHeldPayment(invoice_id) => {
+            control_reject_held_payment(m_state, send_commands, invoice_id)
+        }
     }
 }
@@ -3,15 +3,20 @@ use std::fmt::Debug;
 
 use crypto::crypto_rand::CryptoRandom;
 use crypto::identity::{PublicKey, Signature, SIGNATURE_LEN};
+use crypto::invoice_id::InvoiceId;
+use crypto::uid::Uid;
 
-use proto::app_server::messages::RelayAddress;
+use proto::app_server::messages::ChannelAddress;
+use proto::consts::{MAX_REQUEST_TIMEOUT_TICKS, REQUEST_TIMEOUT_HOP_TICKS};
 use proto::funder::messages::{
-    ChannelerUpdateFriend, FailureSendFunds, FriendMessage, FunderOutgoingControl,
-    MoveTokenRequest, PendingRequest, RequestSendFunds, ResetTerms, ResponseReceived,
-    ResponseSendFunds, ResponseSendFundsResult,
+    ChannelerUpdateFriend, FailureSendFunds, FriendMessage, FunderOutgoingControl, HeldPayment,
+    MoveTokenRequest, PaymentDirection, PaymentStatus, PendingRequest, RequestSendFunds,
+    ResetTerms, ResponseReceived, ResponseSendFunds, ResponseSendFundsResult, SettleStatus,
+    TokenBalance,
 };
 use proto::funder::signature_buff::{prepare_receipt, verify_move_token};
 
+use crate::credit_calc::take_fee;
 use crate::mutual_credit::incoming::{
     IncomingFailureSendFunds, IncomingMessage, IncomingResponseSendFunds,
 };
@@ -22,18 +27,22 @@ use crate::types::{create_pending_request, ChannelerConfig};
 use crate::friend::{
     ChannelInconsistent, ChannelStatus, FriendMutation, ResponseOp, SentLocalRelays,
 };
-use crate::state::FunderMutation;
+use crate::state::{FunderMutation, FunderState, IncomingPayment};
 
-use crate::ephemeral::Ephemeral;
+use crate::freeze_guard::verify_freeze;
+use crate::timeouts::{PendingOrigin, TimeoutsMutation};
 
 use crate::handler::canceler::{
-    cancel_local_pending_requests, cancel_pending_requests, cancel_pending_user_requests,
-    reply_with_failure,
+    cancel_friend_incoming_payments, cancel_incoming_payment, cancel_local_pending_requests,
+    cancel_pending_requests, cancel_pending_user_requests, reply_with_failure,
 };
 use crate::handler::handler::{
-    find_request_origin, is_friend_ready, MutableEphemeral, MutableFunderState,
+    find_mediated_origin, is_friend_ready, is_local_origin, is_response_queued, MutableEphemeral,
+    MutableFunderState,
 };
+use crate::handler::history::{add_payment_record, record_mediated_response};
 use crate::handler::sender::SendCommands;
+use crate::handler::settle::{begin_settle, try_complete_settle};
 
 #[derive(Debug)]
 pub enum HandleFriendError {
@@ -82,15 +91,25 @@ pub fn try_reset_channel<B>(
 {
     let move_token = &move_token_request.friend_move_token;
 
+    // The remote side states the balances from its point of view, with no pending debts:
+    let expected_balances = local_reset_terms
+        .balance_for_reset
+        .iter()
+        .map(|currency_balance| TokenBalance {
+            currency: currency_balance.currency.clone(),
+            balance: currency_balance.balance.checked_neg().unwrap(),
+            local_pending_debt: 0,
+            remote_pending_debt: 0,
+        })
+        .collect::<Vec<_>>();
+
     // Check if incoming message is a valid attempt to reset the channel:
     if move_token.old_token != local_reset_terms.reset_token
         || !move_token.operations.is_empty()
         || move_token.opt_local_relays.is_some()
         || move_token.inconsistency_counter != local_reset_terms.inconsistency_counter
         || move_token.move_token_counter != 0
-        || move_token.balance != local_reset_terms.balance_for_reset.checked_neg().unwrap()
-        || move_token.local_pending_debt != 0
-        || move_token.remote_pending_debt != 0
+        || move_token.balances != expected_balances
         || !verify_move_token(move_token, friend_public_key)
     {
         send_commands.set_resend_outgoing(friend_public_key);
@@ -101,7 +120,7 @@ pub fn try_reset_channel<B>(
         &m_state.state().local_public_key,
         friend_public_key,
         move_token,
-        local_reset_terms.balance_for_reset,
+        &local_reset_terms.balance_for_reset,
     );
 
     // This is a reset message. We reset the token channel:
@@ -139,15 +158,191 @@ fn forward_request<B>(
     send_commands.set_try_send(&next_pk);
 }
 
+/// Queue a response (Containing a receipt) for a request where we are the destination.
+fn respond_to_request<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    remote_public_key: &PublicKey,
+    pending_request: PendingRequest,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let u_response_op = ResponseOp::UnsignedResponse(pending_request);
+    let friend_mutation = FriendMutation::PushBackPendingResponse(u_response_op);
+    let funder_mutation =
+        FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+    send_commands.set_try_send(&remote_public_key);
+}
+
+/// Respond to all the parts of an incoming payment, and forget about the payment.
+pub fn respond_to_incoming_payment<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    invoice_id: &InvoiceId,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let incoming_payment = match m_state.state().incoming_payments.get(invoice_id) {
+        Some(incoming_payment) => incoming_payment.clone(),
+        None => return,
+    };
+
+    for (friend_public_key, pending_request) in incoming_payment.parts {
+        respond_to_request(m_state, send_commands, &friend_public_key, pending_request);
+    }
+    let funder_mutation = FunderMutation::RemoveIncomingPayment(invoice_id.clone());
+    m_state.mutate(funder_mutation);
+}
+
+/// Amount of ticks left until the first part of an incoming payment times out.
+fn incoming_payment_left_ticks<B>(state: &FunderState<B>, incoming_payment: &IncomingPayment) -> u64
+where
+    B: Clone,
+{
+    let timeouts = &state.timeouts;
+    incoming_payment
+        .parts
+        .iter()
+        .filter_map(|(friend_public_key, pending_request)| {
+            let origin = PendingOrigin::Friend(friend_public_key.clone());
+            timeouts
+                .deadlines
+                .get(&(pending_request.request_id.clone(), origin))
+        })
+        .map(|deadline| deadline.saturating_sub(timeouts.ticks))
+        .min()
+        .unwrap_or(0)
+}
+
+/// Handle a request where we are the destination.
+/// A payment may be split over multiple requests (sharing the same invoice_id), sent along
+/// different routes. We only respond (and issue receipts) after the total payment has arrived.
+/// If we hold incoming payments, we respond only after the user accepts the payment.
+fn handle_incoming_payment<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    remote_public_key: &PublicKey,
+    request_send_funds: &RequestSendFunds,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let pending_request = create_pending_request(request_send_funds);
+
+    if pending_request.total_dest_payment < pending_request.dest_payment {
+        // A part can not be larger than the total payment:
+        reply_with_failure(
+            m_state,
+            send_commands,
+            remote_public_key,
+            request_send_funds,
+        );
+        return;
+    }
+
+    let opt_payment_terms = m_state
+        .state()
+        .incoming_payments
+        .get(&pending_request.invoice_id)
+        .map(|incoming_payment| {
+            (
+                incoming_payment.currency.clone(),
+                incoming_payment.total_dest_payment,
+            )
+        });
+
+    match opt_payment_terms {
+        None => {
+            if pending_request.total_dest_payment == pending_request.dest_payment
+                && !m_state.state().hold_incoming_payments
+            {
+                // The whole payment was sent in one request. We return a response:
+                respond_to_request(m_state, send_commands, remote_public_key, pending_request);
+                return;
+            }
+        }
+        Some((currency, total_dest_payment)) => {
+            if currency != pending_request.currency
+                || total_dest_payment != pending_request.total_dest_payment
+            {
+                // Parts of the same payment disagree about the currency or the total payment.
+                // We cancel this request, together with all the other parts:
+                reply_with_failure(
+                    m_state,
+                    send_commands,
+                    remote_public_key,
+                    request_send_funds,
+                );
+                cancel_incoming_payment(m_state, send_commands, &pending_request.invoice_id, None);
+                return;
+            }
+        }
+    }
+
+    let invoice_id = pending_request.invoice_id.clone();
+    let funder_mutation =
+        FunderMutation::AddIncomingPart((remote_public_key.clone(), pending_request));
+    m_state.mutate(funder_mutation);
+
+    let incoming_payment = m_state
+        .state()
+        .incoming_payments
+        .get(&invoice_id)
+        .unwrap()
+        .clone();
+
+    match incoming_payment.received_payment() {
+        Some(received_payment) if received_payment < incoming_payment.total_dest_payment => {
+            // We wait for the rest of the parts to arrive.
+        }
+        Some(received_payment) if received_payment == incoming_payment.total_dest_payment => {
+            if m_state.state().hold_incoming_payments {
+                // The total payment has arrived. We wait for the user to accept or reject it.
+                // If the user does not answer in time, the payment is rejected once the first
+                // part times out.
+                let funder_mutation = FunderMutation::SetIncomingPaymentHeld(invoice_id.clone());
+                m_state.mutate(funder_mutation);
+
+                let held_payment = HeldPayment {
+                    invoice_id,
+                    currency: incoming_payment.currency.clone(),
+                    total_dest_payment: incoming_payment.total_dest_payment,
+                    left_ticks: incoming_payment_left_ticks(m_state.state(), &incoming_payment),
+                };
+                outgoing_control.push(FunderOutgoingControl::IncomingPaymentHeld(held_payment));
+            } else {
+                // The total payment has arrived. We respond to all the parts:
+                respond_to_incoming_payment(m_state, send_commands, &invoice_id);
+            }
+        }
+        _ => {
+            // More than the total payment has arrived (Or an overflow occurred).
+            cancel_incoming_payment(m_state, send_commands, &invoice_id, None);
+        }
+    }
+}
+
 fn handle_request_send_funds<B>(
     m_state: &mut MutableFunderState<B>,
-    ephemeral: &Ephemeral,
+    m_ephemeral: &mut MutableEphemeral,
     send_commands: &mut SendCommands,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
     remote_public_key: &PublicKey,
-    request_send_funds: RequestSendFunds,
+    mut request_send_funds: RequestSendFunds,
 ) where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
+    // We limit the amount of time we are willing to wait for an answer.
+    // If no answer arrives in time, we will send back a failure:
+    let left_ticks = request_send_funds.left_ticks.min(MAX_REQUEST_TIMEOUT_TICKS);
+    let timeouts_mutation = TimeoutsMutation::SetDeadline((
+        request_send_funds.request_id.clone(),
+        left_ticks,
+        PendingOrigin::Friend(remote_public_key.clone()),
+    ));
+    m_state.mutate(FunderMutation::TimeoutsMutation(timeouts_mutation));
+
     // Find ourselves on the route. If we are not there, abort.
     let remote_index = request_send_funds
         .route
@@ -157,14 +352,14 @@ fn handle_request_send_funds<B>(
     let local_index = remote_index.checked_add(1).unwrap();
     let next_index = local_index.checked_add(1).unwrap();
     if next_index >= request_send_funds.route.len() {
-        // We are the destination of this request. We return a response:
-        let pending_request = create_pending_request(&request_send_funds);
-        let u_response_op = ResponseOp::UnsignedResponse(pending_request);
-        let friend_mutation = FriendMutation::PushBackPendingResponse(u_response_op);
-        let funder_mutation =
-            FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
-        m_state.mutate(funder_mutation);
-        send_commands.set_try_send(&remote_public_key);
+        // We are the destination of this request:
+        handle_incoming_payment(
+            m_state,
+            send_commands,
+            outgoing_control,
+            remote_public_key,
+            &request_send_funds,
+        );
         return;
     }
 
@@ -176,7 +371,7 @@ fn handle_request_send_funds<B>(
     // If we forward the request to an offline friend, the request could be stuck for a long
     // time before a response arrives.
     let friend_ready = if friend_exists {
-        is_friend_ready(m_state.state(), ephemeral, &next_public_key)
+        is_friend_ready(m_state.state(), m_ephemeral.ephemeral(), &next_public_key)
     } else {
         false
     };
@@ -191,6 +386,62 @@ fn handle_request_send_funds<B>(
         return;
     }
 
+    // Make sure that the remote friend does not freeze too many of our credits
+    // towards the next friend:
+    if verify_freeze(
+        m_state.state(),
+        remote_public_key,
+        next_public_key,
+        &request_send_funds,
+    )
+    .is_none()
+    {
+        reply_with_failure(
+            m_state,
+            send_commands,
+            remote_public_key,
+            &request_send_funds,
+        );
+        return;
+    }
+
+    // Take our fee for forwarding the request.
+    // The fee is calculated according to the rate we have set for the remote friend:
+    let rate = &m_state.state().friends.get(remote_public_key).unwrap().rate;
+    let left_fees = match take_fee(
+        rate,
+        request_send_funds.dest_payment,
+        request_send_funds.left_fees,
+    ) {
+        Some((_own_fee, left_fees)) => left_fees,
+        None => {
+            // Not enough fees were left for us:
+            reply_with_failure(
+                m_state,
+                send_commands,
+                remote_public_key,
+                &request_send_funds,
+            );
+            return;
+        }
+    };
+    request_send_funds.left_fees = left_fees;
+
+    // The next node must answer before our own deadline passes. If not enough time is left,
+    // we fail the request:
+    request_send_funds.left_ticks = match left_ticks.checked_sub(REQUEST_TIMEOUT_HOP_TICKS) {
+        Some(next_left_ticks) if next_left_ticks > 0 => next_left_ticks,
+        _ => {
+            reply_with_failure(
+                m_state,
+                send_commands,
+                remote_public_key,
+                &request_send_funds,
+            );
+            return;
+        }
+    };
+
     // Queue message to the next node.
     forward_request(m_state, send_commands, request_send_funds);
 }
@@ -204,8 +455,24 @@ fn handle_response_send_funds<B>(
 ) where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
-    match find_request_origin(m_state.state(), &response_send_funds.request_id).cloned() {
+    match find_mediated_origin(m_state.state(), &pending_request) {
         None => {
+            if !is_local_origin(m_state.state(), &pending_request) {
+                // We have already answered the origin of this request (Probably because the
+                // request has timed out). There is nowhere to pass this response to.
+                if remove_unbacked(m_state, &pending_request.request_id) {
+                    warn!(
+                        "handle_response_send_funds(): Paid for unbacked request_id: {:?}",
+                        response_send_funds.request_id
+                    );
+                } else {
+                    warn!(
+                        "handle_response_send_funds(): Late response for request_id: {:?}",
+                        response_send_funds.request_id
+                    );
+                }
+                return;
+            }
             // We are the origin of this request, and we got a response.
             // We provide a receipt to the user:
             let receipt = prepare_receipt(&response_send_funds, &pending_request);
@@ -219,8 +486,26 @@ fn handle_response_send_funds<B>(
             // In that case the user will be able to obtain the receipt again later.
             let funder_mutation = FunderMutation::AddReceipt((pending_request.request_id, receipt));
             m_state.mutate(funder_mutation);
+
+            add_payment_record(
+                m_state,
+                &pending_request,
+                PaymentDirection::Outgoing,
+                PaymentStatus::Success,
+                pending_request.left_fees,
+            );
         }
         Some(friend_public_key) => {
+            if is_response_queued(
+                m_state.state(),
+                &friend_public_key,
+                &response_send_funds.request_id,
+            ) {
+                // A failure was already queued for this request (The request has timed out):
+                return;
+            }
+            record_mediated_response(m_state, &friend_public_key, &pending_request);
+
             // Queue this response message to another token channel:
             let response_op = ResponseOp::Response(response_send_funds);
             let friend_mutation = FriendMutation::PushBackPendingResponse(response_op);
@@ -233,6 +518,20 @@ fn handle_response_send_funds<B>(
     }
 }
 
+/// Forget about an unbacked request, once the next friend has answered it.
+/// Returns true if the request was unbacked.
+fn remove_unbacked<B>(m_state: &mut MutableFunderState<B>, request_id: &Uid) -> bool
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    if !m_state.state().timeouts.unbacked.contains_key(request_id) {
+        return false;
+    }
+    let timeouts_mutation = TimeoutsMutation::RemoveUnbacked(request_id.clone());
+    m_state.mutate(FunderMutation::TimeoutsMutation(timeouts_mutation));
+    true
+}
+
 fn handle_failure_send_funds<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
@@ -242,8 +541,14 @@ fn handle_failure_send_funds<B>(
 ) where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
-    match find_request_origin(m_state.state(), &failure_send_funds.request_id).cloned() {
+    match find_mediated_origin(m_state.state(), &pending_request) {
         None => {
+            if !is_local_origin(m_state.state(), &pending_request) {
+                // We have already answered the origin of this request (Probably because the
+                // request has timed out).
+                let _ = remove_unbacked(m_state, &pending_request.request_id);
+                return;
+            }
             // We are the origin of this request, and we got a failure
             // We should pass it back to encryptor.
 
@@ -253,8 +558,32 @@ fn handle_failure_send_funds<B>(
                 request_id: pending_request.request_id,
                 result: response_send_funds_result,
             }));
+
+            add_payment_record(
+                m_state,
+                &pending_request,
+                PaymentDirection::Outgoing,
+                PaymentStatus::Failure,
+                0,
+            );
         }
         Some(friend_public_key) => {
+            if is_response_queued(
+                m_state.state(),
+                &friend_public_key,
+                &failure_send_funds.request_id,
+            ) {
+                // A failure was already queued for this request (The request has timed out):
+                return;
+            }
+            add_payment_record(
+                m_state,
+                &pending_request,
+                PaymentDirection::Mediated,
+                PaymentStatus::Failure,
+                0,
+            );
+
             // Queue this failure message to another token channel:
             let failure_op = ResponseOp::Failure(failure_send_funds);
             let friend_mutation = FriendMutation::PushBackPendingResponse(failure_op);
@@ -283,8 +612,9 @@ fn handle_move_token_output<B>(
             IncomingMessage::Request(request_send_funds) => {
                 handle_request_send_funds(
                     m_state,
-                    m_ephemeral.ephemeral(),
+                    m_ephemeral,
                     send_commands,
+                    outgoing_control,
                     remote_public_key,
                     request_send_funds,
                 );
@@ -342,6 +672,8 @@ fn handle_move_token_error<B, R>(
     // Cancel all pending requests to this friend:
     cancel_pending_requests(m_state, send_commands, outgoing_control, remote_public_key);
     cancel_pending_user_requests(m_state, outgoing_control, remote_public_key);
+    // Cancel incoming payments that have parts pending inside the token channel:
+    cancel_friend_incoming_payments(m_state, send_commands, remote_public_key);
 
     // Keep outgoing InconsistencyError message details in memory:
     let channel_inconsistent = ChannelInconsistent {
@@ -362,7 +694,7 @@ fn handle_move_token_success<B>(
     m_ephemeral: &mut MutableEphemeral,
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
-    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
     remote_public_key: &PublicKey,
     receive_move_token_output: ReceiveMoveTokenOutput<B>,
     token_wanted: bool,
@@ -431,10 +763,7 @@ fn handle_move_token_success<B>(
 
                     let friend = m_state.state().friends.get(remote_public_key).unwrap();
 
-                    let local_relays = c_last_address
-                        .into_iter()
-                        .map(RelayAddress::from)
-                        .collect::<Vec<_>>();
+                    let local_relays = c_last_address.into_iter().collect::<Vec<_>>();
 
                     // Notify Channeler to change the friend's address:
                     let update_friend = ChannelerUpdateFriend {
@@ -460,6 +789,16 @@ fn handle_move_token_success<B>(
                 cancel_pending_user_requests(m_state, outgoing_control, remote_public_key);
             }
 
+            // If the remote side asked to settle, we settle too:
+            let friend = m_state.state().friends.get(remote_public_key).unwrap();
+            if let ChannelStatus::Consistent(token_channel) = &friend.channel_status {
+                if token_channel.get_mutual_credit().state().settle.remote
+                    && friend.settle_status == SettleStatus::Active
+                {
+                    begin_settle(m_state, send_commands, outgoing_control, remote_public_key);
+                }
+            }
+
             handle_move_token_output(
                 m_state,
                 m_ephemeral,
@@ -468,6 +807,8 @@ fn handle_move_token_success<B>(
                 remote_public_key,
                 incoming_messages,
             );
+
+            try_complete_settle(m_state, send_commands, remote_public_key);
         }
     }
     if token_wanted {
@@ -480,7 +821,7 @@ fn handle_move_token_request<B, R>(
     m_ephemeral: &mut MutableEphemeral,
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
-    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
     rng: &R,
     remote_public_key: &PublicKey,
     friend_move_token_request: MoveTokenRequest<B>,
@@ -561,6 +902,8 @@ where
     // Cancel all pending requests to this friend:
     cancel_pending_requests(m_state, send_commands, outgoing_control, remote_public_key);
     cancel_pending_user_requests(m_state, outgoing_control, remote_public_key);
+    // Cancel incoming payments that have parts pending inside the token channel:
+    cancel_friend_incoming_payments(m_state, send_commands, remote_public_key);
 
     // Save remote incoming inconsistency details:
     let new_remote_reset_terms = remote_reset_terms;
@@ -609,7 +952,7 @@ pub fn handle_friend_message<B, R>(
     m_ephemeral: &mut MutableEphemeral,
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
-    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
     rng: &R,
     remote_public_key: &PublicKey,
     friend_message: FriendMessage<B>,
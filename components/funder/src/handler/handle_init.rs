@@ -1,7 +1,7 @@
 use common::canonical_serialize::CanonicalSerialize;
 use std::fmt::Debug;
 
-use proto::app_server::messages::RelayAddress;
+use proto::app_server::messages::ChannelAddress;
 use proto::funder::messages::{ChannelerUpdateFriend, FriendStatus};
 
 use crate::handler::handler::MutableFunderState;
@@ -9,7 +9,7 @@ use crate::types::ChannelerConfig;
 
 pub fn handle_init<B>(
     m_state: &MutableFunderState<B>,
-    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<ChannelAddress<B>>>,
 ) where
     B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
 {
@@ -35,14 +35,8 @@ pub fn handle_init<B>(
     // self.add_outgoing_control(FunderOutgoingControl::Report(report));
 
     // Notify Channeler about current address:
-    let relays = m_state
-        .state()
-        .relays
-        .iter()
-        .cloned()
-        .map(RelayAddress::from)
-        .collect();
-    outgoing_channeler_config.push(ChannelerConfig::SetRelays(relays));
+    let local_addresses = m_state.state().local_addresses().into_iter().collect();
+    outgoing_channeler_config.push(ChannelerConfig::SetRelays(local_addresses));
 
     // Notify channeler about all enabled friends:
     for enabled_friend in enabled_friends {
@@ -72,13 +66,14 @@ mod tests {
 
         let relays = vec![dummy_named_relay_address(0)];
         let mut state = FunderState::<u32>::new(local_pk, relays);
+        state.mutate(&FunderMutation::SetDirectAddress(Some(1337u32)));
 
         // Add a remote friend:
         let add_friend = AddFriend {
             friend_public_key: pk_b.clone(),
-            relays: vec![dummy_relay_address(3)],
+            relays: vec![ChannelAddress::Direct(1338u32), dummy_relay_address(3)],
             name: "pk_b".into(),
-            balance: 0i128,
+            balances: Vec::new(),
         };
         let f_mutation = FunderMutation::AddFriend(add_friend);
         state.mutate(&f_mutation);
@@ -88,7 +83,7 @@ mod tests {
         let funder_mutation = FunderMutation::FriendMutation((pk_b.clone(), friend_mutation));
         state.mutate(&funder_mutation);
 
-        let mut m_state = MutableFunderState::new(state);
+        let mut m_state = MutableFunderState::new(state, 0);
         let mut outgoing_channeler_config = Vec::new();
         handle_init(&mut m_state, &mut outgoing_channeler_config);
 
@@ -103,7 +98,10 @@ mod tests {
         let channeler_config = outgoing_channeler_config.remove(0);
         match channeler_config {
             ChannelerConfig::SetRelays(cur_relays) => {
-                assert_eq!(cur_relays, vec![dummy_relay_address(0)]);
+                assert_eq!(
+                    cur_relays,
+                    vec![ChannelAddress::Direct(1337u32), dummy_relay_address(0)]
+                );
             }
             _ => unreachable!(),
         };
@@ -114,7 +112,7 @@ mod tests {
             ChannelerConfig::UpdateFriend(channeler_update_friend) => {
                 assert_eq!(
                     channeler_update_friend.friend_relays,
-                    vec![dummy_relay_address(3)]
+                    vec![ChannelAddress::Direct(1338u32), dummy_relay_address(3)]
                 );
                 assert_eq!(channeler_update_friend.friend_public_key, pk_b);
             }
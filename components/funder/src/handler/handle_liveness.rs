@@ -126,7 +126,7 @@ mod tests {
             friend_public_key: remote_pk.clone(),
             relays: vec![dummy_relay_address(1)],
             name: "remote_pk".into(),
-            balance: 0i128,
+            balances: Vec::new(),
         };
         let funder_mutation = FunderMutation::AddFriend(add_friend);
         state.mutate(&funder_mutation);
@@ -146,7 +146,7 @@ mod tests {
 
         let ephemeral = Ephemeral::new();
 
-        let mut m_state = MutableFunderState::new(state);
+        let mut m_state = MutableFunderState::new(state, 0);
         let mut m_ephemeral = MutableEphemeral::new(ephemeral);
         let mut send_commands = SendCommands::new();
         let mut outgoing_control = Vec::new();
@@ -0,0 +1,68 @@
+use common::canonical_serialize::CanonicalSerialize;
+use std::fmt::Debug;
+
+use proto::funder::messages::FunderOutgoingControl;
+
+use crate::state::FunderMutation;
+use crate::timeouts::{PendingOrigin, TimeoutsMutation};
+
+use crate::handler::canceler::{cancel_pending_user_request, cancel_remote_request};
+use crate::handler::handler::MutableFunderState;
+use crate::handler::policy_engine::apply_debt_policies;
+use crate::handler::sender::SendCommands;
+
+/// Handle a time tick: Cancel all the requests that were not answered in time, and apply the
+/// debt policies of friends.
+pub fn handle_timer_tick<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    // Deadlines are persistent. We only count ticks while there are pending deadlines, to avoid
+    // writing to the database on every tick.
+    if !m_state.state().timeouts.deadlines.is_empty() {
+        let funder_mutation = FunderMutation::TimeoutsMutation(TimeoutsMutation::Tick);
+        m_state.mutate(funder_mutation);
+    }
+
+    for (request_id, pending_origin) in m_state.state().timeouts.expired() {
+        let timeouts_mutation =
+            TimeoutsMutation::RemoveDeadline((request_id.clone(), pending_origin.clone()));
+        m_state.mutate(FunderMutation::TimeoutsMutation(timeouts_mutation));
+
+        match pending_origin {
+            PendingOrigin::Friend(remote_public_key) => {
+                cancel_remote_request(
+                    m_state,
+                    send_commands,
+                    &remote_public_key,
+                    &request_id,
+                    false,
+                );
+            }
+            PendingOrigin::Forwarded(remote_public_key) => {
+                cancel_remote_request(
+                    m_state,
+                    send_commands,
+                    &remote_public_key,
+                    &request_id,
+                    true,
+                );
+            }
+            PendingOrigin::User(friend_public_key) => {
+                // If the request was already sent to the friend, we keep waiting for the
+                // friend to answer. The friend will send a failure once its own deadline passes.
+                let _ = cancel_pending_user_request(
+                    m_state,
+                    outgoing_control,
+                    &friend_public_key,
+                    &request_id,
+                );
+            }
+        }
+    }
+
+    apply_debt_policies(m_state, send_commands);
+}
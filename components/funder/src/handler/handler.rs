@@ -6,8 +6,8 @@ use crypto::crypto_rand::CryptoRandom;
 use crypto::identity::PublicKey;
 use crypto::uid::Uid;
 
-use proto::app_server::messages::RelayAddress;
-use proto::funder::messages::FunderOutgoingControl;
+use proto::app_server::messages::ChannelAddress;
+use proto::funder::messages::{FunderOutgoingControl, PendingRequest, SettleStatus};
 use proto::report::messages::{FunderReportMutation, FunderReportMutations};
 
 use identity::IdentityClient;
@@ -18,6 +18,8 @@ use crate::handler::handle_control::handle_control_message;
 use crate::handler::handle_friend::{handle_friend_message, HandleFriendError};
 use crate::handler::handle_init::handle_init;
 use crate::handler::handle_liveness::{handle_liveness_message, HandleLivenessError};
+use crate::handler::handle_timer::handle_timer_tick;
+use crate::handler::history::trim_payment_history;
 use crate::handler::sender::{create_friend_messages, SendCommands};
 
 use crate::ephemeral::{Ephemeral, EphemeralMutation};
@@ -29,17 +31,20 @@ pub struct MutableFunderState<B: Clone> {
     initial_state: FunderState<B>,
     state: FunderState<B>,
     mutations: Vec<FunderMutation<B>>,
+    /// Time of the handled event. Used to stamp new payment records.
+    timestamp: u64,
 }
 
 impl<B> MutableFunderState<B>
 where
     B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
 {
-    pub fn new(state: FunderState<B>) -> Self {
+    pub fn new(state: FunderState<B>, timestamp: u64) -> Self {
         MutableFunderState {
             initial_state: state.clone(),
             state,
             mutations: Vec::new(),
+            timestamp,
         }
     }
 
@@ -52,6 +57,10 @@ where
         &self.state
     }
 
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
     pub fn done(self) -> (FunderState<B>, Vec<FunderMutation<B>>, FunderState<B>) {
         (self.initial_state, self.mutations, self.state)
     }
@@ -132,6 +141,52 @@ where
     None
 }
 
+/// Check if we are the origin of a request (The first node on the route).
+pub fn is_local_origin<B>(state: &FunderState<B>, pending_request: &PendingRequest) -> bool
+where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    pending_request.route.index_to_pk(0) == Some(&state.local_public_key)
+}
+
+/// Find the friend we should pass back a response (or a failure) to, if we are mediating the
+/// given request.
+/// A request that we originated is never considered mediated, even if its route is a cycle that
+/// passes through us again (A self payment, used for rebalancing credits between friends).
+pub fn find_mediated_origin<B>(
+    state: &FunderState<B>,
+    pending_request: &PendingRequest,
+) -> Option<PublicKey>
+where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    if is_local_origin(state, pending_request) {
+        return None;
+    }
+    find_request_origin(state, &pending_request.request_id).cloned()
+}
+
+/// Check if a response (or a failure) for a given request is already waiting to be sent to a
+/// friend. This can happen if the request has timed out, and later an answer has arrived.
+pub fn is_response_queued<B>(
+    state: &FunderState<B>,
+    friend_public_key: &PublicKey,
+    request_id: &Uid,
+) -> bool
+where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let friend = match state.friends.get(friend_public_key) {
+        Some(friend) => friend,
+        None => return false,
+    };
+
+    friend
+        .pending_responses
+        .iter()
+        .any(|response_op| response_op.request_id() == request_id)
+}
+
 pub fn is_friend_ready<B>(
     state: &FunderState<B>,
     ephemeral: &Ephemeral,
@@ -145,6 +200,11 @@ where
         return false;
     }
 
+    // We don't send new requests to a friend we are settling with:
+    if friend.settle_status != SettleStatus::Active {
+        return false;
+    }
+
     // Make sure that the channel is consistent:
     let token_channel = match &friend.channel_status {
         ChannelStatus::Inconsistent(_) => return false,
@@ -163,7 +223,7 @@ where
 type FunderHandleIncomingOutput<B> = (
     SendCommands,
     Vec<FunderOutgoingControl<B>>,
-    Vec<ChannelerConfig<RelayAddress<B>>>,
+    Vec<ChannelerConfig<ChannelAddress<B>>>,
     Option<Uid>,
 );
 pub fn funder_handle_incoming<B, R>(
@@ -188,6 +248,11 @@ where
             None
         }
 
+        FunderIncoming::TimerTick => {
+            handle_timer_tick(&mut m_state, &mut send_commands, &mut outgoing_control);
+            None
+        }
+
         FunderIncoming::Control(funder_incoming_control) => {
             // Even if an error occurs, we must return an indication to the
             // user that the control request was received.
@@ -282,13 +347,15 @@ pub async fn funder_handle_message<'a, B, R>(
     max_node_relays: usize,
     max_operations_in_batch: usize,
     max_pending_user_requests: usize,
+    opt_max_payment_history: Option<usize>,
+    timestamp: u64,
     funder_incoming: FunderIncoming<B>,
 ) -> Result<FunderHandlerOutput<B>, FunderHandlerError>
 where
     B: 'a + Clone + PartialEq + Eq + CanonicalSerialize + Debug,
     R: CryptoRandom + 'a,
 {
-    let mut m_state = MutableFunderState::new(funder_state);
+    let mut m_state = MutableFunderState::new(funder_state, timestamp);
     let mut m_ephemeral = MutableEphemeral::new(funder_ephemeral);
     let mut outgoing_comms = Vec::new();
 
@@ -327,6 +394,11 @@ where
         outgoing_comms.push(FunderOutgoingComm::FriendMessage(friend_message));
     }
 
+    // Trimming the payment history is opt-in. By default all records are kept:
+    if let Some(max_payment_history) = opt_max_payment_history {
+        trim_payment_history(&mut m_state, max_payment_history);
+    }
+
     // Add reports:
     let (initial_state, funder_mutations, _state) = m_state.done();
     let (ephemeral_mutations, _ephemeral) = m_ephemeral.done();
@@ -0,0 +1,248 @@
+use std::fmt::Debug;
+
+use common::canonical_serialize::CanonicalSerialize;
+use crypto::identity::PublicKey;
+
+use proto::funder::messages::{
+    FunderOutgoingControl, PaymentDirection, PaymentRecord, PaymentStatus, PendingRequest,
+    ResponseReceived, ResponseSendFundsResult,
+};
+
+use crate::friend::{ChannelStatus, ResponseOp};
+use crate::handler::handler::MutableFunderState;
+use crate::state::FunderMutation;
+
+/// Append a record to the payment history.
+/// The record is stamped with the time of the handled event.
+pub fn add_payment_record<B>(
+    m_state: &mut MutableFunderState<B>,
+    pending_request: &PendingRequest,
+    direction: PaymentDirection,
+    status: PaymentStatus,
+    fees: u128,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let payment_record = PaymentRecord {
+        request_id: pending_request.request_id,
+        invoice_id: pending_request.invoice_id.clone(),
+        route: pending_request.route.clone(),
+        direction,
+        status,
+        currency: pending_request.currency.clone(),
+        dest_payment: pending_request.dest_payment,
+        fees,
+        timestamp: m_state.timestamp(),
+    };
+    let funder_mutation = FunderMutation::AddPaymentRecord(payment_record);
+    m_state.mutate(funder_mutation);
+}
+
+/// Remove the oldest records of the payment history, until it contains at most
+/// `max_payment_history` records.
+pub fn trim_payment_history<B>(m_state: &mut MutableFunderState<B>, max_payment_history: usize)
+where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    while m_state.state().payment_history.len() > max_payment_history {
+        m_state.mutate(FunderMutation::PopFrontPaymentRecord);
+    }
+}
+
+/// Report to the user that a request we have originated has failed.
+/// We are the reporting node of this failure.
+pub fn fail_user_request<B>(
+    m_state: &mut MutableFunderState<B>,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    pending_request: &PendingRequest,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    add_payment_record(
+        m_state,
+        pending_request,
+        PaymentDirection::Outgoing,
+        PaymentStatus::Failure,
+        0,
+    );
+
+    let response_received = ResponseReceived {
+        request_id: pending_request.request_id,
+        result: ResponseSendFundsResult::Failure(m_state.state().local_public_key.clone()),
+    };
+    outgoing_control.push(FunderOutgoingControl::ResponseReceived(response_received));
+}
+
+/// Record a successful payment we have mediated, once the response arrives from the next friend
+/// on the route. `pending_request` is the request we have forwarded to the next friend.
+/// The fee we have earned is the difference between the fees left in the request we received
+/// from `origin_public_key` and the fees left in the request we have forwarded.
+pub fn record_mediated_response<B>(
+    m_state: &mut MutableFunderState<B>,
+    origin_public_key: &PublicKey,
+    pending_request: &PendingRequest,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let opt_token_channel = m_state
+        .state()
+        .friends
+        .get(origin_public_key)
+        .and_then(|friend| match &friend.channel_status {
+            ChannelStatus::Consistent(token_channel) => Some(token_channel),
+            ChannelStatus::Inconsistent(_) => None,
+        });
+
+    let opt_incoming_left_fees = opt_token_channel.and_then(|token_channel| {
+        token_channel
+            .get_mutual_credit()
+            .state()
+            .pending_requests
+            .pending_remote_requests
+            .get(&pending_request.request_id)
+            .map(|incoming_request| incoming_request.left_fees)
+    });
+
+    let fee = opt_incoming_left_fees
+        .and_then(|incoming_left_fees| incoming_left_fees.checked_sub(pending_request.left_fees))
+        .unwrap_or(0);
+
+    add_payment_record(
+        m_state,
+        pending_request,
+        PaymentDirection::Mediated,
+        PaymentStatus::Success,
+        fee,
+    );
+}
+
+/// Record a response we have signed, right after it was queued to be sent to a friend.
+/// Signed responses and failures are recorded when they arrive from the next friend on the
+/// route, so only unsigned operations are handled here.
+pub fn record_response_op<B>(m_state: &mut MutableFunderState<B>, response_op: &ResponseOp)
+where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    match response_op {
+        ResponseOp::UnsignedResponse(pending_request) => {
+            // Only the destination of a request may send a response:
+            add_payment_record(
+                m_state,
+                pending_request,
+                PaymentDirection::Incoming,
+                PaymentStatus::Success,
+                pending_request.left_fees,
+            );
+        }
+        ResponseOp::UnsignedFailure(pending_request) => {
+            let local_public_key = &m_state.state().local_public_key;
+            let is_destination = pending_request.route.public_keys.last() == Some(local_public_key);
+            let direction = if is_destination {
+                PaymentDirection::Incoming
+            } else {
+                PaymentDirection::Mediated
+            };
+            add_payment_record(
+                m_state,
+                pending_request,
+                direction,
+                PaymentStatus::Failure,
+                0,
+            );
+        }
+        ResponseOp::Response(_) | ResponseOp::Failure(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crypto::identity::PUBLIC_KEY_LEN;
+    use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+    use crypto::uid::{Uid, UID_LEN};
+
+    use proto::funder::messages::FriendsRoute;
+
+    use crate::state::FunderState;
+    use crate::tests::utils::dummy_currency;
+
+    #[test]
+    fn test_add_payment_record_timestamp() {
+        let local_pk = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let state = FunderState::<u32>::new(local_pk, Vec::new());
+        let mut m_state = MutableFunderState::new(state, 0x1234);
+
+        let pending_request = PendingRequest {
+            request_id: Uid::from(&[0; UID_LEN]),
+            route: FriendsRoute {
+                public_keys: Vec::new(),
+            },
+            currency: dummy_currency(),
+            dest_payment: 10,
+            total_dest_payment: 10,
+            left_fees: 0,
+            invoice_id: InvoiceId::from(&[0; INVOICE_ID_LEN]),
+            left_ticks: 0,
+        };
+        add_payment_record(
+            &mut m_state,
+            &pending_request,
+            PaymentDirection::Outgoing,
+            PaymentStatus::Success,
+            0,
+        );
+
+        // The record is stamped both in the state and in the mutation:
+        assert_eq!(m_state.state().payment_history[0].timestamp, 0x1234);
+        let (_initial_state, mutations, _final_state) = m_state.done();
+        match &mutations[0] {
+            FunderMutation::AddPaymentRecord(payment_record) => {
+                assert_eq!(payment_record.timestamp, 0x1234)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_trim_payment_history() {
+        let local_pk = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let mut m_state = MutableFunderState::new(FunderState::<u32>::new(local_pk, Vec::new()), 0);
+
+        for i in 0..4u8 {
+            let payment_record = PaymentRecord {
+                request_id: Uid::from(&[i; UID_LEN]),
+                invoice_id: InvoiceId::from(&[i; INVOICE_ID_LEN]),
+                route: FriendsRoute {
+                    public_keys: Vec::new(),
+                },
+                direction: PaymentDirection::Outgoing,
+                status: PaymentStatus::Success,
+                currency: dummy_currency(),
+                dest_payment: 10,
+                fees: 0,
+                timestamp: 0,
+            };
+            m_state.mutate(FunderMutation::AddPaymentRecord(payment_record));
+        }
+
+        trim_payment_history(&mut m_state, 2);
+
+        // The oldest records were removed:
+        let request_ids = m_state
+            .state()
+            .payment_history
+            .iter()
+            .map(|payment_record| payment_record.request_id)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            request_ids,
+            vec![Uid::from(&[2; UID_LEN]), Uid::from(&[3; UID_LEN])]
+        );
+
+        // Trimming again has no effect:
+        trim_payment_history(&mut m_state, 2);
+        let (_initial_state, mutations, _final_state) = m_state.done();
+        assert_eq!(mutations.len(), 4 + 2);
+    }
+}
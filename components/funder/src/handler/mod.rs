@@ -3,8 +3,12 @@ mod handle_control;
 mod handle_friend;
 mod handle_init;
 mod handle_liveness;
+mod handle_timer;
 mod handler;
+mod history;
+mod policy_engine;
 mod sender;
+mod settle;
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,119 @@
+use std::fmt::Debug;
+
+use common::canonical_serialize::CanonicalSerialize;
+
+use crypto::identity::PublicKey;
+
+use proto::funder::messages::{RequestsStatus, SettleStatus};
+
+use crate::debt_policy::{is_over_soft_threshold, policy_remote_max_debt, update_repaid};
+use crate::friend::{ChannelStatus, FriendMutation};
+use crate::state::FunderMutation;
+
+use crate::handler::handler::MutableFunderState;
+use crate::handler::sender::SendCommands;
+
+/// Apply the debt policy of a friend (If it has one): Set the wanted remote max debt of the
+/// friend according to the policy, and close (or reopen) requests from the friend according to the
+/// soft threshold of the policy.
+pub fn apply_debt_policy<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    friend_public_key: &PublicKey,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let friend = m_state.state().friends.get(friend_public_key).unwrap();
+
+    let debt_policy = match &friend.opt_debt_policy {
+        Some(debt_policy) => debt_policy.clone(),
+        None => return,
+    };
+
+    // The remote max debt and requests status are left alone while settling:
+    if friend.settle_status != SettleStatus::Active {
+        return;
+    }
+
+    let balance = match &friend.channel_status {
+        // A currency without a credit line has zero balance:
+        ChannelStatus::Consistent(token_channel) => token_channel
+            .get_mutual_credit()
+            .state()
+            .balances
+            .get(&debt_policy.currency)
+            .map(|mc_balance| mc_balance.balance)
+            .unwrap_or(0),
+        // We can not tell the balance of an inconsistent channel:
+        ChannelStatus::Inconsistent(_) => return,
+    };
+
+    let mut debt_policy_status = update_repaid(&friend.debt_policy_status, balance);
+    let remote_max_debt = policy_remote_max_debt(&debt_policy, debt_policy_status.total_repaid);
+
+    let mut opt_requests_status = None;
+    if is_over_soft_threshold(&debt_policy, balance) {
+        if !debt_policy_status.closed_requests
+            && friend.wanted_local_requests_status == RequestsStatus::Open
+        {
+            opt_requests_status = Some(RequestsStatus::Closed);
+            debt_policy_status.closed_requests = true;
+        }
+    } else if debt_policy_status.closed_requests {
+        // We only reopen requests if they were closed by the policy:
+        if friend.wanted_local_requests_status == RequestsStatus::Closed {
+            opt_requests_status = Some(RequestsStatus::Open);
+        }
+        debt_policy_status.closed_requests = false;
+    }
+
+    let mut friend_mutations = Vec::new();
+    if debt_policy_status != friend.debt_policy_status {
+        friend_mutations.push(FriendMutation::SetDebtPolicyStatus(debt_policy_status));
+    }
+    let wanted_remote_max_debt = friend
+        .wanted_remote_max_debt
+        .get(&debt_policy.currency)
+        .cloned()
+        .unwrap_or(0);
+    if remote_max_debt != wanted_remote_max_debt {
+        friend_mutations.push(FriendMutation::SetWantedRemoteMaxDebt((
+            debt_policy.currency.clone(),
+            remote_max_debt,
+        )));
+    }
+    if let Some(requests_status) = opt_requests_status {
+        friend_mutations.push(FriendMutation::SetWantedLocalRequestsStatus(
+            requests_status,
+        ));
+    }
+
+    if friend_mutations.is_empty() {
+        return;
+    }
+
+    for friend_mutation in friend_mutations {
+        let funder_mutation =
+            FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+        m_state.mutate(funder_mutation);
+    }
+    send_commands.set_try_send(friend_public_key);
+}
+
+/// Apply the debt policies of all friends.
+pub fn apply_debt_policies<B>(m_state: &mut MutableFunderState<B>, send_commands: &mut SendCommands)
+where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let friend_public_keys = m_state
+        .state()
+        .friends
+        .iter()
+        .filter(|(_, friend)| friend.opt_debt_policy.is_some())
+        .map(|(friend_public_key, _)| friend_public_key.clone())
+        .collect::<Vec<_>>();
+
+    for friend_public_key in &friend_public_keys {
+        apply_debt_policy(m_state, send_commands, friend_public_key);
+    }
+}
@@ -5,11 +5,12 @@ use common::canonical_serialize::CanonicalSerialize;
 
 use crypto::crypto_rand::{CryptoRandom, RandValue};
 use crypto::identity::PublicKey;
+use crypto::uid::Uid;
 
-use proto::app_server::messages::RelayAddress;
+use proto::app_server::messages::ChannelAddress;
 use proto::funder::messages::{
-    ChannelerUpdateFriend, FriendMessage, FriendTcOp, FunderOutgoingControl, MoveTokenRequest,
-    RequestsStatus, ResponseReceived, ResponseSendFundsResult,
+    ChannelerUpdateFriend, CurrencyBalance, FriendMessage, FriendTcOp, FunderOutgoingControl,
+    MoveTokenRequest, RequestsStatus, SetRemoteMaxDebt, SettleStatus, TokenBalance,
 };
 
 use identity::IdentityClient;
@@ -27,7 +28,9 @@ use crate::token_channel::{SetDirection, TcDirection, TcMutation, TokenChannel};
 
 use crate::ephemeral::Ephemeral;
 use crate::handler::handler::{find_request_origin, MutableFunderState};
+use crate::handler::history::{fail_user_request, record_response_op};
 use crate::state::{FunderMutation, FunderState};
+use crate::timeouts::{PendingOrigin, TimeoutsMutation};
 
 #[derive(Debug, Clone)]
 pub struct FriendSendCommands {
@@ -114,7 +117,7 @@ struct PendingMoveToken<B> {
     friend_public_key: PublicKey,
     outgoing_mc: OutgoingMc,
     operations: Vec<FriendTcOp>,
-    opt_local_relays: Option<Vec<RelayAddress<B>>>,
+    opt_local_relays: Option<Vec<ChannelAddress<B>>>,
     token_wanted: bool,
     max_operations_in_batch: usize,
     /// Can we send this move token with empty operations list
@@ -161,7 +164,9 @@ where
                 warn!("Request already exists: {:?}", operation);
                 Ok(vec![])
             }
-            Err(QueueOperationError::InsufficientTrust) => {
+            // A currency without a credit line can not carry any credit:
+            Err(QueueOperationError::InsufficientTrust)
+            | Err(QueueOperationError::UnknownCurrency) => {
                 Err(PendingQueueError::InsufficientTrust)
             }
             Err(_) => unreachable!(),
@@ -183,7 +188,7 @@ where
     }
 
     /// Set local address inside pending move token.
-    fn set_local_relays(&mut self, local_relays: Vec<RelayAddress<B>>) {
+    fn set_local_relays(&mut self, local_relays: Vec<ChannelAddress<B>>) {
         self.opt_local_relays = Some(local_relays);
     }
 }
@@ -234,8 +239,27 @@ pub async fn apply_local_reset<'a, B, R>(
     let rand_nonce = RandValue::new(rng);
     let move_token_counter = 0;
 
-    let local_pending_debt = 0;
-    let remote_pending_debt = 0;
+    // The remote side states the balances from its point of view:
+    let mut balances = remote_reset_terms
+        .balance_for_reset
+        .iter()
+        .map(|currency_balance| CurrencyBalance {
+            currency: currency_balance.currency.clone(),
+            balance: currency_balance.balance.checked_neg().unwrap(),
+        })
+        .collect::<Vec<_>>();
+    balances.sort_by(|a, b| a.currency.cmp(&b.currency));
+
+    let token_balances = balances
+        .iter()
+        .map(|currency_balance| TokenBalance {
+            currency: currency_balance.currency.clone(),
+            balance: currency_balance.balance,
+            local_pending_debt: 0,
+            remote_pending_debt: 0,
+        })
+        .collect::<Vec<_>>();
+
     let opt_local_relays = None;
     let u_reset_move_token = create_unsigned_move_token(
         // No operations are required for a reset move token
@@ -246,9 +270,7 @@ pub async fn apply_local_reset<'a, B, R>(
         friend_public_key.clone(),
         remote_reset_terms.inconsistency_counter,
         move_token_counter,
-        remote_reset_terms.balance_for_reset.checked_neg().unwrap(),
-        local_pending_debt,
-        remote_pending_debt,
+        token_balances,
         rand_nonce,
     );
 
@@ -258,7 +280,7 @@ pub async fn apply_local_reset<'a, B, R>(
         &m_state.state().local_public_key,
         friend_public_key,
         &reset_move_token,
-        remote_reset_terms.balance_for_reset.checked_neg().unwrap(),
+        &balances,
         channel_inconsistent.opt_last_incoming_move_token.clone(),
     );
 
@@ -279,7 +301,7 @@ async fn send_friend_iter1<'a, B, R>(
     failure_public_keys: &'a mut HashSet<PublicKey>,
     mut outgoing_messages: &'a mut Vec<OutgoingMessage<B>>,
     outgoing_control: &'a mut Vec<FunderOutgoingControl<B>>,
-    outgoing_channeler_config: &'a mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &'a mut Vec<ChannelerConfig<ChannelAddress<B>>>,
 ) where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
     R: CryptoRandom,
@@ -392,7 +414,7 @@ where
     match &friend.sent_local_relays {
         SentLocalRelays::NeverSent => return true,
         SentLocalRelays::Transition((relays, _)) | SentLocalRelays::LastSent(relays) => {
-            if relays != &state.relays {
+            if relays != &state.local_addresses() {
                 return true;
             }
         }
@@ -401,8 +423,10 @@ where
     // Check if update to remote_max_debt is required:
     match &friend.channel_status {
         ChannelStatus::Consistent(token_channel) => {
-            if friend.wanted_remote_max_debt != token_channel.get_remote_max_debt() {
-                return true;
+            for (currency, wanted_remote_max_debt) in &friend.wanted_remote_max_debt {
+                if *wanted_remote_max_debt != token_channel.get_remote_max_debt(currency) {
+                    return true;
+                }
             }
 
             // Open or close requests is needed:
@@ -415,6 +439,13 @@ where
             if friend.wanted_local_requests_status != *local_requests_status {
                 return true;
             }
+
+            // Asking the remote side to settle is needed:
+            if friend.settle_status != SettleStatus::Active
+                && !token_channel.get_mutual_credit().state().settle.local
+            {
+                return true;
+            }
         }
         ChannelStatus::Inconsistent(_) => {}
     };
@@ -481,11 +512,8 @@ where
         }
         None => {
             // We are the origin of this request
-            let response_received = ResponseReceived {
-                request_id: request_send_funds.request_id,
-                result: ResponseSendFundsResult::Failure(m_state.state().local_public_key.clone()),
-            };
-            outgoing_control.push(FunderOutgoingControl::ResponseReceived(response_received));
+            let pending_request = create_pending_request(request_send_funds);
+            fail_user_request(m_state, outgoing_control, &pending_request);
         }
     }
 
@@ -527,6 +555,26 @@ where
 }
 */
 
+/// Forget about the deadline of a request that can no longer time out.
+fn remove_deadline<B>(
+    m_state: &mut MutableFunderState<B>,
+    request_id: &Uid,
+    pending_origin: PendingOrigin,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let deadline_key = (request_id.clone(), pending_origin);
+    if m_state
+        .state()
+        .timeouts
+        .deadlines
+        .contains_key(&deadline_key)
+    {
+        let timeouts_mutation = TimeoutsMutation::RemoveDeadline(deadline_key);
+        m_state.mutate(FunderMutation::TimeoutsMutation(timeouts_mutation));
+    }
+}
+
 async fn response_op_to_friend_tc_op<'a, B, R>(
     m_state: &'a mut MutableFunderState<B>,
     response_op: ResponseOp,
@@ -565,7 +613,7 @@ where
 /// Requests that fail to be processed are moved to the failure queues of the relevant friends.
 async fn collect_outgoing_move_token<'a, B, R>(
     m_state: &'a mut MutableFunderState<B>,
-    outgoing_channeler_config: &'a mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    outgoing_channeler_config: &'a mut Vec<ChannelerConfig<ChannelAddress<B>>>,
     outgoing_control: &'a mut Vec<FunderOutgoingControl<B>>,
     failure_public_keys: &'a mut HashSet<PublicKey>,
     friend_public_key: &'a PublicKey,
@@ -592,25 +640,21 @@ where
 
     // Send update about local address if needed:
     let friend = m_state.state().friends.get(friend_public_key).unwrap();
-    let local_named_relays = m_state.state().relays.clone();
+    let local_addresses = m_state.state().local_addresses();
 
-    let local_relays = local_named_relays
-        .iter()
-        .cloned()
-        .map(RelayAddress::from)
-        .collect();
+    let local_relays = local_addresses.iter().cloned().collect::<Vec<_>>();
 
     let opt_new_sent_local_relays = match &friend.sent_local_relays {
         SentLocalRelays::NeverSent => {
             pending_move_token.set_local_relays(local_relays);
-            Some(SentLocalRelays::LastSent(local_named_relays.clone()))
+            Some(SentLocalRelays::LastSent(local_addresses.clone()))
         }
         SentLocalRelays::Transition((last_sent_local_relays, _))
         | SentLocalRelays::LastSent(last_sent_local_relays) => {
-            if &local_named_relays != last_sent_local_relays {
+            if &local_addresses != last_sent_local_relays {
                 pending_move_token.set_local_relays(local_relays.clone());
                 Some(SentLocalRelays::Transition((
-                    local_named_relays.clone(),
+                    local_addresses.clone(),
                     last_sent_local_relays.clone(),
                 )))
             } else {
@@ -640,15 +684,27 @@ where
 
     let friend = m_state.state().friends.get(friend_public_key).unwrap();
 
-    // Set remote_max_debt if needed:
-    let remote_max_debt = match &friend.channel_status {
+    // Set remote_max_debt if needed (For every currency):
+    let token_channel = match &friend.channel_status {
         ChannelStatus::Consistent(token_channel) => token_channel,
         ChannelStatus::Inconsistent(_) => unreachable!(),
-    }
-    .get_remote_max_debt();
+    };
 
-    if friend.wanted_remote_max_debt != remote_max_debt {
-        let operation = FriendTcOp::SetRemoteMaxDebt(friend.wanted_remote_max_debt);
+    let mut set_remote_max_debts = friend
+        .wanted_remote_max_debt
+        .iter()
+        .filter(|(currency, wanted_remote_max_debt)| {
+            **wanted_remote_max_debt != token_channel.get_remote_max_debt(currency)
+        })
+        .map(|(currency, wanted_remote_max_debt)| SetRemoteMaxDebt {
+            currency: currency.clone(),
+            remote_max_debt: *wanted_remote_max_debt,
+        })
+        .collect::<Vec<_>>();
+    set_remote_max_debts.sort_by(|a, b| a.currency.cmp(&b.currency));
+
+    for set_remote_max_debt in set_remote_max_debts {
+        let operation = FriendTcOp::SetRemoteMaxDebt(set_remote_max_debt);
         await!(queue_operation_or_failure(
             m_state,
             pending_move_token,
@@ -686,6 +742,26 @@ where
         ))?;
     }
 
+    let friend = m_state.state().friends.get(friend_public_key).unwrap();
+    let token_channel = match &friend.channel_status {
+        ChannelStatus::Consistent(token_channel) => token_channel,
+        ChannelStatus::Inconsistent(_) => unreachable!(),
+    };
+
+    // Ask the remote side to settle if needed.
+    // This is queued right after closing requests, as no new requests may follow:
+    if friend.settle_status != SettleStatus::Active
+        && !token_channel.get_mutual_credit().state().settle.local
+    {
+        await!(queue_operation_or_failure(
+            m_state,
+            pending_move_token,
+            failure_public_keys,
+            outgoing_control,
+            &FriendTcOp::Settle
+        ))?;
+    }
+
     let friend = m_state.state().friends.get(friend_public_key).unwrap();
     // Send pending responses (responses and failures)
     // TODO: Possibly replace this clone with something more efficient later:
@@ -693,7 +769,7 @@ where
     while let Some(pending_response) = pending_responses.pop_front() {
         let pending_op = await!(response_op_to_friend_tc_op(
             m_state,
-            pending_response,
+            pending_response.clone(),
             identity_client,
             rng
         ));
@@ -704,6 +780,17 @@ where
             outgoing_control,
             &pending_op
         ))?;
+        record_response_op(m_state, &pending_response);
+        remove_deadline(
+            m_state,
+            pending_response.request_id(),
+            PendingOrigin::Friend(friend_public_key.clone()),
+        );
+        remove_deadline(
+            m_state,
+            pending_response.request_id(),
+            PendingOrigin::Forwarded(friend_public_key.clone()),
+        );
 
         let friend_mutation = FriendMutation::PopFrontPendingResponse;
         let funder_mutation =
@@ -736,6 +823,7 @@ where
     // Send as many pending user requests as possible:
     let mut pending_user_requests = friend.pending_user_requests.clone();
     while let Some(request_send_funds) = pending_user_requests.pop_front() {
+        let request_id = request_send_funds.request_id.clone();
         let pending_op = FriendTcOp::RequestSendFunds(request_send_funds);
         await!(queue_operation_or_failure(
             m_state,
@@ -744,6 +832,12 @@ where
             outgoing_control,
             &pending_op
         ))?;
+        // Once the request is sent, it can not be cancelled anymore:
+        remove_deadline(
+            m_state,
+            &request_id,
+            PendingOrigin::User(friend_public_key.clone()),
+        );
         let friend_mutation = FriendMutation::PopFrontPendingUserRequest;
         let funder_mutation =
             FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
@@ -771,7 +865,7 @@ where
     while let Some(pending_response) = pending_responses.pop_front() {
         let pending_op = await!(response_op_to_friend_tc_op(
             m_state,
-            pending_response,
+            pending_response.clone(),
             identity_client,
             rng
         ));
@@ -785,6 +879,17 @@ where
             &mut dummy_outgoing_control,
             &pending_op
         ))?;
+        record_response_op(m_state, &pending_response);
+        remove_deadline(
+            m_state,
+            pending_response.request_id(),
+            PendingOrigin::Friend(friend_public_key.clone()),
+        );
+        remove_deadline(
+            m_state,
+            pending_response.request_id(),
+            PendingOrigin::Forwarded(friend_public_key.clone()),
+        );
 
         let friend_mutation = FriendMutation::PopFrontPendingResponse;
         let funder_mutation =
@@ -925,7 +1030,7 @@ pub async fn create_friend_messages<'a, B, R>(
 ) -> (
     Vec<FunderOutgoingControl<B>>,
     Vec<OutgoingMessage<B>>,
-    Vec<ChannelerConfig<RelayAddress<B>>>,
+    Vec<ChannelerConfig<ChannelAddress<B>>>,
 )
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
@@ -0,0 +1,79 @@
+use std::fmt::Debug;
+
+use common::canonical_serialize::CanonicalSerialize;
+
+use crypto::identity::PublicKey;
+
+use proto::funder::messages::{FunderOutgoingControl, RequestsStatus, SettleStatus};
+
+use crate::friend::{ChannelStatus, FriendMutation};
+use crate::state::FunderMutation;
+
+use crate::handler::canceler::{cancel_pending_requests, cancel_pending_user_requests};
+use crate::handler::handler::MutableFunderState;
+use crate::handler::sender::SendCommands;
+
+/// Start settling the mutual credit with a friend: Close requests from the friend, and cancel
+/// all the requests that are waiting to be sent to the friend.
+/// A Settle operation will be sent to the friend together with the next move token.
+pub fn begin_settle<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    friend_public_key: &PublicKey,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let friend_mutation = FriendMutation::SetSettleStatus(SettleStatus::Settling);
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+
+    let friend_mutation = FriendMutation::SetWantedLocalRequestsStatus(RequestsStatus::Closed);
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+
+    // Requests that were not yet sent to the friend will never be sent:
+    cancel_pending_requests(m_state, send_commands, outgoing_control, friend_public_key);
+    cancel_pending_user_requests(m_state, outgoing_control, friend_public_key);
+
+    send_commands.set_try_send(friend_public_key);
+}
+
+/// Check if the settlement with a friend is complete. This should be called after a move token
+/// was received from the friend.
+///
+/// The settlement is complete when both sides asked to settle and no requests are left pending
+/// in the token channel. The last incoming move token is then a signed commitment of the friend
+/// to the closing balance. We send back one more move token, so that the friend will also have
+/// such a commitment from us.
+pub fn try_complete_settle<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    friend_public_key: &PublicKey,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let friend = m_state.state().friends.get(friend_public_key).unwrap();
+    if friend.settle_status != SettleStatus::Settling {
+        return;
+    }
+
+    let token_channel = match &friend.channel_status {
+        ChannelStatus::Consistent(token_channel) => token_channel,
+        ChannelStatus::Inconsistent(_) => return,
+    };
+
+    if !token_channel.get_mutual_credit().is_settled() {
+        return;
+    }
+
+    let friend_mutation = FriendMutation::SetSettleStatus(SettleStatus::Settled);
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+
+    // Make sure the final move token is sent, even if it is empty:
+    send_commands.set_remote_wants_token(friend_public_key);
+}
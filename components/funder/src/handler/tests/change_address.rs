@@ -79,7 +79,7 @@ async fn task_handler_change_address(
         friend_public_key: pk2.clone(),
         relays: vec![dummy_relay_address(2)],
         name: String::from("pk2"),
-        balance: 0i128,
+        balances: Vec::new(),
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[11; UID_LEN]),
@@ -119,7 +119,7 @@ async fn task_handler_change_address(
         friend_public_key: pk1.clone(),
         relays: vec![dummy_relay_address(1)],
         name: String::from("pk1"),
-        balance: 0i128,
+        balances: Vec::new(),
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[13; UID_LEN]),
@@ -178,7 +178,7 @@ async fn task_handler_change_address(
                 let friend_move_token = &move_token_request.friend_move_token;
                 assert_eq!(friend_move_token.move_token_counter, 0);
                 assert_eq!(friend_move_token.inconsistency_counter, 0);
-                assert_eq!(friend_move_token.balance, 0);
+                assert!(friend_move_token.balances.is_empty());
                 assert_eq!(friend_move_token.opt_local_relays, None);
             } else {
                 unreachable!();
@@ -224,7 +224,7 @@ async fn task_handler_change_address(
 
                 assert_eq!(friend_move_token.move_token_counter, 1);
                 assert_eq!(friend_move_token.inconsistency_counter, 0);
-                assert_eq!(friend_move_token.balance, 0);
+                assert!(friend_move_token.balances.is_empty());
                 assert_eq!(
                     friend_move_token.opt_local_relays,
                     Some(vec![dummy_relay_address(2)])
@@ -260,7 +260,7 @@ async fn task_handler_change_address(
 
                 assert_eq!(friend_move_token.move_token_counter, 2);
                 assert_eq!(friend_move_token.inconsistency_counter, 0);
-                assert_eq!(friend_move_token.balance, 0);
+                assert!(friend_move_token.balances.is_empty());
                 assert_eq!(
                     friend_move_token.opt_local_relays,
                     Some(vec![dummy_relay_address(1)])
@@ -296,7 +296,7 @@ async fn task_handler_change_address(
 
                 assert_eq!(friend_move_token.move_token_counter, 3);
                 assert_eq!(friend_move_token.inconsistency_counter, 0);
-                assert_eq!(friend_move_token.balance, 0);
+                assert!(friend_move_token.balances.is_empty());
                 assert_eq!(friend_move_token.opt_local_relays, None);
             } else {
                 unreachable!();
@@ -369,7 +369,7 @@ async fn task_handler_change_address(
 
                 assert_eq!(friend_move_token.move_token_counter, 4);
                 assert_eq!(friend_move_token.inconsistency_counter, 0);
-                assert_eq!(friend_move_token.balance, 0);
+                assert!(friend_move_token.balances.is_empty());
                 let expected_address = vec![dummy_relay_address(1), dummy_relay_address(11)];
                 assert_eq!(friend_move_token.opt_local_relays, Some(expected_address));
             } else {
@@ -403,7 +403,7 @@ async fn task_handler_change_address(
 
                 assert_eq!(friend_move_token.move_token_counter, 5);
                 assert_eq!(friend_move_token.inconsistency_counter, 0);
-                assert_eq!(friend_move_token.balance, 0);
+                assert!(friend_move_token.balances.is_empty());
                 assert_eq!(friend_move_token.opt_local_relays, None);
             } else {
                 unreachable!();
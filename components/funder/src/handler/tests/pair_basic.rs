@@ -1,6 +1,7 @@
 use super::utils::apply_funder_incoming;
 
 use std::cmp::Ordering;
+use std::convert::TryFrom;
 
 use futures::executor::ThreadPool;
 use futures::task::SpawnExt;
@@ -15,9 +16,9 @@ use crypto::test_utils::DummyRandom;
 use crypto::uid::{Uid, UID_LEN};
 
 use proto::funder::messages::{
-    AddFriend, FriendMessage, FriendStatus, FriendsRoute, FunderControl, FunderIncomingControl,
-    RequestsStatus, SetFriendRemoteMaxDebt, SetFriendStatus, SetRequestsStatus,
-    UserRequestSendFunds,
+    AddFriend, Currency, FriendMessage, FriendStatus, FriendsRoute, FunderControl,
+    FunderIncomingControl, RequestsStatus, SetFriendRemoteMaxDebt, SetFriendStatus,
+    SetRequestsStatus, UserRequestSendFunds,
 };
 
 use crate::ephemeral::Ephemeral;
@@ -56,6 +57,7 @@ async fn task_handler_pair_basic<'a>(
     let mut ephemeral2 = Ephemeral::new();
 
     let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let currency = Currency::try_from("USD".to_owned()).unwrap();
 
     // Initialize 1:
     let funder_incoming = FunderIncoming::Init;
@@ -84,7 +86,7 @@ async fn task_handler_pair_basic<'a>(
         friend_public_key: pk2.clone(),
         relays: vec![dummy_relay_address(2)],
         name: String::from("pk2"),
-        balance: 0i128,
+        balances: Vec::new(),
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[11; UID_LEN]),
@@ -124,7 +126,7 @@ async fn task_handler_pair_basic<'a>(
         friend_public_key: pk1.clone(),
         relays: vec![dummy_relay_address(1)],
         name: String::from("pk1"),
-        balance: 0i128,
+        balances: Vec::new(),
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[13; UID_LEN]),
@@ -184,7 +186,7 @@ async fn task_handler_pair_basic<'a>(
                 let friend_move_token = &move_token_request.friend_move_token;
                 assert_eq!(friend_move_token.move_token_counter, 0);
                 assert_eq!(friend_move_token.inconsistency_counter, 0);
-                assert_eq!(friend_move_token.balance, 0);
+                assert!(friend_move_token.balances.is_empty());
                 assert!(friend_move_token.opt_local_relays.is_none());
             } else {
                 unreachable!();
@@ -244,7 +246,7 @@ async fn task_handler_pair_basic<'a>(
                 let friend_move_token = &move_token_request.friend_move_token;
                 assert_eq!(friend_move_token.move_token_counter, 1);
                 assert_eq!(friend_move_token.inconsistency_counter, 0);
-                assert_eq!(friend_move_token.balance, 0);
+                assert!(friend_move_token.balances.is_empty());
                 assert_eq!(
                     friend_move_token.opt_local_relays,
                     Some(vec![dummy_relay_address(2)])
@@ -280,7 +282,7 @@ async fn task_handler_pair_basic<'a>(
                 let friend_move_token = &move_token_request.friend_move_token;
                 assert_eq!(friend_move_token.move_token_counter, 2);
                 assert_eq!(friend_move_token.inconsistency_counter, 0);
-                assert_eq!(friend_move_token.balance, 0);
+                assert!(friend_move_token.balances.is_empty());
                 assert_eq!(
                     friend_move_token.opt_local_relays,
                     Some(vec![dummy_relay_address(1)])
@@ -317,7 +319,7 @@ async fn task_handler_pair_basic<'a>(
                 let friend_move_token = &move_token_request.friend_move_token;
                 assert_eq!(friend_move_token.move_token_counter, 3);
                 assert_eq!(friend_move_token.inconsistency_counter, 0);
-                assert_eq!(friend_move_token.balance, 0);
+                assert!(friend_move_token.balances.is_empty());
                 assert_eq!(friend_move_token.opt_local_relays, None);
             } else {
                 unreachable!();
@@ -344,6 +346,7 @@ async fn task_handler_pair_basic<'a>(
     // Node1 receives control message to set remote max debt.
     let set_friend_remote_max_debt = SetFriendRemoteMaxDebt {
         friend_public_key: pk2.clone(),
+        currency: currency.clone(),
         remote_max_debt: 100,
     };
     let incoming_control_message = FunderIncomingControl::new(
@@ -390,11 +393,7 @@ async fn task_handler_pair_basic<'a>(
     let friend2 = state1.friends.get(&pk2).unwrap();
     let remote_max_debt = match &friend2.channel_status {
         ChannelStatus::Consistent(token_channel) => {
-            token_channel
-                .get_mutual_credit()
-                .state()
-                .balance
-                .remote_max_debt
+            token_channel.get_mutual_credit().state().balances[&currency].remote_max_debt
         }
         _ => unreachable!(),
     };
@@ -403,11 +402,7 @@ async fn task_handler_pair_basic<'a>(
     let friend1 = state2.friends.get(&pk1).unwrap();
     let local_max_debt = match &friend1.channel_status {
         ChannelStatus::Consistent(token_channel) => {
-            token_channel
-                .get_mutual_credit()
-                .state()
-                .balance
-                .local_max_debt
+            token_channel.get_mutual_credit().state().balances[&currency].local_max_debt
         }
         _ => unreachable!(),
     };
@@ -421,8 +416,12 @@ async fn task_handler_pair_basic<'a>(
         route: FriendsRoute {
             public_keys: vec![pk2.clone(), pk1.clone()],
         },
+        currency: currency.clone(),
         invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
         dest_payment: 20,
+        total_dest_payment: 20,
+        fees: 0,
+        timeout_ticks: 0x100,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[16; UID_LEN]),
@@ -563,8 +562,12 @@ async fn task_handler_pair_basic<'a>(
         route: FriendsRoute {
             public_keys: vec![pk2.clone(), pk1.clone()],
         },
+        currency: currency.clone(),
         invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
         dest_payment: 20,
+        total_dest_payment: 20,
+        fees: 0,
+        timeout_ticks: 0x100,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[18; UID_LEN]),
@@ -608,9 +611,12 @@ async fn task_handler_pair_basic<'a>(
             if let FriendMessage::MoveTokenRequest(move_token_request) = friend_message {
                 assert_eq!(pk, &pk2);
                 let friend_move_token = &move_token_request.friend_move_token;
-                assert_eq!(friend_move_token.balance, 20);
-                assert_eq!(friend_move_token.local_pending_debt, 0);
-                assert_eq!(friend_move_token.remote_pending_debt, 0);
+                assert_eq!(friend_move_token.balances.len(), 1);
+                let token_balance = &friend_move_token.balances[0];
+                assert_eq!(token_balance.currency, currency);
+                assert_eq!(token_balance.balance, 20);
+                assert_eq!(token_balance.local_pending_debt, 0);
+                assert_eq!(token_balance.remote_pending_debt, 0);
             } else {
                 unreachable!();
             }
@@ -637,9 +643,10 @@ async fn task_handler_pair_basic<'a>(
         ChannelStatus::Consistent(token_channel) => token_channel.get_mutual_credit().state(),
         _ => unreachable!(),
     };
-    assert_eq!(mutual_credit_state.balance.balance, 20);
-    assert_eq!(mutual_credit_state.balance.remote_pending_debt, 0);
-    assert_eq!(mutual_credit_state.balance.local_pending_debt, 0);
+    let mc_balance = &mutual_credit_state.balances[&currency];
+    assert_eq!(mc_balance.balance, 20);
+    assert_eq!(mc_balance.remote_pending_debt, 0);
+    assert_eq!(mc_balance.local_pending_debt, 0);
 
     // Current balance from Node2 point of view:
     let friend1 = state2.friends.get(&pk1).unwrap();
@@ -647,9 +654,10 @@ async fn task_handler_pair_basic<'a>(
         ChannelStatus::Consistent(token_channel) => token_channel.get_mutual_credit().state(),
         _ => unreachable!(),
     };
-    assert_eq!(mutual_credit_state.balance.balance, -20);
-    assert_eq!(mutual_credit_state.balance.remote_pending_debt, 0);
-    assert_eq!(mutual_credit_state.balance.local_pending_debt, 0);
+    let mc_balance = &mutual_credit_state.balances[&currency];
+    assert_eq!(mc_balance.balance, -20);
+    assert_eq!(mc_balance.remote_pending_debt, 0);
+    assert_eq!(mc_balance.local_pending_debt, 0);
 }
 
 #[test]
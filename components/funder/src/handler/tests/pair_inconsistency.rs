@@ -1,6 +1,7 @@
 use super::utils::apply_funder_incoming;
 
 use std::cmp::Ordering;
+use std::convert::TryFrom;
 
 use futures::executor::ThreadPool;
 use futures::task::SpawnExt;
@@ -14,8 +15,8 @@ use crypto::test_utils::DummyRandom;
 use crypto::uid::{Uid, UID_LEN};
 
 use proto::funder::messages::{
-    AddFriend, FriendMessage, FriendStatus, FunderControl, FunderIncomingControl,
-    ResetFriendChannel, SetFriendStatus,
+    AddFriend, Currency, CurrencyBalance, FriendMessage, FriendStatus, FunderControl,
+    FunderIncomingControl, ResetFriendChannel, SetFriendStatus,
 };
 
 use crate::ephemeral::Ephemeral;
@@ -53,6 +54,7 @@ async fn task_handler_pair_inconsistency<'a>(
     let mut ephemeral2 = Ephemeral::new();
 
     let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let currency = Currency::try_from("USD".to_owned()).unwrap();
 
     // Initialize 1:
     let funder_incoming = FunderIncoming::Init;
@@ -81,7 +83,10 @@ async fn task_handler_pair_inconsistency<'a>(
         friend_public_key: pk2.clone(),
         relays: vec![dummy_relay_address(2)],
         name: String::from("pk2"),
-        balance: 20i128,
+        balances: vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 20i128,
+        }],
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[11; UID_LEN]),
@@ -123,7 +128,10 @@ async fn task_handler_pair_inconsistency<'a>(
         friend_public_key: pk1.clone(),
         relays: vec![dummy_relay_address(1)],
         name: String::from("pk1"),
-        balance: -10i128,
+        balances: vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: -10i128,
+        }],
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[13; UID_LEN]),
@@ -183,7 +191,7 @@ async fn task_handler_pair_inconsistency<'a>(
                 let friend_move_token = &move_token_request.friend_move_token;
                 assert_eq!(friend_move_token.move_token_counter, 0);
                 assert_eq!(friend_move_token.inconsistency_counter, 0);
-                assert_eq!(friend_move_token.balance, 20i128);
+                assert_eq!(friend_move_token.balances[0].balance, 20i128);
                 assert!(friend_move_token.opt_local_relays.is_none());
             } else {
                 unreachable!();
@@ -235,7 +243,7 @@ async fn task_handler_pair_inconsistency<'a>(
                 let friend_move_token = &move_token_request.friend_move_token;
                 assert_eq!(friend_move_token.move_token_counter, 1);
                 assert_eq!(friend_move_token.inconsistency_counter, 0);
-                assert_eq!(friend_move_token.balance, -10i128);
+                assert_eq!(friend_move_token.balances[0].balance, -10i128);
                 assert!(friend_move_token.opt_local_relays.is_some());
             } else {
                 unreachable!();
@@ -265,7 +273,13 @@ async fn task_handler_pair_inconsistency<'a>(
         FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
             if let FriendMessage::InconsistencyError(reset_terms) = friend_message {
                 assert_eq!(reset_terms.inconsistency_counter, 1);
-                assert_eq!(reset_terms.balance_for_reset, 20i128);
+                assert_eq!(
+                    reset_terms.balance_for_reset,
+                    vec![CurrencyBalance {
+                        currency: currency.clone(),
+                        balance: 20i128,
+                    }]
+                );
                 assert_eq!(pk, &pk2);
             } else {
                 unreachable!();
@@ -294,7 +308,13 @@ async fn task_handler_pair_inconsistency<'a>(
         FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
             if let FriendMessage::InconsistencyError(reset_terms) = friend_message {
                 assert_eq!(reset_terms.inconsistency_counter, 1);
-                assert_eq!(reset_terms.balance_for_reset, -10i128);
+                assert_eq!(
+                    reset_terms.balance_for_reset,
+                    vec![CurrencyBalance {
+                        currency: currency.clone(),
+                        balance: -10i128,
+                    }]
+                );
                 assert_eq!(pk, &pk1);
                 (friend_message.clone(), reset_terms.reset_token.clone())
             } else {
@@ -344,7 +364,7 @@ async fn task_handler_pair_inconsistency<'a>(
     match &friend2.channel_status {
         ChannelStatus::Consistent(token_channel) => {
             assert_eq!(
-                token_channel.get_mutual_credit().state().balance.balance,
+                token_channel.get_mutual_credit().state().balances[&currency].balance,
                 10i128
             );
         }
@@ -364,7 +384,7 @@ async fn task_handler_pair_inconsistency<'a>(
                 assert_eq!(friend_move_token.old_token, reset_token2);
                 assert_eq!(friend_move_token.move_token_counter, 0);
                 assert_eq!(friend_move_token.inconsistency_counter, 1);
-                assert_eq!(friend_move_token.balance, 10i128);
+                assert_eq!(friend_move_token.balances[0].balance, 10i128);
                 assert!(friend_move_token.opt_local_relays.is_none());
             } else {
                 unreachable!();
@@ -399,7 +419,7 @@ async fn task_handler_pair_inconsistency<'a>(
                 assert!(friend_move_token.operations.is_empty());
                 assert_eq!(friend_move_token.move_token_counter, 1);
                 assert_eq!(friend_move_token.inconsistency_counter, 1);
-                assert_eq!(friend_move_token.balance, -10i128);
+                assert_eq!(friend_move_token.balances[0].balance, -10i128);
                 assert!(friend_move_token.opt_local_relays.is_none());
             } else {
                 unreachable!();
@@ -434,7 +454,7 @@ async fn task_handler_pair_inconsistency<'a>(
                 assert!(friend_move_token.operations.is_empty());
                 assert_eq!(friend_move_token.move_token_counter, 2);
                 assert_eq!(friend_move_token.inconsistency_counter, 1);
-                assert_eq!(friend_move_token.balance, 10i128);
+                assert_eq!(friend_move_token.balances[0].balance, 10i128);
                 assert_eq!(
                     friend_move_token.opt_local_relays,
                     Some(vec![dummy_relay_address(1)])
@@ -470,7 +490,7 @@ async fn task_handler_pair_inconsistency<'a>(
                 assert!(friend_move_token.operations.is_empty());
                 assert_eq!(friend_move_token.move_token_counter, 3);
                 assert_eq!(friend_move_token.inconsistency_counter, 1);
-                assert_eq!(friend_move_token.balance, -10i128);
+                assert_eq!(friend_move_token.balances[0].balance, -10i128);
                 assert_eq!(friend_move_token.opt_local_relays, None);
             } else {
                 unreachable!();
@@ -14,6 +14,8 @@ use crate::types::{FunderIncoming, FunderOutgoingComm};
 const TEST_MAX_NODE_RELAYS: usize = 16;
 const TEST_MAX_OPERATIONS_IN_BATCH: usize = 16;
 const TEST_MAX_PENDING_USER_REQUESTS: usize = 16;
+const TEST_MAX_PAYMENT_HISTORY: Option<usize> = Some(16);
+const TEST_TIMESTAMP: u64 = 0x1000;
 
 /// A helper function. Applies an incoming funder message, updating state and ephemeral
 /// accordingly:
@@ -36,6 +38,8 @@ where
         TEST_MAX_NODE_RELAYS,
         TEST_MAX_OPERATIONS_IN_BATCH,
         TEST_MAX_PENDING_USER_REQUESTS,
+        TEST_MAX_PAYMENT_HISTORY,
+        TEST_TIMESTAMP,
         funder_incoming
     ))?;
 
@@ -19,7 +19,9 @@ extern crate log;
 extern crate serde_derive;
 
 mod credit_calc;
+mod debt_policy;
 mod ephemeral;
+mod freeze_guard;
 mod friend;
 mod funder;
 mod handler;
@@ -29,6 +31,7 @@ pub mod report;
 mod state;
 #[cfg(test)]
 mod tests;
+mod timeouts;
 mod token_channel;
 pub mod types;
 
@@ -1,11 +1,10 @@
 use crypto::identity::verify_signature;
 
-use common::int_convert::usize_to_u32;
 use common::safe_arithmetic::SafeSignedArithmetic;
 
 use proto::funder::messages::{
     FailureSendFunds, FriendTcOp, PendingRequest, RequestSendFunds, RequestsStatus,
-    ResponseSendFunds,
+    ResponseSendFunds, SetRemoteMaxDebt,
 };
 use proto::funder::signature_buff::{create_response_signature_buffer, verify_failure_signature};
 
@@ -54,7 +53,6 @@ pub enum ProcessOperationError {
     /// The Route contains some public key twice.
     InvalidRoute,
     RequestsAlreadyDisabled,
-    RouteTooLong,
     InsufficientTrust,
     CreditsCalcOverflow,
     CreditCalculatorFailure,
@@ -65,6 +63,10 @@ pub enum ProcessOperationError {
     InvalidReportingNode,
     InvalidFailureSignature,
     LocalRequestsClosed,
+    /// There is no credit line for the currency of the request.
+    UnknownCurrency,
+    AlreadySettling,
+    RemoteSettling,
 }
 
 #[derive(Debug)]
@@ -105,8 +107,8 @@ pub fn process_operation(
     match friend_tc_op {
         FriendTcOp::EnableRequests => process_enable_requests(mutual_credit),
         FriendTcOp::DisableRequests => process_disable_requests(mutual_credit),
-        FriendTcOp::SetRemoteMaxDebt(proposed_max_debt) => {
-            process_set_remote_max_debt(mutual_credit, proposed_max_debt)
+        FriendTcOp::SetRemoteMaxDebt(set_remote_max_debt) => {
+            process_set_remote_max_debt(mutual_credit, set_remote_max_debt)
         }
         FriendTcOp::RequestSendFunds(request_send_funds) => {
             process_request_send_funds(mutual_credit, request_send_funds)
@@ -117,6 +119,7 @@ pub fn process_operation(
         FriendTcOp::FailureSendFunds(failure_send_funds) => {
             process_failure_send_funds(mutual_credit, failure_send_funds)
         }
+        FriendTcOp::Settle => process_settle(mutual_credit),
     }
 }
 
@@ -155,25 +158,50 @@ fn process_disable_requests(
 
 fn process_set_remote_max_debt(
     mutual_credit: &mut MutualCredit,
-    proposed_max_debt: u128,
+    set_remote_max_debt: SetRemoteMaxDebt,
 ) -> Result<ProcessOperationOutput, ProcessOperationError> {
     let mut op_output = ProcessOperationOutput {
         incoming_message: None,
         mc_mutations: Vec::new(),
     };
 
+    let SetRemoteMaxDebt {
+        currency,
+        remote_max_debt: proposed_max_debt,
+    } = set_remote_max_debt;
+
     if proposed_max_debt > MAX_FUNDER_DEBT {
         Err(ProcessOperationError::RemoteMaxDebtTooLarge(
             proposed_max_debt,
         ))
     } else {
-        let tc_mutation = McMutation::SetLocalMaxDebt(proposed_max_debt);
+        // Note that this also opens a credit line, if this is a new currency:
+        let tc_mutation = McMutation::SetLocalMaxDebt((currency, proposed_max_debt));
         mutual_credit.mutate(&tc_mutation);
         op_output.mc_mutations.push(tc_mutation);
         Ok(op_output)
     }
 }
 
+fn process_settle(
+    mutual_credit: &mut MutualCredit,
+) -> Result<ProcessOperationOutput, ProcessOperationError> {
+    let mut op_output = ProcessOperationOutput {
+        incoming_message: None,
+        mc_mutations: Vec::new(),
+    };
+
+    if mutual_credit.state().settle.remote {
+        return Err(ProcessOperationError::AlreadySettling);
+    }
+
+    let tc_mutation = McMutation::SetRemoteSettle;
+    mutual_credit.mutate(&tc_mutation);
+    op_output.mc_mutations.push(tc_mutation);
+
+    Ok(op_output)
+}
+
 /// Process an incoming RequestSendFunds
 fn process_request_send_funds(
     mutual_credit: &mut MutualCredit,
@@ -184,7 +212,7 @@ fn process_request_send_funds(
     }
 
     // Find ourselves on the route. If we are not there, abort.
-    let remote_index = request_send_funds
+    request_send_funds
         .route
         .find_pk_pair(
             &mutual_credit.state().idents.remote_public_key,
@@ -197,22 +225,30 @@ fn process_request_send_funds(
         return Err(ProcessOperationError::LocalRequestsClosed);
     }
 
-    let route_len =
-        usize_to_u32(request_send_funds.route.len()).ok_or(ProcessOperationError::RouteTooLong)?;
-    let credit_calc = CreditCalculator::new(route_len, request_send_funds.dest_payment);
+    // The remote side may not send new requests after asking to settle:
+    if mutual_credit.state().settle.remote {
+        return Err(ProcessOperationError::RemoteSettling);
+    }
 
-    let local_index = remote_index
-        .checked_add(1)
-        .ok_or(ProcessOperationError::RouteTooLong)?;
-    let local_index = usize_to_u32(local_index).ok_or(ProcessOperationError::RouteTooLong)?;
+    // The amount of credits to freeze depends on the fees left to be paid to us and to the nodes
+    // after us. Verifying that enough fees were left for us is done by the handler,
+    // as our own rate is not known here.
+    let credit_calc = CreditCalculator::new(
+        request_send_funds.dest_payment,
+        request_send_funds.left_fees,
+    );
 
     // Calculate amount of credits to freeze
     let own_freeze_credits = credit_calc
-        .credits_to_freeze(local_index)
+        .credits_to_freeze()
         .ok_or(ProcessOperationError::CreditCalculatorFailure)?;
 
     // Make sure we can freeze the credits
-    let balance = &mutual_credit.state().balance;
+    let balance = mutual_credit
+        .state()
+        .balances
+        .get(&request_send_funds.currency)
+        .ok_or(ProcessOperationError::UnknownCurrency)?;
 
     let new_remote_pending_debt = balance
         .remote_pending_debt
@@ -248,6 +284,7 @@ fn process_request_send_funds(
 
     // Add pending request funds:
     let pending_friend_request = create_pending_request(&request_send_funds);
+    let currency = request_send_funds.currency.clone();
 
     let mut op_output = ProcessOperationOutput {
         incoming_message: Some(IncomingMessage::Request(request_send_funds)),
@@ -259,7 +296,7 @@ fn process_request_send_funds(
     op_output.mc_mutations.push(tc_mutation);
 
     // If we are here, we can freeze the credits:
-    let tc_mutation = McMutation::SetRemotePendingDebt(new_remote_pending_debt);
+    let tc_mutation = McMutation::SetRemotePendingDebt((currency, new_remote_pending_debt));
     mutual_credit.mutate(&tc_mutation);
     op_output.mc_mutations.push(tc_mutation);
 
@@ -298,19 +335,8 @@ fn process_response_send_funds(
         return Err(ProcessOperationError::InvalidResponseSignature);
     }
 
-    // It should never happen that usize_to_u32 fails here, because we
-    // checked this when we created the pending_request.
-    let route_len = usize_to_u32(pending_request.route.len()).unwrap();
-    let credit_calc = CreditCalculator::new(route_len, pending_request.dest_payment);
-
-    // Find ourselves on the route. If we are not there, abort.
-    let local_index = pending_request
-        .route
-        .find_pk_pair(
-            &mutual_credit.state().idents.local_public_key,
-            &mutual_credit.state().idents.remote_public_key,
-        )
-        .unwrap();
+    let credit_calc =
+        CreditCalculator::new(pending_request.dest_payment, pending_request.left_fees);
 
     let mut mc_mutations = Vec::new();
 
@@ -319,30 +345,28 @@ fn process_response_send_funds(
     mutual_credit.mutate(&tc_mutation);
     mc_mutations.push(tc_mutation);
 
-    let remote_index = usize_to_u32(local_index.checked_add(1).unwrap()).unwrap();
-    let success_credits = credit_calc.credits_on_success(remote_index).unwrap();
-    let freeze_credits = credit_calc.credits_to_freeze(remote_index).unwrap();
+    let success_credits = credit_calc.credits_on_success().unwrap();
+    let freeze_credits = credit_calc.credits_to_freeze().unwrap();
+
+    // A pending request always has a credit line of its currency:
+    let currency = &pending_request.currency;
 
     // Decrease frozen credits and decrease balance:
-    let new_local_pending_debt = mutual_credit
-        .state()
-        .balance
+    let new_local_pending_debt = mutual_credit.state().balances[currency]
         .local_pending_debt
         .checked_sub(freeze_credits)
         .unwrap();
 
-    let tc_mutation = McMutation::SetLocalPendingDebt(new_local_pending_debt);
+    let tc_mutation = McMutation::SetLocalPendingDebt((currency.clone(), new_local_pending_debt));
     mutual_credit.mutate(&tc_mutation);
     mc_mutations.push(tc_mutation);
 
-    let new_balance = mutual_credit
-        .state()
-        .balance
+    let new_balance = mutual_credit.state().balances[currency]
         .balance
         .checked_sub_unsigned(success_credits)
         .unwrap();
 
-    let tc_mutation = McMutation::SetBalance(new_balance);
+    let tc_mutation = McMutation::SetBalance((currency.clone(), new_balance));
     mutual_credit.mutate(&tc_mutation);
     mc_mutations.push(tc_mutation);
 
@@ -402,8 +426,8 @@ fn process_failure_send_funds(
         .ok_or(ProcessOperationError::InvalidFailureSignature)?;
 
     // At this point we believe the failure funds is valid.
-    let route_len = usize_to_u32(pending_request.route.len()).unwrap();
-    let credit_calc = CreditCalculator::new(route_len, pending_request.dest_payment);
+    let credit_calc =
+        CreditCalculator::new(pending_request.dest_payment, pending_request.left_fees);
 
     let mut mc_mutations = Vec::new();
 
@@ -412,33 +436,28 @@ fn process_failure_send_funds(
     mutual_credit.mutate(&tc_mutation);
     mc_mutations.push(tc_mutation);
 
-    let remote_index = usize_to_u32(local_index.checked_add(1).unwrap()).unwrap();
-    let reporting_index = usize_to_u32(reporting_index).unwrap();
-    let failure_credits = credit_calc
-        .credits_on_failure(remote_index, reporting_index)
-        .unwrap();
-    let freeze_credits = credit_calc.credits_to_freeze(remote_index).unwrap();
+    let failure_credits = credit_calc.credits_on_failure().unwrap();
+    let freeze_credits = credit_calc.credits_to_freeze().unwrap();
+
+    // A pending request always has a credit line of its currency:
+    let currency = &pending_request.currency;
 
     // Decrease frozen credits and decrease balance:
-    let new_local_pending_debt = mutual_credit
-        .state()
-        .balance
+    let new_local_pending_debt = mutual_credit.state().balances[currency]
         .local_pending_debt
         .checked_sub(freeze_credits)
         .unwrap();
 
-    let tc_mutation = McMutation::SetLocalPendingDebt(new_local_pending_debt);
+    let tc_mutation = McMutation::SetLocalPendingDebt((currency.clone(), new_local_pending_debt));
     mutual_credit.mutate(&tc_mutation);
     mc_mutations.push(tc_mutation);
 
-    let new_balance = mutual_credit
-        .state()
-        .balance
+    let new_balance = mutual_credit.state().balances[currency]
         .balance
         .checked_sub_unsigned(failure_credits)
         .unwrap();
 
-    let tc_mutation = McMutation::SetBalance(new_balance);
+    let tc_mutation = McMutation::SetBalance((currency.clone(), new_balance));
     mutual_credit.mutate(&tc_mutation);
     mc_mutations.push(tc_mutation);
 
@@ -1,10 +1,10 @@
 use crypto::identity::verify_signature;
 
-use common::int_convert::usize_to_u32;
 use common::safe_arithmetic::SafeSignedArithmetic;
 
 use proto::funder::messages::{
     FailureSendFunds, FriendTcOp, RequestSendFunds, RequestsStatus, ResponseSendFunds,
+    SetRemoteMaxDebt,
 };
 use proto::funder::signature_buff::{create_response_signature_buffer, verify_failure_signature};
 
@@ -23,7 +23,6 @@ pub enum QueueOperationError {
     RemoteMaxDebtTooLarge,
     InvalidRoute,
     PkPairNotInRoute,
-    RouteTooLong,
     CreditCalculatorFailure,
     CreditsCalcOverflow,
     InsufficientTrust,
@@ -35,6 +34,8 @@ pub enum QueueOperationError {
     InvalidFailureSignature,
     FailureSentFromDest,
     RemoteRequestsClosed,
+    /// There is no credit line for the currency of the request.
+    UnknownCurrency,
 }
 
 /// A wrapper over a token channel, accumulating funds to be sent as one transaction.
@@ -53,8 +54,8 @@ impl OutgoingMc {
         match operation.clone() {
             FriendTcOp::EnableRequests => self.queue_enable_requests(),
             FriendTcOp::DisableRequests => self.queue_disable_requests(),
-            FriendTcOp::SetRemoteMaxDebt(proposed_max_debt) => {
-                self.queue_set_remote_max_debt(proposed_max_debt)
+            FriendTcOp::SetRemoteMaxDebt(set_remote_max_debt) => {
+                self.queue_set_remote_max_debt(set_remote_max_debt)
             }
             FriendTcOp::RequestSendFunds(request_send_funds) => {
                 self.queue_request_send_funds(request_send_funds)
@@ -65,6 +66,7 @@ impl OutgoingMc {
             FriendTcOp::FailureSendFunds(failure_send_funds) => {
                 self.queue_failure_send_funds(failure_send_funds)
             }
+            FriendTcOp::Settle => self.queue_settle(),
         }
     }
 
@@ -87,16 +89,31 @@ impl OutgoingMc {
         Ok(tc_mutations)
     }
 
+    fn queue_settle(&mut self) -> Result<Vec<McMutation>, QueueOperationError> {
+        let mut tc_mutations = Vec::new();
+        let tc_mutation = McMutation::SetLocalSettle;
+        self.mutual_credit.mutate(&tc_mutation);
+        tc_mutations.push(tc_mutation);
+
+        Ok(tc_mutations)
+    }
+
     fn queue_set_remote_max_debt(
         &mut self,
-        proposed_max_debt: u128,
+        set_remote_max_debt: SetRemoteMaxDebt,
     ) -> Result<Vec<McMutation>, QueueOperationError> {
+        let SetRemoteMaxDebt {
+            currency,
+            remote_max_debt: proposed_max_debt,
+        } = set_remote_max_debt;
+
         if proposed_max_debt > MAX_FUNDER_DEBT {
             return Err(QueueOperationError::RemoteMaxDebtTooLarge);
         }
 
         let mut tc_mutations = Vec::new();
-        let tc_mutation = McMutation::SetRemoteMaxDebt(proposed_max_debt);
+        // Note that this also opens a credit line, if this is a new currency:
+        let tc_mutation = McMutation::SetRemoteMaxDebt((currency, proposed_max_debt));
         self.mutual_credit.mutate(&tc_mutation);
         tc_mutations.push(tc_mutation);
         Ok(tc_mutations)
@@ -111,7 +128,7 @@ impl OutgoingMc {
         }
 
         // Find ourselves on the route. If we are not there, abort.
-        let local_index = request_send_funds
+        request_send_funds
             .route
             .find_pk_pair(
                 &self.mutual_credit.state().idents.local_public_key,
@@ -124,23 +141,22 @@ impl OutgoingMc {
             return Err(QueueOperationError::RemoteRequestsClosed);
         }
 
-        // Calculate amount of credits to freeze.
-        let route_len = usize_to_u32(request_send_funds.route.len())
-            .ok_or(QueueOperationError::RouteTooLong)?;
-        let credit_calc = CreditCalculator::new(route_len, request_send_funds.dest_payment);
-
-        // Get index of remote friend on the route:
-        let remote_index = local_index
-            .checked_add(1)
-            .ok_or(QueueOperationError::RouteTooLong)?;
-        let remote_index = usize_to_u32(remote_index).ok_or(QueueOperationError::RouteTooLong)?;
+        let credit_calc = CreditCalculator::new(
+            request_send_funds.dest_payment,
+            request_send_funds.left_fees,
+        );
 
         // Calculate amount of credits to freeze
         let own_freeze_credits = credit_calc
-            .credits_to_freeze(remote_index)
+            .credits_to_freeze()
             .ok_or(QueueOperationError::CreditCalculatorFailure)?;
 
-        let balance = &self.mutual_credit.state().balance;
+        let balance = self
+            .mutual_credit
+            .state()
+            .balances
+            .get(&request_send_funds.currency)
+            .ok_or(QueueOperationError::UnknownCurrency)?;
 
         // Make sure we can freeze the credits
         let new_local_pending_debt = balance
@@ -174,6 +190,7 @@ impl OutgoingMc {
 
         // Add pending request funds:
         let pending_friend_request = create_pending_request(&request_send_funds);
+        let currency = request_send_funds.currency.clone();
 
         let mut tc_mutations = Vec::new();
         let tc_mutation = McMutation::InsertLocalPendingRequest(pending_friend_request);
@@ -181,7 +198,7 @@ impl OutgoingMc {
         tc_mutations.push(tc_mutation);
 
         // If we are here, we can freeze the credits:
-        let tc_mutation = McMutation::SetLocalPendingDebt(new_local_pending_debt);
+        let tc_mutation = McMutation::SetLocalPendingDebt((currency, new_local_pending_debt));
         self.mutual_credit.mutate(&tc_mutation);
         tc_mutations.push(tc_mutation);
 
@@ -222,21 +239,8 @@ impl OutgoingMc {
             return Err(QueueOperationError::InvalidResponseSignature);
         }
 
-        // Calculate amount of credits to freeze.
-        let route_len =
-            usize_to_u32(pending_request.route.len()).ok_or(QueueOperationError::RouteTooLong)?;
-        let credit_calc = CreditCalculator::new(route_len, pending_request.dest_payment);
-
-        // Find ourselves on the route. If we are not there, abort.
-        let remote_index = pending_request
-            .route
-            .find_pk_pair(
-                &self.mutual_credit.state().idents.remote_public_key,
-                &self.mutual_credit.state().idents.local_public_key,
-            )
-            .unwrap();
-
-        let local_index = usize_to_u32(remote_index.checked_add(1).unwrap()).unwrap();
+        let credit_calc =
+            CreditCalculator::new(pending_request.dest_payment, pending_request.left_fees);
 
         // Remove entry from remote_pending hashmap:
         let mut tc_mutations = Vec::new();
@@ -244,31 +248,29 @@ impl OutgoingMc {
         self.mutual_credit.mutate(&tc_mutation);
         tc_mutations.push(tc_mutation);
 
-        let success_credits = credit_calc.credits_on_success(local_index).unwrap();
-        let freeze_credits = credit_calc.credits_to_freeze(local_index).unwrap();
+        let success_credits = credit_calc.credits_on_success().unwrap();
+        let freeze_credits = credit_calc.credits_to_freeze().unwrap();
+
+        // A pending request always has a credit line of its currency:
+        let currency = &pending_request.currency;
 
         // Decrease frozen credits and increase balance:
-        let new_remote_pending_debt = self
-            .mutual_credit
-            .state()
-            .balance
+        let new_remote_pending_debt = self.mutual_credit.state().balances[currency]
             .remote_pending_debt
             .checked_sub(freeze_credits)
             .expect("Insufficient frozen credit!");
 
-        let tc_mutation = McMutation::SetRemotePendingDebt(new_remote_pending_debt);
+        let tc_mutation =
+            McMutation::SetRemotePendingDebt((currency.clone(), new_remote_pending_debt));
         self.mutual_credit.mutate(&tc_mutation);
         tc_mutations.push(tc_mutation);
 
-        let new_balance = self
-            .mutual_credit
-            .state()
-            .balance
+        let new_balance = self.mutual_credit.state().balances[currency]
             .balance
             .checked_add_unsigned(success_credits)
             .expect("balance overflow");
 
-        let tc_mutation = McMutation::SetBalance(new_balance);
+        let tc_mutation = McMutation::SetBalance((currency.clone(), new_balance));
         self.mutual_credit.mutate(&tc_mutation);
         tc_mutations.push(tc_mutation);
 
@@ -326,9 +328,9 @@ impl OutgoingMc {
             .ok_or(QueueOperationError::InvalidFailureSignature)?;
 
         // At this point we believe the failure funds is valid.
-        let route_len =
-            usize_to_u32(pending_request.route.len()).ok_or(QueueOperationError::RouteTooLong)?;
-        let credit_calc = CreditCalculator::new(route_len, pending_request.dest_payment);
+        let credit_calc =
+            CreditCalculator::new(pending_request.dest_payment, pending_request.left_fees);
+        let currency = pending_request.currency.clone();
 
         // Remove entry from remote hashmap:
         let mut tc_mutations = Vec::new();
@@ -337,37 +339,28 @@ impl OutgoingMc {
         self.mutual_credit.mutate(&tc_mutation);
         tc_mutations.push(tc_mutation);
 
-        let local_index = usize_to_u32(local_index).unwrap();
-        let reporting_index = usize_to_u32(reporting_index).unwrap();
-
-        let failure_credits = credit_calc
-            .credits_on_failure(local_index, reporting_index)
-            .unwrap();
-        let freeze_credits = credit_calc.credits_to_freeze(local_index).unwrap();
+        let failure_credits = credit_calc.credits_on_failure().unwrap();
+        let freeze_credits = credit_calc.credits_to_freeze().unwrap();
 
         // Decrease frozen credits:
-        let new_remote_pending_debt = self
-            .mutual_credit
-            .state()
-            .balance
+        // (A pending request always has a credit line of its currency)
+        let new_remote_pending_debt = self.mutual_credit.state().balances[&currency]
             .remote_pending_debt
             .checked_sub(freeze_credits)
             .unwrap();
 
-        let tc_mutation = McMutation::SetRemotePendingDebt(new_remote_pending_debt);
+        let tc_mutation =
+            McMutation::SetRemotePendingDebt((currency.clone(), new_remote_pending_debt));
         self.mutual_credit.mutate(&tc_mutation);
         tc_mutations.push(tc_mutation);
 
         // Add to balance:
-        let new_balance = self
-            .mutual_credit
-            .state()
-            .balance
+        let new_balance = self.mutual_credit.state().balances[&currency]
             .balance
             .checked_add_unsigned(failure_credits)
             .unwrap();
 
-        let tc_mutation = McMutation::SetBalance(new_balance);
+        let tc_mutation = McMutation::SetBalance((currency, new_balance));
         self.mutual_credit.mutate(&tc_mutation);
         tc_mutations.push(tc_mutation);
 
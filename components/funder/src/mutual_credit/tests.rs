@@ -1,3 +1,5 @@
+use std::convert::TryFrom;
+
 use crypto::identity::{
     generate_pkcs8_key_pair, Identity, Signature, SoftwareEd25519Identity, SIGNATURE_LEN,
 };
@@ -9,7 +11,8 @@ use crypto::crypto_rand::{RandValue, RAND_VALUE_LEN};
 use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
 
 use proto::funder::messages::{
-    FailureSendFunds, FriendTcOp, FriendsRoute, RequestSendFunds, RequestsStatus, ResponseSendFunds,
+    Currency, FailureSendFunds, FriendTcOp, FriendsRoute, RequestSendFunds, RequestsStatus,
+    ResponseSendFunds, SetRemoteMaxDebt,
 };
 use proto::funder::signature_buff::{
     create_failure_signature_buffer, create_response_signature_buffer,
@@ -49,8 +52,7 @@ fn apply_incoming(
 fn test_outgoing_open_close_requests() {
     let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
     let remote_public_key = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
-    let balance = 0;
-    let mut mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, balance);
+    let mut mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, &[]);
 
     assert_eq!(
         mutual_credit.state().requests_status.local,
@@ -86,23 +88,63 @@ fn test_outgoing_open_close_requests() {
 fn test_outgoing_set_remote_max_debt() {
     let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
     let remote_public_key = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
-    let balance = 0;
-    let mut mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, balance);
+    let mut mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, &[]);
+
+    let currency = Currency::try_from("USD".to_owned()).unwrap();
+    assert!(mutual_credit.state().balances.get(&currency).is_none());
+
+    let set_remote_max_debt = SetRemoteMaxDebt {
+        currency: currency.clone(),
+        remote_max_debt: 20,
+    };
+    apply_outgoing(
+        &mut mutual_credit,
+        &FriendTcOp::SetRemoteMaxDebt(set_remote_max_debt),
+    )
+    .unwrap();
+    assert_eq!(
+        mutual_credit.state().balances[&currency].remote_max_debt,
+        20
+    );
+    assert_eq!(mutual_credit.state().balances[&currency].balance, 0);
+}
+
+#[test]
+fn test_settle() {
+    let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+    let remote_public_key = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+    let mut mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, &[]);
+    assert!(!mutual_credit.is_settled());
+
+    apply_outgoing(&mut mutual_credit, &FriendTcOp::Settle).unwrap();
+    assert!(mutual_credit.state().settle.local);
+    assert!(!mutual_credit.is_settled());
 
-    assert_eq!(mutual_credit.state().balance.remote_max_debt, 0);
-    apply_outgoing(&mut mutual_credit, &FriendTcOp::SetRemoteMaxDebt(20)).unwrap();
-    assert_eq!(mutual_credit.state().balance.remote_max_debt, 20);
+    apply_incoming(&mut mutual_credit, FriendTcOp::Settle).unwrap();
+    assert!(mutual_credit.state().settle.remote);
+    assert!(mutual_credit.is_settled());
+
+    // Remote side can not ask to settle twice:
+    assert!(apply_incoming(&mut mutual_credit, FriendTcOp::Settle).is_err());
 }
 
 #[test]
 fn test_request_response_send_funds() {
     let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
     let remote_public_key = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
-    let balance = 0;
-    let mut mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, balance);
+    let mut mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, &[]);
 
     // Make enough trust from remote side, so that we will be able to send credits:
-    apply_incoming(&mut mutual_credit, FriendTcOp::SetRemoteMaxDebt(100)).unwrap();
+    let currency = Currency::try_from("USD".to_owned()).unwrap();
+    let set_remote_max_debt = SetRemoteMaxDebt {
+        currency: currency.clone(),
+        remote_max_debt: 100,
+    };
+    apply_incoming(
+        &mut mutual_credit,
+        FriendTcOp::SetRemoteMaxDebt(set_remote_max_debt),
+    )
+    .unwrap();
 
     // Remote side should open his requests status:
     apply_incoming(&mut mutual_credit, FriendTcOp::EnableRequests).unwrap();
@@ -125,8 +167,12 @@ fn test_request_response_send_funds() {
     let request_send_funds = RequestSendFunds {
         request_id: request_id.clone(),
         route,
+        currency: currency.clone(),
         dest_payment: 10,
+        total_dest_payment: 10,
+        left_fees: 1,
         invoice_id,
+        left_ticks: 0x100,
     };
 
     let pending_request = create_pending_request(&request_send_funds);
@@ -136,12 +182,19 @@ fn test_request_response_send_funds() {
     )
     .unwrap();
 
-    assert_eq!(mutual_credit.state().balance.balance, 0);
-    assert_eq!(mutual_credit.state().balance.local_max_debt, 100);
-    assert_eq!(mutual_credit.state().balance.remote_max_debt, 0);
-    let local_pending_debt = mutual_credit.state().balance.local_pending_debt;
-    assert!(local_pending_debt > 0);
-    assert_eq!(mutual_credit.state().balance.remote_pending_debt, 0);
+    assert_eq!(mutual_credit.state().balances[&currency].balance, 0);
+    assert_eq!(
+        mutual_credit.state().balances[&currency].local_max_debt,
+        100
+    );
+    assert_eq!(mutual_credit.state().balances[&currency].remote_max_debt, 0);
+    let local_pending_debt = mutual_credit.state().balances[&currency].local_pending_debt;
+    // dest_payment + left_fees:
+    assert_eq!(local_pending_debt, 11);
+    assert_eq!(
+        mutual_credit.state().balances[&currency].remote_pending_debt,
+        0
+    );
 
     let rand_nonce = RandValue::from(&[5; RAND_VALUE_LEN]);
 
@@ -161,12 +214,72 @@ fn test_request_response_send_funds() {
     )
     .unwrap();
 
-    let balance = mutual_credit.state().balance.balance;
+    let balance = mutual_credit.state().balances[&currency].balance;
     assert_eq!(balance, -(local_pending_debt as i128));
-    assert_eq!(mutual_credit.state().balance.local_max_debt, 100);
-    assert_eq!(mutual_credit.state().balance.remote_max_debt, 0);
-    assert_eq!(mutual_credit.state().balance.local_pending_debt, 0);
-    assert_eq!(mutual_credit.state().balance.remote_pending_debt, 0);
+    assert_eq!(
+        mutual_credit.state().balances[&currency].local_max_debt,
+        100
+    );
+    assert_eq!(mutual_credit.state().balances[&currency].remote_max_debt, 0);
+    assert_eq!(
+        mutual_credit.state().balances[&currency].local_pending_debt,
+        0
+    );
+    assert_eq!(
+        mutual_credit.state().balances[&currency].remote_pending_debt,
+        0
+    );
+}
+
+#[test]
+fn test_request_send_funds_unknown_currency() {
+    let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+    let remote_public_key = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+    let currency = Currency::try_from("USD".to_owned()).unwrap();
+    let mut mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, &[]);
+
+    // Trust is given only in USD:
+    let set_remote_max_debt = SetRemoteMaxDebt {
+        currency: currency.clone(),
+        remote_max_debt: 100,
+    };
+    apply_incoming(
+        &mut mutual_credit,
+        FriendTcOp::SetRemoteMaxDebt(set_remote_max_debt),
+    )
+    .unwrap();
+    apply_incoming(&mut mutual_credit, FriendTcOp::EnableRequests).unwrap();
+
+    let request_send_funds = RequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![
+                PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+                PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]),
+                PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]),
+            ],
+        },
+        currency: Currency::try_from("EUR".to_owned()).unwrap(),
+        dest_payment: 10,
+        total_dest_payment: 10,
+        left_fees: 1,
+        invoice_id: InvoiceId::from(&[0; INVOICE_ID_LEN]),
+        left_ticks: 0x100,
+    };
+
+    let res = apply_outgoing(
+        &mut mutual_credit,
+        &FriendTcOp::RequestSendFunds(request_send_funds),
+    );
+    match res {
+        Err(QueueOperationError::UnknownCurrency) => {}
+        _ => unreachable!(),
+    };
+    assert_eq!(mutual_credit.state().balances.len(), 1);
+    assert_eq!(
+        mutual_credit.state().balances[&currency].local_pending_debt,
+        0
+    );
 }
 
 #[test]
@@ -178,11 +291,19 @@ fn test_request_failure_send_funds() {
 
     let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
     let remote_public_key = public_key_b.clone();
-    let balance = 0;
-    let mut mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, balance);
+    let mut mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, &[]);
 
     // Make enough trust from remote side, so that we will be able to send credits:
-    apply_incoming(&mut mutual_credit, FriendTcOp::SetRemoteMaxDebt(100)).unwrap();
+    let currency = Currency::try_from("USD".to_owned()).unwrap();
+    let set_remote_max_debt = SetRemoteMaxDebt {
+        currency: currency.clone(),
+        remote_max_debt: 100,
+    };
+    apply_incoming(
+        &mut mutual_credit,
+        FriendTcOp::SetRemoteMaxDebt(set_remote_max_debt),
+    )
+    .unwrap();
 
     // Remote side should open his requests status:
     apply_incoming(&mut mutual_credit, FriendTcOp::EnableRequests).unwrap();
@@ -200,8 +321,12 @@ fn test_request_failure_send_funds() {
     let request_send_funds = RequestSendFunds {
         request_id: request_id.clone(),
         route,
+        currency: currency.clone(),
         dest_payment: 10,
+        total_dest_payment: 10,
+        left_fees: 1,
         invoice_id,
+        left_ticks: 0x100,
     };
 
     let pending_request = create_pending_request(&request_send_funds);
@@ -211,12 +336,18 @@ fn test_request_failure_send_funds() {
     )
     .unwrap();
 
-    assert_eq!(mutual_credit.state().balance.balance, 0);
-    assert_eq!(mutual_credit.state().balance.local_max_debt, 100);
-    assert_eq!(mutual_credit.state().balance.remote_max_debt, 0);
-    let local_pending_debt = mutual_credit.state().balance.local_pending_debt;
+    assert_eq!(mutual_credit.state().balances[&currency].balance, 0);
+    assert_eq!(
+        mutual_credit.state().balances[&currency].local_max_debt,
+        100
+    );
+    assert_eq!(mutual_credit.state().balances[&currency].remote_max_debt, 0);
+    let local_pending_debt = mutual_credit.state().balances[&currency].local_pending_debt;
     assert!(local_pending_debt > 0);
-    assert_eq!(mutual_credit.state().balance.remote_pending_debt, 0);
+    assert_eq!(
+        mutual_credit.state().balances[&currency].remote_pending_debt,
+        0
+    );
 
     let rand_nonce = RandValue::from(&[5; RAND_VALUE_LEN]);
 
@@ -236,9 +367,18 @@ fn test_request_failure_send_funds() {
     )
     .unwrap();
 
-    assert_eq!(mutual_credit.state().balance.balance, 0);
-    assert_eq!(mutual_credit.state().balance.local_max_debt, 100);
-    assert_eq!(mutual_credit.state().balance.remote_max_debt, 0);
-    assert_eq!(mutual_credit.state().balance.local_pending_debt, 0);
-    assert_eq!(mutual_credit.state().balance.remote_pending_debt, 0);
+    assert_eq!(mutual_credit.state().balances[&currency].balance, 0);
+    assert_eq!(
+        mutual_credit.state().balances[&currency].local_max_debt,
+        100
+    );
+    assert_eq!(mutual_credit.state().balances[&currency].remote_max_debt, 0);
+    assert_eq!(
+        mutual_credit.state().balances[&currency].local_pending_debt,
+        0
+    );
+    assert_eq!(
+        mutual_credit.state().balances[&currency].remote_pending_debt,
+        0
+    );
 }
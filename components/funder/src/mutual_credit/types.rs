@@ -4,7 +4,9 @@ use common::safe_arithmetic::SafeSignedArithmetic;
 use crypto::identity::PublicKey;
 use crypto::uid::Uid;
 
-use proto::funder::messages::{PendingRequest, RequestsStatus};
+use proto::funder::messages::{
+    Currency, CurrencyBalance, PendingRequest, RequestsStatus, TokenBalance,
+};
 
 /// The maximum possible funder debt.
 /// We don't use the full u128 because i128 can not go beyond this value.
@@ -86,12 +88,31 @@ impl McRequestsStatus {
     }
 }
 
+#[derive(Eq, PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct McSettle {
+    // Local side asked to settle the mutual credit:
+    pub local: bool,
+    // Remote side asked to settle the mutual credit:
+    pub remote: bool,
+}
+
+impl McSettle {
+    fn new() -> McSettle {
+        McSettle {
+            local: false,
+            remote: false,
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct MutualCreditState {
     pub idents: McIdents,
-    pub balance: McBalance,
+    /// A separate credit line for every currency.
+    pub balances: ImHashMap<Currency, McBalance>,
     pub pending_requests: McPendingRequests,
     pub requests_status: McRequestsStatus,
+    pub settle: McSettle,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -103,48 +124,88 @@ pub struct MutualCredit {
 pub enum McMutation {
     SetLocalRequestsStatus(RequestsStatus),
     SetRemoteRequestsStatus(RequestsStatus),
-    SetLocalMaxDebt(u128),
-    SetRemoteMaxDebt(u128),
-    SetBalance(i128),
+    SetLocalMaxDebt((Currency, u128)),
+    SetRemoteMaxDebt((Currency, u128)),
+    SetBalance((Currency, i128)),
     InsertLocalPendingRequest(PendingRequest),
     RemoveLocalPendingRequest(Uid),
     InsertRemotePendingRequest(PendingRequest),
     RemoveRemotePendingRequest(Uid),
-    SetLocalPendingDebt(u128),
-    SetRemotePendingDebt(u128),
+    SetLocalPendingDebt((Currency, u128)),
+    SetRemotePendingDebt((Currency, u128)),
+    SetLocalSettle,
+    SetRemoteSettle,
 }
 
 impl MutualCredit {
     pub fn new(
         local_public_key: &PublicKey,
         remote_public_key: &PublicKey,
-        balance: i128,
+        balances: &[CurrencyBalance],
     ) -> MutualCredit {
+        let balances = balances
+            .iter()
+            .map(|currency_balance| {
+                (
+                    currency_balance.currency.clone(),
+                    McBalance::new(currency_balance.balance),
+                )
+            })
+            .collect();
+
         MutualCredit {
             state: MutualCreditState {
                 idents: McIdents {
                     local_public_key: local_public_key.clone(),
                     remote_public_key: remote_public_key.clone(),
                 },
-                balance: McBalance::new(balance),
+                balances,
                 pending_requests: McPendingRequests::new(),
                 requests_status: McRequestsStatus::new(),
+                settle: McSettle::new(),
             },
         }
     }
 
-    /// Calculate required balance for reset.
+    /// Calculate required balance for reset, for every currency.
     /// This would be current balance plus additional future profits.
-    pub fn balance_for_reset(&self) -> i128 {
-        self.state
-            .balance
-            .balance
-            .checked_add_unsigned(self.state.balance.remote_pending_debt)
-            .expect("Overflow when calculating balance_for_reset")
+    /// The result is sorted by currency.
+    pub fn balance_for_reset(&self) -> Vec<CurrencyBalance> {
+        let mut balance_for_reset = self
+            .state
+            .balances
+            .iter()
+            .map(|(currency, mc_balance)| CurrencyBalance {
+                currency: currency.clone(),
+                balance: mc_balance
+                    .balance
+                    .checked_add_unsigned(mc_balance.remote_pending_debt)
+                    .expect("Overflow when calculating balance_for_reset"),
+            })
+            .collect::<Vec<_>>();
         // TODO: Is this the correct formula?
         // Other options:
         // *    balance
         // *    balance + remote_pending_debt - local_pending_debt
+        balance_for_reset.sort_by(|a, b| a.currency.cmp(&b.currency));
+        balance_for_reset
+    }
+
+    /// The balances as they appear in an outgoing move token. Sorted by currency.
+    pub fn token_balances(&self) -> Vec<TokenBalance> {
+        let mut token_balances = self
+            .state
+            .balances
+            .iter()
+            .map(|(currency, mc_balance)| TokenBalance {
+                currency: currency.clone(),
+                balance: mc_balance.balance,
+                local_pending_debt: mc_balance.local_pending_debt,
+                remote_pending_debt: mc_balance.remote_pending_debt,
+            })
+            .collect::<Vec<_>>();
+        token_balances.sort_by(|a, b| a.currency.cmp(&b.currency));
+        token_balances
     }
 
     pub fn state(&self) -> &MutualCreditState {
@@ -159,13 +220,13 @@ impl MutualCredit {
             McMutation::SetRemoteRequestsStatus(requests_status) => {
                 self.set_remote_requests_status(requests_status.clone())
             }
-            McMutation::SetLocalMaxDebt(proposed_max_debt) => {
-                self.set_local_max_debt(*proposed_max_debt)
+            McMutation::SetLocalMaxDebt((currency, proposed_max_debt)) => {
+                self.set_local_max_debt(currency, *proposed_max_debt)
             }
-            McMutation::SetRemoteMaxDebt(proposed_max_debt) => {
-                self.set_remote_max_debt(*proposed_max_debt)
+            McMutation::SetRemoteMaxDebt((currency, proposed_max_debt)) => {
+                self.set_remote_max_debt(currency, *proposed_max_debt)
             }
-            McMutation::SetBalance(balance) => self.set_balance(*balance),
+            McMutation::SetBalance((currency, balance)) => self.set_balance(currency, *balance),
             McMutation::InsertLocalPendingRequest(pending_friend_request) => {
                 self.insert_local_pending_request(pending_friend_request)
             }
@@ -178,12 +239,14 @@ impl MutualCredit {
             McMutation::RemoveRemotePendingRequest(request_id) => {
                 self.remove_remote_pending_request(request_id)
             }
-            McMutation::SetLocalPendingDebt(local_pending_debt) => {
-                self.set_local_pending_debt(*local_pending_debt)
+            McMutation::SetLocalPendingDebt((currency, local_pending_debt)) => {
+                self.set_local_pending_debt(currency, *local_pending_debt)
             }
-            McMutation::SetRemotePendingDebt(remote_pending_debt) => {
-                self.set_remote_pending_debt(*remote_pending_debt)
+            McMutation::SetRemotePendingDebt((currency, remote_pending_debt)) => {
+                self.set_remote_pending_debt(currency, *remote_pending_debt)
             }
+            McMutation::SetLocalSettle => self.set_local_settle(),
+            McMutation::SetRemoteSettle => self.set_remote_settle(),
         }
     }
 
@@ -195,16 +258,25 @@ impl MutualCredit {
         self.state.requests_status.remote = requests_status;
     }
 
-    fn set_remote_max_debt(&mut self, proposed_max_debt: u128) {
-        self.state.balance.remote_max_debt = proposed_max_debt;
+    /// Get the credit line of a currency. A new credit line (With zero balance) is opened if
+    /// the currency was not used before.
+    fn balance_mut(&mut self, currency: &Currency) -> &mut McBalance {
+        self.state
+            .balances
+            .entry(currency.clone())
+            .or_insert_with(|| McBalance::new(0))
+    }
+
+    fn set_remote_max_debt(&mut self, currency: &Currency, proposed_max_debt: u128) {
+        self.balance_mut(currency).remote_max_debt = proposed_max_debt;
     }
 
-    fn set_local_max_debt(&mut self, proposed_max_debt: u128) {
-        self.state.balance.local_max_debt = proposed_max_debt;
+    fn set_local_max_debt(&mut self, currency: &Currency, proposed_max_debt: u128) {
+        self.balance_mut(currency).local_max_debt = proposed_max_debt;
     }
 
-    fn set_balance(&mut self, balance: i128) {
-        self.state.balance.balance = balance;
+    fn set_balance(&mut self, currency: &Currency, balance: i128) {
+        self.balance_mut(currency).balance = balance;
     }
 
     fn insert_remote_pending_request(&mut self, pending_friend_request: &PendingRequest) {
@@ -237,11 +309,29 @@ impl MutualCredit {
             .remove(request_id);
     }
 
-    fn set_remote_pending_debt(&mut self, remote_pending_debt: u128) {
-        self.state.balance.remote_pending_debt = remote_pending_debt;
+    fn set_remote_pending_debt(&mut self, currency: &Currency, remote_pending_debt: u128) {
+        self.balance_mut(currency).remote_pending_debt = remote_pending_debt;
+    }
+
+    fn set_local_pending_debt(&mut self, currency: &Currency, local_pending_debt: u128) {
+        self.balance_mut(currency).local_pending_debt = local_pending_debt;
+    }
+
+    fn set_local_settle(&mut self) {
+        self.state.settle.local = true;
+    }
+
+    fn set_remote_settle(&mut self) {
+        self.state.settle.remote = true;
     }
 
-    fn set_local_pending_debt(&mut self, local_pending_debt: u128) {
-        self.state.balance.local_pending_debt = local_pending_debt;
+    /// Did both sides ask to settle, with no requests left pending?
+    /// Once this is true, the balance can not change anymore.
+    pub fn is_settled(&self) -> bool {
+        let pending_requests = &self.state.pending_requests;
+        self.state.settle.local
+            && self.state.settle.remote
+            && pending_requests.pending_local_requests.is_empty()
+            && pending_requests.pending_remote_requests.is_empty()
     }
 }
@@ -1,22 +1,28 @@
 use im::hashmap::HashMap as ImHashMap;
+use im::vector::Vector as ImVec;
 
 use common::canonical_serialize::CanonicalSerialize;
 use common::int_convert::usize_to_u64;
 
+use crypto::invoice_id::InvoiceId;
+
+use proto::funder::messages::Currency;
 use proto::report::messages::{
     AddFriendReport, ChannelInconsistentReport, ChannelStatusReport, DirectionReport,
-    FriendLivenessReport, FriendReport, FriendReportMutation, FriendStatusReport, FunderReport,
-    FunderReportMutation, McBalanceReport, McRequestsStatusReport, MoveTokenHashedReport,
-    RequestsStatusReport, ResetTermsReport, SentLocalRelaysReport, TcReport,
+    FriendLivenessReport, FriendReport, FriendReportMutation, FriendStatusReport,
+    FrozenCreditsReport, FunderReport, FunderReportMutation, HeldPaymentReport, McBalanceReport,
+    McRequestsStatusReport, MoveTokenHashedReport, RequestsStatusReport, ResetTermsReport,
+    SentLocalRelaysReport, SettleStatusReport, TcReport,
 };
 
 use crate::types::MoveTokenHashed;
 
 use crate::ephemeral::{Ephemeral, EphemeralMutation};
+use crate::freeze_guard::frozen_credits_by_next;
 use crate::friend::{ChannelStatus, FriendMutation, FriendState, SentLocalRelays};
 use crate::liveness::LivenessMutation;
 use crate::mutual_credit::types::{McBalance, McRequestsStatus};
-use crate::state::{FunderMutation, FunderState};
+use crate::state::{FunderMutation, FunderState, IncomingPayment};
 use crate::token_channel::{TcDirection, TcMutation, TokenChannel};
 
 impl<B> Into<SentLocalRelaysReport<B>> for &SentLocalRelays<B>
@@ -41,15 +47,14 @@ impl From<&McRequestsStatus> for McRequestsStatusReport {
     }
 }
 
-impl From<&McBalance> for McBalanceReport {
-    fn from(mc_balance: &McBalance) -> McBalanceReport {
-        McBalanceReport {
-            balance: mc_balance.balance,
-            remote_max_debt: mc_balance.remote_max_debt,
-            local_max_debt: mc_balance.local_max_debt,
-            local_pending_debt: mc_balance.local_pending_debt,
-            remote_pending_debt: mc_balance.remote_pending_debt,
-        }
+fn create_mc_balance_report(currency: &Currency, mc_balance: &McBalance) -> McBalanceReport {
+    McBalanceReport {
+        currency: currency.clone(),
+        balance: mc_balance.balance,
+        remote_max_debt: mc_balance.remote_max_debt,
+        local_max_debt: mc_balance.local_max_debt,
+        local_pending_debt: mc_balance.local_pending_debt,
+        remote_pending_debt: mc_balance.remote_pending_debt,
     }
 }
 
@@ -61,9 +66,7 @@ impl From<&MoveTokenHashed> for MoveTokenHashedReport {
             remote_public_key: move_token_hashed.remote_public_key.clone(),
             inconsistency_counter: move_token_hashed.inconsistency_counter,
             move_token_counter: move_token_hashed.move_token_counter,
-            balance: move_token_hashed.balance,
-            local_pending_debt: move_token_hashed.local_pending_debt,
-            remote_pending_debt: move_token_hashed.remote_pending_debt,
+            balances: move_token_hashed.balances.clone(),
             rand_nonce: move_token_hashed.rand_nonce.clone(),
             new_token: move_token_hashed.new_token.clone(),
         }
@@ -80,9 +83,34 @@ where
             TcDirection::Outgoing(_) => DirectionReport::Outgoing,
         };
         let mutual_credit_state = token_channel.get_mutual_credit().state();
+
+        let mut frozen_credits = frozen_credits_by_next(
+            &mutual_credit_state.idents.local_public_key,
+            &mutual_credit_state.pending_requests.pending_remote_requests,
+        )
+        .into_iter()
+        .map(
+            |((currency, next_public_key), frozen_credits)| FrozenCreditsReport {
+                currency,
+                next_public_key,
+                frozen_credits,
+            },
+        )
+        .collect::<Vec<_>>();
+        frozen_credits.sort_by(|a, b| {
+            (&a.currency, &a.next_public_key).cmp(&(&b.currency, &b.next_public_key))
+        });
+
+        let mut balances = mutual_credit_state
+            .balances
+            .iter()
+            .map(|(currency, mc_balance)| create_mc_balance_report(currency, mc_balance))
+            .collect::<Vec<_>>();
+        balances.sort_by(|a, b| a.currency.cmp(&b.currency));
+
         TcReport {
             direction,
-            balance: McBalanceReport::from(&mutual_credit_state.balance),
+            balances,
             requests_status: McRequestsStatusReport::from(&mutual_credit_state.requests_status),
             num_local_pending_requests: usize_to_u64(
                 mutual_credit_state
@@ -98,6 +126,7 @@ where
                     .len(),
             )
             .unwrap(),
+            frozen_credits,
         }
     }
 }
@@ -119,7 +148,8 @@ where
                 let channel_inconsistent_report = ChannelInconsistentReport {
                     local_reset_terms_balance: channel_inconsistent
                         .local_reset_terms
-                        .balance_for_reset,
+                        .balance_for_reset
+                        .clone(),
                     opt_remote_reset_terms,
                 };
                 ChannelStatusReport::Inconsistent(channel_inconsistent_report)
@@ -150,7 +180,7 @@ where
             .map(|move_token_hashed| MoveTokenHashedReport::from(&move_token_hashed)),
         liveness: friend_liveness.clone(),
         channel_status,
-        wanted_remote_max_debt: friend_state.wanted_remote_max_debt,
+        wanted_remote_max_debt: friend_state.wanted_remote_max_debt.clone(),
         wanted_local_requests_status: RequestsStatusReport::from(
             &friend_state.wanted_local_requests_status,
         ),
@@ -158,6 +188,20 @@ where
         num_pending_responses: usize_to_u64(friend_state.pending_responses.len()).unwrap(),
         status: FriendStatusReport::from(&friend_state.status),
         num_pending_user_requests: usize_to_u64(friend_state.pending_user_requests.len()).unwrap(),
+        rate: friend_state.rate.clone(),
+        opt_debt_policy: friend_state.opt_debt_policy.clone(),
+        settle_status: SettleStatusReport::from(&friend_state.settle_status),
+    }
+}
+
+fn create_held_payment_report(
+    invoice_id: &InvoiceId,
+    incoming_payment: &IncomingPayment,
+) -> HeldPaymentReport {
+    HeldPaymentReport {
+        invoice_id: invoice_id.clone(),
+        currency: incoming_payment.currency.clone(),
+        total_dest_payment: incoming_payment.total_dest_payment,
     }
 }
 
@@ -176,11 +220,24 @@ where
         friends.insert(friend_public_key.clone(), friend_report);
     }
 
+    let held_payments = funder_state
+        .incoming_payments
+        .iter()
+        .filter(|(_, incoming_payment)| incoming_payment.held)
+        .map(|(invoice_id, incoming_payment)| {
+            create_held_payment_report(invoice_id, incoming_payment)
+        })
+        .collect::<ImVec<_>>();
+
     FunderReport {
         local_public_key: funder_state.local_public_key.clone(),
         relays: funder_state.relays.clone(),
+        opt_direct_address: funder_state.opt_direct_address.clone(),
         friends,
         num_ready_receipts: usize_to_u64(funder_state.ready_receipts.len()).unwrap(),
+        freeze_policy: funder_state.freeze_policy,
+        hold_incoming_payments: funder_state.hold_incoming_payments,
+        held_payments,
     }
 }
 
@@ -218,9 +275,9 @@ where
                 vec![set_channel_status, set_last_incoming_move_token]
             }
         },
-        FriendMutation::SetWantedRemoteMaxDebt(wanted_remote_max_debt) => {
+        FriendMutation::SetWantedRemoteMaxDebt(currency_max_debt) => {
             vec![FriendReportMutation::SetWantedRemoteMaxDebt(
-                *wanted_remote_max_debt,
+                currency_max_debt.clone(),
             )]
         }
         FriendMutation::SetWantedLocalRequestsStatus(requests_status) => {
@@ -233,7 +290,7 @@ where
                 usize_to_u64(friend_after.pending_requests.len()).unwrap(),
             )]
         }
-        FriendMutation::PopFrontPendingRequest => {
+        FriendMutation::PopFrontPendingRequest | FriendMutation::RemovePendingRequest(_) => {
             vec![FriendReportMutation::SetNumPendingRequests(
                 usize_to_u64(friend_after.pending_requests.len()).unwrap(),
             )]
@@ -253,7 +310,8 @@ where
                 usize_to_u64(friend_after.pending_user_requests.len()).unwrap(),
             )]
         }
-        FriendMutation::PopFrontPendingUserRequest => {
+        FriendMutation::PopFrontPendingUserRequest
+        | FriendMutation::RemovePendingUserRequest(_) => {
             vec![FriendReportMutation::SetNumPendingUserRequests(
                 usize_to_u64(friend_after.pending_user_requests.len()).unwrap(),
             )]
@@ -270,6 +328,17 @@ where
                 sent_local_relays.into(),
             )]
         }
+        FriendMutation::SetRate(rate) => vec![FriendReportMutation::SetRate(rate.clone())],
+        FriendMutation::SetDebtPolicy(opt_debt_policy) => {
+            vec![FriendReportMutation::SetDebtPolicy(opt_debt_policy.clone())]
+        }
+        // The debt policy bookkeeping is not reported:
+        FriendMutation::SetDebtPolicyStatus(_) => Vec::new(),
+        FriendMutation::SetSettleStatus(settle_status) => {
+            vec![FriendReportMutation::SetSettleStatus(
+                SettleStatusReport::from(settle_status),
+            )]
+        }
         FriendMutation::SetInconsistent(_) | FriendMutation::SetConsistent(_) => {
             let channel_status_report = ChannelStatusReport::from(&friend_after.channel_status);
             let set_channel_status = FriendReportMutation::SetChannelStatus(channel_status_report);
@@ -319,6 +388,11 @@ where
         FunderMutation::RemoveRelay(public_key) => {
             vec![FunderReportMutation::RemoveRelay(public_key.clone())]
         }
+        FunderMutation::SetDirectAddress(opt_direct_address) => {
+            vec![FunderReportMutation::SetDirectAddress(
+                opt_direct_address.clone(),
+            )]
+        }
         FunderMutation::AddFriend(add_friend) => {
             let friend_after = funder_state_after
                 .friends
@@ -328,7 +402,7 @@ where
                 friend_public_key: add_friend.friend_public_key.clone(),
                 name: add_friend.name.clone(),
                 relays: add_friend.relays.clone(),
-                balance: add_friend.balance, // Initial balance
+                balances: add_friend.balances.clone(), // Initial balances
                 opt_last_incoming_move_token: friend_after
                     .channel_status
                     .get_last_incoming_move_token_hashed()
@@ -360,6 +434,35 @@ where
                 Vec::new()
             }
         }
+        FunderMutation::AddIncomingPart(_) => Vec::new(),
+        FunderMutation::RemoveIncomingPayment(invoice_id) => {
+            match funder_state.incoming_payments.get(invoice_id) {
+                Some(incoming_payment) if incoming_payment.held => {
+                    vec![FunderReportMutation::RemoveHeldPayment(invoice_id.clone())]
+                }
+                _ => Vec::new(),
+            }
+        }
+        FunderMutation::SetIncomingPaymentHeld(invoice_id) => {
+            let incoming_payment = funder_state_after
+                .incoming_payments
+                .get(invoice_id)
+                .unwrap();
+            vec![FunderReportMutation::AddHeldPayment(
+                create_held_payment_report(invoice_id, incoming_payment),
+            )]
+        }
+        FunderMutation::SetFreezePolicy(freeze_policy) => {
+            vec![FunderReportMutation::SetFreezePolicy(*freeze_policy)]
+        }
+        // Payment history is queried explicitly, and is not part of the report:
+        FunderMutation::AddPaymentRecord(_) | FunderMutation::PopFrontPaymentRecord => Vec::new(),
+        FunderMutation::TimeoutsMutation(_) => Vec::new(),
+        FunderMutation::SetHoldIncomingPayments(hold_incoming_payments) => {
+            vec![FunderReportMutation::SetHoldIncomingPayments(
+                *hold_incoming_payments,
+            )]
+        }
     }
 }
 
@@ -3,12 +3,16 @@ use im::vector::Vector as ImVec;
 
 use common::canonical_serialize::CanonicalSerialize;
 use crypto::identity::PublicKey;
+use crypto::invoice_id::InvoiceId;
 use crypto::uid::Uid;
 
-use proto::app_server::messages::NamedRelayAddress;
-use proto::funder::messages::{AddFriend, Receipt};
+use proto::app_server::messages::{ChannelAddress, NamedRelayAddress, RelayAddress};
+use proto::funder::messages::{
+    AddFriend, Currency, FreezePolicy, PaymentRecord, PendingRequest, Receipt,
+};
 
 use crate::friend::{FriendMutation, FriendState};
+use crate::timeouts::{Timeouts, TimeoutsMutation};
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct FunderState<B: Clone> {
@@ -16,8 +20,51 @@ pub struct FunderState<B: Clone> {
     /// Address of relay we are going to connect to.
     /// None means that no address was configured.
     pub relays: ImVec<NamedRelayAddress<B>>,
+    /// Address friends may connect to us directly, without going through a relay.
+    /// None means that we can not be reached directly.
+    pub opt_direct_address: Option<B>,
     pub friends: ImHashMap<PublicKey, FriendState<B>>,
     pub ready_receipts: ImHashMap<Uid, Receipt>,
+    /// Payments split over multiple routes, where we are the destination.
+    /// We keep the parts that arrived so far, until the total payment arrives.
+    /// Held payments are kept after the total payment has arrived, until they are accepted or
+    /// rejected.
+    pub incoming_payments: ImHashMap<InvoiceId, IncomingPayment>,
+    /// Limits the amount of credits friends may freeze when routing requests through us.
+    pub freeze_policy: FreezePolicy,
+    /// Hold incoming payments until the user accepts or rejects them, instead of issuing a
+    /// receipt as soon as the total payment arrives.
+    pub hold_incoming_payments: bool,
+    /// Ledger of completed, failed and mediated payments, oldest first.
+    /// Records are only appended. If the node was configured with a maximum history size, the
+    /// oldest records are removed once the ledger is full.
+    pub payment_history: ImVec<PaymentRecord>,
+    /// Deadlines of pending requests. Deadlines are kept across restarts, so that a request
+    /// does not get a fresh deadline every time the node is restarted.
+    pub timeouts: Timeouts,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct IncomingPayment {
+    /// All the parts of a payment are paid in the same currency.
+    pub currency: Currency,
+    pub total_dest_payment: u128,
+    /// Parts of the payment that arrived so far, together with the friend that sent each part.
+    pub parts: ImVec<(PublicKey, PendingRequest)>,
+    /// The total payment has arrived, and waits for the user to accept or reject it.
+    pub held: bool,
+}
+
+impl IncomingPayment {
+    /// Sum of the payments of all the parts that arrived so far.
+    /// Returns None on overflow.
+    pub fn received_payment(&self) -> Option<u128> {
+        self.parts
+            .iter()
+            .try_fold(0u128, |acc, (_, pending_request)| {
+                acc.checked_add(pending_request.dest_payment)
+            })
+    }
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -26,10 +73,19 @@ pub enum FunderMutation<B: Clone> {
     FriendMutation((PublicKey, FriendMutation<B>)),
     AddRelay(NamedRelayAddress<B>),
     RemoveRelay(PublicKey),
+    SetDirectAddress(Option<B>),
     AddFriend(AddFriend<B>),
     RemoveFriend(PublicKey),
     AddReceipt((Uid, Receipt)), //(request_id, receipt)
     RemoveReceipt(Uid),
+    AddIncomingPart((PublicKey, PendingRequest)), // (friend_public_key, pending_request)
+    RemoveIncomingPayment(InvoiceId),
+    SetIncomingPaymentHeld(InvoiceId),
+    SetFreezePolicy(FreezePolicy),
+    AddPaymentRecord(PaymentRecord),
+    PopFrontPaymentRecord,
+    SetHoldIncomingPayments(bool),
+    TimeoutsMutation(TimeoutsMutation),
 }
 
 impl<B> FunderState<B>
@@ -43,12 +99,32 @@ where
         FunderState {
             local_public_key,
             relays,
+            opt_direct_address: None,
             friends: ImHashMap::new(),
             ready_receipts: ImHashMap::new(),
+            incoming_payments: ImHashMap::new(),
+            freeze_policy: FreezePolicy::Unlimited,
+            payment_history: ImVec::new(),
+            hold_incoming_payments: false,
+            timeouts: Timeouts::new(),
         }
     }
     // TODO: Add code for initialization from database?
 
+    /// All the addresses we advertise to our friends: Our direct address (If we have one),
+    /// followed by our relays.
+    pub fn local_addresses(&self) -> ImVec<ChannelAddress<B>> {
+        let mut local_addresses = ImVec::new();
+        if let Some(direct_address) = &self.opt_direct_address {
+            local_addresses.push_back(ChannelAddress::Direct(direct_address.clone()));
+        }
+        for named_relay_address in &self.relays {
+            let relay_address = RelayAddress::from(named_relay_address.clone());
+            local_addresses.push_back(ChannelAddress::Relay(relay_address));
+        }
+        local_addresses
+    }
+
     // TODO: Use MutableState trait instead:
     pub fn mutate(&mut self, funder_mutation: &FunderMutation<B>) {
         match funder_mutation {
@@ -69,13 +145,16 @@ where
                     &cur_named_relay_address.public_key != public_key
                 });
             }
+            FunderMutation::SetDirectAddress(opt_direct_address) => {
+                self.opt_direct_address = opt_direct_address.clone();
+            }
             FunderMutation::AddFriend(add_friend) => {
                 let friend = FriendState::new(
                     &self.local_public_key,
                     &add_friend.friend_public_key,
                     add_friend.relays.clone(),
                     add_friend.name.clone(),
-                    add_friend.balance,
+                    &add_friend.balances,
                 );
                 // Insert friend, but also make sure that we didn't override an existing friend
                 // with the same public key:
@@ -94,6 +173,42 @@ where
             FunderMutation::RemoveReceipt(uid) => {
                 let _ = self.ready_receipts.remove(uid);
             }
+            FunderMutation::AddIncomingPart((friend_public_key, pending_request)) => {
+                let incoming_payment = self
+                    .incoming_payments
+                    .entry(pending_request.invoice_id.clone())
+                    .or_insert_with(|| IncomingPayment {
+                        currency: pending_request.currency.clone(),
+                        total_dest_payment: pending_request.total_dest_payment,
+                        parts: ImVec::new(),
+                        held: false,
+                    });
+                incoming_payment
+                    .parts
+                    .push_back((friend_public_key.clone(), pending_request.clone()));
+            }
+            FunderMutation::RemoveIncomingPayment(invoice_id) => {
+                let _ = self.incoming_payments.remove(invoice_id);
+            }
+            FunderMutation::SetIncomingPaymentHeld(invoice_id) => {
+                let incoming_payment = self.incoming_payments.get_mut(invoice_id).unwrap();
+                incoming_payment.held = true;
+            }
+            FunderMutation::SetFreezePolicy(freeze_policy) => {
+                self.freeze_policy = *freeze_policy;
+            }
+            FunderMutation::AddPaymentRecord(payment_record) => {
+                self.payment_history.push_back(payment_record.clone());
+            }
+            FunderMutation::PopFrontPaymentRecord => {
+                let _ = self.payment_history.pop_front();
+            }
+            FunderMutation::SetHoldIncomingPayments(hold_incoming_payments) => {
+                self.hold_incoming_payments = *hold_incoming_payments;
+            }
+            FunderMutation::TimeoutsMutation(timeouts_mutation) => {
+                self.timeouts.mutate(timeouts_mutation);
+            }
         }
     }
 }
@@ -6,15 +6,20 @@ use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
 use crypto::uid::{Uid, UID_LEN};
 
 use proto::funder::messages::{
-    FriendStatus, FriendsRoute, FunderControl, FunderIncomingControl, ReceiptAck, RequestsStatus,
+    CurrencyBalance, FriendStatus, FriendsRoute, FunderControl, FunderIncomingControl,
+    PaymentDirection, PaymentStatus, ReceiptAck, RequestPaymentHistory, RequestsStatus,
     ResetFriendChannel, ResponseSendFundsResult, UserRequestSendFunds,
 };
 use proto::report::messages::{ChannelStatusReport, FunderReport};
 
-use super::utils::{create_node_controls, dummy_named_relay_address, dummy_relay_address};
+use super::utils::{
+    create_node_controls, dummy_currency, dummy_named_relay_address, dummy_relay_address,
+    find_balance,
+};
 
 async fn task_funder_basic(spawner: impl Spawn + Clone + Send + 'static) {
     let num_nodes = 2;
+    let currency = dummy_currency();
     let mut node_controls = await!(create_node_controls(num_nodes, spawner));
 
     let public_keys = node_controls
@@ -24,8 +29,8 @@ async fn task_funder_basic(spawner: impl Spawn + Clone + Send + 'static) {
 
     let relays0 = vec![dummy_relay_address(0)];
     let relays1 = vec![dummy_relay_address(1)];
-    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 8));
-    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", &currency, 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", &currency, -8));
     assert_eq!(node_controls[0].report.friends.len(), 1);
     assert_eq!(node_controls[1].report.friends.len(), 1);
 
@@ -33,8 +38,8 @@ async fn task_funder_basic(spawner: impl Spawn + Clone + Send + 'static) {
     await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
 
     // Set remote max debt for both sides:
-    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
-    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], &currency, 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], &currency, 100));
 
     // Open requests:
     await!(node_controls[0].set_requests_status(&public_keys[1], RequestsStatus::Open));
@@ -53,8 +58,12 @@ async fn task_funder_basic(spawner: impl Spawn + Clone + Send + 'static) {
                 node_controls[1].public_key.clone(),
             ],
         },
+        currency: currency.clone(),
         invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
         dest_payment: 5,
+        total_dest_payment: 5,
+        fees: 0,
+        timeout_ticks: 0x100,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[40; UID_LEN]),
@@ -89,7 +98,11 @@ async fn task_funder_basic(spawner: impl Spawn + Clone + Send + 'static) {
             ChannelStatusReport::Consistent(tc_report) => tc_report,
             _ => return false,
         };
-        tc_report.balance.balance == 3
+        let mc_balance = match find_balance(tc_report, &currency) {
+            Some(mc_balance) => mc_balance,
+            None => return false,
+        };
+        mc_balance.balance == 3
     };
     await!(node_controls[0].recv_until(pred));
 
@@ -99,7 +112,11 @@ async fn task_funder_basic(spawner: impl Spawn + Clone + Send + 'static) {
             ChannelStatusReport::Consistent(tc_report) => tc_report,
             _ => return false,
         };
-        tc_report.balance.balance == -3
+        let mc_balance = match find_balance(tc_report, &currency) {
+            Some(mc_balance) => mc_balance,
+            None => return false,
+        };
+        mc_balance.balance == -3
     };
     await!(node_controls[1].recv_until(pred));
 }
@@ -115,6 +132,7 @@ async fn task_funder_forward_payment(spawner: impl Spawn + Clone + Send + 'stati
      * 0 -- 1 -- 2
      */
     let num_nodes = 3;
+    let currency = dummy_currency();
     let mut node_controls = await!(create_node_controls(num_nodes, spawner));
 
     // Create topology:
@@ -128,10 +146,10 @@ async fn task_funder_forward_payment(spawner: impl Spawn + Clone + Send + 'stati
     let relays0 = vec![dummy_relay_address(0)];
     let relays1 = vec![dummy_relay_address(1)];
     let relays2 = vec![dummy_relay_address(2)];
-    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 8));
-    await!(node_controls[1].add_friend(&public_keys[0], relays0.clone(), "node0", -8));
-    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", 6));
-    await!(node_controls[2].add_friend(&public_keys[1], relays0, "node0", -6));
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", &currency, 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0.clone(), "node0", &currency, -8));
+    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", &currency, 6));
+    await!(node_controls[2].add_friend(&public_keys[1], relays0, "node0", &currency, -6));
 
     // Enable friends:
     await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
@@ -140,10 +158,10 @@ async fn task_funder_forward_payment(spawner: impl Spawn + Clone + Send + 'stati
     await!(node_controls[2].set_friend_status(&public_keys[1], FriendStatus::Enabled));
 
     // Set remote max debt:
-    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
-    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
-    await!(node_controls[1].set_remote_max_debt(&public_keys[2], 300));
-    await!(node_controls[2].set_remote_max_debt(&public_keys[1], 400));
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], &currency, 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], &currency, 100));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[2], &currency, 300));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[1], &currency, 400));
 
     // Open requests, allowing this route: 0 --> 1 --> 2
     await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
@@ -165,8 +183,12 @@ async fn task_funder_forward_payment(spawner: impl Spawn + Clone + Send + 'stati
                 public_keys[2].clone(),
             ],
         },
+        currency: currency.clone(),
         invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
         dest_payment: 20,
+        total_dest_payment: 20,
+        fees: 1,
+        timeout_ticks: 0x100,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[42; UID_LEN]),
@@ -204,9 +226,43 @@ async fn task_funder_forward_payment(spawner: impl Spawn + Clone + Send + 'stati
             ChannelStatusReport::Consistent(tc_report) => tc_report,
             _ => return false,
         };
-        tc_report.balance.balance == -6 + 20
+        let mc_balance = match find_balance(tc_report, &currency) {
+            Some(mc_balance) => mc_balance,
+            None => return false,
+        };
+        mc_balance.balance == -6 + 20
     };
     await!(node_controls[2].recv_until(pred));
+
+    // Make sure that the payment was recorded in the payment history of every node on the route:
+    let expected = vec![
+        (PaymentDirection::Outgoing, 1),
+        (PaymentDirection::Mediated, 1),
+        (PaymentDirection::Incoming, 0),
+    ];
+    for (i, (direction, fees)) in expected.into_iter().enumerate() {
+        let request_payment_history = RequestPaymentHistory {
+            request_id: Uid::from(&[44; UID_LEN]),
+            opt_direction: None,
+            opt_status: None,
+            opt_invoice_id: Some(InvoiceId::from(&[1; INVOICE_ID_LEN])),
+            offset: 0,
+            max_records: 8,
+        };
+        let response_payment_history =
+            await!(node_controls[i].request_payment_history(request_payment_history)).unwrap();
+        assert_eq!(
+            response_payment_history.request_id,
+            Uid::from(&[44; UID_LEN])
+        );
+        assert_eq!(response_payment_history.records.len(), 1);
+        let payment_record = &response_payment_history.records[0];
+        assert_eq!(payment_record.request_id, Uid::from(&[3; UID_LEN]));
+        assert_eq!(payment_record.direction, direction);
+        assert_eq!(payment_record.status, PaymentStatus::Success);
+        assert_eq!(payment_record.dest_payment, 20);
+        assert_eq!(payment_record.fees, fees);
+    }
 }
 
 #[test]
@@ -215,6 +271,625 @@ fn test_funder_forward_payment() {
     thread_pool.run(task_funder_forward_payment(thread_pool.clone()));
 }
 
+async fn task_funder_multi_path_payment(spawner: impl Spawn + Clone + Send + 'static) {
+    /*
+     *   1
+     *  / \
+     * 0   3
+     *  \ /
+     *   2
+     * We pay one invoice from 0 to 3, splitting the payment over two routes:
+     * 0 --> 1 --> 3 and 0 --> 2 --> 3
+     */
+    let num_nodes = 4;
+    let currency = dummy_currency();
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    // Create topology:
+    // ----------------
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Add friends:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    let relays2 = vec![dummy_relay_address(2)];
+    let relays3 = vec![dummy_relay_address(3)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1.clone(), "node1", &currency, 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0.clone(), "node0", &currency, -8));
+    await!(node_controls[0].add_friend(&public_keys[2], relays2.clone(), "node2", &currency, 8));
+    await!(node_controls[2].add_friend(&public_keys[0], relays0, "node0", &currency, -8));
+    await!(node_controls[1].add_friend(&public_keys[3], relays3.clone(), "node3", &currency, 6));
+    await!(node_controls[3].add_friend(&public_keys[1], relays1, "node1", &currency, -6));
+    await!(node_controls[2].add_friend(&public_keys[3], relays3, "node3", &currency, 6));
+    await!(node_controls[3].add_friend(&public_keys[2], relays2, "node2", &currency, -6));
+
+    // Enable friends:
+    for &(a, b) in &[(0, 1), (1, 0), (0, 2), (2, 0), (1, 3), (3, 1), (2, 3), (3, 2)] {
+        await!(node_controls[a].set_friend_status(&public_keys[b], FriendStatus::Enabled));
+    }
+
+    // Set remote max debt:
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], &currency, 100));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[0], &currency, 100));
+    await!(node_controls[3].set_remote_max_debt(&public_keys[1], &currency, 100));
+    await!(node_controls[3].set_remote_max_debt(&public_keys[2], &currency, 100));
+
+    // Open requests, allowing the routes: 0 --> 1 --> 3 and 0 --> 2 --> 3
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+    await!(node_controls[2].set_requests_status(&public_keys[0], RequestsStatus::Open));
+    await!(node_controls[3].set_requests_status(&public_keys[1], RequestsStatus::Open));
+    await!(node_controls[3].set_requests_status(&public_keys[2], RequestsStatus::Open));
+
+    // Wait until the routes are ready (Online + Consistent + open requests)
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[0].wait_until_ready(&public_keys[2]));
+    await!(node_controls[1].wait_until_ready(&public_keys[3]));
+    await!(node_controls[2].wait_until_ready(&public_keys[3]));
+
+    // Send 10 + 10 credits 0 --> 3, for the same invoice:
+    for &(mediator, request_byte) in &[(1, 3u8), (2, 4u8)] {
+        let user_request_send_funds = UserRequestSendFunds {
+            request_id: Uid::from(&[request_byte; UID_LEN]),
+            route: FriendsRoute {
+                public_keys: vec![
+                    public_keys[0].clone(),
+                    public_keys[mediator].clone(),
+                    public_keys[3].clone(),
+                ],
+            },
+            currency: currency.clone(),
+            invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+            dest_payment: 10,
+            total_dest_payment: 20,
+            fees: 1,
+            timeout_ticks: 0x100,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[40 + request_byte; UID_LEN]),
+            FunderControl::RequestSendFunds(user_request_send_funds),
+        );
+        await!(node_controls[0].send(incoming_control_message)).unwrap();
+    }
+
+    // Both parts should succeed, each with a receipt for the total payment:
+    for _ in 0..2 {
+        let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+        let receipt = match response_received.result {
+            ResponseSendFundsResult::Failure(_) => unreachable!(),
+            ResponseSendFundsResult::Success(send_funds_receipt) => send_funds_receipt,
+        };
+        assert_eq!(receipt.dest_payment, 10);
+        assert_eq!(receipt.total_dest_payment, 20);
+    }
+
+    // Make sure that node3 got the credits along both routes:
+    for &mediator in &[1, 2] {
+        let pred = |report: &FunderReport<_>| {
+            let friend = match report.friends.get(&public_keys[mediator]) {
+                None => return false,
+                Some(friend) => friend,
+            };
+            let tc_report = match &friend.channel_status {
+                ChannelStatusReport::Consistent(tc_report) => tc_report,
+                _ => return false,
+            };
+            let mc_balance = match find_balance(tc_report, &currency) {
+                Some(mc_balance) => mc_balance,
+                None => return false,
+            };
+            mc_balance.balance == -6 + 10
+        };
+        await!(node_controls[3].recv_until(pred));
+    }
+}
+
+#[test]
+fn test_funder_multi_path_payment() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_multi_path_payment(thread_pool.clone()));
+}
+
+async fn task_funder_cycle_payment(spawner: impl Spawn + Clone + Send + 'static) {
+    /*
+     *   1
+     *  / \
+     * 0 - 2
+     * Node 0 pays itself along the cycle 0 --> 1 --> 2 --> 0,
+     * moving credits from its channel with node1 to its channel with node2.
+     */
+    let num_nodes = 3;
+    let currency = dummy_currency();
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    // Create topology:
+    // ----------------
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Add friends:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    let relays2 = vec![dummy_relay_address(2)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1.clone(), "node1", &currency, 0));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0.clone(), "node0", &currency, 0));
+    await!(node_controls[1].add_friend(&public_keys[2], relays2.clone(), "node2", &currency, 0));
+    await!(node_controls[2].add_friend(&public_keys[1], relays1, "node1", &currency, 0));
+    await!(node_controls[2].add_friend(&public_keys[0], relays0, "node0", &currency, 0));
+    await!(node_controls[0].add_friend(&public_keys[2], relays2, "node2", &currency, 0));
+
+    // Enable friends:
+    for &(a, b) in &[(0, 1), (1, 0), (1, 2), (2, 1), (2, 0), (0, 2)] {
+        await!(node_controls[a].set_friend_status(&public_keys[b], FriendStatus::Enabled));
+    }
+
+    // Set remote max debt:
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], &currency, 100));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[1], &currency, 100));
+    await!(node_controls[0].set_remote_max_debt(&public_keys[2], &currency, 100));
+
+    // Open requests, allowing the route: 0 --> 1 --> 2 --> 0
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+    await!(node_controls[2].set_requests_status(&public_keys[1], RequestsStatus::Open));
+    await!(node_controls[0].set_requests_status(&public_keys[2], RequestsStatus::Open));
+
+    // Wait until the route is ready (Online + Consistent + open requests)
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[1].wait_until_ready(&public_keys[2]));
+    await!(node_controls[2].wait_until_ready(&public_keys[0]));
+
+    // Send credits 0 --> 1 --> 2 --> 0
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![
+                public_keys[0].clone(),
+                public_keys[1].clone(),
+                public_keys[2].clone(),
+                public_keys[0].clone(),
+            ],
+        },
+        currency: currency.clone(),
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 20,
+        total_dest_payment: 20,
+        fees: 2,
+        timeout_ticks: 0x100,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[42; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+
+    // The response should arrive back to the user of node0, and not be forwarded to node2:
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+        ResponseSendFundsResult::Success(_) => {}
+    };
+
+    // node0 paid node1 the payment together with the fees for both mediators,
+    // and got the payment back from node2:
+    for &(friend_index, balance) in &[(1, -22), (2, 20)] {
+        let pred = |report: &FunderReport<_>| {
+            let friend = match report.friends.get(&public_keys[friend_index]) {
+                None => return false,
+                Some(friend) => friend,
+            };
+            let tc_report = match &friend.channel_status {
+                ChannelStatusReport::Consistent(tc_report) => tc_report,
+                _ => return false,
+            };
+            let mc_balance = match find_balance(tc_report, &currency) {
+                Some(mc_balance) => mc_balance,
+                None => return false,
+            };
+            mc_balance.balance == balance
+        };
+        await!(node_controls[0].recv_until(pred));
+    }
+}
+
+#[test]
+fn test_funder_cycle_payment() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_cycle_payment(thread_pool.clone()));
+}
+
+async fn task_funder_request_timeout(spawner: impl Spawn + Clone + Send + 'static) {
+    /*
+     * 0 -- 1 -- 2
+     * Node 0 sends node 2 only a part of the payment. Node 2 keeps waiting for the rest of the
+     * payment until the request times out.
+     */
+    let num_nodes = 3;
+    let currency = dummy_currency();
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    // Create topology:
+    // ----------------
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Add friends:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    let relays2 = vec![dummy_relay_address(2)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1.clone(), "node1", &currency, 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", &currency, -8));
+    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", &currency, 6));
+    await!(node_controls[2].add_friend(&public_keys[1], relays1, "node1", &currency, -6));
+
+    // Enable friends:
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[2], FriendStatus::Enabled));
+    await!(node_controls[2].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+
+    // Set remote max debt:
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], &currency, 100));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[1], &currency, 100));
+
+    // Open requests, allowing this route: 0 --> 1 --> 2
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+    await!(node_controls[2].set_requests_status(&public_keys[1], RequestsStatus::Open));
+
+    // Wait until the route is ready (Online + Consistent + open requests)
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[1].wait_until_ready(&public_keys[2]));
+
+    // Send only 10 credits out of 20 credits 0 --> 2:
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![
+                public_keys[0].clone(),
+                public_keys[1].clone(),
+                public_keys[2].clone(),
+            ],
+        },
+        currency: currency.clone(),
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 10,
+        total_dest_payment: 20,
+        fees: 1,
+        timeout_ticks: 0x10,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[40; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+
+    // Wait until the request arrives at node 2:
+    let pred = |report: &FunderReport<_>| {
+        let friend = match report.friends.get(&public_keys[1]) {
+            None => return false,
+            Some(friend) => friend,
+        };
+        let tc_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+            _ => return false,
+        };
+        tc_report.num_remote_pending_requests == 1
+    };
+    await!(node_controls[2].recv_until(pred));
+
+    // Let the request time out at node 2:
+    for _ in 0..0x10 {
+        await!(node_controls[2].tick()).unwrap();
+    }
+
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Failure(reporting_public_key) => {
+            assert_eq!(reporting_public_key, public_keys[2]);
+        }
+        ResponseSendFundsResult::Success(_) => unreachable!(),
+    };
+
+    // The frozen credits should be released:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        let tc_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+            _ => return false,
+        };
+        let mc_balance = match find_balance(tc_report, &currency) {
+            Some(mc_balance) => mc_balance,
+            None => return false,
+        };
+        mc_balance.balance == 8 && mc_balance.local_pending_debt == 0
+    };
+    await!(node_controls[0].recv_until(pred));
+}
+
+#[test]
+fn test_funder_request_timeout() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_request_timeout(thread_pool.clone()));
+}
+
+async fn task_funder_late_response(spawner: impl Spawn + Clone + Send + 'static) {
+    /*
+     * 0 -- 1 -- 2
+     * Node 2 holds incoming payments. The deadline of the request passes at node 1 while the
+     * payment is held at node 2. Node 1 should give node 2 a few more ticks, and pass the
+     * response back to node 0 once node 2 accepts the payment.
+     */
+    let num_nodes = 3;
+    let currency = dummy_currency();
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    // Create topology:
+    // ----------------
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Add friends:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    let relays2 = vec![dummy_relay_address(2)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1.clone(), "node1", &currency, 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", &currency, -8));
+    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", &currency, 6));
+    await!(node_controls[2].add_friend(&public_keys[1], relays1, "node1", &currency, -6));
+
+    // Enable friends:
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[2], FriendStatus::Enabled));
+    await!(node_controls[2].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+
+    // Set remote max debt:
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], &currency, 100));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[1], &currency, 100));
+
+    // Open requests, allowing this route: 0 --> 1 --> 2
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+    await!(node_controls[2].set_requests_status(&public_keys[1], RequestsStatus::Open));
+
+    // Wait until the route is ready (Online + Consistent + open requests)
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[1].wait_until_ready(&public_keys[2]));
+
+    // Node 2 holds incoming payments:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[40; UID_LEN]),
+        FunderControl::SetHoldIncomingPayments(true),
+    );
+    await!(node_controls[2].send(incoming_control_message)).unwrap();
+    let pred = |report: &FunderReport<_>| report.hold_incoming_payments;
+    await!(node_controls[2].recv_until(pred));
+
+    // Send 10 credits 0 --> 2:
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![
+                public_keys[0].clone(),
+                public_keys[1].clone(),
+                public_keys[2].clone(),
+            ],
+        },
+        currency: currency.clone(),
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 10,
+        total_dest_payment: 10,
+        fees: 1,
+        timeout_ticks: 0x10,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[41; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+
+    let held_payment = await!(node_controls[2].recv_until_held_payment()).unwrap();
+
+    // Let the deadline pass at node 1. The timer channel has no buffer, so after sending 0x12
+    // ticks we know that at least 0x10 ticks were handled by node 1:
+    for _ in 0..0x12 {
+        await!(node_controls[1].tick()).unwrap();
+    }
+
+    // Node 2 accepts the payment after the deadline has passed at node 1:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[42; UID_LEN]),
+        FunderControl::AcceptHeldPayment(held_payment.invoice_id),
+    );
+    await!(node_controls[2].send(incoming_control_message)).unwrap();
+
+    // Node 1 should pass the late response back to node 0:
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Success(_) => {}
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+    };
+
+    // Node 1 paid node 2, and was paid by node 0 (Including the fee):
+    let pred = |report: &FunderReport<_>| {
+        let balance_with = |friend_public_key: &PublicKey| {
+            let friend = report.friends.get(friend_public_key)?;
+            let tc_report = match &friend.channel_status {
+                ChannelStatusReport::Consistent(tc_report) => tc_report,
+                _ => return None,
+            };
+            let mc_balance = find_balance(tc_report, &currency)?;
+            if mc_balance.local_pending_debt != 0 || mc_balance.remote_pending_debt != 0 {
+                return None;
+            }
+            Some(mc_balance.balance)
+        };
+        match (balance_with(&public_keys[0]), balance_with(&public_keys[2])) {
+            (Some(balance0), Some(balance2)) => balance2 == -4 && balance0 == 3,
+            _ => false,
+        }
+    };
+    await!(node_controls[1].recv_until(pred));
+}
+
+#[test]
+fn test_funder_late_response() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_late_response(thread_pool.clone()));
+}
+
+async fn task_funder_silent_downstream(spawner: impl Spawn + Clone + Send + 'static) {
+    /*
+     * 0 -- 1 -- 2
+     * Node 2 holds incoming payments, and does not answer. Once the deadline of the request (and
+     * the extra ticks given to node 2) pass at node 1, node 1 should fail the request back to
+     * node 0. The credits frozen with node 2 are released once node 2 answers.
+     */
+    let num_nodes = 3;
+    let currency = dummy_currency();
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    // Create topology:
+    // ----------------
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Add friends:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    let relays2 = vec![dummy_relay_address(2)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1.clone(), "node1", &currency, 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", &currency, -8));
+    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", &currency, 6));
+    await!(node_controls[2].add_friend(&public_keys[1], relays1, "node1", &currency, -6));
+
+    // Enable friends:
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[2], FriendStatus::Enabled));
+    await!(node_controls[2].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+
+    // Set remote max debt:
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], &currency, 100));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[1], &currency, 100));
+
+    // Open requests, allowing this route: 0 --> 1 --> 2
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+    await!(node_controls[2].set_requests_status(&public_keys[1], RequestsStatus::Open));
+
+    // Wait until the route is ready (Online + Consistent + open requests)
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[1].wait_until_ready(&public_keys[2]));
+
+    // Node 2 holds incoming payments:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[40; UID_LEN]),
+        FunderControl::SetHoldIncomingPayments(true),
+    );
+    await!(node_controls[2].send(incoming_control_message)).unwrap();
+    let pred = |report: &FunderReport<_>| report.hold_incoming_payments;
+    await!(node_controls[2].recv_until(pred));
+
+    // Send 10 credits 0 --> 2:
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![
+                public_keys[0].clone(),
+                public_keys[1].clone(),
+                public_keys[2].clone(),
+            ],
+        },
+        currency: currency.clone(),
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 10,
+        total_dest_payment: 10,
+        fees: 1,
+        timeout_ticks: 0x10,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[41; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+
+    let _held_payment = await!(node_controls[2].recv_until_held_payment()).unwrap();
+
+    // Let the deadline of the request and the extra ticks pass at node 1.
+    // Node 2 is not ticked, so it does not answer:
+    for _ in 0..0x20 {
+        await!(node_controls[1].tick()).unwrap();
+    }
+
+    // Node 1 fails the request back to node 0:
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Failure(reporting_public_key) => {
+            assert_eq!(reporting_public_key, public_keys[1]);
+        }
+        ResponseSendFundsResult::Success(_) => unreachable!(),
+    };
+
+    // The credits frozen between node 0 and node 1 are released:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        let tc_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+            _ => return false,
+        };
+        let mc_balance = match find_balance(tc_report, &currency) {
+            Some(mc_balance) => mc_balance,
+            None => return false,
+        };
+        mc_balance.balance == 8 && mc_balance.local_pending_debt == 0
+    };
+    await!(node_controls[0].recv_until(pred));
+
+    // The deadline of the held payment passes at node 2. Node 2 fails the request:
+    for _ in 0..0x20 {
+        await!(node_controls[2].tick()).unwrap();
+    }
+
+    // The credits frozen at node 1 are released on both sides:
+    let pred = |report: &FunderReport<_>| {
+        let balance_with = |friend_public_key: &PublicKey| {
+            let friend = report.friends.get(friend_public_key)?;
+            let tc_report = match &friend.channel_status {
+                ChannelStatusReport::Consistent(tc_report) => tc_report,
+                _ => return None,
+            };
+            let mc_balance = find_balance(tc_report, &currency)?;
+            if mc_balance.local_pending_debt != 0 || mc_balance.remote_pending_debt != 0 {
+                return None;
+            }
+            Some(mc_balance.balance)
+        };
+        match (balance_with(&public_keys[0]), balance_with(&public_keys[2])) {
+            (Some(balance0), Some(balance2)) => balance0 == -8 && balance2 == 6,
+            _ => false,
+        }
+    };
+    await!(node_controls[1].recv_until(pred));
+}
+
+#[test]
+fn test_funder_silent_downstream() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_silent_downstream(thread_pool.clone()));
+}
+
 async fn task_funder_payment_failure(spawner: impl Spawn + Clone + Send + 'static) {
     /*
      * 0 -- 1 -- 2
@@ -222,6 +897,7 @@ async fn task_funder_payment_failure(spawner: impl Spawn + Clone + Send + 'stati
      * where 3 does not exist. We expect that node 2 will return a failure response.
      */
     let num_nodes = 4;
+    let currency = dummy_currency();
     let mut node_controls = await!(create_node_controls(num_nodes, spawner));
 
     // Create topology:
@@ -235,10 +911,10 @@ async fn task_funder_payment_failure(spawner: impl Spawn + Clone + Send + 'stati
     let relays0 = vec![dummy_relay_address(0)];
     let relays1 = vec![dummy_relay_address(1)];
     let relays2 = vec![dummy_relay_address(2)];
-    await!(node_controls[0].add_friend(&public_keys[1], relays1.clone(), "node1", 8));
-    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
-    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", 6));
-    await!(node_controls[2].add_friend(&public_keys[1], relays1, "node0", -6));
+    await!(node_controls[0].add_friend(&public_keys[1], relays1.clone(), "node1", &currency, 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", &currency, -8));
+    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", &currency, 6));
+    await!(node_controls[2].add_friend(&public_keys[1], relays1, "node0", &currency, -6));
 
     // Enable friends:
     await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
@@ -247,10 +923,10 @@ async fn task_funder_payment_failure(spawner: impl Spawn + Clone + Send + 'stati
     await!(node_controls[2].set_friend_status(&public_keys[1], FriendStatus::Enabled));
 
     // Set remote max debt:
-    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
-    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
-    await!(node_controls[1].set_remote_max_debt(&public_keys[2], 300));
-    await!(node_controls[2].set_remote_max_debt(&public_keys[1], 400));
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], &currency, 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], &currency, 100));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[2], &currency, 300));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[1], &currency, 400));
 
     // Open requests, allowing this route: 0 --> 1 --> 2
     await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
@@ -273,8 +949,12 @@ async fn task_funder_payment_failure(spawner: impl Spawn + Clone + Send + 'stati
                 public_keys[3].clone(),
             ],
         },
+        currency: currency.clone(),
         invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
         dest_payment: 20,
+        total_dest_payment: 20,
+        fees: 2,
+        timeout_ticks: 0x100,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[44; UID_LEN]),
@@ -299,7 +979,28 @@ async fn task_funder_payment_failure(spawner: impl Spawn + Clone + Send + 'stati
         ChannelStatusReport::Consistent(tc_report) => tc_report,
         _ => unreachable!(),
     };
-    assert_eq!(tc_report.balance.balance, -6);
+    assert_eq!(find_balance(tc_report, &currency).unwrap().balance, -6);
+
+    // The failure is recorded in the payment history of the origin and of the reporting node:
+    for &(i, direction) in &[
+        (0, PaymentDirection::Outgoing),
+        (2, PaymentDirection::Mediated),
+    ] {
+        let request_payment_history = RequestPaymentHistory {
+            request_id: Uid::from(&[45; UID_LEN]),
+            opt_direction: Some(direction),
+            opt_status: Some(PaymentStatus::Failure),
+            opt_invoice_id: None,
+            offset: 0,
+            max_records: 8,
+        };
+        let response_payment_history =
+            await!(node_controls[i].request_payment_history(request_payment_history)).unwrap();
+        assert_eq!(response_payment_history.records.len(), 1);
+        let payment_record = &response_payment_history.records[0];
+        assert_eq!(payment_record.request_id, Uid::from(&[3; UID_LEN]));
+        assert_eq!(payment_record.fees, 0);
+    }
 }
 
 #[test]
@@ -308,12 +1009,136 @@ fn test_funder_payment_failure() {
     thread_pool.run(task_funder_payment_failure(thread_pool.clone()));
 }
 
+async fn task_funder_held_payment(spawner: impl Spawn + Clone + Send + 'static) {
+    /*
+     * 0 -- 1
+     * Node 1 holds incoming payments. It accepts the first payment from node 0, and rejects the
+     * second payment.
+     */
+    let num_nodes = 2;
+    let currency = dummy_currency();
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", &currency, 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", &currency, -8));
+
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], &currency, 100));
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+
+    // Wait for liveness:
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+
+    // Node 1 holds incoming payments:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[40; UID_LEN]),
+        FunderControl::SetHoldIncomingPayments(true),
+    );
+    await!(node_controls[1].send(incoming_control_message)).unwrap();
+    let pred = |report: &FunderReport<_>| report.hold_incoming_payments;
+    await!(node_controls[1].recv_until(pred));
+
+    for &(i, accept) in &[(1u8, true), (2u8, false)] {
+        // Send credits 0 --> 1
+        let user_request_send_funds = UserRequestSendFunds {
+            request_id: Uid::from(&[i; UID_LEN]),
+            route: FriendsRoute {
+                public_keys: vec![public_keys[0].clone(), public_keys[1].clone()],
+            },
+            currency: currency.clone(),
+            invoice_id: InvoiceId::from(&[i; INVOICE_ID_LEN]),
+            dest_payment: 5,
+            total_dest_payment: 5,
+            fees: 0,
+            timeout_ticks: 0x100,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[41; UID_LEN]),
+            FunderControl::RequestSendFunds(user_request_send_funds),
+        );
+        await!(node_controls[0].send(incoming_control_message)).unwrap();
+
+        let held_payment = await!(node_controls[1].recv_until_held_payment()).unwrap();
+        assert_eq!(
+            held_payment.invoice_id,
+            InvoiceId::from(&[i; INVOICE_ID_LEN])
+        );
+        assert_eq!(held_payment.currency, currency);
+        assert_eq!(held_payment.total_dest_payment, 5);
+        assert!(held_payment.left_ticks > 0);
+
+        // The held payment also shows in the report, for apps that connect later:
+        let invoice_id = held_payment.invoice_id.clone();
+        let pred = |report: &FunderReport<_>| {
+            report
+                .held_payments
+                .iter()
+                .any(|held_payment_report| held_payment_report.invoice_id == invoice_id)
+        };
+        await!(node_controls[1].recv_until(pred));
+
+        let funder_control = if accept {
+            FunderControl::AcceptHeldPayment(held_payment.invoice_id)
+        } else {
+            FunderControl::RejectHeldPayment(held_payment.invoice_id)
+        };
+        let incoming_control_message =
+            FunderIncomingControl::new(Uid::from(&[42; UID_LEN]), funder_control);
+        await!(node_controls[1].send(incoming_control_message)).unwrap();
+
+        let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+        assert_eq!(response_received.request_id, Uid::from(&[i; UID_LEN]));
+        match response_received.result {
+            ResponseSendFundsResult::Success(_) => assert!(accept),
+            ResponseSendFundsResult::Failure(reporting_public_key) => {
+                assert!(!accept);
+                assert_eq!(reporting_public_key, public_keys[1]);
+            }
+        };
+
+        // The payment is no longer held:
+        let pred = |report: &FunderReport<_>| report.held_payments.is_empty();
+        await!(node_controls[1].recv_until(pred));
+    }
+
+    // Only the accepted payment was paid:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        let tc_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+            _ => return false,
+        };
+        let mc_balance = match find_balance(tc_report, &currency) {
+            Some(mc_balance) => mc_balance,
+            None => return false,
+        };
+        mc_balance.balance == 3 && mc_balance.local_pending_debt == 0
+    };
+    await!(node_controls[0].recv_until(pred));
+}
+
+#[test]
+fn test_funder_held_payment() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_held_payment(thread_pool.clone()));
+}
+
 /// Test a basic inconsistency between two adjacent nodes
 async fn task_funder_inconsistency_basic<S>(spawner: S)
 where
     S: Spawn + Clone + Send + 'static,
 {
     let num_nodes = 2;
+    let currency = dummy_currency();
     let mut node_controls = await!(create_node_controls(num_nodes, spawner));
 
     let public_keys = node_controls
@@ -324,8 +1149,8 @@ where
     // We set incompatible initial balances (non zero sum) to cause an inconsistency:
     let relays0 = vec![dummy_relay_address(0)];
     let relays1 = vec![dummy_relay_address(1)];
-    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 20));
-    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", &currency, 20));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", &currency, -8));
 
     await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
     await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
@@ -339,14 +1164,23 @@ where
                 channel_inconsistent_report
             }
         };
-        if channel_inconsistent_report.local_reset_terms_balance != 20 {
+        if channel_inconsistent_report.local_reset_terms_balance
+            != vec![CurrencyBalance {
+                currency: currency.clone(),
+                balance: 20,
+            }]
+        {
             return false;
         }
         let reset_terms_report = match &channel_inconsistent_report.opt_remote_reset_terms {
             None => return false,
             Some(reset_terms_report) => reset_terms_report,
         };
-        reset_terms_report.balance_for_reset == -8
+        reset_terms_report.balance_for_reset
+            == vec![CurrencyBalance {
+                currency: currency.clone(),
+                balance: -8,
+            }]
     };
     await!(node_controls[0].recv_until(pred));
 
@@ -387,7 +1221,11 @@ where
             ChannelStatusReport::Consistent(tc_report) => tc_report,
             ChannelStatusReport::Inconsistent(_) => return false,
         };
-        tc_report.balance.balance == 8
+        let mc_balance = match find_balance(tc_report, &currency) {
+            Some(mc_balance) => mc_balance,
+            None => return false,
+        };
+        mc_balance.balance == 8
     };
     await!(node_controls[0].recv_until(pred));
 
@@ -398,14 +1236,18 @@ where
             ChannelStatusReport::Consistent(tc_report) => tc_report,
             ChannelStatusReport::Inconsistent(_) => return false,
         };
-        tc_report.balance.balance == -8
+        let mc_balance = match find_balance(tc_report, &currency) {
+            Some(mc_balance) => mc_balance,
+            None => return false,
+        };
+        mc_balance.balance == -8
     };
     await!(node_controls[1].recv_until(pred));
 
     // Make sure that we manage to send messages over the token channel after resolving the
     // inconsistency:
-    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
-    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 300));
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], &currency, 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], &currency, 300));
 }
 
 #[test]
@@ -1,4 +1,5 @@
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 
 use common::canonical_serialize::CanonicalSerialize;
 use common::mutable_state::MutableState;
@@ -15,13 +16,15 @@ use crypto::uid::{Uid, UID_LEN};
 
 use proto::report::messages::{
     ChannelStatusReport, FriendLivenessReport, FriendStatusReport, FunderReport,
-    FunderReportMutations, RequestsStatusReport,
+    FunderReportMutations, McBalanceReport, RequestsStatusReport, TcReport,
 };
 
-use proto::app_server::messages::{NamedRelayAddress, RelayAddress};
+use proto::app_server::messages::{ChannelAddress, NamedRelayAddress};
 use proto::funder::messages::{
-    AddFriend, FriendStatus, FunderControl, FunderIncomingControl, FunderOutgoingControl,
-    RequestsStatus, ResponseReceived, SetFriendRemoteMaxDebt, SetFriendStatus, SetRequestsStatus,
+    AddFriend, Currency, CurrencyBalance, FriendStatus, FunderControl, FunderIncomingControl,
+    FunderOutgoingControl, HeldPayment, RequestPaymentHistory, RequestsStatus,
+    ResponsePaymentHistory, ResponseReceived, SetFriendRemoteMaxDebt, SetFriendStatus,
+    SetRequestsStatus,
 };
 
 use database::DatabaseClient;
@@ -40,6 +43,7 @@ use crate::types::{
 const TEST_MAX_NODE_RELAYS: usize = 16;
 const TEST_MAX_OPERATIONS_IN_BATCH: usize = 16;
 const TEST_MAX_PENDING_USER_REQUESTS: usize = 16;
+const TEST_MAX_PAYMENT_HISTORY: Option<usize> = Some(16);
 
 // This is required to make sure the tests are not stuck.
 //
@@ -57,9 +61,25 @@ pub fn dummy_named_relay_address(index: u8) -> NamedRelayAddress<u32> {
     }
 }
 
-/// A helper function to quickly create a dummy RelayAddress.
-pub fn dummy_relay_address(index: u8) -> RelayAddress<u32> {
-    dummy_named_relay_address(index).into()
+/// A helper function to quickly create a dummy relay ChannelAddress.
+pub fn dummy_relay_address(index: u8) -> ChannelAddress<u32> {
+    ChannelAddress::Relay(dummy_named_relay_address(index).into())
+}
+
+/// The currency used for the credit lines in the tests.
+pub fn dummy_currency() -> Currency {
+    Currency::try_from("FST".to_owned()).unwrap()
+}
+
+/// Find the balance of a given currency in a token channel report.
+pub fn find_balance<'a>(
+    tc_report: &'a TcReport,
+    currency: &Currency,
+) -> Option<&'a McBalanceReport> {
+    tc_report
+        .balances
+        .iter()
+        .find(|mc_balance_report| &mc_balance_report.currency == currency)
 }
 
 #[derive(Debug)]
@@ -198,6 +218,7 @@ pub struct NodeControl<B: Clone> {
     pub public_key: PublicKey,
     send_control: mpsc::Sender<FunderIncomingControl<B>>,
     recv_control: mpsc::Receiver<FunderOutgoingControl<B>>,
+    send_timer: mpsc::Sender<()>,
     pub report: FunderReport<B>,
 }
 
@@ -205,6 +226,8 @@ pub struct NodeControl<B: Clone> {
 pub enum NodeRecv<B: Clone> {
     ReportMutations(FunderReportMutations<B>),
     ResponseReceived(ResponseReceived),
+    ResponsePaymentHistory(ResponsePaymentHistory),
+    IncomingPaymentHeld(HeldPayment),
 }
 
 impl<B> NodeControl<B>
@@ -215,6 +238,13 @@ where
         await!(self.send_control.send(msg)).ok().map(|_| ())
     }
 
+    /// Advance the Funder's time by one tick.
+    /// The timer channel has no buffer. When this method returns, all the ticks sent before the
+    /// previous tick were fully handled by the Funder.
+    pub async fn tick(&mut self) -> Option<()> {
+        await!(self.send_timer.send(())).ok().map(|_| ())
+    }
+
     pub async fn recv(&mut self) -> Option<NodeRecv<B>> {
         let funder_outgoing_control = await!(self.recv_control.next())?;
         match funder_outgoing_control {
@@ -227,6 +257,12 @@ where
             FunderOutgoingControl::ResponseReceived(response_received) => {
                 Some(NodeRecv::ResponseReceived(response_received))
             }
+            FunderOutgoingControl::ResponsePaymentHistory(response_payment_history) => {
+                Some(NodeRecv::ResponsePaymentHistory(response_payment_history))
+            }
+            FunderOutgoingControl::IncomingPaymentHeld(held_payment) => {
+                Some(NodeRecv::IncomingPaymentHeld(held_payment))
+            }
         }
     }
 
@@ -237,7 +273,9 @@ where
         while !predicate(&self.report) {
             match await!(self.recv()).unwrap() {
                 NodeRecv::ReportMutations(_) => {}
-                NodeRecv::ResponseReceived(_) => unreachable!(),
+                NodeRecv::ResponseReceived(_)
+                | NodeRecv::ResponsePaymentHistory(_)
+                | NodeRecv::IncomingPaymentHeld(_) => unreachable!(),
             };
         }
     }
@@ -247,6 +285,41 @@ where
             match await!(self.recv())? {
                 NodeRecv::ReportMutations(_) => {}
                 NodeRecv::ResponseReceived(response_received) => return Some(response_received),
+                NodeRecv::ResponsePaymentHistory(_) | NodeRecv::IncomingPaymentHeld(_) => {
+                    unreachable!()
+                }
+            };
+        }
+    }
+
+    pub async fn recv_until_held_payment(&mut self) -> Option<HeldPayment> {
+        loop {
+            match await!(self.recv())? {
+                NodeRecv::ReportMutations(_) => {}
+                NodeRecv::IncomingPaymentHeld(held_payment) => return Some(held_payment),
+                NodeRecv::ResponseReceived(_) | NodeRecv::ResponsePaymentHistory(_) => {
+                    unreachable!()
+                }
+            };
+        }
+    }
+
+    pub async fn request_payment_history(
+        &mut self,
+        request_payment_history: RequestPaymentHistory,
+    ) -> Option<ResponsePaymentHistory> {
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[38; UID_LEN]),
+            FunderControl::RequestPaymentHistory(request_payment_history),
+        );
+        await!(self.send(incoming_control_message))?;
+        loop {
+            match await!(self.recv())? {
+                NodeRecv::ReportMutations(_) => {}
+                NodeRecv::ResponseReceived(_) | NodeRecv::IncomingPaymentHeld(_) => unreachable!(),
+                NodeRecv::ResponsePaymentHistory(response_payment_history) => {
+                    return Some(response_payment_history)
+                }
             };
         }
     }
@@ -281,15 +354,19 @@ where
     pub async fn add_friend<'a>(
         &'a mut self,
         friend_public_key: &'a PublicKey,
-        relays: Vec<RelayAddress<B>>,
+        relays: Vec<ChannelAddress<B>>,
         name: &'a str,
+        currency: &'a Currency,
         balance: i128,
     ) {
         let add_friend = AddFriend {
             friend_public_key: friend_public_key.clone(),
             relays,
             name: name.into(),
-            balance,
+            balances: vec![CurrencyBalance {
+                currency: currency.clone(),
+                balance,
+            }],
         };
         let incoming_control_message = FunderIncomingControl::new(
             Uid::from(&[35; UID_LEN]),
@@ -324,10 +401,12 @@ where
     pub async fn set_remote_max_debt<'a>(
         &'a mut self,
         friend_public_key: &'a PublicKey,
+        currency: &'a Currency,
         remote_max_debt: u128,
     ) {
         let set_remote_max_debt = SetFriendRemoteMaxDebt {
             friend_public_key: friend_public_key.clone(),
+            currency: currency.clone(),
             remote_max_debt: remote_max_debt,
         };
         let incoming_control_message = FunderIncomingControl::new(
@@ -345,7 +424,10 @@ where
                 ChannelStatusReport::Consistent(tc_report) => tc_report,
                 _ => return false,
             };
-            tc_report.balance.remote_max_debt == remote_max_debt
+            match find_balance(tc_report, currency) {
+                Some(mc_balance) => mc_balance.remote_max_debt == remote_max_debt,
+                None => false,
+            }
         };
         await!(self.recv_until(pred));
     }
@@ -450,11 +532,14 @@ where
         let (send_comm, incoming_comm) = mpsc::channel(CHANNEL_SIZE);
         let (comm_sender, recv_comm) = mpsc::channel(CHANNEL_SIZE);
 
+        let (send_timer, incoming_timer) = mpsc::channel(0);
+
         let funder_fut = inner_funder_loop(
             identity_client.clone(),
             DummyRandom::new(&[i as u8]),
             incoming_control,
             incoming_comm,
+            incoming_timer,
             control_sender,
             comm_sender,
             funder_state,
@@ -462,6 +547,7 @@ where
             TEST_MAX_NODE_RELAYS,
             TEST_MAX_OPERATIONS_IN_BATCH,
             TEST_MAX_PENDING_USER_REQUESTS,
+            TEST_MAX_PAYMENT_HISTORY,
             None,
         );
 
@@ -487,6 +573,7 @@ where
             public_key: await!(identity_client.request_public_key()).unwrap(),
             send_control,
             recv_control,
+            send_timer,
             report: base_report,
         });
     }
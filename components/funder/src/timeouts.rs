@@ -0,0 +1,190 @@
+use im::hashmap::HashMap as ImHashMap;
+
+use crypto::identity::PublicKey;
+use crypto::uid::Uid;
+
+/// The place where a request that may time out is waiting.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PendingOrigin {
+    /// A request that was received from a friend, and was not yet answered.
+    Friend(PublicKey),
+    /// A request that was issued by the user, and is waiting in the queue of a friend.
+    User(PublicKey),
+    /// A request that was received from a friend and forwarded to the next friend on the route,
+    /// after the first deadline of the request has passed. The next friend gets a few more ticks
+    /// to answer before we fail the request back to the friend.
+    Forwarded(PublicKey),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Timeouts {
+    /// Amount of ticks that passed while requests were pending.
+    /// Ticks are only counted while there are deadlines, and are not counted while the node is
+    /// down.
+    pub ticks: u64,
+    /// (request_id, origin) -> deadline
+    /// A request may have more than one deadline. For example, a payment we send to ourselves
+    /// is both a user request and a request received from a friend.
+    pub deadlines: ImHashMap<(Uid, PendingOrigin), u64>,
+    /// request_id -> next_public_key
+    /// Requests we have forwarded to the next friend on the route, and have already failed back
+    /// to the friend we received them from, because the next friend did not answer in time.
+    /// The credits frozen with the next friend are no longer backed by credits frozen with the
+    /// previous friend. They are released once the next friend answers.
+    pub unbacked: ImHashMap<Uid, PublicKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TimeoutsMutation {
+    Tick,
+    /// Set a deadline for a request: (request_id, left_ticks, origin)
+    SetDeadline((Uid, u64, PendingOrigin)),
+    /// Remove the deadline of a request: (request_id, origin)
+    RemoveDeadline((Uid, PendingOrigin)),
+    /// Add an unbacked request: (request_id, next_public_key)
+    AddUnbacked((Uid, PublicKey)),
+    /// Remove an unbacked request, once the next friend has answered: request_id
+    RemoveUnbacked(Uid),
+}
+
+impl Timeouts {
+    pub fn new() -> Timeouts {
+        Timeouts {
+            ticks: 0,
+            deadlines: ImHashMap::new(),
+            unbacked: ImHashMap::new(),
+        }
+    }
+
+    pub fn mutate(&mut self, mutation: &TimeoutsMutation) {
+        match mutation {
+            TimeoutsMutation::Tick => {
+                self.ticks = self.ticks.saturating_add(1);
+            }
+            TimeoutsMutation::SetDeadline((request_id, left_ticks, origin)) => {
+                let deadline = self.ticks.saturating_add(*left_ticks);
+                self.deadlines
+                    .insert((request_id.clone(), origin.clone()), deadline);
+            }
+            TimeoutsMutation::RemoveDeadline(deadline_key) => {
+                let _ = self.deadlines.remove(deadline_key);
+            }
+            TimeoutsMutation::AddUnbacked((request_id, next_public_key)) => {
+                self.unbacked
+                    .insert(request_id.clone(), next_public_key.clone());
+            }
+            TimeoutsMutation::RemoveUnbacked(request_id) => {
+                let _ = self.unbacked.remove(request_id);
+            }
+        }
+    }
+
+    /// Get all the requests whose deadline has passed.
+    pub fn expired(&self) -> Vec<(Uid, PendingOrigin)> {
+        self.deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= self.ticks)
+            .map(|(deadline_key, _)| deadline_key.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::identity::PUBLIC_KEY_LEN;
+    use crypto::uid::UID_LEN;
+
+    #[test]
+    fn test_timeouts_basic() {
+        let mut timeouts = Timeouts::new();
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let uid_a = Uid::from(&[0; UID_LEN]);
+        let uid_b = Uid::from(&[1; UID_LEN]);
+
+        timeouts.mutate(&TimeoutsMutation::SetDeadline((
+            uid_a.clone(),
+            2,
+            PendingOrigin::Friend(pk_a.clone()),
+        )));
+        timeouts.mutate(&TimeoutsMutation::SetDeadline((
+            uid_b.clone(),
+            3,
+            PendingOrigin::User(pk_a.clone()),
+        )));
+        assert!(timeouts.expired().is_empty());
+
+        timeouts.mutate(&TimeoutsMutation::Tick);
+        assert!(timeouts.expired().is_empty());
+
+        timeouts.mutate(&TimeoutsMutation::Tick);
+        assert_eq!(
+            timeouts.expired(),
+            vec![(uid_a.clone(), PendingOrigin::Friend(pk_a.clone()))]
+        );
+
+        timeouts.mutate(&TimeoutsMutation::RemoveDeadline((
+            uid_a.clone(),
+            PendingOrigin::Friend(pk_a.clone()),
+        )));
+        assert!(timeouts.expired().is_empty());
+
+        timeouts.mutate(&TimeoutsMutation::Tick);
+        assert_eq!(
+            timeouts.expired(),
+            vec![(uid_b.clone(), PendingOrigin::User(pk_a.clone()))]
+        );
+    }
+
+    #[test]
+    fn test_timeouts_same_request_id() {
+        let mut timeouts = Timeouts::new();
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let uid = Uid::from(&[0; UID_LEN]);
+
+        // A payment to ourselves: The same request is both a user request and a request from a
+        // friend:
+        timeouts.mutate(&TimeoutsMutation::SetDeadline((
+            uid.clone(),
+            1,
+            PendingOrigin::User(pk_a.clone()),
+        )));
+        timeouts.mutate(&TimeoutsMutation::SetDeadline((
+            uid.clone(),
+            2,
+            PendingOrigin::Friend(pk_b.clone()),
+        )));
+
+        timeouts.mutate(&TimeoutsMutation::Tick);
+        assert_eq!(
+            timeouts.expired(),
+            vec![(uid.clone(), PendingOrigin::User(pk_a.clone()))]
+        );
+        timeouts.mutate(&TimeoutsMutation::RemoveDeadline((
+            uid.clone(),
+            PendingOrigin::User(pk_a.clone()),
+        )));
+
+        timeouts.mutate(&TimeoutsMutation::Tick);
+        assert_eq!(
+            timeouts.expired(),
+            vec![(uid.clone(), PendingOrigin::Friend(pk_b.clone()))]
+        );
+    }
+
+    #[test]
+    fn test_timeouts_unbacked() {
+        let mut timeouts = Timeouts::new();
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let uid = Uid::from(&[0; UID_LEN]);
+
+        timeouts.mutate(&TimeoutsMutation::AddUnbacked((uid.clone(), pk_a.clone())));
+        assert_eq!(timeouts.unbacked.get(&uid), Some(&pk_a));
+        // Unbacked requests do not have deadlines:
+        assert!(timeouts.expired().is_empty());
+
+        timeouts.mutate(&TimeoutsMutation::RemoveUnbacked(uid.clone()));
+        assert!(timeouts.unbacked.is_empty());
+    }
+}
@@ -7,8 +7,8 @@ use crypto::crypto_rand::{RandValue, RAND_VALUE_LEN};
 use crypto::hash::sha_512_256;
 use crypto::identity::{compare_public_key, PublicKey, Signature, PUBLIC_KEY_LEN, SIGNATURE_LEN};
 
-use proto::app_server::messages::RelayAddress;
-use proto::funder::messages::{FriendTcOp, MoveToken};
+use proto::app_server::messages::ChannelAddress;
+use proto::funder::messages::{Currency, CurrencyBalance, FriendTcOp, MoveToken, TokenBalance};
 use proto::funder::signature_buff::verify_move_token;
 
 use crate::mutual_credit::incoming::{
@@ -74,7 +74,7 @@ pub struct MoveTokenReceived<B> {
     pub incoming_messages: Vec<IncomingMessage>,
     pub mutations: Vec<TcMutation<B>>,
     pub remote_requests_closed: bool,
-    pub opt_local_relays: Option<Vec<RelayAddress<B>>>,
+    pub opt_local_relays: Option<Vec<ChannelAddress<B>>>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -114,8 +114,19 @@ fn rand_nonce_from_public_key(public_key: &PublicKey) -> RandValue {
 fn initial_move_token<B>(
     low_public_key: &PublicKey,
     high_public_key: &PublicKey,
-    balance: i128,
+    balances: &[CurrencyBalance],
 ) -> MoveToken<B> {
+    let mut balances = balances
+        .iter()
+        .map(|currency_balance| TokenBalance {
+            currency: currency_balance.currency.clone(),
+            balance: currency_balance.balance,
+            local_pending_debt: 0,
+            remote_pending_debt: 0,
+        })
+        .collect::<Vec<_>>();
+    balances.sort_by(|a, b| a.currency.cmp(&b.currency));
+
     // This is a special initialization case.
     // Note that this is the only case where new_token is not a valid signature.
     // We do this because we want to have synchronization between the two sides of the token
@@ -129,9 +140,7 @@ fn initial_move_token<B>(
         remote_public_key: high_public_key.clone(),
         inconsistency_counter: 0,
         move_token_counter: 0,
-        balance,
-        local_pending_debt: 0,
-        remote_pending_debt: 0,
+        balances,
         rand_nonce: rand_nonce_from_public_key(&high_public_key),
         new_token: token_from_public_key(&high_public_key),
     }
@@ -141,14 +150,18 @@ impl<B> TokenChannel<B>
 where
     B: Clone + CanonicalSerialize,
 {
-    pub fn new(local_public_key: &PublicKey, remote_public_key: &PublicKey, balance: i128) -> Self {
-        let mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, balance);
+    pub fn new(
+        local_public_key: &PublicKey,
+        remote_public_key: &PublicKey,
+        balances: &[CurrencyBalance],
+    ) -> Self {
+        let mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, balances);
 
         if compare_public_key(&local_public_key, &remote_public_key) == Ordering::Less {
             // We are the first sender
             let tc_outgoing = TcOutgoing {
                 mutual_credit,
-                move_token_out: initial_move_token(local_public_key, remote_public_key, balance),
+                move_token_out: initial_move_token(local_public_key, remote_public_key, balances),
                 opt_prev_move_token_in: None,
             };
             TokenChannel {
@@ -156,12 +169,19 @@ where
             }
         } else {
             // We are the second sender
+            let remote_balances = balances
+                .iter()
+                .map(|currency_balance| CurrencyBalance {
+                    currency: currency_balance.currency.clone(),
+                    balance: currency_balance.balance.checked_neg().unwrap(),
+                })
+                .collect::<Vec<_>>();
             let tc_incoming = TcIncoming {
                 mutual_credit,
                 move_token_in: create_hashed::<B>(&initial_move_token(
                     remote_public_key,
                     local_public_key,
-                    balance.checked_neg().unwrap(),
+                    &remote_balances,
                 )),
             };
             TokenChannel {
@@ -174,12 +194,12 @@ where
         local_public_key: &PublicKey,
         remote_public_key: &PublicKey,
         reset_move_token: &MoveToken<B>,
-        balance: i128,
+        balances: &[CurrencyBalance],
     ) -> TokenChannel<B> {
-        // is balance redundant here?
+        // is balances redundant here?
 
         let tc_incoming = TcIncoming {
-            mutual_credit: MutualCredit::new(local_public_key, remote_public_key, balance),
+            mutual_credit: MutualCredit::new(local_public_key, remote_public_key, balances),
             move_token_in: create_hashed(&reset_move_token),
         };
 
@@ -192,11 +212,11 @@ where
         local_public_key: &PublicKey,
         remote_public_key: &PublicKey,
         reset_move_token: &MoveToken<B>,
-        balance: i128, // Is this redundant?
+        balances: &[CurrencyBalance], // Is this redundant?
         opt_last_incoming_move_token: Option<MoveTokenHashed>,
     ) -> TokenChannel<B> {
         let tc_outgoing = TcOutgoing {
-            mutual_credit: MutualCredit::new(local_public_key, remote_public_key, balance),
+            mutual_credit: MutualCredit::new(local_public_key, remote_public_key, balances),
             move_token_out: reset_move_token.clone(),
             opt_prev_move_token_in: opt_last_incoming_move_token,
         };
@@ -213,8 +233,15 @@ where
         }
     }
 
-    pub fn get_remote_max_debt(&self) -> u128 {
-        self.get_mutual_credit().state().balance.remote_max_debt
+    /// The maximum debt we allow the remote side in a given currency.
+    /// Returns 0 if there is no credit line for this currency.
+    pub fn get_remote_max_debt(&self, currency: &Currency) -> u128 {
+        self.get_mutual_credit()
+            .state()
+            .balances
+            .get(currency)
+            .map(|mc_balance| mc_balance.remote_max_debt)
+            .unwrap_or(0)
     }
 
     pub fn get_direction(&self) -> &TcDirection<B> {
@@ -322,7 +349,7 @@ impl TcIncoming {
     pub fn create_unsigned_move_token<B>(
         &self,
         operations: Vec<FriendTcOp>,
-        opt_local_relays: Option<Vec<RelayAddress<B>>>,
+        opt_local_relays: Option<Vec<ChannelAddress<B>>>,
         rand_nonce: RandValue,
     ) -> UnsignedMoveToken<B> {
         create_unsigned_move_token(
@@ -334,9 +361,7 @@ impl TcIncoming {
             self.move_token_in.local_public_key.clone(),
             self.move_token_in.inconsistency_counter,
             self.move_token_in.move_token_counter.wrapping_add(1),
-            self.mutual_credit.state().balance.balance,
-            self.mutual_credit.state().balance.local_pending_debt,
-            self.mutual_credit.state().balance.remote_pending_debt,
+            self.mutual_credit.token_balances(),
             rand_nonce,
         )
     }
@@ -346,6 +371,17 @@ impl TcIncoming {
     }
 }
 
+/// Convert a balance stated by the remote side to the same balance from our point of view.
+/// Returns None if the stated balance can not be negated.
+fn remote_to_local_token_balance(token_balance: &TokenBalance) -> Option<TokenBalance> {
+    Some(TokenBalance {
+        currency: token_balance.currency.clone(),
+        balance: token_balance.balance.checked_neg()?,
+        local_pending_debt: token_balance.remote_pending_debt,
+        remote_pending_debt: token_balance.local_pending_debt,
+    })
+}
+
 impl<B> TcOutgoing<B>
 where
     B: Clone + CanonicalSerialize,
@@ -438,12 +474,13 @@ where
                     }
                 }
 
-                // Verify stated balances:
-                let check_balance = &check_mutual_credit.state().balance;
-                if check_balance.balance != -new_move_token.balance
-                    || check_balance.local_pending_debt != new_move_token.remote_pending_debt
-                    || check_balance.remote_pending_debt != new_move_token.local_pending_debt
-                {
+                // Verify stated balances (Of all currencies):
+                let opt_stated_balances = new_move_token
+                    .balances
+                    .iter()
+                    .map(remote_to_local_token_balance)
+                    .collect::<Option<Vec<_>>>();
+                if opt_stated_balances != Some(check_mutual_credit.token_balances()) {
                     return Err(ReceiveMoveTokenError::InvalidStatedBalance);
                 }
 
@@ -479,6 +516,7 @@ mod tests {
     use crypto::identity::{generate_pkcs8_key_pair, SoftwareEd25519Identity};
     use crypto::test_utils::DummyRandom;
 
+    use proto::funder::messages::SetRemoteMaxDebt;
     use proto::funder::signature_buff::move_token_signature_buff;
 
     /// A helper function to sign an UnsignedMoveToken using an identity:
@@ -500,9 +538,7 @@ mod tests {
             remote_public_key: unsigned_move_token.remote_public_key,
             inconsistency_counter: unsigned_move_token.inconsistency_counter,
             move_token_counter: unsigned_move_token.move_token_counter,
-            balance: unsigned_move_token.balance,
-            local_pending_debt: unsigned_move_token.local_pending_debt,
-            remote_pending_debt: unsigned_move_token.local_pending_debt,
+            balances: unsigned_move_token.balances,
             rand_nonce: unsigned_move_token.rand_nonce,
             new_token: identity.sign(&signature_buff),
         }
@@ -512,8 +548,17 @@ mod tests {
     fn test_initial_direction() {
         let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
         let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
-        let token_channel_a_b = TokenChannel::<u32>::new(&pk_a, &pk_b, 0i128);
-        let token_channel_b_a = TokenChannel::<u32>::new(&pk_b, &pk_a, 0i128);
+        let currency = Currency::try_from("USD".to_owned()).unwrap();
+        let balances_a_b = vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 5,
+        }];
+        let balances_b_a = vec![CurrencyBalance {
+            currency,
+            balance: -5,
+        }];
+        let token_channel_a_b = TokenChannel::<u32>::new(&pk_a, &pk_b, &balances_a_b);
+        let token_channel_b_a = TokenChannel::<u32>::new(&pk_b, &pk_a, &balances_b_a);
 
         // Only one of those token channels is outgoing:
         let is_a_b_outgoing = token_channel_a_b.is_outgoing();
@@ -556,7 +601,7 @@ mod tests {
     {
         let pk1 = identity1.get_public_key();
         let pk2 = identity2.get_public_key();
-        let token_channel12 = TokenChannel::<u32>::new(&pk1, &pk2, 0i128); // (local, remote)
+        let token_channel12 = TokenChannel::<u32>::new(&pk1, &pk2, &[]); // (local, remote)
         if token_channel12.is_outgoing() {
             (identity1, identity2)
         } else {
@@ -565,13 +610,14 @@ mod tests {
     }
 
     /// Before: tc1: outgoing, tc2: incoming
-    /// Send SetRemoteMaxDebt: tc2 -> tc1
+    /// Send SetRemoteMaxDebt (For the given currency): tc2 -> tc1
     /// After: tc1: incoming, tc2: outgoing
     fn set_remote_max_debt21<I>(
         _identity1: &I,
         identity2: &I,
         tc1: &mut TokenChannel<u32>,
         tc2: &mut TokenChannel<u32>,
+        currency: &Currency,
     ) where
         I: Identity,
     {
@@ -583,7 +629,11 @@ mod tests {
             TcDirection::Outgoing(_) => unreachable!(),
         };
         let mut outgoing_mc = tc2_incoming.begin_outgoing_move_token();
-        let friend_tc_op = FriendTcOp::SetRemoteMaxDebt(100);
+        let set_remote_max_debt = SetRemoteMaxDebt {
+            currency: currency.clone(),
+            remote_max_debt: 100,
+        };
+        let friend_tc_op = FriendTcOp::SetRemoteMaxDebt(set_remote_max_debt);
         let mc_mutations = outgoing_mc.queue_operation(&friend_tc_op).unwrap();
         let operations = vec![friend_tc_op];
 
@@ -622,7 +672,10 @@ mod tests {
             match &move_token_received.mutations[i] {
                 TcMutation::McMutation(mc_mutation) => {
                     seen_mc_mutation = true;
-                    assert_eq!(mc_mutation, &McMutation::SetLocalMaxDebt(100));
+                    assert_eq!(
+                        mc_mutation,
+                        &McMutation::SetLocalMaxDebt((currency.clone(), 100))
+                    );
                 }
                 TcMutation::SetDirection(set_direction) => {
                     seen_set_direction = true;
@@ -650,10 +703,14 @@ mod tests {
             }
         };
         // assert_eq!(&tc1.get_cur_move_token_hashed(), &create_hashed(&friend_move_token));
-        assert_eq!(tc1.get_mutual_credit().state().balance.local_max_debt, 100);
+        assert_eq!(
+            tc1.get_mutual_credit().state().balances[currency].local_max_debt,
+            100
+        );
     }
 
-    /// This tests sends a SetRemoteMaxDebt(100) in both ways.
+    /// This tests sends a SetRemoteMaxDebt(100) in both ways, and then another one in a second
+    /// currency.
     #[test]
     fn test_simulate_receive_move_token_basic() {
         let rng1 = DummyRandom::new(&[1u8]);
@@ -668,18 +725,29 @@ mod tests {
 
         let pk1 = identity1.get_public_key();
         let pk2 = identity2.get_public_key();
-        let mut tc1 = TokenChannel::new(&pk1, &pk2, 0i128); // (local, remote)
-        let mut tc2 = TokenChannel::new(&pk2, &pk1, 0i128); // (local, remote)
+        let mut tc1 = TokenChannel::new(&pk1, &pk2, &[]); // (local, remote)
+        let mut tc2 = TokenChannel::new(&pk2, &pk1, &[]); // (local, remote)
+
+        let currency_usd = Currency::try_from("USD".to_owned()).unwrap();
+        let currency_hours = Currency::try_from("HOURS".to_owned()).unwrap();
 
         // Current state:  tc1 --> tc2
         // tc1: outgoing
         // tc2: incoming
-        set_remote_max_debt21(&identity1, &identity2, &mut tc1, &mut tc2);
+        set_remote_max_debt21(&identity1, &identity2, &mut tc1, &mut tc2, &currency_usd);
 
         // Current state:  tc2 --> tc1
         // tc1: incoming
         // tc2: outgoing
-        set_remote_max_debt21(&identity2, &identity1, &mut tc2, &mut tc1);
+        set_remote_max_debt21(&identity2, &identity1, &mut tc2, &mut tc1, &currency_usd);
+
+        // A second currency gets a separate credit line:
+        set_remote_max_debt21(&identity1, &identity2, &mut tc1, &mut tc2, &currency_hours);
+
+        assert_eq!(tc1.get_remote_max_debt(&currency_usd), 100);
+        assert_eq!(tc1.get_remote_max_debt(&currency_hours), 0);
+        assert_eq!(tc2.get_remote_max_debt(&currency_usd), 100);
+        assert_eq!(tc2.get_remote_max_debt(&currency_hours), 100);
     }
 
     // TODO: Add more tests.
@@ -4,10 +4,11 @@ use crypto::crypto_rand::RandValue;
 use crypto::hash::HashResult;
 use crypto::identity::{PublicKey, Signature};
 
-use proto::app_server::messages::RelayAddress;
+use proto::app_server::messages::ChannelAddress;
 use proto::funder::messages::{
-    ChannelerUpdateFriend, FailureSendFunds, FriendMessage, FriendTcOp, FunderIncomingControl,
-    FunderOutgoingControl, MoveToken, PendingRequest, RequestSendFunds, ResponseSendFunds,
+    ChannelerUpdateFriend, CurrencyBalance, FailureSendFunds, FriendMessage, FriendTcOp,
+    FunderIncomingControl, FunderOutgoingControl, MoveToken, PendingRequest, RequestSendFunds,
+    ResponseSendFunds, SetRemoteMaxDebt, TokenBalance,
 };
 
 use proto::funder::signature_buff::{
@@ -39,9 +40,7 @@ where
         remote_public_key: unsigned_move_token.remote_public_key,
         inconsistency_counter: unsigned_move_token.inconsistency_counter,
         move_token_counter: unsigned_move_token.move_token_counter,
-        balance: unsigned_move_token.balance,
-        local_pending_debt: unsigned_move_token.local_pending_debt,
-        remote_pending_debt: unsigned_move_token.remote_pending_debt,
+        balances: unsigned_move_token.balances,
         rand_nonce: unsigned_move_token.rand_nonce,
         new_token,
     }
@@ -96,7 +95,7 @@ pub async fn create_failure_send_funds<'a>(
 pub enum UnsignedFriendTcOp {
     EnableRequests,
     DisableRequests,
-    SetRemoteMaxDebt(u128),
+    SetRemoteMaxDebt(SetRemoteMaxDebt),
     RequestSendFunds(RequestSendFunds),
     ResponseSendFunds(ResponseSendFunds),
     UnsignedResponseSendFunds(UnsignedResponseSendFunds),
@@ -111,8 +110,12 @@ pub fn create_pending_request(request_send_funds: &RequestSendFunds) -> PendingR
     PendingRequest {
         request_id: request_send_funds.request_id,
         route: request_send_funds.route.clone(),
+        currency: request_send_funds.currency.clone(),
         dest_payment: request_send_funds.dest_payment,
+        total_dest_payment: request_send_funds.total_dest_payment,
+        left_fees: request_send_funds.left_fees,
         invoice_id: request_send_funds.invoice_id.clone(),
+        left_ticks: request_send_funds.left_ticks,
     }
 }
 
@@ -124,24 +127,20 @@ pub struct MoveTokenHashed {
     pub remote_public_key: PublicKey,
     pub inconsistency_counter: u64,
     pub move_token_counter: u128,
-    pub balance: i128,
-    pub local_pending_debt: u128,
-    pub remote_pending_debt: u128,
+    pub balances: Vec<TokenBalance>,
     pub rand_nonce: RandValue,
     pub new_token: Signature,
 }
 
 pub fn create_unsigned_move_token<B>(
     operations: Vec<FriendTcOp>,
-    opt_local_relays: Option<Vec<RelayAddress<B>>>,
+    opt_local_relays: Option<Vec<ChannelAddress<B>>>,
     old_token: Signature,
     local_public_key: PublicKey,
     remote_public_key: PublicKey,
     inconsistency_counter: u64,
     move_token_counter: u128,
-    balance: i128,
-    local_pending_debt: u128,
-    remote_pending_debt: u128,
+    balances: Vec<TokenBalance>,
     rand_nonce: RandValue,
 ) -> UnsignedMoveToken<B> {
     MoveToken {
@@ -152,9 +151,7 @@ pub fn create_unsigned_move_token<B>(
         remote_public_key,
         inconsistency_counter,
         move_token_counter,
-        balance,
-        local_pending_debt,
-        remote_pending_debt,
+        balances,
         rand_nonce,
         new_token: (),
     }
@@ -207,9 +204,7 @@ where
         remote_public_key: move_token.remote_public_key.clone(),
         inconsistency_counter: move_token.inconsistency_counter,
         move_token_counter: move_token.move_token_counter,
-        balance: move_token.balance,
-        local_pending_debt: move_token.local_pending_debt,
-        remote_pending_debt: move_token.remote_pending_debt,
+        balances: move_token.balances.clone(),
         rand_nonce: move_token.rand_nonce.clone(),
         new_token: move_token.new_token.clone(),
     }
@@ -223,7 +218,7 @@ pub enum IncomingLivenessMessage {
 
 pub struct FriendInconsistencyError {
     pub reset_token: Signature,
-    pub balance_for_reset: i128,
+    pub balance_for_reset: Vec<CurrencyBalance>,
 }
 
 #[derive(Debug)]
@@ -250,6 +245,8 @@ pub enum FunderIncoming<B> {
     Init,
     Control(FunderIncomingControl<B>),
     Comm(FunderIncomingComm<B>),
+    /// A time tick, used to cancel requests that were not answered in time.
+    TimerTick,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -266,5 +263,5 @@ where
 #[derive(Debug)]
 pub enum FunderOutgoingComm<B> {
     FriendMessage((PublicKey, FriendMessage<B>)),
-    ChannelerConfig(ChannelerConfig<RelayAddress<B>>),
+    ChannelerConfig(ChannelerConfig<ChannelAddress<B>>),
 }
@@ -3,11 +3,12 @@ use futures::task::{Spawn, SpawnError, SpawnExt};
 use futures::{SinkExt, StreamExt};
 
 use crypto::identity::PublicKey;
+use proto::funder::messages::{Currency, Rate};
 use proto::index_client::messages::{IndexMutation, UpdateFriend};
 
 use crate::seq_map::SeqMap;
 
-pub type SeqFriends = SeqMap<PublicKey, (u128, u128)>;
+pub type SeqFriends = SeqMap<(PublicKey, Currency), (u128, u128, Rate)>;
 
 pub enum SeqFriendsRequest {
     Mutate(IndexMutation, oneshot::Sender<()>),
@@ -29,11 +30,20 @@ pub struct SeqFriendsClient {
 fn apply_index_mutation(seq_friends: &mut SeqFriends, index_mutation: &IndexMutation) {
     match index_mutation {
         IndexMutation::UpdateFriend(update_friend) => {
-            let capacity_pair = (update_friend.send_capacity, update_friend.recv_capacity);
-            let _ = seq_friends.update(update_friend.public_key.clone(), capacity_pair);
+            let friend_info = (
+                update_friend.send_capacity,
+                update_friend.recv_capacity,
+                update_friend.rate.clone(),
+            );
+            let friend_key = (
+                update_friend.public_key.clone(),
+                update_friend.currency.clone(),
+            );
+            let _ = seq_friends.update(friend_key, friend_info);
         }
         IndexMutation::RemoveFriend(public_key) => {
-            let _ = seq_friends.remove(public_key);
+            // Remove the friend's capacities in all currencies:
+            seq_friends.retain(|(friend_public_key, _currency), _| friend_public_key != public_key);
         }
     }
 }
@@ -53,18 +63,19 @@ async fn seq_friends_loop(
                 let _ = response_sender.send(());
             }
             SeqFriendsRequest::NextUpdate(response_sender) => {
-                let update_friend =
-                    seq_friends
-                        .next()
-                        .map(|(cycle_countdown, (public_key, capacities))| {
-                            let (send_capacity, recv_capacity) = capacities;
-                            let update_friend = UpdateFriend {
-                                public_key,
-                                send_capacity,
-                                recv_capacity,
-                            };
-                            (cycle_countdown, update_friend)
-                        });
+                let update_friend = seq_friends.next().map(
+                    |(cycle_countdown, ((public_key, currency), friend_info))| {
+                        let (send_capacity, recv_capacity, rate) = friend_info;
+                        let update_friend = UpdateFriend {
+                            public_key,
+                            send_capacity,
+                            recv_capacity,
+                            currency,
+                            rate,
+                        };
+                        (cycle_countdown, update_friend)
+                    },
+                );
                 let _ = response_sender.send(update_friend);
             }
         }
@@ -45,6 +45,16 @@ where
         self.map.remove(key)
     }
 
+    /// Keep only the pairs for which `f` returns true.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.map.retain(|key, value| f(key, value));
+        let map = &self.map;
+        self.queue.retain(|cur_key| map.contains_key(cur_key));
+    }
+
     pub fn reset_countdown(&mut self) {
         self.cycle_countdown = self.queue.len();
     }
@@ -121,6 +131,21 @@ mod tests {
         assert_eq!(seq_map_pairs(&mut seq_map), vec![(0, 5), (2, 7), (3, 8)]);
     }
 
+    #[test]
+    fn test_seq_map_retain() {
+        let mut hash_map = HashMap::new();
+        hash_map.insert(0u32, 4u64);
+        hash_map.insert(1u32, 5u64);
+        hash_map.insert(2u32, 6u64);
+        hash_map.insert(3u32, 7u64);
+
+        let mut seq_map = SeqMap::new(hash_map);
+
+        seq_map.retain(|key, _value| key % 2 == 0);
+
+        assert_eq!(seq_map_pairs(&mut seq_map), vec![(0, 4), (2, 6)]);
+    }
+
     #[test]
     fn test_seq_map_next() {
         let mut hash_map = HashMap::new();
@@ -14,8 +14,8 @@ use crypto::uid::Uid;
 use identity::IdentityClient;
 
 use proto::index_server::messages::{
-    IndexClientToServer, IndexMutation, IndexServerToClient, MutationsUpdate, RequestRoutes,
-    ResponseRoutes, RouteWithCapacity,
+    IndexClientToServer, IndexMutation, IndexServerToClient, MutationsUpdate, RequestRejected,
+    RequestRoutes, ResponseRoutes, RouteWithCapacity,
 };
 
 pub type ServerConn = ConnPair<IndexClientToServer, IndexServerToClient>;
@@ -108,6 +108,20 @@ where
                     );
                 }
             }
+            IndexServerToClient::RequestRejected(request_rejected) => {
+                let RequestRejected { request_id, reason } = request_rejected;
+                warn!(
+                    "Server rejected request_id: {:?}, reason: {:?}",
+                    &request_id, reason
+                );
+                // Dropping the request sender reports a failure for this request:
+                if self.open_requests.remove(&request_id).is_none() {
+                    warn!(
+                        "Received a rejection for unrecognized request_id: {:?}",
+                        &request_id
+                    );
+                }
+            }
         }
         Ok(())
     }
@@ -233,6 +247,8 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::convert::TryFrom;
+
     use crypto::hash::HASH_RESULT_LEN;
     use futures::executor::ThreadPool;
     use futures::task::{Spawn, SpawnExt};
@@ -245,6 +261,8 @@ mod tests {
     use crypto::uid::UID_LEN;
 
     use identity::create_identity;
+    use proto::funder::messages::Currency;
+    use proto::index_server::messages::{RejectReason, RequestRoutesMode};
 
     async fn task_first_server_time_hash() {
         let (mut to_server, mut from_server) = mpsc::channel(0);
@@ -327,6 +345,13 @@ mod tests {
             source: PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]),
             destination: PublicKey::from(&[0xdd; PUBLIC_KEY_LEN]),
             opt_exclude: None,
+            currency: Currency::try_from("FST".to_owned()).unwrap(),
+            max_routes: 4,
+            mode: RequestRoutesMode::Routes,
+            exclude_nodes: Vec::new(),
+            exclude_edges: Vec::new(),
+            waypoints: Vec::new(),
+            opt_max_route_len: None,
         };
 
         let (response_sender, response_receiver) = oneshot::channel();
@@ -357,6 +382,23 @@ mod tests {
         let routes = await!(response_receiver).unwrap();
         assert_eq!(routes, vec![]);
 
+        // Request routes again:
+        let (response_sender, response_receiver) = oneshot::channel();
+        let single_client_control =
+            SingleClientControl::RequestRoutes((request_routes.clone(), response_sender));
+        await!(control_sender.send(single_client_control)).unwrap();
+        let _ = await!(server_receiver.next()).unwrap();
+
+        // Server rejects the request:
+        let request_rejected = RequestRejected {
+            request_id: Uid::from(&[3; UID_LEN]),
+            reason: RejectReason::RateLimited,
+        };
+        await!(server_sender.send(IndexServerToClient::RequestRejected(request_rejected))).unwrap();
+
+        // Client is notified about the failure:
+        assert!(await!(response_receiver).is_err());
+
         for iter in 0..3 {
             // Counter should increment every time
             // Send mutations:
@@ -1,3 +1,5 @@
+use std::convert::TryFrom;
+
 use futures::channel::{mpsc, oneshot};
 use futures::executor::ThreadPool;
 use futures::task::{Spawn, SpawnExt};
@@ -7,9 +9,10 @@ use common::dummy_connector::{ConnRequest, DummyConnector};
 
 use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
 use crypto::uid::{Uid, UID_LEN};
+use proto::funder::messages::{Currency, Rate};
 use proto::index_client::messages::{
     AppServerToIndexClient, IndexClientReportMutation, IndexClientRequest, IndexClientToAppServer,
-    IndexMutation, RequestRoutes, ResponseRoutesResult, UpdateFriend,
+    IndexMutation, RequestRoutes, RequestRoutesMode, ResponseRoutesResult, UpdateFriend,
 };
 use proto::index_server::messages::{IndexServerAddress, NamedIndexServerAddress};
 
@@ -154,6 +157,8 @@ where
                     public_key: PublicKey::from(PublicKey::from(&[0xaa; PUBLIC_KEY_LEN])),
                     send_capacity: 100,
                     recv_capacity: 50,
+                    currency: Currency::try_from("FST".to_owned()).unwrap(),
+                    rate: Rate { mul: 0, add: 1 },
                 };
                 response_sender.send(Some((0, update_friend))).unwrap();
             }
@@ -320,6 +325,8 @@ where
         public_key: PublicKey::from(PublicKey::from(&[0xbb; PUBLIC_KEY_LEN])),
         send_capacity: 200,
         recv_capacity: 100,
+        currency: Currency::try_from("FST".to_owned()).unwrap(),
+        rate: Rate { mul: 0, add: 1 },
     };
     let index_mutation = IndexMutation::UpdateFriend(update_friend);
     let mutations = vec![index_mutation.clone()];
@@ -343,6 +350,8 @@ where
         public_key: PublicKey::from(PublicKey::from(&[0xcc; PUBLIC_KEY_LEN])),
         send_capacity: 20,
         recv_capacity: 30,
+        currency: Currency::try_from("FST".to_owned()).unwrap(),
+        rate: Rate { mul: 0, add: 1 },
     };
 
     match await!(icc.seq_friends_receiver.next()).unwrap() {
@@ -387,6 +396,13 @@ where
         source: PublicKey::from(PublicKey::from(&[0xee; PUBLIC_KEY_LEN])),
         destination: PublicKey::from(PublicKey::from(&[0xff; PUBLIC_KEY_LEN])),
         opt_exclude: None,
+        currency: Currency::try_from("FST".to_owned()).unwrap(),
+        max_routes: 4,
+        mode: RequestRoutesMode::Routes,
+        exclude_nodes: Vec::new(),
+        exclude_edges: Vec::new(),
+        waypoints: Vec::new(),
+        opt_max_route_len: None,
     };
 
     // Request routes from IndexClient (From AppServer):
@@ -464,6 +480,8 @@ where
         public_key: PublicKey::from(PublicKey::from(&[0xbb; PUBLIC_KEY_LEN])),
         send_capacity: 200,
         recv_capacity: 100,
+        currency: Currency::try_from("FST".to_owned()).unwrap(),
+        rate: Rate { mul: 0, add: 1 },
     };
     let index_mutation = IndexMutation::UpdateFriend(update_friend);
     let mutations = vec![index_mutation.clone()];
@@ -491,6 +509,13 @@ where
         source: PublicKey::from(PublicKey::from(&[0xee; PUBLIC_KEY_LEN])),
         destination: PublicKey::from(PublicKey::from(&[0xff; PUBLIC_KEY_LEN])),
         opt_exclude: None,
+        currency: Currency::try_from("FST".to_owned()).unwrap(),
+        max_routes: 4,
+        mode: RequestRoutesMode::Routes,
+        exclude_nodes: Vec::new(),
+        exclude_edges: Vec::new(),
+        waypoints: Vec::new(),
+        opt_max_route_len: None,
     };
 
     // Request routes from IndexClient (From AppServer):
@@ -0,0 +1,187 @@
+//! Benchmarks comparing the capacity graph backends on synthetic graphs.
+//!
+//! Run with:
+//! cargo bench -p offst-index-server --features bench graph::benches
+
+extern crate test;
+
+use test::Bencher;
+
+use proto::funder::messages::Rate;
+
+use super::capacity_graph::{CapacityGraph, RouteConstraints};
+use super::compact_capacity_graph::CompactCapacityGraph;
+use super::simple_capacity_graph::SimpleCapacityGraph;
+
+/// Nodes of the index server graph are public keys, so nodes of the synthetic graph are of the
+/// same size.
+type Node = [u8; 32];
+
+/// Amount of nodes in the synthetic graph
+const NUM_NODES: u32 = 0x1000;
+/// Amount of friends every node adds to the synthetic graph. Friends are chosen at random, so
+/// some nodes end up with more friends than others.
+const NUM_FRIENDS: usize = 8;
+/// Amount of routes requests performed in every iteration of a routes benchmark
+const NUM_REQUESTS: usize = 0x10;
+/// Amount of edges updated in every iteration of the incremental updates benchmark
+const NUM_UPDATES: usize = 0x100;
+
+/// Deterministic pseudo random numbers (xorshift)
+struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        XorShift { state: seed }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn next_node(&mut self) -> Node {
+        node((self.next() % u64::from(NUM_NODES)) as u32)
+    }
+}
+
+fn node(index: u32) -> Node {
+    let mut node = [0; 32];
+    node[..4].copy_from_slice(&index.to_be_bytes());
+    node
+}
+
+/// Create a graph of `NUM_NODES` nodes, where every node is connected to `NUM_FRIENDS` random
+/// friends. Both sides of every friendship are reported, with random capacities and fees.
+fn synthetic_graph<CG>() -> CG
+where
+    CG: CapacityGraph<Node = Node, Capacity = u128, Rate = Rate> + Default,
+{
+    let mut rng = XorShift::new(0x1337);
+    let mut cg = CG::default();
+    for a in (0..NUM_NODES).map(node) {
+        for _ in 0..NUM_FRIENDS {
+            let b = rng.next_node();
+            let a_b_capacity = u128::from(rng.next() % 1000);
+            let b_a_capacity = u128::from(rng.next() % 1000);
+            let a_rate = Rate {
+                mul: 0,
+                add: (rng.next() % 4) as u32,
+            };
+            let b_rate = Rate {
+                mul: 0,
+                add: (rng.next() % 4) as u32,
+            };
+            cg.update_edge(a, b, (a_b_capacity, b_a_capacity, a_rate));
+            cg.update_edge(b, a, (b_a_capacity, a_b_capacity, b_rate));
+        }
+    }
+    cg
+}
+
+/// Random pairs of (source, destination) for routes requests
+fn request_pairs() -> Vec<(Node, Node)> {
+    let mut rng = XorShift::new(0x7357);
+    (0..NUM_REQUESTS)
+        .map(|_| (rng.next_node(), rng.next_node()))
+        .collect()
+}
+
+fn bench_update_edges<CG>(bencher: &mut Bencher)
+where
+    CG: CapacityGraph<Node = Node, Capacity = u128, Rate = Rate> + Default,
+{
+    bencher.iter(synthetic_graph::<CG>);
+}
+
+fn bench_get_routes<CG>(bencher: &mut Bencher)
+where
+    CG: CapacityGraph<Node = Node, Capacity = u128, Rate = Rate> + Default,
+{
+    let cg = synthetic_graph::<CG>();
+    let pairs = request_pairs();
+    let constraints = RouteConstraints::default();
+    bencher.iter(|| {
+        for (a, b) in &pairs {
+            cg.get_routes(a, b, 100, &constraints, 4);
+        }
+    });
+}
+
+fn bench_get_max_flow_routes<CG>(bencher: &mut Bencher)
+where
+    CG: CapacityGraph<Node = Node, Capacity = u128, Rate = Rate> + Default,
+{
+    let cg = synthetic_graph::<CG>();
+    let pairs = request_pairs();
+    let constraints = RouteConstraints::default();
+    bencher.iter(|| {
+        for (a, b) in &pairs {
+            cg.get_max_flow_routes(a, b, &constraints);
+        }
+    });
+}
+
+/// Add and then remove random edges on top of a large graph.
+fn bench_incremental_updates<CG>(bencher: &mut Bencher)
+where
+    CG: CapacityGraph<Node = Node, Capacity = u128, Rate = Rate> + Default,
+{
+    let mut cg = synthetic_graph::<CG>();
+    let mut rng = XorShift::new(0xbeef);
+    let edges = (0..NUM_UPDATES)
+        .map(|_| (rng.next_node(), rng.next_node()))
+        .collect::<Vec<_>>();
+    bencher.iter(|| {
+        for &(a, b) in &edges {
+            cg.update_edge(a, b, (100, 100, Rate { mul: 0, add: 1 }));
+        }
+        for (a, b) in &edges {
+            cg.remove_edge(a, b);
+        }
+    });
+}
+
+#[bench]
+fn bench_simple_update_edges(bencher: &mut Bencher) {
+    bench_update_edges::<SimpleCapacityGraph<Node, Rate>>(bencher);
+}
+
+#[bench]
+fn bench_compact_update_edges(bencher: &mut Bencher) {
+    bench_update_edges::<CompactCapacityGraph<Node, Rate>>(bencher);
+}
+
+#[bench]
+fn bench_simple_get_routes(bencher: &mut Bencher) {
+    bench_get_routes::<SimpleCapacityGraph<Node, Rate>>(bencher);
+}
+
+#[bench]
+fn bench_compact_get_routes(bencher: &mut Bencher) {
+    bench_get_routes::<CompactCapacityGraph<Node, Rate>>(bencher);
+}
+
+#[bench]
+fn bench_simple_get_max_flow_routes(bencher: &mut Bencher) {
+    bench_get_max_flow_routes::<SimpleCapacityGraph<Node, Rate>>(bencher);
+}
+
+#[bench]
+fn bench_compact_get_max_flow_routes(bencher: &mut Bencher) {
+    bench_get_max_flow_routes::<CompactCapacityGraph<Node, Rate>>(bencher);
+}
+
+#[bench]
+fn bench_simple_incremental_updates(bencher: &mut Bencher) {
+    bench_incremental_updates::<SimpleCapacityGraph<Node, Rate>>(bencher);
+}
+
+#[bench]
+fn bench_compact_incremental_updates(bencher: &mut Bencher) {
+    bench_incremental_updates::<CompactCapacityGraph<Node, Rate>>(bencher);
+}
@@ -1,43 +1,186 @@
-pub type CapacityEdge<C> = (C, C);
-pub type CapacityRoute<N, C> = (Vec<N>, C);
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use proto::funder::messages::Rate;
+
+/// A directed edge, as reported by the node at its start:
+/// (send_capacity, recv_capacity, rate).
+/// The rate is the fee the reporting node charges the remote node for forwarding its requests.
+pub type CapacityEdge<C, T> = (C, C, T);
+/// A route, together with the capacity it is possible to send through the route, and the total
+/// fees paid to the mediators along the route.
+pub type CapacityRoute<N, C> = (Vec<N>, C, C);
+/// A directed edge saved in a snapshot of the graph: (a, b, edge, age).
+/// The age is the amount of ticks the edge has lived since it was last updated.
+pub type SavedEdge<N, C, T> = (N, N, CapacityEdge<C, T>, u128);
+
+/// Amount of ticks an edge could live regardless of coupon collector's approximation.
+/// This is useful to allow the first edges build (n*log(n) is very small for small n).
+pub const BASE_MAX_EDGE_AGE: u128 = 16;
+
+/// The client sends us information about his neighbors in a cyclic fashion.
+/// This means that after about `N` messages a complete cycle will be completed
+/// and we will obtain information about the full client state.
+///
+/// We are being generous and willing to wait about `Const + 3 * N`
+/// until an edge is removed.
+pub fn max_edge_age(num_edges: usize) -> u128 {
+    BASE_MAX_EDGE_AGE + 3 * (num_edges as u128)
+}
+
+/// Constraints on the routes returned from a routes query.
+#[derive(Debug, Clone)]
+pub struct RouteConstraints<N> {
+    /// These nodes must not show up in the route.
+    pub exclude_nodes: HashSet<N>,
+    /// These directed edges must not show up in the route.
+    pub exclude_edges: HashSet<(N, N)>,
+    /// The route must go through these nodes, in this order.
+    pub waypoints: Vec<N>,
+    /// Maximum amount of nodes in the route, including the source and the destination.
+    pub opt_max_route_len: Option<usize>,
+}
+
+impl<N> Default for RouteConstraints<N>
+where
+    N: Hash + Eq,
+{
+    fn default() -> Self {
+        RouteConstraints {
+            exclude_nodes: HashSet::new(),
+            exclude_edges: HashSet::new(),
+            waypoints: Vec::new(),
+            opt_max_route_len: None,
+        }
+    }
+}
+
+impl<N> RouteConstraints<N>
+where
+    N: Hash + Eq + Clone,
+{
+    /// Check if a route satisfies all the constraints.
+    pub fn allows_route(&self, route: &[N]) -> bool {
+        if let Some(max_route_len) = self.opt_max_route_len {
+            if route.len() > max_route_len {
+                return false;
+            }
+        }
+        if route.iter().any(|node| self.exclude_nodes.contains(node)) {
+            return false;
+        }
+        if route.windows(2).any(|edge| {
+            self.exclude_edges
+                .contains(&(edge[0].clone(), edge[1].clone()))
+        }) {
+            return false;
+        }
+        // The waypoints must show up in the route in order:
+        let mut route_iter = route.iter();
+        self.waypoints
+            .iter()
+            .all(|waypoint| route_iter.any(|node| node == waypoint))
+    }
+}
+
+/// A rate of fees charged by a mediator for forwarding a request.
+pub trait LinearRate {
+    type K;
+
+    /// Calculate the fee for forwarding a request of `k` credits.
+    /// Returns None on overflow.
+    fn calc_fee(&self, k: Self::K) -> Option<Self::K>;
+}
+
+impl LinearRate for Rate {
+    type K = u128;
+
+    fn calc_fee(&self, k: u128) -> Option<u128> {
+        Rate::calc_fee(self, k)
+    }
+}
 
 pub trait CapacityGraph {
     type Node; // Node type
     type Capacity; // Directed capacity between two neighboring nodes
+    type Rate; // The fee a node charges for forwarding requests from a neighbor
 
     /// Add or update edge
     fn update_edge(
         &mut self,
         a: Self::Node,
         b: Self::Node,
-        edge: CapacityEdge<Self::Capacity>,
-    ) -> Option<CapacityEdge<Self::Capacity>>;
+        edge: CapacityEdge<Self::Capacity, Self::Rate>,
+    ) -> Option<CapacityEdge<Self::Capacity, Self::Rate>>;
 
     /// Remove an edge from the graph
     fn remove_edge(
         &mut self,
         a: &Self::Node,
         b: &Self::Node,
-    ) -> Option<CapacityEdge<Self::Capacity>>;
+    ) -> Option<CapacityEdge<Self::Capacity, Self::Rate>>;
 
     /// Remove a node and all related edges known from him.
     /// Note: This method will not remove an edge from another node b pointing to a.
     /// Returns true if the node `a` was present, false otherwise
     fn remove_node(&mut self, a: &Self::Node) -> bool;
 
-    /// Get a route with capacity at least `capacity`.
-    /// Returns the route together with the capacity it is possible to send through the route.
+    /// Get at most `max_routes` routes that can deliver `capacity` to `b`, together with the
+    /// fees paid to the mediators along the way. The routes do not share any directed edge, and
+    /// are ranked by their total fees, cheapest first.
+    /// Returns every route together with the capacity it is possible to send through the route
+    /// and the total fees of the route.
     ///
-    /// opt_exclude is an optional edge to exclude (The returned route must not go through this
-    /// edge). This can be useful for finding non trivial loops.
+    /// All the returned routes satisfy `constraints`.
     fn get_routes(
         &self,
         a: &Self::Node,
         b: &Self::Node,
         capacity: Self::Capacity,
-        opt_exclude: Option<(&Self::Node, &Self::Node)>,
+        constraints: &RouteConstraints<Self::Node>,
+        max_routes: usize,
+    ) -> Vec<CapacityRoute<Self::Node, Self::Capacity>>;
+
+    /// Get routes from `a` to `b` that together carry the maximum flow of credits possible
+    /// between the two nodes.
+    /// Returns every route together with the part of the flow going through the route, and the
+    /// fees paid to the mediators for delivering the largest amount possible through the route.
+    /// This amount is the route's part of the flow minus its fees.
+    ///
+    /// All the returned routes satisfy `constraints`. The excluded nodes and edges are left out
+    /// of the flow, while routes of the flow that are too long or skip a waypoint are dropped.
+    fn get_max_flow_routes(
+        &self,
+        a: &Self::Node,
+        b: &Self::Node,
+        constraints: &RouteConstraints<Self::Node>,
     ) -> Vec<CapacityRoute<Self::Node, Self::Capacity>>;
 
     /// Simulate advancement of time. Used to remove old edges.
     fn tick(&mut self, a: &Self::Node);
+
+    /// Get all the directed edges of the graph, together with their ages.
+    /// Used for saving a snapshot of the graph.
+    fn saved_edges(&self) -> Vec<SavedEdge<Self::Node, Self::Capacity, Self::Rate>>;
+
+    /// Add an edge restored from a snapshot of the graph.
+    /// The edge keeps aging from (at least) the age it had when the snapshot was taken, so that
+    /// stale edges still expire.
+    fn restore_edge(&mut self, saved_edge: SavedEdge<Self::Node, Self::Capacity, Self::Rate>);
+
+    /// Remove all nodes that were only restored from a snapshot, and were not heard of since
+    /// (No edge of the node was updated, and no tick was received for the node).
+    fn remove_restored_nodes(&mut self);
+
+    /// Get the amount of nodes that reported edges
+    fn num_nodes(&self) -> usize;
+
+    /// Get the amount of directed edges in the graph
+    fn num_edges(&self) -> usize;
+
+    /// Get all the edges reported by `a`, together with the node at the end of every edge.
+    fn get_neighbors(
+        &self,
+        a: &Self::Node,
+    ) -> Vec<(Self::Node, CapacityEdge<Self::Capacity, Self::Rate>)>;
 }
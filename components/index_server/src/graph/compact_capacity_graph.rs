@@ -0,0 +1,921 @@
+use std::collections::{HashMap, HashSet};
+use std::{cmp, hash, mem};
+
+use super::capacity_graph::{
+    max_edge_age, CapacityEdge, CapacityGraph, LinearRate, RouteConstraints, SavedEdge,
+    BASE_MAX_EDGE_AGE,
+};
+use super::dijkstra::dense_dijkstra;
+use super::max_flow::dense_max_flow_routes;
+
+/// Nodes are interned: Inside the graph every node is represented by a small integer id.
+/// Route searches work only with ids, so they never have to hash or clone the (possibly large)
+/// node type.
+type NodeId = usize;
+
+struct Edge<R> {
+    /// The node at the end of the edge.
+    remote: NodeId,
+    capacity: (u128, u128),
+    /// The fee charged by the node at the start of the edge, for forwarding requests from the
+    /// node at the end of the edge.
+    rate: R,
+    age: u128,
+}
+
+impl<R> Edge<R> {
+    fn new(remote: NodeId, edge: CapacityEdge<u128, R>) -> Self {
+        let (send_capacity, recv_capacity, rate) = edge;
+        Edge {
+            remote,
+            capacity: (send_capacity, recv_capacity),
+            rate,
+            age: 0,
+        }
+    }
+
+    fn into_capacity_edge(self) -> CapacityEdge<u128, R> {
+        let (send_capacity, recv_capacity) = self.capacity;
+        (send_capacity, recv_capacity, self.rate)
+    }
+}
+
+/// The edges reported by a node, kept in one array sorted by the id of the remote node.
+struct NodeEdges<R> {
+    edges: Vec<Edge<R>>,
+    /// The edges of this node were restored from a snapshot, and nothing was heard from this
+    /// node since.
+    restored: bool,
+}
+
+impl<R> NodeEdges<R> {
+    fn new() -> Self {
+        NodeEdges {
+            edges: Vec::new(),
+            restored: false,
+        }
+    }
+
+    /// Find the index of the edge to `remote`, or the index where such an edge should be
+    /// inserted.
+    fn find(&self, remote: NodeId) -> Result<usize, usize> {
+        self.edges.binary_search_by_key(&remote, |edge| edge.remote)
+    }
+
+    fn get(&self, remote: NodeId) -> Option<&Edge<R>> {
+        let index = self.find(remote).ok()?;
+        Some(&self.edges[index])
+    }
+}
+
+struct NodeEntry<N, R> {
+    node: N,
+    /// The edges reported by the node. None if the node did not report any edges, and is only
+    /// known as the remote side of edges reported by other nodes.
+    opt_node_edges: Option<NodeEdges<R>>,
+    /// Amount of edges reported by other nodes that point to this node.
+    num_in_edges: usize,
+}
+
+/// A capacity graph optimized for large networks.
+///
+/// Nodes are interned into integer ids, and the edges of every node are kept in a compact sorted
+/// array. Updating an edge only touches the array of the reporting node, and the id of a node is
+/// released (and later reused) once no edge refers to the node.
+pub struct CompactCapacityGraph<N, R> {
+    node_ids: HashMap<N, NodeId>,
+    /// The entries of all nodes, indexed by node id.
+    entries: Vec<Option<NodeEntry<N, R>>>,
+    /// Ids of removed nodes, to be reused for new nodes.
+    free_ids: Vec<NodeId>,
+}
+
+impl<N, R> CompactCapacityGraph<N, R>
+where
+    N: cmp::Eq + hash::Hash + Clone + std::fmt::Debug,
+    R: LinearRate<K = u128>,
+{
+    pub fn new() -> CompactCapacityGraph<N, R> {
+        CompactCapacityGraph {
+            node_ids: HashMap::new(),
+            entries: Vec::new(),
+            free_ids: Vec::new(),
+        }
+    }
+
+    /// Get the id of a node, allocating a new id if the node is not known.
+    fn intern(&mut self, node: N) -> NodeId {
+        if let Some(&node_id) = self.node_ids.get(&node) {
+            return node_id;
+        }
+        let node_entry = NodeEntry {
+            node: node.clone(),
+            opt_node_edges: None,
+            num_in_edges: 0,
+        };
+        let node_id = match self.free_ids.pop() {
+            Some(node_id) => {
+                self.entries[node_id] = Some(node_entry);
+                node_id
+            }
+            None => {
+                self.entries.push(Some(node_entry));
+                self.entries.len() - 1
+            }
+        };
+        self.node_ids.insert(node, node_id);
+        node_id
+    }
+
+    fn get_id(&self, node: &N) -> Option<NodeId> {
+        self.node_ids.get(node).cloned()
+    }
+
+    /// Get the entry of an allocated node id
+    fn entry(&self, node_id: NodeId) -> &NodeEntry<N, R> {
+        self.entries[node_id].as_ref().unwrap()
+    }
+
+    fn entry_mut(&mut self, node_id: NodeId) -> &mut NodeEntry<N, R> {
+        self.entries[node_id].as_mut().unwrap()
+    }
+
+    fn node(&self, node_id: NodeId) -> &N {
+        &self.entry(node_id).node
+    }
+
+    /// Get all the edges reported by a node
+    fn node_edges(&self, node_id: NodeId) -> &[Edge<R>] {
+        match &self.entry(node_id).opt_node_edges {
+            Some(node_edges) => &node_edges.edges,
+            None => &[],
+        }
+    }
+
+    /// Iterate over the edges of all the nodes that reported edges
+    fn all_node_edges(&self) -> impl Iterator<Item = &NodeEdges<R>> {
+        self.entries
+            .iter()
+            .filter_map(|opt_node_entry| opt_node_entry.as_ref()?.opt_node_edges.as_ref())
+    }
+
+    /// Get a directed edge (if exists)
+    fn get_edge(&self, a: NodeId, b: NodeId) -> Option<&Edge<R>> {
+        self.entry(a).opt_node_edges.as_ref()?.get(b)
+    }
+
+    /// Release the id of a node if no edge refers to the node anymore.
+    fn release_if_unused(&mut self, node_id: NodeId) {
+        match &self.entries[node_id] {
+            Some(node_entry)
+                if node_entry.opt_node_edges.is_none() && node_entry.num_in_edges == 0 => {}
+            _ => return,
+        }
+        let node_entry = self.entries[node_id].take().unwrap();
+        self.node_ids.remove(&node_entry.node);
+        self.free_ids.push(node_id);
+    }
+
+    /// Account for removed edges pointing to the given remote nodes.
+    fn remove_in_edges(&mut self, remotes: impl Iterator<Item = NodeId>) {
+        for remote in remotes {
+            self.entry_mut(remote).num_in_edges -= 1;
+            self.release_if_unused(remote);
+        }
+    }
+
+    /// Remove all the edges reported by a node.
+    /// Returns true if the node has reported any edges.
+    fn remove_node_id(&mut self, node_id: NodeId) -> bool {
+        let node_edges = match self.entry_mut(node_id).opt_node_edges.take() {
+            Some(node_edges) => node_edges,
+            None => return false,
+        };
+        self.remove_in_edges(node_edges.edges.iter().map(|edge| edge.remote));
+        self.release_if_unused(node_id);
+        true
+    }
+
+    /// Insert an edge reported by `a`, replacing the previous edge from `a` to `b`.
+    fn insert_edge(&mut self, a: NodeId, edge: Edge<R>, restored: bool) -> Option<Edge<R>> {
+        let b = edge.remote;
+        let node_edges = self
+            .entry_mut(a)
+            .opt_node_edges
+            .get_or_insert_with(|| NodeEdges {
+                restored,
+                ..NodeEdges::new()
+            });
+        match node_edges.find(b) {
+            Ok(index) => Some(mem::replace(&mut node_edges.edges[index], edge)),
+            Err(index) => {
+                node_edges.edges.insert(index, edge);
+                self.entry_mut(b).num_in_edges += 1;
+                None
+            }
+        }
+    }
+
+    /// Get the send capacity from `a` to a direct neighbor `b`.
+    /// This is calculated as the minimum send capacity reported by `a` and the maximum recv
+    /// capacity reported by `b`.
+    fn get_send_capacity(&self, a: NodeId, b: NodeId) -> u128 {
+        let (a_b_edge, b_a_edge) = match (self.get_edge(a, b), self.get_edge(b, a)) {
+            (Some(a_b_edge), Some(b_a_edge)) => (a_b_edge, b_a_edge),
+            _ => return 0,
+        };
+
+        let (a_send, _a_recv) = a_b_edge.capacity;
+        let (_b_send, b_recv) = b_a_edge.capacity;
+
+        cmp::min(a_send, b_recv)
+    }
+
+    /// Iterate over the neighbors that can send a request to `b` through a direct edge, together
+    /// with the fee `b` charges them for forwarding the request.
+    ///
+    /// The request should deliver `capacity` credits to `dest`, and carry `left_fees` credits of
+    /// fees for the mediators after `b` on the route. `b` takes no fee if it is the destination.
+    fn senders_with_fees<'a>(
+        &'a self,
+        b: NodeId,
+        dest: NodeId,
+        capacity: u128,
+        left_fees: u128,
+    ) -> impl Iterator<Item = (NodeId, u128)> + 'a {
+        self.node_edges(b).iter().filter_map(move |b_a_edge| {
+            let fee = if b == dest {
+                0
+            } else {
+                b_a_edge.rate.calc_fee(capacity)?
+            };
+            // The amount of credits frozen when sending the request from `a` to `b`:
+            let credits = capacity.checked_add(left_fees)?.checked_add(fee)?;
+            // The edge from `b` to `a` is at hand, so only the edge from `a` to `b` is searched:
+            let (a_send, _a_recv) = self.get_edge(b_a_edge.remote, b)?.capacity;
+            let (_b_send, b_recv) = b_a_edge.capacity;
+            if cmp::min(a_send, b_recv) >= credits {
+                Some((b_a_edge.remote, fee))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Calculate the amount of capacity we can send through a route.
+    /// This amount if the minimum of all edge capacities of the route.
+    fn get_route_capacity(&self, route: &[NodeId]) -> Option<u128> {
+        (0..route.len().checked_sub(1)?)
+            .map(|i| self.get_send_capacity(route[i], route[i + 1]))
+            .min()
+    }
+
+    /// Calculate the total fees paid to the mediators of a route for delivering `amount` credits
+    /// to the end of the route.
+    fn get_route_fees(&self, route: &[NodeId], amount: u128) -> Option<u128> {
+        let mut fees: u128 = 0;
+        // The first and last nodes of the route take no fees:
+        for i in 1..route.len().checked_sub(1)? {
+            let rate = &self.get_edge(route[i], route[i - 1])?.rate;
+            fees = fees.checked_add(rate.calc_fee(amount)?)?;
+        }
+        Some(fees)
+    }
+
+    /// Find the largest amount that can be delivered through a route by sending `credits`
+    /// credits through its first edge.
+    /// Returns the fees paid for delivering this amount, or None if nothing can be delivered.
+    fn get_max_amount_fees(&self, route: &[NodeId], credits: u128) -> Option<u128> {
+        let fits = |amount: u128| match self.get_route_fees(route, amount) {
+            Some(fees) => fees
+                .checked_add(amount)
+                .map_or(false, |total| total <= credits),
+            None => false,
+        };
+        if credits == 0 || !fits(1) {
+            return None;
+        }
+        // Binary search for the largest amount that fits:
+        let (mut low, mut high) = (1, credits);
+        while low < high {
+            let mid = low + (high - low + 1) / 2;
+            if fits(mid) {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        self.get_route_fees(route, low)
+    }
+
+    /// Translate route constraints to node ids.
+    /// Unknown nodes can not show up in any route, so excluding them is a no-op. Returns None if
+    /// one of the waypoints is unknown, as no route can go through it.
+    fn id_constraints(
+        &self,
+        constraints: &RouteConstraints<N>,
+    ) -> Option<RouteConstraints<NodeId>> {
+        let waypoints = constraints
+            .waypoints
+            .iter()
+            .map(|waypoint| self.get_id(waypoint))
+            .collect::<Option<Vec<_>>>()?;
+        Some(RouteConstraints {
+            exclude_nodes: constraints
+                .exclude_nodes
+                .iter()
+                .filter_map(|node| self.get_id(node))
+                .collect(),
+            exclude_edges: constraints
+                .exclude_edges
+                .iter()
+                .filter_map(|(a, b)| Some((self.get_id(a)?, self.get_id(b)?)))
+                .collect(),
+            waypoints,
+            opt_max_route_len: constraints.opt_max_route_len,
+        })
+    }
+
+    /// Translate a route of node ids back to nodes
+    fn route_nodes(&self, route: Vec<NodeId>) -> Vec<N> {
+        route
+            .into_iter()
+            .map(|node_id| self.node(node_id).clone())
+            .collect()
+    }
+
+    /// Get the cheapest route that can deliver `capacity` credits to `b`, together with the fees
+    /// paid to the mediators along the route. Between routes of equal fees, a shorter route is
+    /// preferred.
+    /// Returns the route together with the capacity it is possible to send through the route,
+    /// and the total fees of the route.
+    ///
+    /// The returned route satisfies `constraints`. A route with waypoints is found one segment
+    /// (between two consecutive waypoints) at a time, so it might not be the cheapest route
+    /// possible.
+    fn get_route(
+        &self,
+        a: NodeId,
+        b: NodeId,
+        capacity: u128,
+        constraints: &RouteConstraints<NodeId>,
+    ) -> Option<(Vec<NodeId>, u128, u128)> {
+        let mut stops = vec![a];
+        stops.extend(constraints.waypoints.iter().cloned());
+        stops.push(b);
+
+        // A route may not visit a node twice. In particular, it must contain at least two nodes:
+        let stops_set: HashSet<NodeId> = stops.iter().cloned().collect();
+        if stops_set.len() < stops.len() {
+            return None;
+        }
+        if stops
+            .iter()
+            .any(|stop| constraints.exclude_nodes.contains(stop))
+        {
+            return None;
+        }
+
+        // Nodes that may not show up in the middle of a segment:
+        let mut blocked_nodes = vec![false; self.entries.len()];
+        for &node_id in constraints.exclude_nodes.iter().chain(stops.iter()) {
+            blocked_nodes[node_id] = true;
+        }
+        let max_route_len = constraints.opt_max_route_len.unwrap_or(usize::max_value());
+
+        // We search backwards, from `b` to `a`, because the credits frozen on every edge depend
+        // on the fees of the mediators that come after it on the route:
+        let mut route = vec![b];
+        let mut fees: u128 = 0;
+        for i in (0..stops.len() - 1).rev() {
+            let (segment_start, segment_end) = (stops[i], stops[i + 1]);
+            // Each of the `i` segments left to be found adds at least one node to the route:
+            let max_hops = max_route_len.checked_sub(route.len())?.checked_sub(i)?;
+
+            let segment_fees = fees;
+            let blocked_nodes_ref = &blocked_nodes;
+            let get_senders = |cur_node: NodeId, cost: u128| {
+                let left_fees = segment_fees.saturating_add(cost);
+                self.senders_with_fees(cur_node, b, capacity, left_fees)
+                    .filter(move |&(prev_node, _fee)| {
+                        (prev_node == segment_start || !blocked_nodes_ref[prev_node])
+                            && (constraints.exclude_edges.is_empty()
+                                || !constraints.exclude_edges.contains(&(prev_node, cur_node)))
+                    })
+            };
+            let (segment, cost) = dense_dijkstra(
+                self.entries.len(),
+                segment_end,
+                segment_start,
+                max_hops,
+                get_senders,
+            )?;
+            fees = fees.checked_add(cost)?;
+
+            for node_id in segment.into_iter().skip(1) {
+                blocked_nodes[node_id] = true;
+                route.push(node_id);
+            }
+        }
+        route.reverse();
+        // We assert that we will always have valid capacity here:
+        let capacity = self.get_route_capacity(&route).unwrap();
+
+        Some((route, capacity, fees))
+    }
+}
+
+impl<N, R> Default for CompactCapacityGraph<N, R>
+where
+    N: cmp::Eq + hash::Hash + Clone + std::fmt::Debug,
+    R: LinearRate<K = u128>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N, R> CapacityGraph for CompactCapacityGraph<N, R>
+where
+    N: cmp::Eq + hash::Hash + Clone + std::fmt::Debug,
+    R: LinearRate<K = u128> + Clone,
+{
+    type Node = N;
+    type Capacity = u128;
+    type Rate = R;
+
+    /// Add or update edge
+    fn update_edge(
+        &mut self,
+        a: N,
+        b: N,
+        edge: CapacityEdge<u128, R>,
+    ) -> Option<CapacityEdge<u128, R>> {
+        let a_id = self.intern(a);
+        let b_id = self.intern(b);
+        let opt_old_edge = self.insert_edge(a_id, Edge::new(b_id, edge), false);
+        // We heard from `a`, so it is not considered restored anymore:
+        if let Some(node_edges) = &mut self.entry_mut(a_id).opt_node_edges {
+            node_edges.restored = false;
+        }
+        opt_old_edge.map(Edge::into_capacity_edge)
+    }
+
+    /// Remove an edge from the graph
+    fn remove_edge(&mut self, a: &N, b: &N) -> Option<CapacityEdge<u128, R>> {
+        let a_id = self.get_id(a)?;
+        let b_id = self.get_id(b)?;
+
+        let node_edges = self.entry_mut(a_id).opt_node_edges.as_mut()?;
+        let index = node_edges.find(b_id).ok()?;
+        let old_edge = node_edges.edges.remove(index);
+        if node_edges.edges.is_empty() {
+            self.entry_mut(a_id).opt_node_edges = None;
+        }
+
+        self.remove_in_edges(Some(b_id).into_iter());
+        self.release_if_unused(a_id);
+
+        Some(old_edge.into_capacity_edge())
+    }
+
+    /// Remove a node and all related edges known from him.
+    /// Note: This method will not remove an edge from another node b pointing to a.
+    fn remove_node(&mut self, a: &N) -> bool {
+        match self.get_id(a) {
+            Some(a_id) => self.remove_node_id(a_id),
+            None => false,
+        }
+    }
+
+    fn get_routes(
+        &self,
+        a: &N,
+        b: &N,
+        capacity: u128,
+        constraints: &RouteConstraints<N>,
+        max_routes: usize,
+    ) -> Vec<(Vec<N>, u128, u128)> {
+        let (a_id, b_id) = match (self.get_id(a), self.get_id(b)) {
+            (Some(a_id), Some(b_id)) => (a_id, b_id),
+            _ => return Vec::new(),
+        };
+        let mut constraints = match self.id_constraints(constraints) {
+            Some(constraints) => constraints,
+            None => return Vec::new(),
+        };
+
+        let mut routes = Vec::new();
+        while routes.len() < max_routes {
+            let (route, capacity, fees) = match self.get_route(a_id, b_id, capacity, &constraints) {
+                Some(route) => route,
+                None => break,
+            };
+            // The next routes may not use any of the edges of this route. Every route we find is
+            // at least as expensive as the previous ones:
+            for i in 0..route.len() - 1 {
+                constraints.exclude_edges.insert((route[i], route[i + 1]));
+            }
+            routes.push((self.route_nodes(route), capacity, fees));
+        }
+        routes
+    }
+
+    fn get_max_flow_routes(
+        &self,
+        a: &N,
+        b: &N,
+        constraints: &RouteConstraints<N>,
+    ) -> Vec<(Vec<N>, u128, u128)> {
+        let (a_id, b_id) = match (self.get_id(a), self.get_id(b)) {
+            (Some(a_id), Some(b_id)) => (a_id, b_id),
+            _ => return Vec::new(),
+        };
+        let constraints = match self.id_constraints(constraints) {
+            Some(constraints) => constraints,
+            None => return Vec::new(),
+        };
+
+        let mut capacities = Vec::new();
+        for x in 0..self.entries.len() {
+            if self.entries[x].is_none() || constraints.exclude_nodes.contains(&x) {
+                continue;
+            }
+            for edge in self.node_edges(x) {
+                let y = edge.remote;
+                if constraints.exclude_nodes.contains(&y)
+                    || constraints.exclude_edges.contains(&(x, y))
+                {
+                    continue;
+                }
+                let send_capacity = self.get_send_capacity(x, y);
+                if send_capacity > 0 {
+                    capacities.push((x, y, send_capacity));
+                }
+            }
+        }
+
+        // Routes that can not deliver any credits after paying the fees are dropped:
+        dense_max_flow_routes(self.entries.len(), a_id, b_id, &capacities)
+            .into_iter()
+            .filter(|(route, _flow)| constraints.allows_route(route))
+            .filter_map(|(route, flow)| {
+                let fees = self.get_max_amount_fees(&route, flow)?;
+                Some((self.route_nodes(route), flow, fees))
+            })
+            .collect()
+    }
+
+    fn tick(&mut self, a: &N) {
+        let a_id = match self.get_id(a) {
+            Some(a_id) => a_id,
+            None => return,
+        };
+        let node_edges = match &mut self.entry_mut(a_id).opt_node_edges {
+            Some(node_edges) => node_edges,
+            None => return,
+        };
+
+        // We heard from this node, so it is not considered restored anymore:
+        node_edges.restored = false;
+        let max_edge_age = max_edge_age(node_edges.edges.len());
+
+        let mut removed_remotes = Vec::new();
+        for edge in &mut node_edges.edges {
+            edge.age = edge.age.saturating_add(1);
+            if edge.age >= max_edge_age {
+                removed_remotes.push(edge.remote);
+            }
+        }
+        node_edges.edges.retain(|edge| edge.age < max_edge_age);
+
+        self.remove_in_edges(removed_remotes.into_iter());
+    }
+
+    fn saved_edges(&self) -> Vec<SavedEdge<N, u128, R>> {
+        let mut saved_edges = Vec::new();
+        for (node_id, opt_node_entry) in self.entries.iter().enumerate() {
+            let node_entry = match opt_node_entry {
+                Some(node_entry) => node_entry,
+                None => continue,
+            };
+            for edge in self.node_edges(node_id) {
+                let (send_capacity, recv_capacity) = edge.capacity;
+                saved_edges.push((
+                    node_entry.node.clone(),
+                    self.node(edge.remote).clone(),
+                    (send_capacity, recv_capacity, edge.rate.clone()),
+                    edge.age,
+                ));
+            }
+        }
+        saved_edges
+    }
+
+    fn restore_edge(&mut self, saved_edge: SavedEdge<N, u128, R>) {
+        let (a, b, capacity_edge, age) = saved_edge;
+        let a_id = self.intern(a);
+        let b_id = self.intern(b);
+        let mut edge = Edge::new(b_id, capacity_edge);
+        // We don't know how long the edge was kept on disk, so we consider it to be at least
+        // `BASE_MAX_EDGE_AGE` ticks old. It will live about as long as it takes for the node to
+        // send us information about all of its friends again:
+        edge.age = cmp::max(age, BASE_MAX_EDGE_AGE);
+        self.insert_edge(a_id, edge, true);
+    }
+
+    fn remove_restored_nodes(&mut self) {
+        let restored_ids = (0..self.entries.len())
+            .filter(|&node_id| match &self.entries[node_id] {
+                Some(NodeEntry {
+                    opt_node_edges: Some(node_edges),
+                    ..
+                }) => node_edges.restored,
+                _ => false,
+            })
+            .collect::<Vec<_>>();
+        for node_id in restored_ids {
+            self.remove_node_id(node_id);
+        }
+    }
+
+    fn num_nodes(&self) -> usize {
+        self.all_node_edges().count()
+    }
+
+    fn num_edges(&self) -> usize {
+        self.all_node_edges()
+            .map(|node_edges| node_edges.edges.len())
+            .sum()
+    }
+
+    fn get_neighbors(&self, a: &N) -> Vec<(N, CapacityEdge<u128, R>)> {
+        let a_id = match self.get_id(a) {
+            Some(a_id) => a_id,
+            None => return Vec::new(),
+        };
+        self.node_edges(a_id)
+            .iter()
+            .map(|edge| {
+                let (send_capacity, recv_capacity) = edge.capacity;
+                (
+                    self.node(edge.remote).clone(),
+                    (send_capacity, recv_capacity, edge.rate.clone()),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::simple_capacity_graph::SimpleCapacityGraph;
+    use super::*;
+    use proto::funder::messages::Rate;
+
+    /// A rate of no fees at all
+    fn zero_rate() -> Rate {
+        Rate { mul: 0, add: 0 }
+    }
+
+    #[test]
+    fn test_add_remove_edge() {
+        let mut cg = CompactCapacityGraph::<u32, Rate>::new();
+        assert_eq!(cg.remove_edge(&0, &1), None);
+        cg.update_edge(0, 1, (10, 20, zero_rate()));
+        cg.update_edge(1, 0, (15, 5, zero_rate()));
+        assert_eq!(cg.node_ids.len(), 2);
+
+        let (id0, id1) = (cg.get_id(&0).unwrap(), cg.get_id(&1).unwrap());
+        assert_eq!(cg.get_send_capacity(id0, id1), cmp::min(5, 10));
+        assert_eq!(cg.get_send_capacity(id1, id0), cmp::min(15, 20));
+
+        assert_eq!(
+            cg.update_edge(0, 1, (11, 20, zero_rate())),
+            Some((10, 20, zero_rate()))
+        );
+        assert_eq!(cg.remove_edge(&0, &1), Some((11, 20, zero_rate())));
+        assert_eq!(cg.get_send_capacity(id1, id0), 0);
+        // 0 is still pointed to by 1:
+        assert_eq!(cg.node_ids.len(), 2);
+
+        assert!(cg.remove_node(&1));
+        assert!(!cg.remove_node(&1));
+        assert!(cg.node_ids.is_empty());
+
+        // Ids of removed nodes are reused:
+        cg.update_edge(2, 3, (10, 20, zero_rate()));
+        assert_eq!(cg.node_ids.len(), 2);
+        assert_eq!(cg.entries.len(), 2);
+    }
+
+    #[test]
+    fn test_self_edge() {
+        let mut cg = CompactCapacityGraph::<u32, Rate>::new();
+        cg.update_edge(0, 0, (10, 20, zero_rate()));
+        assert!(cg.remove_node(&0));
+        assert!(cg.node_ids.is_empty());
+        assert_eq!(cg.free_ids, vec![0]);
+    }
+
+    #[test]
+    fn test_get_neighbors() {
+        let mut cg = CompactCapacityGraph::<u32, Rate>::new();
+        cg.update_edge(1, 2, (10, 20, zero_rate()));
+        cg.update_edge(1, 0, (30, 40, zero_rate()));
+        cg.update_edge(2, 1, (20, 10, zero_rate()));
+        assert_eq!(cg.num_nodes(), 2);
+        assert_eq!(cg.num_edges(), 3);
+
+        let mut neighbors = cg.get_neighbors(&1);
+        neighbors.sort_by_key(|(b, _edge)| *b);
+        assert_eq!(
+            neighbors,
+            vec![(0, (30, 40, zero_rate())), (2, (10, 20, zero_rate()))]
+        );
+        // 0 did not report any edges:
+        assert!(cg.get_neighbors(&0).is_empty());
+        assert!(cg.get_neighbors(&3).is_empty());
+    }
+
+    /// Deterministic pseudo random numbers (xorshift), used to generate graphs
+    fn next_rand(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    /// Add the same pseudo random edges to two capacity graphs.
+    /// Every mediator charges a constant fee of at most `max_fee` credits.
+    fn add_random_edges<A, B>(
+        cga: &mut A,
+        cgb: &mut B,
+        num_nodes: u64,
+        num_edges: usize,
+        max_fee: u64,
+    ) where
+        A: CapacityGraph<Node = u32, Capacity = u128, Rate = Rate>,
+        B: CapacityGraph<Node = u32, Capacity = u128, Rate = Rate>,
+    {
+        let mut state = 0x1337_u64;
+        for _ in 0..num_edges {
+            let a = (next_rand(&mut state) % num_nodes) as u32;
+            let b = (next_rand(&mut state) % num_nodes) as u32;
+            let send = u128::from(next_rand(&mut state) % 100);
+            let recv = u128::from(next_rand(&mut state) % 100);
+            let rate = Rate {
+                mul: 0,
+                add: (next_rand(&mut state) % (max_fee + 1)) as u32,
+            };
+            cga.update_edge(a, b, (send, recv, rate.clone()));
+            cgb.update_edge(a, b, (send, recv, rate));
+        }
+    }
+
+    #[test]
+    fn test_compare_routes_with_simple_capacity_graph() {
+        let mut cg = CompactCapacityGraph::<u32, Rate>::new();
+        let mut simple_cg = SimpleCapacityGraph::<u32, Rate>::new();
+        add_random_edges(&mut cg, &mut simple_cg, 30, 300, 3);
+
+        let mut constraints = RouteConstraints::default();
+        constraints.exclude_nodes.insert(7);
+        constraints.opt_max_route_len = Some(5);
+
+        for a in 0..10 {
+            for b in 20..30 {
+                // Routes of equal fees might be chosen differently, which affects the next
+                // routes. Only the first (cheapest) route is compared:
+                let routes = cg.get_routes(&a, &b, 5, &constraints, 1);
+                let simple_routes = simple_cg.get_routes(&a, &b, 5, &constraints, 1);
+                assert_eq!(routes.len(), simple_routes.len());
+                for ((route, _, fees), (_, _, simple_fees)) in routes.iter().zip(&simple_routes) {
+                    assert!(constraints.allows_route(route));
+                    assert_eq!(fees, simple_fees);
+                }
+            }
+        }
+
+        let mut saved_edges = cg.saved_edges();
+        let mut simple_saved_edges = simple_cg.saved_edges();
+        saved_edges.sort_by_key(|(a, b, _edge, _age)| (*a, *b));
+        simple_saved_edges.sort_by_key(|(a, b, _edge, _age)| (*a, *b));
+        assert_eq!(saved_edges, simple_saved_edges);
+    }
+
+    #[test]
+    fn test_compare_max_flow_with_simple_capacity_graph() {
+        let mut cg = CompactCapacityGraph::<u32, Rate>::new();
+        let mut simple_cg = SimpleCapacityGraph::<u32, Rate>::new();
+        // Without fees, the routes of the flow are never dropped:
+        add_random_edges(&mut cg, &mut simple_cg, 30, 300, 0);
+
+        let mut constraints = RouteConstraints::default();
+        constraints.exclude_nodes.insert(7);
+
+        for a in 0..10 {
+            for b in 20..30 {
+                let flow: u128 = cg
+                    .get_max_flow_routes(&a, &b, &constraints)
+                    .iter()
+                    .map(|(_, flow, _)| flow)
+                    .sum();
+                let simple_flow: u128 = simple_cg
+                    .get_max_flow_routes(&a, &b, &constraints)
+                    .iter()
+                    .map(|(_, flow, _)| flow)
+                    .sum();
+                assert_eq!(flow, simple_flow);
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_routes_unknown_nodes() {
+        let mut cg = CompactCapacityGraph::<u32, Rate>::new();
+        cg.update_edge(0, 1, (30, 10, zero_rate()));
+        cg.update_edge(1, 0, (10, 30, zero_rate()));
+
+        assert_eq!(
+            cg.get_routes(&0, &1, 10, &RouteConstraints::default(), 4),
+            vec![(vec![0, 1], 30, 0)]
+        );
+        assert_eq!(
+            cg.get_routes(&0, &2, 10, &RouteConstraints::default(), 4),
+            vec![]
+        );
+        assert_eq!(
+            cg.get_max_flow_routes(&2, &1, &RouteConstraints::default()),
+            vec![]
+        );
+
+        // Excluding an unknown node has no effect:
+        let mut constraints = RouteConstraints::default();
+        constraints.exclude_nodes.insert(2);
+        assert_eq!(
+            cg.get_routes(&0, &1, 10, &constraints, 4),
+            vec![(vec![0, 1], 30, 0)]
+        );
+        // No route goes through an unknown waypoint:
+        let mut constraints = RouteConstraints::default();
+        constraints.waypoints = vec![2];
+        assert_eq!(cg.get_routes(&0, &1, 10, &constraints, 4), vec![]);
+    }
+
+    #[test]
+    fn test_saved_edges_restore() {
+        let mut cg = CompactCapacityGraph::<u32, Rate>::new();
+        cg.update_edge(0, 1, (30, 10, zero_rate()));
+        cg.update_edge(1, 0, (10, 30, zero_rate()));
+        cg.update_edge(1, 2, (30, 10, zero_rate()));
+        cg.update_edge(2, 1, (10, 30, zero_rate()));
+
+        let mut restored_cg = CompactCapacityGraph::<u32, Rate>::new();
+        for saved_edge in cg.saved_edges() {
+            restored_cg.restore_edge(saved_edge);
+        }
+        assert_eq!(
+            restored_cg.get_routes(&0, &2, 10, &RouteConstraints::default(), 4),
+            vec![(vec![0, 1, 2], 30, 0)]
+        );
+
+        // Restored edges are considered aged:
+        for (_a, _b, _edge, age) in restored_cg.saved_edges() {
+            assert_eq!(age, BASE_MAX_EDGE_AGE);
+        }
+
+        // We only hear from node 1:
+        restored_cg.tick(&1);
+        restored_cg.remove_restored_nodes();
+
+        let saved_edges = restored_cg.saved_edges();
+        assert_eq!(saved_edges.len(), 2);
+        assert!(saved_edges.iter().all(|(a, _b, _edge, _age)| *a == 1));
+    }
+
+    #[test]
+    fn test_compact_capacity_graph_tick() {
+        let mut cg = CompactCapacityGraph::<u32, Rate>::new();
+
+        cg.update_edge(0, 1, (30, 10, zero_rate()));
+        cg.update_edge(1, 0, (10, 30, zero_rate()));
+
+        let max_edge_age = max_edge_age(1);
+        for _ in 0..max_edge_age - 1 {
+            cg.tick(&0);
+        }
+        assert_eq!(
+            cg.get_routes(&0, &1, 30, &RouteConstraints::default(), 1),
+            vec![(vec![0, 1], 30, 0)]
+        );
+
+        // At this point 0->1 should expire:
+        cg.tick(&0);
+        assert_eq!(
+            cg.get_routes(&0, &1, 30, &RouteConstraints::default(), 1),
+            vec![]
+        );
+        assert_eq!(cg.saved_edges().len(), 1);
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::{cmp, hash};
+
+/// A node waiting to be visited, together with the cost and amount of hops of the cheapest
+/// route found to it so far.
+struct QueueEntry<N> {
+    cost: u128,
+    hops: usize,
+    node: N,
+}
+
+impl<N> QueueEntry<N> {
+    fn key(&self) -> (u128, usize) {
+        (self.cost, self.hops)
+    }
+}
+
+impl<N> PartialEq for QueueEntry<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl<N> Eq for QueueEntry<N> {}
+
+impl<N> PartialOrd for QueueEntry<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<N> Ord for QueueEntry<N> {
+    /// Reversed order: BinaryHeap is a max heap, and we want to pop the cheapest entry first.
+    /// Between entries of equal cost, the one with less hops is popped first.
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        other.key().cmp(&self.key())
+    }
+}
+
+fn dijkstra_loop<'c, I, N, F>(
+    src: &'c N,
+    dst: &'c N,
+    max_hops: usize,
+    get_neighbors: F,
+) -> Option<(HashMap<N, Option<N>>, u128)>
+where
+    I: Iterator<Item = (&'c N, u128)>,
+    F: Fn(&N, u128) -> I,
+    N: Clone + cmp::Eq + hash::Hash,
+{
+    let mut backtrack: HashMap<N, Option<N>> = HashMap::new();
+    let mut best: HashMap<N, (u128, usize)> = HashMap::new();
+    let mut visited: HashSet<N> = HashSet::new();
+    let mut queue: BinaryHeap<QueueEntry<N>> = BinaryHeap::new();
+
+    backtrack.insert(src.clone(), None);
+    best.insert(src.clone(), (0, 0));
+    queue.push(QueueEntry {
+        cost: 0,
+        hops: 0,
+        node: src.clone(),
+    });
+
+    while let Some(QueueEntry { cost, hops, node }) = queue.pop() {
+        if visited.contains(&node) {
+            // A cheaper route to this node was already handled:
+            continue;
+        }
+        if &node == dst {
+            return Some((backtrack, cost));
+        }
+        visited.insert(node.clone());
+        if hops >= max_hops {
+            continue;
+        }
+
+        for (neighbor, edge_cost) in get_neighbors(&node, cost) {
+            if visited.contains(neighbor) {
+                continue;
+            }
+            let new_cost = match cost.checked_add(edge_cost) {
+                Some(new_cost) => new_cost,
+                None => continue,
+            };
+            let new_key = (new_cost, hops + 1);
+            if let Some(old_key) = best.get(neighbor) {
+                if *old_key <= new_key {
+                    continue;
+                }
+            }
+            best.insert(neighbor.clone(), new_key);
+            backtrack.insert(neighbor.clone(), Some(node.clone()));
+            queue.push(QueueEntry {
+                cost: new_cost,
+                hops: hops + 1,
+                node: neighbor.clone(),
+            });
+        }
+    }
+    None
+}
+
+fn dijkstra_backtrack<N>(dst: &N, backtrack: &HashMap<N, Option<N>>) -> Option<Vec<N>>
+where
+    N: Clone + cmp::Eq + hash::Hash,
+{
+    let mut route = Vec::new();
+
+    route.push(dst.clone());
+    let mut node = dst;
+
+    while let Some(new_node) = backtrack.get(node)? {
+        route.push(new_node.clone());
+        node = new_node;
+    }
+
+    route.reverse();
+    Some(route)
+}
+
+/// Find the cheapest route from `src` to `dst`. Between routes of equal cost, a route with less
+/// hops is preferred. Returns the route together with its total cost.
+///
+/// `get_neighbors` is called with a node and the cost of the cheapest route from `src` to this
+/// node. It returns the neighbors of the node, each with the cost of moving to that neighbor.
+///
+/// Routes are not extended beyond `max_hops` hops. Every node is only reached through its
+/// cheapest route, so a longer but cheaper route to a node might hide a route to `dst` that is
+/// short enough.
+pub fn dijkstra<'c, I, N, F>(
+    src: &'c N,
+    dst: &'c N,
+    max_hops: usize,
+    get_neighbors: F,
+) -> Option<(Vec<N>, u128)>
+where
+    I: Iterator<Item = (&'c N, u128)>,
+    F: Fn(&N, u128) -> I,
+    N: Clone + cmp::Eq + hash::Hash,
+{
+    let (backtrack, cost) = dijkstra_loop(src, dst, max_hops, get_neighbors)?;
+    Some((dijkstra_backtrack(dst, &backtrack)?, cost))
+}
+
+/// Find the cheapest route from `src` to `dst` in a graph whose nodes are the integers
+/// `0..num_nodes`. Works exactly like `dijkstra()`, but keeps the state of the search in arrays
+/// indexed by node, instead of hash maps. This is faster for graphs of interned nodes.
+pub fn dense_dijkstra<I, F>(
+    num_nodes: usize,
+    src: usize,
+    dst: usize,
+    max_hops: usize,
+    get_neighbors: F,
+) -> Option<(Vec<usize>, u128)>
+where
+    I: Iterator<Item = (usize, u128)>,
+    F: Fn(usize, u128) -> I,
+{
+    let mut backtrack: Vec<Option<usize>> = vec![None; num_nodes];
+    let mut best: Vec<Option<(u128, usize)>> = vec![None; num_nodes];
+    let mut visited: Vec<bool> = vec![false; num_nodes];
+    let mut queue: BinaryHeap<QueueEntry<usize>> = BinaryHeap::new();
+
+    best[src] = Some((0, 0));
+    queue.push(QueueEntry {
+        cost: 0,
+        hops: 0,
+        node: src,
+    });
+
+    while let Some(QueueEntry { cost, hops, node }) = queue.pop() {
+        if visited[node] {
+            // A cheaper route to this node was already handled:
+            continue;
+        }
+        if node == dst {
+            let mut route = vec![dst];
+            while let Some(prev_node) = backtrack[*route.last().unwrap()] {
+                route.push(prev_node);
+            }
+            route.reverse();
+            return Some((route, cost));
+        }
+        visited[node] = true;
+        if hops >= max_hops {
+            continue;
+        }
+
+        for (neighbor, edge_cost) in get_neighbors(node, cost) {
+            if visited[neighbor] {
+                continue;
+            }
+            let new_cost = match cost.checked_add(edge_cost) {
+                Some(new_cost) => new_cost,
+                None => continue,
+            };
+            let new_key = (new_cost, hops + 1);
+            if let Some(old_key) = best[neighbor] {
+                if old_key <= new_key {
+                    continue;
+                }
+            }
+            best[neighbor] = Some(new_key);
+            backtrack[neighbor] = Some(node);
+            queue.push(QueueEntry {
+                cost: new_cost,
+                hops: hops + 1,
+                node: neighbor,
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dijkstra_backtrack_basic() {
+        let mut backtrack: HashMap<u32, Option<u32>> = HashMap::new();
+        /*
+         *    3 -- 4
+         *     \-- 5 -- 7
+         *     \-- 6 -- 8 -- 9 -- 10
+         *                   \-- 11
+         *
+         */
+        backtrack.insert(3, None);
+        backtrack.insert(4, Some(3));
+        backtrack.insert(5, Some(3));
+        backtrack.insert(6, Some(3));
+        backtrack.insert(7, Some(5));
+        backtrack.insert(8, Some(6));
+        backtrack.insert(9, Some(8));
+        backtrack.insert(10, Some(9));
+        backtrack.insert(11, Some(9));
+
+        let opt_route = dijkstra_backtrack(&11, &backtrack);
+        assert_eq!(opt_route.unwrap(), vec![3, 6, 8, 9, 11]);
+    }
+
+    #[test]
+    fn test_dijkstra_backtrack_failure() {
+        let mut backtrack: HashMap<u32, Option<u32>> = HashMap::new();
+        backtrack.insert(2, Some(1));
+        backtrack.insert(3, Some(2));
+
+        // Backtracking should fail, because 1 is not a key at the backtrack map:
+        assert!(dijkstra_backtrack(&3, &backtrack).is_none());
+    }
+
+    #[test]
+    fn test_dijkstra_unit_costs() {
+        /*
+         Example graph:
+                            0 --> 1
+                            ^     |
+                            |     |
+               9            |     V
+               ^            3 <-- 2
+               |            |
+               |            V
+               8 <-- 6 <--- 4 --> 5
+                     ^
+                     |
+                     V
+                     7
+        */
+
+        let mut graph = HashMap::new();
+        graph.insert(0u32, vec![(1u32, 1u128)]);
+        graph.insert(1, vec![(2, 1)]);
+        graph.insert(2, vec![(3, 1)]);
+        graph.insert(3, vec![(0, 1), (4, 1)]);
+        graph.insert(4, vec![(5, 1), (6, 1)]);
+        graph.insert(5, vec![]);
+        graph.insert(6, vec![(7, 1), (8, 1)]);
+        graph.insert(7, vec![(6, 1)]);
+        graph.insert(8, vec![(9, 1)]);
+        graph.insert(9, vec![]);
+
+        let get_neighbors = |node: &u32, _cost: u128| {
+            graph
+                .get(node)
+                .unwrap()
+                .iter()
+                .map(|(neighbor, cost)| (neighbor, *cost))
+        };
+        assert_eq!(
+            dijkstra(&0, &1, usize::max_value(), get_neighbors),
+            Some((vec![0, 1], 1))
+        );
+        assert_eq!(
+            dijkstra(&1, &0, usize::max_value(), get_neighbors),
+            Some((vec![1, 2, 3, 0], 3))
+        );
+
+        assert_eq!(
+            dijkstra(&0, &9, usize::max_value(), get_neighbors),
+            Some((vec![0, 1, 2, 3, 4, 6, 8, 9], 7))
+        );
+
+        assert_eq!(dijkstra(&8, &6, usize::max_value(), get_neighbors), None);
+        assert_eq!(dijkstra(&9, &8, usize::max_value(), get_neighbors), None);
+        assert_eq!(dijkstra(&5, &4, usize::max_value(), get_neighbors), None);
+        assert_eq!(dijkstra(&4, &3, usize::max_value(), get_neighbors), None);
+
+        assert_eq!(
+            dijkstra(&6, &7, usize::max_value(), get_neighbors),
+            Some((vec![6, 7], 1))
+        );
+        assert_eq!(
+            dijkstra(&7, &6, usize::max_value(), get_neighbors),
+            Some((vec![7, 6], 1))
+        );
+    }
+
+    #[test]
+    fn test_dijkstra_weighted() {
+        /*
+         Example graph (Edge costs in brackets):
+
+                 [5]
+            0 ---------> 3
+            |            ^
+         [1]|            |[1]
+            V            |
+            1 ---------> 2
+                 [1]
+        */
+
+        let mut graph = HashMap::new();
+        graph.insert(0u32, vec![(3u32, 5u128), (1, 1)]);
+        graph.insert(1, vec![(2, 1)]);
+        graph.insert(2, vec![(3, 1)]);
+        graph.insert(3, vec![]);
+
+        let get_neighbors = |node: &u32, _cost: u128| {
+            graph
+                .get(node)
+                .unwrap()
+                .iter()
+                .map(|(neighbor, cost)| (neighbor, *cost))
+        };
+        // The longer route is cheaper:
+        assert_eq!(
+            dijkstra(&0, &3, usize::max_value(), get_neighbors),
+            Some((vec![0, 1, 2, 3], 3))
+        );
+
+        // Between routes of equal cost, the shorter route is chosen:
+        graph.insert(0, vec![(3, 3), (1, 1)]);
+        let get_neighbors = |node: &u32, _cost: u128| {
+            graph
+                .get(node)
+                .unwrap()
+                .iter()
+                .map(|(neighbor, cost)| (neighbor, *cost))
+        };
+        assert_eq!(
+            dijkstra(&0, &3, usize::max_value(), get_neighbors),
+            Some((vec![0, 3], 3))
+        );
+
+        // Neighbors may depend on the cost of the route so far.
+        // Here the edge 2 -> 3 may only be used by routes of cost at most 1, so the cheap route
+        // can not be used:
+        graph.insert(0, vec![(3, 5), (1, 1)]);
+        let get_neighbors = |node: &u32, cost: u128| {
+            let node = *node;
+            graph
+                .get(&node)
+                .unwrap()
+                .iter()
+                .filter(move |(neighbor, _)| node != 2 || *neighbor != 3 || cost <= 1)
+                .map(|(neighbor, cost)| (neighbor, *cost))
+        };
+        assert_eq!(
+            dijkstra(&0, &3, usize::max_value(), get_neighbors),
+            Some((vec![0, 3], 5))
+        );
+
+        // The cheap route is too long:
+        graph.insert(0, vec![(3, 5), (1, 1)]);
+        let get_neighbors = |node: &u32, _cost: u128| {
+            graph
+                .get(node)
+                .unwrap()
+                .iter()
+                .map(|(neighbor, cost)| (neighbor, *cost))
+        };
+        assert_eq!(
+            dijkstra(&0, &3, 3, get_neighbors),
+            Some((vec![0, 1, 2, 3], 3))
+        );
+        assert_eq!(dijkstra(&0, &3, 2, get_neighbors), Some((vec![0, 3], 5)));
+        assert_eq!(dijkstra(&0, &3, 0, get_neighbors), None);
+    }
+
+    #[test]
+    fn test_dense_dijkstra() {
+        /*
+         Example graph (Edge costs in brackets):
+
+                 [3]
+            0 ---------> 3 ---> 4
+            |            ^  [1]
+         [1]|            |[1]
+            V            |
+            1 ---------> 2
+                 [1]
+        */
+
+        let graph: Vec<Vec<(usize, u128)>> = vec![
+            vec![(3, 3), (1, 1)],
+            vec![(2, 1)],
+            vec![(3, 1)],
+            vec![(4, 1)],
+            vec![],
+        ];
+
+        let get_neighbors = |node: usize, _cost: u128| graph[node].iter().cloned();
+        // Between routes of equal cost, the shorter route is chosen:
+        assert_eq!(
+            dense_dijkstra(5, 0, 4, usize::max_value(), get_neighbors),
+            Some((vec![0, 3, 4], 4))
+        );
+        assert_eq!(
+            dense_dijkstra(5, 1, 4, usize::max_value(), get_neighbors),
+            Some((vec![1, 2, 3, 4], 3))
+        );
+        assert_eq!(dense_dijkstra(5, 1, 4, 2, get_neighbors), None);
+        assert_eq!(
+            dense_dijkstra(5, 4, 0, usize::max_value(), get_neighbors),
+            None
+        );
+        assert_eq!(
+            dense_dijkstra(5, 2, 2, usize::max_value(), get_neighbors),
+            Some((vec![2], 0))
+        );
+
+        // Neighbors may depend on the cost of the route so far:
+        let get_neighbors = |node: usize, cost: u128| {
+            graph[node]
+                .iter()
+                .cloned()
+                .filter(move |(neighbor, _)| node != 3 || *neighbor != 4 || cost <= 1)
+        };
+        assert_eq!(
+            dense_dijkstra(5, 1, 4, usize::max_value(), get_neighbors),
+            None
+        );
+    }
+}
@@ -1,33 +1,75 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
 use futures::channel::{mpsc, oneshot};
 use futures::task::{Spawn, SpawnError, SpawnExt};
 use futures::{FutureExt, SinkExt, StreamExt, TryFutureExt};
 
-use super::capacity_graph::{CapacityEdge, CapacityGraph, CapacityRoute};
+use super::capacity_graph::{
+    CapacityEdge, CapacityGraph, CapacityRoute, RouteConstraints, SavedEdge,
+};
+
+/// A snapshot of the capacity graphs of all currencies.
+pub type GraphSnapshot<T, N, C, R> = HashMap<T, Vec<SavedEdge<N, C, R>>>;
+
+/// The size of the capacity graph of a currency: (currency, num_nodes, num_edges)
+pub type GraphStats<T> = (T, usize, usize);
 
-pub enum GraphRequest<N, C> {
-    /// Change capacities on a directed edge:
+/// Requests to the graph service.
+/// Every currency has its own separate capacity graph. `T` is the currency type.
+/// `R` is the type of the rates mediators charge for forwarding requests.
+pub enum GraphRequest<T, N, C, R> {
+    /// Change capacities (and rate) on a directed edge of a certain currency:
     UpdateEdge(
+        T,
         N,
         N,
-        CapacityEdge<C>,
-        oneshot::Sender<Option<CapacityEdge<C>>>,
+        CapacityEdge<C, R>,
+        oneshot::Sender<Option<CapacityEdge<C, R>>>,
     ),
-    /// Remove a directed edge:
-    RemoveEdge(N, N, oneshot::Sender<Option<CapacityEdge<C>>>),
-    /// Remove a node and all edges starting from this node.
+    /// Remove a directed edge of a certain currency:
+    RemoveEdge(T, N, N, oneshot::Sender<Option<CapacityEdge<C, R>>>),
+    /// Remove a directed edge from the graphs of all currencies.
+    /// Returns true if the edge was present in any of the graphs.
+    RemoveEdgeAllCurrencies(N, N, oneshot::Sender<bool>),
+    /// Remove a node and all edges starting from this node (In all currencies).
     /// Note: This will not remove edges going to this node.
     RemoveNode(N, oneshot::Sender<bool>),
-    /// Get some routes from one node to another of at least certain capacity.
-    /// If an exclude directed edge is provided, the routes must not contain this directed edge.
+    /// Get some routes from one node to another that can deliver a certain capacity (together
+    /// with the fees paid to the mediators), in a certain currency.
+    /// The routes must satisfy the given route constraints.
     GetRoutes(
+        T,
         N,
         N,
         C,
-        Option<(N, N)>,
+        RouteConstraints<N>,
+        usize,
         oneshot::Sender<Vec<CapacityRoute<N, C>>>,
-    ), // (from, to, capacity, opt_exclude)
-    /// Expire old outgoing edges for the specified node
+    ), // (currency, from, to, capacity, constraints, max_routes)
+    /// Get routes from one node to another that together carry the maximum flow possible in a
+    /// certain currency.
+    /// The routes must satisfy the given route constraints.
+    GetMaxFlowRoutes(
+        T,
+        N,
+        N,
+        RouteConstraints<N>,
+        oneshot::Sender<Vec<CapacityRoute<N, C>>>,
+    ), // (currency, from, to, constraints)
+    /// Expire old outgoing edges for the specified node (In all currencies)
     Tick(N, oneshot::Sender<()>),
+    /// Get a snapshot of the graphs of all currencies
+    Snapshot(oneshot::Sender<GraphSnapshot<T, N, C, R>>),
+    /// Remove all nodes that were restored from a snapshot and were not heard of since
+    /// (In all currencies)
+    RemoveRestoredNodes(oneshot::Sender<()>),
+    /// Get the size of the graphs of all currencies
+    Stats(oneshot::Sender<Vec<GraphStats<T>>>),
+    /// Get all the edges reported by a node, in a certain currency
+    GetNeighbors(T, N, oneshot::Sender<Vec<(N, CapacityEdge<C, R>)>>),
+    /// Get all the edges reported by a node (In all currencies)
+    GetNodeEdges(N, oneshot::Sender<Vec<(T, N, CapacityEdge<C, R>)>>),
 }
 
 #[derive(Debug)]
@@ -38,43 +80,139 @@ pub enum GraphServiceError {
 
 /// Process one GraphRequest, and send the response through the provided sender.
 /// This function might perform a long computation and take a long time to complete.
-fn process_request<N, C, CG>(capacity_graph: &mut CG, graph_request: GraphRequest<N, C>)
-where
-    CG: CapacityGraph<Node = N, Capacity = C>,
+fn process_request<T, N, C, R, CG>(
+    capacity_graphs: &mut HashMap<T, CG>,
+    graph_request: GraphRequest<T, N, C, R>,
+) where
+    T: Eq + Hash + Clone,
+    CG: CapacityGraph<Node = N, Capacity = C, Rate = R> + Default,
 {
     match graph_request {
-        GraphRequest::UpdateEdge(a, b, capacity_edge, sender) => {
+        GraphRequest::UpdateEdge(currency, a, b, capacity_edge, sender) => {
+            let capacity_graph = capacity_graphs.entry(currency).or_insert_with(CG::default);
             let _ = sender.send(capacity_graph.update_edge(a, b, capacity_edge));
         }
-        GraphRequest::RemoveEdge(a, b, sender) => {
-            let _ = sender.send(capacity_graph.remove_edge(&a, &b));
+        GraphRequest::RemoveEdge(currency, a, b, sender) => {
+            let opt_old_edge = match capacity_graphs.get_mut(&currency) {
+                Some(capacity_graph) => capacity_graph.remove_edge(&a, &b),
+                None => None,
+            };
+            let _ = sender.send(opt_old_edge);
+        }
+        GraphRequest::RemoveEdgeAllCurrencies(a, b, sender) => {
+            let mut removed = false;
+            for capacity_graph in capacity_graphs.values_mut() {
+                removed |= capacity_graph.remove_edge(&a, &b).is_some();
+            }
+            let _ = sender.send(removed);
         }
         GraphRequest::RemoveNode(a, sender) => {
-            let _ = sender.send(capacity_graph.remove_node(&a));
+            let mut removed = false;
+            for capacity_graph in capacity_graphs.values_mut() {
+                removed |= capacity_graph.remove_node(&a);
+            }
+            let _ = sender.send(removed);
         }
-        GraphRequest::GetRoutes(a, b, capacity, opt_exclude, sender) => {
-            let routes = match opt_exclude {
-                Some((c, d)) => capacity_graph.get_routes(&a, &b, capacity, Some((&c, &d))),
-                None => capacity_graph.get_routes(&a, &b, capacity, None),
+        GraphRequest::GetRoutes(currency, a, b, capacity, constraints, max_routes, sender) => {
+            let routes = match capacity_graphs.get(&currency) {
+                Some(capacity_graph) => {
+                    capacity_graph.get_routes(&a, &b, capacity, &constraints, max_routes)
+                }
+                // No edges of this currency are known:
+                None => Vec::new(),
+            };
+            let _ = sender.send(routes);
+        }
+        GraphRequest::GetMaxFlowRoutes(currency, a, b, constraints, sender) => {
+            let routes = match capacity_graphs.get(&currency) {
+                Some(capacity_graph) => capacity_graph.get_max_flow_routes(&a, &b, &constraints),
+                // No edges of this currency are known:
+                None => Vec::new(),
             };
             let _ = sender.send(routes);
         }
         GraphRequest::Tick(a, sender) => {
-            capacity_graph.tick(&a);
+            for capacity_graph in capacity_graphs.values_mut() {
+                capacity_graph.tick(&a);
+            }
             let _ = sender.send(());
         }
+        GraphRequest::Snapshot(sender) => {
+            let graph_snapshot = capacity_graphs
+                .iter()
+                .map(|(currency, capacity_graph)| (currency.clone(), capacity_graph.saved_edges()))
+                .collect();
+            let _ = sender.send(graph_snapshot);
+        }
+        GraphRequest::RemoveRestoredNodes(sender) => {
+            for capacity_graph in capacity_graphs.values_mut() {
+                capacity_graph.remove_restored_nodes();
+            }
+            let _ = sender.send(());
+        }
+        GraphRequest::Stats(sender) => {
+            let graph_stats = capacity_graphs
+                .iter()
+                .map(|(currency, capacity_graph)| {
+                    (
+                        currency.clone(),
+                        capacity_graph.num_nodes(),
+                        capacity_graph.num_edges(),
+                    )
+                })
+                .collect();
+            let _ = sender.send(graph_stats);
+        }
+        GraphRequest::GetNeighbors(currency, a, sender) => {
+            let neighbors = match capacity_graphs.get(&currency) {
+                Some(capacity_graph) => capacity_graph.get_neighbors(&a),
+                // No edges of this currency are known:
+                None => Vec::new(),
+            };
+            let _ = sender.send(neighbors);
+        }
+        GraphRequest::GetNodeEdges(a, sender) => {
+            let mut node_edges = Vec::new();
+            for (currency, capacity_graph) in capacity_graphs.iter() {
+                for (b, capacity_edge) in capacity_graph.get_neighbors(&a) {
+                    node_edges.push((currency.clone(), b, capacity_edge));
+                }
+            }
+            let _ = sender.send(node_edges);
+        }
     }
 }
 
-async fn graph_service_loop<N, C, CG, GS>(
-    mut capacity_graph: CG,
-    mut incoming_requests: mpsc::Receiver<GraphRequest<N, C>>,
+/// Create capacity graphs for all currencies from a snapshot.
+pub fn restore_capacity_graphs<T, N, C, R, CG>(
+    graph_snapshot: GraphSnapshot<T, N, C, R>,
+) -> HashMap<T, CG>
+where
+    T: Eq + Hash,
+    CG: CapacityGraph<Node = N, Capacity = C, Rate = R> + Default,
+{
+    let mut capacity_graphs = HashMap::new();
+    for (currency, saved_edges) in graph_snapshot {
+        let mut capacity_graph = CG::default();
+        for saved_edge in saved_edges {
+            capacity_graph.restore_edge(saved_edge);
+        }
+        capacity_graphs.insert(currency, capacity_graph);
+    }
+    capacity_graphs
+}
+
+async fn graph_service_loop<T, N, C, R, CG, GS>(
+    mut capacity_graphs: HashMap<T, CG>,
+    mut incoming_requests: mpsc::Receiver<GraphRequest<T, N, C, R>>,
     mut graph_service_spawner: GS,
 ) -> Result<(), GraphServiceError>
 where
+    T: Eq + Hash + Clone + Send + 'static,
     N: Send + 'static,
     C: Send + 'static,
-    CG: CapacityGraph<Node = N, Capacity = C> + Send + 'static,
+    R: Send + 'static,
+    CG: CapacityGraph<Node = N, Capacity = C, Rate = R> + Default + Send + 'static,
     GS: Spawn,
 {
     // We use a separate spawner to be used for long graph computations.
@@ -85,14 +223,14 @@ where
         let process_request_handle = graph_service_spawner
             .spawn_with_handle(
                 async move {
-                    process_request(&mut capacity_graph, graph_request);
-                    capacity_graph
+                    process_request(&mut capacity_graphs, graph_request);
+                    capacity_graphs
                 },
             )
             .map_err(|_| GraphServiceError::LocalSpawnError)?;
 
         // Wait for completion of the computation on the external pool:
-        capacity_graph = await!(process_request_handle);
+        capacity_graphs = await!(process_request_handle);
     }
     Ok(())
 }
@@ -116,43 +254,59 @@ impl From<mpsc::SendError> for GraphClientError {
 }
 
 #[derive(Clone)]
-pub struct GraphClient<N, C> {
-    requests_sender: mpsc::Sender<GraphRequest<N, C>>,
+pub struct GraphClient<T, N, C, R> {
+    requests_sender: mpsc::Sender<GraphRequest<T, N, C, R>>,
 }
 
-impl<N, C> GraphClient<N, C> {
-    pub fn new(requests_sender: mpsc::Sender<GraphRequest<N, C>>) -> Self {
+impl<T, N, C, R> GraphClient<T, N, C, R> {
+    pub fn new(requests_sender: mpsc::Sender<GraphRequest<T, N, C, R>>) -> Self {
         GraphClient { requests_sender }
     }
 
-    /// Add or update edge
+    /// Add or update edge of a certain currency
     pub async fn update_edge(
         &mut self,
+        currency: T,
         a: N,
         b: N,
-        edge: CapacityEdge<C>,
-    ) -> Result<Option<CapacityEdge<C>>, GraphClientError> {
-        let (sender, receiver) = oneshot::channel::<Option<CapacityEdge<C>>>();
+        edge: CapacityEdge<C, R>,
+    ) -> Result<Option<CapacityEdge<C, R>>, GraphClientError> {
+        let (sender, receiver) = oneshot::channel::<Option<CapacityEdge<C, R>>>();
         await!(self
             .requests_sender
-            .send(GraphRequest::UpdateEdge(a, b, edge, sender)))?;
+            .send(GraphRequest::UpdateEdge(currency, a, b, edge, sender)))?;
         Ok(await!(receiver)?)
     }
 
-    /// Remove an edge from the graph
+    /// Remove an edge of a certain currency from the graph
     pub async fn remove_edge(
+        &mut self,
+        currency: T,
+        a: N,
+        b: N,
+    ) -> Result<Option<CapacityEdge<C, R>>, GraphClientError> {
+        let (sender, receiver) = oneshot::channel();
+        await!(self
+            .requests_sender
+            .send(GraphRequest::RemoveEdge(currency, a, b, sender)))?;
+        Ok(await!(receiver)?)
+    }
+
+    /// Remove an edge from the graph, in all currencies.
+    /// Returns true if the edge was present in any currency, false otherwise
+    pub async fn remove_edge_all_currencies(
         &mut self,
         a: N,
         b: N,
-    ) -> Result<Option<CapacityEdge<C>>, GraphClientError> {
+    ) -> Result<bool, GraphClientError> {
         let (sender, receiver) = oneshot::channel();
         await!(self
             .requests_sender
-            .send(GraphRequest::RemoveEdge(a, b, sender)))?;
+            .send(GraphRequest::RemoveEdgeAllCurrencies(a, b, sender)))?;
         Ok(await!(receiver)?)
     }
 
-    /// Remove a node and all related edges known from him.
+    /// Remove a node and all related edges known from him (In all currencies).
     /// Note: This method will not remove an edge from another node b pointing to a.
     /// Returns true if the node `a` was present, false otherwise
     pub async fn remove_node(&mut self, a: N) -> Result<bool, GraphClientError> {
@@ -163,24 +317,55 @@ impl<N, C> GraphClient<N, C> {
         Ok(await!(receiver)?)
     }
 
-    /// Obtain routes with capacity at least `capacity`.
-    /// Returns each route together with the capacity it is possible to send through that route.
+    /// Obtain at most `max_routes` routes that can deliver `capacity` to `b`, together with the
+    /// fees paid to the mediators along the way, using only edges of the given currency.
+    /// The routes do not share any directed edge, and are ranked by their total fees, cheapest
+    /// first.
+    /// Returns each route together with the capacity it is possible to send through that route,
+    /// and the total fees of the route.
     ///
-    /// opt_exclude is an optional edge to exclude (The returned route must not go through this
-    /// edge). This can be useful for finding non trivial loops.
+    /// All the returned routes satisfy `constraints`.
     pub async fn get_routes(
         &mut self,
+        currency: T,
         a: N,
         b: N,
         capacity: C,
-        opt_exclude: Option<(N, N)>,
+        constraints: RouteConstraints<N>,
+        max_routes: usize,
     ) -> Result<Vec<CapacityRoute<N, C>>, GraphClientError> {
         let (sender, receiver) = oneshot::channel();
         await!(self.requests_sender.send(GraphRequest::GetRoutes(
+            currency,
             a,
             b,
             capacity,
-            opt_exclude,
+            constraints,
+            max_routes,
+            sender
+        )))?;
+        Ok(await!(receiver)?)
+    }
+
+    /// Obtain routes that together carry the maximum flow possible from `a` to `b`, using only
+    /// edges of the given currency.
+    /// Returns each route together with the part of the flow going through that route, and the
+    /// fees paid for delivering the largest amount possible through the route.
+    ///
+    /// All the returned routes satisfy `constraints`.
+    pub async fn get_max_flow_routes(
+        &mut self,
+        currency: T,
+        a: N,
+        b: N,
+        constraints: RouteConstraints<N>,
+    ) -> Result<Vec<CapacityRoute<N, C>>, GraphClientError> {
+        let (sender, receiver) = oneshot::channel();
+        await!(self.requests_sender.send(GraphRequest::GetMaxFlowRoutes(
+            currency,
+            a,
+            b,
+            constraints,
             sender
         )))?;
         Ok(await!(receiver)?)
@@ -192,26 +377,81 @@ impl<N, C> GraphClient<N, C> {
         await!(self.requests_sender.send(GraphRequest::Tick(a, sender)))?;
         Ok(await!(receiver)?)
     }
+
+    /// Get a snapshot of the graphs of all currencies
+    pub async fn snapshot(&mut self) -> Result<GraphSnapshot<T, N, C, R>, GraphClientError> {
+        let (sender, receiver) = oneshot::channel();
+        await!(self.requests_sender.send(GraphRequest::Snapshot(sender)))?;
+        Ok(await!(receiver)?)
+    }
+
+    /// Remove all nodes that were restored from a snapshot and were not heard of since
+    pub async fn remove_restored_nodes(&mut self) -> Result<(), GraphClientError> {
+        let (sender, receiver) = oneshot::channel();
+        await!(self
+            .requests_sender
+            .send(GraphRequest::RemoveRestoredNodes(sender)))?;
+        Ok(await!(receiver)?)
+    }
+
+    /// Get the amount of nodes and edges in the graphs of all currencies
+    pub async fn stats(&mut self) -> Result<Vec<GraphStats<T>>, GraphClientError> {
+        let (sender, receiver) = oneshot::channel();
+        await!(self.requests_sender.send(GraphRequest::Stats(sender)))?;
+        Ok(await!(receiver)?)
+    }
+
+    /// Get all the edges reported by `a` in a certain currency, together with the node at the end
+    /// of every edge.
+    pub async fn get_neighbors(
+        &mut self,
+        currency: T,
+        a: N,
+    ) -> Result<Vec<(N, CapacityEdge<C, R>)>, GraphClientError> {
+        let (sender, receiver) = oneshot::channel();
+        await!(self
+            .requests_sender
+            .send(GraphRequest::GetNeighbors(currency, a, sender)))?;
+        Ok(await!(receiver)?)
+    }
+
+    /// Get all the edges reported by `a` in all currencies. Every edge is returned together with
+    /// its currency and the node at the end of the edge.
+    pub async fn get_node_edges(
+        &mut self,
+        a: N,
+    ) -> Result<Vec<(T, N, CapacityEdge<C, R>)>, GraphClientError> {
+        let (sender, receiver) = oneshot::channel();
+        await!(self
+            .requests_sender
+            .send(GraphRequest::GetNodeEdges(a, sender)))?;
+        Ok(await!(receiver)?)
+    }
 }
 
 /// Spawn a graph service, returning a GraphClient on success.
 /// GraphClient can be cloned to allow multiple clients.
-pub fn create_graph_service<N, C, CG, GS, S>(
-    capacity_graph: CG,
+///
+/// `capacity_graphs` are the initial graphs for every currency. A new empty graph is created
+/// whenever an edge of a new currency is added.
+pub fn create_graph_service<T, N, C, R, CG, GS, S>(
+    capacity_graphs: HashMap<T, CG>,
     graph_service_spawner: GS,
     mut spawner: S,
-) -> Result<GraphClient<N, C>, SpawnError>
+) -> Result<GraphClient<T, N, C, R>, SpawnError>
 where
+    T: Eq + Hash + Clone + Send + 'static,
     N: Send + 'static,
     C: Send + 'static,
-    CG: CapacityGraph<Node = N, Capacity = C> + Send + 'static,
+    R: Send + 'static,
+    CG: CapacityGraph<Node = N, Capacity = C, Rate = R> + Default + Send + 'static,
     GS: Spawn + Send + 'static,
     S: Spawn,
 {
     let (requests_sender, requests_receiver) = mpsc::channel(0);
 
     let graph_service_loop_fut =
-        graph_service_loop(capacity_graph, requests_receiver, graph_service_spawner)
+        graph_service_loop(capacity_graphs, requests_receiver, graph_service_spawner)
             .map_err(|e| error!("graph_service_loop() error: {:?}", e))
             .map(|_| ());
 
@@ -224,46 +464,173 @@ mod tests {
     use super::super::simple_capacity_graph::SimpleCapacityGraph;
     use super::*;
     use futures::executor::ThreadPool;
+    use proto::funder::messages::Rate;
+
+    /// A rate of no fees at all
+    fn zero_rate() -> Rate {
+        Rate { mul: 0, add: 0 }
+    }
 
     async fn task_create_graph_service_basic<S>(spawner: S)
     where
         S: Spawn,
     {
         let graph_service_spawner = ThreadPool::new().unwrap();
-        let capacity_graph = SimpleCapacityGraph::new();
+        let capacity_graphs = HashMap::<&str, SimpleCapacityGraph<u32, Rate>>::new();
         let mut graph_client =
-            create_graph_service(capacity_graph, graph_service_spawner, spawner).unwrap();
+            create_graph_service(capacity_graphs, graph_service_spawner, spawner).unwrap();
 
-        await!(graph_client.update_edge(2u32, 5u32, (30, 5))).unwrap();
-        await!(graph_client.update_edge(5, 2, (5, 30))).unwrap();
+        await!(graph_client.update_edge("USD", 2u32, 5u32, (30, 5, zero_rate()))).unwrap();
+        await!(graph_client.update_edge("USD", 5, 2, (5, 30, zero_rate()))).unwrap();
 
         assert_eq!(
-            await!(graph_client.get_routes(2, 5, 29, None)).unwrap(),
-            vec![(vec![2, 5], 30)]
+            await!(graph_client.get_routes("USD", 2, 5, 29, RouteConstraints::default(), 1))
+                .unwrap(),
+            vec![(vec![2, 5], 30, 0)]
+        );
+        assert_eq!(
+            await!(graph_client.get_routes("USD", 2, 5, 30, RouteConstraints::default(), 1))
+                .unwrap(),
+            vec![(vec![2, 5], 30, 0)]
+        );
+        assert_eq!(
+            await!(graph_client.get_routes("USD", 2, 5, 31, RouteConstraints::default(), 1))
+                .unwrap(),
+            vec![]
+        );
+        // Edges of other currencies are not used:
+        assert_eq!(
+            await!(graph_client.get_routes("EUR", 2, 5, 29, RouteConstraints::default(), 1))
+                .unwrap(),
+            vec![]
         );
         assert_eq!(
-            await!(graph_client.get_routes(2, 5, 30, None)).unwrap(),
-            vec![(vec![2, 5], 30)]
+            await!(graph_client.get_max_flow_routes("USD", 2, 5, RouteConstraints::default()))
+                .unwrap(),
+            vec![(vec![2, 5], 30, 0)]
         );
         assert_eq!(
-            await!(graph_client.get_routes(2, 5, 31, None)).unwrap(),
+            await!(graph_client.get_max_flow_routes("EUR", 2, 5, RouteConstraints::default()))
+                .unwrap(),
             vec![]
         );
 
+        assert_eq!(await!(graph_client.stats()).unwrap(), vec![("USD", 2, 2)]);
+        assert_eq!(
+            await!(graph_client.get_neighbors("USD", 2)).unwrap(),
+            vec![(5, (30, 5, zero_rate()))]
+        );
+        assert_eq!(
+            await!(graph_client.get_neighbors("EUR", 2)).unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            await!(graph_client.get_node_edges(2)).unwrap(),
+            vec![("USD", 5, (30, 5, zero_rate()))]
+        );
+
         await!(graph_client.tick(2)).unwrap();
 
         assert_eq!(
-            await!(graph_client.remove_edge(2, 5)).unwrap(),
-            Some((30, 5))
+            await!(graph_client.remove_edge("USD", 2, 5)).unwrap(),
+            Some((30, 5, zero_rate()))
         );
         assert_eq!(await!(graph_client.remove_node(2)).unwrap(), false);
         assert_eq!(await!(graph_client.remove_node(5)).unwrap(), true);
     }
 
+    async fn task_graph_service_remove_edge_all_currencies<S>(spawner: S)
+    where
+        S: Spawn,
+    {
+        let graph_service_spawner = ThreadPool::new().unwrap();
+        let capacity_graphs = HashMap::<&str, SimpleCapacityGraph<u32, Rate>>::new();
+        let mut graph_client =
+            create_graph_service(capacity_graphs, graph_service_spawner, spawner).unwrap();
+
+        for &currency in &["USD", "EUR"] {
+            await!(graph_client.update_edge(currency, 2u32, 5u32, (30, 5, zero_rate()))).unwrap();
+            await!(graph_client.update_edge(currency, 5, 2, (5, 30, zero_rate()))).unwrap();
+        }
+
+        assert_eq!(
+            await!(graph_client.remove_edge_all_currencies(2, 5)).unwrap(),
+            true
+        );
+        for &currency in &["USD", "EUR"] {
+            assert_eq!(
+                await!(graph_client.get_routes(currency, 2, 5, 1, RouteConstraints::default(), 1))
+                    .unwrap(),
+                vec![]
+            );
+        }
+        assert_eq!(
+            await!(graph_client.remove_edge_all_currencies(2, 5)).unwrap(),
+            false
+        );
+    }
+
+    async fn task_graph_service_snapshot<S>(spawner: S)
+    where
+        S: Spawn + Clone,
+    {
+        let capacity_graphs = HashMap::<&str, SimpleCapacityGraph<u32, Rate>>::new();
+        let mut graph_client =
+            create_graph_service(capacity_graphs, ThreadPool::new().unwrap(), spawner.clone())
+                .unwrap();
+
+        await!(graph_client.update_edge("USD", 2u32, 5u32, (30, 5, zero_rate()))).unwrap();
+        await!(graph_client.update_edge("USD", 5, 2, (5, 30, zero_rate()))).unwrap();
+
+        let graph_snapshot = await!(graph_client.snapshot()).unwrap();
+        assert_eq!(graph_snapshot.len(), 1);
+        assert_eq!(graph_snapshot["USD"].len(), 2);
+
+        // Start a new graph service from the snapshot:
+        let capacity_graphs: HashMap<_, SimpleCapacityGraph<_, _>> =
+            restore_capacity_graphs(graph_snapshot);
+        let mut graph_client =
+            create_graph_service(capacity_graphs, ThreadPool::new().unwrap(), spawner).unwrap();
+
+        assert_eq!(
+            await!(graph_client.get_routes("USD", 2, 5, 30, RouteConstraints::default(), 1))
+                .unwrap(),
+            vec![(vec![2, 5], 30, 0)]
+        );
+
+        // We hear from node 2, but not from node 5:
+        await!(graph_client.tick(2)).unwrap();
+        await!(graph_client.remove_restored_nodes()).unwrap();
+
+        assert_eq!(
+            await!(graph_client.get_routes("USD", 2, 5, 30, RouteConstraints::default(), 1))
+                .unwrap(),
+            vec![]
+        );
+        assert_eq!(await!(graph_client.remove_node(2)).unwrap(), true);
+        assert_eq!(await!(graph_client.remove_node(5)).unwrap(), false);
+    }
+
     #[test]
     fn test_create_graph_service_basic() {
         let mut thread_pool = ThreadPool::new().unwrap();
 
         thread_pool.run(task_create_graph_service_basic(thread_pool.clone()));
     }
+
+    #[test]
+    fn test_graph_service_remove_edge_all_currencies() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+
+        thread_pool.run(task_graph_service_remove_edge_all_currencies(
+            thread_pool.clone(),
+        ));
+    }
+
+    #[test]
+    fn test_graph_service_snapshot() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+
+        thread_pool.run(task_graph_service_snapshot(thread_pool.clone()));
+    }
 }
@@ -0,0 +1,523 @@
+use std::collections::{HashMap, VecDeque};
+use std::{cmp, hash};
+
+use super::dijkstra::dijkstra;
+
+/// Flows assigned to directed edges.
+struct Flows<N> {
+    flows: HashMap<(N, N), u128>,
+}
+
+impl<N> Flows<N>
+where
+    N: Clone + cmp::Eq + hash::Hash,
+{
+    fn new() -> Self {
+        Flows {
+            flows: HashMap::new(),
+        }
+    }
+
+    fn get(&self, a: &N, b: &N) -> u128 {
+        // TODO: Find a way to avoid cloning here:
+        *self.flows.get(&(a.clone(), b.clone())).unwrap_or(&0)
+    }
+
+    fn add(&mut self, a: &N, b: &N, amount: u128) {
+        let flow = self.flows.entry((a.clone(), b.clone())).or_insert(0);
+        *flow += amount;
+    }
+
+    fn sub(&mut self, a: &N, b: &N, amount: u128) {
+        let key = (a.clone(), b.clone());
+        let flow = self.flows.get_mut(&key).unwrap();
+        *flow -= amount;
+        if *flow == 0 {
+            self.flows.remove(&key);
+        }
+    }
+
+    /// Push `amount` credits from `a` to `b`. Flow going in the opposite direction is cancelled
+    /// first.
+    fn push(&mut self, a: &N, b: &N, amount: u128) {
+        let cancelled = cmp::min(self.get(b, a), amount);
+        if cancelled > 0 {
+            self.sub(b, a, cancelled);
+        }
+        if amount > cancelled {
+            self.add(a, b, amount - cancelled);
+        }
+    }
+}
+
+/// The amount of credits that can still be pushed from `a` to `b`, given the current flows.
+fn residual_capacity<N>(capacities: &HashMap<(N, N), u128>, flows: &Flows<N>, a: &N, b: &N) -> u128
+where
+    N: Clone + cmp::Eq + hash::Hash,
+{
+    let capacity = *capacities.get(&(a.clone(), b.clone())).unwrap_or(&0);
+    // Flow from `a` to `b` never exceeds the capacity:
+    (capacity - flows.get(a, b)).saturating_add(flows.get(b, a))
+}
+
+/// Split the flow leaving `src` into routes going to `dst`.
+/// Every route is returned together with the amount of flow going through it.
+fn decompose_flows<N>(src: &N, dst: &N, mut flows: Flows<N>) -> Vec<(Vec<N>, u128)>
+where
+    N: Clone + cmp::Eq + hash::Hash,
+{
+    let mut routes = Vec::new();
+    loop {
+        let mut route = vec![src.clone()];
+        while route.last().unwrap() != dst {
+            let node = route.last().unwrap().clone();
+            let next_node = match flows
+                .flows
+                .keys()
+                .find(|(a, _b)| a == &node)
+                .map(|(_a, b)| b.clone())
+            {
+                Some(next_node) => next_node,
+                // No more flow leaves `src`:
+                None => return routes,
+            };
+
+            if let Some(pos) = route.iter().position(|route_node| route_node == &next_node) {
+                // We found a cycle of flow. It does not carry any credits from `src` to `dst`,
+                // so we remove it:
+                let mut cycle = route.split_off(pos);
+                cycle.push(next_node);
+                let cycle_flow = (0..cycle.len() - 1)
+                    .map(|i| flows.get(&cycle[i], &cycle[i + 1]))
+                    .min()
+                    .unwrap();
+                for i in 0..cycle.len() - 1 {
+                    flows.sub(&cycle[i], &cycle[i + 1], cycle_flow);
+                }
+                if route.is_empty() {
+                    route.push(src.clone());
+                }
+                continue;
+            }
+            route.push(next_node);
+        }
+
+        let route_flow = (0..route.len() - 1)
+            .map(|i| flows.get(&route[i], &route[i + 1]))
+            .min()
+            .unwrap();
+        for i in 0..route.len() - 1 {
+            flows.sub(&route[i], &route[i + 1], route_flow);
+        }
+        routes.push((route, route_flow));
+    }
+}
+
+/// Calculate a maximum flow from `src` to `dst` (Edmonds-Karp), and split it into routes.
+/// `capacities` contains the capacity of every directed edge.
+///
+/// Returns the routes together with the amount of flow going through every route. The amounts
+/// add up to the maximum flow.
+pub fn max_flow_routes<N>(
+    src: &N,
+    dst: &N,
+    capacities: &HashMap<(N, N), u128>,
+) -> Vec<(Vec<N>, u128)>
+where
+    N: Clone + cmp::Eq + hash::Hash,
+{
+    if src == dst {
+        return Vec::new();
+    }
+
+    // Flow may be pushed against the direction of an edge (Cancelling existing flow), so every
+    // edge connects two neighbors in both directions:
+    let mut neighbors: HashMap<N, Vec<N>> = HashMap::new();
+    for ((a, b), capacity) in capacities {
+        if *capacity == 0 {
+            continue;
+        }
+        neighbors
+            .entry(a.clone())
+            .or_insert_with(Vec::new)
+            .push(b.clone());
+        neighbors
+            .entry(b.clone())
+            .or_insert_with(Vec::new)
+            .push(a.clone());
+    }
+    let empty = Vec::new();
+
+    let mut flows = Flows::new();
+    loop {
+        // Find a shortest route with residual capacity left:
+        let get_neighbors = |node: &N, _cost: u128| {
+            let node = node.clone();
+            let flows = &flows;
+            neighbors
+                .get(&node)
+                .unwrap_or(&empty)
+                .iter()
+                .filter(move |neighbor| residual_capacity(capacities, flows, &node, *neighbor) > 0)
+                .map(|neighbor| (neighbor, 1))
+        };
+        let route = match dijkstra(src, dst, usize::max_value(), get_neighbors) {
+            Some((route, _hops)) => route,
+            None => break,
+        };
+
+        let route_capacity = (0..route.len() - 1)
+            .map(|i| residual_capacity(capacities, &flows, &route[i], &route[i + 1]))
+            .min()
+            .unwrap();
+        for i in 0..route.len() - 1 {
+            flows.push(&route[i], &route[i + 1], route_capacity);
+        }
+    }
+
+    decompose_flows(src, dst, flows)
+}
+
+/// A directed edge of a dense flow network.
+/// Every edge is stored right before its reverse edge, so the reverse of edge `i` is edge `i ^ 1`.
+struct DenseEdge {
+    to: usize,
+    /// The amount of credits that can still be pushed through the edge, given the current flows.
+    residual: u128,
+}
+
+/// Find a shortest route from `src` to `dst` with residual capacity left, using breadth first
+/// search. Returns the indices of the edges of the route, from `dst` back to `src`.
+fn dense_find_route(
+    edges: &[DenseEdge],
+    adjacency: &[Vec<usize>],
+    src: usize,
+    dst: usize,
+) -> Option<Vec<usize>> {
+    let mut via_edge: Vec<Option<usize>> = vec![None; adjacency.len()];
+    let mut visited = vec![false; adjacency.len()];
+    let mut queue = VecDeque::new();
+
+    visited[src] = true;
+    queue.push_back(src);
+    while let Some(node) = queue.pop_front() {
+        if node == dst {
+            let mut route_edges = Vec::new();
+            let mut node = dst;
+            while let Some(edge_index) = via_edge[node] {
+                route_edges.push(edge_index);
+                node = edges[edge_index ^ 1].to;
+            }
+            return Some(route_edges);
+        }
+        for &edge_index in &adjacency[node] {
+            let edge = &edges[edge_index];
+            if edge.residual > 0 && !visited[edge.to] {
+                visited[edge.to] = true;
+                via_edge[edge.to] = Some(edge_index);
+                queue.push_back(edge.to);
+            }
+        }
+    }
+    None
+}
+
+/// Split the flow leaving `src` into routes going to `dst`.
+/// `flows` contains the flow going through every edge: (a, b, flow).
+fn dense_decompose_flows(
+    num_nodes: usize,
+    src: usize,
+    dst: usize,
+    mut flows: Vec<(usize, usize, u128)>,
+) -> Vec<(Vec<usize>, u128)> {
+    // Indices of the edges leaving every node:
+    let mut out_edges: Vec<Vec<usize>> = vec![Vec::new(); num_nodes];
+    for (flow_index, &(a, _b, flow)) in flows.iter().enumerate() {
+        if flow > 0 {
+            out_edges[a].push(flow_index);
+        }
+    }
+    // The position of every node on the route being built:
+    let mut route_pos: Vec<Option<usize>> = vec![None; num_nodes];
+
+    let mut routes = Vec::new();
+    let mut route = vec![src];
+    let mut route_edges: Vec<usize> = Vec::new();
+    route_pos[src] = Some(0);
+    loop {
+        let node = *route.last().unwrap();
+        if node == dst {
+            let route_flow = route_edges.iter().map(|&i| flows[i].2).min().unwrap();
+            for &i in &route_edges {
+                flows[i].2 -= route_flow;
+            }
+            for &route_node in &route {
+                route_pos[route_node] = None;
+            }
+            routes.push((route, route_flow));
+
+            route = vec![src];
+            route_edges.clear();
+            route_pos[src] = Some(0);
+            continue;
+        }
+
+        // Edges that do not carry flow anymore are dropped:
+        while let Some(&flow_index) = out_edges[node].last() {
+            if flows[flow_index].2 > 0 {
+                break;
+            }
+            out_edges[node].pop();
+        }
+        let flow_index = match out_edges[node].last() {
+            Some(&flow_index) => flow_index,
+            // No more flow leaves `src`:
+            None => return routes,
+        };
+        let (_a, next_node, _flow) = flows[flow_index];
+
+        if let Some(pos) = route_pos[next_node] {
+            // We found a cycle of flow. It does not carry any credits from `src` to `dst`,
+            // so we remove it:
+            let cycle_edges = route_edges.split_off(pos);
+            let cycle_flow = cycle_edges
+                .iter()
+                .chain(Some(&flow_index))
+                .map(|&i| flows[i].2)
+                .min()
+                .unwrap();
+            for &i in cycle_edges.iter().chain(Some(&flow_index)) {
+                flows[i].2 -= cycle_flow;
+            }
+            for removed_node in route.split_off(pos + 1) {
+                route_pos[removed_node] = None;
+            }
+            continue;
+        }
+
+        route_pos[next_node] = Some(route.len());
+        route.push(next_node);
+        route_edges.push(flow_index);
+    }
+}
+
+/// Calculate a maximum flow from `src` to `dst` in a graph whose nodes are the integers
+/// `0..num_nodes`, and split it into routes. Works like `max_flow_routes()`, but keeps the state
+/// of the computation in arrays indexed by node and edge, instead of hash maps. This is faster
+/// for graphs of interned nodes.
+/// `capacities` contains the capacity of every directed edge: (a, b, capacity).
+pub fn dense_max_flow_routes(
+    num_nodes: usize,
+    src: usize,
+    dst: usize,
+    capacities: &[(usize, usize, u128)],
+) -> Vec<(Vec<usize>, u128)> {
+    if src == dst {
+        return Vec::new();
+    }
+
+    // Flow may be pushed against the direction of an edge (Cancelling existing flow), so every
+    // edge comes with a reverse edge. The residual capacity of the reverse edge is the flow
+    // going through the edge:
+    let mut edges = Vec::new();
+    let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); num_nodes];
+    for &(a, b, capacity) in capacities {
+        if capacity == 0 {
+            continue;
+        }
+        adjacency[a].push(edges.len());
+        edges.push(DenseEdge {
+            to: b,
+            residual: capacity,
+        });
+        adjacency[b].push(edges.len());
+        edges.push(DenseEdge { to: a, residual: 0 });
+    }
+
+    while let Some(route_edges) = dense_find_route(&edges, &adjacency, src, dst) {
+        let route_capacity = route_edges
+            .iter()
+            .map(|&i| edges[i].residual)
+            .min()
+            .unwrap();
+        for &i in &route_edges {
+            edges[i].residual -= route_capacity;
+            edges[i ^ 1].residual += route_capacity;
+        }
+    }
+
+    let flows = (0..edges.len() / 2)
+        .map(|i| {
+            (
+                edges[2 * i + 1].to,
+                edges[2 * i].to,
+                edges[2 * i + 1].residual,
+            )
+        })
+        .collect();
+    dense_decompose_flows(num_nodes, src, dst, flows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total_flow(routes: &[(Vec<u32>, u128)]) -> u128 {
+        routes.iter().map(|(_route, flow)| flow).sum()
+    }
+
+    /// Make sure that the routes do not use more than the capacity of any edge
+    fn check_capacities(routes: &[(Vec<u32>, u128)], capacities: &HashMap<(u32, u32), u128>) {
+        let mut used: HashMap<(u32, u32), u128> = HashMap::new();
+        for (route, flow) in routes {
+            for i in 0..route.len() - 1 {
+                *used.entry((route[i], route[i + 1])).or_insert(0) += flow;
+            }
+        }
+        for (edge, used_capacity) in used {
+            assert!(used_capacity <= *capacities.get(&edge).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_max_flow_routes_basic() {
+        /*
+         Example graph (Edge capacities in brackets):
+
+                 [10]        [4]
+            0 ---------> 1 ---------> 3
+            |            |            ^
+         [5]|         [8]|            |[9]
+            V            V            |
+            4 ---------> 2 -----------/
+                 [5]
+        */
+        let mut capacities = HashMap::new();
+        capacities.insert((0u32, 1u32), 10u128);
+        capacities.insert((0, 4), 5);
+        capacities.insert((1, 3), 4);
+        capacities.insert((1, 2), 8);
+        capacities.insert((4, 2), 5);
+        capacities.insert((2, 3), 9);
+
+        let routes = max_flow_routes(&0, &3, &capacities);
+        assert_eq!(total_flow(&routes), 13);
+        check_capacities(&routes, &capacities);
+        for (route, _flow) in &routes {
+            assert_eq!(route.first(), Some(&0));
+            assert_eq!(route.last(), Some(&3));
+        }
+
+        assert!(max_flow_routes(&3, &0, &capacities).is_empty());
+        assert!(max_flow_routes(&0, &0, &capacities).is_empty());
+        assert!(max_flow_routes(&0, &5, &capacities).is_empty());
+    }
+
+    #[test]
+    fn test_max_flow_routes_cancel_flow() {
+        /*
+         Example graph (All edges are of capacity 1):
+
+            0 ---> 1 ---> 6 ---> 7
+            |      |             |
+            V      V             V
+            4      2 ----------> 3
+            |      ^
+            V      |
+            5 -----/
+
+         The first route found is the shortest one: 0 -> 1 -> 2 -> 3.
+         Reaching the maximum flow requires cancelling the flow on the edge 1 -> 2.
+        */
+        let mut capacities = HashMap::new();
+        capacities.insert((0u32, 1u32), 1u128);
+        capacities.insert((1, 2), 1);
+        capacities.insert((2, 3), 1);
+        capacities.insert((0, 4), 1);
+        capacities.insert((4, 5), 1);
+        capacities.insert((5, 2), 1);
+        capacities.insert((1, 6), 1);
+        capacities.insert((6, 7), 1);
+        capacities.insert((7, 3), 1);
+
+        let mut routes = max_flow_routes(&0, &3, &capacities);
+        routes.sort();
+        assert_eq!(
+            routes,
+            vec![(vec![0, 1, 6, 7, 3], 1), (vec![0, 4, 5, 2, 3], 1)]
+        );
+    }
+
+    #[test]
+    fn test_dense_max_flow_routes() {
+        // The graph of test_max_flow_routes_basic():
+        let capacities = vec![
+            (0, 1, 10),
+            (0, 4, 5),
+            (1, 3, 4),
+            (1, 2, 8),
+            (4, 2, 5),
+            (2, 3, 9),
+        ];
+        let routes = dense_max_flow_routes(5, 0, 3, &capacities);
+        let routes_u32 = routes
+            .iter()
+            .map(|(route, flow)| (route.iter().map(|&node| node as u32).collect(), *flow))
+            .collect::<Vec<_>>();
+        assert_eq!(total_flow(&routes_u32), 13);
+        check_capacities(
+            &routes_u32,
+            &capacities
+                .iter()
+                .map(|&(a, b, capacity)| ((a as u32, b as u32), capacity))
+                .collect(),
+        );
+        for (route, _flow) in &routes {
+            assert_eq!(route.first(), Some(&0));
+            assert_eq!(route.last(), Some(&3));
+        }
+
+        assert!(dense_max_flow_routes(5, 3, 0, &capacities).is_empty());
+        assert!(dense_max_flow_routes(5, 0, 0, &capacities).is_empty());
+
+        // The graph of test_max_flow_routes_cancel_flow():
+        let capacities = vec![
+            (0, 1, 1),
+            (1, 2, 1),
+            (2, 3, 1),
+            (0, 4, 1),
+            (4, 5, 1),
+            (5, 2, 1),
+            (1, 6, 1),
+            (6, 7, 1),
+            (7, 3, 1),
+        ];
+        let mut routes = dense_max_flow_routes(8, 0, 3, &capacities);
+        routes.sort();
+        assert_eq!(
+            routes,
+            vec![(vec![0, 1, 6, 7, 3], 1), (vec![0, 4, 5, 2, 3], 1)]
+        );
+    }
+
+    #[test]
+    fn test_dense_max_flow_routes_cycle() {
+        /*
+         Example graph (All edges are of capacity 1):
+
+            0 ---> 1 <---> 2 ---> 3
+
+         Flow might go around the cycle 1 -> 2 -> 1. It is removed from the routes.
+        */
+        let capacities = vec![(0, 1, 1), (1, 2, 1), (2, 1, 1), (2, 3, 1)];
+        let flows = vec![(0, 1, 1), (1, 2, 2), (2, 3, 1), (2, 1, 1)];
+        assert_eq!(
+            dense_decompose_flows(4, 0, 3, flows),
+            vec![(vec![0, 1, 2, 3], 1)]
+        );
+        assert_eq!(
+            dense_max_flow_routes(4, 0, 3, &capacities),
+            vec![(vec![0, 1, 2, 3], 1)]
+        );
+    }
+}
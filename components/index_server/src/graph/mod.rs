@@ -1,5 +1,36 @@
-mod bfs;
-mod capacity_graph;
+use std::str::FromStr;
+
+#[cfg(all(test, feature = "bench"))]
+mod benches;
+pub mod capacity_graph;
+pub mod compact_capacity_graph;
+mod dijkstra;
 pub mod graph_service;
+mod max_flow;
 pub mod simple_capacity_graph;
 mod utils;
+
+/// The capacity graph implementation used by the index server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphBackend {
+    /// `SimpleCapacityGraph`: Keeps every node in hash maps. Good enough for small networks.
+    Simple,
+    /// `CompactCapacityGraph`: Interns nodes into integer ids, and keeps edges in compact arrays.
+    /// Optimized for large networks.
+    Compact,
+}
+
+impl FromStr for GraphBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "simple" => Ok(GraphBackend::Simple),
+            "compact" => Ok(GraphBackend::Compact),
+            _ => Err(format!(
+                "Invalid graph backend: {} (Expected simple or compact)",
+                s
+            )),
+        }
+    }
+}
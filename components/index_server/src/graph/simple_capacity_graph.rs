@@ -1,55 +1,64 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::{cmp, hash};
 
-use super::bfs::bfs;
-use super::capacity_graph::{CapacityEdge, CapacityGraph};
-use super::utils::{option_to_vec, OptionIterator};
+use super::capacity_graph::{
+    max_edge_age, CapacityEdge, CapacityGraph, LinearRate, RouteConstraints, SavedEdge,
+    BASE_MAX_EDGE_AGE,
+};
+use super::dijkstra::dijkstra;
+use super::max_flow::max_flow_routes;
+use super::utils::OptionIterator;
 
-/// Amount of ticks an edge could live regardless of coupon collector's approximation.
-/// This is useful to allow the first edges build (n*log(n) is very small for small n).
-const BASE_MAX_EDGE_AGE: u128 = 16;
-
-struct Edge {
-    capacity: CapacityEdge<u128>,
+struct Edge<R> {
+    capacity: (u128, u128),
+    /// The fee charged by the node at the start of the edge, for forwarding requests from the
+    /// node at the end of the edge.
+    rate: R,
     age: u128,
 }
 
-impl Edge {
-    fn new(capacity: CapacityEdge<u128>) -> Self {
-        Edge { capacity, age: 0 }
+impl<R> Edge<R> {
+    fn new(edge: CapacityEdge<u128, R>) -> Self {
+        let (send_capacity, recv_capacity, rate) = edge;
+        Edge {
+            capacity: (send_capacity, recv_capacity),
+            rate,
+            age: 0,
+        }
+    }
+
+    fn into_capacity_edge(self) -> CapacityEdge<u128, R> {
+        let (send_capacity, recv_capacity) = self.capacity;
+        (send_capacity, recv_capacity, self.rate)
     }
 }
 
-struct NodeEdges<N> {
-    edges: HashMap<N, Edge>,
+struct NodeEdges<N, R> {
+    edges: HashMap<N, Edge<R>>,
+    /// The edges of this node were restored from a snapshot, and nothing was heard from this
+    /// node since.
+    restored: bool,
 }
 
-impl<N> NodeEdges<N>
+impl<N, R> NodeEdges<N, R>
 where
     N: cmp::Eq + hash::Hash + Clone + std::fmt::Debug,
 {
     fn new() -> Self {
         NodeEdges {
             edges: HashMap::new(),
+            restored: false,
         }
     }
 }
 
-/// The client sends us information about his neighbors in a cyclic fashion.
-/// This means that after about `N` messages a complete cycle will be completed
-/// and we will obtain information about the full client state.
-///
-/// We are being generous and willing to wait about `Const + 3 * N`
-/// until an edge is removed.
-fn max_edge_age(num_edges: usize) -> u128 {
-    BASE_MAX_EDGE_AGE + 3 * (num_edges as u128)
-}
-
-impl<N> NodeEdges<N>
+impl<N, R> NodeEdges<N, R>
 where
     N: cmp::Eq + hash::Hash + Clone + std::fmt::Debug,
 {
     pub fn tick(&mut self) {
+        // We heard from this node, so it is not considered restored anymore:
+        self.restored = false;
         let max_edge_age = max_edge_age(self.edges.len());
 
         self.edges.retain(|_remote_node, edge| {
@@ -59,29 +68,24 @@ where
     }
 }
 
-pub struct SimpleCapacityGraph<N> {
-    nodes: HashMap<N, NodeEdges<N>>,
+pub struct SimpleCapacityGraph<N, R> {
+    nodes: HashMap<N, NodeEdges<N, R>>,
 }
 
-impl<N> SimpleCapacityGraph<N>
+impl<N, R> SimpleCapacityGraph<N, R>
 where
     N: cmp::Eq + hash::Hash + Clone + std::fmt::Debug,
+    R: LinearRate<K = u128>,
 {
-    pub fn new() -> SimpleCapacityGraph<N> {
+    pub fn new() -> SimpleCapacityGraph<N, R> {
         SimpleCapacityGraph {
             nodes: HashMap::new(),
         }
     }
 
     /// Get a directed edge (if exists)
-    fn get_edge(&self, a: &N, b: &N) -> Option<CapacityEdge<u128>> {
-        match self.nodes.get(a) {
-            None => None,
-            Some(a_edges) => match a_edges.edges.get(b) {
-                None => None,
-                Some(a_b_edge) => Some(a_b_edge.capacity),
-            },
-        }
+    fn get_edge(&self, a: &N, b: &N) -> Option<&Edge<R>> {
+        self.nodes.get(a)?.edges.get(b)
     }
 
     /// Get the send capacity from `a` to a direct neighbor `b`.
@@ -100,25 +104,42 @@ where
             return 0;
         };
 
-        let (a_send, _a_recv) = a_b_edge;
-        let (_b_send, b_recv) = b_a_edge;
+        let (a_send, _a_recv) = a_b_edge.capacity;
+        let (_b_send, b_recv) = b_a_edge.capacity;
 
         cmp::min(a_send, b_recv)
     }
 
-    fn neighbors_with_send_capacity(
-        &self,
-        a: N,
+    /// Iterate over the neighbors that can send a request to `b` through a direct edge, together
+    /// with the fee `b` charges them for forwarding the request.
+    ///
+    /// The request should deliver `capacity` credits to `dest`, and carry `left_fees` credits of
+    /// fees for the mediators after `b` on the route. `b` takes no fee if it is the destination.
+    fn senders_with_fees<'a>(
+        &'a self,
+        b: N,
+        dest: &'a N,
         capacity: u128,
-    ) -> OptionIterator<impl Iterator<Item = &N>> {
-        let a_edges = match self.nodes.get(&a) {
-            Some(a_edges) => a_edges,
+        left_fees: u128,
+    ) -> OptionIterator<impl Iterator<Item = (&'a N, u128)> + 'a> {
+        let b_edges = match self.nodes.get(&b) {
+            Some(b_edges) => b_edges,
             None => return OptionIterator::new(None),
         };
-        let iter = a_edges
-            .edges
-            .keys()
-            .filter(move |b| self.get_send_capacity(&a, b) >= capacity);
+        let iter = b_edges.edges.iter().filter_map(move |(a, b_a_edge)| {
+            let fee = if &b == dest {
+                0
+            } else {
+                b_a_edge.rate.calc_fee(capacity)?
+            };
+            // The amount of credits frozen when sending the request from `a` to `b`:
+            let credits = capacity.checked_add(left_fees)?.checked_add(fee)?;
+            if self.get_send_capacity(a, &b) >= credits {
+                Some((a, fee))
+            } else {
+                None
+            }
+        });
         OptionIterator::new(Some(iter))
     }
 
@@ -130,53 +151,160 @@ where
             .min()
     }
 
-    /// Get a route with capacity at least `capacity`.
-    /// Returns the route together with the capacity it is possible to send through the route.
+    /// Calculate the total fees paid to the mediators of a route for delivering `amount` credits
+    /// to the end of the route.
+    fn get_route_fees(&self, route: &[N], amount: u128) -> Option<u128> {
+        let mut fees: u128 = 0;
+        // The first and last nodes of the route take no fees:
+        for i in 1..route.len().checked_sub(1)? {
+            let rate = &self.get_edge(&route[i], &route[i - 1])?.rate;
+            fees = fees.checked_add(rate.calc_fee(amount)?)?;
+        }
+        Some(fees)
+    }
+
+    /// Find the largest amount that can be delivered through a route by sending `credits`
+    /// credits through its first edge. The credits frozen on the next edges of the route are
+    /// never larger than on the first edge.
+    /// Returns the fees paid for delivering this amount, or None if nothing can be delivered.
+    fn get_max_amount_fees(&self, route: &[N], credits: u128) -> Option<u128> {
+        let fits = |amount: u128| match self.get_route_fees(route, amount) {
+            Some(fees) => fees
+                .checked_add(amount)
+                .map_or(false, |total| total <= credits),
+            None => false,
+        };
+        if credits == 0 || !fits(1) {
+            return None;
+        }
+        // Binary search for the largest amount that fits:
+        let (mut low, mut high) = (1, credits);
+        while low < high {
+            let mid = low + (high - low + 1) / 2;
+            if fits(mid) {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        self.get_route_fees(route, low)
+    }
+
+    /// Get the cheapest route that can deliver `capacity` credits to `b`, together with the fees
+    /// paid to the mediators along the route. Between routes of equal fees, a shorter route is
+    /// preferred.
+    /// Returns the route together with the capacity it is possible to send through the route,
+    /// and the total fees of the route.
     ///
-    /// opt_exclude is an optional edge to exclude (The returned route must not go through this
-    /// edge). This can be useful for finding non trivial loops.
+    /// The returned route satisfies `constraints`. A route with waypoints is found one segment
+    /// (between two consecutive waypoints) at a time, so it might not be the cheapest route
+    /// possible.
     fn get_route(
         &self,
         a: &N,
         b: &N,
         capacity: u128,
-        opt_exclude: Option<(&N, &N)>,
-    ) -> Option<(Vec<N>, u128)> {
-        let (opt_e_start, opt_e_end) = match opt_exclude {
-            Some((e_start, e_end)) => (Some(e_start), Some(e_end)),
-            None => (None, None),
-        };
-        let get_neighbors = |cur_node: &N| {
-            let cur_node_is_e_start = Some(cur_node) == opt_e_start;
-            self.neighbors_with_send_capacity(cur_node.clone(), capacity)
-                .filter(move |&next_node| !cur_node_is_e_start || Some(next_node) != opt_e_end)
-        };
-        let route = bfs(a, b, get_neighbors)?;
+        constraints: &RouteConstraints<N>,
+    ) -> Option<(Vec<N>, u128, u128)> {
+        let mut stops = vec![a.clone()];
+        stops.extend(constraints.waypoints.iter().cloned());
+        stops.push(b.clone());
+
+        // A route may not visit a node twice. In particular, it must contain at least two nodes:
+        let stops_set: HashSet<&N> = stops.iter().collect();
+        if stops_set.len() < stops.len() {
+            return None;
+        }
+        if stops
+            .iter()
+            .any(|stop| constraints.exclude_nodes.contains(stop))
+        {
+            return None;
+        }
+
+        // Nodes that may not show up in the middle of a segment:
+        let mut blocked_nodes: HashSet<N> = constraints
+            .exclude_nodes
+            .iter()
+            .chain(stops.iter())
+            .cloned()
+            .collect();
+        let max_route_len = constraints.opt_max_route_len.unwrap_or(usize::max_value());
+
+        // We search backwards, from `b` to `a`, because the credits frozen on every edge depend
+        // on the fees of the mediators that come after it on the route:
+        let mut route = vec![b.clone()];
+        let mut fees: u128 = 0;
+        for i in (0..stops.len() - 1).rev() {
+            let (segment_start, segment_end) = (&stops[i], &stops[i + 1]);
+            // Each of the `i` segments left to be found adds at least one node to the route:
+            let max_hops = max_route_len.checked_sub(route.len())?.checked_sub(i)?;
+
+            let segment_fees = fees;
+            let blocked_nodes_ref = &blocked_nodes;
+            let get_senders = |cur_node: &N, cost: u128| {
+                let cur_node = cur_node.clone();
+                let left_fees = segment_fees.saturating_add(cost);
+                self.senders_with_fees(cur_node.clone(), b, capacity, left_fees)
+                    .filter(move |(prev_node, _fee)| {
+                        (*prev_node == segment_start || !blocked_nodes_ref.contains(*prev_node))
+                            && !constraints
+                                .exclude_edges
+                                .contains(&((*prev_node).clone(), cur_node.clone()))
+                    })
+            };
+            let (segment, cost) = dijkstra(segment_end, segment_start, max_hops, get_senders)?;
+            fees = fees.checked_add(cost)?;
+
+            for node in segment.into_iter().skip(1) {
+                blocked_nodes.insert(node.clone());
+                route.push(node);
+            }
+        }
+        route.reverse();
         // We assert that we will always have valid capacity here:
         let capacity = self.get_route_capacity(&route).unwrap();
 
-        Some((route, capacity))
+        Some((route, capacity, fees))
+    }
+}
+
+impl<N, R> Default for SimpleCapacityGraph<N, R>
+where
+    N: cmp::Eq + hash::Hash + Clone + std::fmt::Debug,
+    R: LinearRate<K = u128>,
+{
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl<N> CapacityGraph for SimpleCapacityGraph<N>
+impl<N, R> CapacityGraph for SimpleCapacityGraph<N, R>
 where
     N: cmp::Eq + hash::Hash + Clone + std::fmt::Debug,
+    R: LinearRate<K = u128> + Clone,
 {
     type Node = N;
     type Capacity = u128;
+    type Rate = R;
 
     /// Add or update edge
-    fn update_edge(&mut self, a: N, b: N, edge: CapacityEdge<u128>) -> Option<CapacityEdge<u128>> {
+    fn update_edge(
+        &mut self,
+        a: N,
+        b: N,
+        edge: CapacityEdge<u128, R>,
+    ) -> Option<CapacityEdge<u128, R>> {
         let a_entry = self.nodes.entry(a).or_insert_with(NodeEdges::new);
+        a_entry.restored = false;
         a_entry
             .edges
             .insert(b, Edge::new(edge))
-            .map(|edge| edge.capacity)
+            .map(Edge::into_capacity_edge)
     }
 
     /// Remove an edge from the graph
-    fn remove_edge(&mut self, a: &N, b: &N) -> Option<CapacityEdge<u128>> {
+    fn remove_edge(&mut self, a: &N, b: &N) -> Option<CapacityEdge<u128, R>> {
         let a_edges = match self.nodes.get_mut(a) {
             Some(a_edges) => a_edges,
             None => return None,
@@ -191,7 +319,7 @@ where
             self.nodes.remove(a);
         }
 
-        Some(old_edge.capacity)
+        Some(old_edge.into_capacity_edge())
     }
 
     /// Remove a node and all related edges known from him.
@@ -205,9 +333,61 @@ where
         a: &N,
         b: &N,
         capacity: u128,
-        opt_exclude: Option<(&N, &N)>,
-    ) -> Vec<(Vec<N>, u128)> {
-        option_to_vec(self.get_route(a, b, capacity, opt_exclude))
+        constraints: &RouteConstraints<N>,
+        max_routes: usize,
+    ) -> Vec<(Vec<N>, u128, u128)> {
+        let mut constraints = constraints.clone();
+
+        let mut routes = Vec::new();
+        while routes.len() < max_routes {
+            let route = match self.get_route(a, b, capacity, &constraints) {
+                Some(route) => route,
+                None => break,
+            };
+            // The next routes may not use any of the edges of this route. Every route we find is
+            // at least as expensive as the previous ones:
+            let (route_nodes, _capacity, _fees) = &route;
+            for i in 0..route_nodes.len() - 1 {
+                constraints
+                    .exclude_edges
+                    .insert((route_nodes[i].clone(), route_nodes[i + 1].clone()));
+            }
+            routes.push(route);
+        }
+        routes
+    }
+
+    fn get_max_flow_routes(
+        &self,
+        a: &N,
+        b: &N,
+        constraints: &RouteConstraints<N>,
+    ) -> Vec<(Vec<N>, u128, u128)> {
+        let mut capacities = HashMap::new();
+        for (x, x_edges) in &self.nodes {
+            for y in x_edges.edges.keys() {
+                if constraints.exclude_nodes.contains(x)
+                    || constraints.exclude_nodes.contains(y)
+                    || constraints.exclude_edges.contains(&(x.clone(), y.clone()))
+                {
+                    continue;
+                }
+                let send_capacity = self.get_send_capacity(x, y);
+                if send_capacity > 0 {
+                    capacities.insert((x.clone(), y.clone()), send_capacity);
+                }
+            }
+        }
+
+        // Routes that can not deliver any credits after paying the fees are dropped:
+        max_flow_routes(a, b, &capacities)
+            .into_iter()
+            .filter(|(route, _flow)| constraints.allows_route(route))
+            .filter_map(|(route, flow)| {
+                let fees = self.get_max_amount_fees(&route, flow)?;
+                Some((route, flow, fees))
+            })
+            .collect()
     }
 
     fn tick(&mut self, a: &N) {
@@ -215,17 +395,92 @@ where
             node_edges.tick();
         }
     }
+
+    fn saved_edges(&self) -> Vec<SavedEdge<N, u128, R>> {
+        let mut saved_edges = Vec::new();
+        for (a, node_edges) in &self.nodes {
+            for (b, edge) in &node_edges.edges {
+                let (send_capacity, recv_capacity) = edge.capacity;
+                saved_edges.push((
+                    a.clone(),
+                    b.clone(),
+                    (send_capacity, recv_capacity, edge.rate.clone()),
+                    edge.age,
+                ));
+            }
+        }
+        saved_edges
+    }
+
+    fn restore_edge(&mut self, saved_edge: SavedEdge<N, u128, R>) {
+        let (a, b, capacity_edge, age) = saved_edge;
+        let a_entry = self.nodes.entry(a).or_insert_with(|| {
+            let mut node_edges = NodeEdges::new();
+            node_edges.restored = true;
+            node_edges
+        });
+        let mut edge = Edge::new(capacity_edge);
+        // We don't know how long the edge was kept on disk, so we consider it to be at least
+        // `BASE_MAX_EDGE_AGE` ticks old. It will live about as long as it takes for the node to
+        // send us information about all of its friends again:
+        edge.age = cmp::max(age, BASE_MAX_EDGE_AGE);
+        a_entry.edges.insert(b, edge);
+    }
+
+    fn remove_restored_nodes(&mut self) {
+        self.nodes.retain(|_node, node_edges| !node_edges.restored);
+    }
+
+    fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn num_edges(&self) -> usize {
+        self.nodes
+            .values()
+            .map(|node_edges| node_edges.edges.len())
+            .sum()
+    }
+
+    fn get_neighbors(&self, a: &N) -> Vec<(N, CapacityEdge<u128, R>)> {
+        let a_edges = match self.nodes.get(a) {
+            Some(a_edges) => a_edges,
+            None => return Vec::new(),
+        };
+        a_edges
+            .edges
+            .iter()
+            .map(|(b, edge)| {
+                let (send_capacity, recv_capacity) = edge.capacity;
+                (b.clone(), (send_capacity, recv_capacity, edge.rate.clone()))
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proto::funder::messages::Rate;
+
+    /// A rate of no fees at all
+    fn zero_rate() -> Rate {
+        Rate { mul: 0, add: 0 }
+    }
+
+    /// Route constraints that only exclude a set of directed edges
+    fn exclude_edges(edges: &[(u32, u32)]) -> RouteConstraints<u32> {
+        RouteConstraints {
+            exclude_edges: edges.iter().cloned().collect(),
+            ..RouteConstraints::default()
+        }
+    }
 
     #[test]
     fn test_get_send_capacity_basic() {
-        let mut cg = SimpleCapacityGraph::<u32>::new();
-        cg.update_edge(0, 1, (10, 20));
-        cg.update_edge(1, 0, (15, 5));
+        let mut cg = SimpleCapacityGraph::<u32, Rate>::new();
+        cg.update_edge(0, 1, (10, 20, zero_rate()));
+        cg.update_edge(1, 0, (15, 5, zero_rate()));
 
         assert_eq!(cg.get_send_capacity(&0, &1), cmp::min(5, 10));
         assert_eq!(cg.get_send_capacity(&1, &0), cmp::min(15, 20));
@@ -233,8 +488,8 @@ mod tests {
 
     #[test]
     fn test_get_send_capacity_one_sided() {
-        let mut cg = SimpleCapacityGraph::<u32>::new();
-        cg.update_edge(0, 1, (10, 20));
+        let mut cg = SimpleCapacityGraph::<u32, Rate>::new();
+        cg.update_edge(0, 1, (10, 20, zero_rate()));
 
         assert_eq!(cg.get_send_capacity(&0, &1), 0);
         assert_eq!(cg.get_send_capacity(&1, &0), 0);
@@ -242,21 +497,21 @@ mod tests {
 
     #[test]
     fn test_add_remove_edge() {
-        let mut cg = SimpleCapacityGraph::<u32>::new();
+        let mut cg = SimpleCapacityGraph::<u32, Rate>::new();
         assert_eq!(cg.remove_edge(&0, &1), None);
-        cg.update_edge(0, 1, (10, 20));
+        cg.update_edge(0, 1, (10, 20, zero_rate()));
         assert_eq!(cg.nodes.len(), 1);
 
-        assert_eq!(cg.remove_edge(&0, &1), Some((10, 20)));
+        assert_eq!(cg.remove_edge(&0, &1), Some((10, 20, zero_rate())));
         assert_eq!(cg.nodes.len(), 0);
 
-        cg.update_edge(0, 1, (10, 20));
+        cg.update_edge(0, 1, (10, 20, zero_rate()));
         assert_eq!(cg.nodes.len(), 1);
         cg.remove_node(&1);
         assert_eq!(cg.nodes.len(), 1);
     }
 
-    fn example_capacity_graph() -> SimpleCapacityGraph<u32> {
+    fn example_capacity_graph() -> SimpleCapacityGraph<u32, Rate> {
         /*
          * Example graph:
          *
@@ -267,96 +522,510 @@ mod tests {
          *
          */
 
-        let mut cg = SimpleCapacityGraph::<u32>::new();
+        let mut cg = SimpleCapacityGraph::<u32, Rate>::new();
 
-        cg.update_edge(0, 1, (30, 10));
-        cg.update_edge(1, 0, (10, 30));
+        cg.update_edge(0, 1, (30, 10, zero_rate()));
+        cg.update_edge(1, 0, (10, 30, zero_rate()));
 
-        cg.update_edge(1, 2, (10, 10));
-        cg.update_edge(2, 1, (10, 10));
+        cg.update_edge(1, 2, (10, 10, zero_rate()));
+        cg.update_edge(2, 1, (10, 10, zero_rate()));
 
-        cg.update_edge(2, 5, (30, 5));
-        cg.update_edge(5, 2, (5, 30));
+        cg.update_edge(2, 5, (30, 5, zero_rate()));
+        cg.update_edge(5, 2, (5, 30, zero_rate()));
 
-        cg.update_edge(1, 3, (30, 8));
-        cg.update_edge(3, 1, (8, 30));
+        cg.update_edge(1, 3, (30, 8, zero_rate()));
+        cg.update_edge(3, 1, (8, 30, zero_rate()));
 
-        cg.update_edge(3, 4, (30, 6));
-        cg.update_edge(4, 3, (6, 30));
+        cg.update_edge(3, 4, (30, 6, zero_rate()));
+        cg.update_edge(4, 3, (6, 30, zero_rate()));
 
-        cg.update_edge(4, 2, (30, 18));
-        cg.update_edge(2, 4, (18, 30));
+        cg.update_edge(4, 2, (30, 18, zero_rate()));
+        cg.update_edge(2, 4, (18, 30, zero_rate()));
 
         cg
     }
 
+    #[test]
+    fn test_get_neighbors() {
+        let cg = example_capacity_graph();
+        assert_eq!(cg.num_nodes(), 6);
+        assert_eq!(cg.num_edges(), 12);
+
+        let mut neighbors = cg.get_neighbors(&1);
+        neighbors.sort_by_key(|(b, _edge)| *b);
+        assert_eq!(
+            neighbors,
+            vec![
+                (0, (10, 30, zero_rate())),
+                (2, (10, 10, zero_rate())),
+                (3, (30, 8, zero_rate())),
+            ]
+        );
+        assert!(cg.get_neighbors(&6).is_empty());
+    }
+
     #[test]
     fn test_get_route() {
         let cg = example_capacity_graph();
 
-        assert_eq!(cg.get_route(&2, &5, 29, None), Some((vec![2, 5], 30)));
-        assert_eq!(cg.get_route(&2, &5, 30, None), Some((vec![2, 5], 30)));
-        assert_eq!(cg.get_route(&2, &5, 31, None), None);
+        assert_eq!(
+            cg.get_route(&2, &5, 29, &RouteConstraints::default()),
+            Some((vec![2, 5], 30, 0))
+        );
+        assert_eq!(
+            cg.get_route(&2, &5, 30, &RouteConstraints::default()),
+            Some((vec![2, 5], 30, 0))
+        );
+        assert_eq!(cg.get_route(&2, &5, 31, &RouteConstraints::default()), None);
 
         assert_eq!(
-            cg.get_route(&0, &5, 25, None),
-            Some((vec![0, 1, 3, 4, 2, 5], 30))
+            cg.get_route(&0, &5, 25, &RouteConstraints::default()),
+            Some((vec![0, 1, 3, 4, 2, 5], 30, 0))
         );
         assert_eq!(
-            cg.get_route(&0, &5, 29, None),
-            Some((vec![0, 1, 3, 4, 2, 5], 30))
+            cg.get_route(&0, &5, 29, &RouteConstraints::default()),
+            Some((vec![0, 1, 3, 4, 2, 5], 30, 0))
         );
         assert_eq!(
-            cg.get_route(&0, &5, 30, None),
-            Some((vec![0, 1, 3, 4, 2, 5], 30))
+            cg.get_route(&0, &5, 30, &RouteConstraints::default()),
+            Some((vec![0, 1, 3, 4, 2, 5], 30, 0))
         );
-        assert_eq!(cg.get_route(&0, &5, 31, None), None);
+        assert_eq!(cg.get_route(&0, &5, 31, &RouteConstraints::default()), None);
 
         // Block an essential edge:
-        assert_eq!(cg.get_route(&0, &5, 25, Some((&3, &4))), None);
+        assert_eq!(cg.get_route(&0, &5, 25, &exclude_edges(&[(3, 4)])), None);
         // Block an essential edge but the at the reversed direction:
         assert_eq!(
-            cg.get_route(&0, &5, 25, Some((&4, &3))),
-            Some((vec![0, 1, 3, 4, 2, 5], 30))
+            cg.get_route(&0, &5, 25, &exclude_edges(&[(4, 3)])),
+            Some((vec![0, 1, 3, 4, 2, 5], 30, 0))
         );
         // Block an edge not used for the route:
         assert_eq!(
-            cg.get_route(&0, &5, 25, Some((&1, &2))),
-            Some((vec![0, 1, 3, 4, 2, 5], 30))
+            cg.get_route(&0, &5, 25, &exclude_edges(&[(1, 2)])),
+            Some((vec![0, 1, 3, 4, 2, 5], 30, 0))
         );
 
         // Use excluded edge to find a loop from 1 to 1:
         assert_eq!(
-            cg.get_route(&2, &1, 6, Some((&2, &1))),
-            Some((vec![2, 4, 3, 1], 6))
+            cg.get_route(&2, &1, 6, &exclude_edges(&[(2, 1)])),
+            Some((vec![2, 4, 3, 1], 6, 0))
         );
         // Require too much capacity:
-        assert_eq!(cg.get_route(&2, &1, 7, Some((&2, &1))), None);
+        assert_eq!(cg.get_route(&2, &1, 7, &exclude_edges(&[(2, 1)])), None);
+    }
+
+    #[test]
+    fn test_get_route_fees() {
+        /*
+         * Example graph:
+         *
+         *    0 --> 1 --> 3
+         *    |           ^
+         *    V           |
+         *    2 --------> 4
+         *
+         * 1 charges 0 a fee of 10 credits. 2 and 4 charge a fee of 1 credit.
+         */
+
+        let rate = |add| Rate { mul: 0, add };
+        let mut cg = SimpleCapacityGraph::<u32, Rate>::new();
+
+        cg.update_edge(0, 1, (100, 100, zero_rate()));
+        cg.update_edge(1, 0, (100, 100, rate(10)));
+
+        cg.update_edge(1, 3, (100, 100, zero_rate()));
+        cg.update_edge(3, 1, (100, 100, zero_rate()));
+
+        cg.update_edge(0, 2, (22, 100, zero_rate()));
+        cg.update_edge(2, 0, (100, 100, rate(1)));
+
+        cg.update_edge(2, 4, (100, 100, zero_rate()));
+        cg.update_edge(4, 2, (100, 100, rate(1)));
+
+        cg.update_edge(4, 3, (100, 100, zero_rate()));
+        cg.update_edge(3, 4, (100, 100, zero_rate()));
+
+        // The longer route is cheaper:
+        assert_eq!(
+            cg.get_route(&0, &3, 20, &RouteConstraints::default()),
+            Some((vec![0, 2, 4, 3], 22, 2))
+        );
+        // The first edge of the cheap route can not carry the payment together with the fees:
+        assert_eq!(
+            cg.get_route(&0, &3, 21, &RouteConstraints::default()),
+            Some((vec![0, 1, 3], 100, 10))
+        );
+        // No route can carry the payment together with the fees:
+        assert_eq!(cg.get_route(&0, &3, 91, &RouteConstraints::default()), None);
+        assert_eq!(
+            cg.get_route(&0, &3, 90, &RouteConstraints::default()),
+            Some((vec![0, 1, 3], 100, 10))
+        );
+
+        // The route must go through 1:
+        let constraints = RouteConstraints {
+            waypoints: vec![1],
+            ..RouteConstraints::default()
+        };
+        assert_eq!(
+            cg.get_route(&0, &3, 20, &constraints),
+            Some((vec![0, 1, 3], 100, 10))
+        );
+        // Fees of the mediators after a waypoint are carried by the edges before it:
+        let constraints = RouteConstraints {
+            waypoints: vec![4],
+            ..RouteConstraints::default()
+        };
+        assert_eq!(
+            cg.get_route(&0, &3, 20, &constraints),
+            Some((vec![0, 2, 4, 3], 22, 2))
+        );
+        assert_eq!(cg.get_route(&0, &3, 21, &constraints), None);
+
+        // Proportional fees: 2 charges 0 half of the payment:
+        let half_rate = Rate {
+            mul: 1 << 31,
+            add: 0,
+        };
+        cg.update_edge(2, 0, (100, 100, half_rate));
+        assert_eq!(
+            cg.get_route(&0, &3, 20, &RouteConstraints::default()),
+            Some((vec![0, 1, 3], 100, 10))
+        );
+        assert_eq!(
+            cg.get_route(&0, &3, 4, &RouteConstraints::default()),
+            Some((vec![0, 2, 4, 3], 22, 3))
+        );
+
+        // Fees are not paid to the destination:
+        assert_eq!(
+            cg.get_route(&0, &2, 22, &RouteConstraints::default()),
+            Some((vec![0, 2], 22, 0))
+        );
+    }
+
+    #[test]
+    fn test_get_routes() {
+        let cg = example_capacity_graph();
+
+        // Only one route can carry 25 credits:
+        assert_eq!(
+            cg.get_routes(&0, &5, 25, &RouteConstraints::default(), 4),
+            vec![(vec![0, 1, 3, 4, 2, 5], 30, 0)]
+        );
+
+        // Two routes that do not share any edge:
+        let routes = cg.get_routes(&1, &2, 5, &RouteConstraints::default(), 4);
+        assert_eq!(routes, vec![(vec![1, 2], 10, 0), (vec![1, 3, 4, 2], 30, 0)]);
+        assert_eq!(
+            cg.get_routes(&1, &2, 5, &RouteConstraints::default(), 1),
+            vec![(vec![1, 2], 10, 0)]
+        );
+        assert_eq!(
+            cg.get_routes(&1, &2, 5, &RouteConstraints::default(), 0),
+            vec![]
+        );
+
+        // The excluded edge is not used by any of the routes:
+        assert_eq!(
+            cg.get_routes(&1, &2, 5, &exclude_edges(&[(1, 2)]), 4),
+            vec![(vec![1, 3, 4, 2], 30, 0)]
+        );
+    }
+
+    #[test]
+    fn test_get_routes_ranked_by_fees() {
+        /*
+         * Example graph:
+         *
+         *       1
+         *     /   \
+         *    0 --- 2 --- 4
+         *     \   /
+         *       3
+         *
+         * 1 charges 0 a fee of 3 credits. 2 charges 0 a fee of 1 credit. 3 charges 0 a fee of 2
+         * credits.
+         */
+
+        let rate = |add| Rate { mul: 0, add };
+        let mut cg = SimpleCapacityGraph::<u32, Rate>::new();
+
+        for &(mediator, add) in &[(1, 3), (2, 1), (3, 2)] {
+            cg.update_edge(0, mediator, (100, 100, zero_rate()));
+            cg.update_edge(mediator, 0, (100, 100, rate(add)));
+        }
+        for &mediator in &[1, 3] {
+            cg.update_edge(mediator, 2, (100, 100, zero_rate()));
+            cg.update_edge(2, mediator, (100, 100, zero_rate()));
+        }
+        cg.update_edge(2, 4, (100, 100, zero_rate()));
+        cg.update_edge(4, 2, (100, 100, zero_rate()));
+
+        // All routes go through the edge 2 -> 4, so only one route is found:
+        assert_eq!(
+            cg.get_routes(&0, &4, 10, &RouteConstraints::default(), 4),
+            vec![(vec![0, 2, 4], 100, 1)]
+        );
+
+        // Routes to 2 do not share any edge, and are ranked by their fees:
+        assert_eq!(
+            cg.get_routes(&0, &2, 10, &RouteConstraints::default(), 4),
+            vec![
+                (vec![0, 2], 100, 0),
+                (vec![0, 3, 2], 100, 2),
+                (vec![0, 1, 2], 100, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_route_constraints() {
+        let cg = example_capacity_graph();
+
+        // Excluded nodes:
+        let mut constraints = RouteConstraints::default();
+        constraints.exclude_nodes.insert(2);
+        assert_eq!(cg.get_route(&1, &2, 5, &constraints), None);
+        constraints.exclude_nodes = vec![4].into_iter().collect();
+        assert_eq!(cg.get_route(&0, &5, 25, &constraints), None);
+        assert_eq!(
+            cg.get_route(&1, &2, 5, &constraints),
+            Some((vec![1, 2], 10, 0))
+        );
+
+        // Waypoints must be visited in order:
+        let mut constraints = RouteConstraints::default();
+        constraints.waypoints = vec![4];
+        assert_eq!(
+            cg.get_route(&1, &2, 5, &constraints),
+            Some((vec![1, 3, 4, 2], 30, 0))
+        );
+        constraints.waypoints = vec![3, 4];
+        assert_eq!(
+            cg.get_route(&0, &5, 5, &constraints),
+            Some((vec![0, 1, 3, 4, 2, 5], 30, 0))
+        );
+        constraints.waypoints = vec![4, 3];
+        assert_eq!(cg.get_route(&0, &5, 5, &constraints), None);
+        // A route may not visit a node twice:
+        constraints.waypoints = vec![2];
+        assert_eq!(cg.get_route(&1, &2, 5, &constraints), None);
+        // A waypoint can not be excluded:
+        constraints.waypoints = vec![4];
+        constraints.exclude_nodes.insert(4);
+        assert_eq!(cg.get_route(&1, &2, 5, &constraints), None);
+
+        // Maximum route length:
+        let mut constraints = RouteConstraints::default();
+        constraints.opt_max_route_len = Some(6);
+        assert_eq!(
+            cg.get_route(&0, &5, 25, &constraints),
+            Some((vec![0, 1, 3, 4, 2, 5], 30, 0))
+        );
+        constraints.opt_max_route_len = Some(5);
+        assert_eq!(cg.get_route(&0, &5, 25, &constraints), None);
+        constraints.waypoints = vec![4];
+        constraints.opt_max_route_len = Some(4);
+        assert_eq!(
+            cg.get_route(&1, &2, 5, &constraints),
+            Some((vec![1, 3, 4, 2], 30, 0))
+        );
+        constraints.opt_max_route_len = Some(3);
+        assert_eq!(cg.get_route(&1, &2, 5, &constraints), None);
+    }
+
+    #[test]
+    fn test_get_routes_constraints() {
+        let cg = example_capacity_graph();
+
+        let mut constraints = RouteConstraints::default();
+        constraints.opt_max_route_len = Some(2);
+        assert_eq!(
+            cg.get_routes(&1, &2, 5, &constraints, 4),
+            vec![(vec![1, 2], 10, 0)]
+        );
+
+        let mut constraints = RouteConstraints::default();
+        constraints.exclude_nodes.insert(3);
+        assert_eq!(
+            cg.get_routes(&1, &2, 5, &constraints, 4),
+            vec![(vec![1, 2], 10, 0)]
+        );
+    }
+
+    #[test]
+    fn test_get_max_flow_routes() {
+        let cg = example_capacity_graph();
+
+        let mut routes = cg.get_max_flow_routes(&1, &2, &RouteConstraints::default());
+        routes.sort();
+        assert_eq!(routes, vec![(vec![1, 2], 10, 0), (vec![1, 3, 4, 2], 30, 0)]);
+
+        // The flow is limited by the edge 0 -> 1:
+        let routes = cg.get_max_flow_routes(&0, &5, &RouteConstraints::default());
+        let total_flow: u128 = routes.iter().map(|(_route, flow, _fees)| flow).sum();
+        assert_eq!(total_flow, 30);
+
+        // The excluded edge is not used by any of the routes:
+        assert_eq!(
+            cg.get_max_flow_routes(&1, &2, &exclude_edges(&[(1, 2)])),
+            vec![(vec![1, 3, 4, 2], 30, 0)]
+        );
+
+        // The flow is limited by the edge 5 -> 2:
+        assert_eq!(
+            cg.get_max_flow_routes(&5, &0, &RouteConstraints::default()),
+            vec![(vec![5, 2, 1, 0], 5, 0)]
+        );
+        assert_eq!(
+            cg.get_max_flow_routes(&0, &6, &RouteConstraints::default()),
+            vec![]
+        );
+        assert_eq!(
+            cg.get_max_flow_routes(&1, &1, &RouteConstraints::default()),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_get_max_flow_routes_fees() {
+        /*
+         * Example graph:
+         *
+         *       1
+         *     /   \
+         *    0     2
+         *     \   /
+         *       3
+         *
+         * 1 charges 0 half of the payment. 3 charges 0 a fee of 10 credits.
+         */
+
+        let half_rate = Rate {
+            mul: 1 << 31,
+            add: 0,
+        };
+        let mut cg = SimpleCapacityGraph::<u32, Rate>::new();
+
+        cg.update_edge(0, 1, (30, 100, zero_rate()));
+        cg.update_edge(1, 0, (100, 100, half_rate));
+        cg.update_edge(1, 2, (100, 100, zero_rate()));
+        cg.update_edge(2, 1, (100, 100, zero_rate()));
+
+        cg.update_edge(0, 3, (5, 100, zero_rate()));
+        cg.update_edge(3, 0, (100, 100, Rate { mul: 0, add: 10 }));
+        cg.update_edge(3, 2, (100, 100, zero_rate()));
+        cg.update_edge(2, 3, (100, 100, zero_rate()));
+
+        // 20 credits can be delivered through the route 0 -> 1 -> 2, paying 10 credits of fees.
+        // The route through 3 can not carry its fee, so it is dropped:
+        assert_eq!(
+            cg.get_max_flow_routes(&0, &2, &RouteConstraints::default()),
+            vec![(vec![0, 1, 2], 30, 10)]
+        );
+    }
+
+    #[test]
+    fn test_get_max_flow_routes_constraints() {
+        let cg = example_capacity_graph();
+
+        let mut constraints = RouteConstraints::default();
+        constraints.exclude_nodes.insert(3);
+        assert_eq!(
+            cg.get_max_flow_routes(&1, &2, &constraints),
+            vec![(vec![1, 2], 10, 0)]
+        );
+
+        // Routes of the flow that skip a waypoint are dropped:
+        let mut constraints = RouteConstraints::default();
+        constraints.waypoints = vec![4];
+        assert_eq!(
+            cg.get_max_flow_routes(&1, &2, &constraints),
+            vec![(vec![1, 3, 4, 2], 30, 0)]
+        );
+
+        // Routes of the flow that are too long are dropped:
+        let mut constraints = RouteConstraints::default();
+        constraints.opt_max_route_len = Some(2);
+        assert_eq!(
+            cg.get_max_flow_routes(&1, &2, &constraints),
+            vec![(vec![1, 2], 10, 0)]
+        );
+    }
+
+    #[test]
+    fn test_saved_edges_restore() {
+        let mut cg = example_capacity_graph();
+        cg.tick(&1);
+
+        let mut saved_edges = cg.saved_edges();
+        saved_edges.sort_by_key(|(a, b, _edge, _age)| (*a, *b));
+        assert_eq!(saved_edges.len(), 12);
+        assert_eq!(saved_edges[0], (0, 1, (30, 10, zero_rate()), 0));
+
+        let mut restored_cg = SimpleCapacityGraph::<u32, Rate>::new();
+        for saved_edge in saved_edges {
+            restored_cg.restore_edge(saved_edge);
+        }
+        assert_eq!(
+            restored_cg.get_routes(&0, &5, 25, &RouteConstraints::default(), 4),
+            vec![(vec![0, 1, 3, 4, 2, 5], 30, 0)]
+        );
+
+        // Restored edges are considered aged:
+        for (_a, _b, _edge, age) in restored_cg.saved_edges() {
+            assert_eq!(age, BASE_MAX_EDGE_AGE);
+        }
+
+        // We hear from nodes 0 and 1. All other nodes were only restored:
+        restored_cg.tick(&0);
+        restored_cg.update_edge(1, 0, (10, 30, zero_rate()));
+        restored_cg.remove_restored_nodes();
+
+        let mut nodes = restored_cg.nodes.keys().cloned().collect::<Vec<_>>();
+        nodes.sort();
+        assert_eq!(nodes, vec![0, 1]);
     }
 
     #[test]
     fn test_simple_capacity_graph_tick() {
-        let mut cg = SimpleCapacityGraph::<u32>::new();
+        let mut cg = SimpleCapacityGraph::<u32, Rate>::new();
 
-        cg.update_edge(0, 1, (30, 10));
-        cg.update_edge(1, 0, (10, 30));
+        cg.update_edge(0, 1, (30, 10, zero_rate()));
+        cg.update_edge(1, 0, (10, 30, zero_rate()));
 
-        cg.update_edge(2, 3, (30, 10));
-        cg.update_edge(3, 2, (10, 30));
+        cg.update_edge(2, 3, (30, 10, zero_rate()));
+        cg.update_edge(3, 2, (10, 30, zero_rate()));
 
-        assert_eq!(cg.get_route(&0, &1, 30, None), Some((vec![0, 1], 30)));
-        assert_eq!(cg.get_route(&2, &3, 30, None), Some((vec![2, 3], 30)));
+        assert_eq!(
+            cg.get_route(&0, &1, 30, &RouteConstraints::default()),
+            Some((vec![0, 1], 30, 0))
+        );
+        assert_eq!(
+            cg.get_route(&2, &3, 30, &RouteConstraints::default()),
+            Some((vec![2, 3], 30, 0))
+        );
 
         let max_edge_age = max_edge_age(1);
         for _ in 0..max_edge_age - 1 {
             cg.tick(&0);
-            assert_eq!(cg.get_route(&0, &1, 30, None), Some((vec![0, 1], 30)));
-            assert_eq!(cg.get_route(&2, &3, 30, None), Some((vec![2, 3], 30)));
+            assert_eq!(
+                cg.get_route(&0, &1, 30, &RouteConstraints::default()),
+                Some((vec![0, 1], 30, 0))
+            );
+            assert_eq!(
+                cg.get_route(&2, &3, 30, &RouteConstraints::default()),
+                Some((vec![2, 3], 30, 0))
+            );
         }
 
         // At this point 0->1 and 1->0 should expire, but 2->3 and 3->2 don't expire:
         cg.tick(&0);
-        assert_eq!(cg.get_route(&0, &1, 30, None), None);
-        assert_eq!(cg.get_route(&2, &3, 30, None), Some((vec![2, 3], 30)));
+        assert_eq!(cg.get_route(&0, &1, 30, &RouteConstraints::default()), None);
+        assert_eq!(
+            cg.get_route(&2, &3, 30, &RouteConstraints::default()),
+            Some((vec![2, 3], 30, 0))
+        );
     }
 }
@@ -28,13 +28,4 @@ impl<I> OptionIterator<I> {
     }
 }
 
-/// Util function to convert Option<T> to Vec<T>.
-/// Some(t) => vec![t], None => vec![]
-pub fn option_to_vec<T>(opt_t: Option<T>) -> Vec<T> {
-    match opt_t {
-        Some(t) => vec![t],
-        None => vec![],
-    }
-}
-
 // TODO: add tests
@@ -4,6 +4,7 @@
 #![feature(generators)]
 #![feature(never_type)]
 #![feature(map_get_key_value)]
+#![cfg_attr(feature = "bench", feature(test))]
 #![deny(trivial_numeric_casts, warnings)]
 #![allow(intra_doc_link_resolution_failure)]
 #![allow(
@@ -22,7 +23,15 @@ extern crate common;
 mod backoff_connector;
 mod graph;
 mod net_server;
+mod rate_limit;
 mod server;
+mod signed_edges;
+mod snapshot;
+mod trusted_watcher;
 mod verifier;
 
+pub use graph::GraphBackend;
 pub use net_server::{net_index_server, NetIndexServerError};
+pub use rate_limit::ClientLimits;
+pub use server::TrustedServersMutation;
+pub use trusted_watcher::{trusted_servers_watcher, TrustedWatcherError};
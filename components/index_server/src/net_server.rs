@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::marker::Unpin;
+use std::path::PathBuf;
 
 use futures::channel::mpsc;
 use futures::task::{Spawn, SpawnExt};
@@ -9,12 +10,18 @@ use futures::{FutureExt, SinkExt, Stream, StreamExt, TryFutureExt};
 use common::conn::{BoxFuture, ConnPair, ConnPairVec, FuncFutTransform, FutTransform};
 use common::transform_pool::transform_pool_loop;
 
-use proto::consts::{INDEX_NODE_TIMEOUT_TICKS, KEEPALIVE_TICKS, PROTOCOL_VERSION, TICKS_TO_REKEY};
+use proto::consts::{
+    INDEX_NODE_TIMEOUT_TICKS, INDEX_SNAPSHOT_TICKS, KEEPALIVE_TICKS, PROTOCOL_VERSION,
+    TICKS_TO_REKEY,
+};
+use proto::funder::messages::{Currency, Rate};
 use proto::index_server::messages::{
-    IndexClientToServer, IndexServerToClient, IndexServerToServer,
+    IndexAdminToServer, IndexClientToServer, IndexServerToAdmin, IndexServerToClient,
+    IndexServerToServer,
 };
 use proto::index_server::serialize::{
-    deserialize_index_client_to_server, deserialize_index_server_to_server,
+    deserialize_index_admin_to_server, deserialize_index_client_to_server,
+    deserialize_index_server_to_server, serialize_index_server_to_admin,
     serialize_index_server_to_client, serialize_index_server_to_server,
 };
 
@@ -28,40 +35,90 @@ use keepalive::KeepAliveChannel;
 use secure_channel::SecureChannel;
 use version::VersionPrefix;
 
-use crate::server::{server_loop, ServerLoopError};
-pub use crate::server::{ClientConn, ServerConn};
+use crate::server::{server_loop, ServerLoopError, TrustedServersMutation};
+pub use crate::server::{AdminConn, ClientConn, ServerConn};
 
 use crate::backoff_connector::BackoffConnector;
-use crate::graph::graph_service::create_graph_service;
+use crate::graph::capacity_graph::CapacityGraph;
+use crate::graph::compact_capacity_graph::CompactCapacityGraph;
+use crate::graph::graph_service::{create_graph_service, restore_capacity_graphs, GraphClient};
 use crate::graph::simple_capacity_graph::SimpleCapacityGraph;
+use crate::graph::GraphBackend;
+use crate::rate_limit::ClientLimits;
+use crate::snapshot::{load_snapshot_from_file, snapshot_loop, SnapshotError};
 use crate::verifier::simple_verifier::SimpleVerifier;
 
 #[derive(Debug)]
 pub enum IndexServerError {
     RequestTimerStreamError,
     CreateGraphServiceError,
+    LoadSnapshotError(SnapshotError),
+    SpawnSnapshotLoopError,
     ServerLoopError(ServerLoopError),
 }
 
+/// Create a graph service that uses the capacity graph implementation `CG`.
+/// If `opt_snapshot_path` points to an existing file, the graph is restored from this file.
+fn create_index_graph_service<CG, GS, S>(
+    opt_snapshot_path: &Option<PathBuf>,
+    graph_service_spawner: GS,
+    spawner: S,
+) -> Result<GraphClient<Currency, PublicKey, u128, Rate>, IndexServerError>
+where
+    CG: CapacityGraph<Node = PublicKey, Capacity = u128, Rate = Rate> + Default + Send + 'static,
+    GS: Spawn + Send + 'static,
+    S: Spawn,
+{
+    // Every currency has its own capacity graph. Graphs are created on demand:
+    let capacity_graphs = match opt_snapshot_path {
+        Some(snapshot_path) if snapshot_path.exists() => {
+            let graph_snapshot = load_snapshot_from_file(snapshot_path)
+                .map_err(IndexServerError::LoadSnapshotError)?;
+            restore_capacity_graphs(graph_snapshot)
+        }
+        _ => HashMap::<_, CG>::new(),
+    };
+    create_graph_service(capacity_graphs, graph_service_spawner, spawner)
+        .map_err(|_| IndexServerError::CreateGraphServiceError)
+}
+
 /// Run an index server
 /// Will keep running until an error occurs.
-async fn index_server<A, IS, IC, SC, R, GS, S>(
+///
+/// If `opt_snapshot_path` is provided, the graph is restored from this file at startup (if it
+/// exists), and a snapshot of the graph is saved to this file periodically.
+///
+/// Changes to the set of trusted servers are received through `incoming_trusted_mutations`.
+///
+/// `client_limits` limits the load every client may put on the server.
+///
+/// `graph_backend` selects the capacity graph implementation.
+///
+/// Statistics about the server can be queried through `incoming_admin_connections`.
+async fn index_server<A, IS, IC, IA, TM, SC, R, GS, S>(
     local_public_key: PublicKey,
     trusted_servers: HashMap<PublicKey, A>,
     incoming_server_connections: IS,
     incoming_client_connections: IC,
+    incoming_admin_connections: IA,
+    incoming_trusted_mutations: TM,
     server_connector: SC,
     mut timer_client: TimerClient,
     ticks_to_live: usize,
     backoff_ticks: usize,
+    client_limits: ClientLimits,
+    opt_snapshot_path: Option<PathBuf>,
+    graph_backend: GraphBackend,
     rng: R,
     graph_service_spawner: GS,
-    spawner: S,
+    mut spawner: S,
 ) -> Result<(), IndexServerError>
 where
     A: Debug + Send + Clone + 'static,
     IS: Stream<Item = (PublicKey, ServerConn)> + Unpin + Send,
     IC: Stream<Item = (PublicKey, ClientConn)> + Unpin + Send,
+    IA: Stream<Item = AdminConn> + Unpin + Send,
+    TM: Stream<Item = TrustedServersMutation<A>> + Unpin + Send,
     SC: FutTransform<Input = (PublicKey, A), Output = Option<ServerConn>> + Clone + Send + 'static,
     R: CryptoRandom,
     S: Spawn + Clone + Send,
@@ -69,9 +126,37 @@ where
 {
     let verifier = SimpleVerifier::new(ticks_to_live, rng);
 
-    let capacity_graph = SimpleCapacityGraph::new();
-    let graph_client = create_graph_service(capacity_graph, graph_service_spawner, spawner.clone())
-        .map_err(|_| IndexServerError::CreateGraphServiceError)?;
+    let graph_client = match graph_backend {
+        GraphBackend::Simple => create_index_graph_service::<SimpleCapacityGraph<_, _>, _, _>(
+            &opt_snapshot_path,
+            graph_service_spawner,
+            spawner.clone(),
+        )?,
+        GraphBackend::Compact => create_index_graph_service::<CompactCapacityGraph<_, _>, _, _>(
+            &opt_snapshot_path,
+            graph_service_spawner,
+            spawner.clone(),
+        )?,
+    };
+
+    if let Some(snapshot_path) = opt_snapshot_path {
+        let snapshot_timer_stream = await!(timer_client.request_timer_stream())
+            .map_err(|_| IndexServerError::RequestTimerStreamError)?;
+        // Restored nodes we don't hear from are removed after the same amount of ticks it takes
+        // to remove an idle node:
+        let snapshot_loop_fut = snapshot_loop(
+            graph_client.clone(),
+            snapshot_timer_stream,
+            snapshot_path,
+            INDEX_SNAPSHOT_TICKS,
+            ticks_to_live,
+        )
+        .map_err(|e| error!("snapshot_loop() error: {:?}", e))
+        .map(|_| ());
+        spawner
+            .spawn(snapshot_loop_fut)
+            .map_err(|_| IndexServerError::SpawnSnapshotLoopError)?;
+    }
 
     let timer_stream = await!(timer_client.request_timer_stream())
         .map_err(|_| IndexServerError::RequestTimerStreamError)?;
@@ -83,10 +168,13 @@ where
         trusted_servers,
         incoming_server_connections,
         incoming_client_connections,
+        incoming_admin_connections,
+        incoming_trusted_mutations,
         backoff_connector,
         graph_client,
         compare_public_key,
         verifier,
+        client_limits,
         timer_stream,
         spawner,
         None
@@ -259,6 +347,59 @@ where
         )
     }
 
+    /// Transform a raw connection from an admin into connection with the following layers:
+    /// - Version prefix
+    /// - Serialization
+    ///
+    /// Admin connections are not encrypted, and should only be accepted from the local machine.
+    pub fn incoming_index_admin_conn_transform(
+        &self,
+        conn_pair: ConnPairVec,
+    ) -> BoxFuture<'_, Option<ConnPair<IndexServerToAdmin, IndexAdminToServer>>> {
+        let mut c_self = self.clone();
+        Box::pin(
+            async move {
+                let (mut sender, mut receiver) =
+                    await!(c_self.version_transform.transform(conn_pair));
+
+                let (user_sender, mut from_user_sender) = mpsc::channel(0);
+                let (mut to_user_receiver, user_receiver) = mpsc::channel(0);
+
+                // Deserialize received data
+                let _ = c_self.spawner.spawn(
+                    async move {
+                        while let Some(data) = await!(receiver.next()) {
+                            let message = match deserialize_index_admin_to_server(&data) {
+                                Ok(message) => message,
+                                Err(_) => {
+                                    error!("Error deserializing index_admin_to_server");
+                                    return;
+                                }
+                            };
+                            if await!(to_user_receiver.send(message)).is_err() {
+                                return;
+                            }
+                        }
+                    },
+                );
+
+                // Serialize sent data:
+                let _ = c_self.spawner.spawn(
+                    async move {
+                        while let Some(message) = await!(from_user_sender.next()) {
+                            let data = serialize_index_server_to_admin(&message);
+                            if await!(sender.send(data)).is_err() {
+                                return;
+                            }
+                        }
+                    },
+                );
+
+                Some((user_sender, user_receiver))
+            },
+        )
+    }
+
     pub fn outgoing_index_server_conn_transform(
         &self,
         public_key: PublicKey,
@@ -316,16 +457,21 @@ pub enum NetIndexServerError {
     SpawnError,
 }
 
-pub async fn net_index_server<A, ICC, ISC, SC, R, GS, S>(
+pub async fn net_index_server<A, ICC, ISC, IAC, TM, SC, R, GS, S>(
     incoming_client_raw_conns: ICC,
     incoming_server_raw_conns: ISC,
+    incoming_admin_raw_conns: IAC,
     raw_server_net_connector: SC,
     identity_client: IdentityClient,
     timer_client: TimerClient,
     rng: R,
     trusted_servers: HashMap<PublicKey, A>,
+    incoming_trusted_mutations: TM,
     max_concurrent_encrypt: usize,
     backoff_ticks: usize,
+    client_limits: ClientLimits,
+    opt_snapshot_path: Option<PathBuf>,
+    graph_backend: GraphBackend,
     graph_service_spawner: GS,
     mut spawner: S,
 ) -> Result<(), NetIndexServerError>
@@ -334,6 +480,8 @@ where
     SC: FutTransform<Input = A, Output = Option<ConnPairVec>> + Clone + Send + 'static,
     ICC: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
     ISC: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
+    IAC: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
+    TM: Stream<Item = TrustedServersMutation<A>> + Unpin + Send,
     R: CryptoRandom + Clone + 'static,
     GS: Spawn + Send + 'static,
     S: Spawn + Clone + Send + Sync + 'static,
@@ -404,6 +552,28 @@ where
         .spawn(pool_fut)
         .map_err(|_| NetIndexServerError::SpawnError)?;
 
+    // Transform incoming admin connections:
+    let c_conn_transformer = conn_transformer.clone();
+    let incoming_admin_transform = FuncFutTransform::new(move |raw_conn| {
+        let c_conn_transformer = c_conn_transformer.clone();
+        Box::pin(
+            async move { await!(c_conn_transformer.incoming_index_admin_conn_transform(raw_conn)) },
+        )
+    });
+    let (admin_conns_sender, incoming_admin_conns) = mpsc::channel(0);
+    let pool_fut = transform_pool_loop(
+        incoming_admin_raw_conns,
+        admin_conns_sender,
+        incoming_admin_transform,
+        max_concurrent_encrypt,
+        spawner.clone(),
+    )
+    .map_err(|e| error!("admin incoming transform_pool_loop() error: {:?}", e))
+    .map(|_| ());
+    spawner
+        .spawn(pool_fut)
+        .map_err(|_| NetIndexServerError::SpawnError)?;
+
     // Apply transform to create server connector:
     let c_conn_transformer = conn_transformer.clone();
     let server_connector = FuncFutTransform::new(move |(public_key, net_address)| {
@@ -422,10 +592,15 @@ where
         trusted_servers,
         incoming_server_conns,
         incoming_client_conns,
+        incoming_admin_conns,
+        incoming_trusted_mutations,
         server_connector,
         timer_client,
         INDEX_NODE_TIMEOUT_TICKS,
         backoff_ticks,
+        client_limits,
+        opt_snapshot_path,
+        graph_backend,
         rng,
         graph_service_spawner,
         spawner.clone()
@@ -0,0 +1,88 @@
+/// Limits on the load clients may put on the index server.
+#[derive(Debug, Clone)]
+pub struct ClientLimits {
+    /// Maximum amount of route requests a client may send in a burst.
+    pub max_request_tokens: usize,
+    /// Amount of route requests a client may send for every passing tick.
+    pub request_refill_tokens: usize,
+    /// Maximum amount of mutations updates a client may send in a burst.
+    pub max_mutations_tokens: usize,
+    /// Amount of mutations updates a client may send for every passing tick.
+    pub mutations_refill_tokens: usize,
+    /// Maximum amount of route requests handled at the same time (For all clients together).
+    pub max_open_requests: usize,
+}
+
+/// A token bucket, used to limit the rate of messages from a client.
+/// Every message takes one token. The bucket is refilled every tick, and can hold at most
+/// `max_tokens` tokens.
+#[derive(Debug)]
+pub struct TokenBucket {
+    tokens: usize,
+    max_tokens: usize,
+    refill_tokens: usize,
+}
+
+impl TokenBucket {
+    /// Create a full token bucket
+    pub fn new(max_tokens: usize, refill_tokens: usize) -> Self {
+        TokenBucket {
+            tokens: max_tokens,
+            max_tokens,
+            refill_tokens,
+        }
+    }
+
+    /// Attempt to take a token from the bucket.
+    /// Returns false if the bucket is empty.
+    pub fn try_take(&mut self) -> bool {
+        if self.tokens == 0 {
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
+
+    /// Refill the bucket. Should be called every tick.
+    pub fn tick(&mut self) {
+        self.tokens = self
+            .tokens
+            .saturating_add(self.refill_tokens)
+            .min(self.max_tokens);
+    }
+
+    /// Does the bucket hold the maximum amount of tokens?
+    pub fn is_full(&self) -> bool {
+        self.tokens == self.max_tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_basic() {
+        let mut token_bucket = TokenBucket::new(3, 2);
+        assert!(token_bucket.is_full());
+        for _ in 0..3 {
+            assert!(token_bucket.try_take());
+        }
+        assert!(!token_bucket.try_take());
+
+        token_bucket.tick();
+        assert!(token_bucket.try_take());
+        assert!(token_bucket.try_take());
+        assert!(!token_bucket.try_take());
+
+        // The bucket never holds more than `max_tokens` tokens:
+        for _ in 0..5 {
+            token_bucket.tick();
+        }
+        assert!(token_bucket.is_full());
+        for _ in 0..3 {
+            assert!(token_bucket.try_take());
+        }
+        assert!(!token_bucket.try_take());
+    }
+}
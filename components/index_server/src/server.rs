@@ -1,29 +1,42 @@
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::cmp::{self, Ordering};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::marker::Unpin;
+use std::time::{Duration, Instant};
 
 use futures::channel::{mpsc, oneshot};
 use futures::task::{Spawn, SpawnExt};
 use futures::{future, select, stream, FutureExt, SinkExt, Stream, StreamExt, TryFutureExt};
 
 use common::conn::{ConnPair, FutTransform};
+use common::int_convert::{u32_to_usize, usize_to_u64};
 use common::select_streams::{select_streams, BoxStream};
 
 use crypto::identity::PublicKey;
 use crypto::uid::Uid;
 
 use proto::index_server::messages::{
-    ForwardMutationsUpdate, IndexClientToServer, IndexMutation, IndexServerToClient,
-    IndexServerToServer, MutationsUpdate, ResponseRoutes, RouteWithCapacity, TimeProofLink,
+    CurrencyGraphStats, ForwardMutationsUpdate, IndexAdminToServer, IndexClientToServer,
+    IndexMutation, IndexServerStats, IndexServerToAdmin, IndexServerToClient, IndexServerToServer,
+    MutationsUpdate, NeighborEdge, NodeDigest, NodeEdges, RejectReason, RequestNeighbors,
+    RequestRejected, RequestRoutes, RequestRoutesMode, ResponseNeighbors, ResponseRoutes,
+    RouteWithCapacity, RoutesRequestsStats, ServerDigest, TimeProofLink,
 };
 
-use proto::funder::messages::FriendsRoute;
+use proto::consts::{MAX_NODE_DIGESTS_PER_MESSAGE, MAX_ROUTES_PER_REQUEST};
+use proto::funder::messages::{Currency, FriendsRoute, Rate};
 
+use crate::graph::capacity_graph::RouteConstraints;
 use crate::graph::graph_service::{GraphClient, GraphClientError};
+use crate::rate_limit::{ClientLimits, TokenBucket};
+use crate::signed_edges::SignedEdges;
 use crate::verifier::Verifier;
 
 pub type ServerConn = ConnPair<IndexServerToServer, IndexServerToServer>;
 pub type ClientConn = ConnPair<IndexServerToClient, IndexClientToServer>;
+pub type AdminConn = ConnPair<IndexServerToAdmin, IndexAdminToServer>;
+
+/// Amount of recent routes requests used for calculating the routes requests latency
+const NUM_LATENCY_SAMPLES: usize = 0x100;
 
 #[derive(Debug)]
 pub enum ServerLoopError {
@@ -33,6 +46,8 @@ pub enum ServerLoopError {
     ClientEventSenderError,
     ClientSenderError,
     RemoteSendError,
+    AdminEventSenderError,
+    AdminSenderError,
 }
 
 /// A connected remote entity
@@ -65,6 +80,110 @@ impl<T> Connected<T> {
         }
         Ok(())
     }
+
+    /// Close the connection to the remote entity.
+    /// Further send attempts are ignored.
+    pub fn close(&mut self) {
+        self.opt_sender = None;
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.opt_sender.is_none()
+    }
+}
+
+/// Token buckets limiting the rate of messages from a client.
+/// Kept by the client's public key (And not by connection), so that reconnecting does not refill
+/// them.
+#[derive(Debug)]
+struct ClientBuckets {
+    requests_bucket: TokenBucket,
+    mutations_bucket: TokenBucket,
+}
+
+impl ClientBuckets {
+    pub fn new(client_limits: &ClientLimits) -> Self {
+        ClientBuckets {
+            requests_bucket: TokenBucket::new(
+                client_limits.max_request_tokens,
+                client_limits.request_refill_tokens,
+            ),
+            mutations_bucket: TokenBucket::new(
+                client_limits.max_mutations_tokens,
+                client_limits.mutations_refill_tokens,
+            ),
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.requests_bucket.tick();
+        self.mutations_bucket.tick();
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.requests_bucket.is_full() && self.mutations_bucket.is_full()
+    }
+}
+
+fn duration_to_micros(duration: Duration) -> u64 {
+    duration
+        .as_secs()
+        .saturating_mul(1_000_000)
+        .saturating_add(u64::from(duration.subsec_micros()))
+}
+
+/// Statistics about the routes requests sent to the server, reported to admins.
+#[derive(Debug, Default)]
+struct RoutesRequestsCounter {
+    num_handled: u64,
+    num_rejected: u64,
+    /// The time it took to handle each of the most recent requests
+    recent_latencies: VecDeque<Duration>,
+}
+
+impl RoutesRequestsCounter {
+    pub fn add_handled(&mut self, latency: Duration) {
+        self.num_handled = self.num_handled.saturating_add(1);
+        if self.recent_latencies.len() >= NUM_LATENCY_SAMPLES {
+            self.recent_latencies.pop_front();
+        }
+        self.recent_latencies.push_back(latency);
+    }
+
+    pub fn add_rejected(&mut self) {
+        self.num_rejected = self.num_rejected.saturating_add(1);
+    }
+
+    pub fn stats(&self, num_open: usize) -> RoutesRequestsStats {
+        let latencies_micros = self
+            .recent_latencies
+            .iter()
+            .cloned()
+            .map(duration_to_micros);
+        let total_latency_micros = latencies_micros
+            .clone()
+            .fold(0u64, |total, micros| total.saturating_add(micros));
+        let avg_latency_micros = match usize_to_u64(self.recent_latencies.len()).unwrap() {
+            0 => 0,
+            num_latencies => total_latency_micros / num_latencies,
+        };
+
+        RoutesRequestsStats {
+            num_handled: self.num_handled,
+            num_rejected: self.num_rejected,
+            num_open: usize_to_u64(num_open).unwrap(),
+            avg_latency_micros,
+            max_latency_micros: latencies_micros.max().unwrap_or(0),
+        }
+    }
+}
+
+/// A change to the set of trusted servers of a running index server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrustedServersMutation<A> {
+    /// Add a trusted server, or update the address of an existing trusted server.
+    AddServer(PublicKey, A),
+    RemoveServer(PublicKey),
 }
 
 #[derive(Debug)]
@@ -86,15 +205,39 @@ struct RemoteServer<A> {
     state: RemoteServerState,
 }
 
+/// Synchronization of the nodes state with a connected remote server.
+#[derive(Debug, Default)]
+struct RemoteSync {
+    /// Parts of the remote server's digest received so far
+    node_digests: Vec<NodeDigest>,
+    /// Nodes whose current state the remote server is missing.
+    /// Filled once the last part of the remote server's digest is received.
+    pending_nodes: HashSet<PublicKey>,
+}
+
 struct IndexServer<A, S, SC, V, CMP> {
     local_public_key: PublicKey,
     server_connector: SC,
-    graph_client: GraphClient<PublicKey, u128>,
+    graph_client: GraphClient<Currency, PublicKey, u128, Rate>,
     verifier: V,
     compare_public_key: CMP,
     remote_servers: HashMap<PublicKey, RemoteServer<A>>,
     clients: HashMap<PublicKey, Connected<IndexServerToClient>>,
-    event_sender: mpsc::Sender<IndexServerEvent>,
+    /// Token buckets of connected clients, and of recently disconnected clients until their
+    /// buckets are full again.
+    client_buckets: HashMap<PublicKey, ClientBuckets>,
+    client_limits: ClientLimits,
+    /// Amount of route requests currently being handled
+    num_open_requests: usize,
+    routes_requests: RoutesRequestsCounter,
+    /// The latest verified mutations update of every node. Its time proof is used to prove the
+    /// freshness of the node's state to remote servers that have missed it.
+    last_mutations: HashMap<PublicKey, ForwardMutationsUpdate>,
+    /// The signed mutations updates that have set the current edges of every node.
+    /// Sent to remote servers that are missing the state of the node, as proof of the edges.
+    signed_edges: HashMap<PublicKey, SignedEdges>,
+    remote_syncs: HashMap<PublicKey, RemoteSync>,
+    event_sender: mpsc::Sender<IndexServerEvent<A>>,
     spawner: S,
 }
 
@@ -106,12 +249,18 @@ impl From<GraphClientError> for ServerLoopError {
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
-enum IndexServerEvent {
+enum IndexServerEvent<A> {
     ServerConnection((PublicKey, ServerConn)),
     FromServer((PublicKey, Option<IndexServerToServer>)),
     ClientConnection((PublicKey, ClientConn)),
     ClientClosed(PublicKey),
-    ClientMutationsUpdate(MutationsUpdate),
+    ClientMutationsUpdate((PublicKey, MutationsUpdate)),
+    ClientRequestRoutes((PublicKey, RequestRoutes)),
+    /// A routes request was handled. Contains the time it took to handle the request.
+    RequestRoutesDone(Duration),
+    AdminConnection(AdminConn),
+    AdminRequest((mpsc::Sender<IndexServerToAdmin>, IndexAdminToServer)),
+    TrustedServersMutation(TrustedServersMutation<A>),
     TimerTick,
     ClientListenerClosed,
     ServerListenerClosed,
@@ -143,10 +292,11 @@ where
         local_public_key: PublicKey,
         trusted_servers: HashMap<PublicKey, A>,
         server_connector: SC,
-        graph_client: GraphClient<PublicKey, u128>,
+        graph_client: GraphClient<Currency, PublicKey, u128, Rate>,
         compare_public_key: CMP,
         verifier: V,
-        event_sender: mpsc::Sender<IndexServerEvent>,
+        client_limits: ClientLimits,
+        event_sender: mpsc::Sender<IndexServerEvent<A>>,
         spawner: S,
     ) -> Result<Self, ServerLoopError> {
         let mut index_server = IndexServer {
@@ -157,6 +307,13 @@ where
             compare_public_key,
             remote_servers: HashMap::new(),
             clients: HashMap::new(),
+            client_buckets: HashMap::new(),
+            client_limits,
+            num_open_requests: 0,
+            routes_requests: RoutesRequestsCounter::default(),
+            last_mutations: HashMap::new(),
+            signed_edges: HashMap::new(),
+            remote_syncs: HashMap::new(),
             event_sender,
             spawner,
         };
@@ -207,19 +364,26 @@ where
         let cancellable_fut = async move {
             let server_conn_fut = c_server_connector.transform((public_key.clone(), c_address));
             let select_res = select! {
-                server_conn_fut = server_conn_fut.fuse() => server_conn_fut,
+                server_conn_fut = server_conn_fut.fuse() => Some(server_conn_fut),
                 _close_receiver = close_receiver.fuse() => None,
             };
-            if let Some(server_conn) = select_res {
-                let _ = await!(c_event_sender.send(IndexServerEvent::ServerConnection((
-                    public_key,
-                    server_conn
-                ))));
-            } else {
-                // Failed to connect, report that the server was closed
-                // TODO: Test this functionality:
-                let _ =
-                    await!(c_event_sender.send(IndexServerEvent::FromServer((public_key, None))));
+            match select_res {
+                Some(Some(server_conn)) => {
+                    let _ = await!(c_event_sender.send(IndexServerEvent::ServerConnection((
+                        public_key,
+                        server_conn
+                    ))));
+                }
+                Some(None) => {
+                    // Failed to connect, report that the server was closed
+                    // TODO: Test this functionality:
+                    let _ = await!(
+                        c_event_sender.send(IndexServerEvent::FromServer((public_key, None)))
+                    );
+                }
+                // The connection attempt was cancelled. This happens when the server is not
+                // trusted anymore, or when its address changes. Nothing to report.
+                None => {}
             }
         };
 
@@ -232,18 +396,79 @@ where
         Ok(RemoteServer { address, state })
     }
 
-    pub async fn handle_forward_mutations_update(
+    /// Add a trusted server, or update the address of an existing trusted server.
+    pub fn add_trusted_server(
         &mut self,
-        opt_server_public_key: Option<PublicKey>,
-        mut forward_mutations_update: ForwardMutationsUpdate,
+        public_key: PublicKey,
+        address: A,
     ) -> Result<(), ServerLoopError> {
+        if public_key == self.local_public_key {
+            warn!("Attempt to add the local server as a trusted server. Ignoring.");
+            return Ok(());
+        }
+
+        let remote_server = match self.remote_servers.remove(&public_key) {
+            None => {
+                info!("Adding trusted server {:?} at {:?}", public_key, address);
+                self.spawn_server(public_key.clone(), address)?
+            }
+            Some(mut remote_server) => {
+                info!(
+                    "Changing address of trusted server {:?}: {:?} -> {:?}",
+                    public_key, remote_server.address, address
+                );
+                match remote_server.state {
+                    // Restart the connection attempt using the new address.
+                    // The old connection attempt is cancelled when it is dropped:
+                    RemoteServerState::Initiating(_) => {
+                        self.spawn_server(public_key.clone(), address)?
+                    }
+                    // The new address will be used the next time we connect:
+                    RemoteServerState::Connected(_) | RemoteServerState::Listening => {
+                        remote_server.address = address;
+                        remote_server
+                    }
+                }
+            }
+        };
+        self.remote_servers.insert(public_key, remote_server);
+        Ok(())
+    }
+
+    /// Stop trusting a server. Any connection to the server is closed.
+    pub fn remove_trusted_server(&mut self, public_key: &PublicKey) {
+        match self.remote_servers.remove(public_key) {
+            None => warn!(
+                "Attempt to remove {:?}, which is not a trusted server. Ignoring.",
+                public_key
+            ),
+            Some(remote_server) => {
+                info!(
+                    "Removing trusted server {:?} at {:?}",
+                    public_key, remote_server.address
+                );
+                // The connection (Or connection attempt) is closed when `remote_server` is
+                // dropped.
+                let _ = self.verifier.remove_neighbor(public_key);
+                self.remote_syncs.remove(public_key);
+            }
+        }
+    }
+
+    /// Verify the signature and the freshness of a `ForwardMutationsUpdate`.
+    /// On success, a link proving the freshness to our neighbors is added to the time proof.
+    fn verify_forward_mutations_update(
+        &mut self,
+        opt_server_public_key: Option<&PublicKey>,
+        forward_mutations_update: &mut ForwardMutationsUpdate,
+    ) -> bool {
         // Check the signature:
         if !forward_mutations_update.mutations_update.verify_signature() {
             warn!(
-                "{}: handle_forward_mutations_update: Failed verifying signature from server {:?}",
+                "{}: verify_forward_mutations_update: Failed verifying signature from server {:?}",
                 self.local_public_key[0], opt_server_public_key
             );
-            return Ok(());
+            return false;
         }
 
         // Make sure that the signature is fresh, and that the message is not out of order:
@@ -263,57 +488,89 @@ where
             &mutations_update.session_id,
             mutations_update.counter,
         ) {
-            Some(hashes) => hashes,
+            Some(hashes) => hashes.to_vec(),
             None => {
-                warn!("{}: handle_forward_mutations_update: Failed verifying message from server {:?}",
+                warn!("{}: verify_forward_mutations_update: Failed verifying message from server {:?}",
                       self.local_public_key[0],
                       opt_server_public_key);
-                return Ok(());
+                return false;
             }
         };
 
+        // Add a link to the time proof:
+        forward_mutations_update
+            .time_proof_chain
+            .push(TimeProofLink { hashes });
+        true
+    }
+
+    pub async fn handle_forward_mutations_update(
+        &mut self,
+        opt_server_public_key: Option<PublicKey>,
+        mut forward_mutations_update: ForwardMutationsUpdate,
+    ) -> Result<(), ServerLoopError> {
+        if !self.verify_forward_mutations_update(
+            opt_server_public_key.as_ref(),
+            &mut forward_mutations_update,
+        ) {
+            return Ok(());
+        }
+
         // The message is valid and fresh.
+        let mutations_update = &forward_mutations_update.mutations_update;
+        let node_public_key = mutations_update.node_public_key.clone();
 
         // Expire old edges for `node_public_key`:
         // Note: This tick happens every time a message is received from this `node_public_key`,
         // and not every constant amount of time.
-        await!(self
-            .graph_client
-            .tick(mutations_update.node_public_key.clone()))
-        .map_err(|_| ServerLoopError::GraphClientError)?;
-
-        // Add a link to the time proof:
-        forward_mutations_update
-            .time_proof_chain
-            .push(TimeProofLink {
-                hashes: hashes.to_vec(),
-            });
+        await!(self.graph_client.tick(node_public_key.clone()))
+            .map_err(|_| ServerLoopError::GraphClientError)?;
 
         // Apply mutations:
         for index_mutation in &mutations_update.index_mutations {
             match index_mutation {
                 IndexMutation::UpdateFriend(update_friend) => {
                     info!(
-                        "pk: {}, send: {}, recv: {}",
+                        "pk: {}, currency: {:?}, send: {}, recv: {}, rate: {:?}",
                         update_friend.public_key[0],
+                        update_friend.currency,
                         update_friend.send_capacity,
-                        update_friend.recv_capacity
+                        update_friend.recv_capacity,
+                        update_friend.rate
                     );
 
                     await!(self.graph_client.update_edge(
-                        mutations_update.node_public_key.clone(),
+                        update_friend.currency.clone(),
+                        node_public_key.clone(),
                         update_friend.public_key.clone(),
-                        (update_friend.send_capacity, update_friend.recv_capacity)
+                        (
+                            update_friend.send_capacity,
+                            update_friend.recv_capacity,
+                            update_friend.rate.clone()
+                        )
                     ))?;
                 }
                 IndexMutation::RemoveFriend(friend_public_key) => {
-                    await!(self.graph_client.remove_edge(
-                        mutations_update.node_public_key.clone(),
+                    await!(self.graph_client.remove_edge_all_currencies(
+                        node_public_key.clone(),
                         friend_public_key.clone()
                     ))?;
                 }
             }
         }
+        self.signed_edges
+            .entry(node_public_key.clone())
+            .or_insert_with(SignedEdges::default)
+            .apply_mutations_update(mutations_update.clone());
+
+        // Servers that are missing the state of this node get the whole state instead of the
+        // mutations:
+        let pending_servers = self
+            .remote_syncs
+            .iter()
+            .filter(|(_, remote_sync)| remote_sync.pending_nodes.contains(&node_public_key))
+            .map(|(server_public_key, _)| server_public_key.clone())
+            .collect::<Vec<_>>();
 
         // Try to forward to all connected servers:
         for (server_public_key, connected_server) in self.iter_connected_servers() {
@@ -321,10 +578,458 @@ where
                 // Don't send back to the server who sent this ForwardMutationsUpdate message
                 continue;
             }
+            if pending_servers.contains(server_public_key) {
+                continue;
+            }
             let _ = connected_server.try_send(IndexServerToServer::ForwardMutationsUpdate(
                 forward_mutations_update.clone(),
             ));
         }
+
+        self.last_mutations
+            .insert(node_public_key.clone(), forward_mutations_update);
+
+        // The new time proof might allow proving the freshness of the node's state to servers that
+        // are missing it:
+        for server_public_key in pending_servers {
+            await!(self.send_node_states(server_public_key, vec![node_public_key.clone()]))?;
+        }
+        Ok(())
+    }
+
+    pub async fn handle_client_mutations_update(
+        &mut self,
+        public_key: PublicKey,
+        mutations_update: MutationsUpdate,
+    ) -> Result<(), ServerLoopError> {
+        let (connected_client, client_buckets) = match (
+            self.clients.get_mut(&public_key),
+            self.client_buckets.get_mut(&public_key),
+        ) {
+            (Some(connected_client), Some(client_buckets)) => (connected_client, client_buckets),
+            _ => {
+                warn!(
+                    "Mutations update from a non existent client {:?}. Ignoring.",
+                    public_key
+                );
+                return Ok(());
+            }
+        };
+
+        if connected_client.is_closed() {
+            // The connection is being closed:
+            return Ok(());
+        }
+
+        if !client_buckets.mutations_bucket.try_take() {
+            // Dropping the mutations update would leave the client's state on the server
+            // incomplete. Instead, the connection is closed. The client will reconnect and send
+            // its full state again.
+            warn!(
+                "Client {:?} sent too many mutations updates. Closing connection.",
+                public_key
+            );
+            connected_client.close();
+            return Ok(());
+        }
+
+        let forward_mutations_update = ForwardMutationsUpdate {
+            mutations_update,
+            time_proof_chain: Vec::new(),
+        };
+        await!(self.handle_forward_mutations_update(None, forward_mutations_update))
+    }
+
+    /// Handle a routes request from a client.
+    /// The request is rejected if too many requests are being handled, or if the client has sent
+    /// too many requests recently. Otherwise, the routes are found and sent to the client by a
+    /// separate task.
+    pub fn handle_client_request_routes(
+        &mut self,
+        public_key: PublicKey,
+        request_routes: RequestRoutes,
+    ) -> Result<(), ServerLoopError> {
+        let (connected_client, client_buckets) = match (
+            self.clients.get_mut(&public_key),
+            self.client_buckets.get_mut(&public_key),
+        ) {
+            (Some(connected_client), Some(client_buckets)) => (connected_client, client_buckets),
+            _ => {
+                warn!(
+                    "Routes request from a non existent client {:?}. Ignoring.",
+                    public_key
+                );
+                return Ok(());
+            }
+        };
+
+        if connected_client.is_closed() {
+            // The connection is being closed:
+            return Ok(());
+        }
+
+        let opt_reject_reason = if self.num_open_requests >= self.client_limits.max_open_requests {
+            Some(RejectReason::ServerBusy)
+        } else if !client_buckets.requests_bucket.try_take() {
+            Some(RejectReason::RateLimited)
+        } else {
+            None
+        };
+
+        if let Some(reason) = opt_reject_reason {
+            self.routes_requests.add_rejected();
+            warn!(
+                "Rejecting routes request from client {:?}: {:?}",
+                public_key, reason
+            );
+            let request_rejected = RequestRejected {
+                request_id: request_routes.request_id,
+                reason,
+            };
+            let _ =
+                connected_client.try_send(IndexServerToClient::RequestRejected(request_rejected));
+            return Ok(());
+        }
+
+        let client_sender = match &connected_client.opt_sender {
+            Some(client_sender) => client_sender.clone(),
+            None => return Ok(()),
+        };
+
+        let graph_client = self.graph_client.clone();
+        let mut c_event_sender = self.event_sender.clone();
+        let request_routes_fut = async move {
+            let start_time = Instant::now();
+            if let Err(e) = await!(request_routes_handler(
+                graph_client,
+                request_routes,
+                client_sender
+            )) {
+                error!("request_routes_handler() error: {:?}", e);
+            }
+            let _ = await!(
+                c_event_sender.send(IndexServerEvent::RequestRoutesDone(start_time.elapsed()))
+            );
+        };
+
+        self.spawner
+            .spawn(request_routes_fut)
+            .map_err(|_| ServerLoopError::SpawnError)?;
+        self.num_open_requests += 1;
+        Ok(())
+    }
+
+    /// Handle a request from an admin connection.
+    /// Requests that require information from the graph are answered by a separate task.
+    pub fn handle_admin_request(
+        &mut self,
+        admin_sender: mpsc::Sender<IndexServerToAdmin>,
+        admin_request: IndexAdminToServer,
+    ) -> Result<(), ServerLoopError> {
+        let graph_client = self.graph_client.clone();
+        match admin_request {
+            IndexAdminToServer::RequestStats => {
+                let connected_servers = self
+                    .iter_connected_servers()
+                    .map(|(server_public_key, _)| server_public_key.clone())
+                    .collect();
+                // The statistics of the graphs are filled by `admin_stats_handler()`:
+                let index_server_stats = IndexServerStats {
+                    graphs: Vec::new(),
+                    num_clients: usize_to_u64(self.clients.len()).unwrap(),
+                    num_trusted_servers: usize_to_u64(self.remote_servers.len()).unwrap(),
+                    connected_servers,
+                    verifier: self.verifier.stats(),
+                    routes_requests: self.routes_requests.stats(self.num_open_requests),
+                };
+                self.spawner
+                    .spawn(
+                        admin_stats_handler(graph_client, index_server_stats, admin_sender)
+                            .map_err(|e| error!("admin_stats_handler() error: {:?}", e))
+                            .map(|_| ()),
+                    )
+                    .map_err(|_| ServerLoopError::SpawnError)
+            }
+            IndexAdminToServer::RequestNeighbors(request_neighbors) => self
+                .spawner
+                .spawn(
+                    admin_neighbors_handler(graph_client, request_neighbors, admin_sender)
+                        .map_err(|e| error!("admin_neighbors_handler() error: {:?}", e))
+                        .map(|_| ()),
+                )
+                .map_err(|_| ServerLoopError::SpawnError),
+        }
+    }
+
+    /// A summary of the nodes we know, to be sent to a remote server.
+    /// The summary is split into a few messages, to avoid exceeding frame length.
+    fn server_digests(&self) -> Vec<ServerDigest> {
+        let node_digests = self
+            .last_mutations
+            .values()
+            .map(|forward_mutations_update| {
+                create_node_digest(&forward_mutations_update.mutations_update)
+            })
+            .collect::<Vec<_>>();
+
+        let mut server_digests = node_digests
+            .chunks(MAX_NODE_DIGESTS_PER_MESSAGE)
+            .map(|node_digests| ServerDigest {
+                node_digests: node_digests.to_vec(),
+                is_last: false,
+            })
+            .collect::<Vec<_>>();
+
+        if server_digests.is_empty() {
+            server_digests.push(ServerDigest {
+                node_digests: Vec::new(),
+                is_last: false,
+            });
+        }
+        server_digests.last_mut().unwrap().is_last = true;
+        server_digests
+    }
+
+    /// Send messages to a connected remote server in the background, in order.
+    /// Used when there might be more messages than the remote server's channel can queue.
+    fn send_to_server_in_background(
+        &mut self,
+        server_public_key: &PublicKey,
+        messages: Vec<IndexServerToServer>,
+    ) -> Result<(), ServerLoopError> {
+        let opt_sender = match self.remote_servers.get(server_public_key) {
+            Some(RemoteServer {
+                state: RemoteServerState::Connected(connected),
+                ..
+            }) => connected.opt_sender.clone(),
+            _ => None,
+        };
+        let mut sender = match opt_sender {
+            Some(sender) => sender,
+            None => return Ok(()),
+        };
+
+        let mut messages = stream::iter(messages);
+        self.spawner
+            .spawn(
+                async move {
+                    let _ = await!(sender.send_all(&mut messages));
+                },
+            )
+            .map_err(|_| ServerLoopError::SpawnError)
+    }
+
+    /// Handle a part of the digest of a remote server.
+    /// Once the whole digest is received, we start sending the remote server the current state of
+    /// the nodes it is missing.
+    pub async fn handle_server_digest(
+        &mut self,
+        public_key: PublicKey,
+        server_digest: ServerDigest,
+    ) -> Result<(), ServerLoopError> {
+        let remote_sync = self
+            .remote_syncs
+            .entry(public_key.clone())
+            .or_insert_with(RemoteSync::default);
+        remote_sync.node_digests.extend(server_digest.node_digests);
+        if !server_digest.is_last {
+            return Ok(());
+        }
+
+        let remote_counters = remote_sync
+            .node_digests
+            .drain(..)
+            .map(|node_digest| {
+                (
+                    node_digest.node_public_key,
+                    (node_digest.session_id, node_digest.counter),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+
+        for (node_public_key, forward_mutations_update) in &self.last_mutations {
+            let mutations_update = &forward_mutations_update.mutations_update;
+            if let Some((session_id, counter)) = remote_counters.get(node_public_key) {
+                // If the remote server knows another session of this node, we can not tell
+                // which of the sessions is newer. We leave the remote server's session as is.
+                if session_id != &mutations_update.session_id
+                    || *counter >= mutations_update.counter
+                {
+                    continue;
+                }
+            }
+            remote_sync.pending_nodes.insert(node_public_key.clone());
+        }
+
+        let node_public_keys = remote_sync.pending_nodes.iter().cloned().collect();
+        await!(self.send_node_states(public_key, node_public_keys))
+    }
+
+    /// Send a remote server the current state of some of the nodes it is missing: The node's
+    /// latest mutations update, followed by all of the node's edges.
+    ///
+    /// The time proof of the mutations update is extended, so that the remote server can verify
+    /// that the state is fresh. A state that can not be proven fresh to the remote server remains
+    /// pending, and is sent when a newer mutations update arrives from the node. This happens to
+    /// nodes that have not sent mutations updates since the remote server has connected.
+    async fn send_node_states(
+        &mut self,
+        server_public_key: PublicKey,
+        node_public_keys: Vec<PublicKey>,
+    ) -> Result<(), ServerLoopError> {
+        let mut node_states = Vec::new();
+        for node_public_key in node_public_keys {
+            let remote_sync = match self.remote_syncs.get_mut(&server_public_key) {
+                Some(remote_sync) => remote_sync,
+                None => return Ok(()),
+            };
+            let forward_mutations_update = match self.last_mutations.get(&node_public_key) {
+                Some(forward_mutations_update) => forward_mutations_update,
+                None => {
+                    // The node was removed, there is nothing to send:
+                    remote_sync.pending_nodes.remove(&node_public_key);
+                    continue;
+                }
+            };
+
+            // The last link of the time proof was added by us when we verified the message:
+            let hashes = &forward_mutations_update
+                .time_proof_chain
+                .last()
+                .unwrap()
+                .hashes;
+            let expansions = match self.verifier.extend_proof(hashes, &server_public_key) {
+                Some(expansions) => expansions,
+                None => continue,
+            };
+
+            let mut forward_mutations_update = forward_mutations_update.clone();
+            forward_mutations_update.time_proof_chain.extend(
+                expansions
+                    .into_iter()
+                    .map(|hashes| TimeProofLink { hashes }),
+            );
+            remote_sync.pending_nodes.remove(&node_public_key);
+            node_states.push(forward_mutations_update);
+        }
+
+        let mut messages = Vec::new();
+        for forward_mutations_update in node_states {
+            let mutations_update = &forward_mutations_update.mutations_update;
+            let node_public_key = mutations_update.node_public_key.clone();
+            let node_digest = create_node_digest(mutations_update);
+            let current_edges = await!(self.graph_client.get_node_edges(node_public_key.clone()))?
+                .into_iter()
+                .map(|(currency, public_key, _capacity_edge)| (currency, public_key));
+
+            // Every edge is sent along with the node's signed mutations update that has set it.
+            // Edges we can not prove (For example, edges restored from a snapshot) are not sent:
+            let node_edges = match self.signed_edges.get(&node_public_key) {
+                Some(signed_edges) => signed_edges.node_edges(&node_digest, current_edges),
+                None => Vec::new(),
+            };
+
+            messages.push(IndexServerToServer::NodeState(forward_mutations_update));
+            messages.extend(node_edges.into_iter().map(IndexServerToServer::NodeEdges));
+        }
+
+        if messages.is_empty() {
+            return Ok(());
+        }
+        self.send_to_server_in_background(&server_public_key, messages)
+    }
+
+    /// Handle the current state of a node, sent by a remote server.
+    /// The edges of the node arrive in the following `NodeEdges` messages.
+    pub async fn handle_node_state(
+        &mut self,
+        public_key: PublicKey,
+        mut forward_mutations_update: ForwardMutationsUpdate,
+    ) -> Result<(), ServerLoopError> {
+        if !self.verify_forward_mutations_update(Some(&public_key), &mut forward_mutations_update) {
+            return Ok(());
+        }
+
+        // Drop the edges we know from older states of the node:
+        let node_public_key = forward_mutations_update
+            .mutations_update
+            .node_public_key
+            .clone();
+        await!(self.graph_client.remove_node(node_public_key.clone()))?;
+        self.signed_edges.remove(&node_public_key);
+
+        self.last_mutations
+            .insert(node_public_key, forward_mutations_update);
+        Ok(())
+    }
+
+    /// Handle some of the current edges of a node, following a `NodeState` message.
+    /// The edges are ignored if a newer mutations update of the node has arrived in the meanwhile.
+    /// In that case the edges of the node are fixed as the node keeps sending its edges.
+    ///
+    /// Only edges the node has signed are applied: Every edge must be an `UpdateFriend` mutation
+    /// of the attached mutations update, and the mutations update must be signed by the node.
+    pub async fn handle_node_edges(
+        &mut self,
+        public_key: PublicKey,
+        node_edges: NodeEdges,
+    ) -> Result<(), ServerLoopError> {
+        let node_digest = node_edges.node_digest;
+        let is_current = match self.last_mutations.get(&node_digest.node_public_key) {
+            Some(forward_mutations_update) => {
+                create_node_digest(&forward_mutations_update.mutations_update) == node_digest
+            }
+            None => false,
+        };
+        if !is_current {
+            warn!(
+                "{}: handle_node_edges: Edges of an outdated state of node {:?}. Ignoring.",
+                self.local_public_key[0], node_digest.node_public_key
+            );
+            return Ok(());
+        }
+
+        let mutations_update = node_edges.mutations_update;
+        if mutations_update.node_public_key != node_digest.node_public_key
+            || !mutations_update.verify_signature()
+        {
+            warn!(
+                "{}: handle_node_edges: Unsigned edges of node {:?} from server {:?}. Ignoring.",
+                self.local_public_key[0], node_digest.node_public_key, public_key
+            );
+            return Ok(());
+        }
+
+        let mut edge_indices = Vec::new();
+        for edge_index in node_edges.edge_indices {
+            let edge_index = u32_to_usize(edge_index).unwrap();
+            let update_friend = match mutations_update.index_mutations.get(edge_index) {
+                Some(IndexMutation::UpdateFriend(update_friend)) => update_friend,
+                _ => {
+                    warn!(
+                        "{}: handle_node_edges: Invalid edge index {} from server {:?}. Ignoring.",
+                        self.local_public_key[0], edge_index, public_key
+                    );
+                    continue;
+                }
+            };
+            await!(self.graph_client.update_edge(
+                update_friend.currency.clone(),
+                node_digest.node_public_key.clone(),
+                update_friend.public_key.clone(),
+                (
+                    update_friend.send_capacity,
+                    update_friend.recv_capacity,
+                    update_friend.rate.clone()
+                )
+            ))?;
+            edge_indices.push(edge_index);
+        }
+
+        self.signed_edges
+            .entry(node_digest.node_public_key)
+            .or_insert_with(SignedEdges::default)
+            .add_edges(mutations_update, &edge_indices);
         Ok(())
     }
 
@@ -341,6 +1046,15 @@ where
                 await!(self
                     .handle_forward_mutations_update(Some(public_key), forward_mutations_update))?;
             }
+            IndexServerToServer::ServerDigest(server_digest) => {
+                await!(self.handle_server_digest(public_key, server_digest))?;
+            }
+            IndexServerToServer::NodeState(forward_mutations_update) => {
+                await!(self.handle_node_state(public_key, forward_mutations_update))?;
+            }
+            IndexServerToServer::NodeEdges(node_edges) => {
+                await!(self.handle_node_edges(public_key, node_edges))?;
+            }
         };
         Ok(())
     }
@@ -358,8 +1072,35 @@ where
             let _ = connected_client.try_send(IndexServerToClient::TimeHash(time_hash.clone()));
         }
 
+        // Refill the token buckets of all clients. The buckets of a disconnected client are
+        // forgotten only once they are full, so that reconnecting does not help bypassing the
+        // limits:
+        let clients = &self.clients;
+        self.client_buckets.retain(|public_key, client_buckets| {
+            client_buckets.tick();
+            clients.contains_key(public_key) || !client_buckets.is_full()
+        });
+
+        // Forget mutations updates that can not be proven fresh anymore:
+        let verifier = &self.verifier;
+        self.last_mutations.retain(|_, forward_mutations_update| {
+            let hashes = &forward_mutations_update
+                .time_proof_chain
+                .last()
+                .unwrap()
+                .hashes;
+            verifier.is_recent(hashes)
+        });
+        let last_mutations = &self.last_mutations;
+        for remote_sync in self.remote_syncs.values_mut() {
+            remote_sync
+                .pending_nodes
+                .retain(|node_public_key| last_mutations.contains_key(node_public_key));
+        }
+
         // Update the graph service about removed nodes:
         for node_public_key in removed_nodes {
+            self.signed_edges.remove(&node_public_key);
             await!(self.graph_client.remove_node(node_public_key))?;
         }
 
@@ -367,57 +1108,174 @@ where
     }
 }
 
-async fn client_handler(
-    mut graph_client: GraphClient<PublicKey, u128>,
-    _public_key: PublicKey, // TODO: unused?
-    client_conn: ClientConn,
-    mut event_sender: mpsc::Sender<IndexServerEvent>,
-) -> Result<(), ServerLoopError> {
-    let (mut sender, mut receiver) = client_conn;
+/// Identify a mutations update of a node.
+fn create_node_digest(mutations_update: &MutationsUpdate) -> NodeDigest {
+    NodeDigest {
+        node_public_key: mutations_update.node_public_key.clone(),
+        session_id: mutations_update.session_id.clone(),
+        counter: mutations_update.counter,
+    }
+}
 
-    while let Some(client_msg) = await!(receiver.next()) {
-        match client_msg {
-            IndexClientToServer::MutationsUpdate(mutations_update) => {
-                // Forward to main server future to process:
-                await!(event_sender.send(IndexServerEvent::ClientMutationsUpdate(mutations_update)))
-                    .map_err(|_| ServerLoopError::ClientEventSenderError)?;
-            }
-            IndexClientToServer::RequestRoutes(request_routes) => {
-                let route_tuples = await!(graph_client.get_routes(
-                    request_routes.source.clone(),
-                    request_routes.destination.clone(),
-                    request_routes.capacity,
-                    request_routes.opt_exclude.clone()
-                ))?;
-                let routes = route_tuples
-                    .into_iter()
-                    .map(|(route, capacity)| RouteWithCapacity {
-                        route: FriendsRoute { public_keys: route },
-                        capacity,
-                    })
-                    .collect::<Vec<_>>();
+/// Collect the constraints a routes request puts on the returned routes.
+fn route_constraints(request_routes: &RequestRoutes) -> RouteConstraints<PublicKey> {
+    let mut exclude_edges: HashSet<_> = request_routes.exclude_edges.iter().cloned().collect();
+    if let Some(exclude_edge) = &request_routes.opt_exclude {
+        exclude_edges.insert(exclude_edge.clone());
+    }
 
-                let response_routes = ResponseRoutes {
-                    request_id: request_routes.request_id,
-                    routes,
-                };
-                let message = IndexServerToClient::ResponseRoutes(response_routes);
-                await!(sender.send(message)).map_err(|_| ServerLoopError::ClientSenderError)?;
-            }
+    RouteConstraints {
+        exclude_nodes: request_routes.exclude_nodes.iter().cloned().collect(),
+        exclude_edges,
+        waypoints: request_routes.waypoints.clone(),
+        opt_max_route_len: request_routes
+            .opt_max_route_len
+            .map(|max_route_len| u32_to_usize(max_route_len).unwrap()),
+    }
+}
+
+/// Find routes for a client's request, and send them to the client.
+async fn request_routes_handler(
+    mut graph_client: GraphClient<Currency, PublicKey, u128, Rate>,
+    request_routes: RequestRoutes,
+    mut client_sender: mpsc::Sender<IndexServerToClient>,
+) -> Result<(), ServerLoopError> {
+    let constraints = route_constraints(&request_routes);
+    let route_tuples = match request_routes.mode {
+        RequestRoutesMode::Routes => {
+            // We limit the amount of routes, because every route requires another
+            // search over the graph:
+            let max_routes =
+                u32_to_usize(cmp::min(request_routes.max_routes, MAX_ROUTES_PER_REQUEST)).unwrap();
+            await!(graph_client.get_routes(
+                request_routes.currency.clone(),
+                request_routes.source.clone(),
+                request_routes.destination.clone(),
+                request_routes.capacity,
+                constraints,
+                max_routes
+            ))?
         }
+        RequestRoutesMode::MaxFlow => await!(graph_client.get_max_flow_routes(
+            request_routes.currency.clone(),
+            request_routes.source.clone(),
+            request_routes.destination.clone(),
+            constraints
+        ))?,
+    };
+    let routes = route_tuples
+        .into_iter()
+        .map(|(route, capacity, fees)| RouteWithCapacity {
+            route: FriendsRoute { public_keys: route },
+            capacity,
+            fees,
+        })
+        .collect::<Vec<_>>();
+
+    let response_routes = ResponseRoutes {
+        request_id: request_routes.request_id,
+        routes,
+    };
+    let message = IndexServerToClient::ResponseRoutes(response_routes);
+    await!(client_sender.send(message)).map_err(|_| ServerLoopError::ClientSenderError)?;
+    Ok(())
+}
+
+/// Add the statistics of the graphs to the statistics of the server, and send them to an admin.
+async fn admin_stats_handler(
+    mut graph_client: GraphClient<Currency, PublicKey, u128, Rate>,
+    mut index_server_stats: IndexServerStats,
+    mut admin_sender: mpsc::Sender<IndexServerToAdmin>,
+) -> Result<(), ServerLoopError> {
+    index_server_stats.graphs = await!(graph_client.stats())?
+        .into_iter()
+        .map(|(currency, num_nodes, num_edges)| CurrencyGraphStats {
+            currency,
+            num_nodes: usize_to_u64(num_nodes).unwrap(),
+            num_edges: usize_to_u64(num_edges).unwrap(),
+        })
+        .collect();
+    await!(admin_sender.send(IndexServerToAdmin::Stats(index_server_stats)))
+        .map_err(|_| ServerLoopError::AdminSenderError)
+}
+
+/// Find the edges reported by a node, and send them to an admin.
+async fn admin_neighbors_handler(
+    mut graph_client: GraphClient<Currency, PublicKey, u128, Rate>,
+    request_neighbors: RequestNeighbors,
+    mut admin_sender: mpsc::Sender<IndexServerToAdmin>,
+) -> Result<(), ServerLoopError> {
+    let RequestNeighbors {
+        currency,
+        node_public_key,
+    } = request_neighbors;
+    let neighbors = await!(graph_client.get_neighbors(currency.clone(), node_public_key.clone()))?
+        .into_iter()
+        .map(
+            |(public_key, (send_capacity, recv_capacity, rate))| NeighborEdge {
+                public_key,
+                send_capacity,
+                recv_capacity,
+                rate,
+            },
+        )
+        .collect();
+
+    let response_neighbors = ResponseNeighbors {
+        currency,
+        node_public_key,
+        neighbors,
+    };
+    await!(admin_sender.send(IndexServerToAdmin::ResponseNeighbors(response_neighbors)))
+        .map_err(|_| ServerLoopError::AdminSenderError)
+}
+
+/// Forward messages from a client to the main server loop.
+async fn client_handler<A>(
+    public_key: PublicKey,
+    mut receiver: mpsc::Receiver<IndexClientToServer>,
+    mut event_sender: mpsc::Sender<IndexServerEvent<A>>,
+) -> Result<(), ServerLoopError> {
+    while let Some(client_msg) = await!(receiver.next()) {
+        let event = match client_msg {
+            IndexClientToServer::MutationsUpdate(mutations_update) => {
+                IndexServerEvent::ClientMutationsUpdate((public_key.clone(), mutations_update))
+            }
+            IndexClientToServer::RequestRoutes(request_routes) => {
+                IndexServerEvent::ClientRequestRoutes((public_key.clone(), request_routes))
+            }
+        };
+        await!(event_sender.send(event)).map_err(|_| ServerLoopError::ClientEventSenderError)?;
+    }
+    Ok(())
+}
+
+/// Forward requests from an admin to the main server loop.
+/// Every request is sent together with a sender used for answering the admin.
+async fn admin_handler<A>(
+    sender: mpsc::Sender<IndexServerToAdmin>,
+    mut receiver: mpsc::Receiver<IndexAdminToServer>,
+    mut event_sender: mpsc::Sender<IndexServerEvent<A>>,
+) -> Result<(), ServerLoopError> {
+    while let Some(admin_msg) = await!(receiver.next()) {
+        let event = IndexServerEvent::AdminRequest((sender.clone(), admin_msg));
+        await!(event_sender.send(event)).map_err(|_| ServerLoopError::AdminEventSenderError)?;
     }
     Ok(())
 }
 
-pub async fn server_loop<A, IS, IC, SC, CMP, V, TS, S>(
+pub async fn server_loop<A, IS, IC, IA, TM, SC, CMP, V, TS, S>(
     local_public_key: PublicKey,
     trusted_servers: HashMap<PublicKey, A>,
     incoming_server_connections: IS,
     incoming_client_connections: IC,
+    incoming_admin_connections: IA,
+    incoming_trusted_mutations: TM,
     server_connector: SC,
-    graph_client: GraphClient<PublicKey, u128>,
+    graph_client: GraphClient<Currency, PublicKey, u128, Rate>,
     compare_public_key: CMP,
     verifier: V,
+    client_limits: ClientLimits,
     timer_stream: TS,
     spawner: S,
     mut opt_debug_event_sender: Option<mpsc::Sender<()>>,
@@ -426,6 +1284,8 @@ where
     A: Clone + Send + std::fmt::Debug + 'static,
     IS: Stream<Item = (PublicKey, ServerConn)> + Unpin + Send,
     IC: Stream<Item = (PublicKey, ClientConn)> + Unpin + Send,
+    IA: Stream<Item = AdminConn> + Unpin + Send,
+    TM: Stream<Item = TrustedServersMutation<A>> + Unpin + Send,
     SC: FutTransform<Input = (PublicKey, A), Output = Option<ServerConn>> + Clone + Send + 'static,
     V: Verifier<Node = PublicKey, Neighbor = PublicKey, SessionId = Uid>,
     CMP: Clone + Fn(&PublicKey, &PublicKey) -> Ordering + Sync,
@@ -446,6 +1306,7 @@ where
         graph_client,
         compare_public_key,
         verifier,
+        client_limits,
         event_sender,
         spawner,
     )?;
@@ -471,12 +1332,24 @@ where
             IndexServerEvent::ClientListenerClosed,
         )));
 
+    // Note: Admin connections are optional, so the server keeps running if the admin listener
+    // is closed.
+    let incoming_admin_connections =
+        incoming_admin_connections.map(IndexServerEvent::AdminConnection);
+
+    // Note: The trusted servers mutations stream may end. In that case the set of trusted
+    // servers will not change anymore.
+    let incoming_trusted_mutations =
+        incoming_trusted_mutations.map(IndexServerEvent::TrustedServersMutation);
+
     let timer_stream = timer_stream.map(|_| IndexServerEvent::TimerTick);
 
     let mut events = select_streams![
         event_receiver,
         incoming_server_connections,
         incoming_client_connections,
+        incoming_admin_connections,
+        incoming_trusted_mutations,
         timer_stream
     ];
 
@@ -530,9 +1403,27 @@ where
 
                 index_server
                     .remote_servers
-                    .insert(public_key, remote_server);
+                    .insert(public_key.clone(), remote_server);
+
+                // Let the remote server know which nodes we already know, so that it can send us
+                // the current state of the nodes we have missed:
+                let messages = index_server
+                    .server_digests()
+                    .into_iter()
+                    .map(IndexServerToServer::ServerDigest)
+                    .collect();
+                index_server.send_to_server_in_background(&public_key, messages)?;
             }
             IndexServerEvent::FromServer((public_key, Some(index_server_to_server))) => {
+                if !index_server.remote_servers.contains_key(&public_key) {
+                    // Messages might still arrive from a server we stopped trusting, until the
+                    // connection is closed:
+                    warn!(
+                        "Message received from non trusted server {:?}. Ignoring.",
+                        public_key
+                    );
+                    continue;
+                }
                 await!(index_server.handle_from_server(public_key, index_server_to_server))?
             }
             IndexServerEvent::FromServer((public_key, None)) => {
@@ -548,6 +1439,7 @@ where
                     Some(old_server) => old_server,
                 };
                 let _ = index_server.verifier.remove_neighbor(&public_key);
+                index_server.remote_syncs.remove(&public_key);
                 let server = index_server.spawn_server(public_key.clone(), old_server.address)?;
                 index_server.remote_servers.insert(public_key, server);
             }
@@ -557,16 +1449,13 @@ where
                     continue;
                 }
 
-                // Duplicate the client sender:
-                let (ref sender, _) = client_conn;
-                let c_sender = sender.clone();
+                let (sender, receiver) = client_conn;
 
                 let mut c_event_sender = index_server.event_sender.clone();
                 let c_public_key = public_key.clone();
                 let client_handler_fut = client_handler(
-                    index_server.graph_client.clone(),
                     public_key.clone(),
-                    client_conn,
+                    receiver,
                     index_server.event_sender.clone(),
                 )
                 .map_err(|e| error!("client_handler() error: {:?}", e))
@@ -582,18 +1471,37 @@ where
                     .spawner
                     .spawn(client_handler_fut)
                     .map_err(|_| ServerLoopError::SpawnError)?;
+                let client_limits = &index_server.client_limits;
+                index_server
+                    .client_buckets
+                    .entry(public_key.clone())
+                    .or_insert_with(|| ClientBuckets::new(client_limits));
                 index_server
                     .clients
-                    .insert(public_key, Connected::new(c_sender));
+                    .insert(public_key, Connected::new(sender));
             }
-            IndexServerEvent::ClientMutationsUpdate(mutations_update) => {
-                let forward_mutations_update = ForwardMutationsUpdate {
-                    mutations_update,
-                    time_proof_chain: Vec::new(),
-                };
-                await!(
-                    index_server.handle_forward_mutations_update(None, forward_mutations_update)
-                )?;
+            IndexServerEvent::ClientMutationsUpdate((public_key, mutations_update)) => {
+                await!(index_server.handle_client_mutations_update(public_key, mutations_update))?
+            }
+            IndexServerEvent::ClientRequestRoutes((public_key, request_routes)) => {
+                index_server.handle_client_request_routes(public_key, request_routes)?
+            }
+            IndexServerEvent::RequestRoutesDone(latency) => {
+                index_server.num_open_requests -= 1;
+                index_server.routes_requests.add_handled(latency);
+            }
+            IndexServerEvent::AdminConnection((sender, receiver)) => {
+                let admin_handler_fut =
+                    admin_handler(sender, receiver, index_server.event_sender.clone())
+                        .map_err(|e| error!("admin_handler() error: {:?}", e))
+                        .map(|_| ());
+                index_server
+                    .spawner
+                    .spawn(admin_handler_fut)
+                    .map_err(|_| ServerLoopError::SpawnError)?;
+            }
+            IndexServerEvent::AdminRequest((admin_sender, admin_request)) => {
+                index_server.handle_admin_request(admin_sender, admin_request)?
             }
             IndexServerEvent::ClientClosed(public_key) => {
                 // Client connection closed
@@ -601,6 +1509,16 @@ where
                     error!("A non existent client {:?} was closed.", public_key);
                 }
             }
+            IndexServerEvent::TrustedServersMutation(trusted_servers_mutation) => {
+                match trusted_servers_mutation {
+                    TrustedServersMutation::AddServer(public_key, address) => {
+                        index_server.add_trusted_server(public_key, address)?
+                    }
+                    TrustedServersMutation::RemoveServer(public_key) => {
+                        index_server.remove_trusted_server(&public_key)
+                    }
+                }
+            }
             IndexServerEvent::TimerTick => await!(index_server.handle_timer_tick())?,
             IndexServerEvent::ClientListenerClosed => {
                 warn!("server_loop() client listener closed!");
@@ -627,7 +1545,10 @@ mod tests {
     use futures::executor::ThreadPool;
     use futures::task::Spawn;
 
+    use std::convert::TryFrom;
+
     use crypto::crypto_rand::{RandValue, RAND_VALUE_LEN};
+    use crypto::hash::{HashResult, HASH_RESULT_LEN};
     use crypto::identity::{
         generate_pkcs8_key_pair, PublicKey, Signature, SoftwareEd25519Identity, PUBLIC_KEY_LEN,
         SIGNATURE_LEN,
@@ -637,7 +1558,8 @@ mod tests {
 
     use common::dummy_connector::{ConnRequest, DummyConnector};
     use identity::{create_identity, IdentityClient};
-    use proto::index_server::messages::RequestRoutes;
+
+    use proto::index_server::messages::UpdateFriend;
 
     use crate::graph::graph_service::GraphRequest;
     use crate::verifier::simple_verifier::SimpleVerifier;
@@ -673,6 +1595,7 @@ mod tests {
 
         let (_server_connections_sender, incoming_server_connections) = mpsc::channel(0);
         let (mut client_connections_sender, incoming_client_connections) = mpsc::channel(0);
+        let (_trusted_mutations_sender, incoming_trusted_mutations) = mpsc::channel(0);
 
         let (conn_request_sender, _conn_request_receiver) = mpsc::channel(0);
         let server_connector = DummyConnector::new(conn_request_sender);
@@ -689,15 +1612,27 @@ mod tests {
         let rng = DummyRandom::new(&[0u8]);
         let verifier = SimpleVerifier::new(8, rng);
 
+        // The client may send two route requests before it is limited:
+        let client_limits = ClientLimits {
+            max_request_tokens: 2,
+            request_refill_tokens: 1,
+            max_mutations_tokens: 1,
+            mutations_refill_tokens: 1,
+            max_open_requests: 0x10,
+        };
+
         let server_loop_fut = server_loop(
             local_public_key,
             trusted_servers,
             incoming_server_connections,
             incoming_client_connections,
+            stream::empty(),
+            incoming_trusted_mutations,
             server_connector,
             graph_client,
             compare_public_key,
             verifier,
+            client_limits,
             timer_stream,
             spawner.clone(),
             None,
@@ -720,20 +1655,70 @@ mod tests {
         let request_id = Uid::from(&[0; UID_LEN]);
         let request_routes = RequestRoutes {
             request_id: request_id.clone(),
+            currency: Currency::try_from("FST".to_owned()).unwrap(),
             capacity: 100,
             source: PublicKey::from(&[8; PUBLIC_KEY_LEN]),
             destination: PublicKey::from(&[9; PUBLIC_KEY_LEN]),
-            opt_exclude: None,
+            opt_exclude: Some((
+                PublicKey::from(&[9; PUBLIC_KEY_LEN]),
+                PublicKey::from(&[8; PUBLIC_KEY_LEN]),
+            )),
+            max_routes: MAX_ROUTES_PER_REQUEST + 1,
+            mode: RequestRoutesMode::Routes,
+            exclude_nodes: vec![PublicKey::from(&[10; PUBLIC_KEY_LEN])],
+            exclude_edges: vec![(
+                PublicKey::from(&[8; PUBLIC_KEY_LEN]),
+                PublicKey::from(&[9; PUBLIC_KEY_LEN]),
+            )],
+            waypoints: vec![PublicKey::from(&[11; PUBLIC_KEY_LEN])],
+            opt_max_route_len: Some(5),
         };
         await!(client_sender.send(IndexClientToServer::RequestRoutes(request_routes))).unwrap();
 
         // Handle the graph request:
         match await!(graph_requests_receiver.next()).unwrap() {
-            GraphRequest::GetRoutes(src, dest, capacity, opt_exclude, response_sender) => {
+            GraphRequest::GetRoutes(
+                currency,
+                src,
+                dest,
+                capacity,
+                constraints,
+                max_routes,
+                response_sender,
+            ) => {
+                assert_eq!(currency, Currency::try_from("FST".to_owned()).unwrap());
                 assert_eq!(src, PublicKey::from(&[8; PUBLIC_KEY_LEN]));
                 assert_eq!(dest, PublicKey::from(&[9; PUBLIC_KEY_LEN]));
                 assert_eq!(capacity, 100);
-                assert_eq!(opt_exclude, None);
+                assert_eq!(
+                    constraints.exclude_nodes,
+                    vec![PublicKey::from(&[10; PUBLIC_KEY_LEN])]
+                        .into_iter()
+                        .collect()
+                );
+                // The excluded edge is added to the other excluded edges:
+                assert_eq!(
+                    constraints.exclude_edges,
+                    vec![
+                        (
+                            PublicKey::from(&[8; PUBLIC_KEY_LEN]),
+                            PublicKey::from(&[9; PUBLIC_KEY_LEN])
+                        ),
+                        (
+                            PublicKey::from(&[9; PUBLIC_KEY_LEN]),
+                            PublicKey::from(&[8; PUBLIC_KEY_LEN])
+                        ),
+                    ]
+                    .into_iter()
+                    .collect()
+                );
+                assert_eq!(
+                    constraints.waypoints,
+                    vec![PublicKey::from(&[11; PUBLIC_KEY_LEN])]
+                );
+                assert_eq!(constraints.opt_max_route_len, Some(5));
+                // The amount of routes is limited by the server:
+                assert_eq!(max_routes, u32_to_usize(MAX_ROUTES_PER_REQUEST).unwrap());
                 response_sender.send(Vec::new()).unwrap();
             }
             _ => unreachable!(),
@@ -747,6 +1732,80 @@ mod tests {
             _ => unreachable!(),
         };
 
+        // Client requests the maximum flow routes:
+        let request_id = Uid::from(&[1; UID_LEN]);
+        let request_routes = RequestRoutes {
+            request_id: request_id.clone(),
+            currency: Currency::try_from("FST".to_owned()).unwrap(),
+            capacity: 0,
+            source: PublicKey::from(&[8; PUBLIC_KEY_LEN]),
+            destination: PublicKey::from(&[9; PUBLIC_KEY_LEN]),
+            opt_exclude: None,
+            max_routes: 0,
+            mode: RequestRoutesMode::MaxFlow,
+            exclude_nodes: Vec::new(),
+            exclude_edges: Vec::new(),
+            waypoints: Vec::new(),
+            opt_max_route_len: None,
+        };
+        await!(client_sender.send(IndexClientToServer::RequestRoutes(request_routes))).unwrap();
+
+        let route = vec![
+            PublicKey::from(&[8; PUBLIC_KEY_LEN]),
+            PublicKey::from(&[9; PUBLIC_KEY_LEN]),
+        ];
+        match await!(graph_requests_receiver.next()).unwrap() {
+            GraphRequest::GetMaxFlowRoutes(currency, src, dest, constraints, response_sender) => {
+                assert_eq!(currency, Currency::try_from("FST".to_owned()).unwrap());
+                assert_eq!(src, PublicKey::from(&[8; PUBLIC_KEY_LEN]));
+                assert_eq!(dest, PublicKey::from(&[9; PUBLIC_KEY_LEN]));
+                assert!(constraints.exclude_edges.is_empty());
+                response_sender.send(vec![(route.clone(), 50, 0)]).unwrap();
+            }
+            _ => unreachable!(),
+        }
+
+        match await!(client_receiver.next()).unwrap() {
+            IndexServerToClient::ResponseRoutes(response_routes) => {
+                assert_eq!(response_routes.request_id, request_id);
+                assert_eq!(
+                    response_routes.routes,
+                    vec![RouteWithCapacity {
+                        route: FriendsRoute { public_keys: route },
+                        capacity: 50,
+                        fees: 0,
+                    }]
+                );
+            }
+            _ => unreachable!(),
+        };
+
+        // The client has sent too many route requests:
+        let request_id = Uid::from(&[2; UID_LEN]);
+        let request_routes = RequestRoutes {
+            request_id: request_id.clone(),
+            currency: Currency::try_from("FST".to_owned()).unwrap(),
+            capacity: 100,
+            source: PublicKey::from(&[8; PUBLIC_KEY_LEN]),
+            destination: PublicKey::from(&[9; PUBLIC_KEY_LEN]),
+            opt_exclude: None,
+            max_routes: 1,
+            mode: RequestRoutesMode::Routes,
+            exclude_nodes: Vec::new(),
+            exclude_edges: Vec::new(),
+            waypoints: Vec::new(),
+            opt_max_route_len: None,
+        };
+        await!(client_sender.send(IndexClientToServer::RequestRoutes(request_routes))).unwrap();
+
+        match await!(client_receiver.next()).unwrap() {
+            IndexServerToClient::RequestRejected(request_rejected) => {
+                assert_eq!(request_rejected.request_id, request_id);
+                assert_eq!(request_rejected.reason, RejectReason::RateLimited);
+            }
+            _ => unreachable!(),
+        };
+
         // Server should periodically send time hashes to the client:
         await!(tick_sender.send(())).unwrap();
 
@@ -788,10 +1847,10 @@ mod tests {
 
         // Handle the graph request:
         match await!(graph_requests_receiver.next()).unwrap() {
-            GraphRequest::RemoveEdge(src, dest, response_sender) => {
+            GraphRequest::RemoveEdgeAllCurrencies(src, dest, response_sender) => {
                 assert_eq!(src, client_public_key);
                 assert_eq!(dest, PublicKey::from(&[11; PUBLIC_KEY_LEN]));
-                response_sender.send(None).unwrap();
+                response_sender.send(false).unwrap();
             }
             _ => unreachable!(),
         }
@@ -803,162 +1862,643 @@ mod tests {
         thread_pool.run(task_index_server_loop_single_server(thread_pool.clone()));
     }
 
-    // ###########################################################
-    // ###########################################################
-
-    struct TestServer {
-        public_key: PublicKey,
-        tick_sender: mpsc::Sender<()>,
-        server_connections_sender: mpsc::Sender<(PublicKey, ServerConn)>,
-        client_connections_sender: mpsc::Sender<(PublicKey, ClientConn)>,
-        graph_requests_receiver: mpsc::Receiver<GraphRequest<PublicKey, u128>>,
-        server_conn_request_receiver:
-            mpsc::Receiver<ConnRequest<(PublicKey, u8), Option<ServerConn>>>,
-        debug_event_receiver: mpsc::Receiver<()>,
-    }
-
-    fn create_test_server<S>(index: u8, trusted_servers: &[u8], mut spawner: S) -> TestServer
+    async fn task_index_server_loop_server_busy<S>(mut spawner: S)
     where
         S: Spawn + Clone + Send + 'static,
     {
-        let server_public_key = PublicKey::from(&[index; PUBLIC_KEY_LEN]);
-        let trusted_servers = trusted_servers
-            .iter()
-            .map(|&i| (PublicKey::from(&[i; PUBLIC_KEY_LEN]), i))
-            .collect::<HashMap<_, _>>();
-
-        let local_public_key = server_public_key.clone();
+        let local_public_key = PublicKey::from(&[0; PUBLIC_KEY_LEN]);
+        let trusted_servers: HashMap<PublicKey, u8> = HashMap::new();
 
-        let (server_connections_sender, incoming_server_connections) = mpsc::channel(0);
-        let (client_connections_sender, incoming_client_connections) = mpsc::channel(0);
+        let (_server_connections_sender, incoming_server_connections) = mpsc::channel(0);
+        let (mut client_connections_sender, incoming_client_connections) = mpsc::channel(0);
+        let (_trusted_mutations_sender, incoming_trusted_mutations) = mpsc::channel(0);
 
-        let (server_conn_request_sender, server_conn_request_receiver) = mpsc::channel(0);
-        let server_connector = DummyConnector::new(server_conn_request_sender);
+        let (conn_request_sender, _conn_request_receiver) = mpsc::channel(0);
+        let server_connector = DummyConnector::new(conn_request_sender);
 
-        let (tick_sender, timer_stream) = mpsc::channel::<()>(0);
+        let (_tick_sender, timer_stream) = mpsc::channel::<()>(0);
 
-        let (graph_requests_sender, graph_requests_receiver) = mpsc::channel(0);
+        let (graph_requests_sender, mut graph_requests_receiver) = mpsc::channel(0);
         let graph_client = GraphClient::new(graph_requests_sender);
 
         let compare_public_key = |pk_a: &PublicKey, pk_b: &PublicKey| pk_a.cmp(pk_b);
 
-        let rng = DummyRandom::new(&[0x13, 0x37, server_public_key[0]]);
+        let rng = DummyRandom::new(&[0u8]);
         let verifier = SimpleVerifier::new(8, rng);
 
-        let c_server_public_key = server_public_key.clone();
-
-        let (debug_event_sender, debug_event_receiver) = mpsc::channel(0);
+        // Only one route request may be handled at a time:
+        let client_limits = ClientLimits {
+            max_request_tokens: 0x10,
+            request_refill_tokens: 1,
+            max_mutations_tokens: 0x10,
+            mutations_refill_tokens: 1,
+            max_open_requests: 1,
+        };
 
         let server_loop_fut = server_loop(
             local_public_key,
             trusted_servers,
             incoming_server_connections,
             incoming_client_connections,
+            stream::empty(),
+            incoming_trusted_mutations,
             server_connector,
             graph_client,
             compare_public_key,
             verifier,
+            client_limits,
             timer_stream,
             spawner.clone(),
-            Some(debug_event_sender),
+            None,
         )
-        .map_err(move |e| {
-            error!(
-                "Error in server_loop() for pk[0] = {}: {:?}",
-                c_server_public_key[0], e
-            )
-        })
+        .map_err(|e| error!("Error in server_loop(): {:?}", e))
         .map(|_| ());
 
         spawner.spawn(server_loop_fut).unwrap();
 
-        TestServer {
-            public_key: server_public_key.clone(),
-            tick_sender,
-            server_connections_sender,
-            client_connections_sender,
-            graph_requests_receiver,
-            server_conn_request_receiver,
-            debug_event_receiver,
+        let client_public_key = PublicKey::from(&[1; PUBLIC_KEY_LEN]);
+        let (mut client_sender, server_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (server_sender, mut client_receiver) = mpsc::channel(CHANNEL_SIZE);
+        await!(
+            client_connections_sender.send((client_public_key, (server_sender, server_receiver)))
+        )
+        .unwrap();
+
+        // Client sends two route requests:
+        for i in 0..2u8 {
+            let request_routes = RequestRoutes {
+                request_id: Uid::from(&[i; UID_LEN]),
+                currency: Currency::try_from("FST".to_owned()).unwrap(),
+                capacity: 100,
+                source: PublicKey::from(&[8; PUBLIC_KEY_LEN]),
+                destination: PublicKey::from(&[9; PUBLIC_KEY_LEN]),
+                opt_exclude: None,
+                max_routes: 1,
+                mode: RequestRoutesMode::Routes,
+                exclude_nodes: Vec::new(),
+                exclude_edges: Vec::new(),
+                waypoints: Vec::new(),
+                opt_max_route_len: None,
+            };
+            await!(client_sender.send(IndexClientToServer::RequestRoutes(request_routes))).unwrap();
         }
-    }
 
-    async fn handle_connect(test_servers: &mut [TestServer], from_index: usize) {
-        let conn_request =
-            await!(test_servers[from_index].server_conn_request_receiver.next()).unwrap();
-        let (a_sender, b_receiver) = mpsc::channel(CHANNEL_SIZE);
-        let (b_sender, a_receiver) = mpsc::channel(CHANNEL_SIZE);
+        // The first request is still being handled, so the second one is rejected:
+        match await!(client_receiver.next()).unwrap() {
+            IndexServerToClient::RequestRejected(request_rejected) => {
+                assert_eq!(request_rejected.request_id, Uid::from(&[1; UID_LEN]));
+                assert_eq!(request_rejected.reason, RejectReason::ServerBusy);
+            }
+            _ => unreachable!(),
+        };
 
-        let (_dest_public_key, dest_index) = conn_request.address.clone();
-        await!(test_servers[dest_index as usize]
-            .server_connections_sender
-            .send((
-                test_servers[from_index].public_key.clone(),
-                (b_sender, b_receiver)
-            )))
-        .unwrap();
+        // Handle the graph request of the first request:
+        match await!(graph_requests_receiver.next()).unwrap() {
+            GraphRequest::GetRoutes(_, _, _, _, _, _, response_sender) => {
+                response_sender.send(Vec::new()).unwrap();
+            }
+            _ => unreachable!(),
+        }
 
-        await!(test_servers[dest_index as usize]
-            .debug_event_receiver
-            .next())
-        .unwrap();
+        match await!(client_receiver.next()).unwrap() {
+            IndexServerToClient::ResponseRoutes(response_routes) => {
+                assert_eq!(response_routes.request_id, Uid::from(&[0; UID_LEN]));
+                assert!(response_routes.routes.is_empty());
+            }
+            _ => unreachable!(),
+        };
+    }
 
-        conn_request.reply(Some((a_sender, a_receiver)));
-        await!(test_servers[from_index].debug_event_receiver.next()).unwrap();
+    #[test]
+    fn test_index_server_loop_server_busy() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_index_server_loop_server_busy(thread_pool.clone()));
     }
 
-    async fn task_index_server_loop_multi_server<S>(spawner: S)
+    async fn task_index_server_loop_client_rate_limit<S>(mut spawner: S)
     where
         S: Spawn + Clone + Send + 'static,
     {
-        /*
-         *  Servers layout:
-         *
-         *    0 -- 1
-         *    |    |
-         *    2 -- 3 -- 4
-         */
+        let local_public_key = PublicKey::from(&[0; PUBLIC_KEY_LEN]);
+        let trusted_servers: HashMap<PublicKey, u8> = HashMap::new();
 
-        let mut test_servers = Vec::new();
-        test_servers.push(create_test_server(0, &[1, 2], spawner.clone()));
-        test_servers.push(create_test_server(1, &[0, 3], spawner.clone()));
-        test_servers.push(create_test_server(2, &[0, 3], spawner.clone()));
-        test_servers.push(create_test_server(3, &[1, 2, 4], spawner.clone()));
-        test_servers.push(create_test_server(4, &[3], spawner.clone()));
+        let (_server_connections_sender, incoming_server_connections) = mpsc::channel(0);
+        let (mut client_connections_sender, incoming_client_connections) = mpsc::channel(0);
+        let (_trusted_mutations_sender, incoming_trusted_mutations) = mpsc::channel(0);
 
-        // Let all servers connect:
-        await!(handle_connect(&mut test_servers[..], 4)); // 4 connects to {3}
-        await!(handle_connect(&mut test_servers[..], 3)); // 3 connects to {1,2}
-        await!(handle_connect(&mut test_servers[..], 3));
-        await!(handle_connect(&mut test_servers[..], 2)); // 2 connects to {0}
-        await!(handle_connect(&mut test_servers[..], 1)); // 1 connects to {0}
+        let (conn_request_sender, _conn_request_receiver) = mpsc::channel(0);
+        let server_connector = DummyConnector::new(conn_request_sender);
 
-        // Let some time pass, to fill server's time hash lists.
-        // Without this step it will not be possible to forward messages along long routes.
-        for _iter in 0..32usize {
-            await!(test_servers[0].tick_sender.send(())).unwrap();
-            await!(test_servers[0].debug_event_receiver.next()).unwrap();
-            for &j in &[1usize, 2] {
-                await!(test_servers[j].debug_event_receiver.next()).unwrap();
-            }
+        // Time never passes, so the token buckets are never refilled:
+        let (_tick_sender, timer_stream) = mpsc::channel::<()>(0);
 
-            await!(test_servers[1].tick_sender.send(())).unwrap();
-            await!(test_servers[1].debug_event_receiver.next()).unwrap();
-            for &j in &[0usize, 3] {
-                await!(test_servers[j].debug_event_receiver.next()).unwrap();
-            }
+        let (graph_requests_sender, mut graph_requests_receiver) = mpsc::channel(0);
+        let graph_client = GraphClient::new(graph_requests_sender);
 
-            await!(test_servers[2].tick_sender.send(())).unwrap();
-            await!(test_servers[2].debug_event_receiver.next()).unwrap();
-            for &j in &[0usize, 3] {
-                await!(test_servers[j].debug_event_receiver.next()).unwrap();
-            }
+        let compare_public_key = |pk_a: &PublicKey, pk_b: &PublicKey| pk_a.cmp(pk_b);
 
-            await!(test_servers[3].tick_sender.send(())).unwrap();
-            await!(test_servers[3].debug_event_receiver.next()).unwrap();
-            for &j in &[1usize, 2, 4] {
-                await!(test_servers[j].debug_event_receiver.next()).unwrap();
-            }
+        let rng = DummyRandom::new(&[0u8]);
+        let verifier = SimpleVerifier::new(8, rng);
+
+        let client_limits = ClientLimits {
+            max_request_tokens: 1,
+            request_refill_tokens: 1,
+            max_mutations_tokens: 1,
+            mutations_refill_tokens: 1,
+            max_open_requests: 0x10,
+        };
+
+        let (debug_event_sender, mut debug_event_receiver) = mpsc::channel(0);
+
+        let server_loop_fut = server_loop(
+            local_public_key,
+            trusted_servers,
+            incoming_server_connections,
+            incoming_client_connections,
+            stream::empty(),
+            incoming_trusted_mutations,
+            server_connector,
+            graph_client,
+            compare_public_key,
+            verifier,
+            client_limits,
+            timer_stream,
+            spawner.clone(),
+            Some(debug_event_sender),
+        )
+        .map_err(|e| error!("Error in server_loop(): {:?}", e))
+        .map(|_| ());
+
+        spawner.spawn(server_loop_fut).unwrap();
+
+        let client_public_key = PublicKey::from(&[1; PUBLIC_KEY_LEN]);
+        let (mut client_sender, server_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (server_sender, mut client_receiver) = mpsc::channel(CHANNEL_SIZE);
+        await!(client_connections_sender
+            .send((client_public_key.clone(), (server_sender, server_receiver))))
+        .unwrap();
+        await!(debug_event_receiver.next()).unwrap();
+
+        let request_routes = RequestRoutes {
+            request_id: Uid::from(&[0; UID_LEN]),
+            currency: Currency::try_from("FST".to_owned()).unwrap(),
+            capacity: 100,
+            source: PublicKey::from(&[8; PUBLIC_KEY_LEN]),
+            destination: PublicKey::from(&[9; PUBLIC_KEY_LEN]),
+            opt_exclude: None,
+            max_routes: 1,
+            mode: RequestRoutesMode::Routes,
+            exclude_nodes: Vec::new(),
+            exclude_edges: Vec::new(),
+            waypoints: Vec::new(),
+            opt_max_route_len: None,
+        };
+
+        // The client uses its only route request token:
+        await!(client_sender.send(IndexClientToServer::RequestRoutes(request_routes.clone())))
+            .unwrap();
+        await!(debug_event_receiver.next()).unwrap();
+
+        match await!(graph_requests_receiver.next()).unwrap() {
+            GraphRequest::GetRoutes(_, _, _, _, _, _, response_sender) => {
+                response_sender.send(Vec::new()).unwrap();
+            }
+            _ => unreachable!(),
+        }
+        match await!(client_receiver.next()).unwrap() {
+            IndexServerToClient::ResponseRoutes(_) => {}
+            _ => unreachable!(),
+        };
+        // RequestRoutesDone:
+        await!(debug_event_receiver.next()).unwrap();
+
+        // The signature of this mutations update is invalid, but it still takes a token:
+        let mutations_update = MutationsUpdate {
+            node_public_key: client_public_key.clone(),
+            index_mutations: Vec::new(),
+            time_hash: HashResult::from(&[0; HASH_RESULT_LEN]),
+            session_id: Uid::from(&[0; UID_LEN]),
+            counter: 0,
+            rand_nonce: RandValue::from(&[0; RAND_VALUE_LEN]),
+            signature: Signature::from(&[0; SIGNATURE_LEN]),
+        };
+        await!(client_sender.send(IndexClientToServer::MutationsUpdate(
+            mutations_update.clone()
+        )))
+        .unwrap();
+        await!(debug_event_receiver.next()).unwrap();
+
+        // Too many mutations updates. The server closes the connection, to make the client send
+        // its full state again:
+        await!(client_sender.send(IndexClientToServer::MutationsUpdate(
+            mutations_update.clone()
+        )))
+        .unwrap();
+        await!(debug_event_receiver.next()).unwrap();
+        assert!(await!(client_receiver.next()).is_none());
+
+        drop(client_sender);
+        // ClientClosed:
+        await!(debug_event_receiver.next()).unwrap();
+
+        // The client reconnects:
+        let (mut client_sender, server_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (server_sender, mut client_receiver) = mpsc::channel(CHANNEL_SIZE);
+        await!(client_connections_sender
+            .send((client_public_key.clone(), (server_sender, server_receiver))))
+        .unwrap();
+        await!(debug_event_receiver.next()).unwrap();
+
+        // Reconnecting does not refill the token buckets:
+        await!(client_sender.send(IndexClientToServer::RequestRoutes(request_routes.clone())))
+            .unwrap();
+        match await!(client_receiver.next()).unwrap() {
+            IndexServerToClient::RequestRejected(request_rejected) => {
+                assert_eq!(request_rejected.reason, RejectReason::RateLimited);
+            }
+            _ => unreachable!(),
+        };
+
+        await!(client_sender.send(IndexClientToServer::MutationsUpdate(mutations_update))).unwrap();
+        assert!(await!(client_receiver.next()).is_none());
+    }
+
+    #[test]
+    fn test_index_server_loop_client_rate_limit() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_index_server_loop_client_rate_limit(
+            thread_pool.clone(),
+        ));
+    }
+
+    async fn task_index_server_loop_admin<S>(mut spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let local_public_key = PublicKey::from(&[0; PUBLIC_KEY_LEN]);
+        let trusted_servers: HashMap<PublicKey, u8> = HashMap::new();
+
+        let (_server_connections_sender, incoming_server_connections) = mpsc::channel(0);
+        let (mut client_connections_sender, incoming_client_connections) = mpsc::channel(0);
+        let (mut admin_connections_sender, incoming_admin_connections) = mpsc::channel(0);
+        let (_trusted_mutations_sender, incoming_trusted_mutations) = mpsc::channel(0);
+
+        let (conn_request_sender, _conn_request_receiver) = mpsc::channel(0);
+        let server_connector = DummyConnector::new(conn_request_sender);
+
+        let (_tick_sender, timer_stream) = mpsc::channel::<()>(0);
+
+        let (graph_requests_sender, mut graph_requests_receiver) = mpsc::channel(0);
+        let graph_client = GraphClient::new(graph_requests_sender);
+
+        let compare_public_key = |pk_a: &PublicKey, pk_b: &PublicKey| pk_a.cmp(pk_b);
+
+        let rng = DummyRandom::new(&[0u8]);
+        let verifier = SimpleVerifier::new(8, rng);
+
+        let client_limits = ClientLimits {
+            max_request_tokens: 0x10,
+            request_refill_tokens: 1,
+            max_mutations_tokens: 0x10,
+            mutations_refill_tokens: 1,
+            max_open_requests: 0x10,
+        };
+
+        let (debug_event_sender, mut debug_event_receiver) = mpsc::channel(0);
+
+        let server_loop_fut = server_loop(
+            local_public_key,
+            trusted_servers,
+            incoming_server_connections,
+            incoming_client_connections,
+            incoming_admin_connections,
+            incoming_trusted_mutations,
+            server_connector,
+            graph_client,
+            compare_public_key,
+            verifier,
+            client_limits,
+            timer_stream,
+            spawner.clone(),
+            Some(debug_event_sender),
+        )
+        .map_err(|e| error!("Error in server_loop(): {:?}", e))
+        .map(|_| ());
+
+        spawner.spawn(server_loop_fut).unwrap();
+
+        let client_public_key = PublicKey::from(&[1; PUBLIC_KEY_LEN]);
+        let (mut client_sender, server_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (server_sender, mut client_receiver) = mpsc::channel(CHANNEL_SIZE);
+        await!(
+            client_connections_sender.send((client_public_key, (server_sender, server_receiver)))
+        )
+        .unwrap();
+        await!(debug_event_receiver.next()).unwrap();
+
+        // The client requests routes:
+        let request_routes = RequestRoutes {
+            request_id: Uid::from(&[0; UID_LEN]),
+            currency: Currency::try_from("FST".to_owned()).unwrap(),
+            capacity: 100,
+            source: PublicKey::from(&[8; PUBLIC_KEY_LEN]),
+            destination: PublicKey::from(&[9; PUBLIC_KEY_LEN]),
+            opt_exclude: None,
+            max_routes: 1,
+            mode: RequestRoutesMode::Routes,
+            exclude_nodes: Vec::new(),
+            exclude_edges: Vec::new(),
+            waypoints: Vec::new(),
+            opt_max_route_len: None,
+        };
+        await!(client_sender.send(IndexClientToServer::RequestRoutes(request_routes))).unwrap();
+        await!(debug_event_receiver.next()).unwrap();
+
+        match await!(graph_requests_receiver.next()).unwrap() {
+            GraphRequest::GetRoutes(_, _, _, _, _, _, response_sender) => {
+                response_sender.send(Vec::new()).unwrap();
+            }
+            _ => unreachable!(),
+        }
+        match await!(client_receiver.next()).unwrap() {
+            IndexServerToClient::ResponseRoutes(_) => {}
+            _ => unreachable!(),
+        };
+        // RequestRoutesDone:
+        await!(debug_event_receiver.next()).unwrap();
+
+        // An admin connects:
+        let (mut admin_sender, server_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (server_sender, mut admin_receiver) = mpsc::channel(CHANNEL_SIZE);
+        await!(admin_connections_sender.send((server_sender, server_receiver))).unwrap();
+        await!(debug_event_receiver.next()).unwrap();
+
+        // The admin requests the server's statistics:
+        await!(admin_sender.send(IndexAdminToServer::RequestStats)).unwrap();
+        await!(debug_event_receiver.next()).unwrap();
+
+        match await!(graph_requests_receiver.next()).unwrap() {
+            GraphRequest::Stats(response_sender) => {
+                response_sender
+                    .send(vec![(Currency::try_from("FST".to_owned()).unwrap(), 2, 3)])
+                    .unwrap();
+            }
+            _ => unreachable!(),
+        }
+
+        match await!(admin_receiver.next()).unwrap() {
+            IndexServerToAdmin::Stats(index_server_stats) => {
+                assert_eq!(
+                    index_server_stats.graphs,
+                    vec![CurrencyGraphStats {
+                        currency: Currency::try_from("FST".to_owned()).unwrap(),
+                        num_nodes: 2,
+                        num_edges: 3,
+                    }]
+                );
+                assert_eq!(index_server_stats.num_clients, 1);
+                assert_eq!(index_server_stats.num_trusted_servers, 0);
+                assert!(index_server_stats.connected_servers.is_empty());
+                assert_eq!(index_server_stats.verifier.num_neighbors, 0);
+
+                let routes_requests = index_server_stats.routes_requests;
+                assert_eq!(routes_requests.num_handled, 1);
+                assert_eq!(routes_requests.num_rejected, 0);
+                assert_eq!(routes_requests.num_open, 0);
+                assert_eq!(
+                    routes_requests.avg_latency_micros,
+                    routes_requests.max_latency_micros
+                );
+            }
+            _ => unreachable!(),
+        };
+
+        // The admin requests the neighbors of a node:
+        let request_neighbors = RequestNeighbors {
+            currency: Currency::try_from("FST".to_owned()).unwrap(),
+            node_public_key: PublicKey::from(&[8; PUBLIC_KEY_LEN]),
+        };
+        await!(admin_sender.send(IndexAdminToServer::RequestNeighbors(request_neighbors))).unwrap();
+        await!(debug_event_receiver.next()).unwrap();
+
+        let rate = Rate { mul: 0, add: 1 };
+        match await!(graph_requests_receiver.next()).unwrap() {
+            GraphRequest::GetNeighbors(currency, node_public_key, response_sender) => {
+                assert_eq!(currency, Currency::try_from("FST".to_owned()).unwrap());
+                assert_eq!(node_public_key, PublicKey::from(&[8; PUBLIC_KEY_LEN]));
+                response_sender
+                    .send(vec![(
+                        PublicKey::from(&[9; PUBLIC_KEY_LEN]),
+                        (10, 20, rate.clone()),
+                    )])
+                    .unwrap();
+            }
+            _ => unreachable!(),
+        }
+
+        match await!(admin_receiver.next()).unwrap() {
+            IndexServerToAdmin::ResponseNeighbors(response_neighbors) => {
+                assert_eq!(
+                    response_neighbors.node_public_key,
+                    PublicKey::from(&[8; PUBLIC_KEY_LEN])
+                );
+                assert_eq!(
+                    response_neighbors.neighbors,
+                    vec![NeighborEdge {
+                        public_key: PublicKey::from(&[9; PUBLIC_KEY_LEN]),
+                        send_capacity: 10,
+                        recv_capacity: 20,
+                        rate,
+                    }]
+                );
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn test_index_server_loop_admin() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_index_server_loop_admin(thread_pool.clone()));
+    }
+
+    // ###########################################################
+    // ###########################################################
+
+    struct TestServer {
+        public_key: PublicKey,
+        tick_sender: mpsc::Sender<()>,
+        server_connections_sender: mpsc::Sender<(PublicKey, ServerConn)>,
+        client_connections_sender: mpsc::Sender<(PublicKey, ClientConn)>,
+        trusted_mutations_sender: mpsc::Sender<TrustedServersMutation<u8>>,
+        graph_requests_receiver: mpsc::Receiver<GraphRequest<Currency, PublicKey, u128, Rate>>,
+        server_conn_request_receiver:
+            mpsc::Receiver<ConnRequest<(PublicKey, u8), Option<ServerConn>>>,
+        debug_event_receiver: mpsc::Receiver<()>,
+    }
+
+    fn create_test_server<S>(index: u8, trusted_servers: &[u8], mut spawner: S) -> TestServer
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let server_public_key = PublicKey::from(&[index; PUBLIC_KEY_LEN]);
+        let trusted_servers = trusted_servers
+            .iter()
+            .map(|&i| (PublicKey::from(&[i; PUBLIC_KEY_LEN]), i))
+            .collect::<HashMap<_, _>>();
+
+        let local_public_key = server_public_key.clone();
+
+        let (server_connections_sender, incoming_server_connections) = mpsc::channel(0);
+        let (client_connections_sender, incoming_client_connections) = mpsc::channel(0);
+        let (trusted_mutations_sender, incoming_trusted_mutations) = mpsc::channel(0);
+
+        let (server_conn_request_sender, server_conn_request_receiver) = mpsc::channel(0);
+        let server_connector = DummyConnector::new(server_conn_request_sender);
+
+        let (tick_sender, timer_stream) = mpsc::channel::<()>(0);
+
+        let (graph_requests_sender, graph_requests_receiver) = mpsc::channel(0);
+        let graph_client = GraphClient::new(graph_requests_sender);
+
+        let compare_public_key = |pk_a: &PublicKey, pk_b: &PublicKey| pk_a.cmp(pk_b);
+
+        let rng = DummyRandom::new(&[0x13, 0x37, server_public_key[0]]);
+        let verifier = SimpleVerifier::new(8, rng);
+
+        let c_server_public_key = server_public_key.clone();
+
+        let (debug_event_sender, debug_event_receiver) = mpsc::channel(0);
+
+        let client_limits = ClientLimits {
+            max_request_tokens: 0x100,
+            request_refill_tokens: 0x10,
+            max_mutations_tokens: 0x100,
+            mutations_refill_tokens: 0x10,
+            max_open_requests: 0x100,
+        };
+
+        let server_loop_fut = server_loop(
+            local_public_key,
+            trusted_servers,
+            incoming_server_connections,
+            incoming_client_connections,
+            stream::empty(),
+            incoming_trusted_mutations,
+            server_connector,
+            graph_client,
+            compare_public_key,
+            verifier,
+            client_limits,
+            timer_stream,
+            spawner.clone(),
+            Some(debug_event_sender),
+        )
+        .map_err(move |e| {
+            error!(
+                "Error in server_loop() for pk[0] = {}: {:?}",
+                c_server_public_key[0], e
+            )
+        })
+        .map(|_| ());
+
+        spawner.spawn(server_loop_fut).unwrap();
+
+        TestServer {
+            public_key: server_public_key.clone(),
+            tick_sender,
+            server_connections_sender,
+            client_connections_sender,
+            trusted_mutations_sender,
+            graph_requests_receiver,
+            server_conn_request_receiver,
+            debug_event_receiver,
+        }
+    }
+
+    async fn handle_connect(test_servers: &mut [TestServer], from_index: usize) {
+        let conn_request =
+            await!(test_servers[from_index].server_conn_request_receiver.next()).unwrap();
+        let (a_sender, b_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (b_sender, a_receiver) = mpsc::channel(CHANNEL_SIZE);
+
+        let (_dest_public_key, dest_index) = conn_request.address.clone();
+        await!(test_servers[dest_index as usize]
+            .server_connections_sender
+            .send((
+                test_servers[from_index].public_key.clone(),
+                (b_sender, b_receiver)
+            )))
+        .unwrap();
+
+        await!(test_servers[dest_index as usize]
+            .debug_event_receiver
+            .next())
+        .unwrap();
+
+        conn_request.reply(Some((a_sender, a_receiver)));
+        await!(test_servers[from_index].debug_event_receiver.next()).unwrap();
+
+        // Both servers receive the digest of the other server:
+        await!(test_servers[dest_index as usize]
+            .debug_event_receiver
+            .next())
+        .unwrap();
+        await!(test_servers[from_index].debug_event_receiver.next()).unwrap();
+    }
+
+    async fn task_index_server_loop_multi_server<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        /*
+         *  Servers layout:
+         *
+         *    0 -- 1
+         *    |    |
+         *    2 -- 3 -- 4
+         */
+
+        let mut test_servers = Vec::new();
+        test_servers.push(create_test_server(0, &[1, 2], spawner.clone()));
+        test_servers.push(create_test_server(1, &[0, 3], spawner.clone()));
+        test_servers.push(create_test_server(2, &[0, 3], spawner.clone()));
+        test_servers.push(create_test_server(3, &[1, 2, 4], spawner.clone()));
+        test_servers.push(create_test_server(4, &[3], spawner.clone()));
+
+        // Let all servers connect:
+        await!(handle_connect(&mut test_servers[..], 4)); // 4 connects to {3}
+        await!(handle_connect(&mut test_servers[..], 3)); // 3 connects to {1,2}
+        await!(handle_connect(&mut test_servers[..], 3));
+        await!(handle_connect(&mut test_servers[..], 2)); // 2 connects to {0}
+        await!(handle_connect(&mut test_servers[..], 1)); // 1 connects to {0}
+
+        // Let some time pass, to fill server's time hash lists.
+        // Without this step it will not be possible to forward messages along long routes.
+        for _iter in 0..32usize {
+            await!(test_servers[0].tick_sender.send(())).unwrap();
+            await!(test_servers[0].debug_event_receiver.next()).unwrap();
+            for &j in &[1usize, 2] {
+                await!(test_servers[j].debug_event_receiver.next()).unwrap();
+            }
+
+            await!(test_servers[1].tick_sender.send(())).unwrap();
+            await!(test_servers[1].debug_event_receiver.next()).unwrap();
+            for &j in &[0usize, 3] {
+                await!(test_servers[j].debug_event_receiver.next()).unwrap();
+            }
+
+            await!(test_servers[2].tick_sender.send(())).unwrap();
+            await!(test_servers[2].debug_event_receiver.next()).unwrap();
+            for &j in &[0usize, 3] {
+                await!(test_servers[j].debug_event_receiver.next()).unwrap();
+            }
+
+            await!(test_servers[3].tick_sender.send(())).unwrap();
+            await!(test_servers[3].debug_event_receiver.next()).unwrap();
+            for &j in &[1usize, 2, 4] {
+                await!(test_servers[j].debug_event_receiver.next()).unwrap();
+            }
 
             await!(test_servers[4].tick_sender.send(())).unwrap();
             await!(test_servers[4].debug_event_receiver.next()).unwrap();
@@ -985,20 +2525,38 @@ mod tests {
         let request_id = Uid::from(&[0; UID_LEN]);
         let request_routes = RequestRoutes {
             request_id: request_id.clone(),
+            currency: Currency::try_from("FST".to_owned()).unwrap(),
             capacity: 100,
             source: PublicKey::from(&[8; PUBLIC_KEY_LEN]),
             destination: PublicKey::from(&[9; PUBLIC_KEY_LEN]),
             opt_exclude: None,
+            max_routes: 4,
+            mode: RequestRoutesMode::Routes,
+            exclude_nodes: Vec::new(),
+            exclude_edges: Vec::new(),
+            waypoints: Vec::new(),
+            opt_max_route_len: None,
         };
         await!(client_sender.send(IndexClientToServer::RequestRoutes(request_routes))).unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
 
         // Handle the graph request:
         match await!(test_servers[0].graph_requests_receiver.next()).unwrap() {
-            GraphRequest::GetRoutes(src, dest, capacity, opt_exclude, response_sender) => {
+            GraphRequest::GetRoutes(
+                currency,
+                src,
+                dest,
+                capacity,
+                constraints,
+                max_routes,
+                response_sender,
+            ) => {
+                assert_eq!(currency, Currency::try_from("FST".to_owned()).unwrap());
                 assert_eq!(src, PublicKey::from(&[8; PUBLIC_KEY_LEN]));
                 assert_eq!(dest, PublicKey::from(&[9; PUBLIC_KEY_LEN]));
                 assert_eq!(capacity, 100);
-                assert_eq!(opt_exclude, None);
+                assert!(constraints.exclude_edges.is_empty());
+                assert_eq!(max_routes, 4);
                 response_sender.send(Vec::new()).unwrap();
             }
             _ => unreachable!(),
@@ -1011,6 +2569,8 @@ mod tests {
             }
             _ => unreachable!(),
         };
+        // Route request is done:
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
 
         // One time iteration for server 0:
         await!(test_servers[0].tick_sender.send(())).unwrap();
@@ -1059,10 +2619,10 @@ mod tests {
                 }
 
                 match await!(test_servers[$index].graph_requests_receiver.next()).unwrap() {
-                    GraphRequest::RemoveEdge(src, dest, response_sender) => {
+                    GraphRequest::RemoveEdgeAllCurrencies(src, dest, response_sender) => {
                         assert_eq!(src, client_public_key);
                         assert_eq!(dest, PublicKey::from(&[11; PUBLIC_KEY_LEN]));
-                        response_sender.send(None).unwrap();
+                        response_sender.send(false).unwrap();
                     }
                     _ => unreachable!(),
                 };
@@ -1094,5 +2654,429 @@ mod tests {
         thread_pool.run(task_index_server_loop_multi_server(thread_pool.clone()));
     }
 
+    async fn task_index_server_loop_server_sync<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let mut test_servers = Vec::new();
+        test_servers.push(create_test_server(0, &[1], spawner.clone()));
+        test_servers.push(create_test_server(1, &[0], spawner.clone()));
+
+        // Server 1 connects to server 0. Messages from server 1 to server 0 go through us, so that
+        // we can later close the connection:
+        let conn_request = await!(test_servers[1].server_conn_request_receiver.next()).unwrap();
+        let (a_sender, mut relay_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (mut relay_sender, b_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (b_sender, a_receiver) = mpsc::channel(CHANNEL_SIZE);
+
+        await!(test_servers[0]
+            .server_connections_sender
+            .send((test_servers[1].public_key.clone(), (b_sender, b_receiver))))
+        .unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        conn_request.reply(Some((a_sender, a_receiver)));
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+
+        // Server 1 receives the digest of server 0:
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+
+        // Server 0 receives the digest of server 1:
+        let msg = await!(relay_receiver.next()).unwrap();
+        await!(relay_sender.send(msg)).unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        // Exchange some time hashes:
+        for _iter in 0..2usize {
+            await!(test_servers[1].tick_sender.send(())).unwrap();
+            await!(test_servers[1].debug_event_receiver.next()).unwrap();
+            let msg = await!(relay_receiver.next()).unwrap();
+            await!(relay_sender.send(msg)).unwrap();
+            await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+            await!(test_servers[0].tick_sender.send(())).unwrap();
+            await!(test_servers[0].debug_event_receiver.next()).unwrap();
+            await!(test_servers[1].debug_event_receiver.next()).unwrap();
+        }
+
+        // Close the connection between the servers:
+        drop(relay_sender);
+        drop(relay_receiver);
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+
+        // Connect a client to server 0:
+        let identity_client = create_identity_client(spawner.clone(), &[1, 1]);
+        let client_public_key = await!(identity_client.request_public_key()).unwrap();
+
+        let (mut client_sender, server_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (server_sender, mut client_receiver) = mpsc::channel(CHANNEL_SIZE);
+        await!(test_servers[0]
+            .client_connections_sender
+            .send((client_public_key.clone(), (server_sender, server_receiver))))
+        .unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        await!(test_servers[0].tick_sender.send(())).unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        let time_hash0 = match await!(client_receiver.next()).unwrap() {
+            IndexServerToClient::TimeHash(time_hash) => time_hash,
+            _ => unreachable!(),
+        };
+
+        // Send mutations update to server 0, while server 1 is disconnected:
+        let currency = Currency::try_from("FST".to_owned()).unwrap();
+        let index_mutations = vec![
+            IndexMutation::RemoveFriend(PublicKey::from(&[11; PUBLIC_KEY_LEN])),
+            IndexMutation::UpdateFriend(UpdateFriend {
+                public_key: PublicKey::from(&[12; PUBLIC_KEY_LEN]),
+                send_capacity: 100,
+                recv_capacity: 50,
+                currency: currency.clone(),
+                rate: Rate { mul: 0, add: 1 },
+            }),
+        ];
+
+        let mut mutations_update = MutationsUpdate {
+            node_public_key: client_public_key.clone(),
+            index_mutations,
+            time_hash: time_hash0.clone(),
+            session_id: Uid::from(&[0; UID_LEN]),
+            counter: 0,
+            rand_nonce: RandValue::from(&[0; RAND_VALUE_LEN]),
+            signature: Signature::from(&[0; SIGNATURE_LEN]),
+        };
+
+        // Calculate signature:
+        mutations_update.signature =
+            await!(identity_client.request_signature(mutations_update.signature_buff().clone()))
+                .unwrap();
+
+        await!(client_sender.send(IndexClientToServer::MutationsUpdate(mutations_update))).unwrap();
+
+        macro_rules! process_graph_request {
+            ($index:expr) => {
+                // Handle tick request:
+                match await!(test_servers[$index].graph_requests_receiver.next()).unwrap() {
+                    GraphRequest::Tick(node, response_sender) => {
+                        assert_eq!(node, client_public_key);
+                        response_sender.send(()).unwrap();
+                    }
+                    _ => unreachable!(),
+                }
+
+                match await!(test_servers[$index].graph_requests_receiver.next()).unwrap() {
+                    GraphRequest::RemoveEdgeAllCurrencies(src, dest, response_sender) => {
+                        assert_eq!(src, client_public_key);
+                        assert_eq!(dest, PublicKey::from(&[11; PUBLIC_KEY_LEN]));
+                        response_sender.send(false).unwrap();
+                    }
+                    _ => unreachable!(),
+                };
+
+                match await!(test_servers[$index].graph_requests_receiver.next()).unwrap() {
+                    GraphRequest::UpdateEdge(
+                        edge_currency,
+                        src,
+                        dest,
+                        _capacity_edge,
+                        response_sender,
+                    ) => {
+                        assert_eq!(edge_currency, currency);
+                        assert_eq!(src, client_public_key);
+                        assert_eq!(dest, PublicKey::from(&[12; PUBLIC_KEY_LEN]));
+                        response_sender.send(None).unwrap();
+                    }
+                    _ => unreachable!(),
+                };
+                await!(test_servers[$index].debug_event_receiver.next()).unwrap();
+            };
+        }
+
+        process_graph_request!(0);
+
+        // Server 1 reconnects:
+        await!(handle_connect_sync(
+            &mut test_servers[..],
+            &client_public_key
+        ));
+    }
+
+    /// Server 1 connects to server 0, and the servers exchange digests.
+    /// Server 1 is missing the current state of the client `client_public_key`, and server 0 can
+    /// prove the state is fresh to server 1. Server 0 sends the state to server 1.
+    async fn handle_connect_sync(test_servers: &mut [TestServer], client_public_key: &PublicKey) {
+        let conn_request = await!(test_servers[1].server_conn_request_receiver.next()).unwrap();
+        let (a_sender, b_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (b_sender, a_receiver) = mpsc::channel(CHANNEL_SIZE);
+
+        await!(test_servers[0]
+            .server_connections_sender
+            .send((test_servers[1].public_key.clone(), (b_sender, b_receiver))))
+        .unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        conn_request.reply(Some((a_sender, a_receiver)));
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+
+        // Server 1 receives the digest of server 0. Server 1 has no nodes to send:
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+
+        // Server 0 receives the digest of server 1, and sends server 1 the current state of the
+        // client:
+        await!(send_client_state(test_servers, client_public_key));
+    }
+
+    /// Server 0 sends server 1 the current state of the client `client_public_key`.
+    /// The client has two edges in the graph of server 0. Only one of them was reported by the
+    /// client, the other one can not be proven to server 1.
+    async fn send_client_state(test_servers: &mut [TestServer], client_public_key: &PublicKey) {
+        let currency = Currency::try_from("FST".to_owned()).unwrap();
+        let friend_public_key = PublicKey::from(&[12; PUBLIC_KEY_LEN]);
+        let rate = Rate { mul: 0, add: 1 };
+
+        match await!(test_servers[0].graph_requests_receiver.next()).unwrap() {
+            GraphRequest::GetNodeEdges(node, response_sender) => {
+                assert_eq!(&node, client_public_key);
+                response_sender
+                    .send(vec![
+                        (
+                            currency.clone(),
+                            friend_public_key.clone(),
+                            (100, 50, rate.clone()),
+                        ),
+                        (
+                            currency.clone(),
+                            PublicKey::from(&[13; PUBLIC_KEY_LEN]),
+                            (200, 0, rate.clone()),
+                        ),
+                    ])
+                    .unwrap();
+            }
+            _ => unreachable!(),
+        };
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        // Server 1 drops the edges it knows from older states of the client:
+        match await!(test_servers[1].graph_requests_receiver.next()).unwrap() {
+            GraphRequest::RemoveNode(node, response_sender) => {
+                assert_eq!(&node, client_public_key);
+                response_sender.send(false).unwrap();
+            }
+            _ => unreachable!(),
+        };
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+
+        // Server 1 applies the current edges of the client:
+        match await!(test_servers[1].graph_requests_receiver.next()).unwrap() {
+            GraphRequest::UpdateEdge(edge_currency, src, dest, capacity_edge, response_sender) => {
+                assert_eq!(edge_currency, currency);
+                assert_eq!(&src, client_public_key);
+                assert_eq!(dest, friend_public_key);
+                assert_eq!(capacity_edge, (100, 50, rate));
+                response_sender.send(None).unwrap();
+            }
+            _ => unreachable!(),
+        };
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+    }
+
+    #[test]
+    fn test_index_server_loop_server_sync() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_index_server_loop_server_sync(thread_pool.clone()));
+    }
+
+    async fn task_index_server_loop_server_join<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let mut test_servers = Vec::new();
+        test_servers.push(create_test_server(0, &[1], spawner.clone()));
+        test_servers.push(create_test_server(1, &[0], spawner.clone()));
+
+        // Connect a client to server 0, before server 1 joins:
+        let identity_client = create_identity_client(spawner.clone(), &[1, 1]);
+        let client_public_key = await!(identity_client.request_public_key()).unwrap();
+
+        let (mut client_sender, server_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (server_sender, mut client_receiver) = mpsc::channel(CHANNEL_SIZE);
+        await!(test_servers[0]
+            .client_connections_sender
+            .send((client_public_key.clone(), (server_sender, server_receiver))))
+        .unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        await!(test_servers[0].tick_sender.send(())).unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        let time_hash0 = match await!(client_receiver.next()).unwrap() {
+            IndexServerToClient::TimeHash(time_hash) => time_hash,
+            _ => unreachable!(),
+        };
+
+        // The client reports an edge to server 0:
+        let currency = Currency::try_from("FST".to_owned()).unwrap();
+        let index_mutations = vec![IndexMutation::UpdateFriend(UpdateFriend {
+            public_key: PublicKey::from(&[12; PUBLIC_KEY_LEN]),
+            send_capacity: 100,
+            recv_capacity: 50,
+            currency: currency.clone(),
+            rate: Rate { mul: 0, add: 1 },
+        })];
+
+        let mut mutations_update = MutationsUpdate {
+            node_public_key: client_public_key.clone(),
+            index_mutations,
+            time_hash: time_hash0.clone(),
+            session_id: Uid::from(&[0; UID_LEN]),
+            counter: 0,
+            rand_nonce: RandValue::from(&[0; RAND_VALUE_LEN]),
+            signature: Signature::from(&[0; SIGNATURE_LEN]),
+        };
+        mutations_update.signature =
+            await!(identity_client.request_signature(mutations_update.signature_buff().clone()))
+                .unwrap();
+        await!(client_sender.send(IndexClientToServer::MutationsUpdate(mutations_update))).unwrap();
+
+        match await!(test_servers[0].graph_requests_receiver.next()).unwrap() {
+            GraphRequest::Tick(node, response_sender) => {
+                assert_eq!(node, client_public_key);
+                response_sender.send(()).unwrap();
+            }
+            _ => unreachable!(),
+        };
+        match await!(test_servers[0].graph_requests_receiver.next()).unwrap() {
+            GraphRequest::UpdateEdge(_currency, src, _dest, _capacity_edge, response_sender) => {
+                assert_eq!(src, client_public_key);
+                response_sender.send(None).unwrap();
+            }
+            _ => unreachable!(),
+        };
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        // Server 1 joins with an empty graph, and the servers exchange digests:
+        let conn_request = await!(test_servers[1].server_conn_request_receiver.next()).unwrap();
+        let (a_sender, b_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (b_sender, a_receiver) = mpsc::channel(CHANNEL_SIZE);
+
+        await!(test_servers[0]
+            .server_connections_sender
+            .send((test_servers[1].public_key.clone(), (b_sender, b_receiver))))
+        .unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        conn_request.reply(Some((a_sender, a_receiver)));
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+
+        // Server 1 is missing the state of the client. The state was signed before server 1 has
+        // joined, so server 0 can not prove to server 1 that the state is fresh yet:
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        // The servers exchange time hashes:
+        await!(test_servers[1].tick_sender.send(())).unwrap();
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        await!(test_servers[0].tick_sender.send(())).unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+
+        let time_hash1 = match await!(client_receiver.next()).unwrap() {
+            IndexServerToClient::TimeHash(time_hash) => time_hash,
+            _ => unreachable!(),
+        };
+
+        // The client sends a keepalive to server 0:
+        let mut mutations_update = MutationsUpdate {
+            node_public_key: client_public_key.clone(),
+            index_mutations: Vec::new(),
+            time_hash: time_hash1.clone(),
+            session_id: Uid::from(&[0; UID_LEN]),
+            counter: 1,
+            rand_nonce: RandValue::from(&[1; RAND_VALUE_LEN]),
+            signature: Signature::from(&[0; SIGNATURE_LEN]),
+        };
+        mutations_update.signature =
+            await!(identity_client.request_signature(mutations_update.signature_buff().clone()))
+                .unwrap();
+        await!(client_sender.send(IndexClientToServer::MutationsUpdate(mutations_update))).unwrap();
+
+        match await!(test_servers[0].graph_requests_receiver.next()).unwrap() {
+            GraphRequest::Tick(node, response_sender) => {
+                assert_eq!(node, client_public_key);
+                response_sender.send(()).unwrap();
+            }
+            _ => unreachable!(),
+        };
+
+        // The keepalive proves the client's state is fresh. Server 0 sends the whole state of the
+        // client to server 1:
+        await!(send_client_state(&mut test_servers[..], &client_public_key));
+    }
+
+    #[test]
+    fn test_index_server_loop_server_join() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_index_server_loop_server_join(thread_pool.clone()));
+    }
+
+    async fn task_index_server_loop_trusted_servers_mutations<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        // Initially the servers do not trust each other:
+        let mut test_servers = Vec::new();
+        test_servers.push(create_test_server(0, &[], spawner.clone()));
+        test_servers.push(create_test_server(1, &[], spawner.clone()));
+
+        // Both servers start trusting each other:
+        await!(test_servers[1]
+            .trusted_mutations_sender
+            .send(TrustedServersMutation::AddServer(
+                test_servers[0].public_key.clone(),
+                0
+            )))
+        .unwrap();
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+
+        await!(test_servers[0]
+            .trusted_mutations_sender
+            .send(TrustedServersMutation::AddServer(
+                test_servers[1].public_key.clone(),
+                1
+            )))
+        .unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        // Server 1 connects to server 0:
+        await!(handle_connect(&mut test_servers[..], 1));
+
+        // Server 0 stops trusting server 1:
+        await!(test_servers[0].trusted_mutations_sender.send(
+            TrustedServersMutation::RemoveServer(test_servers[1].public_key.clone())
+        ))
+        .unwrap();
+        await!(test_servers[0].debug_event_receiver.next()).unwrap();
+
+        // The connection is closed, and server 1 attempts to reconnect:
+        await!(test_servers[1].debug_event_receiver.next()).unwrap();
+        let conn_request = await!(test_servers[1].server_conn_request_receiver.next()).unwrap();
+        assert_eq!(
+            conn_request.address,
+            (test_servers[0].public_key.clone(), 0)
+        );
+    }
+
+    #[test]
+    fn test_index_server_loop_trusted_servers_mutations() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_index_server_loop_trusted_servers_mutations(
+            thread_pool.clone(),
+        ));
+    }
+
     // TODO: Add tests.
 }
@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use common::int_convert::usize_to_u32;
+
+use crypto::identity::PublicKey;
+
+use proto::funder::messages::Currency;
+use proto::index_server::messages::{IndexMutation, MutationsUpdate, NodeDigest, NodeEdges};
+
+/// The signed mutations updates that have set the current edges of a node.
+/// Used to prove the edges of the node to remote servers, which can not trust our graph.
+#[derive(Debug, Default)]
+pub struct SignedEdges {
+    /// The mutations update that has set every edge, and the index of the mutation that has set
+    /// it. Edges are identified by (currency, friend public key).
+    edges: HashMap<(Currency, PublicKey), (u64, usize)>,
+    /// Mutations updates that have set current edges, by a locally assigned id.
+    mutations_updates: HashMap<u64, MutationsUpdate>,
+    next_id: u64,
+}
+
+impl SignedEdges {
+    /// Apply all the mutations of a verified mutations update of the node.
+    pub fn apply_mutations_update(&mut self, mutations_update: MutationsUpdate) {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        for (index, index_mutation) in mutations_update.index_mutations.iter().enumerate() {
+            match index_mutation {
+                IndexMutation::UpdateFriend(update_friend) => {
+                    self.edges.insert(
+                        (
+                            update_friend.currency.clone(),
+                            update_friend.public_key.clone(),
+                        ),
+                        (id, index),
+                    );
+                }
+                IndexMutation::RemoveFriend(friend_public_key) => {
+                    self.edges
+                        .retain(|(_currency, public_key), _| public_key != friend_public_key);
+                }
+            }
+        }
+        self.mutations_updates.insert(id, mutations_update);
+        self.forget_unused();
+    }
+
+    /// Add edges of the node received from a remote server.
+    /// `edge_indices` are the indices of the verified `UpdateFriend` mutations of
+    /// `mutations_update` that are current edges.
+    pub fn add_edges(&mut self, mutations_update: MutationsUpdate, edge_indices: &[usize]) {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        for &index in edge_indices {
+            if let Some(IndexMutation::UpdateFriend(update_friend)) =
+                mutations_update.index_mutations.get(index)
+            {
+                self.edges.insert(
+                    (
+                        update_friend.currency.clone(),
+                        update_friend.public_key.clone(),
+                    ),
+                    (id, index),
+                );
+            }
+        }
+        self.mutations_updates.insert(id, mutations_update);
+        self.forget_unused();
+    }
+
+    /// Forget mutations updates that do not set any current edge.
+    fn forget_unused(&mut self) {
+        let used_ids = self
+            .edges
+            .values()
+            .map(|(id, _index)| *id)
+            .collect::<HashSet<_>>();
+        self.mutations_updates.retain(|id, _| used_ids.contains(id));
+    }
+
+    /// Create the `NodeEdges` messages proving `current_edges`, the edges of the node that are
+    /// still in the graph. Edges that were not set by a known mutations update are left out.
+    pub fn node_edges(
+        &self,
+        node_digest: &NodeDigest,
+        current_edges: impl IntoIterator<Item = (Currency, PublicKey)>,
+    ) -> Vec<NodeEdges> {
+        let mut edge_indices = BTreeMap::<u64, Vec<u32>>::new();
+        for edge in current_edges {
+            if let Some((id, index)) = self.edges.get(&edge) {
+                edge_indices
+                    .entry(*id)
+                    .or_insert_with(Vec::new)
+                    .push(usize_to_u32(*index).unwrap());
+            }
+        }
+
+        edge_indices
+            .into_iter()
+            .map(|(id, edge_indices)| NodeEdges {
+                node_digest: node_digest.clone(),
+                mutations_update: self.mutations_updates[&id].clone(),
+                edge_indices,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::TryFrom;
+
+    use crypto::crypto_rand::{RandValue, RAND_VALUE_LEN};
+    use crypto::hash::{HashResult, HASH_RESULT_LEN};
+    use crypto::identity::{Signature, PUBLIC_KEY_LEN, SIGNATURE_LEN};
+    use crypto::uid::{Uid, UID_LEN};
+
+    use proto::funder::messages::Rate;
+    use proto::index_server::messages::UpdateFriend;
+
+    fn create_mutations_update(
+        counter: u64,
+        index_mutations: Vec<IndexMutation>,
+    ) -> MutationsUpdate {
+        MutationsUpdate {
+            node_public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+            index_mutations,
+            time_hash: HashResult::from(&[0; HASH_RESULT_LEN]),
+            session_id: Uid::from(&[0; UID_LEN]),
+            counter,
+            rand_nonce: RandValue::from(&[0; RAND_VALUE_LEN]),
+            signature: Signature::from(&[0; SIGNATURE_LEN]),
+        }
+    }
+
+    fn update_friend(friend: u8, currency: &Currency, send_capacity: u128) -> IndexMutation {
+        IndexMutation::UpdateFriend(UpdateFriend {
+            public_key: PublicKey::from(&[friend; PUBLIC_KEY_LEN]),
+            send_capacity,
+            recv_capacity: 0,
+            currency: currency.clone(),
+            rate: Rate { mul: 0, add: 0 },
+        })
+    }
+
+    #[test]
+    fn test_signed_edges_basic() {
+        let currency = Currency::try_from("FST".to_owned()).unwrap();
+        let node_digest = NodeDigest {
+            node_public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+            session_id: Uid::from(&[0; UID_LEN]),
+            counter: 2,
+        };
+        let edge = |friend: u8| (currency.clone(), PublicKey::from(&[friend; PUBLIC_KEY_LEN]));
+
+        let mut signed_edges = SignedEdges::default();
+        signed_edges.apply_mutations_update(create_mutations_update(
+            0,
+            vec![
+                update_friend(1, &currency, 10),
+                update_friend(2, &currency, 20),
+                update_friend(3, &currency, 30),
+            ],
+        ));
+        // Overrides the edge to friend 2, and removes the edge to friend 3:
+        signed_edges.apply_mutations_update(create_mutations_update(
+            1,
+            vec![
+                update_friend(2, &currency, 21),
+                IndexMutation::RemoveFriend(PublicKey::from(&[3; PUBLIC_KEY_LEN])),
+            ],
+        ));
+        // Does not set any edge, and is forgotten:
+        signed_edges.apply_mutations_update(create_mutations_update(2, Vec::new()));
+        assert_eq!(signed_edges.mutations_updates.len(), 2);
+
+        // Friend 4 was never reported by the node:
+        let node_edges =
+            signed_edges.node_edges(&node_digest, vec![edge(1), edge(2), edge(3), edge(4)]);
+        assert_eq!(node_edges.len(), 2);
+        assert_eq!(node_edges[0].node_digest, node_digest);
+        assert_eq!(node_edges[0].mutations_update.counter, 0);
+        assert_eq!(node_edges[0].edge_indices, vec![0]);
+        assert_eq!(node_edges[1].mutations_update.counter, 1);
+        assert_eq!(node_edges[1].edge_indices, vec![0]);
+
+        // Only edges that are still in the graph are sent:
+        let node_edges = signed_edges.node_edges(&node_digest, vec![edge(2)]);
+        assert_eq!(node_edges.len(), 1);
+        assert_eq!(node_edges[0].mutations_update.counter, 1);
+
+        // Edges received from a remote server:
+        let mutations_update = create_mutations_update(
+            0,
+            vec![
+                update_friend(1, &currency, 11),
+                IndexMutation::RemoveFriend(PublicKey::from(&[2; PUBLIC_KEY_LEN])),
+                update_friend(5, &currency, 50),
+            ],
+        );
+        signed_edges.add_edges(mutations_update, &[0, 1]);
+        let node_edges = signed_edges.node_edges(&node_digest, vec![edge(1), edge(2), edge(5)]);
+        assert_eq!(node_edges.len(), 2);
+        assert_eq!(node_edges[0].mutations_update.counter, 1);
+        assert_eq!(node_edges[0].edge_indices, vec![0]);
+        assert_eq!(node_edges[1].mutations_update.counter, 0);
+        assert_eq!(node_edges[1].edge_indices, vec![0]);
+    }
+}
@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::marker::Unpin;
+use std::path::{Path, PathBuf};
+
+use futures::{Stream, StreamExt};
+
+use atomicwrites;
+use bincode;
+
+use crypto::identity::PublicKey;
+use proto::funder::messages::{Currency, Rate};
+
+use crate::graph::graph_service::{GraphClient, GraphClientError, GraphSnapshot};
+
+/// A snapshot of the capacity graphs of the index server, as saved to disk.
+pub type IndexGraphSnapshot = GraphSnapshot<Currency, PublicKey, u128, Rate>;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    OpenError(io::Error),
+    ReadError(io::Error),
+    WriteError(atomicwrites::Error<io::Error>),
+    SerializeError(bincode::Error),
+    DeserializeError(bincode::Error),
+}
+
+/// Load a graph snapshot from file
+pub fn load_snapshot_from_file(path: &Path) -> Result<IndexGraphSnapshot, SnapshotError> {
+    let mut f = File::open(path).map_err(SnapshotError::OpenError)?;
+    let mut serialized_buff = Vec::new();
+    f.read_to_end(&mut serialized_buff)
+        .map_err(SnapshotError::ReadError)?;
+
+    bincode::deserialize(&serialized_buff).map_err(SnapshotError::DeserializeError)
+}
+
+/// Store a graph snapshot to file. The file is replaced atomically, so that a crash during
+/// the write will not leave a corrupt snapshot behind.
+pub fn store_snapshot_to_file(
+    graph_snapshot: &IndexGraphSnapshot,
+    path: &Path,
+) -> Result<(), SnapshotError> {
+    let serialized_buff =
+        bincode::serialize(graph_snapshot).map_err(SnapshotError::SerializeError)?;
+
+    let af = atomicwrites::AtomicFile::new(path, atomicwrites::AllowOverwrite);
+    af.write(|fw| fw.write_all(&serialized_buff))
+        .map_err(SnapshotError::WriteError)
+}
+
+#[derive(Debug)]
+pub enum SnapshotLoopError {
+    GraphClientError(GraphClientError),
+}
+
+impl From<GraphClientError> for SnapshotLoopError {
+    fn from(e: GraphClientError) -> Self {
+        SnapshotLoopError::GraphClientError(e)
+    }
+}
+
+/// Save a snapshot of the graph to `snapshot_path` every `snapshot_ticks` ticks.
+///
+/// Nodes restored from a snapshot at startup are removed after `restored_ticks` ticks, unless
+/// they were heard of since. This is the same treatment a node gets when it stops sending us
+/// updates.
+pub async fn snapshot_loop<TS>(
+    mut graph_client: GraphClient<Currency, PublicKey, u128, Rate>,
+    mut timer_stream: TS,
+    snapshot_path: PathBuf,
+    snapshot_ticks: usize,
+    restored_ticks: usize,
+) -> Result<(), SnapshotLoopError>
+where
+    TS: Stream + Unpin,
+{
+    assert!(snapshot_ticks > 0);
+
+    let mut ticks_to_snapshot = snapshot_ticks;
+    let mut opt_ticks_to_remove_restored = Some(restored_ticks);
+
+    while let Some(_) = await!(timer_stream.next()) {
+        if let Some(ticks_to_remove_restored) = opt_ticks_to_remove_restored.as_mut() {
+            *ticks_to_remove_restored = ticks_to_remove_restored.saturating_sub(1);
+            if *ticks_to_remove_restored == 0 {
+                await!(graph_client.remove_restored_nodes())?;
+                opt_ticks_to_remove_restored = None;
+            }
+        }
+
+        ticks_to_snapshot -= 1;
+        if ticks_to_snapshot > 0 {
+            continue;
+        }
+        ticks_to_snapshot = snapshot_ticks;
+
+        let graph_snapshot = await!(graph_client.snapshot())?;
+        // Failing to save a snapshot is not fatal. We will try again next time:
+        if let Err(e) = store_snapshot_to_file(&graph_snapshot, &snapshot_path) {
+            error!("snapshot_loop(): Failed to store snapshot: {:?}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::convert::TryFrom;
+
+    use tempfile::tempdir;
+
+    use crypto::identity::PUBLIC_KEY_LEN;
+
+    #[test]
+    fn test_store_load_snapshot() {
+        let dir = tempdir().unwrap();
+        let snapshot_path = dir.path().join("graph_snapshot");
+
+        // There is no snapshot yet:
+        assert!(load_snapshot_from_file(&snapshot_path).is_err());
+
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let rate = Rate { mul: 1, add: 2 };
+
+        let mut graph_snapshot: IndexGraphSnapshot = HashMap::new();
+        graph_snapshot.insert(
+            Currency::try_from("FST".to_owned()).unwrap(),
+            vec![
+                (pk_a.clone(), pk_b.clone(), (10, 20, rate.clone()), 3),
+                (pk_b.clone(), pk_a.clone(), (20, 10, rate.clone()), 0),
+            ],
+        );
+
+        store_snapshot_to_file(&graph_snapshot, &snapshot_path).unwrap();
+        assert_eq!(
+            load_snapshot_from_file(&snapshot_path).unwrap(),
+            graph_snapshot
+        );
+
+        // A newer snapshot replaces the old one:
+        graph_snapshot.clear();
+        store_snapshot_to_file(&graph_snapshot, &snapshot_path).unwrap();
+        assert!(load_snapshot_from_file(&snapshot_path).unwrap().is_empty());
+
+        dir.close().unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+
+use common::watcher::watch_changes;
+
+use crypto::identity::PublicKey;
+use timer::TimerClient;
+
+use crate::server::TrustedServersMutation;
+
+#[derive(Debug)]
+pub enum TrustedWatcherError {
+    RequestTimerStreamError,
+    MutationsSenderError,
+}
+
+/// Calculate the mutations required to change the set of trusted servers `old_trusted` into
+/// `new_trusted`.
+pub fn diff_trusted_servers<A>(
+    old_trusted: &HashMap<PublicKey, A>,
+    new_trusted: &HashMap<PublicKey, A>,
+) -> Vec<TrustedServersMutation<A>>
+where
+    A: Clone + PartialEq,
+{
+    let mut mutations = Vec::new();
+
+    for public_key in old_trusted.keys() {
+        if !new_trusted.contains_key(public_key) {
+            mutations.push(TrustedServersMutation::RemoveServer(public_key.clone()));
+        }
+    }
+
+    for (public_key, address) in new_trusted {
+        if old_trusted.get(public_key) != Some(address) {
+            mutations.push(TrustedServersMutation::AddServer(
+                public_key.clone(),
+                address.clone(),
+            ));
+        }
+    }
+
+    mutations
+}
+
+/// Reload the set of trusted servers every `scan_ticks` ticks, and send the changes through
+/// `mutations_sender`.
+///
+/// `trusted_servers` is the set of trusted servers the index server was started with.
+/// If loading the trusted servers fails, the error is logged and the set of trusted servers is
+/// left unchanged until the next successful load.
+pub async fn trusted_servers_watcher<A, F, E>(
+    load_trusted_servers: F,
+    mut trusted_servers: HashMap<PublicKey, A>,
+    mut timer_client: TimerClient,
+    scan_ticks: usize,
+    mut mutations_sender: mpsc::Sender<TrustedServersMutation<A>>,
+) -> Result<(), TrustedWatcherError>
+where
+    A: Clone + PartialEq,
+    F: FnMut() -> Result<HashMap<PublicKey, A>, E>,
+    E: Debug,
+{
+    let timer_stream = await!(timer_client.request_timer_stream())
+        .map_err(|_| TrustedWatcherError::RequestTimerStreamError)?;
+
+    let mut trusted_servers_changes = watch_changes(
+        load_trusted_servers,
+        trusted_servers.clone(),
+        timer_stream,
+        scan_ticks,
+    );
+
+    while let Some(new_trusted_servers) = await!(trusted_servers_changes.next()) {
+        for mutation in diff_trusted_servers(&trusted_servers, &new_trusted_servers) {
+            await!(mutations_sender.send(mutation))
+                .map_err(|_| TrustedWatcherError::MutationsSenderError)?;
+        }
+        trusted_servers = new_trusted_servers;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::ThreadPool;
+    use futures::task::{Spawn, SpawnExt};
+    use futures::FutureExt;
+
+    use crypto::identity::PUBLIC_KEY_LEN;
+    use timer::{dummy_timer_multi_sender, TimerTick};
+
+    #[test]
+    fn test_diff_trusted_servers() {
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let pk_c = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+
+        let mut old_trusted = HashMap::new();
+        old_trusted.insert(pk_a.clone(), 1u32);
+        old_trusted.insert(pk_b.clone(), 2u32);
+
+        assert!(diff_trusted_servers(&old_trusted, &old_trusted).is_empty());
+
+        let mut new_trusted = HashMap::new();
+        new_trusted.insert(pk_b.clone(), 3u32);
+        new_trusted.insert(pk_c.clone(), 4u32);
+
+        let mut mutations = diff_trusted_servers(&old_trusted, &new_trusted);
+        // Sort the added servers, to get deterministic results:
+        mutations[1..].sort_by_key(|mutation| match mutation {
+            TrustedServersMutation::AddServer(_public_key, address) => *address,
+            TrustedServersMutation::RemoveServer(_public_key) => unreachable!(),
+        });
+        assert_eq!(
+            mutations,
+            vec![
+                TrustedServersMutation::RemoveServer(pk_a.clone()),
+                TrustedServersMutation::AddServer(pk_b.clone(), 3u32),
+                TrustedServersMutation::AddServer(pk_c.clone(), 4u32),
+            ]
+        );
+    }
+
+    async fn task_trusted_servers_watcher_basic<S>(mut spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (mut tick_sender_receiver, timer_client) = dummy_timer_multi_sender(spawner.clone());
+
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+
+        let mut trusted_servers = HashMap::new();
+        trusted_servers.insert(pk_a.clone(), 1u32);
+
+        // Results of consecutive loads of the trusted servers:
+        let mut load_results = vec![
+            Ok(trusted_servers.clone()),
+            Err(()),
+            Ok(HashMap::new()),
+            Ok(vec![(pk_b.clone(), 2u32)].into_iter().collect()),
+        ]
+        .into_iter();
+        let load_trusted_servers = move || load_results.next().unwrap();
+
+        let (mutations_sender, mut mutations_receiver) = mpsc::channel(0);
+
+        let scan_ticks = 4;
+        let watcher_fut = trusted_servers_watcher(
+            load_trusted_servers,
+            trusted_servers,
+            timer_client,
+            scan_ticks,
+            mutations_sender,
+        )
+        .map(|_| ());
+        spawner.spawn(watcher_fut).unwrap();
+
+        let mut tick_sender = await!(tick_sender_receiver.next()).unwrap();
+
+        // Nothing changed, and then the load fails:
+        for _ in 0..2 * scan_ticks {
+            await!(tick_sender.send(TimerTick)).unwrap();
+        }
+
+        // Server a is removed:
+        for _ in 0..scan_ticks {
+            await!(tick_sender.send(TimerTick)).unwrap();
+        }
+        assert_eq!(
+            await!(mutations_receiver.next()).unwrap(),
+            TrustedServersMutation::RemoveServer(pk_a.clone())
+        );
+
+        // Server b is added:
+        for _ in 0..scan_ticks {
+            await!(tick_sender.send(TimerTick)).unwrap();
+        }
+        assert_eq!(
+            await!(mutations_receiver.next()).unwrap(),
+            TrustedServersMutation::AddServer(pk_b.clone(), 2u32)
+        );
+    }
+
+    #[test]
+    fn test_trusted_servers_watcher_basic() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_trusted_servers_watcher_basic(thread_pool.clone()));
+    }
+}
@@ -2,6 +2,7 @@ use std::marker::PhantomData;
 
 use super::verifier::Verifier;
 use crypto::hash::{HashResult, HASH_RESULT_LEN};
+use proto::index_server::messages::VerifierStats;
 
 pub struct DummyVerifier<N, B, U> {
     phantom_n: PhantomData<N>,
@@ -45,6 +46,15 @@ impl<N, B, U> Verifier for DummyVerifier<N, B, U> {
         Some(&self.hash_vec)
     }
 
+    fn is_recent(&self, _hashes: &[HashResult]) -> bool {
+        true
+    }
+
+    fn extend_proof(&self, _hashes: &[HashResult], _neighbor: &B) -> Option<Vec<Vec<HashResult>>> {
+        // No additional proof is required:
+        Some(Vec::new())
+    }
+
     fn tick(&mut self) -> (HashResult, Vec<N>) {
         // Nothing happens. Always the same tick.
         (HashResult::from(&[0; HASH_RESULT_LEN]), Vec::new())
@@ -59,4 +69,12 @@ impl<N, B, U> Verifier for DummyVerifier<N, B, U> {
         // Nothing happens
         None
     }
+
+    fn stats(&self) -> VerifierStats {
+        VerifierStats {
+            num_neighbors: 0,
+            num_ticks: 0,
+            num_nodes: 0,
+        }
+    }
 }
@@ -6,13 +6,21 @@ use std::collections::{HashMap, VecDeque};
 /// Prefix put before hashing a list of hashes:
 const HASH_CLOCK_PREFIX: &[u8] = b"HASH_CLOCK";
 
+/// Information about a tick hash created at this HashClock.
+struct TickInfo<N> {
+    /// The list of hashes that produce the tick hash
+    expansion: Vec<HashResult>,
+    /// Neighbors whose hashes are included in the expansion
+    neighbors: Vec<N>,
+}
+
 pub struct HashClock<N> {
     /// Last hash we received from each neighbor
     neighbor_hashes: HashMap<N, HashResult>,
     /// Maximum length of last_ticks:
     last_ticks_max_len: usize,
     last_ticks: VecDeque<HashResult>,
-    last_ticks_map: HashMap<HashResult, Vec<HashResult>>,
+    last_ticks_map: HashMap<HashResult, TickInfo<N>>,
 }
 
 /// Combine a list of hashes into one hash result:
@@ -46,7 +54,7 @@ where
     }
 
     /// Insert a new pair of (hash, hash_info)
-    fn insert_tick_hash(&mut self, tick_hash: HashResult, expansion: Vec<HashResult>) {
+    fn insert_tick_hash(&mut self, tick_hash: HashResult, tick_info: TickInfo<N>) {
         assert!(self.last_ticks_max_len > 0);
         self.last_ticks.push_back(tick_hash.clone());
 
@@ -56,7 +64,7 @@ where
         }
 
         assert!(self.last_ticks.len() <= self.last_ticks_max_len);
-        self.last_ticks_map.insert(tick_hash.clone(), expansion);
+        self.last_ticks_map.insert(tick_hash.clone(), tick_info);
     }
 
     /// Should be called when a new hash is received from a neighbor.
@@ -69,6 +77,16 @@ where
         self.neighbor_hashes.remove(neighbor)
     }
 
+    /// Amount of neighbors whose hashes are included in our next tick hash
+    pub fn num_neighbors(&self) -> usize {
+        self.neighbor_hashes.len()
+    }
+
+    /// Amount of recent tick hashes remembered by this HashClock
+    pub fn num_ticks(&self) -> usize {
+        self.last_ticks.len()
+    }
+
     /// Advance the time in the clock by one tick.
     /// The resulting tick_hash should be sent to all the neighbors.
     pub fn tick(&mut self, rand_value: RandValue) -> HashResult {
@@ -77,13 +95,27 @@ where
         let hashed_rand_value = sha_512_256(&rand_value);
         expansion.push(hashed_rand_value);
 
+        // Include our previous tick hash. This allows proving to a neighbor that an older tick
+        // hash of ours is composed of one of its tick hashes. (See `get_expansion_chain()`)
+        if let Some(last_tick_hash) = self.last_ticks.back() {
+            expansion.push(last_tick_hash.clone());
+        }
+
         // Concatenate all hashes, and update hash_info accordingly:
-        for hash in self.neighbor_hashes.values() {
+        let mut neighbors = Vec::new();
+        for (neighbor, hash) in &self.neighbor_hashes {
             expansion.push(hash.clone());
+            neighbors.push(neighbor.clone());
         }
 
         let tick_hash = hash_hashes(&expansion);
-        self.insert_tick_hash(tick_hash.clone(), expansion);
+        self.insert_tick_hash(
+            tick_hash.clone(),
+            TickInfo {
+                expansion,
+                neighbors,
+            },
+        );
 
         tick_hash
     }
@@ -92,7 +124,44 @@ where
     pub fn get_expansion(&self, tick_hash: &HashResult) -> Option<&[HashResult]> {
         self.last_ticks_map
             .get(tick_hash)
-            .map(|expansion| &expansion[..])
+            .map(|tick_info| &tick_info.expansion[..])
+    }
+
+    /// Check if `expansion` produces a tick hash that is still remembered by this HashClock.
+    pub fn is_recent_expansion(&self, expansion: &[HashResult]) -> bool {
+        self.last_ticks_map.contains_key(&hash_hashes(expansion))
+    }
+
+    /// Given an expansion of a tick hash (that was created in this HashClock), create a chain of
+    /// expansions that continues the hash proof for `neighbor`.
+    /// The chain goes through our previous tick hashes, until reaching a tick hash that includes a
+    /// hash received from `neighbor`. The chain is empty if the tick hash of `expansion` already
+    /// includes a hash received from `neighbor`.
+    ///
+    /// Returns None if no such chain exists, for example if we have not received any hash from
+    /// `neighbor` recently.
+    pub fn get_expansion_chain(
+        &self,
+        expansion: &[HashResult],
+        neighbor: &N,
+    ) -> Option<Vec<Vec<HashResult>>> {
+        let tick_hash = hash_hashes(expansion);
+        let pos = self
+            .last_ticks
+            .iter()
+            .position(|last_tick_hash| last_tick_hash == &tick_hash)?;
+
+        let mut expansion_chain = Vec::new();
+        for last_tick_hash in self.last_ticks.iter().take(pos + 1).rev() {
+            let tick_info = &self.last_ticks_map[last_tick_hash];
+            if last_tick_hash != &tick_hash {
+                expansion_chain.push(tick_info.expansion.clone());
+            }
+            if tick_info.neighbors.contains(neighbor) {
+                return Some(expansion_chain);
+            }
+        }
+        None
     }
 
     /// Verify a chain of hash proof links.
@@ -205,4 +274,59 @@ mod tests {
             .verify_expansion_chain(&origin_tick_hash, &[&expansion1, &expansion2, &expansion3])
             .is_none());
     }
+
+    #[test]
+    fn test_hash_clock_expansion_chain() {
+        let last_ticks_max_len = 4;
+
+        let mut hash_clock0: HashClock<u32> = HashClock::new(last_ticks_max_len);
+        let mut hash_clock1 = HashClock::new(last_ticks_max_len);
+
+        // Neighbor 0 sends a tick hash to neighbor 1:
+        let rand_value = RandValue::from(&[0; RAND_VALUE_LEN]);
+        let tick_hash0 = hash_clock0.tick(rand_value);
+        hash_clock1.neighbor_tick(0, tick_hash0);
+
+        let rand_value = RandValue::from(&[1; RAND_VALUE_LEN]);
+        let tick_hash1 = hash_clock1.tick(rand_value);
+
+        // Neighbor 0 disconnects from neighbor 1, but neighbor 1 keeps ticking:
+        hash_clock1.remove_neighbor(&0);
+        let rand_value = RandValue::from(&[2; RAND_VALUE_LEN]);
+        let origin_tick_hash = hash_clock1.tick(rand_value);
+
+        // `origin_tick_hash` does not include a hash from neighbor 0 directly:
+        let expansion = hash_clock1
+            .get_expansion(&origin_tick_hash)
+            .unwrap()
+            .to_vec();
+        assert!(hash_clock0
+            .verify_expansion_chain(&origin_tick_hash, &[&expansion])
+            .is_none());
+
+        // Going through the previous tick hash of neighbor 1 we reach a hash from neighbor 0:
+        let expansion_chain = hash_clock1.get_expansion_chain(&expansion, &0).unwrap();
+        assert_eq!(
+            expansion_chain,
+            vec![hash_clock1.get_expansion(&tick_hash1).unwrap().to_vec()]
+        );
+
+        let mut full_chain: Vec<&[HashResult]> = vec![&expansion];
+        full_chain.extend(expansion_chain.iter().map(|hashes| &hashes[..]));
+        assert!(hash_clock0
+            .verify_expansion_chain(&origin_tick_hash, &full_chain)
+            .is_some());
+
+        // No hash was ever received from neighbor 2:
+        assert!(hash_clock1.get_expansion_chain(&expansion, &2).is_none());
+
+        // Old expansions are forgotten:
+        assert!(hash_clock1.is_recent_expansion(&expansion));
+        for iter in 0..last_ticks_max_len {
+            let rand_value = RandValue::from(&[iter as u8; RAND_VALUE_LEN]);
+            hash_clock1.tick(rand_value);
+        }
+        assert!(!hash_clock1.is_recent_expansion(&expansion));
+        assert!(hash_clock1.get_expansion_chain(&expansion, &0).is_none());
+    }
 }
@@ -87,6 +87,11 @@ where
         removed_nodes
     }
 
+    /// Amount of nodes that have a ratchet
+    pub fn num_nodes(&self) -> usize {
+        self.ratchets.len()
+    }
+
     /// Try to update a certain ratchet.
     /// Returns true if ratchet moved forward (Or created)
     pub fn update(&mut self, node: &N, session_id: &U, counter: u64) -> bool {
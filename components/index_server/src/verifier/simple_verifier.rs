@@ -1,6 +1,10 @@
+use common::int_convert::usize_to_u64;
+
 use crypto::crypto_rand::{CryptoRandom, RandValue};
 use crypto::hash::HashResult;
 
+use proto::index_server::messages::VerifierStats;
+
 use super::hash_clock::HashClock;
 use super::ratchet::RatchetPool;
 use super::verifier::Verifier;
@@ -67,6 +71,14 @@ where
         Some(hashes)
     }
 
+    fn is_recent(&self, hashes: &[HashResult]) -> bool {
+        self.hash_clock.is_recent_expansion(hashes)
+    }
+
+    fn extend_proof(&self, hashes: &[HashResult], neighbor: &B) -> Option<Vec<Vec<HashResult>>> {
+        self.hash_clock.get_expansion_chain(hashes, neighbor)
+    }
+
     fn tick(&mut self) -> (HashResult, Vec<N>) {
         let rand_value = RandValue::new(&self.rng);
         (self.hash_clock.tick(rand_value), self.ratchet_pool.tick())
@@ -79,6 +91,14 @@ where
     fn remove_neighbor(&mut self, neighbor: &B) -> Option<HashResult> {
         self.hash_clock.remove_neighbor(neighbor)
     }
+
+    fn stats(&self) -> VerifierStats {
+        VerifierStats {
+            num_neighbors: usize_to_u64(self.hash_clock.num_neighbors()).unwrap(),
+            num_ticks: usize_to_u64(self.hash_clock.num_ticks()).unwrap(),
+            num_nodes: usize_to_u64(self.ratchet_pool.num_nodes()).unwrap(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -135,6 +155,15 @@ mod tests {
             )
             .unwrap()
             .to_vec();
+
+        assert_eq!(
+            svs[0].stats(),
+            VerifierStats {
+                num_neighbors: 3,
+                num_ticks: 8,
+                num_nodes: 1,
+            }
+        );
     }
 
     // TODO: Add more tests?
@@ -1,5 +1,7 @@
 use crypto::hash::HashResult;
 
+use proto::index_server::messages::VerifierStats;
+
 pub trait Verifier {
     type Node;
     type Neighbor;
@@ -17,6 +19,20 @@ pub trait Verifier {
         counter: u64,
     ) -> Option<&[HashResult]>;
 
+    /// Check if a list of hashes previously returned from `verify()` still proves freshness.
+    /// Old hashes are forgotten as time passes.
+    fn is_recent(&self, hashes: &[HashResult]) -> bool;
+
+    /// Continue a time proof for a message that was verified earlier, so that `neighbor`
+    /// can verify it. `hashes` is the list of hashes returned from `verify()` for this message.
+    /// Returns the list of expansions to append to the time proof chain, or None if the message
+    /// can not be proven fresh to `neighbor`.
+    fn extend_proof(
+        &self,
+        hashes: &[HashResult],
+        neighbor: &Self::Neighbor,
+    ) -> Option<Vec<Vec<HashResult>>>;
+
     /// One time tick. Returns a `tick_hash` representing the local current time,
     /// and a vector of all the nodes removed due to timeout
     fn tick(&mut self) -> (HashResult, Vec<Self::Node>);
@@ -32,4 +48,7 @@ pub trait Verifier {
     /// Remove a neighbor. This method should be invoked when a neighbor disconnects.
     /// If not called, the time proofs (list of hashes) will be larger than needed.
     fn remove_neighbor(&mut self, neighbor: &Self::Neighbor) -> Option<HashResult>;
+
+    /// Get a summary of the verifier's state, for monitoring.
+    fn stats(&self) -> VerifierStats;
 }
@@ -17,6 +17,7 @@
 extern crate log;
 
 mod net_connector;
+mod net_listener;
 mod resolver;
 mod tcp_connector;
 mod tcp_listener;
@@ -26,4 +27,5 @@ mod types;
 mod utils;
 
 pub use self::net_connector::NetConnector;
+pub use self::net_listener::NetListener;
 pub use self::tcp_listener::TcpListener;
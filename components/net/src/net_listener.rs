@@ -0,0 +1,83 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use futures::channel::mpsc;
+use futures::task::Spawn;
+
+use common::conn::{ConnPairVec, Listener};
+use proto::net::messages::NetAddress;
+
+use crate::tcp_listener::TcpListener;
+
+/// Get the port of a `host:port` address.
+fn net_address_port(net_address: &NetAddress) -> Option<u16> {
+    net_address.as_str().rsplit(':').next()?.parse().ok()
+}
+
+/// Listen for incoming TCP connections for a given address.
+/// The host part of the address is the name others use to reach us, and it might not belong to a
+/// local interface (For example, behind NAT). Therefore we listen on the configured bind address
+/// if there is one, and otherwise on all IPv4 interfaces at the port of the given address.
+#[derive(Clone)]
+pub struct NetListener<S> {
+    opt_bind_addr: Option<SocketAddr>,
+    tcp_listener: TcpListener<S>,
+}
+
+impl<S> NetListener<S> {
+    pub fn new(opt_bind_addr: Option<SocketAddr>, max_frame_length: usize, spawner: S) -> Self {
+        NetListener {
+            opt_bind_addr,
+            tcp_listener: TcpListener::new(max_frame_length, spawner),
+        }
+    }
+}
+
+impl<S> Listener for NetListener<S>
+where
+    S: Spawn + Send + Clone + 'static,
+{
+    type Connection = ConnPairVec;
+    type Config = ();
+    type Arg = NetAddress;
+
+    fn listen(
+        self,
+        net_address: Self::Arg,
+    ) -> (mpsc::Sender<Self::Config>, mpsc::Receiver<Self::Connection>) {
+        if let Some(bind_addr) = self.opt_bind_addr {
+            return self.tcp_listener.listen(bind_addr);
+        }
+
+        let port = match net_address_port(&net_address) {
+            Some(port) => port,
+            None => {
+                warn!("Invalid listen address {:?}", net_address);
+                // Return empty channels:
+                let (config_sender, _config_receiver) = mpsc::channel(0);
+                let (_conn_sender, conn_receiver) = mpsc::channel(0);
+                return (config_sender, conn_receiver);
+            }
+        };
+
+        let socket_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port);
+        self.tcp_listener.listen(socket_addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_net_address_port() {
+        let net_address: NetAddress = "example.com:1337".to_owned().try_into().unwrap();
+        assert_eq!(net_address_port(&net_address), Some(1337));
+
+        let net_address: NetAddress = "::1:1338".to_owned().try_into().unwrap();
+        assert_eq!(net_address_port(&net_address), Some(1338));
+
+        let net_address: NetAddress = "example.com".to_owned().try_into().unwrap();
+        assert_eq!(net_address_port(&net_address), None);
+    }
+}
@@ -12,6 +12,7 @@ use common::conn::{ConnPairVec, Listener};
 use futures::compat::Stream01CompatExt;
 
 /// Listen for incoming TCP connections
+#[derive(Clone)]
 pub struct TcpListener<S> {
     max_frame_length: usize,
     spawner: S,
@@ -1,5 +1,5 @@
 use std::convert::TryInto;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
 use env_logger;
 
@@ -11,6 +11,7 @@ use common::conn::{FutTransform, Listener};
 use proto::net::messages::NetAddress;
 
 use crate::net_connector::NetConnector;
+use crate::net_listener::NetListener;
 use crate::tcp_connector::TcpConnector;
 use crate::tcp_listener::TcpListener;
 
@@ -27,6 +28,13 @@ fn get_available_port_v4() -> u16 {
     listener.local_addr().unwrap().port()
 }
 
+/// Get an available port we can listen on (IPv6)
+fn get_available_port_v6() -> u16 {
+    let socket_addr = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 0);
+    let listener = TokioTcpListener::bind(&socket_addr).unwrap();
+    listener.local_addr().unwrap().port()
+}
+
 const TEST_MAX_FRAME_LEN: usize = 0x100;
 
 async fn task_tcp_client_server_v4<S>(spawner: S)
@@ -107,6 +115,73 @@ fn test_net_connector_v4_basic() {
     thread_pool.run(task_net_connector_v4_basic(thread_pool.clone()));
 }
 
+async fn task_net_listener_v4_basic<S>(spawner: S)
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let available_port = get_available_port_v4();
+
+    let net_listener = NetListener::new(None, TEST_MAX_FRAME_LEN, spawner.clone());
+    let mut net_connector = NetConnector::new(TEST_MAX_FRAME_LEN, spawner.clone(), spawner.clone());
+
+    // Only the port of the listen address is used:
+    let listen_address: NetAddress = format!("example.com:{}", available_port)
+        .try_into()
+        .unwrap();
+    let (_config_sender, mut incoming_connections) = net_listener.listen(listen_address);
+
+    let net_address: NetAddress = format!("127.0.0.1:{}", available_port).try_into().unwrap();
+
+    let (mut client_sender, mut client_receiver) =
+        await!(net_connector.transform(net_address.clone())).unwrap();
+    let (mut server_sender, mut server_receiver) = await!(incoming_connections.next()).unwrap();
+
+    await!(client_sender.send(vec![1, 2, 3])).unwrap();
+    assert_eq!(await!(server_receiver.next()).unwrap(), vec![1, 2, 3]);
+
+    await!(server_sender.send(vec![3, 2, 1])).unwrap();
+    assert_eq!(await!(client_receiver.next()).unwrap(), vec![3, 2, 1]);
+}
+
+#[test]
+fn test_net_listener_v4_basic() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_net_listener_v4_basic(thread_pool.clone()));
+}
+
+async fn task_net_listener_v6_bind_addr<S>(spawner: S)
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let available_port = get_available_port_v6();
+    let bind_addr = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), available_port);
+
+    let net_listener = NetListener::new(Some(bind_addr), TEST_MAX_FRAME_LEN, spawner.clone());
+    let mut net_connector = NetConnector::new(TEST_MAX_FRAME_LEN, spawner.clone(), spawner.clone());
+
+    // The configured bind address is used instead of the listen address:
+    let listen_address: NetAddress = "example.com:1".to_owned().try_into().unwrap();
+    let (_config_sender, mut incoming_connections) = net_listener.listen(listen_address);
+
+    let net_address: NetAddress = format!("[::1]:{}", available_port).try_into().unwrap();
+
+    let (mut client_sender, mut client_receiver) =
+        await!(net_connector.transform(net_address.clone())).unwrap();
+    let (mut server_sender, mut server_receiver) = await!(incoming_connections.next()).unwrap();
+
+    await!(client_sender.send(vec![1, 2, 3])).unwrap();
+    assert_eq!(await!(server_receiver.next()).unwrap(), vec![1, 2, 3]);
+
+    await!(server_sender.send(vec![3, 2, 1])).unwrap();
+    assert_eq!(await!(client_receiver.next()).unwrap(), vec![3, 2, 1]);
+}
+
+#[test]
+fn test_net_listener_v6_bind_addr() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_net_listener_v6_bind_addr(thread_pool.clone()));
+}
+
 async fn task_net_connector_v4_drop_sender<S>(spawner: S)
 where
     S: Spawn + Clone + Send + 'static,
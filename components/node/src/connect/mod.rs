@@ -4,5 +4,6 @@ mod node_connection;
 pub use self::connect::{node_connect, NodeConnection};
 
 pub use self::node_connection::{
-    config::AppConfig, report::AppReport, routes::AppRoutes, send_funds::AppSendFunds,
+    config::AppConfig, history::AppHistory, report::AppReport, routes::AppRoutes,
+    send_funds::AppSendFunds,
 };
@@ -7,11 +7,13 @@ use crypto::crypto_rand::{CryptoRandom, OffstSystemRandom};
 use crypto::identity::{PublicKey, Signature};
 use crypto::uid::Uid;
 
-use proto::app_server::messages::{AppRequest, AppToAppServer, NamedRelayAddress, RelayAddress};
+use proto::app_server::messages::{AppRequest, AppToAppServer, ChannelAddress, NamedRelayAddress};
 use proto::funder::messages::{
-    AddFriend, ResetFriendChannel, SetFriendRelays, SetFriendRemoteMaxDebt,
+    AddFriend, Currency, CurrencyBalance, DebtPolicy, FreezePolicy, Rate, ResetFriendChannel,
+    SetFriendDebtPolicy, SetFriendRate, SetFriendRelays, SetFriendRemoteMaxDebt,
 };
 use proto::index_server::messages::NamedIndexServerAddress;
+use proto::net::messages::NetAddress;
 
 #[derive(Debug)]
 pub struct AppConfigError;
@@ -74,18 +76,25 @@ where
         await!(self.send_request(AppRequest::RemoveRelay(relay_public_key)))
     }
 
+    pub async fn set_direct_address(
+        &mut self,
+        opt_direct_address: Option<NetAddress>,
+    ) -> Result<(), AppConfigError> {
+        await!(self.send_request(AppRequest::SetDirectAddress(opt_direct_address)))
+    }
+
     pub async fn add_friend(
         &mut self,
         friend_public_key: PublicKey,
-        relays: Vec<RelayAddress>,
+        relays: Vec<ChannelAddress>,
         name: String,
-        balance: i128,
+        balances: Vec<CurrencyBalance>,
     ) -> Result<(), AppConfigError> {
         let add_friend = AddFriend {
             friend_public_key,
             relays,
             name,
-            balance,
+            balances,
         };
         await!(self.send_request(AppRequest::AddFriend(add_friend)))
     }
@@ -93,7 +102,7 @@ where
     pub async fn set_friend_relays(
         &mut self,
         friend_public_key: PublicKey,
-        relays: Vec<RelayAddress>,
+        relays: Vec<ChannelAddress>,
     ) -> Result<(), AppConfigError> {
         let set_friend_relays = SetFriendRelays {
             friend_public_key,
@@ -140,10 +149,12 @@ where
     pub async fn set_friend_remote_max_debt(
         &mut self,
         friend_public_key: PublicKey,
+        currency: Currency,
         remote_max_debt: u128,
     ) -> Result<(), AppConfigError> {
         let set_friend_remote_max_debt = SetFriendRemoteMaxDebt {
             friend_public_key,
+            currency,
             remote_max_debt,
         };
         await!(self.send_request(AppRequest::SetFriendRemoteMaxDebt(
@@ -151,6 +162,44 @@ where
         )))
     }
 
+    pub async fn set_friend_rate(
+        &mut self,
+        friend_public_key: PublicKey,
+        rate: Rate,
+    ) -> Result<(), AppConfigError> {
+        let set_friend_rate = SetFriendRate {
+            friend_public_key,
+            rate,
+        };
+        await!(self.send_request(AppRequest::SetFriendRate(set_friend_rate)))
+    }
+
+    pub async fn set_friend_debt_policy(
+        &mut self,
+        friend_public_key: PublicKey,
+        debt_policy: DebtPolicy,
+    ) -> Result<(), AppConfigError> {
+        let set_friend_debt_policy = SetFriendDebtPolicy {
+            friend_public_key,
+            debt_policy,
+        };
+        await!(self.send_request(AppRequest::SetFriendDebtPolicy(set_friend_debt_policy)))
+    }
+
+    pub async fn clear_friend_debt_policy(
+        &mut self,
+        friend_public_key: PublicKey,
+    ) -> Result<(), AppConfigError> {
+        await!(self.send_request(AppRequest::ClearFriendDebtPolicy(friend_public_key)))
+    }
+
+    pub async fn settle_friend(
+        &mut self,
+        friend_public_key: PublicKey,
+    ) -> Result<(), AppConfigError> {
+        await!(self.send_request(AppRequest::SettleFriend(friend_public_key)))
+    }
+
     pub async fn reset_friend_channel(
         &mut self,
         friend_public_key: PublicKey,
@@ -167,6 +216,21 @@ where
         await!(self.send_request(AppRequest::ResetFriendChannel(reset_friend_channel)))
     }
 
+    pub async fn set_freeze_policy(
+        &mut self,
+        freeze_policy: FreezePolicy,
+    ) -> Result<(), AppConfigError> {
+        await!(self.send_request(AppRequest::SetFreezePolicy(freeze_policy)))
+    }
+
+    /// Hold incoming payments until they are accepted or rejected by an app.
+    pub async fn set_hold_incoming_payments(
+        &mut self,
+        hold_incoming_payments: bool,
+    ) -> Result<(), AppConfigError> {
+        await!(self.send_request(AppRequest::SetHoldIncomingPayments(hold_incoming_payments)))
+    }
+
     pub async fn add_index_server(
         &mut self,
         named_index_server: NamedIndexServerAddress,
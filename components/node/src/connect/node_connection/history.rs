@@ -0,0 +1,80 @@
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+
+use common::multi_consumer::MultiConsumerClient;
+
+use crypto::crypto_rand::{CryptoRandom, OffstSystemRandom};
+use crypto::invoice_id::InvoiceId;
+use crypto::uid::Uid;
+
+use proto::app_server::messages::{AppRequest, AppToAppServer};
+use proto::funder::messages::{
+    PaymentDirection, PaymentRecord, PaymentStatus, RequestPaymentHistory, ResponsePaymentHistory,
+};
+
+#[derive(Debug)]
+pub struct AppHistoryError;
+
+#[derive(Clone)]
+pub struct AppHistory<R = OffstSystemRandom> {
+    sender: mpsc::Sender<AppToAppServer>,
+    history_mc: MultiConsumerClient<ResponsePaymentHistory>,
+    rng: R,
+}
+
+impl<R> AppHistory<R>
+where
+    R: CryptoRandom,
+{
+    pub(super) fn new(
+        sender: mpsc::Sender<AppToAppServer>,
+        history_mc: MultiConsumerClient<ResponsePaymentHistory>,
+        rng: R,
+    ) -> Self {
+        AppHistory {
+            sender,
+            history_mc,
+            rng,
+        }
+    }
+
+    /// Query the payment history of the node.
+    /// Returns the matching records, from the most recent to the oldest.
+    pub async fn request_payment_history(
+        &mut self,
+        opt_direction: Option<PaymentDirection>,
+        opt_status: Option<PaymentStatus>,
+        opt_invoice_id: Option<InvoiceId>,
+        offset: u64,
+        max_records: u64,
+    ) -> Result<Vec<PaymentRecord>, AppHistoryError> {
+        let request_id = Uid::new(&self.rng);
+        let request_payment_history = RequestPaymentHistory {
+            request_id,
+            opt_direction,
+            opt_status,
+            opt_invoice_id,
+            offset,
+            max_records,
+        };
+
+        let app_request = AppRequest::RequestPaymentHistory(request_payment_history);
+        let to_app_server = AppToAppServer::new(Uid::new(&self.rng), app_request);
+
+        // Start listening for incoming payment history responses:
+        let mut incoming_history =
+            await!(self.history_mc.request_stream()).map_err(|_| AppHistoryError)?;
+
+        // Send our request to offst node:
+        await!(self.sender.send(to_app_server)).map_err(|_| AppHistoryError)?;
+
+        while let Some(response_payment_history) = await!(incoming_history.next()) {
+            if response_payment_history.request_id != request_id {
+                // This is not our request
+                continue;
+            }
+            return Ok(response_payment_history.records);
+        }
+        Err(AppHistoryError)
+    }
+}
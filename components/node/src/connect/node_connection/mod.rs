@@ -1,4 +1,5 @@
 pub mod config;
+pub mod history;
 pub mod report;
 pub mod routes;
 pub mod send_funds;
@@ -11,6 +11,7 @@ use common::mutable_state::BatchMutable;
 use common::state_service::{state_service, StateClient};
 
 use super::config::AppConfig;
+use super::history::AppHistory;
 use super::report::AppReport;
 use super::routes::AppRoutes;
 use super::send_funds::AppSendFunds;
@@ -30,6 +31,7 @@ pub struct NodeConnection<R = OffstSystemRandom> {
     opt_config: Option<AppConfig<R>>,
     opt_routes: Option<AppRoutes<R>>,
     opt_send_funds: Option<AppSendFunds<R>>,
+    history: AppHistory<R>,
     rng: R,
 }
 
@@ -81,6 +83,26 @@ where
             .spawn(send_funds_fut)
             .map_err(|_| NodeConnectionError::SpawnError)?;
 
+        let (mut incoming_held_payments_sender, incoming_held_payments) = mpsc::channel(0);
+        let (requests_sender, incoming_requests) = mpsc::channel(0);
+        let held_payments_mc = MultiConsumerClient::new(requests_sender);
+        let held_payments_fut = multi_consumer_service(incoming_held_payments, incoming_requests)
+            .map_err(|e| error!("HeldPayments multi_consumer_service() error: {:?}", e))
+            .map(|_| ());
+        spawner
+            .spawn(held_payments_fut)
+            .map_err(|_| NodeConnectionError::SpawnError)?;
+
+        let (mut incoming_history_sender, incoming_history) = mpsc::channel(0);
+        let (requests_sender, incoming_requests) = mpsc::channel(0);
+        let history_mc = MultiConsumerClient::new(requests_sender);
+        let history_fut = multi_consumer_service(incoming_history, incoming_requests)
+            .map_err(|e| error!("History multi_consumer_service() error: {:?}", e))
+            .map(|_| ());
+        spawner
+            .spawn(history_fut)
+            .map_err(|_| NodeConnectionError::SpawnError)?;
+
         let (mut incoming_done_app_requests_sender, incoming_done_app_requests) = mpsc::channel(0);
         let (requests_sender, incoming_requests) = mpsc::channel(0);
         let done_app_requests_mc = MultiConsumerClient::new(requests_sender);
@@ -123,6 +145,13 @@ where
                             AppServerToApp::ResponseRoutes(client_response_routes) => {
                                 let _ = await!(incoming_routes_sender.send(client_response_routes));
                             }
+                            AppServerToApp::ResponsePaymentHistory(response_payment_history) => {
+                                let _ =
+                                    await!(incoming_history_sender.send(response_payment_history));
+                            }
+                            AppServerToApp::IncomingPaymentHeld(held_payment) => {
+                                let _ = await!(incoming_held_payments_sender.send(held_payment));
+                            }
                         }
                     }
                 },
@@ -153,6 +182,7 @@ where
             Some(AppSendFunds::new(
                 sender.clone(),
                 send_funds_mc.clone(),
+                held_payments_mc.clone(),
                 done_app_requests_mc.clone(),
                 rng.clone(),
             ))
@@ -165,6 +195,7 @@ where
             opt_config,
             opt_routes,
             opt_send_funds,
+            history: AppHistory::new(sender, history_mc, rng.clone()),
             rng,
         })
     }
@@ -184,4 +215,8 @@ where
     pub fn send_funds(&mut self) -> Option<&mut AppSendFunds<R>> {
         self.opt_send_funds.as_mut()
     }
+
+    pub fn history(&mut self) -> &mut AppHistory<R> {
+        &mut self.history
+    }
 }
@@ -8,8 +8,9 @@ use crypto::identity::PublicKey;
 use crypto::uid::Uid;
 
 use proto::app_server::messages::{AppRequest, AppToAppServer};
+use proto::funder::messages::Currency;
 use proto::index_client::messages::{ClientResponseRoutes, ResponseRoutesResult};
-use proto::index_server::messages::{RequestRoutes, RouteWithCapacity};
+use proto::index_server::messages::{RequestRoutes, RequestRoutesMode, RouteWithCapacity};
 
 #[derive(Debug)]
 pub struct AppRoutesError;
@@ -64,22 +65,64 @@ where
         }
     }
 
+    /// Request routes that can each deliver `capacity` credits to `destination`.
     pub async fn request_routes(
         &mut self,
         capacity: u128,
         source: PublicKey,
         destination: PublicKey,
         opt_exclude: Option<(PublicKey, PublicKey)>,
+        currency: Currency,
+        max_routes: u32,
     ) -> Result<Vec<RouteWithCapacity>, AppRoutesError> {
-        let request_routes_id = Uid::new(&self.rng);
         let request_routes = RequestRoutes {
-            request_id: request_routes_id,
+            request_id: Uid::new(&self.rng),
             capacity,
             source,
             destination,
             opt_exclude,
+            currency,
+            max_routes,
+            mode: RequestRoutesMode::Routes,
+            exclude_nodes: Vec::new(),
+            exclude_edges: Vec::new(),
+            waypoints: Vec::new(),
+            opt_max_route_len: None,
         };
+        await!(self.send_request_routes(request_routes))
+    }
 
+    /// Request routes that together carry the maximum flow of credits possible from `source` to
+    /// `destination`.
+    pub async fn request_max_flow_routes(
+        &mut self,
+        source: PublicKey,
+        destination: PublicKey,
+        currency: Currency,
+    ) -> Result<Vec<RouteWithCapacity>, AppRoutesError> {
+        let request_routes = RequestRoutes {
+            request_id: Uid::new(&self.rng),
+            // Ignored by the index server for max flow requests:
+            capacity: 0,
+            source,
+            destination,
+            opt_exclude: None,
+            currency,
+            max_routes: 0,
+            mode: RequestRoutesMode::MaxFlow,
+            exclude_nodes: Vec::new(),
+            exclude_edges: Vec::new(),
+            waypoints: Vec::new(),
+            opt_max_route_len: None,
+        };
+        await!(self.send_request_routes(request_routes))
+    }
+
+    async fn send_request_routes(
+        &mut self,
+        request_routes: RequestRoutes,
+    ) -> Result<Vec<RouteWithCapacity>, AppRoutesError> {
+        let request_routes_id = request_routes.request_id.clone();
         let app_request = AppRequest::RequestRoutes(request_routes);
         let to_app_server = AppToAppServer::new(Uid::new(&self.rng), app_request);
 
@@ -7,10 +7,11 @@ use crypto::identity::PublicKey;
 use crypto::invoice_id::InvoiceId;
 use crypto::uid::Uid;
 
-use proto::app_server::messages::{AppRequest, AppToAppServer};
+use proto::app_server::messages::{AppRequest, AppToAppServer, RequestRebalance};
+use proto::consts::DEFAULT_REQUEST_TIMEOUT_TICKS;
 use proto::funder::messages::{
-    FriendsRoute, Receipt, ReceiptAck, ResponseReceived, ResponseSendFundsResult,
-    UserRequestSendFunds,
+    Currency, FriendsRoute, HeldPayment, Receipt, ReceiptAck, ResponseReceived,
+    ResponseSendFundsResult, UserRequestSendFunds,
 };
 
 // TODO; Different in naming convention from AppConfigError and AppRoutesError:
@@ -30,10 +31,17 @@ pub enum SendFundsError {
 #[derive(Debug)]
 pub struct ReceiptAckError;
 
+#[derive(Debug)]
+pub struct CancelRequestError;
+
+#[derive(Debug)]
+pub struct HeldPaymentError;
+
 #[derive(Clone)]
 pub struct AppSendFunds<R = OffstSystemRandom> {
     sender: mpsc::Sender<AppToAppServer>,
     send_funds_mc: MultiConsumerClient<ResponseReceived>,
+    held_payments_mc: MultiConsumerClient<HeldPayment>,
     done_app_requests_mc: MultiConsumerClient<Uid>,
     rng: R,
 }
@@ -45,12 +53,14 @@ where
     pub(super) fn new(
         sender: mpsc::Sender<AppToAppServer>,
         send_funds_mc: MultiConsumerClient<ResponseReceived>,
+        held_payments_mc: MultiConsumerClient<HeldPayment>,
         done_app_requests_mc: MultiConsumerClient<Uid>,
         rng: R,
     ) -> Self {
         AppSendFunds {
             sender,
             send_funds_mc,
+            held_payments_mc,
             done_app_requests_mc,
             rng,
         }
@@ -60,14 +70,49 @@ where
         &mut self,
         request_id: Uid,
         route: FriendsRoute,
+        currency: Currency,
         invoice_id: InvoiceId,
         dest_payment: u128,
+        fees: u128,
+    ) -> Result<Receipt, SendFundsError> {
+        await!(self.request_send_funds_part(
+            request_id,
+            route,
+            currency,
+            invoice_id,
+            dest_payment,
+            dest_payment,
+            fees,
+            DEFAULT_REQUEST_TIMEOUT_TICKS
+        ))
+    }
+
+    /// Send one part of a payment that is split over multiple routes.
+    /// All the parts should share the same `invoice_id` and `total_dest_payment`.
+    /// The destination will only return a receipt for any of the parts after the sum of all the
+    /// parts reaches `total_dest_payment`. Parts may be sent concurrently (Using clones of
+    /// `AppSendFunds`).
+    /// If the request is not answered after `timeout_ticks`, it is cancelled.
+    pub async fn request_send_funds_part(
+        &mut self,
+        request_id: Uid,
+        route: FriendsRoute,
+        currency: Currency,
+        invoice_id: InvoiceId,
+        dest_payment: u128,
+        total_dest_payment: u128,
+        fees: u128,
+        timeout_ticks: u64,
     ) -> Result<Receipt, SendFundsError> {
         let user_request_send_funds = UserRequestSendFunds {
             request_id,
             route,
+            currency,
             invoice_id,
             dest_payment,
+            total_dest_payment,
+            fees,
+            timeout_ticks,
         };
         let app_request_id = Uid::new(&self.rng);
         let to_app_server = AppToAppServer::new(
@@ -75,6 +120,47 @@ where
             AppRequest::RequestSendFunds(user_request_send_funds),
         );
 
+        await!(self.wait_response_received(request_id, to_app_server))
+    }
+
+    /// Move `dest_payment` credits of `currency` from our channel with `out_friend_public_key` to our channel
+    /// with `in_friend_public_key`. The node finds a cycle that goes through the two friends, and
+    /// sends a payment to itself along the cycle. At most `max_fees` credits are paid to the
+    /// mediators along the cycle.
+    pub async fn request_rebalance(
+        &mut self,
+        request_id: Uid,
+        out_friend_public_key: PublicKey,
+        in_friend_public_key: PublicKey,
+        currency: Currency,
+        dest_payment: u128,
+        max_fees: u128,
+    ) -> Result<Receipt, SendFundsError> {
+        let request_rebalance = RequestRebalance {
+            request_id,
+            invoice_id: InvoiceId::new(&self.rng),
+            out_friend_public_key,
+            in_friend_public_key,
+            dest_payment,
+            max_fees,
+            timeout_ticks: DEFAULT_REQUEST_TIMEOUT_TICKS,
+            currency,
+        };
+        let app_request_id = Uid::new(&self.rng);
+        let to_app_server = AppToAppServer::new(
+            app_request_id,
+            AppRequest::RequestRebalance(request_rebalance),
+        );
+
+        await!(self.wait_response_received(request_id, to_app_server))
+    }
+
+    /// Send a request to the node, and wait for the response for `request_id`.
+    async fn wait_response_received(
+        &mut self,
+        request_id: Uid,
+        to_app_server: AppToAppServer,
+    ) -> Result<Receipt, SendFundsError> {
         let mut incoming_send_funds =
             await!(self.send_funds_mc.request_stream()).map_err(|_| SendFundsError::LocalError)?;
 
@@ -126,4 +212,82 @@ where
         }
         Err(ReceiptAckError)
     }
+
+    /// Cancel a request to send funds that was not yet sent to the first friend on the route.
+    /// If the request was cancelled, the pending `request_send_funds()` call will return a
+    /// failure. Requests that were already sent can not be cancelled, and will be answered (at
+    /// the latest) when their timeout passes.
+    pub async fn cancel_request_send_funds(
+        &mut self,
+        request_id: Uid,
+    ) -> Result<(), CancelRequestError> {
+        let app_request_id = Uid::new(&self.rng);
+        let to_app_server = AppToAppServer::new(
+            app_request_id,
+            AppRequest::CancelRequestSendFunds(request_id),
+        );
+
+        // Start listening to done requests:
+        let mut incoming_done_requests =
+            await!(self.done_app_requests_mc.request_stream()).map_err(|_| CancelRequestError)?;
+
+        // Send CancelRequestSendFunds:
+        await!(self.sender.send(to_app_server)).map_err(|_| CancelRequestError)?;
+
+        // Wait for a sign that our request was received:
+        while let Some(done_request_id) = await!(incoming_done_requests.next()) {
+            if app_request_id == done_request_id {
+                return Ok(());
+            }
+        }
+        Err(CancelRequestError)
+    }
+
+    /// Listen to incoming payments that are held by the node, waiting to be accepted or rejected.
+    /// The node holds incoming payments only if it was configured to do so (See
+    /// `AppConfig::set_hold_incoming_payments()`).
+    pub async fn incoming_held_payments(
+        &mut self,
+    ) -> Result<mpsc::Receiver<HeldPayment>, HeldPaymentError> {
+        await!(self.held_payments_mc.request_stream()).map_err(|_| HeldPaymentError)
+    }
+
+    /// Accept a held payment. Receipts are issued for the payment.
+    /// Has no effect if the held payment has already timed out.
+    pub async fn accept_held_payment(
+        &mut self,
+        invoice_id: InvoiceId,
+    ) -> Result<(), HeldPaymentError> {
+        await!(self.send_held_payment_request(AppRequest::AcceptHeldPayment(invoice_id)))
+    }
+
+    /// Reject a held payment. The buyer will get a failure for the payment.
+    pub async fn reject_held_payment(
+        &mut self,
+        invoice_id: InvoiceId,
+    ) -> Result<(), HeldPaymentError> {
+        await!(self.send_held_payment_request(AppRequest::RejectHeldPayment(invoice_id)))
+    }
+
+    async fn send_held_payment_request(
+        &mut self,
+        app_request: AppRequest,
+    ) -> Result<(), HeldPaymentError> {
+        let app_request_id = Uid::new(&self.rng);
+        let to_app_server = AppToAppServer::new(app_request_id, app_request);
+
+        // Start listening to done requests:
+        let mut incoming_done_requests =
+            await!(self.done_app_requests_mc.request_stream()).map_err(|_| HeldPaymentError)?;
+
+        await!(self.sender.send(to_app_server)).map_err(|_| HeldPaymentError)?;
+
+        // Wait for a sign that our request was received:
+        while let Some(done_request_id) = await!(incoming_done_requests.next()) {
+            if app_request_id == done_request_id {
+                return Ok(());
+            }
+        }
+        Err(HeldPaymentError)
+    }
 }
@@ -5,7 +5,7 @@ use futures::channel::mpsc;
 use futures::task::{Spawn, SpawnExt};
 use futures::{future, FutureExt, SinkExt, Stream, StreamExt, TryFutureExt};
 
-use common::conn::{BoxFuture, ConnPairVec, FuncFutTransform, FutTransform};
+use common::conn::{BoxFuture, ConnPairVec, FuncFutTransform, FutTransform, Listener};
 use common::transform_pool::transform_pool_loop;
 
 use crypto::crypto_rand::CryptoRandom;
@@ -154,9 +154,12 @@ where
     }
 }
 
-pub async fn net_node<IAC, C, R, GT, AD, DS, TS, S>(
+/// `raw_listener` is used to listen for direct connections from friends, at the direct address
+/// we advertise (See `ChannelAddress`).
+pub async fn net_node<IAC, C, L, R, GT, AD, DS, TS, S>(
     incoming_app_raw_conns: IAC,
     net_connector: C,
+    raw_listener: L,
     timer_client: TimerClient,
     identity_client: IdentityClient,
     rng: R,
@@ -174,6 +177,11 @@ where
         + Send
         + Sync
         + 'static,
+    L: Listener<Connection = ConnPairVec, Config = (), Arg = NetAddress>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     R: CryptoRandom + Clone + 'static,
     GT: Fn() -> Option<HashMap<PublicKey, AppPermissions>> + Clone + Send + 'static,
     AD: AtomicDb<State = NodeState<NetAddress>, Mutation = NodeMutation<NetAddress>>
@@ -234,7 +242,7 @@ where
         KeepAliveChannel::new(timer_client.clone(), KEEPALIVE_TICKS, spawner.clone());
 
     let app_conn_transform = AppConnTransform::new(
-        version_transform,
+        version_transform.clone(),
         encrypt_transform,
         keepalive_transform,
         get_trusted_apps,
@@ -268,6 +276,8 @@ where
         node_state,
         database_client,
         version_connector,
+        version_transform,
+        raw_listener,
         incoming_apps,
         rng,
         spawner.clone()
@@ -4,13 +4,13 @@ use futures::{select, Future, FutureExt, SinkExt, Stream, StreamExt};
 
 use derive_more::*;
 
-use common::conn::{ConnPairVec, FutTransform};
+use common::conn::{ConnPairVec, FutTransform, Listener};
 use crypto::crypto_rand::CryptoRandom;
 use crypto::identity::PublicKey;
 
 use database::DatabaseClient;
 use identity::IdentityClient;
-use timer::TimerClient;
+use timer::{TimerClient, TimerTick};
 
 use app_server::{app_server_loop, AppServerError, IncomingAppConnection};
 use channeler::{spawn_channeler, ChannelerError};
@@ -23,7 +23,7 @@ use secure_channel::SecureChannel;
 
 use index_client::{spawn_index_client, IndexClientError};
 
-use proto::app_server::messages::RelayAddress;
+use proto::app_server::messages::ChannelAddress;
 use proto::funder::messages::{
     ChannelerToFunder, FunderIncomingControl, FunderOutgoingControl, FunderToChanneler,
 };
@@ -38,6 +38,7 @@ use crate::types::{create_node_report, NodeConfig, NodeMutation, NodeState};
 #[derive(Debug, From)]
 pub enum NodeError {
     RequestPublicKeyError,
+    RequestTimerStreamError,
     SpawnError,
     ChannelerError(ChannelerError),
     FunderError(FunderError),
@@ -45,14 +46,16 @@ pub enum NodeError {
     AppServerError(AppServerError),
 }
 
-fn node_spawn_channeler<C, R, S>(
+fn node_spawn_channeler<C, VT, L, R, S>(
     node_config: &NodeConfig,
     local_public_key: PublicKey,
     identity_client: IdentityClient,
     timer_client: TimerClient,
     version_connector: C,
+    version_transform: VT,
+    raw_listener: L,
     rng: R,
-    from_funder: mpsc::Receiver<FunderToChanneler<RelayAddress>>,
+    from_funder: mpsc::Receiver<FunderToChanneler<ChannelAddress>>,
     to_funder: mpsc::Sender<ChannelerToFunder>,
     mut spawner: S,
 ) -> Result<impl Future<Output = Result<(), ChannelerError>>, NodeError>
@@ -62,6 +65,12 @@ where
         + Send
         + Sync
         + 'static,
+    VT: FutTransform<Input = ConnPairVec, Output = ConnPairVec> + Clone + Send + Sync + 'static,
+    L: Listener<Connection = ConnPairVec, Config = (), Arg = NetAddress>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     R: CryptoRandom + Clone + 'static,
     S: Spawn + Clone + Send + Sync + 'static,
 {
@@ -79,7 +88,8 @@ where
         spawner.clone(),
     );
 
-    let enc_relay_connector = EncRelayConnector::new(encrypt_transform.clone(), version_connector);
+    let enc_relay_connector =
+        EncRelayConnector::new(encrypt_transform.clone(), version_connector.clone());
 
     spawner
         .spawn_with_handle(spawn_channeler(
@@ -89,6 +99,9 @@ where
             node_config.conn_timeout_ticks,
             node_config.max_concurrent_encrypt,
             enc_relay_connector,
+            version_connector,
+            raw_listener,
+            version_transform,
             encrypt_transform,
             keepalive_transform,
             from_funder,
@@ -104,9 +117,10 @@ fn node_spawn_funder<R, S>(
     funder_state: FunderState<NetAddress>,
     mut database_client: DatabaseClient<NodeMutation<NetAddress>>,
     mut from_channeler: mpsc::Receiver<ChannelerToFunder>,
-    mut to_channeler: mpsc::Sender<FunderToChanneler<RelayAddress>>,
+    mut to_channeler: mpsc::Sender<FunderToChanneler<ChannelAddress>>,
     from_app_server: mpsc::Receiver<FunderIncomingControl<NetAddress>>,
     to_app_server: mpsc::Sender<FunderOutgoingControl<NetAddress>>,
+    timer_stream: mpsc::Receiver<TimerTick>,
     rng: R,
     mut spawner: S,
 ) -> Result<impl Future<Output = Result<(), FunderError>>, NodeError>
@@ -209,11 +223,13 @@ where
         rng.clone(),
         from_app_server,
         incoming_comm,
+        timer_stream,
         to_app_server,
         outgoing_comm_sender,
         node_config.max_node_relays,
         node_config.max_operations_in_batch,
         node_config.max_pending_user_requests,
+        node_config.opt_max_payment_history,
         funder_state,
         funder_db_client,
     );
@@ -316,13 +332,17 @@ where
     .map_err(|_| NodeError::SpawnError)
 }
 
-pub async fn node<C, IA, R, S>(
+/// Friends may be reached directly, without a relay: Direct connections are opened using
+/// `version_connector`, and received by listening on our direct address using `raw_listener`.
+pub async fn node<C, VT, L, IA, R, S>(
     node_config: NodeConfig,
     identity_client: IdentityClient,
-    timer_client: TimerClient,
+    mut timer_client: TimerClient,
     node_state: NodeState<NetAddress>,
     database_client: DatabaseClient<NodeMutation<NetAddress>>,
     version_connector: C,
+    version_transform: VT,
+    raw_listener: L,
     incoming_apps: IA,
     rng: R,
     mut spawner: S,
@@ -333,6 +353,12 @@ where
         + Send
         + Sync
         + 'static,
+    VT: FutTransform<Input = ConnPairVec, Output = ConnPairVec> + Clone + Send + Sync + 'static,
+    L: Listener<Connection = ConnPairVec, Config = (), Arg = NetAddress>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
     IA: Stream<Item = IncomingAppConnection<NetAddress>> + Unpin + Send + 'static,
     R: CryptoRandom + Clone + 'static,
     S: Spawn + Clone + Send + Sync + 'static,
@@ -355,6 +381,8 @@ where
         identity_client.clone(),
         timer_client.clone(),
         version_connector.clone(),
+        version_transform,
+        raw_listener,
         rng.clone(),
         funder_to_channeler_receiver,
         channeler_to_funder_sender,
@@ -367,6 +395,10 @@ where
     let (funder_to_app_server_sender, funder_to_app_server_receiver) =
         mpsc::channel(node_config.channel_len);
 
+    // Timer ticks are used by the Funder to cancel requests that were not answered in time:
+    let funder_timer_stream = await!(timer_client.request_timer_stream())
+        .map_err(|_| NodeError::RequestTimerStreamError)?;
+
     let funder_handle = node_spawn_funder(
         &node_config,
         identity_client.clone(),
@@ -376,6 +408,7 @@ where
         funder_to_channeler_sender,
         app_server_to_funder_receiver,
         funder_to_app_server_sender,
+        funder_timer_stream,
         rng.clone(),
         spawner.clone(),
     )?;
@@ -393,6 +426,7 @@ where
         app_server_to_index_client_sender,
         incoming_apps,
         initial_node_report.clone(),
+        rng.clone(),
         spawner.clone(),
     );
 
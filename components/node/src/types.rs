@@ -99,6 +99,9 @@ pub struct NodeConfig {
     pub max_operations_in_batch: usize,
     /// The size we allocate for the user send funds requests queue.
     pub max_pending_user_requests: usize,
+    /// Maximum amount of records kept in the payment history. Older records are removed first.
+    /// None means that all the records are kept.
+    pub opt_max_payment_history: Option<usize>,
     /// Maximum amount of concurrent index client requests:
     pub max_open_index_client_requests: usize,
     /// Maximum amount of relays a node may use.
@@ -1,11 +1,13 @@
 use common::canonical_serialize::CanonicalSerialize;
 use common::mutable_state::MutableState;
 use crypto::identity::PublicKey;
+use crypto::invoice_id::InvoiceId;
 use crypto::uid::Uid;
 
 use crate::funder::messages::{
-    AddFriend, ReceiptAck, ResetFriendChannel, ResponseReceived, SetFriendName, SetFriendRelays,
-    SetFriendRemoteMaxDebt, UserRequestSendFunds,
+    AddFriend, Currency, FreezePolicy, HeldPayment, ReceiptAck, RequestPaymentHistory,
+    ResetFriendChannel, ResponsePaymentHistory, ResponseReceived, SetFriendDebtPolicy,
+    SetFriendName, SetFriendRate, SetFriendRelays, SetFriendRemoteMaxDebt, UserRequestSendFunds,
 };
 use crate::index_client::messages::{
     ClientResponseRoutes, IndexClientReport, IndexClientReportMutation,
@@ -49,6 +51,39 @@ where
     }
 }
 
+/// An address a node can be reached at: Either through a relay, or directly.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChannelAddress<B = NetAddress> {
+    Relay(RelayAddress<B>),
+    Direct(B),
+}
+
+impl<B> From<RelayAddress<B>> for ChannelAddress<B> {
+    fn from(from: RelayAddress<B>) -> Self {
+        ChannelAddress::Relay(from)
+    }
+}
+
+impl<B> CanonicalSerialize for ChannelAddress<B>
+where
+    B: CanonicalSerialize,
+{
+    fn canonical_serialize(&self) -> Vec<u8> {
+        let mut res_bytes = Vec::new();
+        match self {
+            ChannelAddress::Relay(relay_address) => {
+                res_bytes.push(0u8);
+                res_bytes.extend_from_slice(&relay_address.canonical_serialize());
+            }
+            ChannelAddress::Direct(address) => {
+                res_bytes.push(1u8);
+                res_bytes.extend_from_slice(&address.canonical_serialize());
+            }
+        }
+        res_bytes
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NodeReport<B = NetAddress>
 where
@@ -87,6 +122,10 @@ where
     Report(NodeReport<B>),
     ReportMutations(ReportMutations<B>),
     ResponseRoutes(ClientResponseRoutes),
+    /// Payment history:
+    ResponsePaymentHistory(ResponsePaymentHistory),
+    /// A payment has arrived, and waits to be accepted or rejected:
+    IncomingPaymentHeld(HeldPayment),
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -95,6 +134,23 @@ pub enum NamedRelaysMutation<B = NetAddress> {
     RemoveRelay(PublicKey),
 }
 
+/// Move credits from one friend to another, by sending a payment to ourselves along a cycle.
+/// The cycle starts with `out_friend_public_key` and ends with `in_friend_public_key`.
+/// Our balance with the out friend decreases, and our balance with the in friend increases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestRebalance {
+    pub request_id: Uid,
+    pub invoice_id: InvoiceId,
+    pub out_friend_public_key: PublicKey,
+    pub in_friend_public_key: PublicKey,
+    pub dest_payment: u128,
+    /// Maximum amount of credits we are willing to pay mediators along the cycle.
+    /// Unused fees return to us together with the payment.
+    pub max_fees: u128,
+    pub timeout_ticks: u64,
+    pub currency: Currency,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum AppRequest<B = NetAddress> {
     /// Manage locally used relays:
@@ -102,6 +158,7 @@ pub enum AppRequest<B = NetAddress> {
     RemoveRelay(PublicKey),
     /// Sending funds:
     RequestSendFunds(UserRequestSendFunds),
+    CancelRequestSendFunds(Uid),
     ReceiptAck(ReceiptAck),
     /// Friend management:
     AddFriend(AddFriend<B>),
@@ -113,12 +170,30 @@ pub enum AppRequest<B = NetAddress> {
     OpenFriend(PublicKey),
     CloseFriend(PublicKey),
     SetFriendRemoteMaxDebt(SetFriendRemoteMaxDebt),
+    SetFriendRate(SetFriendRate),
     ResetFriendChannel(ResetFriendChannel),
     /// Request routes from one node to another:
     RequestRoutes(RequestRoutes),
     /// Manage index servers:
     AddIndexServer(NamedIndexServerAddress<B>),
     RemoveIndexServer(PublicKey),
+    /// Limit the credits friends may freeze:
+    SetFreezePolicy(FreezePolicy),
+    /// Query the payment history:
+    RequestPaymentHistory(RequestPaymentHistory),
+    /// Move credits between two friends using a self payment:
+    RequestRebalance(RequestRebalance),
+    /// Automatically adjust the max debt of a friend:
+    SetFriendDebtPolicy(SetFriendDebtPolicy),
+    ClearFriendDebtPolicy(PublicKey),
+    /// Settle the mutual credit with a friend before removing it:
+    SettleFriend(PublicKey),
+    /// Hold incoming payments until they are accepted or rejected:
+    SetHoldIncomingPayments(bool),
+    AcceptHeldPayment(InvoiceId),
+    RejectHeldPayment(InvoiceId),
+    /// Set the address friends may use to connect to us directly, without a relay:
+    SetDirectAddress(Option<B>),
 }
 #[derive(Debug, PartialEq, Eq)]
 pub struct AppToAppServer<B = NetAddress> {
@@ -1,11 +1,13 @@
 use std::io;
 
 use crate::capnp_common::{
-    read_custom_int128, read_custom_u_int128, read_invoice_id, read_named_index_server_address,
-    read_named_relay_address, read_public_key, read_receipt, read_relay_address, read_signature,
-    read_uid, write_custom_int128, write_custom_u_int128, write_invoice_id,
-    write_named_index_server_address, write_named_relay_address, write_public_key, write_receipt,
-    write_relay_address, write_signature, write_uid,
+    read_channel_address, read_currency, read_currency_balance, read_custom_u_int128,
+    read_debt_policy, read_freeze_policy, read_invoice_id, read_named_index_server_address,
+    read_named_relay_address, read_opt_net_address, read_public_key, read_rate, read_receipt,
+    read_signature, read_uid, write_channel_address, write_currency, write_currency_balance,
+    write_custom_u_int128, write_debt_policy, write_freeze_policy, write_invoice_id,
+    write_named_index_server_address, write_named_relay_address, write_opt_net_address,
+    write_public_key, write_rate, write_receipt, write_signature, write_uid,
 };
 use capnp;
 use capnp::serialize_packed;
@@ -24,8 +26,10 @@ use index_server::serialize::{
 };
 
 use crate::funder::messages::{
-    AddFriend, ReceiptAck, ResetFriendChannel, ResponseReceived, ResponseSendFundsResult,
-    SetFriendName, SetFriendRelays, SetFriendRemoteMaxDebt, UserRequestSendFunds,
+    AddFriend, HeldPayment, PaymentDirection, PaymentRecord, PaymentStatus, ReceiptAck,
+    RequestPaymentHistory, ResetFriendChannel, ResponsePaymentHistory, ResponseReceived,
+    ResponseSendFundsResult, SetFriendDebtPolicy, SetFriendName, SetFriendRate, SetFriendRelays,
+    SetFriendRemoteMaxDebt, UserRequestSendFunds,
 };
 use crate::funder::serialize::{deser_friends_route, ser_friends_route};
 
@@ -56,6 +60,25 @@ fn ser_user_request_send_funds(
         &user_request_send_funds.invoice_id,
         &mut user_request_send_funds_builder.reborrow().init_invoice_id(),
     );
+
+    write_custom_u_int128(
+        user_request_send_funds.fees,
+        &mut user_request_send_funds_builder.reborrow().init_fees(),
+    );
+
+    write_custom_u_int128(
+        user_request_send_funds.total_dest_payment,
+        &mut user_request_send_funds_builder
+            .reborrow()
+            .init_total_dest_payment(),
+    );
+
+    user_request_send_funds_builder.set_timeout_ticks(user_request_send_funds.timeout_ticks);
+
+    write_currency(
+        &user_request_send_funds.currency,
+        &mut user_request_send_funds_builder.reborrow().init_currency(),
+    );
 }
 
 fn deser_user_request_send_funds(
@@ -65,7 +88,13 @@ fn deser_user_request_send_funds(
         request_id: read_uid(&user_request_send_funds_reader.get_request_id()?)?,
         route: deser_friends_route(&user_request_send_funds_reader.get_route()?)?,
         dest_payment: read_custom_u_int128(&user_request_send_funds_reader.get_dest_payment()?)?,
+        total_dest_payment: read_custom_u_int128(
+            &user_request_send_funds_reader.get_total_dest_payment()?,
+        )?,
         invoice_id: read_invoice_id(&user_request_send_funds_reader.get_invoice_id()?)?,
+        fees: read_custom_u_int128(&user_request_send_funds_reader.get_fees()?)?,
+        timeout_ticks: user_request_send_funds_reader.get_timeout_ticks(),
+        currency: read_currency(&user_request_send_funds_reader.get_currency()?)?,
     })
 }
 
@@ -145,16 +174,22 @@ fn ser_add_friend(
 
     let relays_len = usize_to_u32(add_friend.relays.len()).unwrap();
     let mut relays_builder = add_friend_builder.reborrow().init_relays(relays_len);
-    for (index, relay_address) in add_friend.relays.iter().enumerate() {
-        let mut relay_address_builder = relays_builder.reborrow().get(usize_to_u32(index).unwrap());
-        write_relay_address(relay_address, &mut relay_address_builder);
+    for (index, channel_address) in add_friend.relays.iter().enumerate() {
+        let mut channel_address_builder =
+            relays_builder.reborrow().get(usize_to_u32(index).unwrap());
+        write_channel_address(channel_address, &mut channel_address_builder);
     }
 
     add_friend_builder.reborrow().set_name(&add_friend.name);
-    write_custom_int128(
-        add_friend.balance,
-        &mut add_friend_builder.reborrow().init_balance(),
-    );
+
+    let balances_len = usize_to_u32(add_friend.balances.len()).unwrap();
+    let mut balances_builder = add_friend_builder.reborrow().init_balances(balances_len);
+    for (index, currency_balance) in add_friend.balances.iter().enumerate() {
+        let mut currency_balance_builder = balances_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        write_currency_balance(currency_balance, &mut currency_balance_builder);
+    }
 }
 
 fn deser_add_friend(
@@ -162,15 +197,20 @@ fn deser_add_friend(
 ) -> Result<AddFriend, SerializeError> {
     // TODO
     let mut relays = Vec::new();
-    for relay_address in add_friend_reader.get_relays()? {
-        relays.push(read_relay_address(&relay_address)?);
+    for channel_address in add_friend_reader.get_relays()? {
+        relays.push(read_channel_address(&channel_address)?);
+    }
+
+    let mut balances = Vec::new();
+    for currency_balance_reader in add_friend_reader.get_balances()? {
+        balances.push(read_currency_balance(&currency_balance_reader)?);
     }
 
     Ok(AddFriend {
         friend_public_key: read_public_key(&add_friend_reader.get_friend_public_key()?)?,
         relays,
         name: add_friend_reader.get_name()?.to_owned(),
-        balance: read_custom_int128(&add_friend_reader.get_balance()?)?,
+        balances,
     })
 }
 
@@ -208,9 +248,10 @@ fn ser_set_friend_relays(
 
     let relays_len = usize_to_u32(set_friend_relays.relays.len()).unwrap();
     let mut relays_builder = set_friend_relays_builder.reborrow().init_relays(relays_len);
-    for (index, relay_address) in set_friend_relays.relays.iter().enumerate() {
-        let mut relay_address_builder = relays_builder.reborrow().get(usize_to_u32(index).unwrap());
-        write_relay_address(relay_address, &mut relay_address_builder);
+    for (index, channel_address) in set_friend_relays.relays.iter().enumerate() {
+        let mut channel_address_builder =
+            relays_builder.reborrow().get(usize_to_u32(index).unwrap());
+        write_channel_address(channel_address, &mut channel_address_builder);
     }
 }
 
@@ -218,8 +259,8 @@ fn deser_set_friend_relays(
     set_friend_relays_reader: &app_server_capnp::set_friend_relays::Reader,
 ) -> Result<SetFriendRelays, SerializeError> {
     let mut relays = Vec::new();
-    for relay_address in set_friend_relays_reader.get_relays()? {
-        relays.push(read_relay_address(&relay_address)?);
+    for channel_address in set_friend_relays_reader.get_relays()? {
+        relays.push(read_channel_address(&channel_address)?);
     }
 
     Ok(SetFriendRelays {
@@ -245,6 +286,13 @@ fn ser_set_friend_remote_max_debt(
             .reborrow()
             .init_remote_max_debt(),
     );
+
+    write_currency(
+        &set_friend_remote_max_debt.currency,
+        &mut set_friend_remote_max_debt_builder
+            .reborrow()
+            .init_currency(),
+    );
 }
 
 fn deser_set_friend_remote_max_debt(
@@ -257,6 +305,59 @@ fn deser_set_friend_remote_max_debt(
         remote_max_debt: read_custom_u_int128(
             &set_friend_remote_max_debt_reader.get_remote_max_debt()?,
         )?,
+        currency: read_currency(&set_friend_remote_max_debt_reader.get_currency()?)?,
+    })
+}
+
+fn ser_set_friend_rate(
+    set_friend_rate: &SetFriendRate,
+    set_friend_rate_builder: &mut app_server_capnp::set_friend_rate::Builder,
+) {
+    write_public_key(
+        &set_friend_rate.friend_public_key,
+        &mut set_friend_rate_builder.reborrow().init_friend_public_key(),
+    );
+
+    write_rate(
+        &set_friend_rate.rate,
+        &mut set_friend_rate_builder.reborrow().init_rate(),
+    );
+}
+
+fn deser_set_friend_rate(
+    set_friend_rate_reader: &app_server_capnp::set_friend_rate::Reader,
+) -> Result<SetFriendRate, SerializeError> {
+    Ok(SetFriendRate {
+        friend_public_key: read_public_key(&set_friend_rate_reader.get_friend_public_key()?)?,
+        rate: read_rate(&set_friend_rate_reader.get_rate()?)?,
+    })
+}
+
+fn ser_set_friend_debt_policy(
+    set_friend_debt_policy: &SetFriendDebtPolicy,
+    set_friend_debt_policy_builder: &mut app_server_capnp::set_friend_debt_policy::Builder,
+) {
+    write_public_key(
+        &set_friend_debt_policy.friend_public_key,
+        &mut set_friend_debt_policy_builder
+            .reborrow()
+            .init_friend_public_key(),
+    );
+
+    write_debt_policy(
+        &set_friend_debt_policy.debt_policy,
+        &mut set_friend_debt_policy_builder.reborrow().init_debt_policy(),
+    );
+}
+
+fn deser_set_friend_debt_policy(
+    set_friend_debt_policy_reader: &app_server_capnp::set_friend_debt_policy::Reader,
+) -> Result<SetFriendDebtPolicy, SerializeError> {
+    Ok(SetFriendDebtPolicy {
+        friend_public_key: read_public_key(
+            &set_friend_debt_policy_reader.get_friend_public_key()?,
+        )?,
+        debt_policy: read_debt_policy(&set_friend_debt_policy_reader.get_debt_policy()?)?,
     })
 }
 
@@ -346,6 +447,317 @@ fn deser_client_response_routes(
     })
 }
 
+fn ser_payment_direction(
+    payment_direction: PaymentDirection,
+    payment_direction_builder: &mut app_server_capnp::payment_direction::Builder,
+) {
+    match payment_direction {
+        PaymentDirection::Outgoing => payment_direction_builder.set_outgoing(()),
+        PaymentDirection::Incoming => payment_direction_builder.set_incoming(()),
+        PaymentDirection::Mediated => payment_direction_builder.set_mediated(()),
+    }
+}
+
+fn deser_payment_direction(
+    payment_direction_reader: &app_server_capnp::payment_direction::Reader,
+) -> Result<PaymentDirection, SerializeError> {
+    Ok(match payment_direction_reader.which()? {
+        app_server_capnp::payment_direction::Outgoing(()) => PaymentDirection::Outgoing,
+        app_server_capnp::payment_direction::Incoming(()) => PaymentDirection::Incoming,
+        app_server_capnp::payment_direction::Mediated(()) => PaymentDirection::Mediated,
+    })
+}
+
+fn ser_payment_status(
+    payment_status: PaymentStatus,
+    payment_status_builder: &mut app_server_capnp::payment_status::Builder,
+) {
+    match payment_status {
+        PaymentStatus::Success => payment_status_builder.set_success(()),
+        PaymentStatus::Failure => payment_status_builder.set_failure(()),
+    }
+}
+
+fn deser_payment_status(
+    payment_status_reader: &app_server_capnp::payment_status::Reader,
+) -> Result<PaymentStatus, SerializeError> {
+    Ok(match payment_status_reader.which()? {
+        app_server_capnp::payment_status::Success(()) => PaymentStatus::Success,
+        app_server_capnp::payment_status::Failure(()) => PaymentStatus::Failure,
+    })
+}
+
+fn ser_payment_record(
+    payment_record: &PaymentRecord,
+    payment_record_builder: &mut app_server_capnp::payment_record::Builder,
+) {
+    write_uid(
+        &payment_record.request_id,
+        &mut payment_record_builder.reborrow().init_request_id(),
+    );
+    write_invoice_id(
+        &payment_record.invoice_id,
+        &mut payment_record_builder.reborrow().init_invoice_id(),
+    );
+    ser_friends_route(
+        &payment_record.route,
+        &mut payment_record_builder.reborrow().init_route(),
+    );
+    ser_payment_direction(
+        payment_record.direction,
+        &mut payment_record_builder.reborrow().init_direction(),
+    );
+    ser_payment_status(
+        payment_record.status,
+        &mut payment_record_builder.reborrow().init_status(),
+    );
+    write_custom_u_int128(
+        payment_record.dest_payment,
+        &mut payment_record_builder.reborrow().init_dest_payment(),
+    );
+    write_custom_u_int128(
+        payment_record.fees,
+        &mut payment_record_builder.reborrow().init_fees(),
+    );
+    payment_record_builder
+        .reborrow()
+        .set_timestamp(payment_record.timestamp);
+    write_currency(
+        &payment_record.currency,
+        &mut payment_record_builder.reborrow().init_currency(),
+    );
+}
+
+fn deser_payment_record(
+    payment_record_reader: &app_server_capnp::payment_record::Reader,
+) -> Result<PaymentRecord, SerializeError> {
+    Ok(PaymentRecord {
+        request_id: read_uid(&payment_record_reader.get_request_id()?)?,
+        invoice_id: read_invoice_id(&payment_record_reader.get_invoice_id()?)?,
+        route: deser_friends_route(&payment_record_reader.get_route()?)?,
+        direction: deser_payment_direction(&payment_record_reader.get_direction()?)?,
+        status: deser_payment_status(&payment_record_reader.get_status()?)?,
+        currency: read_currency(&payment_record_reader.get_currency()?)?,
+        dest_payment: read_custom_u_int128(&payment_record_reader.get_dest_payment()?)?,
+        fees: read_custom_u_int128(&payment_record_reader.get_fees()?)?,
+        timestamp: payment_record_reader.get_timestamp(),
+    })
+}
+
+fn ser_request_payment_history(
+    request_payment_history: &RequestPaymentHistory,
+    request_payment_history_builder: &mut app_server_capnp::request_payment_history::Builder,
+) {
+    write_uid(
+        &request_payment_history.request_id,
+        &mut request_payment_history_builder.reborrow().init_request_id(),
+    );
+
+    let mut opt_direction_builder = request_payment_history_builder
+        .reborrow()
+        .init_opt_direction();
+    match request_payment_history.opt_direction {
+        Some(direction) => {
+            ser_payment_direction(direction, &mut opt_direction_builder.init_direction())
+        }
+        None => opt_direction_builder.set_empty(()),
+    };
+
+    let mut opt_status_builder = request_payment_history_builder.reborrow().init_opt_status();
+    match request_payment_history.opt_status {
+        Some(status) => ser_payment_status(status, &mut opt_status_builder.init_status()),
+        None => opt_status_builder.set_empty(()),
+    };
+
+    let mut opt_invoice_id_builder = request_payment_history_builder
+        .reborrow()
+        .init_opt_invoice_id();
+    match &request_payment_history.opt_invoice_id {
+        Some(invoice_id) => {
+            write_invoice_id(invoice_id, &mut opt_invoice_id_builder.init_invoice_id())
+        }
+        None => opt_invoice_id_builder.set_empty(()),
+    };
+
+    request_payment_history_builder
+        .reborrow()
+        .set_offset(request_payment_history.offset);
+    request_payment_history_builder
+        .reborrow()
+        .set_max_records(request_payment_history.max_records);
+}
+
+fn deser_request_payment_history(
+    request_payment_history_reader: &app_server_capnp::request_payment_history::Reader,
+) -> Result<RequestPaymentHistory, SerializeError> {
+    let opt_direction = match request_payment_history_reader.get_opt_direction().which()? {
+        app_server_capnp::request_payment_history::opt_direction::Direction(direction_reader) => {
+            Some(deser_payment_direction(&direction_reader?)?)
+        }
+        app_server_capnp::request_payment_history::opt_direction::Empty(()) => None,
+    };
+
+    let opt_status = match request_payment_history_reader.get_opt_status().which()? {
+        app_server_capnp::request_payment_history::opt_status::Status(status_reader) => {
+            Some(deser_payment_status(&status_reader?)?)
+        }
+        app_server_capnp::request_payment_history::opt_status::Empty(()) => None,
+    };
+
+    let opt_invoice_id = match request_payment_history_reader
+        .get_opt_invoice_id()
+        .which()?
+    {
+        app_server_capnp::request_payment_history::opt_invoice_id::InvoiceId(invoice_id_reader) => {
+            Some(read_invoice_id(&invoice_id_reader?)?)
+        }
+        app_server_capnp::request_payment_history::opt_invoice_id::Empty(()) => None,
+    };
+
+    Ok(RequestPaymentHistory {
+        request_id: read_uid(&request_payment_history_reader.get_request_id()?)?,
+        opt_direction,
+        opt_status,
+        opt_invoice_id,
+        offset: request_payment_history_reader.get_offset(),
+        max_records: request_payment_history_reader.get_max_records(),
+    })
+}
+
+fn ser_response_payment_history(
+    response_payment_history: &ResponsePaymentHistory,
+    response_payment_history_builder: &mut app_server_capnp::response_payment_history::Builder,
+) {
+    write_uid(
+        &response_payment_history.request_id,
+        &mut response_payment_history_builder
+            .reborrow()
+            .init_request_id(),
+    );
+
+    let records_len = usize_to_u32(response_payment_history.records.len()).unwrap();
+    let mut records_builder = response_payment_history_builder
+        .reborrow()
+        .init_records(records_len);
+    for (index, payment_record) in response_payment_history.records.iter().enumerate() {
+        let mut payment_record_builder =
+            records_builder.reborrow().get(usize_to_u32(index).unwrap());
+        ser_payment_record(payment_record, &mut payment_record_builder);
+    }
+}
+
+fn deser_response_payment_history(
+    response_payment_history_reader: &app_server_capnp::response_payment_history::Reader,
+) -> Result<ResponsePaymentHistory, SerializeError> {
+    let mut records = Vec::new();
+    for payment_record in response_payment_history_reader.get_records()? {
+        records.push(deser_payment_record(&payment_record)?);
+    }
+
+    Ok(ResponsePaymentHistory {
+        request_id: read_uid(&response_payment_history_reader.get_request_id()?)?,
+        records,
+    })
+}
+
+fn ser_request_rebalance(
+    request_rebalance: &RequestRebalance,
+    request_rebalance_builder: &mut app_server_capnp::request_rebalance::Builder,
+) {
+    write_uid(
+        &request_rebalance.request_id,
+        &mut request_rebalance_builder.reborrow().init_request_id(),
+    );
+
+    write_invoice_id(
+        &request_rebalance.invoice_id,
+        &mut request_rebalance_builder.reborrow().init_invoice_id(),
+    );
+
+    write_public_key(
+        &request_rebalance.out_friend_public_key,
+        &mut request_rebalance_builder
+            .reborrow()
+            .init_out_friend_public_key(),
+    );
+
+    write_public_key(
+        &request_rebalance.in_friend_public_key,
+        &mut request_rebalance_builder
+            .reborrow()
+            .init_in_friend_public_key(),
+    );
+
+    write_custom_u_int128(
+        request_rebalance.dest_payment,
+        &mut request_rebalance_builder.reborrow().init_dest_payment(),
+    );
+
+    write_custom_u_int128(
+        request_rebalance.max_fees,
+        &mut request_rebalance_builder.reborrow().init_max_fees(),
+    );
+
+    request_rebalance_builder.set_timeout_ticks(request_rebalance.timeout_ticks);
+
+    write_currency(
+        &request_rebalance.currency,
+        &mut request_rebalance_builder.reborrow().init_currency(),
+    );
+}
+
+fn deser_request_rebalance(
+    request_rebalance_reader: &app_server_capnp::request_rebalance::Reader,
+) -> Result<RequestRebalance, SerializeError> {
+    Ok(RequestRebalance {
+        request_id: read_uid(&request_rebalance_reader.get_request_id()?)?,
+        invoice_id: read_invoice_id(&request_rebalance_reader.get_invoice_id()?)?,
+        out_friend_public_key: read_public_key(
+            &request_rebalance_reader.get_out_friend_public_key()?,
+        )?,
+        in_friend_public_key: read_public_key(
+            &request_rebalance_reader.get_in_friend_public_key()?,
+        )?,
+        dest_payment: read_custom_u_int128(&request_rebalance_reader.get_dest_payment()?)?,
+        max_fees: read_custom_u_int128(&request_rebalance_reader.get_max_fees()?)?,
+        timeout_ticks: request_rebalance_reader.get_timeout_ticks(),
+        currency: read_currency(&request_rebalance_reader.get_currency()?)?,
+    })
+}
+
+fn ser_held_payment(
+    held_payment: &HeldPayment,
+    held_payment_builder: &mut app_server_capnp::held_payment::Builder,
+) {
+    write_invoice_id(
+        &held_payment.invoice_id,
+        &mut held_payment_builder.reborrow().init_invoice_id(),
+    );
+
+    write_currency(
+        &held_payment.currency,
+        &mut held_payment_builder.reborrow().init_currency(),
+    );
+
+    write_custom_u_int128(
+        held_payment.total_dest_payment,
+        &mut held_payment_builder.reborrow().init_total_dest_payment(),
+    );
+
+    held_payment_builder.set_left_ticks(held_payment.left_ticks);
+}
+
+fn deser_held_payment(
+    held_payment_reader: &app_server_capnp::held_payment::Reader,
+) -> Result<HeldPayment, SerializeError> {
+    Ok(HeldPayment {
+        invoice_id: read_invoice_id(&held_payment_reader.get_invoice_id()?)?,
+        currency: read_currency(&held_payment_reader.get_currency()?)?,
+        total_dest_payment: read_custom_u_int128(&held_payment_reader.get_total_dest_payment()?)?,
+        left_ticks: held_payment_reader.get_left_ticks(),
+    })
+}
+
 /*
 fn ser_add_index_server(add_index_server: &AddIndexServer<NetAddress>,
                             add_index_server_builder: &mut app_server_capnp::add_index_server::Builder) {
@@ -464,6 +876,20 @@ fn ser_app_server_to_app(
             response_routes,
             &mut app_server_to_app_builder.reborrow().init_response_routes(),
         ),
+        AppServerToApp::ResponsePaymentHistory(response_payment_history) => {
+            ser_response_payment_history(
+                response_payment_history,
+                &mut app_server_to_app_builder
+                    .reborrow()
+                    .init_response_payment_history(),
+            )
+        }
+        AppServerToApp::IncomingPaymentHeld(held_payment) => ser_held_payment(
+            held_payment,
+            &mut app_server_to_app_builder
+                .reborrow()
+                .init_incoming_payment_held(),
+        ),
     }
 }
 
@@ -485,6 +911,14 @@ fn deser_app_server_to_app(
                 &client_response_routes_reader?,
             )?)
         }
+        app_server_capnp::app_server_to_app::ResponsePaymentHistory(
+            response_payment_history_reader,
+        ) => AppServerToApp::ResponsePaymentHistory(deser_response_payment_history(
+            &response_payment_history_reader?,
+        )?),
+        app_server_capnp::app_server_to_app::IncomingPaymentHeld(held_payment_reader) => {
+            AppServerToApp::IncomingPaymentHeld(deser_held_payment(&held_payment_reader?)?)
+        }
     })
 }
 
@@ -549,6 +983,16 @@ fn ser_app_request(
                     .init_set_friend_remote_max_debt(),
             )
         }
+        AppRequest::SetFriendRate(set_friend_rate) => ser_set_friend_rate(
+            set_friend_rate,
+            &mut app_request_builder.reborrow().init_set_friend_rate(),
+        ),
+        AppRequest::CancelRequestSendFunds(request_id) => write_uid(
+            request_id,
+            &mut app_request_builder
+                .reborrow()
+                .init_cancel_request_send_funds(),
+        ),
         AppRequest::ResetFriendChannel(reset_friend_channel) => ser_reset_friend_channel(
             reset_friend_channel,
             &mut app_request_builder.reborrow().init_reset_friend_channel(),
@@ -567,6 +1011,49 @@ fn ser_app_request(
             public_key,
             &mut app_request_builder.reborrow().init_remove_index_server(),
         ),
+        AppRequest::SetFreezePolicy(freeze_policy) => write_freeze_policy(
+            freeze_policy,
+            &mut app_request_builder.reborrow().init_set_freeze_policy(),
+        ),
+        AppRequest::RequestPaymentHistory(request_payment_history) => ser_request_payment_history(
+            request_payment_history,
+            &mut app_request_builder
+                .reborrow()
+                .init_request_payment_history(),
+        ),
+        AppRequest::RequestRebalance(request_rebalance) => ser_request_rebalance(
+            request_rebalance,
+            &mut app_request_builder.reborrow().init_request_rebalance(),
+        ),
+        AppRequest::SetFriendDebtPolicy(set_friend_debt_policy) => ser_set_friend_debt_policy(
+            set_friend_debt_policy,
+            &mut app_request_builder.reborrow().init_set_friend_debt_policy(),
+        ),
+        AppRequest::ClearFriendDebtPolicy(friend_public_key) => write_public_key(
+            friend_public_key,
+            &mut app_request_builder
+                .reborrow()
+                .init_clear_friend_debt_policy(),
+        ),
+        AppRequest::SettleFriend(friend_public_key) => write_public_key(
+            friend_public_key,
+            &mut app_request_builder.reborrow().init_settle_friend(),
+        ),
+        AppRequest::SetHoldIncomingPayments(hold_incoming_payments) => app_request_builder
+            .reborrow()
+            .set_set_hold_incoming_payments(*hold_incoming_payments),
+        AppRequest::AcceptHeldPayment(invoice_id) => write_invoice_id(
+            invoice_id,
+            &mut app_request_builder.reborrow().init_accept_held_payment(),
+        ),
+        AppRequest::RejectHeldPayment(invoice_id) => write_invoice_id(
+            invoice_id,
+            &mut app_request_builder.reborrow().init_reject_held_payment(),
+        ),
+        AppRequest::SetDirectAddress(opt_direct_address) => write_opt_net_address(
+            opt_direct_address,
+            &mut app_request_builder.reborrow().init_set_direct_address(),
+        ),
     }
 }
 
@@ -617,6 +1104,12 @@ fn deser_app_request(
         ) => AppRequest::SetFriendRemoteMaxDebt(deser_set_friend_remote_max_debt(
             &set_friend_remote_max_debt_reader?,
         )?),
+        app_server_capnp::app_request::SetFriendRate(set_friend_rate_reader) => {
+            AppRequest::SetFriendRate(deser_set_friend_rate(&set_friend_rate_reader?)?)
+        }
+        app_server_capnp::app_request::CancelRequestSendFunds(request_id_reader) => {
+            AppRequest::CancelRequestSendFunds(read_uid(&request_id_reader?)?)
+        }
         app_server_capnp::app_request::ResetFriendChannel(reset_friend_channel_reader) => {
             AppRequest::ResetFriendChannel(deser_reset_friend_channel(
                 &reset_friend_channel_reader?,
@@ -631,6 +1124,40 @@ fn deser_app_request(
         app_server_capnp::app_request::RemoveIndexServer(public_key_reader) => {
             AppRequest::RemoveIndexServer(read_public_key(&public_key_reader?)?)
         }
+        app_server_capnp::app_request::SetFreezePolicy(freeze_policy_reader) => {
+            AppRequest::SetFreezePolicy(read_freeze_policy(&freeze_policy_reader?)?)
+        }
+        app_server_capnp::app_request::RequestPaymentHistory(request_payment_history_reader) => {
+            AppRequest::RequestPaymentHistory(deser_request_payment_history(
+                &request_payment_history_reader?,
+            )?)
+        }
+        app_server_capnp::app_request::RequestRebalance(request_rebalance_reader) => {
+            AppRequest::RequestRebalance(deser_request_rebalance(&request_rebalance_reader?)?)
+        }
+        app_server_capnp::app_request::SetFriendDebtPolicy(set_friend_debt_policy_reader) => {
+            AppRequest::SetFriendDebtPolicy(deser_set_friend_debt_policy(
+                &set_friend_debt_policy_reader?,
+            )?)
+        }
+        app_server_capnp::app_request::ClearFriendDebtPolicy(public_key_reader) => {
+            AppRequest::ClearFriendDebtPolicy(read_public_key(&public_key_reader?)?)
+        }
+        app_server_capnp::app_request::SettleFriend(public_key_reader) => {
+            AppRequest::SettleFriend(read_public_key(&public_key_reader?)?)
+        }
+        app_server_capnp::app_request::SetHoldIncomingPayments(hold_incoming_payments) => {
+            AppRequest::SetHoldIncomingPayments(hold_incoming_payments)
+        }
+        app_server_capnp::app_request::AcceptHeldPayment(invoice_id_reader) => {
+            AppRequest::AcceptHeldPayment(read_invoice_id(&invoice_id_reader?)?)
+        }
+        app_server_capnp::app_request::RejectHeldPayment(invoice_id_reader) => {
+            AppRequest::RejectHeldPayment(read_invoice_id(&invoice_id_reader?)?)
+        }
+        app_server_capnp::app_request::SetDirectAddress(opt_net_address_reader) => {
+            AppRequest::SetDirectAddress(read_opt_net_address(&opt_net_address_reader?)?)
+        }
     })
 }
 
@@ -723,12 +1250,14 @@ pub fn deserialize_app_to_app_server(data: &[u8]) -> Result<AppToAppServer, Seri
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::app_server::messages::{NodeReportMutation, RelayAddress};
+    use crate::app_server::messages::{ChannelAddress, NodeReportMutation, RelayAddress};
+    use crate::funder::messages::{Currency, CurrencyBalance, DebtPolicy, FriendsRoute};
     use crate::index_client::messages::IndexClientReportMutation;
     use crate::report::messages::FunderReportMutation;
     use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
+    use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
     use crypto::uid::{Uid, UID_LEN};
-    use std::convert::TryInto;
+    use std::convert::{TryFrom, TryInto};
 
     #[test]
     fn test_serialize_app_permissions() {
@@ -772,20 +1301,32 @@ mod tests {
     #[test]
     fn test_serialize_app_to_app_server() {
         let mut relays = Vec::new();
-        relays.push(RelayAddress {
+        relays.push(ChannelAddress::Relay(RelayAddress {
             public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
             address: "MyAddress:1338".to_owned().try_into().unwrap(),
-        });
-        relays.push(RelayAddress {
+        }));
+        relays.push(ChannelAddress::Relay(RelayAddress {
             public_key: PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]),
             address: "MyAddress:1339".to_owned().try_into().unwrap(),
-        });
+        }));
+        relays.push(ChannelAddress::Direct(
+            "MyAddress:1340".to_owned().try_into().unwrap(),
+        ));
 
         let add_friend = AddFriend {
             friend_public_key: PublicKey::from(&[0xee; PUBLIC_KEY_LEN]),
             relays,
             name: "Friend name".to_owned(),
-            balance: -500,
+            balances: vec![
+                CurrencyBalance {
+                    currency: Currency::try_from("USD".to_owned()).unwrap(),
+                    balance: -500,
+                },
+                CurrencyBalance {
+                    currency: Currency::try_from("HOURS".to_owned()).unwrap(),
+                    balance: 3,
+                },
+            ],
         };
         let app_to_app_server = AppToAppServer {
             app_request_id: Uid::from(&[1; UID_LEN]),
@@ -797,5 +1338,166 @@ mod tests {
         assert_eq!(app_to_app_server, app_to_app_server2);
     }
 
+    #[test]
+    fn test_serialize_payment_history() {
+        let request_payment_history = RequestPaymentHistory {
+            request_id: Uid::from(&[2; UID_LEN]),
+            opt_direction: Some(PaymentDirection::Mediated),
+            opt_status: None,
+            opt_invoice_id: Some(InvoiceId::from(&[3; INVOICE_ID_LEN])),
+            offset: 5,
+            max_records: 10,
+        };
+        let app_to_app_server = AppToAppServer {
+            app_request_id: Uid::from(&[1; UID_LEN]),
+            app_request: AppRequest::RequestPaymentHistory(request_payment_history),
+        };
+
+        let data = serialize_app_to_app_server(&app_to_app_server);
+        let app_to_app_server2 = deserialize_app_to_app_server(&data).unwrap();
+        assert_eq!(app_to_app_server, app_to_app_server2);
+
+        let payment_record = PaymentRecord {
+            request_id: Uid::from(&[4; UID_LEN]),
+            invoice_id: InvoiceId::from(&[3; INVOICE_ID_LEN]),
+            route: FriendsRoute {
+                public_keys: vec![
+                    PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+                    PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]),
+                    PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]),
+                ],
+            },
+            direction: PaymentDirection::Mediated,
+            status: PaymentStatus::Success,
+            currency: Currency::try_from("USD".to_owned()).unwrap(),
+            dest_payment: 100,
+            fees: 2,
+            timestamp: 0x1234_5678,
+        };
+        let response_payment_history = ResponsePaymentHistory {
+            request_id: Uid::from(&[2; UID_LEN]),
+            records: vec![payment_record],
+        };
+        let app_server_to_app = AppServerToApp::ResponsePaymentHistory(response_payment_history);
+
+        let data = serialize_app_server_to_app(&app_server_to_app);
+        let app_server_to_app2 = deserialize_app_server_to_app(&data).unwrap();
+        assert_eq!(app_server_to_app, app_server_to_app2);
+    }
+
+    #[test]
+    fn test_serialize_request_rebalance() {
+        let request_rebalance = RequestRebalance {
+            request_id: Uid::from(&[2; UID_LEN]),
+            invoice_id: InvoiceId::from(&[3; INVOICE_ID_LEN]),
+            out_friend_public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+            in_friend_public_key: PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]),
+            dest_payment: 100,
+            max_fees: 5,
+            timeout_ticks: 8,
+            currency: Currency::try_from("USD".to_owned()).unwrap(),
+        };
+        let app_to_app_server = AppToAppServer {
+            app_request_id: Uid::from(&[1; UID_LEN]),
+            app_request: AppRequest::RequestRebalance(request_rebalance),
+        };
+
+        let data = serialize_app_to_app_server(&app_to_app_server);
+        let app_to_app_server2 = deserialize_app_to_app_server(&data).unwrap();
+        assert_eq!(app_to_app_server, app_to_app_server2);
+    }
+
+    #[test]
+    fn test_serialize_set_friend_debt_policy() {
+        let mut debt_policy = DebtPolicy {
+            currency: Currency::try_from("USD".to_owned()).unwrap(),
+            base_max_debt: 100,
+            repaid_percent: 50,
+            max_debt_cap: 1000,
+            opt_soft_threshold: Some(80),
+        };
+
+        for _ in 0..2 {
+            let set_friend_debt_policy = SetFriendDebtPolicy {
+                friend_public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+                debt_policy: debt_policy.clone(),
+            };
+            let app_to_app_server = AppToAppServer {
+                app_request_id: Uid::from(&[1; UID_LEN]),
+                app_request: AppRequest::SetFriendDebtPolicy(set_friend_debt_policy),
+            };
+
+            let data = serialize_app_to_app_server(&app_to_app_server);
+            let app_to_app_server2 = deserialize_app_to_app_server(&data).unwrap();
+            assert_eq!(app_to_app_server, app_to_app_server2);
+
+            debt_policy.opt_soft_threshold = None;
+        }
+    }
+
+    #[test]
+    fn test_serialize_set_friend_remote_max_debt() {
+        let set_friend_remote_max_debt = SetFriendRemoteMaxDebt {
+            friend_public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+            remote_max_debt: 200,
+            currency: Currency::try_from("HOURS".to_owned()).unwrap(),
+        };
+        let app_to_app_server = AppToAppServer {
+            app_request_id: Uid::from(&[1; UID_LEN]),
+            app_request: AppRequest::SetFriendRemoteMaxDebt(set_friend_remote_max_debt),
+        };
+
+        let data = serialize_app_to_app_server(&app_to_app_server);
+        let app_to_app_server2 = deserialize_app_to_app_server(&data).unwrap();
+        assert_eq!(app_to_app_server, app_to_app_server2);
+    }
+
+    #[test]
+    fn test_serialize_held_payment() {
+        let app_to_app_server = AppToAppServer {
+            app_request_id: Uid::from(&[1; UID_LEN]),
+            app_request: AppRequest::AcceptHeldPayment(InvoiceId::from(&[3; INVOICE_ID_LEN])),
+        };
+
+        let data = serialize_app_to_app_server(&app_to_app_server);
+        let app_to_app_server2 = deserialize_app_to_app_server(&data).unwrap();
+        assert_eq!(app_to_app_server, app_to_app_server2);
+
+        let held_payment = HeldPayment {
+            invoice_id: InvoiceId::from(&[3; INVOICE_ID_LEN]),
+            currency: Currency::try_from("USD".to_owned()).unwrap(),
+            total_dest_payment: 100,
+            left_ticks: 8,
+        };
+        let app_server_to_app = AppServerToApp::IncomingPaymentHeld(held_payment);
+
+        let data = serialize_app_server_to_app(&app_server_to_app);
+        let app_server_to_app2 = deserialize_app_server_to_app(&data).unwrap();
+        assert_eq!(app_server_to_app, app_server_to_app2);
+    }
+
+    #[test]
+    fn test_serialize_set_direct_address() {
+        let app_to_app_server = AppToAppServer {
+            app_request_id: Uid::from(&[1; UID_LEN]),
+            app_request: AppRequest::SetDirectAddress(Some(
+                "MyAddress:1341".to_owned().try_into().unwrap(),
+            )),
+        };
+
+        let data = serialize_app_to_app_server(&app_to_app_server);
+        let app_to_app_server2 = deserialize_app_to_app_server(&data).unwrap();
+        assert_eq!(app_to_app_server, app_to_app_server2);
+
+        let app_to_app_server = AppToAppServer {
+            app_request_id: Uid::from(&[2; UID_LEN]),
+            app_request: AppRequest::SetDirectAddress(None),
+        };
+
+        let data = serialize_app_to_app_server(&app_to_app_server);
+        let app_to_app_server2 = deserialize_app_to_app_server(&data).unwrap();
+        assert_eq!(app_to_app_server, app_to_app_server2);
+    }
+
     // TODO: More tests are required here
 }
@@ -3,13 +3,14 @@ use std::convert::{TryFrom, TryInto};
 use std::io;
 
 use common_capnp::{
-    buffer128, buffer256, buffer512, custom_int128, custom_u_int128, dh_public_key, hash,
-    invoice_id, named_index_server_address, named_relay_address, net_address, public_key,
-    rand_nonce, receipt, relay_address, salt, signature, uid,
+    buffer128, buffer256, buffer512, channel_address, currency, currency_balance, custom_int128,
+    custom_u_int128, debt_policy, dh_public_key, freeze_policy, hash, invoice_id,
+    named_index_server_address, named_relay_address, net_address, opt_net_address, public_key,
+    rand_nonce, rate, receipt, relay_address, salt, signature, uid,
 };
 
-use crate::app_server::messages::{NamedRelayAddress, RelayAddress};
-use crate::funder::messages::Receipt;
+use crate::app_server::messages::{ChannelAddress, NamedRelayAddress, RelayAddress};
+use crate::funder::messages::{Currency, CurrencyBalance, DebtPolicy, FreezePolicy, Rate, Receipt};
 use crate::index_server::messages::NamedIndexServerAddress;
 use crate::net::messages::NetAddress;
 use crate::serialize::SerializeError;
@@ -202,6 +203,46 @@ pub fn write_net_address(from: &NetAddress, to: &mut net_address::Builder) {
     to.set_address(from.as_str());
 }
 
+pub fn read_opt_net_address(
+    from: &opt_net_address::Reader,
+) -> Result<Option<NetAddress>, SerializeError> {
+    Ok(match from.which()? {
+        opt_net_address::NetAddress(net_address_reader) => {
+            Some(read_net_address(&net_address_reader?)?)
+        }
+        opt_net_address::Empty(()) => None,
+    })
+}
+
+pub fn write_opt_net_address(from: &Option<NetAddress>, to: &mut opt_net_address::Builder) {
+    match from {
+        Some(net_address) => write_net_address(net_address, &mut to.reborrow().init_net_address()),
+        None => to.set_empty(()),
+    }
+}
+
+pub fn read_currency(from: &currency::Reader) -> Result<Currency, SerializeError> {
+    Ok(from.get_currency()?.to_string().try_into()?)
+}
+
+pub fn write_currency(from: &Currency, to: &mut currency::Builder) {
+    to.set_currency(from.as_str());
+}
+
+pub fn read_currency_balance(
+    from: &currency_balance::Reader,
+) -> Result<CurrencyBalance, SerializeError> {
+    Ok(CurrencyBalance {
+        currency: read_currency(&from.get_currency()?)?,
+        balance: read_custom_int128(&from.get_balance()?)?,
+    })
+}
+
+pub fn write_currency_balance(from: &CurrencyBalance, to: &mut currency_balance::Builder) {
+    write_currency(&from.currency, &mut to.reborrow().init_currency());
+    write_custom_int128(from.balance, &mut to.reborrow().init_balance());
+}
+
 pub fn read_relay_address(
     from: &relay_address::Reader,
 ) -> Result<RelayAddress<NetAddress>, SerializeError> {
@@ -216,6 +257,30 @@ pub fn write_relay_address(from: &RelayAddress<NetAddress>, to: &mut relay_addre
     write_net_address(&from.address, &mut to.reborrow().init_address());
 }
 
+pub fn read_channel_address(
+    from: &channel_address::Reader,
+) -> Result<ChannelAddress<NetAddress>, SerializeError> {
+    Ok(match from.which()? {
+        channel_address::Relay(relay_address_reader) => {
+            ChannelAddress::Relay(read_relay_address(&relay_address_reader?)?)
+        }
+        channel_address::Direct(net_address_reader) => {
+            ChannelAddress::Direct(read_net_address(&net_address_reader?)?)
+        }
+    })
+}
+
+pub fn write_channel_address(from: &ChannelAddress<NetAddress>, to: &mut channel_address::Builder) {
+    match from {
+        ChannelAddress::Relay(relay_address) => {
+            write_relay_address(relay_address, &mut to.reborrow().init_relay())
+        }
+        ChannelAddress::Direct(net_address) => {
+            write_net_address(net_address, &mut to.reborrow().init_direct())
+        }
+    }
+}
+
 pub fn read_named_relay_address(
     from: &named_relay_address::Reader,
 ) -> Result<NamedRelayAddress<NetAddress>, SerializeError> {
@@ -273,7 +338,9 @@ pub fn read_receipt(from: &receipt::Reader) -> Result<Receipt, SerializeError> {
     Ok(Receipt {
         response_hash: read_hash(&from.get_response_hash()?)?,
         invoice_id: read_invoice_id(&from.get_invoice_id()?)?,
+        currency: read_currency(&from.get_currency()?)?,
         dest_payment: read_custom_u_int128(&from.get_dest_payment()?)?,
+        total_dest_payment: read_custom_u_int128(&from.get_total_dest_payment()?)?,
         signature: read_signature(&from.get_signature()?)?,
     })
 }
@@ -281,6 +348,70 @@ pub fn read_receipt(from: &receipt::Reader) -> Result<Receipt, SerializeError> {
 pub fn write_receipt(from: &Receipt, to: &mut receipt::Builder) {
     write_hash(&from.response_hash, &mut to.reborrow().init_response_hash());
     write_invoice_id(&from.invoice_id, &mut to.reborrow().init_invoice_id());
+    write_currency(&from.currency, &mut to.reborrow().init_currency());
     write_custom_u_int128(from.dest_payment, &mut to.reborrow().init_dest_payment());
+    write_custom_u_int128(
+        from.total_dest_payment,
+        &mut to.reborrow().init_total_dest_payment(),
+    );
     write_signature(&from.signature, &mut to.reborrow().init_signature());
 }
+
+pub fn read_rate(from: &rate::Reader) -> Result<Rate, SerializeError> {
+    Ok(Rate {
+        mul: from.get_mul(),
+        add: from.get_add(),
+    })
+}
+
+pub fn write_rate(from: &Rate, to: &mut rate::Builder) {
+    to.set_mul(from.mul);
+    to.set_add(from.add);
+}
+
+pub fn read_freeze_policy(from: &freeze_policy::Reader) -> Result<FreezePolicy, SerializeError> {
+    Ok(match from.which()? {
+        freeze_policy::Unlimited(()) => FreezePolicy::Unlimited,
+        freeze_policy::Proportional(()) => FreezePolicy::Proportional,
+    })
+}
+
+pub fn write_freeze_policy(from: &FreezePolicy, to: &mut freeze_policy::Builder) {
+    match from {
+        FreezePolicy::Unlimited => to.set_unlimited(()),
+        FreezePolicy::Proportional => to.set_proportional(()),
+    }
+}
+
+pub fn read_debt_policy(from: &debt_policy::Reader) -> Result<DebtPolicy, SerializeError> {
+    let opt_soft_threshold = match from.get_opt_soft_threshold().which()? {
+        debt_policy::opt_soft_threshold::SoftThreshold(soft_threshold_reader) => {
+            Some(read_custom_u_int128(&soft_threshold_reader?)?)
+        }
+        debt_policy::opt_soft_threshold::Empty(()) => None,
+    };
+
+    Ok(DebtPolicy {
+        currency: read_currency(&from.get_currency()?)?,
+        base_max_debt: read_custom_u_int128(&from.get_base_max_debt()?)?,
+        repaid_percent: from.get_repaid_percent(),
+        max_debt_cap: read_custom_u_int128(&from.get_max_debt_cap()?)?,
+        opt_soft_threshold,
+    })
+}
+
+pub fn write_debt_policy(from: &DebtPolicy, to: &mut debt_policy::Builder) {
+    write_currency(&from.currency, &mut to.reborrow().init_currency());
+    write_custom_u_int128(from.base_max_debt, &mut to.reborrow().init_base_max_debt());
+    to.set_repaid_percent(from.repaid_percent);
+    write_custom_u_int128(from.max_debt_cap, &mut to.reborrow().init_max_debt_cap());
+
+    let mut opt_soft_threshold_builder = to.reborrow().init_opt_soft_threshold();
+    match from.opt_soft_threshold {
+        Some(soft_threshold) => write_custom_u_int128(
+            soft_threshold,
+            &mut opt_soft_threshold_builder.init_soft_threshold(),
+        ),
+        None => opt_soft_threshold_builder.set_empty(()),
+    }
+}
@@ -28,10 +28,46 @@ pub const MAX_FRAME_LENGTH: usize = 1 << 20; // 1[MB]
 /// index server database.
 pub const INDEX_NODE_TIMEOUT_TICKS: usize = 60 * (1000 / TICK_MS); // 1 minute
 
+/// Index server: The amount of ticks between two snapshots of the graph saved to disk.
+pub const INDEX_SNAPSHOT_TICKS: usize = 5 * 60 * (1000 / TICK_MS); // 5 minutes
+
+/// Index server: Maximum amount of routes returned for a single routes request.
+pub const MAX_ROUTES_PER_REQUEST: u32 = 16;
+
+/// Index server: Maximum amount of node digests sent to a remote server in one message.
+/// Larger digests are split into a few messages, to avoid exceeding frame length.
+pub const MAX_NODE_DIGESTS_PER_MESSAGE: usize = 0x1000;
+
+/// The amount of alternative routes an app asks for when looking for a route to send credits.
+pub const DEFAULT_MAX_ROUTES: u32 = 4;
+
 /// Maximum length for an address string used in NetAddress
 pub const MAX_NET_ADDRESS_LENGTH: usize = 256;
 
+/// Maximum length for a currency name (For example: "USD" or "hours")
+pub const MAX_CURRENCY_LENGTH: usize = 16;
+
 /// Maximum amount of relays a node may use.
 /// We limit this number because sending many relays in a single move token message
 /// might exceed frame length
 pub const MAX_NODE_RELAYS: usize = 16;
+
+/// The constant fee (in credits) a node charges for forwarding a request,
+/// for friends that were not configured with a specific rate.
+pub const DEFAULT_RATE_ADD: u32 = 1;
+
+/// Default amount of ticks a user is willing to wait for a response to a request to send funds.
+pub const DEFAULT_REQUEST_TIMEOUT_TICKS: u64 = 0x100;
+
+/// Maximum amount of ticks a request may be pending at a node before it times out.
+/// A mediator will not keep credits frozen for longer than this amount of ticks.
+pub const MAX_REQUEST_TIMEOUT_TICKS: u64 = 0x1000;
+
+/// Amount of ticks a mediator subtracts from the ticks left for a request before forwarding it.
+/// This way a request usually times out at the next node before it times out at the current node.
+/// A mediator that has already forwarded a request waits this amount of ticks for every hop left
+/// on the route after its own deadline passes, before failing the request.
+pub const REQUEST_TIMEOUT_HOP_TICKS: u64 = 4;
+
+/// Maximum amount of payment records returned for a single payment history query.
+pub const MAX_PAYMENT_HISTORY_RECORDS: u64 = 0x100;
@@ -10,13 +10,13 @@ use net::messages::NetAddressError;
 
 use crate::file::ser_string::{public_key_to_string, string_to_public_key, SerStringError};
 
-use crate::app_server::messages::RelayAddress;
+use crate::app_server::messages::{ChannelAddress, RelayAddress};
 use crate::file::relay::RelayFile;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FriendAddress {
     pub public_key: PublicKey,
-    pub relays: Vec<RelayAddress>,
+    pub relays: Vec<ChannelAddress>,
 }
 
 #[derive(Debug, From)]
@@ -40,6 +40,9 @@ impl From<SerStringError> for FriendFileError {
 #[derive(Serialize, Deserialize)]
 struct FriendFile {
     public_key: String,
+    /// Addresses the friend may be reached at directly, without a relay.
+    #[serde(default)]
+    direct_addresses: Vec<String>,
     relays: Vec<RelayFile>,
 }
 
@@ -52,14 +55,18 @@ pub fn load_friend_from_file(path: &Path) -> Result<FriendAddress, FriendFileErr
     let public_key = string_to_public_key(&friend_file.public_key)?;
 
     let mut relays = Vec::new();
+    for direct_address in friend_file.direct_addresses {
+        relays.push(ChannelAddress::Direct(direct_address.try_into()?));
+    }
+
     for relay_file in friend_file.relays {
         // Decode public key:
         let public_key = string_to_public_key(&relay_file.public_key)?;
 
-        relays.push(RelayAddress {
+        relays.push(ChannelAddress::Relay(RelayAddress {
             public_key,
             address: relay_file.address.try_into()?,
-        });
+        }));
     }
 
     Ok(FriendAddress { public_key, relays })
@@ -75,22 +82,31 @@ pub fn store_friend_to_file(
         ref relays,
     } = friend_address;
 
+    let mut direct_addresses = Vec::new();
     let mut relay_files = Vec::new();
 
-    for relay_address in relays {
-        let RelayAddress {
-            ref public_key,
-            ref address,
-        } = relay_address;
-
-        relay_files.push(RelayFile {
-            public_key: public_key_to_string(&public_key),
-            address: address.as_str().to_string(),
-        });
+    for channel_address in relays {
+        match channel_address {
+            ChannelAddress::Relay(relay_address) => {
+                let RelayAddress {
+                    ref public_key,
+                    ref address,
+                } = relay_address;
+
+                relay_files.push(RelayFile {
+                    public_key: public_key_to_string(&public_key),
+                    address: address.as_str().to_string(),
+                });
+            }
+            ChannelAddress::Direct(address) => {
+                direct_addresses.push(address.as_str().to_string());
+            }
+        }
     }
 
     let friend_file = FriendFile {
         public_key: public_key_to_string(&public_key),
+        direct_addresses,
         relays: relay_files,
     };
 
@@ -130,6 +146,7 @@ mod tests {
             friend_file.public_key,
             "qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqo"
         );
+        assert!(friend_file.direct_addresses.is_empty());
         assert_eq!(friend_file.relays.len(), 2);
 
         assert_eq!(
@@ -145,12 +162,32 @@ mod tests {
         assert_eq!(friend_file.relays[1].address, "127.0.0.1:1338");
     }
 
+    #[test]
+    fn test_friend_file_direct_addresses() {
+        let friend_file: FriendFile = toml::from_str(
+            r#"
+            public_key = 'qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqo'
+            direct_addresses = ['[::1]:1339']
+
+            [[relays]]
+            public_key = 'u7u7u7u7u7u7u7u7u7u7u7u7u7u7u7u7u7u7u7u7u7s'
+            address = '127.0.0.1:1338'
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(friend_file.direct_addresses, vec!["[::1]:1339".to_owned()]);
+        assert_eq!(friend_file.relays.len(), 1);
+    }
+
     #[test]
     fn test_store_load_friend() {
         // Create a temporary directory:
         let dir = tempdir().unwrap();
         let file_path = dir.path().join("friend_address_file");
 
+        let direct_address = "127.0.0.1:1336".to_owned().try_into().unwrap();
+
         let relay_address0 = RelayAddress {
             public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
             address: "127.0.0.1:1337".to_owned().try_into().unwrap(),
@@ -163,7 +200,11 @@ mod tests {
 
         let friend_address = FriendAddress {
             public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
-            relays: vec![relay_address0, relay_address1],
+            relays: vec![
+                ChannelAddress::Direct(direct_address),
+                ChannelAddress::Relay(relay_address0),
+                ChannelAddress::Relay(relay_address1),
+            ],
         };
 
         store_friend_to_file(&friend_address, &file_path).unwrap();
@@ -1,11 +1,14 @@
+use std::collections::HashSet;
 use std::convert::TryInto;
 use std::fs::{self, File};
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::file::ser_string::{public_key_to_string, string_to_public_key, SerStringError};
 use toml;
 
+use crypto::identity::PublicKey;
+
 use crate::app_server::messages::RelayAddress;
 use crate::net::messages::NetAddressError;
 
@@ -70,12 +73,95 @@ pub fn store_relay_to_file(
     Ok(())
 }
 
+/// A helper structure for reading the public key out of a friend file or a node file.
+/// Other fields are ignored.
+#[derive(Deserialize)]
+struct AllowedNodeFile {
+    public_key: String,
+}
+
+/// Load the public key of a node allowed to use a relay, from a friend file or a node file.
+fn load_allowed_node_from_file(path: &Path) -> Result<PublicKey, RelayFileError> {
+    let data = fs::read_to_string(&path)?;
+    let allowed_node_file: AllowedNodeFile = toml::from_str(&data)?;
+
+    Ok(string_to_public_key(&allowed_node_file.public_key)?)
+}
+
+#[derive(Debug)]
+pub enum AllowedNodesDirectoryError {
+    IoError(io::Error),
+    InvalidDirectory(io::Error),
+    InvalidFile(PathBuf, RelayFileError),
+}
+
+impl From<io::Error> for AllowedNodesDirectoryError {
+    fn from(e: io::Error) -> Self {
+        AllowedNodesDirectoryError::IoError(e)
+    }
+}
+
+/// Load a directory of friend or node files, and return the public keys of all the nodes
+/// allowed to use a relay.
+pub fn load_allowed_nodes(
+    dir_path: &Path,
+) -> Result<HashSet<PublicKey>, AllowedNodesDirectoryError> {
+    let mut res_allowed = HashSet::new();
+    for entry in fs::read_dir(dir_path).map_err(AllowedNodesDirectoryError::InvalidDirectory)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_dir() {
+            continue;
+        }
+        res_allowed.insert(
+            load_allowed_node_from_file(&path)
+                .map_err(|e| AllowedNodesDirectoryError::InvalidFile(path, e))?,
+        );
+    }
+    Ok(res_allowed)
+}
+
+#[derive(Debug)]
+pub enum PeerRelaysDirectoryError {
+    IoError(io::Error),
+    InvalidDirectory(io::Error),
+    InvalidFile(PathBuf, RelayFileError),
+}
+
+impl From<io::Error> for PeerRelaysDirectoryError {
+    fn from(e: io::Error) -> Self {
+        PeerRelaysDirectoryError::IoError(e)
+    }
+}
+
+/// Load a directory of relay files, describing the peer relays of a relay.
+pub fn load_peer_relays(dir_path: &Path) -> Result<Vec<RelayAddress>, PeerRelaysDirectoryError> {
+    let mut res_relays = Vec::new();
+    for entry in fs::read_dir(dir_path).map_err(PeerRelaysDirectoryError::InvalidDirectory)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_dir() {
+            continue;
+        }
+        res_relays.push(
+            load_relay_from_file(&path)
+                .map_err(|e| PeerRelaysDirectoryError::InvalidFile(path, e))?,
+        );
+    }
+    Ok(res_relays)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use tempfile::tempdir;
 
-    use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
+    use crypto::identity::{compare_public_key, PUBLIC_KEY_LEN};
+
+    use crate::app_server::messages::ChannelAddress;
+    use crate::file::friend::{store_friend_to_file, FriendAddress};
+    use crate::file::node::store_node_to_file;
+    use crate::node::types::NodeAddress;
 
     #[test]
     fn test_relay_file_basic() {
@@ -107,4 +193,64 @@ mod tests {
 
         assert_eq!(relay_address, relay_address2);
     }
+
+    #[test]
+    fn test_load_allowed_nodes() {
+        // Create a temporary directory:
+        let dir = tempdir().unwrap();
+
+        let file_path = dir.path().join("node_file_a");
+        let node_address = NodeAddress {
+            public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+            address: "127.0.0.1:1000".to_owned().try_into().unwrap(),
+        };
+        store_node_to_file(&node_address, &file_path).unwrap();
+
+        let file_path = dir.path().join("friend_file_b");
+        let friend_address = FriendAddress {
+            public_key: PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]),
+            relays: vec![ChannelAddress::Relay(RelayAddress {
+                public_key: PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]),
+                address: "127.0.0.1:1001".to_owned().try_into().unwrap(),
+            })],
+        };
+        store_friend_to_file(&friend_address, &file_path).unwrap();
+
+        let allowed_nodes = load_allowed_nodes(&dir.path()).unwrap();
+        assert_eq!(
+            allowed_nodes,
+            vec![
+                PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+                PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]),
+            ]
+            .into_iter()
+            .collect::<HashSet<_>>()
+        );
+    }
+
+    #[test]
+    fn test_load_peer_relays() {
+        // Create a temporary directory:
+        let dir = tempdir().unwrap();
+
+        let relay_address_a = RelayAddress {
+            public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+            address: "127.0.0.1:1000".to_owned().try_into().unwrap(),
+        };
+        store_relay_to_file(&relay_address_a, &dir.path().join("relay_a")).unwrap();
+
+        let relay_address_b = RelayAddress {
+            public_key: PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]),
+            address: "127.0.0.1:1001".to_owned().try_into().unwrap(),
+        };
+        store_relay_to_file(&relay_address_b, &dir.path().join("relay_b")).unwrap();
+
+        let mut peer_relays = load_peer_relays(&dir.path()).unwrap();
+        peer_relays.sort_by(|a, b| compare_public_key(&a.public_key, &b.public_key));
+        assert_eq!(peer_relays, vec![relay_address_a, relay_address_b]);
+
+        // A file that is not a relay file:
+        fs::write(dir.path().join("not_a_relay"), "hello").unwrap();
+        assert!(load_peer_relays(&dir.path()).is_err());
+    }
 }
@@ -1,5 +1,6 @@
 use byteorder::{BigEndian, WriteBytesExt};
 use std::collections::HashSet;
+use std::convert::TryFrom;
 
 use crypto::crypto_rand::RandValue;
 use crypto::hash::{self, HashResult};
@@ -7,8 +8,8 @@ use crypto::identity::{PublicKey, Signature};
 use crypto::invoice_id::InvoiceId;
 use crypto::uid::Uid;
 
-use crate::app_server::messages::{NamedRelayAddress, RelayAddress};
-use crate::consts::MAX_ROUTE_LEN;
+use crate::app_server::messages::{ChannelAddress, NamedRelayAddress};
+use crate::consts::{MAX_CURRENCY_LENGTH, MAX_ROUTE_LEN};
 use crate::net::messages::NetAddress;
 use crate::report::messages::FunderReportMutations;
 use common::canonical_serialize::CanonicalSerialize;
@@ -54,17 +55,116 @@ pub const INVOICE_ID_LEN: usize = 32;
 define_fixed_bytes!(InvoiceId, INVOICE_ID_LEN);
 */
 
+/// A unit of credit (For example: "USD" or "hours").
+/// Two friends may keep a separate credit line for every currency they agree on.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Display)]
+#[display(fmt = "{}", _0)]
+pub struct Currency(String);
+
+impl Currency {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug)]
+pub enum CurrencyError {
+    EmptyCurrency,
+    CurrencyTooLong,
+}
+
+impl TryFrom<String> for Currency {
+    type Error = CurrencyError;
+    fn try_from(currency: String) -> Result<Self, Self::Error> {
+        if currency.is_empty() {
+            return Err(CurrencyError::EmptyCurrency);
+        }
+        if currency.len() > MAX_CURRENCY_LENGTH {
+            return Err(CurrencyError::CurrencyTooLong);
+        }
+        Ok(Currency(currency))
+    }
+}
+
+/// Balance of a single currency.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CurrencyBalance {
+    pub currency: Currency,
+    pub balance: i128,
+}
+
+/// Balance of a single currency, as stated by the sender of a move token.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TokenBalance {
+    pub currency: Currency,
+    pub balance: i128,
+    pub local_pending_debt: u128,
+    pub remote_pending_debt: u128,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct FriendsRoute {
     pub public_keys: Vec<PublicKey>,
 }
 
+/// The fee policy a node applies for forwarding requests.
+/// The fee for forwarding a request of `dest_payment` credits is:
+/// `mul * dest_payment / 2^32 + add`
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Rate {
+    /// Proportional part of the fee, in units of 2^-32.
+    pub mul: u32,
+    /// Constant part of the fee.
+    pub add: u32,
+}
+
+/// The policy a node applies to limit the amount of credits a friend may freeze by sending
+/// requests that continue to other friends.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum FreezePolicy {
+    /// No limit, other than the max debt of the token channel.
+    Unlimited,
+    /// Proportional credit freezing allocation: The credits frozen by requests that arrive
+    /// from friend J and continue to friend I are limited to `md(I) * md(J) / (md - md(J))`,
+    /// where `md(X)` is the max debt we allow friend X, and `md` is the sum of max debts we
+    /// allow all our friends.
+    Proportional,
+}
+
+/// Rules for automatically managing the credit we give a friend (The remote max debt).
+/// The remote max debt is `base_max_debt`, plus `repaid_percent` percent of the total debt the
+/// friend has repaid us so far, but never more than `max_debt_cap`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DebtPolicy {
+    /// The currency of the credit line managed by the policy.
+    pub currency: Currency,
+    /// The credit we give a friend that has not repaid anything yet (The trust tier of the
+    /// friend).
+    pub base_max_debt: u128,
+    /// Percent of repaid debt that is added to the remote max debt.
+    pub repaid_percent: u32,
+    /// The remote max debt is never raised above this value.
+    pub max_debt_cap: u128,
+    /// Requests from the friend are closed while its debt is above this threshold.
+    pub opt_soft_threshold: Option<u128>,
+}
+
 #[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct RequestSendFunds {
     pub request_id: Uid,
     pub route: FriendsRoute,
+    /// All the credits along the route are paid in this currency.
+    pub currency: Currency,
     pub dest_payment: u128,
+    /// Total payment for `invoice_id`, possibly split over a few requests sent along different
+    /// routes. Equals `dest_payment` for a payment sent over a single route.
+    pub total_dest_payment: u128,
+    /// Amount of fees left to be paid to the remaining mediators along the route.
+    pub left_fees: u128,
     pub invoice_id: InvoiceId,
+    /// Amount of ticks left until this request times out.
+    /// Every mediator along the route decreases this value before forwarding the request.
+    pub left_ticks: u64,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
@@ -82,28 +182,38 @@ pub struct FailureSendFunds<S = Signature> {
     pub signature: S,
 }
 
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct SetRemoteMaxDebt {
+    pub currency: Currency,
+    pub remote_max_debt: u128,
+}
+
 #[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum FriendTcOp {
     EnableRequests,
     DisableRequests,
-    SetRemoteMaxDebt(u128),
+    /// Set the max debt of the remote side in one currency.
+    /// Opens a credit line in this currency if there is none yet.
+    SetRemoteMaxDebt(SetRemoteMaxDebt),
     RequestSendFunds(RequestSendFunds),
     ResponseSendFunds(ResponseSendFunds),
     FailureSendFunds(FailureSendFunds),
+    /// Ask the remote side to settle the mutual credit before closing the channel.
+    /// No new requests may be sent after this operation.
+    Settle,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct MoveToken<B = NetAddress, S = Signature> {
     pub operations: Vec<FriendTcOp>,
-    pub opt_local_relays: Option<Vec<RelayAddress<B>>>,
+    pub opt_local_relays: Option<Vec<ChannelAddress<B>>>,
     pub old_token: Signature,
     pub local_public_key: PublicKey,
     pub remote_public_key: PublicKey,
     pub inconsistency_counter: u64,
     pub move_token_counter: u128,
-    pub balance: i128,
-    pub local_pending_debt: u128,
-    pub remote_pending_debt: u128,
+    /// Balances of all the currencies of the token channel, sorted by currency.
+    pub balances: Vec<TokenBalance>,
     pub rand_nonce: RandValue,
     pub new_token: S,
 }
@@ -112,7 +222,8 @@ pub struct MoveToken<B = NetAddress, S = Signature> {
 pub struct ResetTerms {
     pub reset_token: Signature,
     pub inconsistency_counter: u64,
-    pub balance_for_reset: i128,
+    /// Sorted by currency.
+    pub balance_for_reset: Vec<CurrencyBalance>,
 }
 
 #[derive(PartialEq, Eq, Clone, Serialize, Debug)]
@@ -136,13 +247,17 @@ pub struct Receipt {
     pub response_hash: HashResult,
     // = sha512/256(requestId || sha512/256(route) || randNonce)
     pub invoice_id: InvoiceId,
+    pub currency: Currency,
     pub dest_payment: u128,
+    pub total_dest_payment: u128,
     pub signature: Signature,
     // Signature{key=recipientKey}(
     //   "FUND_SUCCESS" ||
     //   sha512/256(requestId || sha512/256(route) || randNonce) ||
     //   invoiceId ||
-    //   destPayment
+    //   currency ||
+    //   destPayment ||
+    //   totalDestPayment
     // )
 }
 
@@ -150,8 +265,12 @@ pub struct Receipt {
 pub struct PendingRequest {
     pub request_id: Uid,
     pub route: FriendsRoute,
+    pub currency: Currency,
     pub dest_payment: u128,
+    pub total_dest_payment: u128,
+    pub left_fees: u128,
     pub invoice_id: InvoiceId,
+    pub left_ticks: u64,
 }
 
 // ==================================================================
@@ -162,10 +281,16 @@ impl CanonicalSerialize for RequestSendFunds {
         let mut res_bytes = Vec::new();
         res_bytes.extend_from_slice(&self.request_id);
         res_bytes.extend_from_slice(&self.route.canonical_serialize());
+        res_bytes.extend_from_slice(&self.currency.canonical_serialize());
         res_bytes
             .write_u128::<BigEndian>(self.dest_payment)
             .unwrap();
         res_bytes
+            .write_u128::<BigEndian>(self.total_dest_payment)
+            .unwrap();
+        res_bytes.write_u128::<BigEndian>(self.left_fees).unwrap();
+        res_bytes.write_u64::<BigEndian>(self.left_ticks).unwrap();
+        res_bytes
     }
 }
 
@@ -189,6 +314,17 @@ impl CanonicalSerialize for FailureSendFunds {
     }
 }
 
+impl CanonicalSerialize for SetRemoteMaxDebt {
+    fn canonical_serialize(&self) -> Vec<u8> {
+        let mut res_bytes = Vec::new();
+        res_bytes.extend_from_slice(&self.currency.canonical_serialize());
+        res_bytes
+            .write_u128::<BigEndian>(self.remote_max_debt)
+            .unwrap();
+        res_bytes
+    }
+}
+
 impl CanonicalSerialize for FriendTcOp {
     fn canonical_serialize(&self) -> Vec<u8> {
         let mut res_bytes = Vec::new();
@@ -199,9 +335,9 @@ impl CanonicalSerialize for FriendTcOp {
             FriendTcOp::DisableRequests => {
                 res_bytes.push(1u8);
             }
-            FriendTcOp::SetRemoteMaxDebt(remote_max_debt) => {
+            FriendTcOp::SetRemoteMaxDebt(set_remote_max_debt) => {
                 res_bytes.push(2u8);
-                res_bytes.write_u128::<BigEndian>(*remote_max_debt).unwrap();
+                res_bytes.append(&mut set_remote_max_debt.canonical_serialize())
             }
             FriendTcOp::RequestSendFunds(request_send_funds) => {
                 res_bytes.push(3u8);
@@ -215,11 +351,49 @@ impl CanonicalSerialize for FriendTcOp {
                 res_bytes.push(5u8);
                 res_bytes.append(&mut failure_send_funds.canonical_serialize())
             }
+            FriendTcOp::Settle => {
+                res_bytes.push(6u8);
+            }
         }
         res_bytes
     }
 }
 
+impl CanonicalSerialize for Currency {
+    fn canonical_serialize(&self) -> Vec<u8> {
+        let mut res_bytes = Vec::new();
+        res_bytes
+            .write_u64::<BigEndian>(usize_to_u64(self.0.len()).unwrap())
+            .unwrap();
+        res_bytes.extend_from_slice(self.0.as_bytes());
+        res_bytes
+    }
+}
+
+impl CanonicalSerialize for CurrencyBalance {
+    fn canonical_serialize(&self) -> Vec<u8> {
+        let mut res_bytes = Vec::new();
+        res_bytes.extend_from_slice(&self.currency.canonical_serialize());
+        res_bytes.write_i128::<BigEndian>(self.balance).unwrap();
+        res_bytes
+    }
+}
+
+impl CanonicalSerialize for TokenBalance {
+    fn canonical_serialize(&self) -> Vec<u8> {
+        let mut res_bytes = Vec::new();
+        res_bytes.extend_from_slice(&self.currency.canonical_serialize());
+        res_bytes.write_i128::<BigEndian>(self.balance).unwrap();
+        res_bytes
+            .write_u128::<BigEndian>(self.local_pending_debt)
+            .unwrap();
+        res_bytes
+            .write_u128::<BigEndian>(self.remote_pending_debt)
+            .unwrap();
+        res_bytes
+    }
+}
+
 impl CanonicalSerialize for FriendsRoute {
     fn canonical_serialize(&self) -> Vec<u8> {
         let mut res_bytes = Vec::new();
@@ -301,11 +475,21 @@ impl FriendsRoute {
     }
 }
 
+impl Rate {
+    /// Calculate the fee for forwarding a request of `dest_payment` credits.
+    /// Returns None on overflow.
+    pub fn calc_fee(&self, dest_payment: u128) -> Option<u128> {
+        let mul_res = dest_payment.checked_mul(u128::from(self.mul))? >> 32;
+        mul_res.checked_add(u128::from(self.add))
+    }
+}
+
 impl CanonicalSerialize for Receipt {
     fn canonical_serialize(&self) -> Vec<u8> {
         let mut res_bytes = Vec::new();
         res_bytes.extend_from_slice(&self.response_hash);
         res_bytes.extend_from_slice(&self.invoice_id);
+        res_bytes.extend_from_slice(&self.currency.canonical_serialize());
         res_bytes
             .write_u128::<BigEndian>(self.dest_payment)
             .unwrap();
@@ -339,12 +523,24 @@ impl RequestsStatus {
     }
 }
 
+/// Settlement of the mutual credit with a friend, before closing the channel.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum SettleStatus {
+    /// No settlement was requested.
+    Active,
+    /// Requests are closed. Waiting for pending requests to drain and for a final move token
+    /// from the friend.
+    Settling,
+    /// The last incoming move token from the friend commits to the closing balance.
+    Settled,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AddFriend<B = NetAddress> {
     pub friend_public_key: PublicKey,
-    pub relays: Vec<RelayAddress<B>>,
+    pub relays: Vec<ChannelAddress<B>>,
     pub name: String,
-    pub balance: i128, // Initial balance
+    pub balances: Vec<CurrencyBalance>, // Initial balances
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -367,6 +563,7 @@ pub struct SetFriendStatus {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SetFriendRemoteMaxDebt {
     pub friend_public_key: PublicKey,
+    pub currency: Currency,
     pub remote_max_debt: u128,
 }
 
@@ -379,7 +576,19 @@ pub struct SetFriendName {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SetFriendRelays<B = NetAddress> {
     pub friend_public_key: PublicKey,
-    pub relays: Vec<RelayAddress<B>>,
+    pub relays: Vec<ChannelAddress<B>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetFriendRate {
+    pub friend_public_key: PublicKey,
+    pub rate: Rate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetFriendDebtPolicy {
+    pub friend_public_key: PublicKey,
+    pub debt_policy: DebtPolicy,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -393,8 +602,18 @@ pub struct ResetFriendChannel {
 pub struct UserRequestSendFunds {
     pub request_id: Uid,
     pub route: FriendsRoute,
+    pub currency: Currency,
     pub invoice_id: InvoiceId,
     pub dest_payment: u128,
+    /// Total payment for `invoice_id`. Set it to `dest_payment` to pay over a single route, or
+    /// to the sum of the `dest_payment`-s of a few requests sharing the same `invoice_id` to
+    /// split the payment over a few routes.
+    pub total_dest_payment: u128,
+    /// Total amount of fees we are willing to pay to mediators along the route.
+    pub fees: u128,
+    /// Amount of ticks we are willing to wait for a response.
+    /// If no response arrives in time, the request fails.
+    pub timeout_ticks: u64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -403,10 +622,67 @@ pub struct ReceiptAck {
     pub receipt_signature: Signature,
 }
 
+/// Our part in a payment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaymentDirection {
+    /// We sent the payment
+    Outgoing,
+    /// We were the destination of the payment
+    Incoming,
+    /// We forwarded the payment between two friends
+    Mediated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaymentStatus {
+    Success,
+    Failure,
+}
+
+/// A record of a payment that was completed or failed, kept in the payment history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentRecord {
+    pub request_id: Uid,
+    pub invoice_id: InvoiceId,
+    pub route: FriendsRoute,
+    pub direction: PaymentDirection,
+    pub status: PaymentStatus,
+    pub currency: Currency,
+    pub dest_payment: u128,
+    /// Outgoing: Credits paid on top of `dest_payment` (Fees to the mediators along the route).
+    /// Incoming: Fees left unused by the mediators, paid to us on top of `dest_payment`.
+    /// Mediated: The fee we have earned.
+    /// Always zero for a failed payment.
+    pub fees: u128,
+    /// Time the payment was completed or failed (Seconds since the Unix epoch).
+    pub timestamp: u64,
+}
+
+/// A query over the payment history.
+/// Matching records are returned from the most recent to the oldest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestPaymentHistory {
+    pub request_id: Uid,
+    pub opt_direction: Option<PaymentDirection>,
+    pub opt_status: Option<PaymentStatus>,
+    pub opt_invoice_id: Option<InvoiceId>,
+    /// Amount of matching records to skip
+    pub offset: u64,
+    /// Maximum amount of records to return
+    pub max_records: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponsePaymentHistory {
+    pub request_id: Uid,
+    pub records: Vec<PaymentRecord>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FunderControl<B> {
     AddRelay(NamedRelayAddress<B>),
     RemoveRelay(PublicKey),
+    SetDirectAddress(Option<B>),
     AddFriend(AddFriend<B>),
     RemoveFriend(RemoveFriend),
     SetRequestsStatus(SetRequestsStatus),
@@ -414,9 +690,19 @@ pub enum FunderControl<B> {
     SetFriendRemoteMaxDebt(SetFriendRemoteMaxDebt),
     SetFriendRelays(SetFriendRelays<B>),
     SetFriendName(SetFriendName),
+    SetFriendRate(SetFriendRate),
+    SetFriendDebtPolicy(SetFriendDebtPolicy),
+    ClearFriendDebtPolicy(PublicKey),
+    SettleFriend(PublicKey),
     ResetFriendChannel(ResetFriendChannel),
     RequestSendFunds(UserRequestSendFunds),
+    CancelRequestSendFunds(Uid),
     ReceiptAck(ReceiptAck),
+    SetFreezePolicy(FreezePolicy),
+    RequestPaymentHistory(RequestPaymentHistory),
+    SetHoldIncomingPayments(bool),
+    AcceptHeldPayment(InvoiceId),
+    RejectHeldPayment(InvoiceId),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -439,8 +725,12 @@ impl UserRequestSendFunds {
         RequestSendFunds {
             request_id: self.request_id,
             route: self.route,
+            currency: self.currency,
             invoice_id: self.invoice_id,
             dest_payment: self.dest_payment,
+            total_dest_payment: self.total_dest_payment,
+            left_fees: self.fees,
+            left_ticks: self.timeout_ticks,
         }
     }
 
@@ -448,8 +738,12 @@ impl UserRequestSendFunds {
         PendingRequest {
             request_id: self.request_id,
             route: self.route.clone(),
+            currency: self.currency.clone(),
             dest_payment: self.dest_payment,
+            total_dest_payment: self.total_dest_payment,
+            left_fees: self.fees,
             invoice_id: self.invoice_id.clone(),
+            left_ticks: self.timeout_ticks,
         }
     }
 }
@@ -466,8 +760,22 @@ pub struct ResponseReceived {
     pub result: ResponseSendFundsResult,
 }
 
+/// A payment (where we are the destination) that has fully arrived, and is held until the user
+/// accepts or rejects it. No receipt is issued before the payment is accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeldPayment {
+    pub invoice_id: InvoiceId,
+    pub currency: Currency,
+    pub total_dest_payment: u128,
+    /// Amount of ticks left to accept the payment.
+    /// If the payment is not accepted in time, it is rejected automatically.
+    pub left_ticks: u64,
+}
+
 #[derive(Debug)]
 pub enum FunderOutgoingControl<B: Clone> {
     ResponseReceived(ResponseReceived),
     ReportMutations(FunderReportMutations<B>),
+    ResponsePaymentHistory(ResponsePaymentHistory),
+    IncomingPaymentHeld(HeldPayment),
 }
@@ -1,7 +1,8 @@
 use crate::capnp_common::{
-    read_custom_int128, read_custom_u_int128, read_invoice_id, read_public_key, read_rand_nonce,
-    read_relay_address, read_signature, read_uid, write_custom_int128, write_custom_u_int128,
-    write_invoice_id, write_public_key, write_rand_nonce, write_relay_address, write_signature,
+    read_channel_address, read_currency, read_currency_balance, read_custom_int128,
+    read_custom_u_int128, read_invoice_id, read_public_key, read_rand_nonce, read_signature,
+    read_uid, write_channel_address, write_currency, write_currency_balance, write_custom_int128,
+    write_custom_u_int128, write_invoice_id, write_public_key, write_rand_nonce, write_signature,
     write_uid,
 };
 use capnp;
@@ -13,7 +14,7 @@ use funder_capnp;
 
 use super::messages::{
     FailureSendFunds, FriendMessage, FriendTcOp, FriendsRoute, MoveToken, MoveTokenRequest,
-    RequestSendFunds, ResetTerms, ResponseSendFunds,
+    RequestSendFunds, ResetTerms, ResponseSendFunds, SetRemoteMaxDebt, TokenBalance,
 };
 
 use crate::serialize::SerializeError;
@@ -47,6 +48,11 @@ fn ser_request_send_funds_op(
     let mut route_builder = request_send_funds_op_builder.reborrow().init_route();
     ser_friends_route(&request_send_funds.route, &mut route_builder);
 
+    write_currency(
+        &request_send_funds.currency,
+        &mut request_send_funds_op_builder.reborrow().init_currency(),
+    );
+
     write_custom_u_int128(
         request_send_funds.dest_payment,
         &mut request_send_funds_op_builder.reborrow().init_dest_payment(),
@@ -56,6 +62,36 @@ fn ser_request_send_funds_op(
         &request_send_funds.invoice_id,
         &mut request_send_funds_op_builder.reborrow().init_invoice_id(),
     );
+
+    write_custom_u_int128(
+        request_send_funds.left_fees,
+        &mut request_send_funds_op_builder.reborrow().init_left_fees(),
+    );
+
+    write_custom_u_int128(
+        request_send_funds.total_dest_payment,
+        &mut request_send_funds_op_builder
+            .reborrow()
+            .init_total_dest_payment(),
+    );
+
+    request_send_funds_op_builder.set_left_ticks(request_send_funds.left_ticks);
+}
+
+fn ser_set_remote_max_debt_op(
+    set_remote_max_debt: &SetRemoteMaxDebt,
+    set_remote_max_debt_op_builder: &mut funder_capnp::set_remote_max_debt_op::Builder,
+) {
+    write_currency(
+        &set_remote_max_debt.currency,
+        &mut set_remote_max_debt_op_builder.reborrow().init_currency(),
+    );
+    write_custom_u_int128(
+        set_remote_max_debt.remote_max_debt,
+        &mut set_remote_max_debt_op_builder
+            .reborrow()
+            .init_remote_max_debt(),
+    );
 }
 
 fn ser_response_send_funds_op(
@@ -107,10 +143,10 @@ fn ser_friend_operation(
     match operation {
         FriendTcOp::EnableRequests => operation_builder.set_enable_requests(()),
         FriendTcOp::DisableRequests => operation_builder.set_disable_requests(()),
-        FriendTcOp::SetRemoteMaxDebt(remote_max_debt) => {
+        FriendTcOp::SetRemoteMaxDebt(set_remote_max_debt) => {
             let mut set_remote_max_debt_builder =
                 operation_builder.reborrow().init_set_remote_max_debt();
-            write_custom_u_int128(*remote_max_debt, &mut set_remote_max_debt_builder);
+            ser_set_remote_max_debt_op(set_remote_max_debt, &mut set_remote_max_debt_builder);
         }
         FriendTcOp::RequestSendFunds(request_send_funds) => {
             let mut request_send_funds_builder =
@@ -127,9 +163,32 @@ fn ser_friend_operation(
                 operation_builder.reborrow().init_failure_send_funds();
             ser_failure_send_funds_op(failure_send_funds, &mut failure_send_funds_builder);
         }
+        FriendTcOp::Settle => operation_builder.set_settle(()),
     };
 }
 
+pub fn ser_token_balance(
+    token_balance: &TokenBalance,
+    token_balance_builder: &mut funder_capnp::token_balance::Builder,
+) {
+    write_currency(
+        &token_balance.currency,
+        &mut token_balance_builder.reborrow().init_currency(),
+    );
+    write_custom_int128(
+        token_balance.balance,
+        &mut token_balance_builder.reborrow().init_balance(),
+    );
+    write_custom_u_int128(
+        token_balance.local_pending_debt,
+        &mut token_balance_builder.reborrow().init_local_pending_debt(),
+    );
+    write_custom_u_int128(
+        token_balance.remote_pending_debt,
+        &mut token_balance_builder.reborrow().init_remote_pending_debt(),
+    );
+}
+
 fn ser_move_token(
     move_token: &MoveToken,
     move_token_builder: &mut funder_capnp::move_token::Builder,
@@ -150,10 +209,10 @@ fn ser_move_token(
         Some(local_address) => {
             let local_address_len = usize_to_u32(local_address.len()).unwrap();
             let mut address_builder = opt_local_relays_builder.init_relays(local_address_len);
-            for (index, relay_address) in local_address.iter().enumerate() {
-                let mut relay_address_builder =
+            for (index, channel_address) in local_address.iter().enumerate() {
+                let mut channel_address_builder =
                     address_builder.reborrow().get(usize_to_u32(index).unwrap());
-                write_relay_address(relay_address, &mut relay_address_builder);
+                write_channel_address(channel_address, &mut channel_address_builder);
             }
         }
         None => {
@@ -181,18 +240,16 @@ fn ser_move_token(
         move_token.move_token_counter,
         &mut move_token_builder.reborrow().init_move_token_counter(),
     );
-    write_custom_int128(
-        move_token.balance,
-        &mut move_token_builder.reborrow().init_balance(),
-    );
-    write_custom_u_int128(
-        move_token.local_pending_debt,
-        &mut move_token_builder.reborrow().init_local_pending_debt(),
-    );
-    write_custom_u_int128(
-        move_token.remote_pending_debt,
-        &mut move_token_builder.reborrow().init_remote_pending_debt(),
-    );
+
+    let balances_len = usize_to_u32(move_token.balances.len()).unwrap();
+    let mut balances_builder = move_token_builder.reborrow().init_balances(balances_len);
+    for (index, token_balance) in move_token.balances.iter().enumerate() {
+        let mut token_balance_builder = balances_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        ser_token_balance(token_balance, &mut token_balance_builder);
+    }
+
     write_rand_nonce(
         &move_token.rand_nonce,
         &mut move_token_builder.reborrow().init_rand_nonce(),
@@ -225,10 +282,16 @@ fn ser_inconsistency_error(
 
     inconsistency_error_builder.set_inconsistency_counter(reset_terms.inconsistency_counter);
 
-    let mut balance_for_reset = inconsistency_error_builder
+    let balance_for_reset_len = usize_to_u32(reset_terms.balance_for_reset.len()).unwrap();
+    let mut balance_for_reset_builder = inconsistency_error_builder
         .reborrow()
-        .init_balance_for_reset();
-    write_custom_int128(reset_terms.balance_for_reset, &mut balance_for_reset);
+        .init_balance_for_reset(balance_for_reset_len);
+    for (index, currency_balance) in reset_terms.balance_for_reset.iter().enumerate() {
+        let mut currency_balance_builder = balance_for_reset_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        write_currency_balance(currency_balance, &mut currency_balance_builder);
+    }
 }
 
 fn ser_friend_message(
@@ -281,8 +344,25 @@ fn deser_request_send_funds_op(
     Ok(RequestSendFunds {
         request_id: read_uid(&request_send_funds_op_reader.get_request_id()?)?,
         route: deser_friends_route(&request_send_funds_op_reader.get_route()?)?,
+        currency: read_currency(&request_send_funds_op_reader.get_currency()?)?,
         dest_payment: read_custom_u_int128(&request_send_funds_op_reader.get_dest_payment()?)?,
+        total_dest_payment: read_custom_u_int128(
+            &request_send_funds_op_reader.get_total_dest_payment()?,
+        )?,
         invoice_id: read_invoice_id(&request_send_funds_op_reader.get_invoice_id()?)?,
+        left_fees: read_custom_u_int128(&request_send_funds_op_reader.get_left_fees()?)?,
+        left_ticks: request_send_funds_op_reader.get_left_ticks(),
+    })
+}
+
+fn deser_set_remote_max_debt_op(
+    set_remote_max_debt_op_reader: &funder_capnp::set_remote_max_debt_op::Reader,
+) -> Result<SetRemoteMaxDebt, SerializeError> {
+    Ok(SetRemoteMaxDebt {
+        currency: read_currency(&set_remote_max_debt_op_reader.get_currency()?)?,
+        remote_max_debt: read_custom_u_int128(
+            &set_remote_max_debt_op_reader.get_remote_max_debt()?,
+        )?,
     })
 }
 
@@ -316,7 +396,9 @@ fn deser_friend_operation(
         funder_capnp::friend_operation::EnableRequests(()) => FriendTcOp::EnableRequests,
         funder_capnp::friend_operation::DisableRequests(()) => FriendTcOp::DisableRequests,
         funder_capnp::friend_operation::SetRemoteMaxDebt(set_remote_max_debt_reader) => {
-            FriendTcOp::SetRemoteMaxDebt(read_custom_u_int128(&set_remote_max_debt_reader?)?)
+            FriendTcOp::SetRemoteMaxDebt(deser_set_remote_max_debt_op(
+                &set_remote_max_debt_reader?,
+            )?)
         }
         funder_capnp::friend_operation::RequestSendFunds(request_send_funds_reader) => {
             FriendTcOp::RequestSendFunds(deser_request_send_funds_op(&request_send_funds_reader?)?)
@@ -329,6 +411,20 @@ fn deser_friend_operation(
         funder_capnp::friend_operation::FailureSendFunds(failure_send_funds_reader) => {
             FriendTcOp::FailureSendFunds(deser_failure_send_funds_op(&failure_send_funds_reader?)?)
         }
+        funder_capnp::friend_operation::Settle(()) => FriendTcOp::Settle,
+    })
+}
+
+pub fn deser_token_balance(
+    token_balance_reader: &funder_capnp::token_balance::Reader,
+) -> Result<TokenBalance, SerializeError> {
+    Ok(TokenBalance {
+        currency: read_currency(&token_balance_reader.get_currency()?)?,
+        balance: read_custom_int128(&token_balance_reader.get_balance()?)?,
+        local_pending_debt: read_custom_u_int128(&token_balance_reader.get_local_pending_debt()?)?,
+        remote_pending_debt: read_custom_u_int128(
+            &token_balance_reader.get_remote_pending_debt()?,
+        )?,
     })
 }
 
@@ -343,15 +439,20 @@ fn deser_move_token(
     let opt_local_relays_reader = move_token_reader.get_opt_local_relays();
     let opt_local_relays = match opt_local_relays_reader.which()? {
         funder_capnp::move_token::opt_local_relays::Empty(()) => None,
-        funder_capnp::move_token::opt_local_relays::Relays(relay_address_reader) => {
+        funder_capnp::move_token::opt_local_relays::Relays(channel_address_reader) => {
             let mut addresses = Vec::new();
-            for address in relay_address_reader? {
-                addresses.push(read_relay_address(&address)?);
+            for address in channel_address_reader? {
+                addresses.push(read_channel_address(&address)?);
             }
             Some(addresses)
         }
     };
 
+    let mut balances = Vec::new();
+    for token_balance_reader in move_token_reader.get_balances()? {
+        balances.push(deser_token_balance(&token_balance_reader)?);
+    }
+
     Ok(MoveToken {
         operations,
         opt_local_relays,
@@ -360,9 +461,7 @@ fn deser_move_token(
         remote_public_key: read_public_key(&move_token_reader.get_remote_public_key()?)?,
         inconsistency_counter: move_token_reader.get_inconsistency_counter(),
         move_token_counter: read_custom_u_int128(&move_token_reader.get_move_token_counter()?)?,
-        balance: read_custom_int128(&move_token_reader.get_balance()?)?,
-        local_pending_debt: read_custom_u_int128(&move_token_reader.get_local_pending_debt()?)?,
-        remote_pending_debt: read_custom_u_int128(&move_token_reader.get_remote_pending_debt()?)?,
+        balances,
         rand_nonce: read_rand_nonce(&move_token_reader.get_rand_nonce()?)?,
         new_token: read_signature(&move_token_reader.get_new_token()?)?,
     })
@@ -383,12 +482,15 @@ fn deser_move_token_request(
 fn deser_inconsistency_error(
     inconsistency_error_reader: &funder_capnp::inconsistency_error::Reader,
 ) -> Result<ResetTerms, SerializeError> {
+    let mut balance_for_reset = Vec::new();
+    for currency_balance_reader in inconsistency_error_reader.get_balance_for_reset()? {
+        balance_for_reset.push(read_currency_balance(&currency_balance_reader)?);
+    }
+
     Ok(ResetTerms {
         reset_token: read_signature(&inconsistency_error_reader.get_reset_token()?)?,
         inconsistency_counter: inconsistency_error_reader.get_inconsistency_counter(),
-        balance_for_reset: read_custom_int128(
-            &inconsistency_error_reader.get_balance_for_reset()?,
-        )?,
+        balance_for_reset,
     })
 }
 
@@ -420,7 +522,8 @@ pub fn deserialize_friend_message(data: &[u8]) -> Result<FriendMessage, Serializ
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::app_server::messages::RelayAddress;
+    use crate::app_server::messages::{ChannelAddress, RelayAddress};
+    use crate::funder::messages::{Currency, CurrencyBalance};
     use crypto::crypto_rand::{RandValue, RAND_VALUE_LEN};
     use crypto::identity::{PublicKey, Signature, PUBLIC_KEY_LEN, SIGNATURE_LEN};
     use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
@@ -438,11 +541,17 @@ mod tests {
             ],
         };
 
+        let currency: Currency = "USD".to_owned().try_into().unwrap();
+
         let request_send_funds = RequestSendFunds {
             request_id: Uid::from(&[22; UID_LEN]),
             route,
+            currency: currency.clone(),
             dest_payment: 48,
+            total_dest_payment: 60,
+            left_fees: 3,
             invoice_id: InvoiceId::from(&[0x99; INVOICE_ID_LEN]),
+            left_ticks: 0x100,
         };
         let response_send_funds = ResponseSendFunds {
             request_id: Uid::from(&[10; UID_LEN]),
@@ -460,10 +569,14 @@ mod tests {
         let operations = vec![
             FriendTcOp::EnableRequests,
             FriendTcOp::DisableRequests,
-            FriendTcOp::SetRemoteMaxDebt(101),
+            FriendTcOp::SetRemoteMaxDebt(SetRemoteMaxDebt {
+                currency: currency.clone(),
+                remote_max_debt: 101,
+            }),
             FriendTcOp::RequestSendFunds(request_send_funds),
             FriendTcOp::ResponseSendFunds(response_send_funds),
             FriendTcOp::FailureSendFunds(failure_send_funds),
+            FriendTcOp::Settle,
         ];
 
         let relay_address4 = RelayAddress {
@@ -476,17 +589,34 @@ mod tests {
             address: "MyAddress:1338".to_owned().try_into().unwrap(),
         };
 
+        let direct_address = "MyAddress:1339".to_owned().try_into().unwrap();
+
         let move_token = MoveToken {
             operations,
-            opt_local_relays: Some(vec![relay_address4, relay_address6]),
+            opt_local_relays: Some(vec![
+                ChannelAddress::Direct(direct_address),
+                ChannelAddress::Relay(relay_address4),
+                ChannelAddress::Relay(relay_address6),
+            ]),
             old_token: Signature::from(&[0; SIGNATURE_LEN]),
             local_public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
             remote_public_key: PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]),
             inconsistency_counter: 2,
             move_token_counter: 18,
-            balance: -5,
-            local_pending_debt: 20,
-            remote_pending_debt: 80,
+            balances: vec![
+                TokenBalance {
+                    currency: "HOURS".to_owned().try_into().unwrap(),
+                    balance: 7,
+                    local_pending_debt: 0,
+                    remote_pending_debt: 1,
+                },
+                TokenBalance {
+                    currency,
+                    balance: -5,
+                    local_pending_debt: 20,
+                    remote_pending_debt: 80,
+                },
+            ],
             rand_nonce: RandValue::from(&[0xaa; RAND_VALUE_LEN]),
             new_token: Signature::from(&[1; SIGNATURE_LEN]),
         };
@@ -503,7 +633,10 @@ mod tests {
         let reset_terms = ResetTerms {
             reset_token: Signature::from(&[2; SIGNATURE_LEN]),
             inconsistency_counter: 9,
-            balance_for_reset: 301,
+            balance_for_reset: vec![CurrencyBalance {
+                currency: "USD".to_owned().try_into().unwrap(),
+                balance: 301,
+            }],
         };
         FriendMessage::InconsistencyError(reset_terms)
     }
@@ -28,9 +28,13 @@ pub fn create_response_signature_buffer<S>(
 
     sbuffer.extend_from_slice(&hash::sha_512_256(&inner_blob));
     sbuffer.extend_from_slice(&pending_request.invoice_id);
+    sbuffer.extend_from_slice(&pending_request.currency.canonical_serialize());
     sbuffer
         .write_u128::<BigEndian>(pending_request.dest_payment)
         .unwrap();
+    sbuffer
+        .write_u128::<BigEndian>(pending_request.total_dest_payment)
+        .unwrap();
 
     sbuffer
 }
@@ -50,6 +54,7 @@ pub fn create_failure_signature_buffer<S>(
     sbuffer.extend_from_slice(&pending_request.request_id);
     sbuffer.extend_from_slice(&pending_request.route.hash());
 
+    sbuffer.extend_from_slice(&pending_request.currency.canonical_serialize());
     sbuffer
         .write_u128::<BigEndian>(pending_request.dest_payment)
         .unwrap();
@@ -96,7 +101,9 @@ pub fn prepare_receipt(
     Receipt {
         response_hash,
         invoice_id: pending_request.invoice_id.clone(),
+        currency: pending_request.currency.clone(),
         dest_payment: pending_request.dest_payment,
+        total_dest_payment: pending_request.total_dest_payment,
         signature: response_send_funds.signature.clone(),
     }
 }
@@ -107,7 +114,9 @@ pub fn verify_receipt(receipt: &Receipt, public_key: &PublicKey) -> bool {
     data.extend_from_slice(&hash::sha_512_256(FUND_SUCCESS_PREFIX));
     data.extend(receipt.response_hash.as_ref());
     data.extend(receipt.invoice_id.as_ref());
+    data.extend(receipt.currency.canonical_serialize());
     data.write_u128::<BigEndian>(receipt.dest_payment).unwrap();
+    data.write_u128::<BigEndian>(receipt.total_dest_payment).unwrap();
     verify_signature(&data, public_key, &receipt.signature)
 }
 
@@ -171,15 +180,7 @@ where
     sig_buffer
         .write_u128::<BigEndian>(move_token.move_token_counter)
         .unwrap();
-    sig_buffer
-        .write_i128::<BigEndian>(move_token.balance)
-        .unwrap();
-    sig_buffer
-        .write_u128::<BigEndian>(move_token.local_pending_debt)
-        .unwrap();
-    sig_buffer
-        .write_u128::<BigEndian>(move_token.remote_pending_debt)
-        .unwrap();
+    sig_buffer.extend_from_slice(&move_token.balances.canonical_serialize());
     sig_buffer.extend_from_slice(&move_token.rand_nonce);
 
     sig_buffer
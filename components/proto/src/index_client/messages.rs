@@ -3,12 +3,17 @@ use std::collections::HashMap;
 use crypto::identity::PublicKey;
 use crypto::uid::Uid;
 
-pub use crate::index_server::messages::{IndexMutation, RequestRoutes, UpdateFriend};
+use crate::funder::messages::{Currency, Rate};
+pub use crate::index_server::messages::{
+    IndexMutation, RequestRoutes, RequestRoutesMode, UpdateFriend,
+};
 use crate::index_server::messages::{NamedIndexServerAddress, RouteWithCapacity};
 
 #[derive(Debug, Clone)]
 pub struct IndexClientState {
-    pub friends: HashMap<PublicKey, (u128, u128)>,
+    /// Send and receive capacities of every (friend, currency) pair, together with the rate we
+    /// charge the friend for forwarding its requests.
+    pub friends: HashMap<(PublicKey, Currency), (u128, u128, Rate)>,
 }
 
 // ---------------------------------------------------
@@ -3,27 +3,58 @@ use crypto::hash::HashResult;
 use crypto::identity::{PublicKey, Signature};
 use crypto::uid::Uid;
 
-use crate::funder::messages::FriendsRoute;
+use crate::funder::messages::{Currency, FriendsRoute, Rate};
 use crate::net::messages::NetAddress;
 
+/// The kind of routes requested from the index server.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RequestRoutesMode {
+    /// Routes that can each deliver the requested capacity to the destination.
+    Routes,
+    /// Routes that together carry the maximum flow of credits possible between the source and
+    /// the destination. The requested capacity and `max_routes` are ignored.
+    MaxFlow,
+}
+
 /// IndexClient -> IndexServer
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct RequestRoutes {
     pub request_id: Uid,
-    /// Wanted capacity for the route.
-    /// 0 means we want to optimize for capacity??
+    /// The amount of credits the destination should receive.
+    /// Returned routes are able to carry this amount together with the fees paid to the
+    /// mediators along the route.
     pub capacity: u128,
     pub source: PublicKey,
     pub destination: PublicKey,
     /// This directed edge must not show up in the route.
     /// Useful for finding non trivial directed loops.
     pub opt_exclude: Option<(PublicKey, PublicKey)>,
+    /// Only edges of this currency may show up in the route.
+    pub currency: Currency,
+    /// Maximum amount of routes to return. The returned routes do not share any directed edge,
+    /// and are ranked by their total fees.
+    pub max_routes: u32,
+    pub mode: RequestRoutesMode,
+    /// These nodes must not show up in the route.
+    pub exclude_nodes: Vec<PublicKey>,
+    /// These directed edges must not show up in the route.
+    pub exclude_edges: Vec<(PublicKey, PublicKey)>,
+    /// The route must go through these nodes, in this order.
+    pub waypoints: Vec<PublicKey>,
+    /// Maximum amount of nodes in the route, including the source and the destination.
+    pub opt_max_route_len: Option<u32>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct RouteWithCapacity {
     pub route: FriendsRoute,
+    /// For a `MaxFlow` request, this is the part of the maximum flow that goes through this
+    /// route.
     pub capacity: u128,
+    /// Total fees paid to the mediators of the route, for delivering the requested capacity to
+    /// the destination. For a `MaxFlow` request, these are the fees for delivering the largest
+    /// amount possible through the route, which is `capacity - fees`.
+    pub fees: u128,
 }
 
 /// IndexServer -> IndexClient
@@ -33,6 +64,23 @@ pub struct ResponseRoutes {
     pub routes: Vec<RouteWithCapacity>,
 }
 
+/// The reason an index server rejected a request.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RejectReason {
+    /// The client sent too many requests recently.
+    RateLimited,
+    /// The server is handling too many requests at the moment.
+    ServerBusy,
+}
+
+/// IndexServer -> IndexClient
+/// Sent instead of `ResponseRoutes` when a request is not handled.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RequestRejected {
+    pub request_id: Uid,
+    pub reason: RejectReason,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UpdateFriend {
     /// Friend's public key
@@ -41,6 +89,10 @@ pub struct UpdateFriend {
     pub send_capacity: u128,
     /// To denote local requests closed, assign 0 to recvCapacity
     pub recv_capacity: u128,
+    /// The currency of the capacities
+    pub currency: Currency,
+    /// The fee we charge the friend for forwarding its requests
+    pub rate: Rate,
 }
 
 /// IndexClient -> IndexServer
@@ -94,10 +146,113 @@ pub struct ForwardMutationsUpdate {
     pub time_proof_chain: Vec<TimeProofLink>,
 }
 
+/// The latest `MutationsUpdate` known from a certain node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeDigest {
+    pub node_public_key: PublicKey,
+    pub session_id: Uid,
+    pub counter: u64,
+}
+
+/// A summary of the nodes known to an index server.
+/// Sent to a remote server when a connection is established, allowing the remote server to send
+/// us the current state of the nodes we have missed.
+/// A large digest is split into a few messages. `is_last` is set on the last of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerDigest {
+    pub node_digests: Vec<NodeDigest>,
+    pub is_last: bool,
+}
+
+/// Some of the current edges of a node, sent after a `NodeState` message.
+/// `node_digest` identifies the mutations update of the `NodeState` message.
+///
+/// The edges are given as `UpdateFriend` mutations of a mutations update signed by the node, so
+/// that the receiver can verify that the node has reported them.
+#[derive(Debug, Clone)]
+pub struct NodeEdges {
+    pub node_digest: NodeDigest,
+    /// A mutations update of the node, which has set some of the node's current edges.
+    pub mutations_update: MutationsUpdate,
+    /// Indices of the `UpdateFriend` mutations in `mutations_update` that are still current
+    /// edges of the node.
+    pub edge_indices: Vec<u32>,
+}
+
+/// IndexAdmin -> IndexServer
+/// Request all the edges reported by a node, in a certain currency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestNeighbors {
+    pub currency: Currency,
+    pub node_public_key: PublicKey,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CurrencyGraphStats {
+    pub currency: Currency,
+    /// Amount of nodes that reported edges of this currency
+    pub num_nodes: u64,
+    pub num_edges: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifierStats {
+    /// Amount of servers whose time hashes are included in our time hashes
+    pub num_neighbors: u64,
+    /// Amount of our recent time hashes that can still prove freshness
+    pub num_ticks: u64,
+    /// Amount of nodes with a known mutations session
+    pub num_nodes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutesRequestsStats {
+    /// Amount of routes requests handled since the server started
+    pub num_handled: u64,
+    /// Amount of routes requests rejected since the server started
+    pub num_rejected: u64,
+    /// Amount of routes requests being handled at the moment
+    pub num_open: u64,
+    /// Average time it took to handle the recent routes requests
+    pub avg_latency_micros: u64,
+    /// Maximum time it took to handle the recent routes requests
+    pub max_latency_micros: u64,
+}
+
+/// IndexServer -> IndexAdmin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexServerStats {
+    pub graphs: Vec<CurrencyGraphStats>,
+    pub num_clients: u64,
+    pub num_trusted_servers: u64,
+    pub connected_servers: Vec<PublicKey>,
+    pub verifier: VerifierStats,
+    pub routes_requests: RoutesRequestsStats,
+}
+
+/// An edge reported by a node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NeighborEdge {
+    pub public_key: PublicKey,
+    pub send_capacity: u128,
+    pub recv_capacity: u128,
+    /// The fee the node charges the neighbor for forwarding its requests
+    pub rate: Rate,
+}
+
+/// IndexServer -> IndexAdmin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseNeighbors {
+    pub currency: Currency,
+    pub node_public_key: PublicKey,
+    pub neighbors: Vec<NeighborEdge>,
+}
+
 #[derive(Debug)]
 pub enum IndexServerToClient {
     TimeHash(HashResult),
     ResponseRoutes(ResponseRoutes),
+    RequestRejected(RequestRejected),
 }
 
 #[derive(Debug)]
@@ -110,6 +265,24 @@ pub enum IndexClientToServer {
 pub enum IndexServerToServer {
     TimeHash(HashResult),
     ForwardMutationsUpdate(ForwardMutationsUpdate),
+    ServerDigest(ServerDigest),
+    /// The latest mutations update of a node, proving that the node's state is fresh.
+    /// The receiver drops all the edges it knows from the node, and waits for the node's current
+    /// edges in the following `NodeEdges` messages.
+    NodeState(ForwardMutationsUpdate),
+    NodeEdges(NodeEdges),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexAdminToServer {
+    RequestStats,
+    RequestNeighbors(RequestNeighbors),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexServerToAdmin {
+    Stats(IndexServerStats),
+    ResponseNeighbors(ResponseNeighbors),
 }
 
 // ----------------------------------------------
@@ -2,23 +2,45 @@ use capnp::serialize_packed;
 use std::io;
 
 use crate::capnp_common::{
-    read_custom_u_int128, read_hash, read_public_key, read_rand_nonce, read_signature, read_uid,
-    write_custom_u_int128, write_hash, write_public_key, write_rand_nonce, write_signature,
-    write_uid,
+    read_currency, read_custom_u_int128, read_hash, read_public_key, read_rand_nonce, read_rate,
+    read_signature, read_uid, write_currency, write_custom_u_int128, write_hash, write_public_key,
+    write_rand_nonce, write_rate, write_signature, write_uid,
 };
 use common::int_convert::usize_to_u32;
 use index_capnp;
 
 use super::messages::{
-    ForwardMutationsUpdate, IndexClientToServer, IndexMutation, IndexServerToClient,
-    IndexServerToServer, MutationsUpdate, RequestRoutes, ResponseRoutes, RouteWithCapacity,
-    TimeProofLink, UpdateFriend,
+    CurrencyGraphStats, ForwardMutationsUpdate, IndexAdminToServer, IndexClientToServer,
+    IndexMutation, IndexServerStats, IndexServerToAdmin, IndexServerToClient, IndexServerToServer,
+    MutationsUpdate, NeighborEdge, NodeDigest, NodeEdges, RejectReason, RequestNeighbors,
+    RequestRejected, RequestRoutes, RequestRoutesMode, ResponseNeighbors, ResponseRoutes,
+    RouteWithCapacity, RoutesRequestsStats, ServerDigest, TimeProofLink, UpdateFriend,
+    VerifierStats,
 };
 
 use crate::funder::serialize::{deser_friends_route, ser_friends_route};
 
 use crate::serialize::SerializeError;
 
+fn ser_request_routes_mode(
+    mode: &RequestRoutesMode,
+    mode_builder: &mut index_capnp::request_routes_mode::Builder,
+) {
+    match mode {
+        RequestRoutesMode::Routes => mode_builder.set_routes(()),
+        RequestRoutesMode::MaxFlow => mode_builder.set_max_flow(()),
+    }
+}
+
+fn deser_request_routes_mode(
+    mode_reader: &index_capnp::request_routes_mode::Reader,
+) -> Result<RequestRoutesMode, SerializeError> {
+    Ok(match mode_reader.which()? {
+        index_capnp::request_routes_mode::Routes(()) => RequestRoutesMode::Routes,
+        index_capnp::request_routes_mode::MaxFlow(()) => RequestRoutesMode::MaxFlow,
+    })
+}
+
 pub fn ser_request_routes(
     request_routes: &RequestRoutes,
     request_routes_builder: &mut index_capnp::request_routes::Builder,
@@ -57,6 +79,63 @@ pub fn ser_request_routes(
             opt_exclude_builder.set_empty(());
         }
     }
+
+    write_currency(
+        &request_routes.currency,
+        &mut request_routes_builder.reborrow().init_currency(),
+    );
+    request_routes_builder.set_max_routes(request_routes.max_routes);
+    ser_request_routes_mode(
+        &request_routes.mode,
+        &mut request_routes_builder.reborrow().init_mode(),
+    );
+
+    let exclude_nodes_len = usize_to_u32(request_routes.exclude_nodes.len()).unwrap();
+    let mut exclude_nodes_builder = request_routes_builder
+        .reborrow()
+        .init_exclude_nodes(exclude_nodes_len);
+    for (index, public_key) in request_routes.exclude_nodes.iter().enumerate() {
+        let mut public_key_builder = exclude_nodes_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        write_public_key(public_key, &mut public_key_builder);
+    }
+
+    let exclude_edges_len = usize_to_u32(request_routes.exclude_edges.len()).unwrap();
+    let mut exclude_edges_builder = request_routes_builder
+        .reborrow()
+        .init_exclude_edges(exclude_edges_len);
+    for (index, (from_public_key, to_public_key)) in request_routes.exclude_edges.iter().enumerate()
+    {
+        let mut edge_builder = exclude_edges_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        write_public_key(
+            from_public_key,
+            &mut edge_builder.reborrow().init_from_public_key(),
+        );
+        write_public_key(
+            to_public_key,
+            &mut edge_builder.reborrow().init_to_public_key(),
+        );
+    }
+
+    let waypoints_len = usize_to_u32(request_routes.waypoints.len()).unwrap();
+    let mut waypoints_builder = request_routes_builder
+        .reborrow()
+        .init_waypoints(waypoints_len);
+    for (index, public_key) in request_routes.waypoints.iter().enumerate() {
+        let mut public_key_builder = waypoints_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        write_public_key(public_key, &mut public_key_builder);
+    }
+
+    let mut opt_max_route_len_builder = request_routes_builder.reborrow().init_opt_max_route_len();
+    match request_routes.opt_max_route_len {
+        Some(max_route_len) => opt_max_route_len_builder.set_max_route_len(max_route_len),
+        None => opt_max_route_len_builder.set_empty(()),
+    }
 }
 
 pub fn deser_request_routes(
@@ -72,12 +151,43 @@ pub fn deser_request_routes(
         index_capnp::request_routes::opt_exclude::Empty(()) => None,
     };
 
+    let mut exclude_nodes = Vec::new();
+    for public_key_reader in request_routes_reader.get_exclude_nodes()? {
+        exclude_nodes.push(read_public_key(&public_key_reader)?);
+    }
+
+    let mut exclude_edges = Vec::new();
+    for edge_reader in request_routes_reader.get_exclude_edges()? {
+        let from_public_key = read_public_key(&edge_reader.get_from_public_key()?)?;
+        let to_public_key = read_public_key(&edge_reader.get_to_public_key()?)?;
+        exclude_edges.push((from_public_key, to_public_key));
+    }
+
+    let mut waypoints = Vec::new();
+    for public_key_reader in request_routes_reader.get_waypoints()? {
+        waypoints.push(read_public_key(&public_key_reader)?);
+    }
+
+    let opt_max_route_len = match request_routes_reader.get_opt_max_route_len().which()? {
+        index_capnp::request_routes::opt_max_route_len::MaxRouteLen(max_route_len) => {
+            Some(max_route_len)
+        }
+        index_capnp::request_routes::opt_max_route_len::Empty(()) => None,
+    };
+
     Ok(RequestRoutes {
         request_id: read_uid(&request_routes_reader.get_request_id()?)?,
         capacity: read_custom_u_int128(&request_routes_reader.get_capacity()?)?,
         source: read_public_key(&request_routes_reader.get_source()?)?,
         destination: read_public_key(&request_routes_reader.get_destination()?)?,
         opt_exclude,
+        currency: read_currency(&request_routes_reader.get_currency()?)?,
+        max_routes: request_routes_reader.get_max_routes(),
+        mode: deser_request_routes_mode(&request_routes_reader.get_mode()?)?,
+        exclude_nodes,
+        exclude_edges,
+        waypoints,
+        opt_max_route_len,
     })
 }
 
@@ -93,6 +203,10 @@ pub fn ser_route_with_capacity(
         route_with_capacity.capacity,
         &mut route_with_capacity_builder.reborrow().init_capacity(),
     );
+    write_custom_u_int128(
+        route_with_capacity.fees,
+        &mut route_with_capacity_builder.reborrow().init_fees(),
+    );
 }
 
 pub fn deser_route_with_capacity(
@@ -101,6 +215,7 @@ pub fn deser_route_with_capacity(
     Ok(RouteWithCapacity {
         route: deser_friends_route(&route_with_capacity_reader.get_route()?)?,
         capacity: read_custom_u_int128(&route_with_capacity_reader.get_capacity()?)?,
+        fees: read_custom_u_int128(&route_with_capacity_reader.get_fees()?)?,
     })
 }
 
@@ -136,6 +251,48 @@ fn deser_response_routes(
     })
 }
 
+fn ser_reject_reason(
+    reject_reason: &RejectReason,
+    reject_reason_builder: &mut index_capnp::reject_reason::Builder,
+) {
+    match reject_reason {
+        RejectReason::RateLimited => reject_reason_builder.set_rate_limited(()),
+        RejectReason::ServerBusy => reject_reason_builder.set_server_busy(()),
+    }
+}
+
+fn deser_reject_reason(
+    reject_reason_reader: &index_capnp::reject_reason::Reader,
+) -> Result<RejectReason, SerializeError> {
+    Ok(match reject_reason_reader.which()? {
+        index_capnp::reject_reason::RateLimited(()) => RejectReason::RateLimited,
+        index_capnp::reject_reason::ServerBusy(()) => RejectReason::ServerBusy,
+    })
+}
+
+fn ser_request_rejected(
+    request_rejected: &RequestRejected,
+    request_rejected_builder: &mut index_capnp::request_rejected::Builder,
+) {
+    write_uid(
+        &request_rejected.request_id,
+        &mut request_rejected_builder.reborrow().init_request_id(),
+    );
+    ser_reject_reason(
+        &request_rejected.reason,
+        &mut request_rejected_builder.reborrow().init_reason(),
+    );
+}
+
+fn deser_request_rejected(
+    request_rejected_reader: &index_capnp::request_rejected::Reader,
+) -> Result<RequestRejected, SerializeError> {
+    Ok(RequestRejected {
+        request_id: read_uid(&request_rejected_reader.get_request_id()?)?,
+        reason: deser_reject_reason(&request_rejected_reader.get_reason()?)?,
+    })
+}
+
 fn ser_update_friend(
     update_friend: &UpdateFriend,
     update_friend_builder: &mut index_capnp::update_friend::Builder,
@@ -152,6 +309,14 @@ fn ser_update_friend(
         update_friend.recv_capacity,
         &mut update_friend_builder.reborrow().init_recv_capacity(),
     );
+    write_currency(
+        &update_friend.currency,
+        &mut update_friend_builder.reborrow().init_currency(),
+    );
+    write_rate(
+        &update_friend.rate,
+        &mut update_friend_builder.reborrow().init_rate(),
+    );
 }
 
 fn deser_update_friend(
@@ -161,6 +326,8 @@ fn deser_update_friend(
         public_key: read_public_key(&update_friend_reader.get_public_key()?)?,
         send_capacity: read_custom_u_int128(&update_friend_reader.get_send_capacity()?)?,
         recv_capacity: read_custom_u_int128(&update_friend_reader.get_recv_capacity()?)?,
+        currency: read_currency(&update_friend_reader.get_currency()?)?,
+        rate: read_rate(&update_friend_reader.get_rate()?)?,
     })
 }
 
@@ -323,6 +490,348 @@ fn deser_forward_mutations_update(
     })
 }
 
+fn ser_node_digest(
+    node_digest: &NodeDigest,
+    node_digest_builder: &mut index_capnp::node_digest::Builder,
+) {
+    write_public_key(
+        &node_digest.node_public_key,
+        &mut node_digest_builder.reborrow().init_node_public_key(),
+    );
+    write_uid(
+        &node_digest.session_id,
+        &mut node_digest_builder.reborrow().init_session_id(),
+    );
+    node_digest_builder
+        .reborrow()
+        .set_counter(node_digest.counter);
+}
+
+fn deser_node_digest(
+    node_digest_reader: &index_capnp::node_digest::Reader,
+) -> Result<NodeDigest, SerializeError> {
+    Ok(NodeDigest {
+        node_public_key: read_public_key(&node_digest_reader.get_node_public_key()?)?,
+        session_id: read_uid(&node_digest_reader.get_session_id()?)?,
+        counter: node_digest_reader.get_counter(),
+    })
+}
+
+fn ser_server_digest(
+    server_digest: &ServerDigest,
+    server_digest_builder: &mut index_capnp::server_digest::Builder,
+) {
+    let node_digests_len = usize_to_u32(server_digest.node_digests.len()).unwrap();
+    let mut node_digests_builder = server_digest_builder
+        .reborrow()
+        .init_node_digests(node_digests_len);
+
+    for (index, node_digest) in server_digest.node_digests.iter().enumerate() {
+        let mut node_digest_builder = node_digests_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        ser_node_digest(node_digest, &mut node_digest_builder);
+    }
+
+    server_digest_builder
+        .reborrow()
+        .set_is_last(server_digest.is_last);
+}
+
+fn deser_server_digest(
+    server_digest_reader: &index_capnp::server_digest::Reader,
+) -> Result<ServerDigest, SerializeError> {
+    let mut node_digests = Vec::new();
+    for node_digest_reader in server_digest_reader.get_node_digests()? {
+        node_digests.push(deser_node_digest(&node_digest_reader)?);
+    }
+
+    Ok(ServerDigest {
+        node_digests,
+        is_last: server_digest_reader.get_is_last(),
+    })
+}
+
+fn ser_node_edges(
+    node_edges: &NodeEdges,
+    node_edges_builder: &mut index_capnp::node_edges::Builder,
+) {
+    ser_node_digest(
+        &node_edges.node_digest,
+        &mut node_edges_builder.reborrow().init_node_digest(),
+    );
+    ser_mutations_update(
+        &node_edges.mutations_update,
+        &mut node_edges_builder.reborrow().init_mutations_update(),
+    );
+
+    let edge_indices_len = usize_to_u32(node_edges.edge_indices.len()).unwrap();
+    let mut edge_indices_builder = node_edges_builder
+        .reborrow()
+        .init_edge_indices(edge_indices_len);
+
+    for (index, edge_index) in node_edges.edge_indices.iter().enumerate() {
+        edge_indices_builder.set(usize_to_u32(index).unwrap(), *edge_index);
+    }
+}
+
+fn deser_node_edges(
+    node_edges_reader: &index_capnp::node_edges::Reader,
+) -> Result<NodeEdges, SerializeError> {
+    Ok(NodeEdges {
+        node_digest: deser_node_digest(&node_edges_reader.get_node_digest()?)?,
+        mutations_update: deser_mutations_update(&node_edges_reader.get_mutations_update()?)?,
+        edge_indices: node_edges_reader.get_edge_indices()?.iter().collect(),
+    })
+}
+
+fn ser_request_neighbors(
+    request_neighbors: &RequestNeighbors,
+    request_neighbors_builder: &mut index_capnp::request_neighbors::Builder,
+) {
+    write_currency(
+        &request_neighbors.currency,
+        &mut request_neighbors_builder.reborrow().init_currency(),
+    );
+    write_public_key(
+        &request_neighbors.node_public_key,
+        &mut request_neighbors_builder.reborrow().init_node_public_key(),
+    );
+}
+
+fn deser_request_neighbors(
+    request_neighbors_reader: &index_capnp::request_neighbors::Reader,
+) -> Result<RequestNeighbors, SerializeError> {
+    Ok(RequestNeighbors {
+        currency: read_currency(&request_neighbors_reader.get_currency()?)?,
+        node_public_key: read_public_key(&request_neighbors_reader.get_node_public_key()?)?,
+    })
+}
+
+fn ser_currency_graph_stats(
+    currency_graph_stats: &CurrencyGraphStats,
+    currency_graph_stats_builder: &mut index_capnp::currency_graph_stats::Builder,
+) {
+    write_currency(
+        &currency_graph_stats.currency,
+        &mut currency_graph_stats_builder.reborrow().init_currency(),
+    );
+    currency_graph_stats_builder
+        .reborrow()
+        .set_num_nodes(currency_graph_stats.num_nodes);
+    currency_graph_stats_builder
+        .reborrow()
+        .set_num_edges(currency_graph_stats.num_edges);
+}
+
+fn deser_currency_graph_stats(
+    currency_graph_stats_reader: &index_capnp::currency_graph_stats::Reader,
+) -> Result<CurrencyGraphStats, SerializeError> {
+    Ok(CurrencyGraphStats {
+        currency: read_currency(&currency_graph_stats_reader.get_currency()?)?,
+        num_nodes: currency_graph_stats_reader.get_num_nodes(),
+        num_edges: currency_graph_stats_reader.get_num_edges(),
+    })
+}
+
+fn ser_verifier_stats(
+    verifier_stats: &VerifierStats,
+    verifier_stats_builder: &mut index_capnp::verifier_stats::Builder,
+) {
+    verifier_stats_builder
+        .reborrow()
+        .set_num_neighbors(verifier_stats.num_neighbors);
+    verifier_stats_builder
+        .reborrow()
+        .set_num_ticks(verifier_stats.num_ticks);
+    verifier_stats_builder
+        .reborrow()
+        .set_num_nodes(verifier_stats.num_nodes);
+}
+
+fn deser_verifier_stats(
+    verifier_stats_reader: &index_capnp::verifier_stats::Reader,
+) -> Result<VerifierStats, SerializeError> {
+    Ok(VerifierStats {
+        num_neighbors: verifier_stats_reader.get_num_neighbors(),
+        num_ticks: verifier_stats_reader.get_num_ticks(),
+        num_nodes: verifier_stats_reader.get_num_nodes(),
+    })
+}
+
+fn ser_routes_requests_stats(
+    routes_requests_stats: &RoutesRequestsStats,
+    routes_requests_stats_builder: &mut index_capnp::routes_requests_stats::Builder,
+) {
+    routes_requests_stats_builder
+        .reborrow()
+        .set_num_handled(routes_requests_stats.num_handled);
+    routes_requests_stats_builder
+        .reborrow()
+        .set_num_rejected(routes_requests_stats.num_rejected);
+    routes_requests_stats_builder
+        .reborrow()
+        .set_num_open(routes_requests_stats.num_open);
+    routes_requests_stats_builder
+        .reborrow()
+        .set_avg_latency_micros(routes_requests_stats.avg_latency_micros);
+    routes_requests_stats_builder
+        .reborrow()
+        .set_max_latency_micros(routes_requests_stats.max_latency_micros);
+}
+
+fn deser_routes_requests_stats(
+    routes_requests_stats_reader: &index_capnp::routes_requests_stats::Reader,
+) -> Result<RoutesRequestsStats, SerializeError> {
+    Ok(RoutesRequestsStats {
+        num_handled: routes_requests_stats_reader.get_num_handled(),
+        num_rejected: routes_requests_stats_reader.get_num_rejected(),
+        num_open: routes_requests_stats_reader.get_num_open(),
+        avg_latency_micros: routes_requests_stats_reader.get_avg_latency_micros(),
+        max_latency_micros: routes_requests_stats_reader.get_max_latency_micros(),
+    })
+}
+
+fn ser_index_server_stats(
+    index_server_stats: &IndexServerStats,
+    index_server_stats_builder: &mut index_capnp::index_server_stats::Builder,
+) {
+    let graphs_len = usize_to_u32(index_server_stats.graphs.len()).unwrap();
+    let mut graphs_builder = index_server_stats_builder
+        .reborrow()
+        .init_graphs(graphs_len);
+    for (index, currency_graph_stats) in index_server_stats.graphs.iter().enumerate() {
+        let mut currency_graph_stats_builder =
+            graphs_builder.reborrow().get(usize_to_u32(index).unwrap());
+        ser_currency_graph_stats(currency_graph_stats, &mut currency_graph_stats_builder);
+    }
+
+    index_server_stats_builder
+        .reborrow()
+        .set_num_clients(index_server_stats.num_clients);
+    index_server_stats_builder
+        .reborrow()
+        .set_num_trusted_servers(index_server_stats.num_trusted_servers);
+
+    let connected_servers_len = usize_to_u32(index_server_stats.connected_servers.len()).unwrap();
+    let mut connected_servers_builder = index_server_stats_builder
+        .reborrow()
+        .init_connected_servers(connected_servers_len);
+    for (index, public_key) in index_server_stats.connected_servers.iter().enumerate() {
+        let mut public_key_builder = connected_servers_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        write_public_key(public_key, &mut public_key_builder);
+    }
+
+    ser_verifier_stats(
+        &index_server_stats.verifier,
+        &mut index_server_stats_builder.reborrow().init_verifier(),
+    );
+    ser_routes_requests_stats(
+        &index_server_stats.routes_requests,
+        &mut index_server_stats_builder.reborrow().init_routes_requests(),
+    );
+}
+
+fn deser_index_server_stats(
+    index_server_stats_reader: &index_capnp::index_server_stats::Reader,
+) -> Result<IndexServerStats, SerializeError> {
+    let mut graphs = Vec::new();
+    for currency_graph_stats_reader in index_server_stats_reader.get_graphs()? {
+        graphs.push(deser_currency_graph_stats(&currency_graph_stats_reader)?);
+    }
+
+    let mut connected_servers = Vec::new();
+    for public_key_reader in index_server_stats_reader.get_connected_servers()? {
+        connected_servers.push(read_public_key(&public_key_reader)?);
+    }
+
+    Ok(IndexServerStats {
+        graphs,
+        num_clients: index_server_stats_reader.get_num_clients(),
+        num_trusted_servers: index_server_stats_reader.get_num_trusted_servers(),
+        connected_servers,
+        verifier: deser_verifier_stats(&index_server_stats_reader.get_verifier()?)?,
+        routes_requests: deser_routes_requests_stats(
+            &index_server_stats_reader.get_routes_requests()?,
+        )?,
+    })
+}
+
+fn ser_neighbor_edge(
+    neighbor_edge: &NeighborEdge,
+    neighbor_edge_builder: &mut index_capnp::neighbor_edge::Builder,
+) {
+    write_public_key(
+        &neighbor_edge.public_key,
+        &mut neighbor_edge_builder.reborrow().init_public_key(),
+    );
+    write_custom_u_int128(
+        neighbor_edge.send_capacity,
+        &mut neighbor_edge_builder.reborrow().init_send_capacity(),
+    );
+    write_custom_u_int128(
+        neighbor_edge.recv_capacity,
+        &mut neighbor_edge_builder.reborrow().init_recv_capacity(),
+    );
+    write_rate(
+        &neighbor_edge.rate,
+        &mut neighbor_edge_builder.reborrow().init_rate(),
+    );
+}
+
+fn deser_neighbor_edge(
+    neighbor_edge_reader: &index_capnp::neighbor_edge::Reader,
+) -> Result<NeighborEdge, SerializeError> {
+    Ok(NeighborEdge {
+        public_key: read_public_key(&neighbor_edge_reader.get_public_key()?)?,
+        send_capacity: read_custom_u_int128(&neighbor_edge_reader.get_send_capacity()?)?,
+        recv_capacity: read_custom_u_int128(&neighbor_edge_reader.get_recv_capacity()?)?,
+        rate: read_rate(&neighbor_edge_reader.get_rate()?)?,
+    })
+}
+
+fn ser_response_neighbors(
+    response_neighbors: &ResponseNeighbors,
+    response_neighbors_builder: &mut index_capnp::response_neighbors::Builder,
+) {
+    write_currency(
+        &response_neighbors.currency,
+        &mut response_neighbors_builder.reborrow().init_currency(),
+    );
+    write_public_key(
+        &response_neighbors.node_public_key,
+        &mut response_neighbors_builder.reborrow().init_node_public_key(),
+    );
+
+    let neighbors_len = usize_to_u32(response_neighbors.neighbors.len()).unwrap();
+    let mut neighbors_builder = response_neighbors_builder
+        .reborrow()
+        .init_neighbors(neighbors_len);
+    for (index, neighbor_edge) in response_neighbors.neighbors.iter().enumerate() {
+        let mut neighbor_edge_builder = neighbors_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        ser_neighbor_edge(neighbor_edge, &mut neighbor_edge_builder);
+    }
+}
+
+fn deser_response_neighbors(
+    response_neighbors_reader: &index_capnp::response_neighbors::Reader,
+) -> Result<ResponseNeighbors, SerializeError> {
+    let mut neighbors = Vec::new();
+    for neighbor_edge_reader in response_neighbors_reader.get_neighbors()? {
+        neighbors.push(deser_neighbor_edge(&neighbor_edge_reader)?);
+    }
+
+    Ok(ResponseNeighbors {
+        currency: read_currency(&response_neighbors_reader.get_currency()?)?,
+        node_public_key: read_public_key(&response_neighbors_reader.get_node_public_key()?)?,
+        neighbors,
+    })
+}
+
 fn ser_index_server_to_client(
     index_server_to_client: &IndexServerToClient,
     index_server_to_client_builder: &mut index_capnp::index_server_to_client::Builder,
@@ -338,6 +847,12 @@ fn ser_index_server_to_client(
                 .init_response_routes();
             ser_response_routes(response_routes, &mut response_routes_builder);
         }
+        IndexServerToClient::RequestRejected(request_rejected) => {
+            let mut request_rejected_builder = index_server_to_client_builder
+                .reborrow()
+                .init_request_rejected();
+            ser_request_rejected(request_rejected, &mut request_rejected_builder);
+        }
     }
 }
 
@@ -351,6 +866,9 @@ fn deser_index_server_to_client(
         index_capnp::index_server_to_client::ResponseRoutes(response_routes_reader) => {
             IndexServerToClient::ResponseRoutes(deser_response_routes(&response_routes_reader?)?)
         }
+        index_capnp::index_server_to_client::RequestRejected(request_rejected_reader) => {
+            IndexServerToClient::RequestRejected(deser_request_rejected(&request_rejected_reader?)?)
+        }
     })
 }
 
@@ -405,6 +923,25 @@ fn ser_index_server_to_server(
                 &mut forward_mutations_update_builder,
             );
         }
+        IndexServerToServer::ServerDigest(server_digest) => {
+            let mut server_digest_builder = index_server_to_server_builder
+                .reborrow()
+                .init_server_digest();
+            ser_server_digest(server_digest, &mut server_digest_builder);
+        }
+        IndexServerToServer::NodeState(forward_mutations_update) => {
+            let mut forward_mutations_update_builder =
+                index_server_to_server_builder.reborrow().init_node_state();
+            ser_forward_mutations_update(
+                forward_mutations_update,
+                &mut forward_mutations_update_builder,
+            );
+        }
+        IndexServerToServer::NodeEdges(node_edges) => {
+            let mut node_edges_builder =
+                index_server_to_server_builder.reborrow().init_node_edges();
+            ser_node_edges(node_edges, &mut node_edges_builder);
+        }
     }
 }
 
@@ -420,6 +957,79 @@ fn deser_index_server_to_server(
         ) => IndexServerToServer::ForwardMutationsUpdate(deser_forward_mutations_update(
             &forward_mutations_update_reader?,
         )?),
+        index_capnp::index_server_to_server::ServerDigest(server_digest_reader) => {
+            IndexServerToServer::ServerDigest(deser_server_digest(&server_digest_reader?)?)
+        }
+        index_capnp::index_server_to_server::NodeState(forward_mutations_update_reader) => {
+            IndexServerToServer::NodeState(deser_forward_mutations_update(
+                &forward_mutations_update_reader?,
+            )?)
+        }
+        index_capnp::index_server_to_server::NodeEdges(node_edges_reader) => {
+            IndexServerToServer::NodeEdges(deser_node_edges(&node_edges_reader?)?)
+        }
+    })
+}
+
+fn ser_index_admin_to_server(
+    index_admin_to_server: &IndexAdminToServer,
+    index_admin_to_server_builder: &mut index_capnp::index_admin_to_server::Builder,
+) {
+    match index_admin_to_server {
+        IndexAdminToServer::RequestStats => index_admin_to_server_builder.set_request_stats(()),
+        IndexAdminToServer::RequestNeighbors(request_neighbors) => {
+            let mut request_neighbors_builder = index_admin_to_server_builder
+                .reborrow()
+                .init_request_neighbors();
+            ser_request_neighbors(request_neighbors, &mut request_neighbors_builder);
+        }
+    }
+}
+
+fn deser_index_admin_to_server(
+    index_admin_to_server_reader: &index_capnp::index_admin_to_server::Reader,
+) -> Result<IndexAdminToServer, SerializeError> {
+    Ok(match index_admin_to_server_reader.which()? {
+        index_capnp::index_admin_to_server::RequestStats(()) => IndexAdminToServer::RequestStats,
+        index_capnp::index_admin_to_server::RequestNeighbors(request_neighbors_reader) => {
+            IndexAdminToServer::RequestNeighbors(deser_request_neighbors(
+                &request_neighbors_reader?,
+            )?)
+        }
+    })
+}
+
+fn ser_index_server_to_admin(
+    index_server_to_admin: &IndexServerToAdmin,
+    index_server_to_admin_builder: &mut index_capnp::index_server_to_admin::Builder,
+) {
+    match index_server_to_admin {
+        IndexServerToAdmin::Stats(index_server_stats) => {
+            let mut index_server_stats_builder =
+                index_server_to_admin_builder.reborrow().init_stats();
+            ser_index_server_stats(index_server_stats, &mut index_server_stats_builder);
+        }
+        IndexServerToAdmin::ResponseNeighbors(response_neighbors) => {
+            let mut response_neighbors_builder = index_server_to_admin_builder
+                .reborrow()
+                .init_response_neighbors();
+            ser_response_neighbors(response_neighbors, &mut response_neighbors_builder);
+        }
+    }
+}
+
+fn deser_index_server_to_admin(
+    index_server_to_admin_reader: &index_capnp::index_server_to_admin::Reader,
+) -> Result<IndexServerToAdmin, SerializeError> {
+    Ok(match index_server_to_admin_reader.which()? {
+        index_capnp::index_server_to_admin::Stats(index_server_stats_reader) => {
+            IndexServerToAdmin::Stats(deser_index_server_stats(&index_server_stats_reader?)?)
+        }
+        index_capnp::index_server_to_admin::ResponseNeighbors(response_neighbors_reader) => {
+            IndexServerToAdmin::ResponseNeighbors(deser_response_neighbors(
+                &response_neighbors_reader?,
+            )?)
+        }
     })
 }
 
@@ -466,6 +1076,32 @@ pub fn serialize_index_server_to_client(index_server_to_client: &IndexServerToCl
     ser_buff
 }
 
+/// Serialize IndexAdminToServer into a vector of bytes
+pub fn serialize_index_admin_to_server(index_admin_to_server: &IndexAdminToServer) -> Vec<u8> {
+    let mut builder = capnp::message::Builder::new_default();
+    let mut index_admin_to_server_builder =
+        builder.init_root::<index_capnp::index_admin_to_server::Builder>();
+
+    ser_index_admin_to_server(index_admin_to_server, &mut index_admin_to_server_builder);
+
+    let mut ser_buff = Vec::new();
+    serialize_packed::write_message(&mut ser_buff, &builder).unwrap();
+    ser_buff
+}
+
+/// Serialize IndexServerToAdmin into a vector of bytes
+pub fn serialize_index_server_to_admin(index_server_to_admin: &IndexServerToAdmin) -> Vec<u8> {
+    let mut builder = capnp::message::Builder::new_default();
+    let mut index_server_to_admin_builder =
+        builder.init_root::<index_capnp::index_server_to_admin::Builder>();
+
+    ser_index_server_to_admin(index_server_to_admin, &mut index_server_to_admin_builder);
+
+    let mut ser_buff = Vec::new();
+    serialize_packed::write_message(&mut ser_buff, &builder).unwrap();
+    ser_buff
+}
+
 // -------------------------------------------------------------
 // -------------------[Deserialize]-----------------------------
 // -------------------------------------------------------------
@@ -508,3 +1144,29 @@ pub fn deserialize_index_server_to_client(
 
     deser_index_server_to_client(&index_server_to_client_reader)
 }
+
+/// Deserialize IndexAdminToServer from an array of bytes
+pub fn deserialize_index_admin_to_server(
+    data: &[u8],
+) -> Result<IndexAdminToServer, SerializeError> {
+    let mut cursor = io::Cursor::new(data);
+    let reader =
+        serialize_packed::read_message(&mut cursor, ::capnp::message::ReaderOptions::new())?;
+    let index_admin_to_server_reader =
+        reader.get_root::<index_capnp::index_admin_to_server::Reader>()?;
+
+    deser_index_admin_to_server(&index_admin_to_server_reader)
+}
+
+/// Deserialize IndexServerToAdmin from an array of bytes
+pub fn deserialize_index_server_to_admin(
+    data: &[u8],
+) -> Result<IndexServerToAdmin, SerializeError> {
+    let mut cursor = io::Cursor::new(data);
+    let reader =
+        serialize_packed::read_message(&mut cursor, ::capnp::message::ReaderOptions::new())?;
+    let index_server_to_admin_reader =
+        reader.get_root::<index_capnp::index_server_to_admin::Reader>()?;
+
+    deser_index_server_to_admin(&index_server_to_admin_reader)
+}
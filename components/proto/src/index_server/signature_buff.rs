@@ -19,6 +19,9 @@ impl CanonicalSerialize for UpdateFriend {
         res_bytes
             .write_u128::<BigEndian>(self.recv_capacity)
             .unwrap();
+        res_bytes.extend(self.currency.canonical_serialize());
+        res_bytes.write_u32::<BigEndian>(self.rate.mul).unwrap();
+        res_bytes.write_u32::<BigEndian>(self.rate.add).unwrap();
         res_bytes
     }
 }
@@ -7,6 +7,16 @@ pub enum InitConnection {
     Accept(PublicKey),
     // remote side wants to connect to public_key
     Connect(PublicKey),
+    // remote side is a peer relay, and wants to be notified about listening nodes
+    Peer,
+    // remote side is a peer relay, forwarding a connection to a listening node
+    Forward(ForwardConnection),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ForwardConnection {
+    pub init_public_key: PublicKey,
+    pub connect_public_key: PublicKey,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -18,3 +28,9 @@ pub struct RejectConnection {
 pub struct IncomingConnection {
     pub public_key: PublicKey,
 }
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ListenerUpdate {
+    Listening(PublicKey),
+    NotListening(PublicKey),
+}
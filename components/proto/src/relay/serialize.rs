@@ -5,7 +5,9 @@ use std::io;
 
 use relay_capnp;
 
-use super::messages::{IncomingConnection, InitConnection, RejectConnection};
+use super::messages::{
+    ForwardConnection, IncomingConnection, InitConnection, ListenerUpdate, RejectConnection,
+};
 
 use crate::serialize::SerializeError;
 
@@ -23,6 +25,18 @@ pub fn serialize_init_connection(init_connection: &InitConnection) -> Vec<u8> {
             let mut connect = msg.init_connect();
             write_public_key(&public_key, &mut connect);
         }
+        InitConnection::Peer => msg.set_peer(()),
+        InitConnection::Forward(forward_connection) => {
+            let mut forward = msg.init_forward();
+            write_public_key(
+                &forward_connection.init_public_key,
+                &mut forward.reborrow().init_init_public_key(),
+            );
+            write_public_key(
+                &forward_connection.connect_public_key,
+                &mut forward.reborrow().init_connect_public_key(),
+            );
+        }
     }
 
     let mut serialized_msg = Vec::new();
@@ -46,6 +60,14 @@ pub fn deserialize_init_connection(data: &[u8]) -> Result<InitConnection, Serial
             let public_key = read_public_key(&(public_key?))?;
             Ok(InitConnection::Connect(public_key))
         }
+        Ok(relay_capnp::init_connection::Peer(())) => Ok(InitConnection::Peer),
+        Ok(relay_capnp::init_connection::Forward(forward)) => {
+            let forward = forward?;
+            Ok(InitConnection::Forward(ForwardConnection {
+                init_public_key: read_public_key(&forward.get_init_public_key()?)?,
+                connect_public_key: read_public_key(&forward.get_connect_public_key()?)?,
+            }))
+        }
         Err(e) => Err(SerializeError::NotInSchema(e)),
     }
 }
@@ -92,6 +114,43 @@ pub fn deserialize_incoming_connection(data: &[u8]) -> Result<IncomingConnection
     Ok(IncomingConnection { public_key })
 }
 
+pub fn serialize_listener_update(listener_update: &ListenerUpdate) -> Vec<u8> {
+    let mut builder = capnp::message::Builder::new_default();
+    let mut msg = builder.init_root::<relay_capnp::listener_update::Builder>();
+
+    match listener_update {
+        ListenerUpdate::Listening(public_key) => {
+            write_public_key(&public_key, &mut msg.init_listening());
+        }
+        ListenerUpdate::NotListening(public_key) => {
+            write_public_key(&public_key, &mut msg.init_not_listening());
+        }
+    }
+
+    let mut serialized_msg = Vec::new();
+    serialize_packed::write_message(&mut serialized_msg, &builder).unwrap();
+    serialized_msg
+}
+
+pub fn deserialize_listener_update(data: &[u8]) -> Result<ListenerUpdate, SerializeError> {
+    let mut cursor = io::Cursor::new(data);
+    let reader =
+        serialize_packed::read_message(&mut cursor, ::capnp::message::ReaderOptions::new())?;
+    let msg = reader.get_root::<relay_capnp::listener_update::Reader>()?;
+
+    match msg.which() {
+        Ok(relay_capnp::listener_update::Listening(public_key)) => {
+            let public_key = read_public_key(&(public_key?))?;
+            Ok(ListenerUpdate::Listening(public_key))
+        }
+        Ok(relay_capnp::listener_update::NotListening(public_key)) => {
+            let public_key = read_public_key(&(public_key?))?;
+            Ok(ListenerUpdate::NotListening(public_key))
+        }
+        Err(e) => Err(SerializeError::NotInSchema(e)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -117,6 +176,19 @@ mod tests {
         let serialized = serialize_init_connection(&msg);
         let msg2 = deserialize_init_connection(&serialized[..]).unwrap();
         assert_eq!(msg, msg2);
+
+        let msg = InitConnection::Peer;
+        let serialized = serialize_init_connection(&msg);
+        let msg2 = deserialize_init_connection(&serialized[..]).unwrap();
+        assert_eq!(msg, msg2);
+
+        let msg = InitConnection::Forward(ForwardConnection {
+            init_public_key: PublicKey::try_from(&[0x03u8; PUBLIC_KEY_LEN][..]).unwrap(),
+            connect_public_key: PublicKey::try_from(&[0x04u8; PUBLIC_KEY_LEN][..]).unwrap(),
+        });
+        let serialized = serialize_init_connection(&msg);
+        let msg2 = deserialize_init_connection(&serialized[..]).unwrap();
+        assert_eq!(msg, msg2);
     }
 
     #[test]
@@ -136,4 +208,18 @@ mod tests {
         let msg2 = deserialize_incoming_connection(&serialized[..]).unwrap();
         assert_eq!(msg, msg2);
     }
+
+    #[test]
+    fn test_serialize_listener_update() {
+        let public_key = PublicKey::try_from(&[0x55u8; PUBLIC_KEY_LEN][..]).unwrap();
+        let msg = ListenerUpdate::Listening(public_key.clone());
+        let serialized = serialize_listener_update(&msg);
+        let msg2 = deserialize_listener_update(&serialized[..]).unwrap();
+        assert_eq!(msg, msg2);
+
+        let msg = ListenerUpdate::NotListening(public_key);
+        let serialized = serialize_listener_update(&msg);
+        let msg2 = deserialize_listener_update(&serialized[..]).unwrap();
+        assert_eq!(msg, msg2);
+    }
 }
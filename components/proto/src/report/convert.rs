@@ -6,12 +6,13 @@ use common::safe_arithmetic::{SafeSignedArithmetic, SafeUnsignedArithmetic};
 
 use crypto::identity::PublicKey;
 
+use crate::funder::messages::{Currency, Rate};
 use crate::index_client::messages::IndexClientState;
 use crate::index_server::messages::{IndexMutation, UpdateFriend};
 
 use crate::report::messages::{
     ChannelStatusReport, FriendLivenessReport, FriendReport, FriendStatusReport, FunderReport,
-    FunderReportMutation, RequestsStatusReport,
+    FunderReportMutation, McBalanceReport, McRequestsStatusReport, RequestsStatusReport,
 };
 
 // Conversion to index client mutations and state
@@ -24,25 +25,12 @@ use crate::report::messages::{
 // TODO: Maybe this logic shouldn't be here? Where should we move it to?
 // TODO: Add tests (Mostly for arithmetic stuff here)
 
-/// Calculate send and receive capacities for a given `friend_report`.
-fn calc_friend_capacities<B>(friend_report: &FriendReport<B>) -> (u128, u128)
-where
-    B: Clone,
-{
-    if friend_report.status == FriendStatusReport::Disabled
-        || friend_report.liveness == FriendLivenessReport::Offline
-    {
-        return (0, 0);
-    }
-
-    let tc_report = match &friend_report.channel_status {
-        ChannelStatusReport::Inconsistent(_) => return (0, 0),
-        ChannelStatusReport::Consistent(tc_report) => tc_report,
-    };
-
-    let balance = &tc_report.balance;
-
-    let send_capacity = if tc_report.requests_status.remote == RequestsStatusReport::Closed {
+/// Calculate send and receive capacities for a single currency of a mutual credit.
+fn calc_balance_capacities(
+    balance: &McBalanceReport,
+    requests_status: &McRequestsStatusReport,
+) -> (u128, u128) {
+    let send_capacity = if requests_status.remote == RequestsStatusReport::Closed {
         0
     } else {
         // local_max_debt + balance - local_pending_debt
@@ -54,7 +42,7 @@ where
         )
     };
 
-    let recv_capacity = if tc_report.requests_status.local == RequestsStatusReport::Closed {
+    let recv_capacity = if requests_status.local == RequestsStatusReport::Closed {
         0
     } else {
         balance.remote_max_debt.saturating_sub_signed(
@@ -68,6 +56,40 @@ where
     (send_capacity, recv_capacity)
 }
 
+/// Calculate send and receive capacities for every currency of a given `friend_report`, together
+/// with the rate we charge the friend for forwarding its requests.
+/// Currencies are omitted if the friend can not be used for routing at all.
+pub fn calc_friend_capacities<B>(
+    friend_report: &FriendReport<B>,
+) -> HashMap<Currency, (u128, u128, Rate)>
+where
+    B: Clone,
+{
+    if friend_report.status == FriendStatusReport::Disabled
+        || friend_report.liveness == FriendLivenessReport::Offline
+    {
+        return HashMap::new();
+    }
+
+    let tc_report = match &friend_report.channel_status {
+        ChannelStatusReport::Inconsistent(_) => return HashMap::new(),
+        ChannelStatusReport::Consistent(tc_report) => tc_report,
+    };
+
+    tc_report
+        .balances
+        .iter()
+        .map(|balance| {
+            let (send_capacity, recv_capacity) =
+                calc_balance_capacities(balance, &tc_report.requests_status);
+            (
+                balance.currency.clone(),
+                (send_capacity, recv_capacity, friend_report.rate.clone()),
+            )
+        })
+        .collect()
+}
+
 pub fn funder_report_to_index_client_state<B>(funder_report: &FunderReport<B>) -> IndexClientState
 where
     B: Clone,
@@ -75,14 +97,17 @@ where
     let friends = funder_report
         .friends
         .iter()
-        .map(|(friend_public_key, friend_report)| {
-            (
-                friend_public_key.clone(),
-                calc_friend_capacities(friend_report),
-            )
+        .flat_map(|(friend_public_key, friend_report)| {
+            calc_friend_capacities(friend_report)
+                .into_iter()
+                .map(move |(currency, capacities)| {
+                    ((friend_public_key.clone(), currency), capacities)
+                })
+        })
+        .filter(|(_, (send_capacity, recv_capacity, _rate))| {
+            *send_capacity != 0 || *recv_capacity != 0
         })
-        .filter(|(_, (send_capacity, recv_capacity))| *send_capacity != 0 || *recv_capacity != 0)
-        .collect::<HashMap<PublicKey, (u128, u128)>>();
+        .collect::<HashMap<(PublicKey, Currency), (u128, u128, Rate)>>();
 
     IndexClientState { friends }
 }
@@ -90,7 +115,7 @@ where
 pub fn funder_report_mutation_to_index_mutation<B>(
     funder_report: &FunderReport<B>,
     funder_report_mutation: &FunderReportMutation<B>,
-) -> Option<IndexMutation>
+) -> Vec<IndexMutation>
 where
     B: Clone + Debug,
 {
@@ -107,29 +132,49 @@ where
 
         let new_capacities = calc_friend_capacities(new_friend_report);
 
-        // Return UpdateFriend if the new capacities are different than the old ones:
-        if opt_old_capacities != Some(new_capacities) {
-            let (send_capacity, recv_capacity) = new_capacities;
-            let update_friend = UpdateFriend {
-                public_key: public_key.clone(),
-                send_capacity,
-                recv_capacity,
-            };
-            Some(IndexMutation::UpdateFriend(update_friend))
-        } else {
-            None
+        // A new friend has no old capacities:
+        let old_capacities = opt_old_capacities.unwrap_or_default();
+
+        // Send UpdateFriend for every currency where the new capacities (or rate) are different
+        // than the old ones. A currency that is not usable anymore gets zero capacities:
+        let mut currencies = old_capacities.keys().collect::<Vec<_>>();
+        currencies.extend(new_capacities.keys());
+        currencies.sort();
+        currencies.dedup();
+
+        let mut index_mutations = Vec::new();
+        for currency in currencies {
+            let new_currency_capacities = new_capacities
+                .get(currency)
+                .cloned()
+                .unwrap_or_else(|| (0, 0, new_friend_report.rate.clone()));
+            if old_capacities.get(currency) != Some(&new_currency_capacities) {
+                let (send_capacity, recv_capacity, rate) = new_currency_capacities;
+                index_mutations.push(IndexMutation::UpdateFriend(UpdateFriend {
+                    public_key: public_key.clone(),
+                    send_capacity,
+                    recv_capacity,
+                    currency: currency.clone(),
+                    rate,
+                }));
+            }
         }
+        index_mutations
     };
 
     match funder_report_mutation {
         FunderReportMutation::AddRelay(_)
         | FunderReportMutation::RemoveRelay(_)
-        | FunderReportMutation::SetNumReadyReceipts(_) => None,
+        | FunderReportMutation::SetNumReadyReceipts(_)
+        | FunderReportMutation::SetFreezePolicy(_)
+        | FunderReportMutation::SetHoldIncomingPayments(_)
+        | FunderReportMutation::AddHeldPayment(_)
+        | FunderReportMutation::RemoveHeldPayment(_) => Vec::new(),
         FunderReportMutation::AddFriend(add_friend_report) => {
             create_update_friend(&add_friend_report.friend_public_key)
         }
         FunderReportMutation::RemoveFriend(public_key) => {
-            Some(IndexMutation::RemoveFriend(public_key.clone()))
+            vec![IndexMutation::RemoveFriend(public_key.clone())]
         }
         FunderReportMutation::FriendReportMutation((public_key, _friend_report_mutation)) => {
             create_update_friend(&public_key)
@@ -6,10 +6,15 @@ use common::mutable_state::MutableState;
 use crypto::crypto_rand::RandValue;
 use crypto::hash::HashResult;
 use crypto::identity::{PublicKey, Signature};
+use crypto::invoice_id::InvoiceId;
 use crypto::uid::Uid;
 
-use crate::app_server::messages::{NamedRelayAddress, RelayAddress};
-use crate::funder::messages::{FriendStatus, RequestsStatus};
+use crate::app_server::messages::{ChannelAddress, NamedRelayAddress};
+use crate::consts::DEFAULT_RATE_ADD;
+use crate::funder::messages::{
+    Currency, CurrencyBalance, DebtPolicy, FreezePolicy, FriendStatus, Rate, RequestsStatus,
+    SettleStatus, TokenBalance,
+};
 use crate::net::messages::NetAddress;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -19,9 +24,7 @@ pub struct MoveTokenHashedReport {
     pub remote_public_key: PublicKey,
     pub inconsistency_counter: u64,
     pub move_token_counter: u128,
-    pub balance: i128,
-    pub local_pending_debt: u128,
-    pub remote_pending_debt: u128,
+    pub balances: Vec<TokenBalance>,
     pub rand_nonce: RandValue,
     pub new_token: Signature,
 }
@@ -32,8 +35,8 @@ where
     B: Clone,
 {
     NeverSent,
-    Transition((ImVec<NamedRelayAddress<B>>, ImVec<NamedRelayAddress<B>>)), // (last sent, before last sent)
-    LastSent(ImVec<NamedRelayAddress<B>>),
+    Transition((ImVec<ChannelAddress<B>>, ImVec<ChannelAddress<B>>)), // (last sent, before last sent)
+    LastSent(ImVec<ChannelAddress<B>>),
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
@@ -48,6 +51,13 @@ pub enum RequestsStatusReport {
     Closed,
 }
 
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum SettleStatusReport {
+    Active,
+    Settling,
+    Settled,
+}
+
 #[derive(Eq, PartialEq, Clone, Serialize, Deserialize, Debug)]
 pub struct McRequestsStatusReport {
     // Local is open/closed for incoming requests:
@@ -58,6 +68,8 @@ pub struct McRequestsStatusReport {
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct McBalanceReport {
+    /// The currency of this credit line
+    pub currency: Currency,
     /// Amount of credits this side has against the remote side.
     /// The other side keeps the negation of this value.
     pub balance: i128,
@@ -111,24 +123,35 @@ impl FriendLivenessReport {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrozenCreditsReport {
+    pub currency: Currency,
+    pub next_public_key: PublicKey,
+    pub frozen_credits: u128,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TcReport {
     pub direction: DirectionReport,
-    pub balance: McBalanceReport,
+    // Balance of every currency in the mutual credit. Sorted by currency.
+    pub balances: Vec<McBalanceReport>,
     pub requests_status: McRequestsStatusReport,
     pub num_local_pending_requests: u64,
     pub num_remote_pending_requests: u64,
+    // Credits frozen by the remote side, grouped by the next friend on the route.
+    // Sorted by currency and next_public_key.
+    pub frozen_credits: Vec<FrozenCreditsReport>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ResetTermsReport {
     pub reset_token: Signature,
-    pub balance_for_reset: i128,
+    pub balance_for_reset: Vec<CurrencyBalance>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ChannelInconsistentReport {
-    pub local_reset_terms_balance: i128,
+    pub local_reset_terms_balance: Vec<CurrencyBalance>,
     pub opt_remote_reset_terms: Option<ResetTermsReport>,
 }
 
@@ -144,7 +167,7 @@ where
     B: Clone,
 {
     pub name: String,
-    pub remote_relays: Vec<RelayAddress<B>>,
+    pub remote_relays: Vec<ChannelAddress<B>>,
     pub sent_local_relays: SentLocalRelaysReport<B>,
     // Last message signed by the remote side.
     // Can be used as a proof for the last known balance.
@@ -153,7 +176,7 @@ where
     // Can we somehow express this in the type system?
     pub liveness: FriendLivenessReport, // is the friend online/offline?
     pub channel_status: ChannelStatusReport,
-    pub wanted_remote_max_debt: u128,
+    pub wanted_remote_max_debt: ImHashMap<Currency, u128>,
     pub wanted_local_requests_status: RequestsStatusReport,
     pub num_pending_requests: u64,
     pub num_pending_responses: u64,
@@ -162,6 +185,21 @@ where
     pub num_pending_user_requests: u64,
     // Request that the user has sent to this neighbor,
     // but have not been processed yet. Bounded in size.
+    pub rate: Rate,
+    // The fee we charge this friend for forwarding its requests.
+    pub opt_debt_policy: Option<DebtPolicy>,
+    // Rules for automatically managing the remote max debt of this friend.
+    pub settle_status: SettleStatusReport,
+    // Settlement of the mutual credit, before closing the channel.
+}
+
+/// A payment (where we are the destination) that has fully arrived, and waits for the user to
+/// accept or reject it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeldPaymentReport {
+    pub invoice_id: InvoiceId,
+    pub currency: Currency,
+    pub total_dest_payment: u128,
 }
 
 /// A FunderReport is a summary of a FunderState.
@@ -174,8 +212,15 @@ where
 {
     pub local_public_key: PublicKey,
     pub relays: ImVec<NamedRelayAddress<B>>,
+    /// Address friends may use to connect to us directly, without a relay.
+    pub opt_direct_address: Option<B>,
     pub friends: ImHashMap<PublicKey, FriendReport<B>>,
     pub num_ready_receipts: u64,
+    pub freeze_policy: FreezePolicy,
+    /// Hold incoming payments until they are accepted by the user.
+    pub hold_incoming_payments: bool,
+    /// Incoming payments that are waiting to be accepted or rejected by the user.
+    pub held_payments: ImVec<HeldPaymentReport>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -184,11 +229,11 @@ pub enum FriendReportMutation<B = NetAddress>
 where
     B: Clone,
 {
-    SetRemoteRelays(Vec<RelayAddress<B>>),
+    SetRemoteRelays(Vec<ChannelAddress<B>>),
     SetName(String),
     SetSentLocalRelays(SentLocalRelaysReport<B>),
     SetChannelStatus(ChannelStatusReport),
-    SetWantedRemoteMaxDebt(u128),
+    SetWantedRemoteMaxDebt((Currency, u128)),
     SetWantedLocalRequestsStatus(RequestsStatusReport),
     SetNumPendingRequests(u64),
     SetNumPendingResponses(u64),
@@ -196,14 +241,17 @@ where
     SetNumPendingUserRequests(u64),
     SetOptLastIncomingMoveToken(Option<MoveTokenHashedReport>),
     SetLiveness(FriendLivenessReport),
+    SetRate(Rate),
+    SetDebtPolicy(Option<DebtPolicy>),
+    SetSettleStatus(SettleStatusReport),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct AddFriendReport<B = NetAddress> {
     pub friend_public_key: PublicKey,
     pub name: String,
-    pub relays: Vec<RelayAddress<B>>,
-    pub balance: i128, // Initial balance
+    pub relays: Vec<ChannelAddress<B>>,
+    pub balances: Vec<CurrencyBalance>, // Initial balances
     pub opt_last_incoming_move_token: Option<MoveTokenHashedReport>,
     pub channel_status: ChannelStatusReport,
 }
@@ -216,10 +264,15 @@ where
 {
     AddRelay(NamedRelayAddress<B>),
     RemoveRelay(PublicKey),
+    SetDirectAddress(Option<B>),
     AddFriend(AddFriendReport<B>),
     RemoveFriend(PublicKey),
     FriendReportMutation((PublicKey, FriendReportMutation<B>)),
     SetNumReadyReceipts(u64),
+    SetFreezePolicy(FreezePolicy),
+    SetHoldIncomingPayments(bool),
+    AddHeldPayment(HeldPaymentReport),
+    RemoveHeldPayment(InvoiceId),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -246,10 +299,22 @@ impl From<&RequestsStatus> for RequestsStatusReport {
     }
 }
 
+impl From<&SettleStatus> for SettleStatusReport {
+    fn from(settle_status: &SettleStatus) -> SettleStatusReport {
+        match settle_status {
+            SettleStatus::Active => SettleStatusReport::Active,
+            SettleStatus::Settling => SettleStatusReport::Settling,
+            SettleStatus::Settled => SettleStatusReport::Settled,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum FunderReportMutateError {
     FriendDoesNotExist,
     FriendAlreadyExists,
+    HeldPaymentDoesNotExist,
+    HeldPaymentAlreadyExists,
 }
 
 impl<B> MutableState for FriendReport<B>
@@ -273,8 +338,9 @@ where
             FriendReportMutation::SetChannelStatus(channel_status_report) => {
                 self.channel_status = channel_status_report.clone();
             }
-            FriendReportMutation::SetWantedRemoteMaxDebt(wanted_remote_max_debt) => {
-                self.wanted_remote_max_debt = *wanted_remote_max_debt;
+            FriendReportMutation::SetWantedRemoteMaxDebt((currency, wanted_remote_max_debt)) => {
+                self.wanted_remote_max_debt
+                    .insert(currency.clone(), *wanted_remote_max_debt);
             }
             FriendReportMutation::SetWantedLocalRequestsStatus(wanted_local_requests_status) => {
                 self.wanted_local_requests_status = wanted_local_requests_status.clone();
@@ -297,6 +363,15 @@ where
             FriendReportMutation::SetLiveness(friend_liveness_report) => {
                 self.liveness = friend_liveness_report.clone();
             }
+            FriendReportMutation::SetRate(rate) => {
+                self.rate = rate.clone();
+            }
+            FriendReportMutation::SetDebtPolicy(opt_debt_policy) => {
+                self.opt_debt_policy = opt_debt_policy.clone();
+            }
+            FriendReportMutation::SetSettleStatus(settle_status) => {
+                self.settle_status = settle_status.clone();
+            }
         };
         Ok(())
     }
@@ -326,6 +401,10 @@ where
                 });
                 Ok(())
             }
+            FunderReportMutation::SetDirectAddress(opt_direct_address) => {
+                self.opt_direct_address = opt_direct_address.clone();
+                Ok(())
+            }
             FunderReportMutation::AddFriend(add_friend_report) => {
                 let friend_report = FriendReport {
                     name: add_friend_report.name.clone(),
@@ -336,7 +415,7 @@ where
                         .clone(),
                     liveness: FriendLivenessReport::Offline,
                     channel_status: add_friend_report.channel_status.clone(),
-                    wanted_remote_max_debt: 0,
+                    wanted_remote_max_debt: ImHashMap::new(),
                     wanted_local_requests_status: RequestsStatusReport::from(
                         &RequestsStatus::Closed,
                     ),
@@ -344,6 +423,12 @@ where
                     num_pending_requests: 0,
                     status: FriendStatusReport::from(&FriendStatus::Disabled),
                     num_pending_user_requests: 0,
+                    rate: Rate {
+                        mul: 0,
+                        add: DEFAULT_RATE_ADD,
+                    },
+                    opt_debt_policy: None,
+                    settle_status: SettleStatusReport::from(&SettleStatus::Active),
                 };
                 if self
                     .friends
@@ -377,6 +462,34 @@ where
                 self.num_ready_receipts = *num_ready_receipts;
                 Ok(())
             }
+            FunderReportMutation::SetFreezePolicy(freeze_policy) => {
+                self.freeze_policy = *freeze_policy;
+                Ok(())
+            }
+            FunderReportMutation::SetHoldIncomingPayments(hold_incoming_payments) => {
+                self.hold_incoming_payments = *hold_incoming_payments;
+                Ok(())
+            }
+            FunderReportMutation::AddHeldPayment(held_payment_report) => {
+                if self.held_payments.iter().any(|cur_held_payment| {
+                    cur_held_payment.invoice_id == held_payment_report.invoice_id
+                }) {
+                    Err(FunderReportMutateError::HeldPaymentAlreadyExists)
+                } else {
+                    self.held_payments.push_back(held_payment_report.clone());
+                    Ok(())
+                }
+            }
+            FunderReportMutation::RemoveHeldPayment(invoice_id) => {
+                let len_before = self.held_payments.len();
+                self.held_payments
+                    .retain(|held_payment| &held_payment.invoice_id != invoice_id);
+                if self.held_payments.len() == len_before {
+                    Err(FunderReportMutateError::HeldPaymentDoesNotExist)
+                } else {
+                    Ok(())
+                }
+            }
         }
     }
 }
@@ -2,26 +2,32 @@ use im::hashmap::HashMap as ImHashMap;
 use im::vector::Vector as ImVec;
 
 use crate::capnp_common::{
-    read_custom_int128, read_custom_u_int128, read_hash, read_named_index_server_address,
-    read_named_relay_address, read_public_key, read_rand_nonce, read_relay_address, read_signature,
-    write_custom_int128, write_custom_u_int128, write_hash, write_named_index_server_address,
-    write_named_relay_address, write_public_key, write_rand_nonce, write_relay_address,
-    write_signature,
+    read_channel_address, read_currency, read_currency_balance, read_custom_int128,
+    read_custom_u_int128, read_debt_policy, read_freeze_policy, read_hash, read_invoice_id,
+    read_named_index_server_address, read_named_relay_address, read_opt_net_address,
+    read_public_key, read_rand_nonce, read_rate, read_signature, write_channel_address,
+    write_currency, write_currency_balance, write_custom_int128, write_custom_u_int128,
+    write_debt_policy, write_freeze_policy, write_hash, write_invoice_id,
+    write_named_index_server_address, write_named_relay_address, write_opt_net_address,
+    write_public_key, write_rand_nonce, write_rate, write_signature,
 };
 use common::int_convert::usize_to_u32;
 use crypto::identity::PublicKey;
 
 use crate::report::messages::{
     AddFriendReport, ChannelInconsistentReport, ChannelStatusReport, DirectionReport,
-    FriendLivenessReport, FriendReport, FriendReportMutation, FriendStatusReport, FunderReport,
-    FunderReportMutation, McBalanceReport, McRequestsStatusReport, MoveTokenHashedReport,
-    RequestsStatusReport, ResetTermsReport, SentLocalRelaysReport, TcReport,
+    FriendLivenessReport, FriendReport, FriendReportMutation, FriendStatusReport,
+    FrozenCreditsReport, FunderReport, FunderReportMutation, HeldPaymentReport, McBalanceReport,
+    McRequestsStatusReport, MoveTokenHashedReport, RequestsStatusReport, ResetTermsReport,
+    SentLocalRelaysReport, SettleStatusReport, TcReport,
 };
 use crate::serialize::SerializeError;
 use report_capnp;
 
-use crate::app_server::messages::NamedRelayAddress;
+use crate::app_server::messages::ChannelAddress;
 use crate::app_server::messages::{NodeReport, NodeReportMutation};
+use crate::funder::messages::{Currency, CurrencyBalance, DebtPolicy};
+use crate::funder::serialize::{deser_token_balance, ser_token_balance};
 use crate::index_client::messages::{IndexClientReport, IndexClientReportMutation};
 use crate::net::messages::NetAddress;
 
@@ -61,24 +67,16 @@ fn ser_move_token_hashed_report(
             .init_move_token_counter(),
     );
 
-    write_custom_int128(
-        move_token_hashed_report.balance,
-        &mut move_token_hashed_report_builder.reborrow().init_balance(),
-    );
-
-    write_custom_u_int128(
-        move_token_hashed_report.local_pending_debt,
-        &mut move_token_hashed_report_builder
-            .reborrow()
-            .init_local_pending_debt(),
-    );
-
-    write_custom_u_int128(
-        move_token_hashed_report.remote_pending_debt,
-        &mut move_token_hashed_report_builder
+    let balances_len = usize_to_u32(move_token_hashed_report.balances.len()).unwrap();
+    let mut balances_builder = move_token_hashed_report_builder
+        .reborrow()
+        .init_balances(balances_len);
+    for (index, token_balance) in move_token_hashed_report.balances.iter().enumerate() {
+        let mut token_balance_builder = balances_builder
             .reborrow()
-            .init_remote_pending_debt(),
-    );
+            .get(usize_to_u32(index).unwrap());
+        ser_token_balance(token_balance, &mut token_balance_builder);
+    }
 
     write_rand_nonce(
         &move_token_hashed_report.rand_nonce,
@@ -96,6 +94,11 @@ fn ser_move_token_hashed_report(
 fn deser_move_token_hashed_report(
     move_token_hashed_report_reader: &report_capnp::move_token_hashed_report::Reader,
 ) -> Result<MoveTokenHashedReport, SerializeError> {
+    let mut balances = Vec::new();
+    for token_balance_reader in move_token_hashed_report_reader.get_balances()? {
+        balances.push(deser_token_balance(&token_balance_reader)?);
+    }
+
     Ok(MoveTokenHashedReport {
         prefix_hash: read_hash(&move_token_hashed_report_reader.get_prefix_hash()?)?,
         local_public_key: read_public_key(
@@ -108,13 +111,7 @@ fn deser_move_token_hashed_report(
         move_token_counter: read_custom_u_int128(
             &move_token_hashed_report_reader.get_move_token_counter()?,
         )?,
-        balance: read_custom_int128(&move_token_hashed_report_reader.get_balance()?)?,
-        local_pending_debt: read_custom_u_int128(
-            &move_token_hashed_report_reader.get_local_pending_debt()?,
-        )?,
-        remote_pending_debt: read_custom_u_int128(
-            &move_token_hashed_report_reader.get_remote_pending_debt()?,
-        )?,
+        balances,
         rand_nonce: read_rand_nonce(&move_token_hashed_report_reader.get_rand_nonce()?)?,
         new_token: read_signature(&move_token_hashed_report_reader.get_new_token()?)?,
     })
@@ -158,6 +155,27 @@ fn deser_requests_status_report(
     })
 }
 
+fn ser_settle_status_report(
+    settle_status_report: &SettleStatusReport,
+    settle_status_report_builder: &mut report_capnp::settle_status_report::Builder,
+) {
+    match settle_status_report {
+        SettleStatusReport::Active => settle_status_report_builder.set_active(()),
+        SettleStatusReport::Settling => settle_status_report_builder.set_settling(()),
+        SettleStatusReport::Settled => settle_status_report_builder.set_settled(()),
+    }
+}
+
+fn deser_settle_status_report(
+    settle_status_report_reader: &report_capnp::settle_status_report::Reader,
+) -> Result<SettleStatusReport, SerializeError> {
+    Ok(match settle_status_report_reader.which()? {
+        report_capnp::settle_status_report::Active(()) => SettleStatusReport::Active,
+        report_capnp::settle_status_report::Settling(()) => SettleStatusReport::Settling,
+        report_capnp::settle_status_report::Settled(()) => SettleStatusReport::Settled,
+    })
+}
+
 fn ser_friend_liveness_report(
     friend_liveness_report: &FriendLivenessReport,
     friend_liveness_report_builder: &mut report_capnp::friend_liveness_report::Builder,
@@ -252,12 +270,18 @@ fn ser_mc_balance_report(
             .reborrow()
             .init_remote_pending_debt(),
     );
+
+    write_currency(
+        &mc_balance_report.currency,
+        &mut mc_balance_report_builder.reborrow().init_currency(),
+    );
 }
 
 fn deser_mc_balance_report(
     mc_balance_report_reader: &report_capnp::mc_balance_report::Reader,
 ) -> Result<McBalanceReport, SerializeError> {
     Ok(McBalanceReport {
+        currency: read_currency(&mc_balance_report_reader.get_currency()?)?,
         balance: read_custom_int128(&mc_balance_report_reader.get_balance()?)?,
         local_max_debt: read_custom_u_int128(&mc_balance_report_reader.get_local_max_debt()?)?,
         remote_max_debt: read_custom_u_int128(&mc_balance_report_reader.get_remote_max_debt()?)?,
@@ -270,16 +294,53 @@ fn deser_mc_balance_report(
     })
 }
 
+fn ser_frozen_credits_report(
+    frozen_credits_report: &FrozenCreditsReport,
+    frozen_credits_report_builder: &mut report_capnp::frozen_credits_report::Builder,
+) {
+    write_public_key(
+        &frozen_credits_report.next_public_key,
+        &mut frozen_credits_report_builder
+            .reborrow()
+            .init_next_public_key(),
+    );
+    write_custom_u_int128(
+        frozen_credits_report.frozen_credits,
+        &mut frozen_credits_report_builder
+            .reborrow()
+            .init_frozen_credits(),
+    );
+
+    write_currency(
+        &frozen_credits_report.currency,
+        &mut frozen_credits_report_builder.reborrow().init_currency(),
+    );
+}
+
+fn deser_frozen_credits_report(
+    frozen_credits_report_reader: &report_capnp::frozen_credits_report::Reader,
+) -> Result<FrozenCreditsReport, SerializeError> {
+    Ok(FrozenCreditsReport {
+        currency: read_currency(&frozen_credits_report_reader.get_currency()?)?,
+        next_public_key: read_public_key(&frozen_credits_report_reader.get_next_public_key()?)?,
+        frozen_credits: read_custom_u_int128(&frozen_credits_report_reader.get_frozen_credits()?)?,
+    })
+}
+
 fn ser_tc_report(tc_report: &TcReport, tc_report_builder: &mut report_capnp::tc_report::Builder) {
     ser_direction_report(
         &tc_report.direction,
         &mut tc_report_builder.reborrow().init_direction(),
     );
 
-    ser_mc_balance_report(
-        &tc_report.balance,
-        &mut tc_report_builder.reborrow().init_balance(),
-    );
+    let balances_len = usize_to_u32(tc_report.balances.len()).unwrap();
+    let mut balances_builder = tc_report_builder.reborrow().init_balances(balances_len);
+    for (index, mc_balance_report) in tc_report.balances.iter().enumerate() {
+        let mut mc_balance_report_builder = balances_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        ser_mc_balance_report(mc_balance_report, &mut mc_balance_report_builder);
+    }
 
     ser_mc_requests_status_report(
         &tc_report.requests_status,
@@ -292,17 +353,39 @@ fn ser_tc_report(tc_report: &TcReport, tc_report_builder: &mut report_capnp::tc_
     tc_report_builder
         .reborrow()
         .set_num_remote_pending_requests(tc_report.num_remote_pending_requests);
+
+    let frozen_credits_len = usize_to_u32(tc_report.frozen_credits.len()).unwrap();
+    let mut frozen_credits_builder = tc_report_builder
+        .reborrow()
+        .init_frozen_credits(frozen_credits_len);
+    for (index, frozen_credits_report) in tc_report.frozen_credits.iter().enumerate() {
+        let mut frozen_credits_report_builder = frozen_credits_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        ser_frozen_credits_report(frozen_credits_report, &mut frozen_credits_report_builder);
+    }
 }
 
 fn deser_tc_report(
     tc_report_reader: &report_capnp::tc_report::Reader,
 ) -> Result<TcReport, SerializeError> {
+    let mut balances = Vec::new();
+    for mc_balance_report_reader in tc_report_reader.get_balances()? {
+        balances.push(deser_mc_balance_report(&mc_balance_report_reader)?);
+    }
+
+    let mut frozen_credits = Vec::new();
+    for frozen_credits_report in tc_report_reader.get_frozen_credits()? {
+        frozen_credits.push(deser_frozen_credits_report(&frozen_credits_report)?);
+    }
+
     Ok(TcReport {
         direction: deser_direction_report(&tc_report_reader.get_direction()?)?,
-        balance: deser_mc_balance_report(&tc_report_reader.get_balance()?)?,
+        balances,
         requests_status: deser_mc_requests_status_report(&tc_report_reader.get_requests_status()?)?,
         num_local_pending_requests: tc_report_reader.get_num_local_pending_requests(),
         num_remote_pending_requests: tc_report_reader.get_num_remote_pending_requests(),
+        frozen_credits,
     })
 }
 
@@ -315,20 +398,29 @@ fn ser_reset_terms_report(
         &mut reset_terms_report_builder.reborrow().init_reset_token(),
     );
 
-    write_custom_int128(
-        reset_terms_report.balance_for_reset,
-        &mut reset_terms_report_builder
+    let balance_for_reset_len = usize_to_u32(reset_terms_report.balance_for_reset.len()).unwrap();
+    let mut balance_for_reset_builder = reset_terms_report_builder
+        .reborrow()
+        .init_balance_for_reset(balance_for_reset_len);
+    for (index, currency_balance) in reset_terms_report.balance_for_reset.iter().enumerate() {
+        let mut currency_balance_builder = balance_for_reset_builder
             .reborrow()
-            .init_balance_for_reset(),
-    );
+            .get(usize_to_u32(index).unwrap());
+        write_currency_balance(currency_balance, &mut currency_balance_builder);
+    }
 }
 
 fn deser_reset_terms_report(
     reset_terms_report_reader: &report_capnp::reset_terms_report::Reader,
 ) -> Result<ResetTermsReport, SerializeError> {
+    let mut balance_for_reset = Vec::new();
+    for currency_balance_reader in reset_terms_report_reader.get_balance_for_reset()? {
+        balance_for_reset.push(read_currency_balance(&currency_balance_reader)?);
+    }
+
     Ok(ResetTermsReport {
         reset_token: read_signature(&reset_terms_report_reader.get_reset_token()?)?,
-        balance_for_reset: read_custom_int128(&reset_terms_report_reader.get_balance_for_reset()?)?,
+        balance_for_reset,
     })
 }
 
@@ -336,12 +428,21 @@ fn ser_channel_inconsistent_report(
     channel_inconsistent_report: &ChannelInconsistentReport,
     channel_inconsistent_report_builder: &mut report_capnp::channel_inconsistent_report::Builder,
 ) {
-    write_custom_int128(
-        channel_inconsistent_report.local_reset_terms_balance,
-        &mut channel_inconsistent_report_builder
+    let local_reset_terms_balance_len =
+        usize_to_u32(channel_inconsistent_report.local_reset_terms_balance.len()).unwrap();
+    let mut local_reset_terms_balance_builder = channel_inconsistent_report_builder
+        .reborrow()
+        .init_local_reset_terms_balance(local_reset_terms_balance_len);
+    for (index, currency_balance) in channel_inconsistent_report
+        .local_reset_terms_balance
+        .iter()
+        .enumerate()
+    {
+        let mut currency_balance_builder = local_reset_terms_balance_builder
             .reborrow()
-            .init_local_reset_terms_balance(),
-    );
+            .get(usize_to_u32(index).unwrap());
+        write_currency_balance(currency_balance, &mut currency_balance_builder);
+    }
 
     let mut opt_remote_reset_terms_builder = channel_inconsistent_report_builder
         .reborrow()
@@ -372,10 +473,15 @@ fn deser_channel_inconsistent_report(
         report_capnp::channel_inconsistent_report::opt_remote_reset_terms::Empty(()) => None,
     };
 
+    let mut local_reset_terms_balance = Vec::new();
+    for currency_balance_reader in
+        channel_inconsistent_report_reader.get_local_reset_terms_balance()?
+    {
+        local_reset_terms_balance.push(read_currency_balance(&currency_balance_reader)?);
+    }
+
     Ok(ChannelInconsistentReport {
-        local_reset_terms_balance: read_custom_int128(
-            &channel_inconsistent_report_reader.get_local_reset_terms_balance()?,
-        )?,
+        local_reset_terms_balance,
         opt_remote_reset_terms,
     })
 }
@@ -441,10 +547,36 @@ fn deser_opt_last_incoming_move_token(
     })
 }
 
+fn ser_opt_debt_policy(
+    opt_debt_policy: &Option<DebtPolicy>,
+    opt_debt_policy_builder: &mut report_capnp::opt_debt_policy::Builder,
+) {
+    match opt_debt_policy {
+        Some(debt_policy) => {
+            let mut debt_policy_builder = opt_debt_policy_builder.reborrow().init_debt_policy();
+            write_debt_policy(debt_policy, &mut debt_policy_builder);
+        }
+        None => {
+            opt_debt_policy_builder.set_empty(());
+        }
+    };
+}
+
+fn deser_opt_debt_policy(
+    opt_debt_policy_reader: &report_capnp::opt_debt_policy::Reader,
+) -> Result<Option<DebtPolicy>, SerializeError> {
+    Ok(match opt_debt_policy_reader.which()? {
+        report_capnp::opt_debt_policy::DebtPolicy(debt_policy_reader) => {
+            Some(read_debt_policy(&debt_policy_reader?)?)
+        }
+        report_capnp::opt_debt_policy::Empty(()) => None,
+    })
+}
+
 fn ser_relays_transition(
     relays_transition: &(
-        ImVec<NamedRelayAddress<NetAddress>>,
-        ImVec<NamedRelayAddress<NetAddress>>,
+        ImVec<ChannelAddress<NetAddress>>,
+        ImVec<ChannelAddress<NetAddress>>,
     ),
     relays_transition_builder: &mut report_capnp::relays_transition::Builder,
 ) {
@@ -454,40 +586,40 @@ fn ser_relays_transition(
         .reborrow()
         .init_last_sent(usize_to_u32(last_sent.len()).unwrap());
 
-    for (index, named_relay_address) in last_sent.iter().enumerate() {
-        let mut named_relay_address_builder = last_sent_builder
+    for (index, channel_address) in last_sent.iter().enumerate() {
+        let mut channel_address_builder = last_sent_builder
             .reborrow()
             .get(usize_to_u32(index).unwrap());
-        write_named_relay_address(named_relay_address, &mut named_relay_address_builder);
+        write_channel_address(channel_address, &mut channel_address_builder);
     }
 
     let mut before_last_sent_builder = relays_transition_builder
         .reborrow()
         .init_before_last_sent(usize_to_u32(before_last_sent.len()).unwrap());
 
-    for (index, named_relay_address) in before_last_sent.iter().enumerate() {
-        let mut named_relay_address_builder = before_last_sent_builder
+    for (index, channel_address) in before_last_sent.iter().enumerate() {
+        let mut channel_address_builder = before_last_sent_builder
             .reborrow()
             .get(usize_to_u32(index).unwrap());
-        write_named_relay_address(named_relay_address, &mut named_relay_address_builder);
+        write_channel_address(channel_address, &mut channel_address_builder);
     }
 }
 
 type RelaysTransitions = (
-    ImVec<NamedRelayAddress<NetAddress>>,
-    ImVec<NamedRelayAddress<NetAddress>>,
+    ImVec<ChannelAddress<NetAddress>>,
+    ImVec<ChannelAddress<NetAddress>>,
 );
 fn deser_relays_transition(
     relays_transition_reader: &report_capnp::relays_transition::Reader,
 ) -> Result<RelaysTransitions, SerializeError> {
     let mut last_sent = ImVec::new();
-    for named_relay_address in relays_transition_reader.get_last_sent()? {
-        last_sent.push_back(read_named_relay_address(&named_relay_address)?);
+    for channel_address in relays_transition_reader.get_last_sent()? {
+        last_sent.push_back(read_channel_address(&channel_address)?);
     }
 
     let mut before_last_sent = ImVec::new();
-    for named_relay_address in relays_transition_reader.get_before_last_sent()? {
-        before_last_sent.push_back(read_named_relay_address(&named_relay_address)?);
+    for channel_address in relays_transition_reader.get_before_last_sent()? {
+        before_last_sent.push_back(read_channel_address(&channel_address)?);
     }
 
     Ok((last_sent, before_last_sent))
@@ -512,11 +644,11 @@ fn ser_sent_local_relays_report(
             let mut last_sent_builder = sent_local_relays_report_builder
                 .reborrow()
                 .init_last_sent(relays_len);
-            for (index, named_relay_address) in last_sent.iter().enumerate() {
-                let mut named_relay_address_builder = last_sent_builder
+            for (index, channel_address) in last_sent.iter().enumerate() {
+                let mut channel_address_builder = last_sent_builder
                     .reborrow()
                     .get(usize_to_u32(index).unwrap());
-                write_named_relay_address(named_relay_address, &mut named_relay_address_builder);
+                write_channel_address(channel_address, &mut channel_address_builder);
             }
         }
     }
@@ -532,14 +664,38 @@ fn deser_sent_local_relays_report(
         }
         report_capnp::sent_local_relays_report::LastSent(last_sent_reader) => {
             let mut last_sent = Vec::new();
-            for named_relay_address in last_sent_reader? {
-                last_sent.push(read_named_relay_address(&named_relay_address)?);
+            for channel_address in last_sent_reader? {
+                last_sent.push(read_channel_address(&channel_address)?);
             }
             SentLocalRelaysReport::LastSent(last_sent.into_iter().collect())
         }
     })
 }
 
+fn ser_currency_max_debt(
+    currency: &Currency,
+    max_debt: u128,
+    currency_max_debt_builder: &mut report_capnp::currency_max_debt::Builder,
+) {
+    write_currency(
+        currency,
+        &mut currency_max_debt_builder.reborrow().init_currency(),
+    );
+    write_custom_u_int128(
+        max_debt,
+        &mut currency_max_debt_builder.reborrow().init_max_debt(),
+    );
+}
+
+fn deser_currency_max_debt(
+    currency_max_debt_reader: &report_capnp::currency_max_debt::Reader,
+) -> Result<(Currency, u128), SerializeError> {
+    Ok((
+        read_currency(&currency_max_debt_reader.get_currency()?)?,
+        read_custom_u_int128(&currency_max_debt_reader.get_max_debt()?)?,
+    ))
+}
+
 fn ser_friend_report(
     friend_report: &FriendReport,
     friend_report_builder: &mut report_capnp::friend_report::Builder,
@@ -553,9 +709,10 @@ fn ser_friend_report(
     let mut relays_builder = friend_report_builder
         .reborrow()
         .init_remote_relays(relays_len);
-    for (index, relay_address) in friend_report.remote_relays.iter().enumerate() {
-        let mut relay_address_builder = relays_builder.reborrow().get(usize_to_u32(index).unwrap());
-        write_relay_address(relay_address, &mut relay_address_builder);
+    for (index, channel_address) in friend_report.remote_relays.iter().enumerate() {
+        let mut channel_address_builder =
+            relays_builder.reborrow().get(usize_to_u32(index).unwrap());
+        write_channel_address(channel_address, &mut channel_address_builder);
     }
 
     ser_sent_local_relays_report(
@@ -580,12 +737,17 @@ fn ser_friend_report(
         &mut friend_report_builder.reborrow().init_channel_status(),
     );
 
-    write_custom_u_int128(
-        friend_report.wanted_remote_max_debt,
-        &mut friend_report_builder
+    let wanted_remote_max_debt_len =
+        usize_to_u32(friend_report.wanted_remote_max_debt.len()).unwrap();
+    let mut wanted_remote_max_debt_builder = friend_report_builder
+        .reborrow()
+        .init_wanted_remote_max_debt(wanted_remote_max_debt_len);
+    for (index, (currency, max_debt)) in friend_report.wanted_remote_max_debt.iter().enumerate() {
+        let mut currency_max_debt_builder = wanted_remote_max_debt_builder
             .reborrow()
-            .init_wanted_remote_max_debt(),
-    );
+            .get(usize_to_u32(index).unwrap());
+        ser_currency_max_debt(currency, *max_debt, &mut currency_max_debt_builder);
+    }
 
     ser_requests_status_report(
         &friend_report.wanted_local_requests_status,
@@ -603,14 +765,35 @@ fn ser_friend_report(
     );
 
     friend_report_builder.set_num_pending_user_requests(friend_report.num_pending_user_requests);
+
+    write_rate(
+        &friend_report.rate,
+        &mut friend_report_builder.reborrow().init_rate(),
+    );
+
+    ser_opt_debt_policy(
+        &friend_report.opt_debt_policy,
+        &mut friend_report_builder.reborrow().init_opt_debt_policy(),
+    );
+
+    ser_settle_status_report(
+        &friend_report.settle_status,
+        &mut friend_report_builder.reborrow().init_settle_status(),
+    );
 }
 
 fn deser_friend_report(
     friend_report_reader: &report_capnp::friend_report::Reader,
 ) -> Result<FriendReport, SerializeError> {
     let mut remote_relays = Vec::new();
-    for relay_address in friend_report_reader.get_remote_relays()? {
-        remote_relays.push(read_relay_address(&relay_address)?);
+    for channel_address in friend_report_reader.get_remote_relays()? {
+        remote_relays.push(read_channel_address(&channel_address)?);
+    }
+
+    let mut wanted_remote_max_debt = ImHashMap::new();
+    for currency_max_debt_reader in friend_report_reader.get_wanted_remote_max_debt()? {
+        let (currency, max_debt) = deser_currency_max_debt(&currency_max_debt_reader)?;
+        wanted_remote_max_debt.insert(currency, max_debt);
     }
 
     Ok(FriendReport {
@@ -624,9 +807,7 @@ fn deser_friend_report(
         )?,
         liveness: deser_friend_liveness_report(&friend_report_reader.get_liveness()?)?,
         channel_status: deser_channel_status_report(&friend_report_reader.get_channel_status()?)?,
-        wanted_remote_max_debt: read_custom_u_int128(
-            &friend_report_reader.get_wanted_remote_max_debt()?,
-        )?,
+        wanted_remote_max_debt,
         wanted_local_requests_status: deser_requests_status_report(
             &friend_report_reader.get_wanted_local_requests_status()?,
         )?,
@@ -634,6 +815,9 @@ fn deser_friend_report(
         num_pending_responses: friend_report_reader.get_num_pending_responses(),
         status: deser_friend_status_report(&friend_report_reader.get_status()?)?,
         num_pending_user_requests: friend_report_reader.get_num_pending_user_requests(),
+        rate: read_rate(&friend_report_reader.get_rate()?)?,
+        opt_debt_policy: deser_opt_debt_policy(&friend_report_reader.get_opt_debt_policy()?)?,
+        settle_status: deser_settle_status_report(&friend_report_reader.get_settle_status()?)?,
     })
 }
 
@@ -678,6 +862,11 @@ fn ser_funder_report(
         write_named_relay_address(named_relay_address, &mut named_relay_address_builder);
     }
 
+    write_opt_net_address(
+        &funder_report.opt_direct_address,
+        &mut funder_report_builder.reborrow().init_opt_direct_address(),
+    );
+
     let friends_len = usize_to_u32(funder_report.friends.len()).unwrap();
     let mut friends_builder = funder_report_builder.reborrow().init_friends(friends_len);
     for (index, pk_friend) in funder_report.friends.iter().enumerate() {
@@ -686,6 +875,56 @@ fn ser_funder_report(
     }
 
     funder_report_builder.set_num_ready_receipts(funder_report.num_ready_receipts);
+
+    write_freeze_policy(
+        &funder_report.freeze_policy,
+        &mut funder_report_builder.reborrow().init_freeze_policy(),
+    );
+
+    funder_report_builder.set_hold_incoming_payments(funder_report.hold_incoming_payments);
+
+    let held_payments_len = usize_to_u32(funder_report.held_payments.len()).unwrap();
+    let mut held_payments_builder = funder_report_builder
+        .reborrow()
+        .init_held_payments(held_payments_len);
+    for (index, held_payment_report) in funder_report.held_payments.iter().enumerate() {
+        let mut held_payment_report_builder = held_payments_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        ser_held_payment_report(held_payment_report, &mut held_payment_report_builder);
+    }
+}
+
+fn ser_held_payment_report(
+    held_payment_report: &HeldPaymentReport,
+    held_payment_report_builder: &mut report_capnp::held_payment_report::Builder,
+) {
+    write_invoice_id(
+        &held_payment_report.invoice_id,
+        &mut held_payment_report_builder.reborrow().init_invoice_id(),
+    );
+    write_currency(
+        &held_payment_report.currency,
+        &mut held_payment_report_builder.reborrow().init_currency(),
+    );
+    write_custom_u_int128(
+        held_payment_report.total_dest_payment,
+        &mut held_payment_report_builder
+            .reborrow()
+            .init_total_dest_payment(),
+    );
+}
+
+fn deser_held_payment_report(
+    held_payment_report_reader: &report_capnp::held_payment_report::Reader,
+) -> Result<HeldPaymentReport, SerializeError> {
+    Ok(HeldPaymentReport {
+        invoice_id: read_invoice_id(&held_payment_report_reader.get_invoice_id()?)?,
+        currency: read_currency(&held_payment_report_reader.get_currency()?)?,
+        total_dest_payment: read_custom_u_int128(
+            &held_payment_report_reader.get_total_dest_payment()?,
+        )?,
+    })
 }
 
 fn deser_funder_report(
@@ -702,11 +941,20 @@ fn deser_funder_report(
         friends.insert(friend_public_key, friend_report);
     }
 
+    let mut held_payments = ImVec::new();
+    for held_payment_report in funder_report_reader.get_held_payments()? {
+        held_payments.push_back(deser_held_payment_report(&held_payment_report)?);
+    }
+
     Ok(FunderReport {
         local_public_key: read_public_key(&funder_report_reader.get_local_public_key()?)?,
         relays: named_relays.into_iter().collect(),
+        opt_direct_address: read_opt_net_address(&funder_report_reader.get_opt_direct_address()?)?,
         friends,
         num_ready_receipts: funder_report_reader.get_num_ready_receipts(),
+        freeze_policy: read_freeze_policy(&funder_report_reader.get_freeze_policy()?)?,
+        hold_incoming_payments: funder_report_reader.get_hold_incoming_payments(),
+        held_payments,
     })
 }
 
@@ -725,15 +973,22 @@ fn ser_add_friend_report(
 
     let relays_len = usize_to_u32(add_friend_report.relays.len()).unwrap();
     let mut relays_builder = add_friend_report_builder.reborrow().init_relays(relays_len);
-    for (index, relay_address) in add_friend_report.relays.iter().enumerate() {
-        let mut relay_address_builder = relays_builder.reborrow().get(usize_to_u32(index).unwrap());
-        write_relay_address(relay_address, &mut relay_address_builder);
+    for (index, channel_address) in add_friend_report.relays.iter().enumerate() {
+        let mut channel_address_builder =
+            relays_builder.reborrow().get(usize_to_u32(index).unwrap());
+        write_channel_address(channel_address, &mut channel_address_builder);
     }
 
-    write_custom_int128(
-        add_friend_report.balance,
-        &mut add_friend_report_builder.reborrow().init_balance(),
-    );
+    let balances_len = usize_to_u32(add_friend_report.balances.len()).unwrap();
+    let mut balances_builder = add_friend_report_builder
+        .reborrow()
+        .init_balances(balances_len);
+    for (index, currency_balance) in add_friend_report.balances.iter().enumerate() {
+        let mut currency_balance_builder = balances_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        write_currency_balance(currency_balance, &mut currency_balance_builder);
+    }
 
     ser_opt_last_incoming_move_token(
         &add_friend_report.opt_last_incoming_move_token,
@@ -752,15 +1007,20 @@ fn deser_add_friend_report(
     add_friend_report_reader: &report_capnp::add_friend_report::Reader,
 ) -> Result<AddFriendReport, SerializeError> {
     let mut relays = Vec::new();
-    for relay_address in add_friend_report_reader.get_relays()? {
-        relays.push(read_relay_address(&relay_address)?);
+    for channel_address in add_friend_report_reader.get_relays()? {
+        relays.push(read_channel_address(&channel_address)?);
+    }
+
+    let mut balances = Vec::new();
+    for currency_balance_reader in add_friend_report_reader.get_balances()? {
+        balances.push(read_currency_balance(&currency_balance_reader)?);
     }
 
     Ok(AddFriendReport {
         friend_public_key: read_public_key(&add_friend_report_reader.get_friend_public_key()?)?,
         name: add_friend_report_reader.get_name()?.to_owned(),
         relays,
-        balance: read_custom_int128(&add_friend_report_reader.get_balance()?)?,
+        balances,
         opt_last_incoming_move_token: deser_opt_last_incoming_move_token(
             &add_friend_report_reader.get_opt_last_incoming_move_token()?,
         )?,
@@ -780,10 +1040,10 @@ fn ser_friend_report_mutation(
             let mut relays_builder = friend_report_mutation_builder
                 .reborrow()
                 .init_set_remote_relays(relays_len);
-            for (index, relay_address) in relays.iter().enumerate() {
-                let mut relay_address_builder =
+            for (index, channel_address) in relays.iter().enumerate() {
+                let mut channel_address_builder =
                     relays_builder.reborrow().get(usize_to_u32(index).unwrap());
-                write_relay_address(relay_address, &mut relay_address_builder);
+                write_channel_address(channel_address, &mut channel_address_builder);
             }
         }
         FriendReportMutation::SetName(name) => {
@@ -803,8 +1063,9 @@ fn ser_friend_report_mutation(
                 .reborrow()
                 .init_set_channel_status(),
         ),
-        FriendReportMutation::SetWantedRemoteMaxDebt(wanted_remote_max_debt) => {
-            write_custom_u_int128(
+        FriendReportMutation::SetWantedRemoteMaxDebt((currency, wanted_remote_max_debt)) => {
+            ser_currency_max_debt(
+                currency,
                 *wanted_remote_max_debt,
                 &mut friend_report_mutation_builder
                     .reborrow()
@@ -852,6 +1113,22 @@ fn ser_friend_report_mutation(
                 .reborrow()
                 .init_set_liveness(),
         ),
+        FriendReportMutation::SetRate(rate) => write_rate(
+            rate,
+            &mut friend_report_mutation_builder.reborrow().init_set_rate(),
+        ),
+        FriendReportMutation::SetDebtPolicy(opt_debt_policy) => ser_opt_debt_policy(
+            opt_debt_policy,
+            &mut friend_report_mutation_builder
+                .reborrow()
+                .init_set_debt_policy(),
+        ),
+        FriendReportMutation::SetSettleStatus(settle_status_report) => ser_settle_status_report(
+            settle_status_report,
+            &mut friend_report_mutation_builder
+                .reborrow()
+                .init_set_settle_status(),
+        ),
     };
 }
 
@@ -861,8 +1138,8 @@ fn deser_friend_report_mutation(
     Ok(match friend_report_mutation.which()? {
         report_capnp::friend_report_mutation::SetRemoteRelays(relays_reader) => {
             let mut relays = Vec::new();
-            for relay_address in relays_reader? {
-                relays.push(read_relay_address(&relay_address)?);
+            for channel_address in relays_reader? {
+                relays.push(read_channel_address(&channel_address)?);
             }
             FriendReportMutation::SetRemoteRelays(relays)
         }
@@ -881,7 +1158,7 @@ fn deser_friend_report_mutation(
         }
         report_capnp::friend_report_mutation::SetWantedRemoteMaxDebt(
             wanted_remote_max_debt_reader,
-        ) => FriendReportMutation::SetWantedRemoteMaxDebt(read_custom_u_int128(
+        ) => FriendReportMutation::SetWantedRemoteMaxDebt(deser_currency_max_debt(
             &wanted_remote_max_debt_reader?,
         )?),
         report_capnp::friend_report_mutation::SetWantedLocalRequestsStatus(
@@ -913,6 +1190,17 @@ fn deser_friend_report_mutation(
                 &friend_liveness_report_reader?,
             )?)
         }
+        report_capnp::friend_report_mutation::SetRate(rate_reader) => {
+            FriendReportMutation::SetRate(read_rate(&rate_reader?)?)
+        }
+        report_capnp::friend_report_mutation::SetDebtPolicy(opt_debt_policy_reader) => {
+            FriendReportMutation::SetDebtPolicy(deser_opt_debt_policy(&opt_debt_policy_reader?)?)
+        }
+        report_capnp::friend_report_mutation::SetSettleStatus(settle_status_report_reader) => {
+            FriendReportMutation::SetSettleStatus(deser_settle_status_report(
+                &settle_status_report_reader?,
+            )?)
+        }
     })
 }
 
@@ -966,6 +1254,14 @@ fn ser_funder_report_mutation(
                     .init_remove_relay(),
             );
         }
+        FunderReportMutation::SetDirectAddress(opt_direct_address) => {
+            write_opt_net_address(
+                opt_direct_address,
+                &mut funder_report_mutation_builder
+                    .reborrow()
+                    .init_set_direct_address(),
+            );
+        }
         FunderReportMutation::AddFriend(add_friend_report) => {
             ser_add_friend_report(
                 add_friend_report,
@@ -993,6 +1289,35 @@ fn ser_funder_report_mutation(
                 .reborrow()
                 .set_set_num_ready_receipts(*num_ready_receipts);
         }
+        FunderReportMutation::SetFreezePolicy(freeze_policy) => {
+            write_freeze_policy(
+                freeze_policy,
+                &mut funder_report_mutation_builder
+                    .reborrow()
+                    .init_set_freeze_policy(),
+            );
+        }
+        FunderReportMutation::SetHoldIncomingPayments(hold_incoming_payments) => {
+            funder_report_mutation_builder
+                .reborrow()
+                .set_set_hold_incoming_payments(*hold_incoming_payments);
+        }
+        FunderReportMutation::AddHeldPayment(held_payment_report) => {
+            ser_held_payment_report(
+                held_payment_report,
+                &mut funder_report_mutation_builder
+                    .reborrow()
+                    .init_add_held_payment(),
+            );
+        }
+        FunderReportMutation::RemoveHeldPayment(invoice_id) => {
+            write_invoice_id(
+                invoice_id,
+                &mut funder_report_mutation_builder
+                    .reborrow()
+                    .init_remove_held_payment(),
+            );
+        }
     }
 }
 
@@ -1006,6 +1331,9 @@ fn deser_funder_report_mutation(
         report_capnp::funder_report_mutation::RemoveRelay(public_key_reader) => {
             FunderReportMutation::RemoveRelay(read_public_key(&public_key_reader?)?)
         }
+        report_capnp::funder_report_mutation::SetDirectAddress(opt_net_address_reader) => {
+            FunderReportMutation::SetDirectAddress(read_opt_net_address(&opt_net_address_reader?)?)
+        }
         report_capnp::funder_report_mutation::AddFriend(add_friend_report_reader) => {
             FunderReportMutation::AddFriend(deser_add_friend_report(&add_friend_report_reader?)?)
         }
@@ -1020,6 +1348,20 @@ fn deser_funder_report_mutation(
         report_capnp::funder_report_mutation::SetNumReadyReceipts(num_ready_receipts) => {
             FunderReportMutation::SetNumReadyReceipts(num_ready_receipts)
         }
+        report_capnp::funder_report_mutation::SetFreezePolicy(freeze_policy_reader) => {
+            FunderReportMutation::SetFreezePolicy(read_freeze_policy(&freeze_policy_reader?)?)
+        }
+        report_capnp::funder_report_mutation::SetHoldIncomingPayments(hold_incoming_payments) => {
+            FunderReportMutation::SetHoldIncomingPayments(hold_incoming_payments)
+        }
+        report_capnp::funder_report_mutation::AddHeldPayment(held_payment_report_reader) => {
+            FunderReportMutation::AddHeldPayment(deser_held_payment_report(
+                &held_payment_report_reader?,
+            )?)
+        }
+        report_capnp::funder_report_mutation::RemoveHeldPayment(invoice_id_reader) => {
+            FunderReportMutation::RemoveHeldPayment(read_invoice_id(&invoice_id_reader?)?)
+        }
     })
 }
 
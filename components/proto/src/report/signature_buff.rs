@@ -1,5 +1,7 @@
 use byteorder::{BigEndian, WriteBytesExt};
 
+use common::canonical_serialize::CanonicalSerialize;
+
 use crypto::hash::sha_512_256;
 use crypto::identity::{verify_signature, PublicKey};
 
@@ -22,15 +24,7 @@ fn move_token_hashed_report_signature_buff(
     sig_buffer
         .write_u128::<BigEndian>(move_token_hashed_report.move_token_counter)
         .unwrap();
-    sig_buffer
-        .write_i128::<BigEndian>(move_token_hashed_report.balance)
-        .unwrap();
-    sig_buffer
-        .write_u128::<BigEndian>(move_token_hashed_report.local_pending_debt)
-        .unwrap();
-    sig_buffer
-        .write_u128::<BigEndian>(move_token_hashed_report.remote_pending_debt)
-        .unwrap();
+    sig_buffer.extend_from_slice(&move_token_hashed_report.balances.canonical_serialize());
     sig_buffer.extend_from_slice(&move_token_hashed_report.rand_nonce);
 
     sig_buffer
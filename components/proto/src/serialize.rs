@@ -1,3 +1,4 @@
+use crate::funder::messages::CurrencyError;
 use crate::net::messages::NetAddressError;
 use capnp;
 use std::io;
@@ -8,4 +9,5 @@ pub enum SerializeError {
     NotInSchema(capnp::NotInSchema),
     IoError(io::Error),
     NetAddressError(NetAddressError),
+    CurrencyError(CurrencyError),
 }
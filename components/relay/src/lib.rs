@@ -24,4 +24,8 @@ mod server;
 
 pub use self::client::client_connector::ClientConnector;
 pub use self::client::client_listener::ClientListener;
-pub use self::server::net_server::{net_relay_server, NetRelayServerError};
+pub use self::server::net_server::{net_relay_server, NetRelayServerError, RelayLimits};
+pub use self::server::peers::{PeerEvent, PeerWatcherError};
+pub use self::server::stats::{
+    log_relay_stats, ListenerStats, RejectedConns, RelayStats, TunnelStats,
+};
@@ -1,9 +1,15 @@
-#![allow(unused)]
 use core::pin::Pin;
-use futures::channel::oneshot;
-use futures::task::Waker;
-use futures::{Poll, Sink, Stream, StreamExt};
+use std::collections::{HashMap, HashSet};
 use std::marker::Unpin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures::channel::{mpsc, oneshot};
+use futures::task::{Spawn, SpawnExt, Waker};
+use futures::{future, stream, Poll, SinkExt, Stream, StreamExt};
+
+use common::conn::ConnPairVec;
+use common::select_streams::{select_streams, BoxStream};
 
 use crypto::identity::PublicKey;
 
@@ -41,12 +47,271 @@ impl<T> Drop for Tracked<T> {
     }
 }
 
-async fn conn_limiter<M, K, KE, T>(incoming_conns: T, max_conns: usize) -> Result<(), ()>
+#[derive(Debug)]
+pub enum ConnLimiterError {
+    SpawnError,
+    SendConnError,
+}
+
+enum ConnLimiterEvent {
+    IncomingConn((PublicKey, ConnPairVec)),
+    IncomingConnsClosed,
+    ConnClosed(PublicKey),
+}
+
+/// Limit the amount of open connections: At most `max_conns` connections in total, and at most
+/// `max_conns_per_public_key` connections from the same public key.
+/// Connections from `unlimited_public_keys` are only limited by `max_conns`. Those are peer
+/// relays, forwarding connections on behalf of many different nodes.
+/// Connections beyond those limits are closed immediately, and counted in `too_many_conns`.
+/// Other connections are forwarded to `conns_sender`.
+pub async fn conn_limiter<T, S>(
+    incoming_conns: T,
+    mut conns_sender: mpsc::Sender<(PublicKey, ConnPairVec)>,
+    max_conns: usize,
+    max_conns_per_public_key: usize,
+    unlimited_public_keys: HashSet<PublicKey>,
+    too_many_conns: Arc<AtomicUsize>,
+    mut spawner: S,
+) -> Result<(), ConnLimiterError>
 where
-    T: Stream<Item = (M, K, PublicKey)>,
-    M: Stream<Item = Vec<u8>>,
-    K: Sink<SinkItem = Vec<u8>, SinkError = KE>,
+    T: Stream<Item = (PublicKey, ConnPairVec)> + Unpin + Send,
+    S: Spawn,
 {
-    let mut cur_conns: usize = 0;
-    unimplemented!();
+    let incoming_conns = incoming_conns
+        .map(ConnLimiterEvent::IncomingConn)
+        .chain(stream::once(future::ready(
+            ConnLimiterEvent::IncomingConnsClosed,
+        )));
+
+    let (event_sender, event_receiver) = mpsc::channel::<ConnLimiterEvent>(0);
+
+    let mut events = select_streams![incoming_conns, event_receiver];
+
+    let mut num_conns: usize = 0;
+    let mut public_key_conns: HashMap<PublicKey, usize> = HashMap::new();
+
+    while let Some(event) = await!(events.next()) {
+        match event {
+            ConnLimiterEvent::IncomingConn((public_key, (sender, receiver))) => {
+                let num_public_key_conns = public_key_conns.get(&public_key).cloned().unwrap_or(0);
+                if num_conns >= max_conns
+                    || (num_public_key_conns >= max_conns_per_public_key
+                        && !unlimited_public_keys.contains(&public_key))
+                {
+                    warn!(
+                        "conn_limiter(): Too many connections. Closing connection from {:?}",
+                        public_key
+                    );
+                    too_many_conns.fetch_add(1, Ordering::Relaxed);
+                    // The connection is closed when dropped:
+                    continue;
+                }
+                num_conns += 1;
+                *public_key_conns.entry(public_key.clone()).or_insert(0) += 1;
+
+                // We get notified when the incoming side of the connection is closed:
+                let (drop_sender, drop_receiver) = oneshot::channel();
+                let mut tracked_receiver = Tracked::new(receiver, drop_sender);
+                let (mut user_sender, user_receiver) = mpsc::channel(0);
+                spawner
+                    .spawn(
+                        async move {
+                            let _ = await!(user_sender.send_all(&mut tracked_receiver));
+                        },
+                    )
+                    .map_err(|_| ConnLimiterError::SpawnError)?;
+
+                let mut c_event_sender = event_sender.clone();
+                let c_public_key = public_key.clone();
+                spawner
+                    .spawn(
+                        async move {
+                            let _ = await!(drop_receiver);
+                            let _ = await!(
+                                c_event_sender.send(ConnLimiterEvent::ConnClosed(c_public_key))
+                            );
+                        },
+                    )
+                    .map_err(|_| ConnLimiterError::SpawnError)?;
+
+                await!(conns_sender.send((public_key, (sender, user_receiver))))
+                    .map_err(|_| ConnLimiterError::SendConnError)?;
+            }
+            ConnLimiterEvent::IncomingConnsClosed => break,
+            ConnLimiterEvent::ConnClosed(public_key) => {
+                num_conns = num_conns.checked_sub(1).unwrap();
+                let num_public_key_conns = public_key_conns.get_mut(&public_key).unwrap();
+                *num_public_key_conns = num_public_key_conns.checked_sub(1).unwrap();
+                if *num_public_key_conns == 0 {
+                    public_key_conns.remove(&public_key);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::ThreadPool;
+    use futures::{FutureExt, TryFutureExt};
+
+    use crypto::identity::PUBLIC_KEY_LEN;
+
+    async fn task_conn_limiter_basic(mut spawner: impl Spawn + Clone + Send + 'static) {
+        let (mut incoming_sender, incoming_conns) = mpsc::channel(0);
+        let (conns_sender, mut outgoing_conns) = mpsc::channel(0);
+
+        let too_many_conns = Arc::new(AtomicUsize::new(0));
+        let limiter_fut = conn_limiter(
+            incoming_conns,
+            conns_sender,
+            3,
+            2,
+            HashSet::new(),
+            too_many_conns.clone(),
+            spawner.clone(),
+        )
+        .map_err(|e| error!("conn_limiter() error: {:?}", e))
+        .map(|_| ());
+        spawner.spawn(limiter_fut).unwrap();
+
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let pk_c = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+
+        // Two connections from a are accepted:
+        let mut a_remotes = Vec::new();
+        for _ in 0..2 {
+            let (local_sender, remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+            let (remote_sender, local_receiver) = mpsc::channel::<Vec<u8>>(0);
+            await!(incoming_sender.send((pk_a.clone(), (local_sender, local_receiver)))).unwrap();
+            let (public_key, conn_pair) = await!(outgoing_conns.next()).unwrap();
+            assert_eq!(public_key, pk_a);
+            a_remotes.push((remote_sender, remote_receiver, conn_pair));
+        }
+
+        // A third connection from a is closed:
+        let (local_sender, mut remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (_remote_sender, local_receiver) = mpsc::channel::<Vec<u8>>(0);
+        await!(incoming_sender.send((pk_a.clone(), (local_sender, local_receiver)))).unwrap();
+        assert!(await!(remote_receiver.next()).is_none());
+
+        // A connection from b is accepted:
+        let (local_sender, _b_remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (mut b_remote_sender, local_receiver) = mpsc::channel::<Vec<u8>>(0);
+        await!(incoming_sender.send((pk_b.clone(), (local_sender, local_receiver)))).unwrap();
+        let (public_key, (_b_sender, mut b_receiver)) = await!(outgoing_conns.next()).unwrap();
+        assert_eq!(public_key, pk_b);
+
+        // Messages go through:
+        await!(b_remote_sender.send(vec![1, 2, 3])).unwrap();
+        assert_eq!(await!(b_receiver.next()).unwrap(), vec![1, 2, 3]);
+
+        // We reached the maximum amount of connections. A connection from c is closed:
+        let (local_sender, mut remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (_remote_sender, local_receiver) = mpsc::channel::<Vec<u8>>(0);
+        await!(incoming_sender.send((pk_c.clone(), (local_sender, local_receiver)))).unwrap();
+        assert!(await!(remote_receiver.next()).is_none());
+        assert_eq!(too_many_conns.load(Ordering::Relaxed), 2);
+
+        // Close one of the connections from a:
+        let (remote_sender, _remote_receiver, (_sender, mut receiver)) = a_remotes.pop().unwrap();
+        drop(remote_sender);
+        assert!(await!(receiver.next()).is_none());
+
+        // A connection from c will be accepted after conn_limiter notices that the connection
+        // was closed. This happens asynchronously, therefore we might need a few attempts:
+        loop {
+            let (local_sender, remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+            let (_remote_sender, local_receiver) = mpsc::channel::<Vec<u8>>(0);
+            await!(incoming_sender.send((pk_c.clone(), (local_sender, local_receiver)))).unwrap();
+
+            // Either we get the connection, or it is closed:
+            let mut outcomes = (&mut outgoing_conns)
+                .map(|(public_key, _conn_pair)| Some(public_key))
+                .select(
+                    remote_receiver
+                        .map(|_| None)
+                        .chain(stream::once(future::ready(None))),
+                );
+            if let Some(public_key) = await!(outcomes.next()).unwrap() {
+                assert_eq!(public_key, pk_c);
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_conn_limiter_basic() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_conn_limiter_basic(thread_pool.clone()));
+    }
+
+    async fn task_conn_limiter_unlimited_public_keys(
+        mut spawner: impl Spawn + Clone + Send + 'static,
+    ) {
+        let (mut incoming_sender, incoming_conns) = mpsc::channel(0);
+        let (conns_sender, mut outgoing_conns) = mpsc::channel(0);
+
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_peer = PublicKey::from(&[0xee; PUBLIC_KEY_LEN]);
+
+        let too_many_conns = Arc::new(AtomicUsize::new(0));
+        let limiter_fut = conn_limiter(
+            incoming_conns,
+            conns_sender,
+            7,
+            2,
+            vec![pk_peer.clone()].into_iter().collect(),
+            too_many_conns.clone(),
+            spawner.clone(),
+        )
+        .map_err(|e| error!("conn_limiter() error: {:?}", e))
+        .map(|_| ());
+        spawner.spawn(limiter_fut).unwrap();
+
+        // The peer relay forwards more connections than allowed for a single public key:
+        let mut remotes = Vec::new();
+        for _ in 0..5 {
+            let (local_sender, remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+            let (remote_sender, local_receiver) = mpsc::channel::<Vec<u8>>(0);
+            await!(incoming_sender.send((pk_peer.clone(), (local_sender, local_receiver))))
+                .unwrap();
+            let (public_key, conn_pair) = await!(outgoing_conns.next()).unwrap();
+            assert_eq!(public_key, pk_peer);
+            remotes.push((remote_sender, remote_receiver, conn_pair));
+        }
+
+        // Other public keys are still limited:
+        for _ in 0..2 {
+            let (local_sender, remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+            let (remote_sender, local_receiver) = mpsc::channel::<Vec<u8>>(0);
+            await!(incoming_sender.send((pk_a.clone(), (local_sender, local_receiver)))).unwrap();
+            let (public_key, conn_pair) = await!(outgoing_conns.next()).unwrap();
+            assert_eq!(public_key, pk_a);
+            remotes.push((remote_sender, remote_receiver, conn_pair));
+        }
+        let (local_sender, mut remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (_remote_sender, local_receiver) = mpsc::channel::<Vec<u8>>(0);
+        await!(incoming_sender.send((pk_a.clone(), (local_sender, local_receiver)))).unwrap();
+        assert!(await!(remote_receiver.next()).is_none());
+
+        // The peer relay is still limited by the total amount of connections:
+        let (local_sender, mut remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (_remote_sender, local_receiver) = mpsc::channel::<Vec<u8>>(0);
+        await!(incoming_sender.send((pk_peer.clone(), (local_sender, local_receiver)))).unwrap();
+        assert!(await!(remote_receiver.next()).is_none());
+
+        assert_eq!(too_many_conns.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_conn_limiter_unlimited_public_keys() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_conn_limiter_unlimited_public_keys(thread_pool.clone()));
+    }
 }
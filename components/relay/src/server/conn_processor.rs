@@ -10,11 +10,15 @@ use timer::utils::future_timeout;
 use timer::TimerClient;
 
 use super::types::{
-    IncomingAccept, IncomingConn, IncomingConnInner, IncomingConnect, IncomingListen,
+    IncomingAccept, IncomingConn, IncomingConnInner, IncomingConnect, IncomingForward,
+    IncomingListen, IncomingPeer,
+};
+use proto::relay::messages::{
+    IncomingConnection, InitConnection, ListenerUpdate, RejectConnection,
 };
-use proto::relay::messages::{IncomingConnection, InitConnection, RejectConnection};
 use proto::relay::serialize::{
     deserialize_init_connection, deserialize_reject_connection, serialize_incoming_connection,
+    serialize_listener_update,
 };
 
 async fn dispatch_conn<FT>(
@@ -31,6 +35,7 @@ async fn dispatch_conn<FT>(
         impl Sink<SinkItem = Vec<u8>, SinkError = ()> + Unpin,
         impl Stream<Item = Vec<u8>> + Unpin,
         impl Sink<SinkItem = Vec<u8>, SinkError = ()> + Unpin,
+        impl Sink<SinkItem = ListenerUpdate, SinkError = ()> + Unpin,
     >,
 >
 where
@@ -59,6 +64,17 @@ where
                 connect_public_key,
             })
         }
+        InitConnection::Peer => IncomingConnInner::Peer(IncomingPeer {
+            receiver,
+            sender: sender.with(|msg| future::ready(Ok(serialize_listener_update(&msg)))),
+        }),
+        InitConnection::Forward(forward_connection) => {
+            IncomingConnInner::Forward(IncomingForward {
+                receiver,
+                sender,
+                forward_connection,
+            })
+        }
     };
 
     Some(IncomingConn { public_key, inner })
@@ -79,6 +95,7 @@ async fn process_conn<FT>(
         impl Sink<SinkItem = Vec<u8>, SinkError = ()> + Unpin,
         impl Stream<Item = Vec<u8>> + Unpin,
         impl Sink<SinkItem = Vec<u8>, SinkError = ()> + Unpin,
+        impl Sink<SinkItem = ListenerUpdate, SinkError = ()> + Unpin,
     >,
 >
 where
@@ -133,6 +150,7 @@ pub fn conn_processor<T, FT>(
         impl Sink<SinkItem = Vec<u8>, SinkError = ()>,
         impl Stream<Item = Vec<u8>>,
         impl Sink<SinkItem = Vec<u8>, SinkError = ()>,
+        impl Sink<SinkItem = ListenerUpdate, SinkError = ()>,
     >,
 >
 where
@@ -167,6 +185,7 @@ mod tests {
     use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
     use timer::create_timer_incoming;
 
+    use proto::relay::messages::ForwardConnection;
     use proto::relay::serialize::serialize_init_connection;
 
     async fn task_dispatch_conn_basic(spawner: impl Spawn + Clone) {
@@ -239,6 +258,32 @@ mod tests {
             }
             _ => panic!("Wrong IncomingConnInner"),
         };
+
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>(0);
+        let forward_connection = ForwardConnection {
+            init_public_key: PublicKey::from(&[0x44; PUBLIC_KEY_LEN]),
+            connect_public_key: PublicKey::from(&[0x33; PUBLIC_KEY_LEN]),
+        };
+        let first_msg = InitConnection::Forward(forward_connection.clone());
+        let ser_first_msg = serialize_init_connection(&first_msg);
+        let public_key = PublicKey::from(&[0x77; PUBLIC_KEY_LEN]);
+        let keepalive_transform = FuncFutTransform::new(|x| Box::pin(future::ready(x)));
+        let incoming_conn = await!(dispatch_conn(
+            sender,
+            receiver,
+            public_key.clone(),
+            ser_first_msg,
+            keepalive_transform
+        ))
+        .unwrap();
+
+        assert_eq!(incoming_conn.public_key, public_key);
+        match incoming_conn.inner {
+            IncomingConnInner::Forward(incoming_forward) => {
+                assert_eq!(incoming_forward.forward_connection, forward_connection)
+            }
+            _ => panic!("Wrong IncomingConnInner"),
+        };
     }
 
     #[test]
@@ -1,5 +1,7 @@
 mod conn_limiter;
 mod conn_processor;
 pub mod net_server;
+pub mod peers;
 mod server;
+pub mod stats;
 mod types;
@@ -1,15 +1,20 @@
+use std::collections::{HashMap, HashSet};
 use std::marker::Unpin;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
 
 use futures::channel::mpsc;
 use futures::task::{Spawn, SpawnExt};
-use futures::{FutureExt, Stream, StreamExt, TryFutureExt};
+use futures::{FutureExt, SinkExt, Stream, StreamExt, TryFutureExt};
 
 use derive_more::*;
 
-use common::conn::{BoxFuture, ConnPairVec, FutTransform};
+use common::conn::{BoxFuture, ConnPairVec, FuncFutTransform, FutTransform};
 use common::transform_pool::transform_pool_loop;
 
 use proto::consts::{CONN_TIMEOUT_TICKS, KEEPALIVE_TICKS, PROTOCOL_VERSION, TICKS_TO_REKEY};
+use proto::relay::messages::InitConnection;
+use proto::relay::serialize::serialize_init_connection;
 
 use crypto::crypto_rand::CryptoRandom;
 use crypto::identity::PublicKey;
@@ -21,9 +26,13 @@ use timer::TimerClient;
 use secure_channel::SecureChannel;
 use version::VersionPrefix;
 
+use super::conn_limiter::conn_limiter;
 use super::conn_processor::conn_processor;
+use super::peers::{peer_watcher, PeerEvent};
 use super::server::relay_server_loop;
 pub use super::server::RelayServerError;
+use super::stats::RelayStats;
+pub use super::types::RelayLimits;
 
 /// A relay server loop. Incoming connections should contain both (sender, receiver) and a
 /// public_key of the remote side (Should be obtained after authentication).
@@ -32,16 +41,43 @@ pub use super::server::RelayServerError;
 /// its purpose.
 /// `keepalive_ticks` is the amount of time we are willing to let the remote side to be idle before
 /// we disconnect. It is also used to timeout open half tunnels that were not claimed.
-async fn relay_server<IC, S>(
+/// If `opt_allowed_nodes` is not None, only the nodes in this set may listen or connect. Updates
+/// to this set are received through `incoming_allowed_nodes`.
+/// Relays in `peer_relays` may subscribe to our listeners and forward connections to them. The
+/// nodes listening on peer relays are received through `incoming_peer_events`, and connections
+/// to peer relays are opened using `peer_connector`.
+/// `max_half_tunnels` is the maximum amount of connections waiting to be accepted by a single
+/// listener.
+/// `max_tunnel_bytes_per_tick` is the maximum amount of bytes a tunnel may forward in every
+/// direction, per tick.
+/// Every `stats_ticks` ticks, a report of the relay server's state is sent through
+/// `stats_sender`.
+async fn relay_server<IC, AN, PE, PC, S>(
     incoming_conns: IC,
+    opt_allowed_nodes: Option<HashSet<PublicKey>>,
+    incoming_allowed_nodes: AN,
+    peer_relays: HashSet<PublicKey>,
+    incoming_peer_events: PE,
+    peer_connector: PC,
     timer_client: TimerClient,
     conn_timeout_ticks: usize,
     keepalive_ticks: usize,
+    max_half_tunnels: usize,
+    max_tunnel_bytes_per_tick: usize,
+    stats_ticks: usize,
+    too_many_conns: Arc<AtomicUsize>,
+    stats_sender: mpsc::Sender<RelayStats>,
     spawner: S,
 ) -> Result<(), RelayServerError>
 where
     S: Spawn + Clone + Send + 'static,
     IC: Stream<Item = (PublicKey, ConnPairVec)> + Unpin + Send + 'static,
+    AN: Stream<Item = HashSet<PublicKey>> + Unpin + Send,
+    PE: Stream<Item = PeerEvent> + Unpin + Send,
+    PC: FutTransform<Input = (PublicKey, InitConnection), Output = Option<ConnPairVec>>
+        + Clone
+        + Send
+        + 'static,
 {
     let keepalive_transform =
         KeepAliveChannel::new(timer_client.clone(), keepalive_ticks, spawner.clone());
@@ -65,7 +101,17 @@ where
     await!(relay_server_loop(
         timer_client,
         processed_conns,
+        opt_allowed_nodes,
+        incoming_allowed_nodes,
+        peer_relays,
+        incoming_peer_events,
+        peer_connector,
         half_tunnel_ticks,
+        max_half_tunnels,
+        max_tunnel_bytes_per_tick,
+        stats_ticks,
+        too_many_conns,
+        stats_sender,
         spawner
     ))
 }
@@ -74,6 +120,7 @@ where
 pub enum NetRelayServerError {
     RelayServerError(RelayServerError),
     SpawnError,
+    SpawnPeerWatcherError,
 }
 
 /// Start a secure channel without knowing the identity of the remote
@@ -104,16 +151,41 @@ where
     }
 }
 
-pub async fn net_relay_server<IRC, R, S>(
+/// Run a relay server over incoming raw connections.
+/// `relay_limits` limits the resources the relay server may use. Connections exceeding those
+/// limits are closed.
+///
+/// If `opt_allowed_nodes` is not None, only the nodes in this set may listen or connect. Updates
+/// to this set are received through `incoming_allowed_nodes`.
+///
+/// `peer_relays` are relays (and their addresses) we federate with. We keep a connection to every
+/// peer relay (Using `raw_peer_connector`) to learn which nodes are listening on it, reconnecting
+/// after `backoff_ticks` if the connection is lost. Connections to nodes listening on a peer relay
+/// are forwarded to the peer relay.
+///
+/// Every `stats_ticks` ticks, a report of the relay server's state is sent through
+/// `stats_sender` (See `log_relay_stats`).
+pub async fn net_relay_server<A, IRC, C, AN, R, S>(
     incoming_raw_conns: IRC,
+    raw_peer_connector: C,
+    opt_allowed_nodes: Option<HashSet<PublicKey>>,
+    incoming_allowed_nodes: AN,
+    peer_relays: HashMap<PublicKey, A>,
     identity_client: IdentityClient,
     timer_client: TimerClient,
     rng: R,
     max_concurrent_encrypt: usize,
+    relay_limits: RelayLimits,
+    backoff_ticks: usize,
+    stats_ticks: usize,
+    stats_sender: mpsc::Sender<RelayStats>,
     mut spawner: S,
 ) -> Result<(), NetRelayServerError>
 where
+    A: Clone + Send + Sync + 'static,
     IRC: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
+    C: FutTransform<Input = A, Output = Option<ConnPairVec>> + Clone + Send + Sync + 'static,
+    AN: Stream<Item = HashSet<PublicKey>> + Unpin + Send,
     R: CryptoRandom + Clone + 'static,
     S: Spawn + Clone + Send + Sync + 'static,
 {
@@ -127,6 +199,50 @@ where
         spawner.clone(),
     );
 
+    // Connections to peer relays begin with an InitConnection message, sent before the keepalive
+    // layer, just like connections from relay clients:
+    let c_version_transform = version_transform.clone();
+    let c_encrypt_transform = encrypt_transform.clone();
+    let keepalive_transform =
+        KeepAliveChannel::new(timer_client.clone(), KEEPALIVE_TICKS, spawner.clone());
+    let peer_addresses = peer_relays.clone();
+    let peer_connector = FuncFutTransform::new(
+        move |(public_key, init_connection): (PublicKey, InitConnection)| {
+            let opt_address = peer_addresses.get(&public_key).cloned();
+            let mut c_raw_peer_connector = raw_peer_connector.clone();
+            let mut c_version_transform = c_version_transform.clone();
+            let mut c_encrypt_transform = c_encrypt_transform.clone();
+            let mut c_keepalive_transform = keepalive_transform.clone();
+            Box::pin(
+                async move {
+                    let raw_conn = await!(c_raw_peer_connector.transform(opt_address?))?;
+                    let conn_pair = await!(c_version_transform.transform(raw_conn));
+                    let (_public_key, (mut sender, receiver)) =
+                        await!(c_encrypt_transform.transform((Some(public_key), conn_pair)))?;
+                    await!(sender.send(serialize_init_connection(&init_connection))).ok()?;
+                    Some(await!(c_keepalive_transform.transform((sender, receiver))))
+                },
+            )
+        },
+    );
+
+    // Keep track of the nodes listening on every peer relay:
+    let (peer_events_sender, incoming_peer_events) = mpsc::channel(0);
+    for peer_public_key in peer_relays.keys() {
+        let watcher_fut = peer_watcher(
+            peer_public_key.clone(),
+            peer_connector.clone(),
+            timer_client.clone(),
+            backoff_ticks,
+            peer_events_sender.clone(),
+        )
+        .map_err(|e| error!("peer_watcher() error: {:?}", e))
+        .map(|_| ());
+        spawner
+            .spawn(watcher_fut)
+            .map_err(|_| NetRelayServerError::SpawnPeerWatcherError)?;
+    }
+
     // TODO; How to get rid of Box::pin() here?
     let incoming_ver_conns = Box::pin(incoming_raw_conns.then(move |raw_conn| {
         // TODO: A more efficient way to do this?
@@ -152,11 +268,46 @@ where
         .spawn(enc_pool_fut)
         .map_err(|_| NetRelayServerError::SpawnError)?;
 
-    await!(relay_server(
+    // Close connections beyond the allowed amount.
+    // Peer relays forward connections of many different nodes, therefore they are not limited per
+    // public key:
+    let (limited_conns_sender, incoming_limited_conns) =
+        mpsc::channel::<(PublicKey, ConnPairVec)>(0);
+
+    // Amount of connections closed by the limiter:
+    let too_many_conns = Arc::new(AtomicUsize::new(0));
+
+    let limiter_fut = conn_limiter(
         incoming_enc_conns,
+        limited_conns_sender,
+        relay_limits.max_conns,
+        relay_limits.max_conns_per_public_key,
+        peer_relays.keys().cloned().collect(),
+        too_many_conns.clone(),
+        spawner.clone(),
+    )
+    .map_err(|e| error!("conn_limiter() error: {:?}", e))
+    .map(|_| ());
+
+    spawner
+        .spawn(limiter_fut)
+        .map_err(|_| NetRelayServerError::SpawnError)?;
+
+    await!(relay_server(
+        incoming_limited_conns,
+        opt_allowed_nodes,
+        incoming_allowed_nodes,
+        peer_relays.keys().cloned().collect(),
+        incoming_peer_events,
+        peer_connector,
         timer_client,
         CONN_TIMEOUT_TICKS,
         KEEPALIVE_TICKS,
+        relay_limits.max_half_tunnels,
+        relay_limits.max_tunnel_bytes_per_tick,
+        stats_ticks,
+        too_many_conns,
+        stats_sender,
         spawner.clone()
     ))?;
     Ok(())
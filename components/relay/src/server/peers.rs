@@ -0,0 +1,155 @@
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+
+use common::conn::{ConnPairVec, FutTransform};
+
+use crypto::identity::PublicKey;
+use timer::utils::sleep_ticks;
+use timer::TimerClient;
+
+use proto::relay::messages::{InitConnection, ListenerUpdate};
+use proto::relay::serialize::deserialize_listener_update;
+
+/// An event about the nodes listening on a peer relay.
+#[derive(Debug)]
+pub enum PeerEvent {
+    /// A node started or stopped listening on a peer relay.
+    Update((PublicKey, ListenerUpdate)),
+    /// The connection to a peer relay was closed.
+    /// All the nodes listening on this peer relay should be forgotten.
+    Closed(PublicKey),
+}
+
+#[derive(Debug)]
+pub enum PeerWatcherError {
+    EventSenderError,
+    SleepTicksError,
+}
+
+/// Keep a connection to the peer relay `peer_public_key`, and report the nodes listening on the
+/// peer relay through `event_sender`.
+///
+/// `peer_connector` connects to a peer relay and sends the given `InitConnection` message.
+/// If the connection fails or is closed, we attempt to reconnect after `backoff_ticks` ticks.
+pub async fn peer_watcher<PC>(
+    peer_public_key: PublicKey,
+    mut peer_connector: PC,
+    timer_client: TimerClient,
+    backoff_ticks: usize,
+    mut event_sender: mpsc::Sender<PeerEvent>,
+) -> Result<(), PeerWatcherError>
+where
+    PC: FutTransform<Input = (PublicKey, InitConnection), Output = Option<ConnPairVec>>,
+{
+    loop {
+        let opt_conn_pair =
+            await!(peer_connector.transform((peer_public_key.clone(), InitConnection::Peer)));
+        // Note that the sender is kept alive until the connection is closed:
+        if let Some((_sender, mut receiver)) = opt_conn_pair {
+            while let Some(data) = await!(receiver.next()) {
+                let listener_update = match deserialize_listener_update(&data) {
+                    Ok(listener_update) => listener_update,
+                    Err(_) => {
+                        warn!(
+                            "peer_watcher(): Invalid listener update from {:?}",
+                            peer_public_key
+                        );
+                        break;
+                    }
+                };
+                await!(event_sender.send(PeerEvent::Update((
+                    peer_public_key.clone(),
+                    listener_update
+                ))))
+                .map_err(|_| PeerWatcherError::EventSenderError)?;
+            }
+            await!(event_sender.send(PeerEvent::Closed(peer_public_key.clone())))
+                .map_err(|_| PeerWatcherError::EventSenderError)?;
+        }
+
+        // Wait before we attempt to reconnect:
+        await!(sleep_ticks(backoff_ticks, timer_client.clone()))
+            .map_err(|_| PeerWatcherError::SleepTicksError)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::ThreadPool;
+    use futures::task::{Spawn, SpawnExt};
+    use futures::FutureExt;
+
+    use common::dummy_connector::DummyConnector;
+    use crypto::identity::PUBLIC_KEY_LEN;
+    use proto::relay::serialize::serialize_listener_update;
+    use timer::{dummy_timer_multi_sender, TimerTick};
+
+    async fn task_peer_watcher_basic<S>(mut spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (mut tick_sender_receiver, timer_client) = dummy_timer_multi_sender(spawner.clone());
+
+        let (conn_request_sender, mut conn_request_receiver) = mpsc::channel(0);
+        let peer_connector = DummyConnector::new(conn_request_sender);
+        let (event_sender, mut event_receiver) = mpsc::channel(0);
+
+        let peer_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let listen_public_key = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+
+        let backoff_ticks = 2;
+        let watcher_fut = peer_watcher(
+            peer_public_key.clone(),
+            peer_connector,
+            timer_client,
+            backoff_ticks,
+            event_sender,
+        )
+        .map(|_| ());
+        spawner.spawn(watcher_fut).unwrap();
+
+        // The first connection attempt fails:
+        let conn_request = await!(conn_request_receiver.next()).unwrap();
+        assert_eq!(
+            conn_request.address,
+            (peer_public_key.clone(), InitConnection::Peer)
+        );
+        conn_request.reply(None);
+
+        // We attempt to reconnect after backoff_ticks:
+        let mut tick_sender = await!(tick_sender_receiver.next()).unwrap();
+        for _ in 0..backoff_ticks {
+            await!(tick_sender.send(TimerTick)).unwrap();
+        }
+
+        let conn_request = await!(conn_request_receiver.next()).unwrap();
+        let (local_sender, _remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (mut remote_sender, local_receiver) = mpsc::channel::<Vec<u8>>(0);
+        conn_request.reply(Some((local_sender, local_receiver)));
+
+        let listener_update = ListenerUpdate::Listening(listen_public_key.clone());
+        await!(remote_sender.send(serialize_listener_update(&listener_update))).unwrap();
+        match await!(event_receiver.next()).unwrap() {
+            PeerEvent::Update((public_key, update)) => {
+                assert_eq!(public_key, peer_public_key);
+                assert_eq!(update, listener_update);
+            }
+            _ => unreachable!(),
+        };
+
+        // The peer relay closes the connection:
+        drop(remote_sender);
+        match await!(event_receiver.next()).unwrap() {
+            PeerEvent::Closed(public_key) => assert_eq!(public_key, peer_public_key),
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn test_peer_watcher_basic() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_peer_watcher_basic(thread_pool.clone()));
+    }
+}
@@ -4,15 +4,25 @@ use futures::{future, stream, FutureExt, Sink, SinkExt, Stream, StreamExt, TryFu
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::marker::Unpin;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
+use common::conn::{ConnPairVec, FutTransform};
 use common::futures_compat::send_to_sink;
 use common::select_streams::{select_streams, BoxStream};
 use crypto::identity::PublicKey;
 use timer::TimerClient;
 
-use proto::relay::messages::{IncomingConnection, RejectConnection};
+use proto::relay::messages::{
+    ForwardConnection, IncomingConnection, InitConnection, ListenerUpdate, RejectConnection,
+};
 
-use super::types::{IncomingAccept, IncomingConn, IncomingConnInner};
+use super::peers::PeerEvent;
+use super::stats::{ListenerStats, RejectedConns, RelayStats, TunnelStats};
+use super::types::{
+    IncomingAccept, IncomingConn, IncomingConnInner, IncomingConnect, IncomingForward, IncomingPeer,
+};
 
 struct ConnPair<M, K> {
     receiver: M,
@@ -34,6 +44,8 @@ struct Listener<MT, KT> {
     half_tunnels: HashMap<PublicKey, HalfTunnel<MT, KT>>,
     tunnels: HashSet<PublicKey>,
     opt_sender: Option<mpsc::Sender<IncomingConnection>>,
+    /// Bytes forwarded through tunnels to this listener that were already closed:
+    closed_tunnels_bytes: usize,
 }
 
 impl<MT, KT> Listener<MT, KT> {
@@ -42,26 +54,56 @@ impl<MT, KT> Listener<MT, KT> {
             half_tunnels: HashMap::new(),
             tunnels: HashSet::new(),
             opt_sender: Some(sender),
+            closed_tunnels_bytes: 0,
+        }
+    }
+}
+
+/// An open tunnel. The byte counters are updated by the tasks forwarding the tunnel's messages.
+struct Tunnel {
+    init_public_key: PublicKey,
+    listen_public_key: PublicKey,
+    ticks_open: usize,
+    bytes_to_listener: Arc<AtomicUsize>,
+    bytes_from_listener: Arc<AtomicUsize>,
+}
+
+impl Tunnel {
+    fn stats(&self) -> TunnelStats {
+        TunnelStats {
+            init_public_key: self.init_public_key.clone(),
+            listen_public_key: self.listen_public_key.clone(),
+            ticks_open: self.ticks_open,
+            bytes_to_listener: self.bytes_to_listener.load(Ordering::Relaxed),
+            bytes_from_listener: self.bytes_from_listener.load(Ordering::Relaxed),
         }
     }
 }
 
 struct TunnelClosed {
+    tunnel_id: u64,
     init_public_key: PublicKey,
     listen_public_key: PublicKey,
 }
 
-enum RelayServerEvent<ML, KL, MA, KA, MC, KC> {
-    IncomingConn(IncomingConn<ML, KL, MA, KA, MC, KC>),
+/// Maximum amount of listener updates waiting to be sent to a peer relay.
+/// A peer relay that falls behind is disconnected, and receives all the listening nodes again
+/// when it reconnects.
+const MAX_PENDING_LISTENER_UPDATES: usize = 0x100;
+
+enum RelayServerEvent<ML, KL, MA, KA, MC, KC, KP> {
+    IncomingConn(IncomingConn<ML, KL, MA, KA, MC, KC, KP>),
     IncomingConnsClosed,
     TunnelClosed(TunnelClosed),
     ListenerMessage((PublicKey, RejectConnection)),
     ListenerClosed(PublicKey),
+    AllowedNodes(HashSet<PublicKey>),
+    PeerEvent(PeerEvent),
     TimerTick,
     TimerClosed,
 }
 
-impl<ML, KL, MA, KA, MC, KC> fmt::Debug for RelayServerEvent<ML, KL, MA, KA, MC, KC> {
+impl<ML, KL, MA, KA, MC, KC, KP> fmt::Debug for RelayServerEvent<ML, KL, MA, KA, MC, KC, KP> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             RelayServerEvent::IncomingConn(_) => write!(f, "RelayServerEvent::IncomingConn"),
@@ -71,12 +113,21 @@ impl<ML, KL, MA, KA, MC, KC> fmt::Debug for RelayServerEvent<ML, KL, MA, KA, MC,
             RelayServerEvent::TunnelClosed(_) => write!(f, "RelayServerEvent::TunnelClosed"),
             RelayServerEvent::ListenerMessage(_) => write!(f, "RelayServerEvent::ListenerMessage"),
             RelayServerEvent::ListenerClosed(_) => write!(f, "RelayServerEvent::ListenerClosed"),
+            RelayServerEvent::AllowedNodes(_) => write!(f, "RelayServerEvent::AllowedNodes"),
+            RelayServerEvent::PeerEvent(_) => write!(f, "RelayServerEvent::PeerEvent"),
             RelayServerEvent::TimerTick => write!(f, "RelayServerEvent::TimerTick"),
             RelayServerEvent::TimerClosed => write!(f, "RelayServerEvent::TimerClosed"),
         }
     }
 }
 
+enum TunnelEvent {
+    Message(Vec<u8>),
+    ReceiverClosed,
+    TimerTick,
+    TimerClosed,
+}
+
 #[derive(Debug)]
 pub enum RelayServerError {
     IncomingConnsError,
@@ -89,14 +140,271 @@ pub enum RelayServerError {
     EventReceiverError,
 }
 
+/// Forward messages from `receiver` to `sender`, letting at most `max_bytes_per_tick` bytes
+/// through every tick. A message exceeding the quota of the current tick is forwarded anyway,
+/// and the excess is deducted from the quota of the following ticks.
+/// The amount of forwarded bytes is added to `bytes_counter`.
+async fn forward_rate_limited<M, K>(
+    mut receiver: M,
+    mut sender: K,
+    mut timer_client: TimerClient,
+    max_bytes_per_tick: usize,
+    bytes_counter: Arc<AtomicUsize>,
+) -> Result<(), RelayServerError>
+where
+    M: Stream<Item = Vec<u8>> + Unpin,
+    K: Sink<SinkItem = Vec<u8>, SinkError = ()> + Unpin,
+{
+    let mut timer_stream = await!(timer_client.request_timer_stream())
+        .map_err(|_| RelayServerError::RequestTimerStreamError)?;
+
+    // Amount of bytes we may still forward during the current tick:
+    let mut bytes_left = max_bytes_per_tick;
+    // Amount of bytes forwarded beyond the quota, to be deducted from the next ticks:
+    let mut bytes_owed: usize = 0;
+
+    loop {
+        let timer_events = (&mut timer_stream)
+            .map(|_| TunnelEvent::TimerTick)
+            .chain(stream::once(future::ready(TunnelEvent::TimerClosed)));
+        let opt_event = if bytes_left == 0 {
+            // Quota is used up. We stop reading messages until the next tick:
+            await!(timer_events.into_future()).0
+        } else {
+            let messages = (&mut receiver)
+                .map(TunnelEvent::Message)
+                .chain(stream::once(future::ready(TunnelEvent::ReceiverClosed)));
+            await!(messages.select(timer_events).into_future()).0
+        };
+
+        match opt_event {
+            Some(TunnelEvent::Message(message)) => {
+                let message_len = message.len();
+                if message_len <= bytes_left {
+                    bytes_left -= message_len;
+                } else {
+                    bytes_owed += message_len - bytes_left;
+                    bytes_left = 0;
+                }
+                if await!(sender.send(message)).is_err() {
+                    // The remote side has closed the tunnel:
+                    return Ok(());
+                }
+                bytes_counter.fetch_add(message_len, Ordering::Relaxed);
+            }
+            Some(TunnelEvent::ReceiverClosed) => return Ok(()),
+            Some(TunnelEvent::TimerTick) => {
+                if bytes_owed >= max_bytes_per_tick {
+                    bytes_owed -= max_bytes_per_tick;
+                } else {
+                    bytes_left = max_bytes_per_tick - bytes_owed;
+                    bytes_owed = 0;
+                }
+            }
+            Some(TunnelEvent::TimerClosed) | None => {
+                return Err(RelayServerError::TimerStreamError)
+            }
+        }
+    }
+}
+
+fn collect_stats<MT, KT>(
+    listeners: &HashMap<PublicKey, Listener<MT, KT>>,
+    tunnels: &HashMap<u64, Tunnel>,
+    closed_tunnels: Vec<TunnelStats>,
+    rejected_conns: RejectedConns,
+) -> RelayStats {
+    let tunnels = tunnels.values().map(Tunnel::stats).collect::<Vec<_>>();
+
+    // Amount of open tunnels and bytes forwarded through them, for every listener:
+    let mut listeners_tunnels: HashMap<&PublicKey, (usize, usize)> = HashMap::new();
+    for tunnel_stats in &tunnels {
+        let (num_tunnels, bytes_forwarded) = listeners_tunnels
+            .entry(&tunnel_stats.listen_public_key)
+            .or_insert((0, 0));
+        *num_tunnels += 1;
+        *bytes_forwarded = bytes_forwarded
+            .saturating_add(tunnel_stats.bytes_to_listener)
+            .saturating_add(tunnel_stats.bytes_from_listener);
+    }
+
+    let listeners = listeners
+        .iter()
+        .filter(|(_public_key, listener)| listener.opt_sender.is_some())
+        .map(|(public_key, listener)| {
+            let (num_tunnels, bytes_forwarded) =
+                listeners_tunnels.get(public_key).cloned().unwrap_or((0, 0));
+            ListenerStats {
+                public_key: public_key.clone(),
+                num_half_tunnels: listener.half_tunnels.len(),
+                num_tunnels,
+                bytes_forwarded: bytes_forwarded.saturating_add(listener.closed_tunnels_bytes),
+            }
+        })
+        .collect();
+
+    RelayStats {
+        listeners,
+        tunnels,
+        closed_tunnels,
+        rejected_conns,
+    }
+}
+
+/// Send a listener update to all the subscribed peer relays.
+/// Peer relays that can not keep up with the updates are disconnected.
+fn broadcast_listener_update(
+    subscribers: &mut HashMap<PublicKey, mpsc::Sender<ListenerUpdate>>,
+    listener_update: ListenerUpdate,
+) {
+    subscribers.retain(|peer_public_key, updates_sender| {
+        match updates_sender.try_send(listener_update.clone()) {
+            Ok(()) => true,
+            Err(e) => {
+                if e.is_full() {
+                    warn!(
+                        "broadcast_listener_update(): Too many pending updates to {:?}",
+                        peer_public_key
+                    );
+                }
+                false
+            }
+        }
+    });
+}
+
+/// Add a pending connection from `init_public_key` to the local listener
+/// `connect_public_key`, and notify the listener about it.
+fn add_half_tunnel<MT, KT>(
+    listeners: &mut HashMap<PublicKey, Listener<MT, KT>>,
+    init_public_key: PublicKey,
+    connect_public_key: &PublicKey,
+    conn_pair: ConnPair<MT, KT>,
+    half_tunnel_ticks: usize,
+    max_half_tunnels: usize,
+    rejected_conns: &mut RejectedConns,
+) {
+    let listener = match listeners.get_mut(connect_public_key) {
+        Some(listener) => listener,
+        None => {
+            rejected_conns.not_listening += 1;
+            return; // Discard connection
+        }
+    };
+    if listener.half_tunnels.contains_key(&init_public_key)
+        || listener.tunnels.contains(&init_public_key)
+    {
+        rejected_conns.already_pending += 1;
+        return;
+    }
+    if listener.half_tunnels.len() >= max_half_tunnels {
+        warn!(
+            "add_half_tunnel(): Too many pending connections to {:?}",
+            connect_public_key
+        );
+        rejected_conns.too_many_half_tunnels += 1;
+        return; // Discard connection
+    }
+
+    let half_tunnel = HalfTunnel {
+        conn_pair,
+        ticks_to_close: half_tunnel_ticks,
+    };
+    let mut reported = false;
+    if let Some(sender) = &mut listener.opt_sender {
+        // Try to send a message to listener about new pending connection:
+        if let Ok(()) = sender.try_send(IncomingConnection {
+            public_key: init_public_key.clone(),
+        }) {
+            listener.half_tunnels.insert(init_public_key, half_tunnel);
+            reported = true;
+        }
+    }
+    if !reported {
+        rejected_conns.listener_busy += 1;
+    }
+}
+
+/// Forward a connection to the peer relay `peer_public_key`, and pass messages between the
+/// connection and the peer relay.
+fn forward_to_peer<MC, KC, PC, S>(
+    peer_public_key: PublicKey,
+    forward_connection: ForwardConnection,
+    conn_pair: ConnPair<MC, KC>,
+    mut peer_connector: PC,
+    timer_client: TimerClient,
+    max_tunnel_bytes_per_tick: usize,
+    mut spawner: S,
+) where
+    MC: Stream<Item = Vec<u8>> + Unpin + Send + 'static,
+    KC: Sink<SinkItem = Vec<u8>, SinkError = ()> + Unpin + Send + 'static,
+    PC: FutTransform<Input = (PublicKey, InitConnection), Output = Option<ConnPairVec>>
+        + Send
+        + 'static,
+    S: Spawn + Clone + Send + 'static,
+{
+    let mut c_spawner = spawner.clone();
+    let forward_fut = async move {
+        let init_connection = InitConnection::Forward(forward_connection);
+        let opt_peer_conn_pair =
+            await!(peer_connector.transform((peer_public_key.clone(), init_connection)));
+        let (peer_sender, peer_receiver) = match opt_peer_conn_pair {
+            Some(peer_conn_pair) => peer_conn_pair,
+            None => {
+                warn!(
+                    "forward_to_peer(): Failed connecting to peer relay {:?}",
+                    peer_public_key
+                );
+                return;
+            }
+        };
+        let ConnPair { receiver, sender } = conn_pair;
+
+        let send_fut1 = forward_rate_limited(
+            receiver,
+            peer_sender.sink_map_err(|_| ()),
+            timer_client.clone(),
+            max_tunnel_bytes_per_tick,
+            Arc::new(AtomicUsize::new(0)),
+        )
+        .map_err(|e| error!("forward_to_peer(): send_fut1 error: {:?}", e))
+        .then(|_| future::ready(()));
+        let send_fut2 = forward_rate_limited(
+            peer_receiver,
+            sender,
+            timer_client,
+            max_tunnel_bytes_per_tick,
+            Arc::new(AtomicUsize::new(0)),
+        )
+        .map_err(|e| error!("forward_to_peer(): send_fut2 error: {:?}", e))
+        .then(|_| future::ready(()));
+
+        c_spawner.spawn(send_fut1).unwrap();
+        c_spawner.spawn(send_fut2).unwrap();
+    };
+    spawner.spawn(forward_fut).unwrap();
+}
+
+/// Check if a node is allowed to use the relay.
+/// If `opt_allowed_nodes` is None, all nodes are allowed.
+fn is_allowed(opt_allowed_nodes: &Option<HashSet<PublicKey>>, public_key: &PublicKey) -> bool {
+    match opt_allowed_nodes {
+        Some(allowed_nodes) => allowed_nodes.contains(public_key),
+        None => true,
+    }
+}
+
 fn handle_accept<MT, KT, MA, KA, TCL>(
     listeners: &mut HashMap<PublicKey, Listener<MT, KT>>,
     acceptor_public_key: PublicKey,
     incoming_accept: IncomingAccept<MA, KA>,
+    tunnel_id: u64,
     // TODO: This should be a oneshot:
     tunnel_closed_sender: TCL,
+    timer_client: TimerClient,
+    max_tunnel_bytes_per_tick: usize,
     mut spawner: impl Spawn,
-) -> Result<(), RelayServerError>
+) -> Result<Tunnel, RelayServerError>
 where
     MT: Stream<Item = Vec<u8>> + Unpin + Send + 'static,
     KT: Sink<SinkItem = Vec<u8>, SinkError = ()> + Unpin + Send + 'static,
@@ -109,8 +417,8 @@ where
         None => return Err(RelayServerError::ListeningNotInProgress),
     };
     let IncomingAccept {
-        mut receiver,
-        mut sender,
+        receiver,
+        sender,
         accept_public_key,
     } = incoming_accept;
     let conn_pair = match listener.half_tunnels.remove(&accept_public_key) {
@@ -120,40 +428,83 @@ where
     let c_accept_public_key = accept_public_key.clone();
 
     let ConnPair {
-        sender: mut remote_sender,
-        receiver: mut remote_receiver,
+        sender: remote_sender,
+        receiver: remote_receiver,
     } = conn_pair;
 
-    let send_fut1 = async move {
-        await!(remote_sender
-            .send_all(&mut receiver)
-            .map_err(|e| error!("send_fut1 error: {:?}", e))
-            .then(|_| future::ready(())))
-    };
-    let send_fut2 = async move {
-        await!(sender
-            .send_all(&mut remote_receiver)
-            .map_err(|e| error!("send_fut2 error: {:?}", e))
-            .then(move |_| {
-                let tunnel_closed = TunnelClosed {
-                    init_public_key: c_accept_public_key,
-                    listen_public_key: acceptor_public_key,
-                };
-                send_to_sink(tunnel_closed_sender, tunnel_closed).then(|_| future::ready(()))
-            }))
+    let tunnel = Tunnel {
+        init_public_key: accept_public_key.clone(),
+        listen_public_key: acceptor_public_key.clone(),
+        ticks_open: 0,
+        bytes_to_listener: Arc::new(AtomicUsize::new(0)),
+        bytes_from_listener: Arc::new(AtomicUsize::new(0)),
     };
 
+    let send_fut1 = forward_rate_limited(
+        receiver,
+        remote_sender,
+        timer_client.clone(),
+        max_tunnel_bytes_per_tick,
+        tunnel.bytes_from_listener.clone(),
+    )
+    .map_err(|e| error!("send_fut1 error: {:?}", e))
+    .then(|_| future::ready(()));
+    let send_fut2 = forward_rate_limited(
+        remote_receiver,
+        sender,
+        timer_client,
+        max_tunnel_bytes_per_tick,
+        tunnel.bytes_to_listener.clone(),
+    )
+    .map_err(|e| error!("send_fut2 error: {:?}", e))
+    .then(move |_| {
+        let tunnel_closed = TunnelClosed {
+            tunnel_id,
+            init_public_key: c_accept_public_key,
+            listen_public_key: acceptor_public_key,
+        };
+        send_to_sink(tunnel_closed_sender, tunnel_closed).then(|_| future::ready(()))
+    });
+
     spawner.spawn(send_fut1).unwrap();
     spawner.spawn(send_fut2).unwrap();
 
-    Ok(())
+    Ok(tunnel)
 }
 
-pub async fn relay_server_loop<ML, KL, MA, KA, MC, KC, S>(
+/// Pair listening, connecting and accepting connections into tunnels.
+///
+/// Only nodes in `opt_allowed_nodes` may listen or connect (If not None). Updates to the set of
+/// allowed nodes are received through `incoming_allowed_nodes`. Nodes removed from the set stop
+/// listening, but tunnels that are already open are not closed.
+///
+/// Relays in `peer_relays` may subscribe to updates about the nodes listening on this relay, and
+/// forward connections to those nodes. The nodes listening on peer relays are received through
+/// `incoming_peer_events`. A connection to a node that does not listen on this relay is forwarded
+/// (using `peer_connector`) to a peer relay the node listens on. Forwarded connections are
+/// delivered only to local listeners, and their initiators must be in `opt_allowed_nodes` too.
+///
+/// Every listener may have at most `max_half_tunnels` connections waiting to be accepted. Every
+/// tunnel may forward at most `max_tunnel_bytes_per_tick` bytes in every direction, per tick.
+///
+/// Every `stats_ticks` ticks a `RelayStats` report is sent through `stats_sender`. If the
+/// previous report was not yet consumed, the new report is dropped. `too_many_conns` counts the
+/// connections closed before reaching the relay server loop because of too many open connections.
+pub async fn relay_server_loop<ML, KL, MA, KA, MC, KC, KP, S, AN, PE, PC>(
     mut timer_client: TimerClient,
     incoming_conns: S,
+    mut opt_allowed_nodes: Option<HashSet<PublicKey>>,
+    incoming_allowed_nodes: AN,
+    peer_relays: HashSet<PublicKey>,
+    incoming_peer_events: PE,
+    peer_connector: PC,
     half_tunnel_ticks: usize,
-    mut spawner: impl Spawn + Clone,
+    max_half_tunnels: usize,
+    max_tunnel_bytes_per_tick: usize,
+    stats_ticks: usize,
+    too_many_conns: Arc<AtomicUsize>,
+    mut stats_sender: mpsc::Sender<RelayStats>,
+    mut spawner: impl Spawn + Clone + Send + 'static,
 ) -> Result<(), RelayServerError>
 where
     ML: Stream<Item = RejectConnection> + Unpin + Send + 'static,
@@ -162,8 +513,17 @@ where
     KA: Sink<SinkItem = Vec<u8>, SinkError = ()> + Unpin + Send + 'static,
     MC: Stream<Item = Vec<u8>> + Unpin + Send + 'static,
     KC: Sink<SinkItem = Vec<u8>, SinkError = ()> + Unpin + Send + 'static,
-    S: Stream<Item = IncomingConn<ML, KL, MA, KA, MC, KC>> + Unpin + Send,
+    KP: Sink<SinkItem = ListenerUpdate, SinkError = ()> + Unpin + Send + 'static,
+    S: Stream<Item = IncomingConn<ML, KL, MA, KA, MC, KC, KP>> + Unpin + Send,
+    AN: Stream<Item = HashSet<PublicKey>> + Unpin + Send,
+    PE: Stream<Item = PeerEvent> + Unpin + Send,
+    PC: FutTransform<Input = (PublicKey, InitConnection), Output = Option<ConnPairVec>>
+        + Clone
+        + Send
+        + 'static,
 {
+    assert!(stats_ticks > 0);
+
     let timer_stream = await!(timer_client.request_timer_stream())
         .map_err(|_| RelayServerError::RequestTimerStreamError)?;
     let timer_stream = timer_stream
@@ -176,12 +536,30 @@ where
             RelayServerEvent::IncomingConnsClosed,
         )));
 
-    let (event_sender, event_receiver) = mpsc::channel::<RelayServerEvent<_, _, _, _, _, _>>(0);
+    let incoming_allowed_nodes = incoming_allowed_nodes.map(RelayServerEvent::AllowedNodes);
+    let incoming_peer_events = incoming_peer_events.map(RelayServerEvent::PeerEvent);
 
-    let mut relay_server_events = select_streams![timer_stream, incoming_conns, event_receiver];
+    let (event_sender, event_receiver) = mpsc::channel::<RelayServerEvent<_, _, _, _, _, _, _>>(0);
+
+    let mut relay_server_events = select_streams![
+        timer_stream,
+        incoming_conns,
+        incoming_allowed_nodes,
+        incoming_peer_events,
+        event_receiver
+    ];
 
     let mut incoming_conns_closed = false;
     let mut listeners: HashMap<PublicKey, Listener<_, _>> = HashMap::new();
+    let mut tunnels: HashMap<u64, Tunnel> = HashMap::new();
+    let mut next_tunnel_id: u64 = 0;
+    let mut closed_tunnels: Vec<TunnelStats> = Vec::new();
+    let mut rejected_conns = RejectedConns::default();
+    let mut ticks_to_stats = stats_ticks;
+    // Peer relays subscribed to updates about our listeners:
+    let mut subscribers: HashMap<PublicKey, mpsc::Sender<ListenerUpdate>> = HashMap::new();
+    // Nodes listening on peer relays (listener public key -> peer relays public keys):
+    let mut remote_listeners: HashMap<PublicKey, HashSet<PublicKey>> = HashMap::new();
 
     while let Some(relay_server_event) = await!(relay_server_events.next()) {
         let c_event_sender = event_sender.clone().sink_map_err(|_| ());
@@ -190,7 +568,16 @@ where
                 let IncomingConn { public_key, inner } = incoming_conn;
                 match inner {
                     IncomingConnInner::Listen(incoming_listen) => {
+                        if !is_allowed(&opt_allowed_nodes, &public_key) {
+                            warn!(
+                                "relay_server_loop(): {:?} is not allowed to listen",
+                                public_key
+                            );
+                            rejected_conns.not_allowed += 1;
+                            continue; // Discard Listen connection
+                        }
                         if listeners.contains_key(&public_key) {
+                            rejected_conns.already_listening += 1;
                             continue; // Discard Listen connection
                         }
 
@@ -213,6 +600,10 @@ where
                             .unwrap();
                         let listener = Listener::new(mpsc_sender);
                         listeners.insert(public_key.clone(), listener);
+                        broadcast_listener_update(
+                            &mut subscribers,
+                            ListenerUpdate::Listening(public_key.clone()),
+                        );
                         let c_public_key = public_key.clone();
                         let mut receiver = receiver
                             .map(move |reject_connection| {
@@ -239,53 +630,170 @@ where
                         let tunnel_closed_sender = c_event_sender.with(|tunnel_closed| {
                             future::ready(Ok(RelayServerEvent::TunnelClosed(tunnel_closed)))
                         });
-                        let _ = handle_accept(
+                        match handle_accept(
                             &mut listeners,
                             public_key.clone(),
                             incoming_accept,
+                            next_tunnel_id,
                             tunnel_closed_sender,
+                            timer_client.clone(),
+                            max_tunnel_bytes_per_tick,
                             spawner.clone(),
-                        )
-                        .map_err(|e| warn!("handle_accept() error: {:?}", e));
+                        ) {
+                            Ok(tunnel) => {
+                                tunnels.insert(next_tunnel_id, tunnel);
+                                next_tunnel_id = next_tunnel_id.wrapping_add(1);
+                            }
+                            Err(e) => {
+                                warn!("handle_accept() error: {:?}", e);
+                                rejected_conns.invalid_accept += 1;
+                            }
+                        }
                     }
                     IncomingConnInner::Connect(incoming_connect) => {
-                        let listener = match listeners.get_mut(&incoming_connect.connect_public_key)
-                        {
-                            Some(listener) => listener,
-                            None => continue, // Discard Connect connection
-                        };
-                        if listener.half_tunnels.contains_key(&public_key)
-                            || listener.tunnels.contains(&public_key)
-                        {
-                            continue;
+                        if !is_allowed(&opt_allowed_nodes, &public_key) {
+                            warn!(
+                                "relay_server_loop(): {:?} is not allowed to connect",
+                                public_key
+                            );
+                            rejected_conns.not_allowed += 1;
+                            continue; // Discard Connect connection
                         }
-
-                        let half_tunnel = HalfTunnel {
-                            conn_pair: ConnPair::new(
-                                incoming_connect.receiver,
-                                incoming_connect.sender,
-                            ),
-                            ticks_to_close: half_tunnel_ticks,
-                        };
-                        if let Some(sender) = &mut listener.opt_sender {
-                            // Try to send a message to listener about new pending connection:
-                            if let Ok(()) = sender.try_send(IncomingConnection {
-                                public_key: public_key.clone(),
-                            }) {
-                                listener
-                                    .half_tunnels
-                                    .insert(public_key.clone(), half_tunnel);
+                        let IncomingConnect {
+                            receiver,
+                            sender,
+                            connect_public_key,
+                        } = incoming_connect;
+                        let conn_pair = ConnPair::new(receiver, sender);
+
+                        if !listeners.contains_key(&connect_public_key) {
+                            // Forward the connection to a peer relay, if the node is listening
+                            // there:
+                            let opt_peer_public_key = remote_listeners
+                                .get(&connect_public_key)
+                                .and_then(|peers| peers.iter().next().cloned());
+                            if let Some(peer_public_key) = opt_peer_public_key {
+                                let forward_connection = ForwardConnection {
+                                    init_public_key: public_key.clone(),
+                                    connect_public_key,
+                                };
+                                forward_to_peer(
+                                    peer_public_key,
+                                    forward_connection,
+                                    conn_pair,
+                                    peer_connector.clone(),
+                                    timer_client.clone(),
+                                    max_tunnel_bytes_per_tick,
+                                    spawner.clone(),
+                                );
+                                continue;
                             }
                         }
+
+                        add_half_tunnel(
+                            &mut listeners,
+                            public_key.clone(),
+                            &connect_public_key,
+                            conn_pair,
+                            half_tunnel_ticks,
+                            max_half_tunnels,
+                            &mut rejected_conns,
+                        );
+                    }
+                    IncomingConnInner::Forward(incoming_forward) => {
+                        if !peer_relays.contains(&public_key) {
+                            warn!(
+                                "relay_server_loop(): {:?} is not a peer relay. Can not forward",
+                                public_key
+                            );
+                            rejected_conns.not_allowed += 1;
+                            continue; // Discard Forward connection
+                        }
+                        let IncomingForward {
+                            receiver,
+                            sender,
+                            forward_connection,
+                        } = incoming_forward;
+                        if !is_allowed(&opt_allowed_nodes, &forward_connection.init_public_key) {
+                            warn!(
+                                "relay_server_loop(): {:?} is not allowed to connect",
+                                forward_connection.init_public_key
+                            );
+                            rejected_conns.not_allowed += 1;
+                            continue; // Discard Forward connection
+                        }
+                        // Forwarded connections are only delivered to local listeners. They are
+                        // never forwarded again:
+                        add_half_tunnel(
+                            &mut listeners,
+                            forward_connection.init_public_key,
+                            &forward_connection.connect_public_key,
+                            ConnPair::new(receiver, sender),
+                            half_tunnel_ticks,
+                            max_half_tunnels,
+                            &mut rejected_conns,
+                        );
+                    }
+                    IncomingConnInner::Peer(incoming_peer) => {
+                        if !peer_relays.contains(&public_key) {
+                            warn!(
+                                "relay_server_loop(): {:?} is not a peer relay. Can not subscribe",
+                                public_key
+                            );
+                            rejected_conns.not_allowed += 1;
+                            continue; // Discard Peer connection
+                        }
+                        let IncomingPeer {
+                            receiver,
+                            mut sender,
+                        } = incoming_peer;
+
+                        // The peer relay first receives all the nodes currently listening, and
+                        // then updates as they happen:
+                        let listening = listeners
+                            .iter()
+                            .filter(|(_public_key, listener)| listener.opt_sender.is_some())
+                            .map(|(public_key, _listener)| {
+                                ListenerUpdate::Listening(public_key.clone())
+                            })
+                            .collect::<Vec<_>>();
+                        let (updates_sender, updates_receiver) =
+                            mpsc::channel(MAX_PENDING_LISTENER_UPDATES);
+                        let mut updates = stream::iter(listening).chain(updates_receiver);
+                        spawner
+                            .spawn(
+                                async move {
+                                    // The peer relay does not send us anything, but we keep the
+                                    // receiver to keep the connection open:
+                                    let _receiver = receiver;
+                                    let _ = await!(sender.send_all(&mut updates));
+                                },
+                            )
+                            .unwrap();
+                        // An older subscription of this peer relay is closed when dropped:
+                        subscribers.insert(public_key.clone(), updates_sender);
                     }
                 }
             }
             RelayServerEvent::IncomingConnsClosed => incoming_conns_closed = true,
             RelayServerEvent::TunnelClosed(tunnel_closed) => {
+                let opt_tunnel_stats = tunnels
+                    .remove(&tunnel_closed.tunnel_id)
+                    .map(|tunnel| tunnel.stats());
                 let listener = match listeners.get_mut(&tunnel_closed.listen_public_key) {
                     Some(listener) => listener,
-                    None => continue,
+                    None => {
+                        closed_tunnels.extend(opt_tunnel_stats);
+                        continue;
+                    }
                 };
+                if let Some(tunnel_stats) = opt_tunnel_stats {
+                    listener.closed_tunnels_bytes = listener
+                        .closed_tunnels_bytes
+                        .saturating_add(tunnel_stats.bytes_to_listener)
+                        .saturating_add(tunnel_stats.bytes_from_listener);
+                    closed_tunnels.push(tunnel_stats);
+                }
                 listener.tunnels.remove(&tunnel_closed.init_public_key);
                 if listener.opt_sender.is_none() && listener.tunnels.is_empty() {
                     listeners.remove(&tunnel_closed.listen_public_key);
@@ -301,22 +809,76 @@ where
                     Some(listener) => listener,
                     None => continue,
                 };
-                let _ = listener.half_tunnels.remove(&rejected_public_key);
+                if listener.half_tunnels.remove(&rejected_public_key).is_some() {
+                    rejected_conns.rejected_by_listener += 1;
+                }
             }
             RelayServerEvent::ListenerClosed(public_key) => {
                 let listener = match listeners.get_mut(&public_key) {
                     Some(listener) => listener,
                     None => continue,
                 };
-                listener.opt_sender = None;
+                if listener.opt_sender.take().is_some() {
+                    broadcast_listener_update(
+                        &mut subscribers,
+                        ListenerUpdate::NotListening(public_key.clone()),
+                    );
+                }
                 listener.half_tunnels = HashMap::new();
                 if listener.tunnels.is_empty() {
                     listeners.remove(&public_key);
                 }
             }
+            RelayServerEvent::AllowedNodes(allowed_nodes) => {
+                for (public_key, listener) in &mut listeners {
+                    if !allowed_nodes.contains(public_key) {
+                        // Stop listening. The listener is removed when the connection is closed:
+                        if listener.opt_sender.take().is_some() {
+                            broadcast_listener_update(
+                                &mut subscribers,
+                                ListenerUpdate::NotListening(public_key.clone()),
+                            );
+                        }
+                        listener.half_tunnels = HashMap::new();
+                    } else {
+                        // Discard pending connections from nodes that are no longer allowed:
+                        listener
+                            .half_tunnels
+                            .retain(|init_public_key, _half_tunnel| {
+                                allowed_nodes.contains(init_public_key)
+                            });
+                    }
+                }
+                opt_allowed_nodes = Some(allowed_nodes);
+            }
+            RelayServerEvent::PeerEvent(PeerEvent::Update((peer_public_key, listener_update))) => {
+                match listener_update {
+                    ListenerUpdate::Listening(public_key) => {
+                        remote_listeners
+                            .entry(public_key)
+                            .or_insert_with(HashSet::new)
+                            .insert(peer_public_key);
+                    }
+                    ListenerUpdate::NotListening(public_key) => {
+                        if let Some(peers) = remote_listeners.get_mut(&public_key) {
+                            peers.remove(&peer_public_key);
+                            if peers.is_empty() {
+                                remote_listeners.remove(&public_key);
+                            }
+                        }
+                    }
+                }
+            }
+            RelayServerEvent::PeerEvent(PeerEvent::Closed(peer_public_key)) => {
+                remote_listeners.retain(|_public_key, peers| {
+                    peers.remove(&peer_public_key);
+                    !peers.is_empty()
+                });
+            }
             RelayServerEvent::TimerTick => {
                 // Remove old half tunnels:
                 for listener in listeners.values_mut() {
+                    let num_half_tunnels = listener.half_tunnels.len();
                     listener
                         .half_tunnels
                         .retain(|_init_public_key, half_tunnel| {
@@ -324,6 +886,25 @@ where
                                 half_tunnel.ticks_to_close.saturating_sub(1);
                             half_tunnel.ticks_to_close > 0
                         });
+                    rejected_conns.timed_out += num_half_tunnels - listener.half_tunnels.len();
+                }
+                for tunnel in tunnels.values_mut() {
+                    tunnel.ticks_open = tunnel.ticks_open.saturating_add(1);
+                }
+
+                ticks_to_stats -= 1;
+                if ticks_to_stats == 0 {
+                    ticks_to_stats = stats_ticks;
+                    rejected_conns.too_many_conns = too_many_conns.load(Ordering::Relaxed);
+                    let relay_stats = collect_stats(
+                        &listeners,
+                        &tunnels,
+                        mem::replace(&mut closed_tunnels, Vec::new()),
+                        rejected_conns.clone(),
+                    );
+                    // We don't want to wait for the stats to be consumed. If the previous report
+                    // is still pending, this report is dropped:
+                    let _ = stats_sender.try_send(relay_stats);
                 }
             }
             RelayServerEvent::TimerClosed => break,
@@ -343,6 +924,8 @@ mod tests {
     use futures::task::{Spawn, SpawnExt};
 
     use super::super::types::{IncomingAccept, IncomingConnect, IncomingListen};
+    use common::conn::FuncFutTransform;
+    use common::dummy_connector::DummyConnector;
     use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
     use timer::create_timer_incoming;
 
@@ -356,11 +939,23 @@ mod tests {
         let (mut outgoing_conns, incoming_conns) = mpsc::channel::<_>(0);
 
         let half_tunnel_ticks: usize = 16;
+        let max_half_tunnels: usize = 16;
+        let max_tunnel_bytes_per_tick: usize = 0x100;
 
         let fut_relay_server = relay_server_loop(
             timer_client,
             incoming_conns,
+            None,
+            stream::empty(),
+            HashSet::new(),
+            stream::empty(),
+            FuncFutTransform::new(|_| Box::pin(future::ready(None))),
             half_tunnel_ticks,
+            max_half_tunnels,
+            max_tunnel_bytes_per_tick,
+            0x10,
+            Arc::new(AtomicUsize::new(0)),
+            mpsc::channel(0).0,
             spawner.clone(),
         );
 
@@ -444,6 +1039,21 @@ mod tests {
         let msg = await!(a_ca1.next()).unwrap();
         assert_eq!(msg, vec![4, 3, 2, 1]);
 
+        // This is done to help the compiler deduce the types for
+        // IncomingConn:
+        if false {
+            let (_p_pc, c_pc) = mpsc::channel::<Vec<u8>>(0);
+            let (c_cp, _p_cp) = mpsc::channel::<ListenerUpdate>(0);
+            let incoming_conn_peer = IncomingConn {
+                public_key: a_public_key.clone(),
+                inner: IncomingConnInner::Peer(IncomingPeer {
+                    receiver: c_pc,
+                    sender: c_cp.sink_map_err(|_| ()),
+                }),
+            };
+            await!(outgoing_conns.send(incoming_conn_peer)).unwrap();
+        }
+
         // If one side's sender is dropped, the other side's receiver will be notified:
         drop(b_bc);
         assert!(await!(a_ca1.next()).is_none());
@@ -473,11 +1083,23 @@ mod tests {
         let (mut outgoing_conns, incoming_conns) = mpsc::channel::<_>(0);
 
         let half_tunnel_ticks: usize = 16;
+        let max_half_tunnels: usize = 16;
+        let max_tunnel_bytes_per_tick: usize = 0x100;
 
         let fut_relay_server = relay_server_loop(
             timer_client,
             incoming_conns,
+            None,
+            stream::empty(),
+            HashSet::new(),
+            stream::empty(),
+            FuncFutTransform::new(|_| Box::pin(future::ready(None))),
             half_tunnel_ticks,
+            max_half_tunnels,
+            max_tunnel_bytes_per_tick,
+            0x10,
+            Arc::new(AtomicUsize::new(0)),
+            mpsc::channel(0).0,
             spawner.clone(),
         );
 
@@ -554,6 +1176,17 @@ mod tests {
                 inner: IncomingConnInner::Accept(incoming_accept_a),
             };
             await!(outgoing_conns.send(incoming_conn_accept_a)).unwrap();
+
+            let (_p_pc, c_pc) = mpsc::channel::<Vec<u8>>(0);
+            let (c_cp, _p_cp) = mpsc::channel::<ListenerUpdate>(0);
+            let incoming_conn_peer = IncomingConn {
+                public_key: a_public_key.clone(),
+                inner: IncomingConnInner::Peer(IncomingPeer {
+                    receiver: c_pc,
+                    sender: c_cp.sink_map_err(|_| ()),
+                }),
+            };
+            await!(outgoing_conns.send(incoming_conn_peer)).unwrap();
         }
 
         // A rejects B's connection:
@@ -582,6 +1215,544 @@ mod tests {
             .unwrap();
     }
 
+    async fn task_relay_server_allowed_nodes(mut spawner: impl Spawn + Clone + Send + 'static) {
+        // Create a mock time service:
+        let (_tick_sender, tick_receiver) = mpsc::channel::<()>(0);
+        let timer_client = create_timer_incoming(tick_receiver, spawner.clone()).unwrap();
+
+        let a_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let b_public_key = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let c_public_key = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+
+        let (mut outgoing_conns, incoming_conns) = mpsc::channel::<_>(0);
+        let (mut allowed_sender, incoming_allowed_nodes) = mpsc::channel(0);
+
+        let allowed_nodes = vec![a_public_key.clone(), b_public_key.clone()]
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let fut_relay_server = relay_server_loop(
+            timer_client,
+            incoming_conns,
+            Some(allowed_nodes),
+            incoming_allowed_nodes,
+            HashSet::new(),
+            stream::empty(),
+            FuncFutTransform::new(|_| Box::pin(future::ready(None))),
+            16,
+            16,
+            0x100,
+            0x10,
+            Arc::new(AtomicUsize::new(0)),
+            mpsc::channel(0).0,
+            spawner.clone(),
+        );
+
+        spawner
+            .spawn(
+                fut_relay_server
+                    .map_err(|e| error!("relay_server_loop() error: {:?}", e))
+                    .map(|_| ()),
+            )
+            .unwrap();
+
+        // c is not allowed to listen:
+        let (_c_ac, c_ac) = mpsc::channel::<RejectConnection>(0);
+        let (c_ca, mut c_ca_receiver) = mpsc::channel::<IncomingConnection>(0);
+        let incoming_conn_c = IncomingConn {
+            public_key: c_public_key.clone(),
+            inner: IncomingConnInner::Listen(IncomingListen {
+                receiver: c_ac,
+                sender: c_ca.sink_map_err(|_| ()),
+            }),
+        };
+        await!(outgoing_conns.send(incoming_conn_c)).unwrap();
+        assert!(await!(c_ca_receiver.next()).is_none());
+
+        // a listens, and b connects to a:
+        let (_a_ac, c_ac) = mpsc::channel::<RejectConnection>(0);
+        let (c_ca, mut a_ca) = mpsc::channel::<IncomingConnection>(0);
+        let incoming_conn_a = IncomingConn {
+            public_key: a_public_key.clone(),
+            inner: IncomingConnInner::Listen(IncomingListen {
+                receiver: c_ac,
+                sender: c_ca.sink_map_err(|_| ()),
+            }),
+        };
+        await!(outgoing_conns.send(incoming_conn_a)).unwrap();
+
+        let (_b_bc, c_bc) = mpsc::channel::<Vec<u8>>(0);
+        let (c_cb, mut b_cb) = mpsc::channel::<Vec<u8>>(0);
+        let incoming_conn_b = IncomingConn {
+            public_key: b_public_key.clone(),
+            inner: IncomingConnInner::Connect(IncomingConnect {
+                receiver: c_bc,
+                sender: c_cb.sink_map_err(|_| ()),
+                connect_public_key: a_public_key.clone(),
+            }),
+        };
+        await!(outgoing_conns.send(incoming_conn_b)).unwrap();
+
+        assert_eq!(
+            await!(a_ca.next()).unwrap(),
+            IncomingConnection {
+                public_key: b_public_key.clone()
+            }
+        );
+
+        // This is done to help the compiler deduce the types for
+        // IncomingConn:
+        if false {
+            let (_a_ac1, c_ac1) = mpsc::channel::<Vec<u8>>(0);
+            let (c_ca1, _a_ca1) = mpsc::channel::<Vec<u8>>(0);
+            let incoming_conn_accept_a = IncomingConn {
+                public_key: a_public_key.clone(),
+                inner: IncomingConnInner::Accept(IncomingAccept {
+                    receiver: c_ac1,
+                    sender: c_ca1.sink_map_err(|_| ()),
+                    accept_public_key: b_public_key.clone(),
+                }),
+            };
+            await!(outgoing_conns.send(incoming_conn_accept_a)).unwrap();
+
+            let (_p_pc, c_pc) = mpsc::channel::<Vec<u8>>(0);
+            let (c_cp, _p_cp) = mpsc::channel::<ListenerUpdate>(0);
+            let incoming_conn_peer = IncomingConn {
+                public_key: a_public_key.clone(),
+                inner: IncomingConnInner::Peer(IncomingPeer {
+                    receiver: c_pc,
+                    sender: c_cp.sink_map_err(|_| ()),
+                }),
+            };
+            await!(outgoing_conns.send(incoming_conn_peer)).unwrap();
+        }
+
+        // a is no longer allowed. a stops listening, and the pending connection from b is closed:
+        let allowed_nodes = vec![b_public_key.clone()]
+            .into_iter()
+            .collect::<HashSet<_>>();
+        await!(allowed_sender.send(allowed_nodes)).unwrap();
+
+        assert!(await!(a_ca.next()).is_none());
+        assert!(await!(b_cb.next()).is_none());
+    }
+
+    #[test]
+    fn test_relay_server_allowed_nodes() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_relay_server_allowed_nodes(thread_pool.clone()));
+    }
+
+    async fn task_forward_rate_limited(mut spawner: impl Spawn + Clone + Send + 'static) {
+        // Create a mock time service:
+        let (mut tick_sender, tick_receiver) = mpsc::channel::<()>(0);
+        let timer_client = create_timer_incoming(tick_receiver, spawner.clone()).unwrap();
+
+        let (mut a_sender, receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (sender, mut b_receiver) = mpsc::channel::<Vec<u8>>(0);
+
+        let max_bytes_per_tick: usize = 8;
+        let bytes_counter = Arc::new(AtomicUsize::new(0));
+        let forward_fut = forward_rate_limited(
+            receiver,
+            sender.sink_map_err(|_| ()),
+            timer_client,
+            max_bytes_per_tick,
+            bytes_counter.clone(),
+        )
+        .map_err(|e| error!("forward_rate_limited() error: {:?}", e))
+        .map(|_| ());
+        spawner.spawn(forward_fut).unwrap();
+
+        await!(a_sender.send(vec![0; 5])).unwrap();
+        assert_eq!(await!(b_receiver.next()).unwrap(), vec![0; 5]);
+
+        // This message exceeds the quota of the current tick. It is forwarded, and we owe 17 bytes:
+        await!(a_sender.send(vec![1; 20])).unwrap();
+        assert_eq!(await!(b_receiver.next()).unwrap(), vec![1; 20]);
+
+        // The next message is held until the owed bytes are paid, after 3 ticks:
+        await!(a_sender.send(vec![2; 3])).unwrap();
+        for _ in 0..2 {
+            await!(tick_sender.send(())).unwrap();
+        }
+        assert!(b_receiver.try_next().is_err());
+
+        await!(tick_sender.send(())).unwrap();
+        assert_eq!(await!(b_receiver.next()).unwrap(), vec![2; 3]);
+
+        // Closing the sending side closes the tunnel:
+        drop(a_sender);
+        assert!(await!(b_receiver.next()).is_none());
+        assert_eq!(bytes_counter.load(Ordering::Relaxed), 5 + 20 + 3);
+    }
+
+    #[test]
+    fn test_forward_rate_limited() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_forward_rate_limited(thread_pool.clone()));
+    }
+
+    async fn task_relay_server_stats(mut spawner: impl Spawn + Clone + Send + 'static) {
+        // Create a mock time service:
+        let (mut tick_sender, tick_receiver) = mpsc::channel::<()>(0);
+        let timer_client = create_timer_incoming(tick_receiver, spawner.clone()).unwrap();
+
+        let a_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let b_public_key = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let c_public_key = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+        let d_public_key = PublicKey::from(&[0xdd; PUBLIC_KEY_LEN]);
+
+        let (mut outgoing_conns, incoming_conns) = mpsc::channel::<_>(0);
+        let (stats_sender, mut stats_receiver) = mpsc::channel(0);
+        let too_many_conns = Arc::new(AtomicUsize::new(2));
+
+        let fut_relay_server = relay_server_loop(
+            timer_client,
+            incoming_conns,
+            None,
+            stream::empty(),
+            HashSet::new(),
+            stream::empty(),
+            FuncFutTransform::new(|_| Box::pin(future::ready(None))),
+            16,
+            16,
+            0x100,
+            1,
+            too_many_conns,
+            stats_sender,
+            spawner.clone(),
+        );
+
+        spawner
+            .spawn(
+                fut_relay_server
+                    .map_err(|e| error!("relay_server_loop() error: {:?}", e))
+                    .map(|_| ()),
+            )
+            .unwrap();
+
+        // a listens, and b connects to a:
+        let (_a_ac, c_ac) = mpsc::channel::<RejectConnection>(0);
+        let (c_ca, mut a_ca) = mpsc::channel::<IncomingConnection>(0);
+        let incoming_conn_a = IncomingConn {
+            public_key: a_public_key.clone(),
+            inner: IncomingConnInner::Listen(IncomingListen {
+                receiver: c_ac,
+                sender: c_ca.sink_map_err(|_| ()),
+            }),
+        };
+        await!(outgoing_conns.send(incoming_conn_a)).unwrap();
+
+        let (mut b_bc, c_bc) = mpsc::channel::<Vec<u8>>(0);
+        let (c_cb, mut b_cb) = mpsc::channel::<Vec<u8>>(0);
+        let incoming_conn_b = IncomingConn {
+            public_key: b_public_key.clone(),
+            inner: IncomingConnInner::Connect(IncomingConnect {
+                receiver: c_bc,
+                sender: c_cb.sink_map_err(|_| ()),
+                connect_public_key: a_public_key.clone(),
+            }),
+        };
+        await!(outgoing_conns.send(incoming_conn_b)).unwrap();
+
+        assert_eq!(
+            await!(a_ca.next()).unwrap(),
+            IncomingConnection {
+                public_key: b_public_key.clone()
+            }
+        );
+
+        // a accepts the connection from b:
+        let (mut a_ac1, c_ac1) = mpsc::channel::<Vec<u8>>(0);
+        let (c_ca1, mut a_ca1) = mpsc::channel::<Vec<u8>>(0);
+        let incoming_conn_accept_a = IncomingConn {
+            public_key: a_public_key.clone(),
+            inner: IncomingConnInner::Accept(IncomingAccept {
+                receiver: c_ac1,
+                sender: c_ca1.sink_map_err(|_| ()),
+                accept_public_key: b_public_key.clone(),
+            }),
+        };
+        await!(outgoing_conns.send(incoming_conn_accept_a)).unwrap();
+
+        await!(b_bc.send(vec![1, 2, 3])).unwrap();
+        assert_eq!(await!(a_ca1.next()).unwrap(), vec![1, 2, 3]);
+        await!(a_ac1.send(vec![4, 5])).unwrap();
+        assert_eq!(await!(b_cb.next()).unwrap(), vec![4, 5]);
+
+        // This is done to help the compiler deduce the types for
+        // IncomingConn:
+        if false {
+            let (_p_pc, c_pc) = mpsc::channel::<Vec<u8>>(0);
+            let (c_cp, _p_cp) = mpsc::channel::<ListenerUpdate>(0);
+            let incoming_conn_peer = IncomingConn {
+                public_key: a_public_key.clone(),
+                inner: IncomingConnInner::Peer(IncomingPeer {
+                    receiver: c_pc,
+                    sender: c_cp.sink_map_err(|_| ()),
+                }),
+            };
+            await!(outgoing_conns.send(incoming_conn_peer)).unwrap();
+        }
+
+        // c connects to d, which is not listening:
+        let (_c_cd, c_cd) = mpsc::channel::<Vec<u8>>(0);
+        let (c_dc, mut c_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let incoming_conn_c = IncomingConn {
+            public_key: c_public_key.clone(),
+            inner: IncomingConnInner::Connect(IncomingConnect {
+                receiver: c_cd,
+                sender: c_dc.sink_map_err(|_| ()),
+                connect_public_key: d_public_key.clone(),
+            }),
+        };
+        await!(outgoing_conns.send(incoming_conn_c)).unwrap();
+        assert!(await!(c_receiver.next()).is_none());
+
+        // The byte counters are updated asynchronously, therefore we might need a few reports:
+        let relay_stats = loop {
+            await!(tick_sender.send(())).unwrap();
+            let relay_stats: RelayStats = await!(stats_receiver.next()).unwrap();
+            let tunnel_stats = &relay_stats.tunnels[0];
+            if tunnel_stats.bytes_to_listener == 3 && tunnel_stats.bytes_from_listener == 2 {
+                break relay_stats;
+            }
+        };
+
+        assert_eq!(
+            relay_stats.listeners,
+            vec![ListenerStats {
+                public_key: a_public_key.clone(),
+                num_half_tunnels: 0,
+                num_tunnels: 1,
+                bytes_forwarded: 5,
+            }]
+        );
+        assert_eq!(relay_stats.tunnels.len(), 1);
+        let tunnel_stats = &relay_stats.tunnels[0];
+        assert_eq!(tunnel_stats.init_public_key, b_public_key);
+        assert_eq!(tunnel_stats.listen_public_key, a_public_key);
+        assert!(tunnel_stats.ticks_open > 0);
+        assert!(relay_stats.closed_tunnels.is_empty());
+        assert_eq!(
+            relay_stats.rejected_conns,
+            RejectedConns {
+                too_many_conns: 2,
+                not_listening: 1,
+                ..RejectedConns::default()
+            }
+        );
+
+        // b closes the tunnel:
+        drop(b_bc);
+        assert!(await!(a_ca1.next()).is_none());
+
+        let relay_stats = loop {
+            await!(tick_sender.send(())).unwrap();
+            let relay_stats: RelayStats = await!(stats_receiver.next()).unwrap();
+            if !relay_stats.closed_tunnels.is_empty() {
+                break relay_stats;
+            }
+        };
+        assert!(relay_stats.tunnels.is_empty());
+        assert_eq!(relay_stats.closed_tunnels.len(), 1);
+        assert_eq!(relay_stats.closed_tunnels[0].bytes_to_listener, 3);
+        assert_eq!(relay_stats.closed_tunnels[0].bytes_from_listener, 2);
+        assert_eq!(relay_stats.listeners[0].num_tunnels, 0);
+        assert_eq!(relay_stats.listeners[0].bytes_forwarded, 5);
+    }
+
+    #[test]
+    fn test_relay_server_stats() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_relay_server_stats(thread_pool.clone()));
+    }
+
+    async fn task_relay_server_peers(mut spawner: impl Spawn + Clone + Send + 'static) {
+        // Create a mock time service:
+        let (_tick_sender, tick_receiver) = mpsc::channel::<()>(0);
+        let timer_client = create_timer_incoming(tick_receiver, spawner.clone()).unwrap();
+
+        let (mut outgoing_conns, incoming_conns) = mpsc::channel::<_>(0);
+        let (mut peer_events_sender, incoming_peer_events) = mpsc::channel(0);
+        let (conn_request_sender, mut conn_request_receiver) = mpsc::channel(0);
+        let peer_connector = DummyConnector::new(conn_request_sender);
+
+        let a_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let c_public_key = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+        let d_public_key = PublicKey::from(&[0xdd; PUBLIC_KEY_LEN]);
+        let e_public_key = PublicKey::from(&[0xee; PUBLIC_KEY_LEN]);
+        let g_public_key = PublicKey::from(&[0x11; PUBLIC_KEY_LEN]);
+        // A peer relay:
+        let p_public_key = PublicKey::from(&[0x22; PUBLIC_KEY_LEN]);
+
+        let peer_relays = vec![p_public_key.clone()]
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let fut_relay_server = relay_server_loop(
+            timer_client,
+            incoming_conns,
+            None,
+            stream::empty(),
+            peer_relays,
+            incoming_peer_events,
+            peer_connector,
+            16,    // half_tunnel_ticks
+            16,    // max_half_tunnels
+            0x100, // max_tunnel_bytes_per_tick
+            0x10,
+            Arc::new(AtomicUsize::new(0)),
+            mpsc::channel(0).0,
+            spawner.clone(),
+        );
+
+        spawner
+            .spawn(
+                fut_relay_server
+                    .map_err(|e| error!("relay_server_loop() error: {:?}", e))
+                    .map(|_| ()),
+            )
+            .unwrap();
+
+        // a listens:
+        let (a_ac, c_ac) = mpsc::channel::<RejectConnection>(0);
+        let (c_ca, mut a_ca) = mpsc::channel::<IncomingConnection>(0);
+        let incoming_conn_a = IncomingConn {
+            public_key: a_public_key.clone(),
+            inner: IncomingConnInner::Listen(IncomingListen {
+                receiver: c_ac,
+                sender: c_ca.sink_map_err(|_| ()),
+            }),
+        };
+        await!(outgoing_conns.send(incoming_conn_a)).unwrap();
+
+        // g is not a peer relay, and therefore can not subscribe:
+        let (_g_gc, c_gc) = mpsc::channel::<Vec<u8>>(0);
+        let (c_cg, mut g_cg) = mpsc::channel::<ListenerUpdate>(0);
+        let incoming_conn_g = IncomingConn {
+            public_key: g_public_key.clone(),
+            inner: IncomingConnInner::Peer(IncomingPeer {
+                receiver: c_gc,
+                sender: c_cg.sink_map_err(|_| ()),
+            }),
+        };
+        await!(outgoing_conns.send(incoming_conn_g)).unwrap();
+        assert!(await!(g_cg.next()).is_none());
+
+        // p subscribes, and receives the nodes currently listening:
+        let (_p_pc, c_pc) = mpsc::channel::<Vec<u8>>(0);
+        let (c_cp, mut p_cp) = mpsc::channel::<ListenerUpdate>(0);
+        let incoming_conn_p = IncomingConn {
+            public_key: p_public_key.clone(),
+            inner: IncomingConnInner::Peer(IncomingPeer {
+                receiver: c_pc,
+                sender: c_cp.sink_map_err(|_| ()),
+            }),
+        };
+        await!(outgoing_conns.send(incoming_conn_p)).unwrap();
+        assert_eq!(
+            await!(p_cp.next()).unwrap(),
+            ListenerUpdate::Listening(a_public_key.clone())
+        );
+
+        // d listens on p:
+        await!(peer_events_sender.send(PeerEvent::Update((
+            p_public_key.clone(),
+            ListenerUpdate::Listening(d_public_key.clone())
+        ))))
+        .unwrap();
+        // Make sure that the previous event was received by the relay server:
+        await!(peer_events_sender.send(PeerEvent::Update((
+            p_public_key.clone(),
+            ListenerUpdate::NotListening(g_public_key.clone())
+        ))))
+        .unwrap();
+
+        // c connects to d. The connection is forwarded to p:
+        let (mut c_cd, c_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (c_sender, _c_dc) = mpsc::channel::<Vec<u8>>(0);
+        let incoming_conn_c = IncomingConn {
+            public_key: c_public_key.clone(),
+            inner: IncomingConnInner::Connect(IncomingConnect {
+                receiver: c_receiver,
+                sender: c_sender.sink_map_err(|_| ()),
+                connect_public_key: d_public_key.clone(),
+            }),
+        };
+        await!(outgoing_conns.send(incoming_conn_c)).unwrap();
+
+        let conn_request = await!(conn_request_receiver.next()).unwrap();
+        assert_eq!(
+            conn_request.address,
+            (
+                p_public_key.clone(),
+                InitConnection::Forward(ForwardConnection {
+                    init_public_key: c_public_key.clone(),
+                    connect_public_key: d_public_key.clone(),
+                })
+            )
+        );
+        let (peer_sender, mut p_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (_p_sender, peer_receiver) = mpsc::channel::<Vec<u8>>(0);
+        conn_request.reply(Some((peer_sender, peer_receiver)));
+
+        await!(c_cd.send(vec![1, 2, 3])).unwrap();
+        assert_eq!(await!(p_receiver.next()).unwrap(), vec![1, 2, 3]);
+
+        // p forwards a connection from e to a:
+        let (_p_pa, c_pa) = mpsc::channel::<Vec<u8>>(0);
+        let (c_ap, _p_ap) = mpsc::channel::<Vec<u8>>(0);
+        let incoming_conn_forward = IncomingConn {
+            public_key: p_public_key.clone(),
+            inner: IncomingConnInner::Forward(IncomingForward {
+                receiver: c_pa,
+                sender: c_ap.sink_map_err(|_| ()),
+                forward_connection: ForwardConnection {
+                    init_public_key: e_public_key.clone(),
+                    connect_public_key: a_public_key.clone(),
+                },
+            }),
+        };
+        await!(outgoing_conns.send(incoming_conn_forward)).unwrap();
+        assert_eq!(
+            await!(a_ca.next()).unwrap(),
+            IncomingConnection {
+                public_key: e_public_key.clone()
+            }
+        );
+
+        // This is done to help the compiler deduce the types for
+        // IncomingConn:
+        if false {
+            let (_a_ac1, c_ac1) = mpsc::channel::<Vec<u8>>(0);
+            let (c_ca1, _a_ca1) = mpsc::channel::<Vec<u8>>(0);
+            let incoming_conn_accept_a = IncomingConn {
+                public_key: a_public_key.clone(),
+                inner: IncomingConnInner::Accept(IncomingAccept {
+                    receiver: c_ac1,
+                    sender: c_ca1.sink_map_err(|_| ()),
+                    accept_public_key: e_public_key.clone(),
+                }),
+            };
+            await!(outgoing_conns.send(incoming_conn_accept_a)).unwrap();
+        }
+
+        // a stops listening. p is notified:
+        drop(a_ac);
+        assert_eq!(
+            await!(p_cp.next()).unwrap(),
+            ListenerUpdate::NotListening(a_public_key.clone())
+        );
+    }
+
+    #[test]
+    fn test_relay_server_peers() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_relay_server_peers(thread_pool.clone()));
+    }
+
     // TODO: Add tests:
     // - Timeout of half tunnels
     //      (Do some action first, to make sure timer_stream was already obtained).
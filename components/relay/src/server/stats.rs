@@ -0,0 +1,115 @@
+use crypto::identity::PublicKey;
+
+use proto::file::ser_string::public_key_to_string;
+
+/// Amounts of connections rejected by the relay server since it was started, by reason.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RejectedConns {
+    /// Closed because of too many open connections (See `RelayLimits`).
+    pub too_many_conns: usize,
+    /// Listen or Connect from a node that is not allowed to use the relay.
+    pub not_allowed: usize,
+    /// Listen from a node that is already listening.
+    pub already_listening: usize,
+    /// Connect to a node that is not listening.
+    pub not_listening: usize,
+    /// Connect while a connection between the same pair of nodes is already pending.
+    pub already_pending: usize,
+    /// Connect to a listener that has too many pending connections.
+    pub too_many_half_tunnels: usize,
+    /// Connect that could not be reported to the listener.
+    pub listener_busy: usize,
+    /// Pending connection rejected by the listener.
+    pub rejected_by_listener: usize,
+    /// Pending connection that was not accepted in time.
+    pub timed_out: usize,
+    /// Accept without a matching pending connection.
+    pub invalid_accept: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenerStats {
+    pub public_key: PublicKey,
+    /// Amount of connections waiting to be accepted.
+    pub num_half_tunnels: usize,
+    /// Amount of open tunnels to this listener.
+    pub num_tunnels: usize,
+    /// Total amount of bytes forwarded through tunnels to this listener (In both directions),
+    /// since it started listening.
+    pub bytes_forwarded: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TunnelStats {
+    pub init_public_key: PublicKey,
+    pub listen_public_key: PublicKey,
+    /// Amount of ticks since the tunnel was opened.
+    pub ticks_open: usize,
+    /// Amount of bytes forwarded from the initiator to the listener.
+    pub bytes_to_listener: usize,
+    /// Amount of bytes forwarded from the listener to the initiator.
+    pub bytes_from_listener: usize,
+}
+
+/// A periodic report of the state of the relay server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayStats {
+    pub listeners: Vec<ListenerStats>,
+    pub tunnels: Vec<TunnelStats>,
+    /// Tunnels closed since the previous report.
+    pub closed_tunnels: Vec<TunnelStats>,
+    pub rejected_conns: RejectedConns,
+}
+
+fn log_tunnel_stats(state: &str, tunnel: &TunnelStats) {
+    info!(
+        "relay_stats: tunnel={} init={} listen={} ticks_open={} bytes_to_listener={} \
+         bytes_from_listener={}",
+        state,
+        public_key_to_string(&tunnel.init_public_key),
+        public_key_to_string(&tunnel.listen_public_key),
+        tunnel.ticks_open,
+        tunnel.bytes_to_listener,
+        tunnel.bytes_from_listener
+    );
+}
+
+/// Write a relay stats report to the log, one `key=value` record per line.
+pub fn log_relay_stats(relay_stats: &RelayStats) {
+    let rejected = &relay_stats.rejected_conns;
+    info!(
+        "relay_stats: listeners={} tunnels={} closed_tunnels={} rejected_too_many_conns={} \
+         rejected_not_allowed={} rejected_already_listening={} rejected_not_listening={} \
+         rejected_already_pending={} rejected_too_many_half_tunnels={} \
+         rejected_listener_busy={} rejected_by_listener={} rejected_timed_out={} \
+         rejected_invalid_accept={}",
+        relay_stats.listeners.len(),
+        relay_stats.tunnels.len(),
+        relay_stats.closed_tunnels.len(),
+        rejected.too_many_conns,
+        rejected.not_allowed,
+        rejected.already_listening,
+        rejected.not_listening,
+        rejected.already_pending,
+        rejected.too_many_half_tunnels,
+        rejected.listener_busy,
+        rejected.rejected_by_listener,
+        rejected.timed_out,
+        rejected.invalid_accept
+    );
+    for listener in &relay_stats.listeners {
+        info!(
+            "relay_stats: listener={} half_tunnels={} tunnels={} bytes_forwarded={}",
+            public_key_to_string(&listener.public_key),
+            listener.num_half_tunnels,
+            listener.num_tunnels,
+            listener.bytes_forwarded
+        );
+    }
+    for tunnel in &relay_stats.tunnels {
+        log_tunnel_stats("open", tunnel);
+    }
+    for tunnel in &relay_stats.closed_tunnels {
+        log_tunnel_stats("closed", tunnel);
+    }
+}
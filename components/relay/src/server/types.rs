@@ -1,5 +1,7 @@
 use crypto::identity::PublicKey;
 
+use proto::relay::messages::ForwardConnection;
+
 pub struct IncomingListen<M, K> {
     pub receiver: M,
     pub sender: K,
@@ -17,13 +19,41 @@ pub struct IncomingConnect<M, K> {
     pub connect_public_key: PublicKey,
 }
 
-pub enum IncomingConnInner<ML, KL, MA, KA, MC, KC> {
+/// A peer relay subscribing to updates about listening nodes.
+pub struct IncomingPeer<M, K> {
+    pub receiver: M,
+    pub sender: K,
+}
+
+/// A peer relay forwarding a connection to a listening node.
+pub struct IncomingForward<M, K> {
+    pub receiver: M,
+    pub sender: K,
+    pub forward_connection: ForwardConnection,
+}
+
+pub enum IncomingConnInner<ML, KL, MA, KA, MC, KC, KP> {
     Listen(IncomingListen<ML, KL>),
     Accept(IncomingAccept<MA, KA>),
     Connect(IncomingConnect<MC, KC>),
+    Peer(IncomingPeer<MC, KP>),
+    Forward(IncomingForward<MC, KC>),
 }
 
-pub struct IncomingConn<ML, KL, MA, KA, MC, KC> {
+pub struct IncomingConn<ML, KL, MA, KA, MC, KC, KP> {
     pub public_key: PublicKey,
-    pub inner: IncomingConnInner<ML, KL, MA, KA, MC, KC>,
+    pub inner: IncomingConnInner<ML, KL, MA, KA, MC, KC, KP>,
+}
+
+/// Limits on the resources used by the relay server.
+#[derive(Debug, Clone)]
+pub struct RelayLimits {
+    /// Maximum amount of open connections (For all public keys together).
+    pub max_conns: usize,
+    /// Maximum amount of open connections from a single public key (Except for peer relays).
+    pub max_conns_per_public_key: usize,
+    /// Maximum amount of connections waiting to be accepted by a single listener.
+    pub max_half_tunnels: usize,
+    /// Maximum amount of bytes a tunnel may forward in every direction, per tick.
+    pub max_tunnel_bytes_per_tick: usize,
 }
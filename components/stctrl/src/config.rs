@@ -1,11 +1,13 @@
+use std::convert::TryFrom;
 use std::path::PathBuf;
 
 use structopt::StructOpt;
 
 use app::report::{ChannelStatusReport, NodeReport};
 use app::{
-    load_friend_from_file, load_index_server_from_file, load_relay_from_file, AppConfig,
-    NamedIndexServerAddress, NamedRelayAddress, NodeConnection,
+    load_friend_from_file, load_index_server_from_file, load_relay_from_file, AppConfig, Currency,
+    CurrencyBalance, DebtPolicy, FreezePolicy, NamedIndexServerAddress, NamedRelayAddress,
+    NetAddress, NodeConnection, Rate,
 };
 
 use crate::utils::friend_public_key_by_name;
@@ -29,6 +31,14 @@ pub struct RemoveRelayCmd {
     pub relay_name: String,
 }
 
+/// Set the address friends may use to connect to us directly, without a relay.
+#[derive(Clone, Debug, StructOpt)]
+pub struct SetDirectCmd {
+    /// Address friends connect to (For example: "example.com:9600" or "[2001:db8::1]:9600")
+    #[structopt(long = "address", short = "a")]
+    pub address: String,
+}
+
 /// Add index
 #[derive(Clone, Debug, StructOpt)]
 pub struct AddIndexCmd {
@@ -57,6 +67,9 @@ pub struct AddFriendCmd {
     /// Assigned friend name (You can pick any name)
     #[structopt(long = "name", short = "n")]
     pub friend_name: String,
+    /// Currency of the initial balance
+    #[structopt(long = "currency", short = "c")]
+    pub currency: String,
     /// Initial balance with friend
     #[structopt(long = "balance")]
     pub balance: i128,
@@ -120,11 +133,71 @@ pub struct SetFriendMaxDebtCmd {
     /// Friend name
     #[structopt(long = "name", short = "n")]
     pub friend_name: String,
+    /// Currency of the credit line
+    #[structopt(long = "currency", short = "c")]
+    pub currency: String,
     /// Max debt allowed for friend
     #[structopt(long = "mdebt", short = "m")]
     pub max_debt: u128,
 }
 
+/// Set the fee we charge a friend for forwarding its requests.
+/// fee = mul * amount / 2^32 + add
+#[derive(Clone, Debug, StructOpt)]
+pub struct SetFriendRateCmd {
+    /// Friend name
+    #[structopt(long = "name", short = "n")]
+    pub friend_name: String,
+    /// Proportional part of the fee (In units of 2^-32)
+    #[structopt(long = "mul", short = "m")]
+    pub mul: u32,
+    /// Constant part of the fee
+    #[structopt(long = "add", short = "a")]
+    pub add: u32,
+}
+
+/// Automatically adjust friend's maximum allowed debt as the friend repays its debt.
+/// max debt = min(base + repaid * percent / 100, cap)
+#[derive(Clone, Debug, StructOpt)]
+pub struct SetFriendDebtPolicyCmd {
+    /// Friend name
+    #[structopt(long = "name", short = "n")]
+    pub friend_name: String,
+    /// Currency of the credit line adjusted by the policy
+    #[structopt(long = "currency")]
+    pub currency: String,
+    /// Max debt allowed for friend before repaying any debt
+    #[structopt(long = "base", short = "b")]
+    pub base_max_debt: u128,
+    /// Percentage of repaid debt added to the max debt
+    #[structopt(long = "percent", short = "p")]
+    pub repaid_percent: u32,
+    /// Max debt will never exceed this amount
+    #[structopt(long = "cap", short = "c")]
+    pub max_debt_cap: u128,
+    /// Close requests from friend while its debt is above this amount
+    #[structopt(long = "soft-threshold", short = "s")]
+    pub soft_threshold: Option<u128>,
+}
+
+/// Stop adjusting friend's maximum allowed debt automatically.
+/// The current max debt of the friend is kept.
+#[derive(Clone, Debug, StructOpt)]
+pub struct ClearFriendDebtPolicyCmd {
+    /// Friend name
+    #[structopt(long = "name", short = "n")]
+    pub friend_name: String,
+}
+
+/// Settle mutual credit with friend: Stop new requests, wait for pending requests to complete
+/// and exchange a final token committing to the closing balance.
+#[derive(Clone, Debug, StructOpt)]
+pub struct SettleFriendCmd {
+    /// Friend name to settle with
+    #[structopt(long = "name", short = "n")]
+    pub friend_name: String,
+}
+
 /// Reset mutual credit with friend according to friend's terms.
 #[derive(Clone, Debug, StructOpt)]
 pub struct ResetFriendCmd {
@@ -133,6 +206,22 @@ pub struct ResetFriendCmd {
     pub friend_name: String,
 }
 
+/// Set the policy limiting the credits friends may freeze when routing requests through us.
+#[derive(Clone, Debug, StructOpt)]
+pub struct SetFreezePolicyCmd {
+    /// Freeze policy: "unlimited" or "proportional"
+    #[structopt(long = "policy", short = "p")]
+    pub policy: String,
+}
+
+/// Hold incoming payments until they are accepted or rejected.
+#[derive(Clone, Debug, StructOpt)]
+pub struct SetHoldPaymentsCmd {
+    /// Hold payments: "on" or "off"
+    #[structopt(long = "hold")]
+    pub hold: String,
+}
+
 #[derive(Clone, Debug, StructOpt)]
 pub enum ConfigCmd {
     /// Add a relay server
@@ -141,6 +230,12 @@ pub enum ConfigCmd {
     /// Remove a relay server
     #[structopt(name = "remove-relay")]
     RemoveRelay(RemoveRelayCmd),
+    /// Set our direct address
+    #[structopt(name = "set-direct")]
+    SetDirect(SetDirectCmd),
+    /// Stop accepting direct connections from friends
+    #[structopt(name = "unset-direct")]
+    UnsetDirect,
     /// Add an index server
     #[structopt(name = "add-index")]
     AddIndex(AddIndexCmd),
@@ -168,12 +263,30 @@ pub enum ConfigCmd {
     /// Close requests from friend
     #[structopt(name = "close-friend")]
     CloseFriend(CloseFriendCmd),
-    /// Set friend's max debt
+    /// Set friend's max debt (Rejected while a debt policy manages the currency)
     #[structopt(name = "set-friend-max-debt")]
     SetFriendMaxDebt(SetFriendMaxDebtCmd),
+    /// Set friend's forwarding fee rate
+    #[structopt(name = "set-friend-rate")]
+    SetFriendRate(SetFriendRateCmd),
+    /// Set friend's debt policy
+    #[structopt(name = "set-friend-debt-policy")]
+    SetFriendDebtPolicy(SetFriendDebtPolicyCmd),
+    /// Clear friend's debt policy
+    #[structopt(name = "clear-friend-debt-policy")]
+    ClearFriendDebtPolicy(ClearFriendDebtPolicyCmd),
+    /// Settle mutual credit with a friend
+    #[structopt(name = "settle-friend")]
+    SettleFriend(SettleFriendCmd),
     /// Reset mutual credit with a friend according to friend's terms
     #[structopt(name = "reset-friend")]
     ResetFriend(ResetFriendCmd),
+    /// Set the policy for credits frozen by friends
+    #[structopt(name = "set-freeze-policy")]
+    SetFreezePolicy(SetFreezePolicyCmd),
+    /// Hold incoming payments until they are accepted
+    #[structopt(name = "set-hold-payments")]
+    SetHoldPayments(SetHoldPaymentsCmd),
 }
 
 #[derive(Debug)]
@@ -198,6 +311,10 @@ pub enum ConfigError {
     ParseMaxDebtError,
     ChannelNotInconsistent,
     UnknownRemoteResetTerms,
+    ParseFreezePolicyError,
+    ParseCurrencyError,
+    ParseHoldError,
+    ParseDirectAddressError,
 }
 
 async fn config_add_relay(
@@ -244,6 +361,21 @@ async fn config_remove_relay(
     await!(app_config.remove_relay(relay_public_key)).map_err(|_| ConfigError::AppConfigError)
 }
 
+async fn config_set_direct(
+    set_direct_cmd: SetDirectCmd,
+    mut app_config: AppConfig,
+) -> Result<(), ConfigError> {
+    let direct_address = NetAddress::try_from(set_direct_cmd.address)
+        .map_err(|_| ConfigError::ParseDirectAddressError)?;
+
+    await!(app_config.set_direct_address(Some(direct_address)))
+        .map_err(|_| ConfigError::AppConfigError)
+}
+
+async fn config_unset_direct(mut app_config: AppConfig) -> Result<(), ConfigError> {
+    await!(app_config.set_direct_address(None)).map_err(|_| ConfigError::AppConfigError)
+}
+
 async fn config_add_index(
     add_index_cmd: AddIndexCmd,
     mut app_config: AppConfig,
@@ -303,9 +435,12 @@ async fn config_add_friend(
     let AddFriendCmd {
         friend_file,
         friend_name,
+        currency,
         balance,
     } = add_friend_cmd;
 
+    let currency = Currency::try_from(currency).map_err(|_| ConfigError::ParseCurrencyError)?;
+
     for (_friend_public_key, friend_report) in node_report.funder_report.friends {
         if friend_report.name == friend_name {
             return Err(ConfigError::FriendNameAlreadyExists);
@@ -323,7 +458,7 @@ async fn config_add_friend(
         friend_address.public_key,
         friend_address.relays,
         friend_name.to_owned(),
-        balance
+        vec![CurrencyBalance { currency, balance }]
     ))
     .map_err(|_| ConfigError::AppConfigError)?;
     Ok(())
@@ -430,17 +565,99 @@ async fn config_set_friend_max_debt(
 ) -> Result<(), ConfigError> {
     let SetFriendMaxDebtCmd {
         friend_name,
+        currency,
         max_debt,
     } = set_friend_max_debt_cmd;
 
+    let currency = Currency::try_from(currency).map_err(|_| ConfigError::ParseCurrencyError)?;
+
+    let friend_public_key = friend_public_key_by_name(&node_report, &friend_name)
+        .ok_or(ConfigError::FriendNameNotFound)?
+        .clone();
+
+    await!(app_config.set_friend_remote_max_debt(friend_public_key, currency, max_debt))
+        .map_err(|_| ConfigError::AppConfigError)
+}
+
+async fn config_set_friend_rate(
+    set_friend_rate_cmd: SetFriendRateCmd,
+    mut app_config: AppConfig,
+    node_report: NodeReport,
+) -> Result<(), ConfigError> {
+    let SetFriendRateCmd {
+        friend_name,
+        mul,
+        add,
+    } = set_friend_rate_cmd;
+
+    let friend_public_key = friend_public_key_by_name(&node_report, &friend_name)
+        .ok_or(ConfigError::FriendNameNotFound)?
+        .clone();
+
+    let rate = Rate { mul, add };
+
+    await!(app_config.set_friend_rate(friend_public_key, rate))
+        .map_err(|_| ConfigError::AppConfigError)
+}
+
+async fn config_set_friend_debt_policy(
+    set_friend_debt_policy_cmd: SetFriendDebtPolicyCmd,
+    mut app_config: AppConfig,
+    node_report: NodeReport,
+) -> Result<(), ConfigError> {
+    let SetFriendDebtPolicyCmd {
+        friend_name,
+        currency,
+        base_max_debt,
+        repaid_percent,
+        max_debt_cap,
+        soft_threshold,
+    } = set_friend_debt_policy_cmd;
+
     let friend_public_key = friend_public_key_by_name(&node_report, &friend_name)
         .ok_or(ConfigError::FriendNameNotFound)?
         .clone();
 
-    await!(app_config.set_friend_remote_max_debt(friend_public_key, max_debt))
+    let currency = Currency::try_from(currency).map_err(|_| ConfigError::ParseCurrencyError)?;
+
+    let debt_policy = DebtPolicy {
+        currency,
+        base_max_debt,
+        repaid_percent,
+        max_debt_cap,
+        opt_soft_threshold: soft_threshold,
+    };
+
+    await!(app_config.set_friend_debt_policy(friend_public_key, debt_policy))
         .map_err(|_| ConfigError::AppConfigError)
 }
 
+async fn config_clear_friend_debt_policy(
+    clear_friend_debt_policy_cmd: ClearFriendDebtPolicyCmd,
+    mut app_config: AppConfig,
+    node_report: NodeReport,
+) -> Result<(), ConfigError> {
+    let friend_public_key =
+        friend_public_key_by_name(&node_report, &clear_friend_debt_policy_cmd.friend_name)
+            .ok_or(ConfigError::FriendNameNotFound)?
+            .clone();
+
+    await!(app_config.clear_friend_debt_policy(friend_public_key))
+        .map_err(|_| ConfigError::AppConfigError)
+}
+
+async fn config_settle_friend(
+    settle_friend_cmd: SettleFriendCmd,
+    mut app_config: AppConfig,
+    node_report: NodeReport,
+) -> Result<(), ConfigError> {
+    let friend_public_key = friend_public_key_by_name(&node_report, &settle_friend_cmd.friend_name)
+        .ok_or(ConfigError::FriendNameNotFound)?
+        .clone();
+
+    await!(app_config.settle_friend(friend_public_key)).map_err(|_| ConfigError::AppConfigError)
+}
+
 async fn config_reset_friend(
     reset_friend_cmd: ResetFriendCmd,
     mut app_config: AppConfig,
@@ -473,6 +690,33 @@ async fn config_reset_friend(
         .map_err(|_| ConfigError::AppConfigError)
 }
 
+async fn config_set_freeze_policy(
+    set_freeze_policy_cmd: SetFreezePolicyCmd,
+    mut app_config: AppConfig,
+) -> Result<(), ConfigError> {
+    let freeze_policy = match set_freeze_policy_cmd.policy.as_str() {
+        "unlimited" => FreezePolicy::Unlimited,
+        "proportional" => FreezePolicy::Proportional,
+        _ => return Err(ConfigError::ParseFreezePolicyError),
+    };
+
+    await!(app_config.set_freeze_policy(freeze_policy)).map_err(|_| ConfigError::AppConfigError)
+}
+
+async fn config_set_hold_payments(
+    set_hold_payments_cmd: SetHoldPaymentsCmd,
+    mut app_config: AppConfig,
+) -> Result<(), ConfigError> {
+    let hold_incoming_payments = match set_hold_payments_cmd.hold.as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return Err(ConfigError::ParseHoldError),
+    };
+
+    await!(app_config.set_hold_incoming_payments(hold_incoming_payments))
+        .map_err(|_| ConfigError::AppConfigError)
+}
+
 pub async fn config(
     config_cmd: ConfigCmd,
     mut node_connection: NodeConnection,
@@ -500,6 +744,10 @@ pub async fn config(
             app_config,
             node_report
         ))?,
+        ConfigCmd::SetDirect(set_direct_cmd) => {
+            await!(config_set_direct(set_direct_cmd, app_config))?
+        }
+        ConfigCmd::UnsetDirect => await!(config_unset_direct(app_config))?,
         ConfigCmd::AddIndex(add_index_cmd) => {
             await!(config_add_index(add_index_cmd, app_config, node_report))?
         }
@@ -542,11 +790,33 @@ pub async fn config(
         ConfigCmd::SetFriendMaxDebt(set_friend_max_debt_cmd) => await!(
             config_set_friend_max_debt(set_friend_max_debt_cmd, app_config, node_report)
         )?,
+        ConfigCmd::SetFriendRate(set_friend_rate_cmd) => await!(config_set_friend_rate(
+            set_friend_rate_cmd,
+            app_config,
+            node_report
+        ))?,
+        ConfigCmd::SetFriendDebtPolicy(set_friend_debt_policy_cmd) => await!(
+            config_set_friend_debt_policy(set_friend_debt_policy_cmd, app_config, node_report)
+        )?,
+        ConfigCmd::ClearFriendDebtPolicy(clear_friend_debt_policy_cmd) => await!(
+            config_clear_friend_debt_policy(clear_friend_debt_policy_cmd, app_config, node_report)
+        )?,
+        ConfigCmd::SettleFriend(settle_friend_cmd) => await!(config_settle_friend(
+            settle_friend_cmd,
+            app_config,
+            node_report
+        ))?,
         ConfigCmd::ResetFriend(reset_friend_cmd) => await!(config_reset_friend(
             reset_friend_cmd,
             app_config,
             node_report
         ))?,
+        ConfigCmd::SetFreezePolicy(set_freeze_policy_cmd) => {
+            await!(config_set_freeze_policy(set_freeze_policy_cmd, app_config))?
+        }
+        ConfigCmd::SetHoldPayments(set_hold_payments_cmd) => {
+            await!(config_set_hold_payments(set_hold_payments_cmd, app_config))?
+        }
     }
 
     Ok(())
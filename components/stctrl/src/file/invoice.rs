@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::Path;
@@ -8,7 +9,7 @@ use app::ser_string::{
     invoice_id_to_string, public_key_to_string, string_to_invoice_id, string_to_public_key,
     SerStringError,
 };
-use app::PublicKey;
+use app::{Currency, PublicKey};
 
 use app::invoice::InvoiceId;
 
@@ -18,6 +19,7 @@ use toml;
 pub struct Invoice {
     pub invoice_id: InvoiceId,
     pub dest_public_key: PublicKey,
+    pub currency: Currency,
     pub dest_payment: u128,
 }
 
@@ -27,6 +29,7 @@ pub enum InvoiceFileError {
     TomlDeError(toml::de::Error),
     TomlSeError(toml::ser::Error),
     SerStringError,
+    ParseCurrencyError,
     ParseDestPaymentError,
     InvalidPublicKey,
 }
@@ -36,6 +39,7 @@ pub enum InvoiceFileError {
 pub struct InvoiceFile {
     pub invoice_id: String,
     pub dest_public_key: String,
+    pub currency: String,
     pub dest_payment: String,
 }
 
@@ -52,6 +56,8 @@ pub fn load_invoice_from_file(path: &Path) -> Result<Invoice, InvoiceFileError>
 
     let invoice_id = string_to_invoice_id(&invoice_file.invoice_id)?;
     let dest_public_key = string_to_public_key(&invoice_file.dest_public_key)?;
+    let currency = Currency::try_from(invoice_file.currency)
+        .map_err(|_| InvoiceFileError::ParseCurrencyError)?;
     let dest_payment = invoice_file
         .dest_payment
         .parse()
@@ -60,6 +66,7 @@ pub fn load_invoice_from_file(path: &Path) -> Result<Invoice, InvoiceFileError>
     Ok(Invoice {
         invoice_id,
         dest_public_key,
+        currency,
         dest_payment,
     })
 }
@@ -69,12 +76,14 @@ pub fn store_invoice_to_file(invoice: &Invoice, path: &Path) -> Result<(), Invoi
     let Invoice {
         ref invoice_id,
         ref dest_public_key,
+        ref currency,
         dest_payment,
     } = invoice;
 
     let invoice_file = InvoiceFile {
         invoice_id: invoice_id_to_string(invoice_id),
         dest_public_key: public_key_to_string(dest_public_key),
+        currency: currency.to_string(),
         dest_payment: dest_payment.to_string(),
     };
 
@@ -100,6 +109,7 @@ mod tests {
             r#"
             invoice_id = 'invoice_id'
             dest_public_key = 'dest_public_key'
+            currency = 'FST'
             dest_payment = '100'
         "#,
         )
@@ -107,6 +117,7 @@ mod tests {
 
         assert_eq!(invoice_file.invoice_id, "invoice_id");
         assert_eq!(invoice_file.dest_public_key, "dest_public_key");
+        assert_eq!(invoice_file.currency, "FST");
         assert_eq!(invoice_file.dest_payment, "100");
     }
 
@@ -119,6 +130,7 @@ mod tests {
         let invoice = Invoice {
             invoice_id: InvoiceId::from(&[0; INVOICE_ID_LEN]),
             dest_public_key: PublicKey::from(&[1; PUBLIC_KEY_LEN]),
+            currency: Currency::try_from("FST".to_owned()).unwrap(),
             dest_payment: 100,
         };
 
@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::Path;
@@ -8,7 +9,7 @@ use app::ser_string::{
     hash_result_to_string, invoice_id_to_string, signature_to_string, string_to_hash_result,
     string_to_invoice_id, string_to_signature, SerStringError,
 };
-use app::Receipt;
+use app::{Currency, Receipt};
 
 use toml;
 
@@ -18,7 +19,9 @@ pub enum ReceiptFileError {
     TomlDeError(toml::de::Error),
     TomlSeError(toml::ser::Error),
     SerStringError,
+    ParseCurrencyError,
     ParseDestPaymentError,
+    ParseTotalDestPaymentError,
     InvalidPublicKey,
 }
 
@@ -27,7 +30,9 @@ pub enum ReceiptFileError {
 pub struct ReceiptFile {
     pub response_hash: String,
     pub invoice_id: String,
+    pub currency: String,
     pub dest_payment: String,
+    pub total_dest_payment: String,
     pub signature: String,
 }
 
@@ -44,16 +49,24 @@ pub fn load_receipt_from_file(path: &Path) -> Result<Receipt, ReceiptFileError>
 
     let response_hash = string_to_hash_result(&receipt_file.response_hash)?;
     let invoice_id = string_to_invoice_id(&receipt_file.invoice_id)?;
+    let currency = Currency::try_from(receipt_file.currency)
+        .map_err(|_| ReceiptFileError::ParseCurrencyError)?;
     let dest_payment = receipt_file
         .dest_payment
         .parse()
         .map_err(|_| ReceiptFileError::ParseDestPaymentError)?;
+    let total_dest_payment = receipt_file
+        .total_dest_payment
+        .parse()
+        .map_err(|_| ReceiptFileError::ParseTotalDestPaymentError)?;
     let signature = string_to_signature(&receipt_file.signature)?;
 
     Ok(Receipt {
         response_hash,
         invoice_id,
+        currency,
         dest_payment,
+        total_dest_payment,
         signature,
     })
 }
@@ -63,14 +76,18 @@ pub fn store_receipt_to_file(receipt: &Receipt, path: &Path) -> Result<(), Recei
     let Receipt {
         ref response_hash,
         ref invoice_id,
+        ref currency,
         dest_payment,
+        total_dest_payment,
         ref signature,
     } = receipt;
 
     let receipt_file = ReceiptFile {
         response_hash: hash_result_to_string(&response_hash),
         invoice_id: invoice_id_to_string(&invoice_id),
+        currency: currency.to_string(),
         dest_payment: dest_payment.to_string(),
+        total_dest_payment: total_dest_payment.to_string(),
         signature: signature_to_string(&signature),
     };
 
@@ -96,7 +113,9 @@ mod tests {
             r#"
             response_hash = 'response_hash'
             invoice_id = 'invoice_id'
+            currency = 'FST'
             dest_payment = '100'
+            total_dest_payment = '150'
             signature = 'signature'
         "#,
         )
@@ -104,7 +123,9 @@ mod tests {
 
         assert_eq!(receipt_file.response_hash, "response_hash");
         assert_eq!(receipt_file.invoice_id, "invoice_id");
+        assert_eq!(receipt_file.currency, "FST");
         assert_eq!(receipt_file.dest_payment, "100");
+        assert_eq!(receipt_file.total_dest_payment, "150");
         assert_eq!(receipt_file.signature, "signature");
     }
 
@@ -117,7 +138,9 @@ mod tests {
         let receipt = Receipt {
             response_hash: HashResult::from(&[0; HASH_RESULT_LEN]),
             invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+            currency: Currency::try_from("FST".to_owned()).unwrap(),
             dest_payment: 100,
+            total_dest_payment: 150,
             signature: Signature::from(&[2; SIGNATURE_LEN]),
         };
 
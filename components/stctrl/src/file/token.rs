@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::Path;
@@ -10,6 +11,7 @@ use app::ser_string::{
     string_to_hash_result, string_to_public_key, string_to_rand_value, string_to_signature,
     SerStringError,
 };
+use app::{Currency, TokenBalance};
 
 use toml;
 
@@ -21,12 +23,22 @@ pub enum TokenFileError {
     SerStringError,
     ParseInconsistencyCounterError,
     ParseMoveTokenCounterError,
+    ParseCurrencyError,
     ParseBalanceError,
     ParseLocalPendingDebtError,
     ParseRemotePendingDebtError,
     InvalidPublicKey,
 }
 
+/// A helper structure for serialize and deserializing the balance of one currency in a Token.
+#[derive(Serialize, Deserialize)]
+pub struct TokenBalanceFile {
+    pub currency: String,            // Currency,
+    pub balance: String,             // i128,
+    pub local_pending_debt: String,  // u128,
+    pub remote_pending_debt: String, // u128,
+}
+
 /// A helper structure for serialize and deserializing Token.
 #[derive(Serialize, Deserialize)]
 pub struct TokenFile {
@@ -35,11 +47,10 @@ pub struct TokenFile {
     pub remote_public_key: String,     // PublicKey,
     pub inconsistency_counter: String, // u64,
     pub move_token_counter: String,    // u128,
-    pub balance: String,               // i128,
-    pub local_pending_debt: String,    // u128,
-    pub remote_pending_debt: String,   // u128,
     pub rand_nonce: String,            // RandValue,
     pub new_token: String,             // Signature,
+    // Tables must come after all the plain values in TOML:
+    pub balances: Vec<TokenBalanceFile>,
 }
 
 impl From<SerStringError> for TokenFileError {
@@ -48,11 +59,46 @@ impl From<SerStringError> for TokenFileError {
     }
 }
 
+fn load_token_balance(
+    token_balance_file: TokenBalanceFile,
+) -> Result<TokenBalance, TokenFileError> {
+    Ok(TokenBalance {
+        currency: Currency::try_from(token_balance_file.currency)
+            .map_err(|_| TokenFileError::ParseCurrencyError)?,
+        balance: token_balance_file
+            .balance
+            .parse()
+            .map_err(|_| TokenFileError::ParseBalanceError)?,
+        local_pending_debt: token_balance_file
+            .local_pending_debt
+            .parse()
+            .map_err(|_| TokenFileError::ParseLocalPendingDebtError)?,
+        remote_pending_debt: token_balance_file
+            .remote_pending_debt
+            .parse()
+            .map_err(|_| TokenFileError::ParseRemotePendingDebtError)?,
+    })
+}
+
+fn store_token_balance(token_balance: &TokenBalance) -> TokenBalanceFile {
+    TokenBalanceFile {
+        currency: token_balance.currency.to_string(),
+        balance: token_balance.balance.to_string(),
+        local_pending_debt: token_balance.local_pending_debt.to_string(),
+        remote_pending_debt: token_balance.remote_pending_debt.to_string(),
+    }
+}
+
 /// Load Token from a file
 pub fn load_token_from_file(path: &Path) -> Result<MoveTokenHashedReport, TokenFileError> {
     let data = fs::read_to_string(&path)?;
     let token_file: TokenFile = toml::from_str(&data)?;
 
+    let mut balances = Vec::new();
+    for token_balance_file in token_file.balances {
+        balances.push(load_token_balance(token_balance_file)?);
+    }
+
     Ok(MoveTokenHashedReport {
         prefix_hash: string_to_hash_result(&token_file.prefix_hash)?,
         local_public_key: string_to_public_key(&token_file.local_public_key)?,
@@ -65,18 +111,7 @@ pub fn load_token_from_file(path: &Path) -> Result<MoveTokenHashedReport, TokenF
             .move_token_counter
             .parse()
             .map_err(|_| TokenFileError::ParseMoveTokenCounterError)?,
-        balance: token_file
-            .balance
-            .parse()
-            .map_err(|_| TokenFileError::ParseBalanceError)?,
-        local_pending_debt: token_file
-            .local_pending_debt
-            .parse()
-            .map_err(|_| TokenFileError::ParseLocalPendingDebtError)?,
-        remote_pending_debt: token_file
-            .remote_pending_debt
-            .parse()
-            .map_err(|_| TokenFileError::ParseRemotePendingDebtError)?,
+        balances,
         rand_nonce: string_to_rand_value(&token_file.rand_nonce)?,
         new_token: string_to_signature(&token_file.new_token)?,
     })
@@ -93,9 +128,7 @@ pub fn store_token_to_file(
         ref remote_public_key,
         inconsistency_counter,
         move_token_counter,
-        balance,
-        local_pending_debt,
-        remote_pending_debt,
+        ref balances,
         ref rand_nonce,
         ref new_token,
     } = token;
@@ -106,11 +139,9 @@ pub fn store_token_to_file(
         remote_public_key: public_key_to_string(remote_public_key),
         inconsistency_counter: inconsistency_counter.to_string(),
         move_token_counter: move_token_counter.to_string(),
-        balance: balance.to_string(),
-        local_pending_debt: local_pending_debt.to_string(),
-        remote_pending_debt: remote_pending_debt.to_string(),
         rand_nonce: rand_value_to_string(rand_nonce),
         new_token: signature_to_string(new_token),
+        balances: balances.iter().map(store_token_balance).collect(),
     };
 
     let data = toml::to_string(&token_file)?;
@@ -140,11 +171,14 @@ mod tests {
             remote_public_key = 'remote_public_key'
             inconsistency_counter = 'inconsistency_counter'
             move_token_counter = 'move_token_counter'
+            rand_nonce = 'rand_nonce'
+            new_token = 'new_token'
+
+            [[balances]]
+            currency = 'currency'
             balance = 'balance'
             local_pending_debt = 'local_pending_debt'
             remote_pending_debt = 'remote_pending_debt'
-            rand_nonce = 'rand_nonce'
-            new_token = 'new_token'
         "#,
         )
         .unwrap();
@@ -154,11 +188,19 @@ mod tests {
         assert_eq!(token_file.remote_public_key, "remote_public_key");
         assert_eq!(token_file.inconsistency_counter, "inconsistency_counter");
         assert_eq!(token_file.move_token_counter, "move_token_counter");
-        assert_eq!(token_file.balance, "balance");
-        assert_eq!(token_file.local_pending_debt, "local_pending_debt");
-        assert_eq!(token_file.remote_pending_debt, "remote_pending_debt");
         assert_eq!(token_file.rand_nonce, "rand_nonce");
         assert_eq!(token_file.new_token, "new_token");
+        assert_eq!(token_file.balances.len(), 1);
+        assert_eq!(token_file.balances[0].currency, "currency");
+        assert_eq!(token_file.balances[0].balance, "balance");
+        assert_eq!(
+            token_file.balances[0].local_pending_debt,
+            "local_pending_debt"
+        );
+        assert_eq!(
+            token_file.balances[0].remote_pending_debt,
+            "remote_pending_debt"
+        );
     }
 
     #[test]
@@ -173,9 +215,20 @@ mod tests {
             remote_public_key: PublicKey::from(&[2; PUBLIC_KEY_LEN]),
             inconsistency_counter: 8u64,
             move_token_counter: 15u128,
-            balance: -1500i128,
-            local_pending_debt: 10u128,
-            remote_pending_debt: 20u128,
+            balances: vec![
+                TokenBalance {
+                    currency: Currency::try_from("FST".to_owned()).unwrap(),
+                    balance: -1500i128,
+                    local_pending_debt: 10u128,
+                    remote_pending_debt: 20u128,
+                },
+                TokenBalance {
+                    currency: Currency::try_from("USD".to_owned()).unwrap(),
+                    balance: 35i128,
+                    local_pending_debt: 0u128,
+                    remote_pending_debt: 5u128,
+                },
+            ],
             rand_nonce: RandValue::from(&[3; RAND_VALUE_LEN]),
             new_token: Signature::from(&[4; SIGNATURE_LEN]),
         };
@@ -1,17 +1,21 @@
+use std::convert::TryFrom;
 use std::io;
 use std::path::PathBuf;
 
+use app::report::NodeReport;
 use app::ser_string::string_to_public_key;
-use app::{AppRoutes, AppSendFunds, NodeConnection, PublicKey};
+use app::{AppReport, AppRoutes, AppSendFunds, Currency, NodeConnection, PublicKey};
 
+use futures::StreamExt;
 use structopt::StructOpt;
 
 use app::gen::gen_uid;
 use app::invoice::{InvoiceId, INVOICE_ID_LEN};
-use app::route::{FriendsRoute, RouteWithCapacity};
+use app::route::{FriendsRoute, RouteWithCapacity, DEFAULT_MAX_ROUTES};
 
-use crate::file::invoice::load_invoice_from_file;
+use crate::file::invoice::{load_invoice_from_file, Invoice};
 use crate::file::receipt::store_receipt_to_file;
+use crate::utils::friend_public_key_by_name;
 
 /// Send funds to a remote destination
 #[derive(Clone, Debug, StructOpt)]
@@ -19,6 +23,9 @@ pub struct SendFundsCmd {
     /// recipient's public key
     #[structopt(short = "d", long = "dest")]
     pub destination_str: String,
+    /// Currency of the credits to send
+    #[structopt(short = "c", long = "currency")]
+    pub currency: String,
     /// Amount of credits to send
     #[structopt(short = "a", long = "amount")]
     pub dest_payment: u128,
@@ -38,6 +45,50 @@ pub struct PayInvoiceCmd {
     pub receipt_file: PathBuf,
 }
 
+/// Move credits from one friend to another, by paying ourselves along a cycle
+#[derive(Clone, Debug, StructOpt)]
+pub struct RebalanceCmd {
+    /// Name of the friend to move credits from (Our balance with this friend decreases)
+    #[structopt(short = "f", long = "from")]
+    pub from_friend_name: String,
+    /// Name of the friend to move credits to (Our balance with this friend increases)
+    #[structopt(short = "t", long = "to")]
+    pub to_friend_name: String,
+    /// Currency of the credits to move
+    #[structopt(short = "c", long = "currency")]
+    pub currency: String,
+    /// Amount of credits to move
+    #[structopt(short = "a", long = "amount")]
+    pub dest_payment: u128,
+    /// Maximum amount of credits to pay as fees to mediators
+    #[structopt(short = "m", long = "max-fees")]
+    pub max_fees: u128,
+}
+
+/// Wait for a held payment of an invoice to arrive
+#[derive(Clone, Debug, StructOpt)]
+pub struct WaitPaymentCmd {
+    /// Path to the invoice file
+    #[structopt(parse(from_os_str), short = "i", long = "invoice")]
+    pub invoice_file: PathBuf,
+}
+
+/// Accept a held payment of an invoice, issuing a receipt to the buyer
+#[derive(Clone, Debug, StructOpt)]
+pub struct AcceptPaymentCmd {
+    /// Path to the invoice file
+    #[structopt(parse(from_os_str), short = "i", long = "invoice")]
+    pub invoice_file: PathBuf,
+}
+
+/// Reject a held payment of an invoice
+#[derive(Clone, Debug, StructOpt)]
+pub struct RejectPaymentCmd {
+    /// Path to the invoice file
+    #[structopt(parse(from_os_str), short = "i", long = "invoice")]
+    pub invoice_file: PathBuf,
+}
+
 /// Funds sending related commands
 #[derive(Clone, Debug, StructOpt)]
 pub enum FundsCmd {
@@ -47,6 +98,18 @@ pub enum FundsCmd {
     /// Pay an invoice (Using an invoice file)
     #[structopt(name = "pay-invoice")]
     PayInvoice(PayInvoiceCmd),
+    /// Move credits between two friends (Using friend names)
+    #[structopt(name = "rebalance")]
+    Rebalance(RebalanceCmd),
+    /// Wait for a held payment of an invoice (Using an invoice file)
+    #[structopt(name = "wait-payment")]
+    WaitPayment(WaitPaymentCmd),
+    /// Accept a held payment of an invoice (Using an invoice file)
+    #[structopt(name = "accept-payment")]
+    AcceptPayment(AcceptPaymentCmd),
+    /// Reject a held payment of an invoice (Using an invoice file)
+    #[structopt(name = "reject-payment")]
+    RejectPayment(RejectPaymentCmd),
 }
 
 #[derive(Debug)]
@@ -55,6 +118,7 @@ pub enum FundsError {
     NoFundsPermissions,
     NoRoutesPermissions,
     InvalidDestination,
+    ParseCurrencyError,
     ParseAmountError,
     AppRoutesError,
     SendFundsError,
@@ -64,45 +128,50 @@ pub enum FundsError {
     ReceiptAckError,
     LoadInvoiceError,
     WriteError,
+    FriendNameNotFound,
+    HeldPaymentError,
 }
 
-/// Choose a route for pushing `amount` credits
+/// Choose a route for pushing `amount` credits.
+/// The index server reports the fees paid to the mediators of every route. We pick the cheapest
+/// route that can carry `amount` together with its fees.
+/// Returns the chosen route, together with the fees we offer to pay along the route.
 fn choose_route(
     routes_with_capacity: Vec<RouteWithCapacity>,
     amount: u128,
-) -> Result<FriendsRoute, FundsError> {
-    // We naively select the first route we find suitable:
-    // TODO: Possibly improve this later:
+) -> Result<(FriendsRoute, u128), FundsError> {
+    let mut opt_chosen: Option<RouteWithCapacity> = None;
     for route_with_capacity in routes_with_capacity {
-        // TODO: Is this dangerous? How can we do this safely?
-        let length = route_with_capacity.route.len() as u128;
-
-        // For route of length 2 we pay 0. (source and destination are included)
-        // For route of length 3 we pay 1.
-        // ...
-        let extra: u128 = if let Some(extra) = length.checked_sub(2) {
-            extra
-        } else {
-            // This is an invalid route
+        // Source and destination are included in the route:
+        if route_with_capacity.route.len() < 2 {
             warn!(
                 "Received invalid route of length: {}. Skipping route",
                 route_with_capacity.route.len()
             );
             continue;
-        };
+        }
 
-        let total: u128 = if let Some(total) = extra.checked_add(amount) {
+        let total: u128 = if let Some(total) = amount.checked_add(route_with_capacity.fees) {
             total
         } else {
             warn!("Overflow when calculating total payment. Skipping route");
             continue;
         };
 
-        if total <= route_with_capacity.capacity {
-            return Ok(route_with_capacity.route);
+        if total > route_with_capacity.capacity {
+            continue;
+        }
+
+        if let Some(chosen) = &opt_chosen {
+            if chosen.fees <= route_with_capacity.fees {
+                continue;
+            }
         }
+        opt_chosen = Some(route_with_capacity);
     }
-    Err(FundsError::NoSuitableRoute)
+
+    let chosen = opt_chosen.ok_or(FundsError::NoSuitableRoute)?;
+    Ok((chosen.route, chosen.fees))
 }
 
 /// Send funds to a remote destination without using an invoice.
@@ -115,10 +184,13 @@ async fn funds_send_funds(
 ) -> Result<(), FundsError> {
     let SendFundsCmd {
         destination_str,
+        currency,
         dest_payment,
         opt_receipt_file,
     } = send_raw_cmd;
 
+    let currency = Currency::try_from(currency).map_err(|_| FundsError::ParseCurrencyError)?;
+
     // In case the user wants a receipt, make sure that we will be able to write the receipt
     // before we do the actual payment:
     if let Some(receipt_file) = &opt_receipt_file {
@@ -131,29 +203,33 @@ async fn funds_send_funds(
     let destination =
         string_to_public_key(&destination_str).map_err(|_| FundsError::InvalidDestination)?;
 
-    // TODO: We might get routes with the exact capacity,
-    // but this will not be enough for sending our amount because
-    // we also need to pay nodes on the way.
-    // We might need to solve this issue at the index server side
-    // (Should the Server take into account the extra credits that should be paid along the way?).
+    // The index server returns routes that can carry our amount together with the fees paid to
+    // the mediators along the way:
     let routes_with_capacity = await!(app_routes.request_routes(
         dest_payment,
         local_public_key, // source
         destination,
-        None
-    )) // No exclusion of edges
+        None, // No exclusion of edges
+        currency.clone(),
+        DEFAULT_MAX_ROUTES
+    ))
     .map_err(|_| FundsError::AppRoutesError)?;
 
-    let route = choose_route(routes_with_capacity, dest_payment)?;
-    let fees = route.len().checked_sub(2).unwrap();
+    let (route, fees) = choose_route(routes_with_capacity, dest_payment)?;
 
     // A trivial invoice:
     let request_id = gen_uid();
     let invoice_id = InvoiceId::from(&[0; INVOICE_ID_LEN]);
 
-    let receipt =
-        await!(app_send_funds.request_send_funds(request_id, route, invoice_id, dest_payment))
-            .map_err(|_| FundsError::SendFundsError)?;
+    let receipt = await!(app_send_funds.request_send_funds(
+        request_id,
+        route,
+        currency,
+        invoice_id,
+        dest_payment,
+        fees
+    ))
+    .map_err(|_| FundsError::SendFundsError)?;
 
     writeln!(writer, "Payment successful!").map_err(|_| FundsError::WriteError)?;
     writeln!(writer, "Fees: {}", fees).map_err(|_| FundsError::WriteError)?;
@@ -191,21 +267,19 @@ async fn funds_pay_invoice(
     let invoice =
         load_invoice_from_file(&invoice_file).map_err(|_| FundsError::LoadInvoiceError)?;
 
-    // TODO: We might get routes with the exact capacity,
-    // but this will not be enough for sending our amount because
-    // we also need to pay nodes on the way.
-    // We might need to solve this issue at the index server side
-    // (Should the Server take into account the extra credits that should be paid along the way?).
+    // The index server returns routes that can carry our amount together with the fees paid to
+    // the mediators along the way:
     let routes_with_capacity = await!(app_routes.request_routes(
         invoice.dest_payment,
         local_public_key, // source
         invoice.dest_public_key,
-        None
-    )) // No exclusion of edges
+        None, // No exclusion of edges
+        invoice.currency.clone(),
+        DEFAULT_MAX_ROUTES
+    ))
     .map_err(|_| FundsError::AppRoutesError)?;
 
-    let route = choose_route(routes_with_capacity, invoice.dest_payment)?;
-    let fees = route.len().checked_sub(2).unwrap();
+    let (route, fees) = choose_route(routes_with_capacity, invoice.dest_payment)?;
 
     // Randomly generate a request id:
     let request_id = gen_uid();
@@ -213,8 +287,10 @@ async fn funds_pay_invoice(
     let receipt = await!(app_send_funds.request_send_funds(
         request_id,
         route,
+        invoice.currency,
         invoice.invoice_id,
-        invoice.dest_payment
+        invoice.dest_payment,
+        fees
     ))
     .map_err(|_| FundsError::SendFundsError)?;
 
@@ -227,6 +303,141 @@ async fn funds_pay_invoice(
     // We only send the ack if we managed to get the receipt:
     await!(app_send_funds.receipt_ack(request_id, receipt)).map_err(|_| FundsError::ReceiptAckError)
 }
+
+/// Move credits from one friend to another
+async fn funds_rebalance(
+    rebalance_cmd: RebalanceCmd,
+    node_report: NodeReport,
+    mut app_send_funds: AppSendFunds,
+    writer: &mut impl io::Write,
+) -> Result<(), FundsError> {
+    let RebalanceCmd {
+        from_friend_name,
+        to_friend_name,
+        currency,
+        dest_payment,
+        max_fees,
+    } = rebalance_cmd;
+
+    let currency = Currency::try_from(currency).map_err(|_| FundsError::ParseCurrencyError)?;
+
+    let out_friend_public_key = friend_public_key_by_name(&node_report, &from_friend_name)
+        .ok_or(FundsError::FriendNameNotFound)?
+        .clone();
+    let in_friend_public_key = friend_public_key_by_name(&node_report, &to_friend_name)
+        .ok_or(FundsError::FriendNameNotFound)?
+        .clone();
+
+    // Randomly generate a request id:
+    let request_id = gen_uid();
+
+    let receipt = await!(app_send_funds.request_rebalance(
+        request_id,
+        out_friend_public_key,
+        in_friend_public_key,
+        currency,
+        dest_payment,
+        max_fees
+    ))
+    .map_err(|_| FundsError::SendFundsError)?;
+
+    writeln!(writer, "Rebalance successful!").map_err(|_| FundsError::WriteError)?;
+
+    // We only send the ack if we managed to get the receipt:
+    await!(app_send_funds.receipt_ack(request_id, receipt)).map_err(|_| FundsError::ReceiptAckError)
+}
+
+/// Show a held payment, warning if it does not match the invoice.
+fn write_held_payment(
+    invoice: &Invoice,
+    currency: &Currency,
+    total_dest_payment: u128,
+    writer: &mut impl io::Write,
+) -> Result<(), FundsError> {
+    writeln!(writer, "Payment held: {} {}", total_dest_payment, currency)
+        .map_err(|_| FundsError::WriteError)?;
+    if currency != &invoice.currency || total_dest_payment != invoice.dest_payment {
+        writeln!(writer, "Warning: The payment does not match the invoice!")
+            .map_err(|_| FundsError::WriteError)?;
+    }
+    Ok(())
+}
+
+/// Wait until the payment for an invoice arrives, and is held by the node.
+/// If the payment is already held, return immediately.
+async fn funds_wait_payment(
+    wait_payment_cmd: WaitPaymentCmd,
+    mut app_report: AppReport,
+    mut app_send_funds: AppSendFunds,
+    writer: &mut impl io::Write,
+) -> Result<(), FundsError> {
+    let invoice = load_invoice_from_file(&wait_payment_cmd.invoice_file)
+        .map_err(|_| FundsError::LoadInvoiceError)?;
+
+    // We subscribe to new held payments before checking the report, so that a payment held in
+    // between is not missed:
+    let mut incoming_held_payments = await!(app_send_funds.incoming_held_payments())
+        .map_err(|_| FundsError::HeldPaymentError)?;
+
+    let (node_report, incoming_mutations) =
+        await!(app_report.incoming_reports()).map_err(|_| FundsError::GetReportError)?;
+    drop(incoming_mutations);
+
+    let opt_held_payment_report = node_report
+        .funder_report
+        .held_payments
+        .iter()
+        .find(|held_payment_report| held_payment_report.invoice_id == invoice.invoice_id);
+    if let Some(held_payment_report) = opt_held_payment_report {
+        return write_held_payment(
+            &invoice,
+            &held_payment_report.currency,
+            held_payment_report.total_dest_payment,
+            writer,
+        );
+    }
+
+    while let Some(held_payment) = await!(incoming_held_payments.next()) {
+        if held_payment.invoice_id != invoice.invoice_id {
+            // A payment for another invoice
+            continue;
+        }
+
+        write_held_payment(
+            &invoice,
+            &held_payment.currency,
+            held_payment.total_dest_payment,
+            writer,
+        )?;
+        writeln!(writer, "Ticks left to accept: {}", held_payment.left_ticks)
+            .map_err(|_| FundsError::WriteError)?;
+        return Ok(());
+    }
+    Err(FundsError::HeldPaymentError)
+}
+
+async fn funds_accept_payment(
+    accept_payment_cmd: AcceptPaymentCmd,
+    mut app_send_funds: AppSendFunds,
+) -> Result<(), FundsError> {
+    let invoice = load_invoice_from_file(&accept_payment_cmd.invoice_file)
+        .map_err(|_| FundsError::LoadInvoiceError)?;
+
+    await!(app_send_funds.accept_held_payment(invoice.invoice_id))
+        .map_err(|_| FundsError::HeldPaymentError)
+}
+
+async fn funds_reject_payment(
+    reject_payment_cmd: RejectPaymentCmd,
+    mut app_send_funds: AppSendFunds,
+) -> Result<(), FundsError> {
+    let invoice = load_invoice_from_file(&reject_payment_cmd.invoice_file)
+        .map_err(|_| FundsError::LoadInvoiceError)?;
+
+    await!(app_send_funds.reject_held_payment(invoice.invoice_id))
+        .map_err(|_| FundsError::HeldPaymentError)
+}
+
 pub async fn funds(
     funds_cmd: FundsCmd,
     mut node_connection: NodeConnection,
@@ -266,6 +477,24 @@ pub async fn funds(
             app_send_funds,
             writer,
         ))?,
+        FundsCmd::Rebalance(rebalance_cmd) => await!(funds_rebalance(
+            rebalance_cmd,
+            node_report,
+            app_send_funds,
+            writer,
+        ))?,
+        FundsCmd::WaitPayment(wait_payment_cmd) => await!(funds_wait_payment(
+            wait_payment_cmd,
+            app_report,
+            app_send_funds,
+            writer
+        ))?,
+        FundsCmd::AcceptPayment(accept_payment_cmd) => {
+            await!(funds_accept_payment(accept_payment_cmd, app_send_funds))?
+        }
+        FundsCmd::RejectPayment(reject_payment_cmd) => {
+            await!(funds_reject_payment(reject_payment_cmd, app_send_funds))?
+        }
     }
 
     Ok(())
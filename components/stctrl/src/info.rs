@@ -1,14 +1,23 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::io;
 use std::path::PathBuf;
 
 use prettytable::Table;
 use structopt::StructOpt;
 
+use app::history::{PaymentDirection, PaymentStatus};
 use app::report::{
     ChannelStatusReport, FriendReport, FriendStatusReport, NodeReport, RequestsStatusReport,
+    SettleStatusReport,
+};
+use app::ser_string::{
+    invoice_id_to_string, public_key_to_string, string_to_invoice_id, string_to_public_key,
+};
+use app::{
+    store_friend_to_file, AppHistory, AppReport, AppRoutes, ChannelAddress, Currency,
+    CurrencyBalance, FriendAddress, NodeConnection,
 };
-use app::ser_string::public_key_to_string;
-use app::{store_friend_to_file, AppReport, FriendAddress, NodeConnection, RelayAddress};
 
 use crate::file::token::store_token_to_file;
 use crate::utils::friend_public_key_by_name;
@@ -40,10 +49,41 @@ pub struct FriendLastTokenCmd {
     pub output_file: PathBuf,
 }
 
+/// Export the final token obtained from a friend after settling
+#[derive(Clone, Debug, StructOpt)]
+pub struct FriendSettleTokenCmd {
+    /// Friend's name
+    #[structopt(short = "n", long = "name")]
+    pub friend_name: String,
+    /// Path for output token file
+    #[structopt(short = "o", long = "output")]
+    pub output_file: PathBuf,
+}
+
 /// Display balance summary
 #[derive(Clone, Debug, StructOpt)]
 pub struct BalanceCmd {}
 
+/// Show the payment history
+#[derive(Clone, Debug, StructOpt)]
+pub struct HistoryCmd {
+    /// Show only payments of this direction (outgoing, incoming or mediated)
+    #[structopt(short = "d", long = "direction")]
+    pub direction: Option<String>,
+    /// Show only payments with this status (success or failure)
+    #[structopt(short = "s", long = "status")]
+    pub status: Option<String>,
+    /// Show only payments for this invoice id
+    #[structopt(short = "i", long = "invoice")]
+    pub invoice_id: Option<String>,
+    /// Amount of most recent matching payments to skip
+    #[structopt(short = "o", long = "offset", default_value = "0")]
+    pub offset: u64,
+    /// Maximum amount of payments to show
+    #[structopt(short = "c", long = "count", default_value = "32")]
+    pub count: u64,
+}
+
 /// Export a ticket of this node's contact information
 #[derive(Clone, Debug, StructOpt)]
 pub struct ExportTicketCmd {
@@ -52,6 +92,17 @@ pub struct ExportTicketCmd {
     pub output_file: PathBuf,
 }
 
+/// Show the maximum amount of credits that can currently be sent to a remote destination
+#[derive(Clone, Debug, StructOpt)]
+pub struct MaxSendCmd {
+    /// recipient's public key
+    #[structopt(short = "d", long = "dest")]
+    pub destination_str: String,
+    /// Currency of the credits to send
+    #[structopt(short = "c", long = "currency")]
+    pub currency: String,
+}
+
 #[derive(Clone, Debug, StructOpt)]
 pub enum InfoCmd {
     /// Show local public key (Used as address for sending funds)
@@ -69,12 +120,21 @@ pub enum InfoCmd {
     /// Export friend's last token
     #[structopt(name = "friend-last-token")]
     FriendLastToken(FriendLastTokenCmd),
+    /// Export friend's final token after settling
+    #[structopt(name = "friend-settle-token")]
+    FriendSettleToken(FriendSettleTokenCmd),
     /// Show current balance
     #[structopt(name = "balance")]
     Balance(BalanceCmd),
     /// Export ticket for this node
     #[structopt(name = "export-ticket")]
     ExportTicket(ExportTicketCmd),
+    /// Show payment history
+    #[structopt(name = "history")]
+    History(HistoryCmd),
+    /// Show the maximum amount of credits that can be sent to a destination
+    #[structopt(name = "max-send")]
+    MaxSend(MaxSendCmd),
 }
 
 #[derive(Debug)]
@@ -86,7 +146,17 @@ pub enum InfoError {
     FriendNameNotFound,
     MissingLastIncomingMoveToken,
     StoreLastIncomingMoveTokenError,
+    FriendNotSettled,
     WriteError,
+    ParseDirectionError,
+    ParseStatusError,
+    ParseInvoiceIdError,
+    GetHistoryError,
+    NoRoutesPermissions,
+    ParseCurrencyError,
+    InvalidDestination,
+    GetRoutesError,
+    MaxSendOverflow,
 }
 
 /// Get a most recently known node report:
@@ -134,6 +204,10 @@ pub async fn info_relays(
     } else {
         writeln!(writer, "No configured relay servers.").map_err(|_| InfoError::WriteError)?;
     }
+    if let Some(direct_address) = &report.funder_report.opt_direct_address {
+        writeln!(writer, "Direct address: {}", direct_address)
+            .map_err(|_| InfoError::WriteError)?;
+    }
     Ok(())
 }
 
@@ -189,6 +263,17 @@ fn is_consistent(friend_report: &FriendReport) -> bool {
 }
 */
 
+/// A user friendly string of a list of balances, one for every currency
+fn currency_balances_str(currency_balances: &[CurrencyBalance]) -> String {
+    currency_balances
+        .iter()
+        .map(|currency_balance| {
+            format!("{}:{}", currency_balance.currency, currency_balance.balance)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 /// A user friendly string explaining the current channel status
 fn friend_channel_status(friend_report: &FriendReport) -> String {
     let mut res = String::new();
@@ -200,26 +285,37 @@ fn friend_channel_status(friend_report: &FriendReport) -> String {
 
             res += &format!("LR={}, RR={}\n", local_requests_str, remote_requests_str);
 
-            let balance = &tc_report.balance;
-            res += &format!(
-                "B  ={}\nLMD={}\nRMD={}\nLPD={}\nRPD={}\n",
-                balance.balance,
-                balance.local_max_debt,
-                balance.remote_max_debt,
-                balance.local_pending_debt,
-                balance.remote_pending_debt
-            );
+            match &friend_report.settle_status {
+                SettleStatusReport::Active => {}
+                SettleStatusReport::Settling => res += "SETTLING\n",
+                SettleStatusReport::Settled => res += "SETTLED\n",
+            }
+
+            for balance in &tc_report.balances {
+                res += &format!(
+                    "[{}]\nB  ={}\nLMD={}\nRMD={}\nLPD={}\nRPD={}\n",
+                    balance.currency,
+                    balance.balance,
+                    balance.local_max_debt,
+                    balance.remote_max_debt,
+                    balance.local_pending_debt,
+                    balance.remote_pending_debt
+                );
+            }
         }
         ChannelStatusReport::Inconsistent(channel_inconsistent_report) => {
             res += "I:\n";
             res += &format!(
                 "LT={}\n",
-                channel_inconsistent_report.local_reset_terms_balance
+                currency_balances_str(&channel_inconsistent_report.local_reset_terms_balance)
             );
             match &channel_inconsistent_report.opt_remote_reset_terms {
                 Some(remote_reset_terms) => {
                     // TODO: Possibly negate this value? Maybe we should do it at the funder?
-                    res += &format!("RT={}", remote_reset_terms.balance_for_reset);
+                    res += &format!(
+                        "RT={}",
+                        currency_balances_str(&remote_reset_terms.balance_for_reset)
+                    );
                 }
                 None => {
                     res += "RT=?";
@@ -313,13 +409,63 @@ pub async fn info_friend_last_token(
         .map_err(|_| InfoError::StoreLastIncomingMoveTokenError)
 }
 
-/// Get an approximate value for mutual balance with a friend.
+/// Obtain the final move token received from a friend after settling the mutual credit.
+/// This is a signed commitment made by the friend to the closing balance, and can be used as a
+/// proof of the debt.
+pub async fn info_friend_settle_token(
+    friend_settle_token_cmd: FriendSettleTokenCmd,
+    mut app_report: AppReport,
+) -> Result<(), InfoError> {
+    let FriendSettleTokenCmd {
+        friend_name,
+        output_file,
+    } = friend_settle_token_cmd;
+
+    if output_file.exists() {
+        return Err(InfoError::OutputFileAlreadyExists);
+    }
+
+    let node_report = await!(get_report(&mut app_report))?;
+
+    let friend_public_key = friend_public_key_by_name(&node_report, &friend_name)
+        .ok_or(InfoError::FriendNameNotFound)?;
+
+    let friend_report = node_report
+        .funder_report
+        .friends
+        .get(&friend_public_key)
+        .unwrap();
+
+    if friend_report.settle_status != SettleStatusReport::Settled {
+        return Err(InfoError::FriendNotSettled);
+    }
+
+    let last_incoming_move_token = friend_report
+        .opt_last_incoming_move_token
+        .as_ref()
+        .ok_or(InfoError::MissingLastIncomingMoveToken)?;
+
+    store_token_to_file(last_incoming_move_token, &output_file)
+        .map_err(|_| InfoError::StoreLastIncomingMoveTokenError)
+}
+
+/// Get an approximate value for mutual balance with a friend, for every currency.
 /// In case of an inconsistency we take the local reset terms to represent the balance.
-fn friend_balance(friend_report: &FriendReport) -> i128 {
+fn friend_balances(friend_report: &FriendReport) -> Vec<(Currency, i128)> {
     match &friend_report.channel_status {
-        ChannelStatusReport::Consistent(tc_report) => tc_report.balance.balance,
+        ChannelStatusReport::Consistent(tc_report) => tc_report
+            .balances
+            .iter()
+            .map(|balance| (balance.currency.clone(), balance.balance))
+            .collect(),
         ChannelStatusReport::Inconsistent(channel_inconsistent_report) => {
-            channel_inconsistent_report.local_reset_terms_balance
+            channel_inconsistent_report
+                .local_reset_terms_balance
+                .iter()
+                .map(|currency_balance| {
+                    (currency_balance.currency.clone(), currency_balance.balance)
+                })
+                .collect()
         }
     }
 }
@@ -330,14 +476,23 @@ pub async fn info_balance(
 ) -> Result<(), InfoError> {
     let report = await!(get_report(&mut app_report))?;
 
-    let mut total_balance: i128 = 0;
+    // Total balance for every currency, sorted by currency:
+    let mut total_balances: BTreeMap<Currency, i128> = BTreeMap::new();
     for (_friend_public_key, friend_report) in &report.funder_report.friends {
-        total_balance = total_balance
-            .checked_add(friend_balance(&friend_report))
-            .ok_or(InfoError::BalanceOverflow)?;
+        for (currency, balance) in friend_balances(&friend_report) {
+            let total_balance = total_balances.entry(currency).or_insert(0);
+            *total_balance = total_balance
+                .checked_add(balance)
+                .ok_or(InfoError::BalanceOverflow)?;
+        }
     }
 
-    writeln!(writer, "{}", total_balance).map_err(|_| InfoError::WriteError)?;
+    if total_balances.is_empty() {
+        writeln!(writer, "No balances.").map_err(|_| InfoError::WriteError)?;
+    }
+    for (currency, total_balance) in &total_balances {
+        writeln!(writer, "{}: {}", currency, total_balance).map_err(|_| InfoError::WriteError)?;
+    }
     Ok(())
 }
 
@@ -352,11 +507,19 @@ pub async fn info_export_ticket(
     }
 
     let report = await!(get_report(&mut app_report))?;
-    let relays: Vec<RelayAddress> = report
+    // Friends should try to connect to us directly before going through a relay:
+    let relays: Vec<ChannelAddress> = report
         .funder_report
-        .relays
+        .opt_direct_address
         .into_iter()
-        .map(Into::into)
+        .map(ChannelAddress::Direct)
+        .chain(
+            report
+                .funder_report
+                .relays
+                .into_iter()
+                .map(|named_relay_address| ChannelAddress::Relay(named_relay_address.into())),
+        )
         .collect();
 
     let node_address = FriendAddress {
@@ -370,6 +533,150 @@ pub async fn info_export_ticket(
     Ok(())
 }
 
+fn payment_direction_str(payment_direction: PaymentDirection) -> &'static str {
+    match payment_direction {
+        PaymentDirection::Outgoing => "out",
+        PaymentDirection::Incoming => "in",
+        PaymentDirection::Mediated => "med",
+    }
+}
+
+fn payment_status_str(payment_status: PaymentStatus) -> &'static str {
+    match payment_status {
+        PaymentStatus::Success => "success",
+        PaymentStatus::Failure => "failure",
+    }
+}
+
+/// Show the payment history, from the most recent payment to the oldest
+pub async fn info_history(
+    history_cmd: HistoryCmd,
+    mut app_history: AppHistory,
+    writer: &mut impl io::Write,
+) -> Result<(), InfoError> {
+    let HistoryCmd {
+        direction,
+        status,
+        invoice_id,
+        offset,
+        count,
+    } = history_cmd;
+
+    let opt_direction = match direction.as_ref().map(String::as_str) {
+        None => None,
+        Some("outgoing") => Some(PaymentDirection::Outgoing),
+        Some("incoming") => Some(PaymentDirection::Incoming),
+        Some("mediated") => Some(PaymentDirection::Mediated),
+        Some(_) => return Err(InfoError::ParseDirectionError),
+    };
+
+    let opt_status = match status.as_ref().map(String::as_str) {
+        None => None,
+        Some("success") => Some(PaymentStatus::Success),
+        Some("failure") => Some(PaymentStatus::Failure),
+        Some(_) => return Err(InfoError::ParseStatusError),
+    };
+
+    let opt_invoice_id = match invoice_id {
+        None => None,
+        Some(invoice_id_str) => Some(
+            string_to_invoice_id(&invoice_id_str).map_err(|_| InfoError::ParseInvoiceIdError)?,
+        ),
+    };
+
+    let payment_records = await!(app_history.request_payment_history(
+        opt_direction,
+        opt_status,
+        opt_invoice_id,
+        offset,
+        count
+    ))
+    .map_err(|_| InfoError::GetHistoryError)?;
+
+    let mut table = Table::new();
+    // Add title:
+    table.set_titles(row![
+        "time", "dir", "status", "currency", "amount", "fees", "hops", "invoice"
+    ]);
+
+    for payment_record in &payment_records {
+        table.add_row(row![
+            payment_record.timestamp,
+            payment_direction_str(payment_record.direction),
+            payment_status_str(payment_record.status),
+            payment_record.currency,
+            payment_record.dest_payment,
+            payment_record.fees,
+            payment_record.route.public_keys.len().saturating_sub(1),
+            invoice_id_to_string(&payment_record.invoice_id),
+        ]);
+    }
+
+    if !table.is_empty() {
+        table.print(writer).map_err(|_| InfoError::WriteError)?;
+    } else {
+        writeln!(writer, "No matching payments.").map_err(|_| InfoError::WriteError)?;
+    }
+    Ok(())
+}
+
+/// Show the maximum amount of credits that can be sent to a destination, and the routes that
+/// carry it
+pub async fn info_max_send(
+    max_send_cmd: MaxSendCmd,
+    mut app_report: AppReport,
+    mut app_routes: AppRoutes,
+    writer: &mut impl io::Write,
+) -> Result<(), InfoError> {
+    let MaxSendCmd {
+        destination_str,
+        currency,
+    } = max_send_cmd;
+
+    let currency = Currency::try_from(currency).map_err(|_| InfoError::ParseCurrencyError)?;
+    let destination =
+        string_to_public_key(&destination_str).map_err(|_| InfoError::InvalidDestination)?;
+
+    let report = await!(get_report(&mut app_report))?;
+    let local_public_key = report.funder_report.local_public_key.clone();
+
+    let routes_with_capacity =
+        await!(app_routes.request_max_flow_routes(local_public_key, destination, currency))
+            .map_err(|_| InfoError::GetRoutesError)?;
+
+    let mut table = Table::new();
+    // Add title:
+    table.set_titles(row!["hops", "capacity", "fees", "amount"]);
+
+    let mut max_send: u128 = 0;
+    for route_with_capacity in &routes_with_capacity {
+        // The amount the destination receives through this route:
+        let amount = route_with_capacity
+            .capacity
+            .saturating_sub(route_with_capacity.fees);
+        max_send = max_send
+            .checked_add(amount)
+            .ok_or(InfoError::MaxSendOverflow)?;
+
+        table.add_row(row![
+            route_with_capacity
+                .route
+                .public_keys
+                .len()
+                .saturating_sub(1),
+            route_with_capacity.capacity,
+            route_with_capacity.fees,
+            amount,
+        ]);
+    }
+
+    if !table.is_empty() {
+        table.print(writer).map_err(|_| InfoError::WriteError)?;
+    }
+    writeln!(writer, "Max send: {}", max_send).map_err(|_| InfoError::WriteError)?;
+    Ok(())
+}
+
 pub async fn info(
     info_cmd: InfoCmd,
     mut node_connection: NodeConnection,
@@ -385,10 +692,25 @@ pub async fn info(
         InfoCmd::FriendLastToken(friend_last_token_cmd) => {
             await!(info_friend_last_token(friend_last_token_cmd, app_report))?
         }
+        InfoCmd::FriendSettleToken(friend_settle_token_cmd) => await!(info_friend_settle_token(
+            friend_settle_token_cmd,
+            app_report
+        ))?,
         InfoCmd::Balance(_balance_cmd) => await!(info_balance(app_report, writer))?,
         InfoCmd::ExportTicket(export_ticket_cmd) => {
             await!(info_export_ticket(export_ticket_cmd, app_report))?
         }
+        InfoCmd::History(history_cmd) => {
+            let app_history = node_connection.history().clone();
+            await!(info_history(history_cmd, app_history, writer))?
+        }
+        InfoCmd::MaxSend(max_send_cmd) => {
+            let app_routes = node_connection
+                .routes()
+                .ok_or(InfoError::NoRoutesPermissions)?
+                .clone();
+            await!(info_max_send(max_send_cmd, app_report, app_routes, writer))?
+        }
     }
     Ok(())
 }
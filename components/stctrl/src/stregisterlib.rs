@@ -1,10 +1,11 @@
+use std::convert::TryFrom;
 use std::io;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
 use app::gen::gen_invoice_id;
 use app::ser_string::{public_key_to_string, string_to_public_key};
-use app::{verify_move_token_hashed_report, verify_receipt};
+use app::{verify_move_token_hashed_report, verify_receipt, Currency};
 
 use crate::file::invoice::{load_invoice_from_file, store_invoice_to_file, Invoice};
 use crate::file::receipt::load_receipt_from_file;
@@ -16,10 +17,12 @@ pub enum StRegisterError {
     StoreInvoiceError,
     LoadInvoiceError,
     LoadReceiptError,
+    CurrencyMismatch,
     DestPaymentMismatch,
     InvoiceIdMismatch,
     InvalidReceipt,
     ParsePublicKeyError,
+    ParseCurrencyError,
     LoadTokenError,
     TokenInvalid,
     WriteError,
@@ -31,6 +34,9 @@ pub struct GenInvoiceCmd {
     /// Payment recipient's public key (In base 64)
     #[structopt(short = "p", long = "pubkey")]
     pub public_key: String,
+    /// Currency of the payment
+    #[structopt(short = "c", long = "currency")]
+    pub currency: String,
     /// Amount of credits to pay (A non negative integer)
     #[structopt(short = "a", long = "amount")]
     pub amount: u128,
@@ -81,9 +87,13 @@ fn subcommand_gen_invoice(gen_invoice_cmd: GenInvoiceCmd) -> Result<(), StRegist
     let dest_public_key = string_to_public_key(&gen_invoice_cmd.public_key)
         .map_err(|_| StRegisterError::ParsePublicKeyError)?;
 
+    let currency = Currency::try_from(gen_invoice_cmd.currency)
+        .map_err(|_| StRegisterError::ParseCurrencyError)?;
+
     let invoice = Invoice {
         invoice_id,
         dest_public_key,
+        currency,
         dest_payment: gen_invoice_cmd.amount,
     };
 
@@ -112,8 +122,14 @@ fn subcommand_verify_receipt(
     if invoice.invoice_id != receipt.invoice_id {
         return Err(StRegisterError::InvoiceIdMismatch);
     }
-    // Verify dest_payment match:
-    if invoice.dest_payment != receipt.dest_payment {
+    // Verify currency match:
+    if invoice.currency != receipt.currency {
+        return Err(StRegisterError::CurrencyMismatch);
+    }
+    // Verify dest_payment match.
+    // Note that a receipt for one part of a payment split over multiple routes is issued only
+    // after the total payment has arrived, so we compare against the total payment:
+    if invoice.dest_payment != receipt.total_dest_payment {
         return Err(StRegisterError::DestPaymentMismatch);
     }
 
@@ -158,26 +174,31 @@ fn subcommand_verify_token(
             move_token_hashed_report.inconsistency_counter
         )
         .map_err(|_| StRegisterError::WriteError)?;
-        writeln!(writer, "balance: {}", move_token_hashed_report.balance)
-            .map_err(|_| StRegisterError::WriteError)?;
         writeln!(
             writer,
             "move_token_counter: {}",
             move_token_hashed_report.move_token_counter
         )
         .map_err(|_| StRegisterError::WriteError)?;
-        writeln!(
-            writer,
-            "local_pending_debt: {}",
-            move_token_hashed_report.local_pending_debt
-        )
-        .map_err(|_| StRegisterError::WriteError)?;
-        writeln!(
-            writer,
-            "remote_pending_debt: {}",
-            move_token_hashed_report.remote_pending_debt
-        )
-        .map_err(|_| StRegisterError::WriteError)?;
+        for token_balance in &move_token_hashed_report.balances {
+            writeln!(writer).map_err(|_| StRegisterError::WriteError)?;
+            writeln!(writer, "currency: {}", token_balance.currency)
+                .map_err(|_| StRegisterError::WriteError)?;
+            writeln!(writer, "balance: {}", token_balance.balance)
+                .map_err(|_| StRegisterError::WriteError)?;
+            writeln!(
+                writer,
+                "local_pending_debt: {}",
+                token_balance.local_pending_debt
+            )
+            .map_err(|_| StRegisterError::WriteError)?;
+            writeln!(
+                writer,
+                "remote_pending_debt: {}",
+                token_balance.remote_pending_debt
+            )
+            .map_err(|_| StRegisterError::WriteError)?;
+        }
 
         Ok(())
     } else {
@@ -2,6 +2,9 @@ use std::{str, thread, time};
 
 use tempfile::tempdir;
 
+use index_server::GraphBackend;
+
+use bin::stindexadmlib::{stindexadm, AdmQueryCmd, StIndexAdmCmd};
 use bin::stindexlib::{stindex, StIndexCmd};
 use bin::stnodelib::{stnode, StNodeCmd};
 use bin::strelaylib::{strelay, StRelayCmd};
@@ -34,6 +37,10 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
         lclient: stctrl_setup.index0_client_addr.parse().unwrap(),
         lserver: stctrl_setup.index0_server_addr.parse().unwrap(),
         trusted: stctrl_setup.temp_dir_path.join("index0").join("trusted"),
+        snapshot: None,
+        graph: GraphBackend::Simple,
+        ladmin: Some(stctrl_setup.index0_admin_addr.parse().unwrap()),
+        admin_remote: false,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -50,6 +57,10 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
         lclient: stctrl_setup.index1_client_addr.parse().unwrap(),
         lserver: stctrl_setup.index1_server_addr.parse().unwrap(),
         trusted: stctrl_setup.temp_dir_path.join("index1").join("trusted"),
+        snapshot: None,
+        graph: GraphBackend::Simple,
+        ladmin: None,
+        admin_remote: false,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -64,6 +75,13 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
             .join("relay0")
             .join("relay0.ident"),
         laddr: stctrl_setup.relay0_addr.parse().unwrap(),
+        max_conns: 16384,
+        max_conns_per_key: 256,
+        max_half_tunnels: 64,
+        max_tunnel_bandwidth: 1_048_576,
+        allowed: None,
+        peers: None,
+        stats_interval: 60,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -78,6 +96,13 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
             .join("relay1")
             .join("relay1.ident"),
         laddr: stctrl_setup.relay1_addr.parse().unwrap(),
+        max_conns: 16384,
+        max_conns_per_key: 256,
+        max_half_tunnels: 64,
+        max_tunnel_bandwidth: 1_048_576,
+        allowed: None,
+        peers: None,
+        stats_interval: 60,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -89,8 +114,10 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
     let st_node_cmd = StNodeCmd {
         idfile: stctrl_setup.temp_dir_path.join("node0").join("node0.ident"),
         laddr: stctrl_setup.node0_addr.clone().parse().unwrap(),
+        ldirect: None,
         database: stctrl_setup.temp_dir_path.join("node0").join("node0.db"),
         trusted: stctrl_setup.temp_dir_path.join("node0").join("trusted"),
+        max_history: None,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -102,8 +129,10 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
     let st_node_cmd = StNodeCmd {
         idfile: stctrl_setup.temp_dir_path.join("node1").join("node1.ident"),
         laddr: stctrl_setup.node1_addr.clone().parse().unwrap(),
+        ldirect: None,
         database: stctrl_setup.temp_dir_path.join("node1").join("node1.db"),
         trusted: stctrl_setup.temp_dir_path.join("node1").join("trusted"),
+        max_history: None,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -270,6 +299,7 @@ fn configure_mutual_credit(stctrl_setup: &StCtrlSetup) {
                 .join(format!("app{}", 1 - j))
                 .join(format!("node{}.friend", 1 - j)),
             friend_name: format!("node{}", 1 - j),
+            currency: "FST".to_owned(),
             balance,
         };
         let config_cmd = ConfigCmd::AddFriend(add_friend_cmd);
@@ -401,6 +431,7 @@ fn send_funds(stctrl_setup: &StCtrlSetup) {
     // node0 sets remote max debt for node1:
     let set_friend_max_debt_cmd = SetFriendMaxDebtCmd {
         friend_name: "node1".to_owned(),
+        currency: "FST".to_owned(),
         max_debt: 200,
     };
     let config_cmd = ConfigCmd::SetFriendMaxDebt(set_friend_max_debt_cmd);
@@ -448,6 +479,7 @@ fn send_funds(stctrl_setup: &StCtrlSetup) {
     // -----------------------------
     let send_funds_cmd = SendFundsCmd {
         destination_str: node0_pk_string,
+        currency: "FST".to_owned(),
         dest_payment: 50,
         opt_receipt_file: Some(
             stctrl_setup
@@ -497,7 +529,7 @@ fn send_funds(stctrl_setup: &StCtrlSetup) {
 
     let mut output = Vec::new();
     stctrl(st_ctrl_cmd.clone(), &mut output).unwrap();
-    assert!(str::from_utf8(&output).unwrap().contains("-70"));
+    assert!(str::from_utf8(&output).unwrap().contains("FST: -70"));
 }
 
 /// Node0: generate an invoice
@@ -511,6 +543,7 @@ fn pay_invoice(stctrl_setup: &StCtrlSetup) {
     // ---------------------------
     let gen_invoice_cmd = GenInvoiceCmd {
         public_key: node0_pk_string,
+        currency: "FST".to_owned(),
         amount: 40,
         output: stctrl_setup
             .temp_dir_path
@@ -600,6 +633,27 @@ fn export_token(stctrl_setup: &StCtrlSetup) {
 }
 
 /// Close requests and disable friends
+/// Query index0 statistics through its admin address
+fn index_admin_stats(stctrl_setup: &StCtrlSetup) {
+    let st_index_adm_cmd = StIndexAdmCmd {
+        address: stctrl_setup.index0_admin_addr.clone(),
+        query: AdmQueryCmd::Stats,
+    };
+
+    // node0 is a client of index0. The edges of node1 are forwarded from index1:
+    loop {
+        let mut output = Vec::new();
+        stindexadm(st_index_adm_cmd.clone(), &mut output).unwrap();
+        let output_string = str::from_utf8(&output).unwrap();
+        assert!(output_string.contains("Trusted servers: 1"));
+        if output_string.contains("Connected clients: 1") && output_string.contains("FST: 2 nodes")
+        {
+            break;
+        }
+        thread::sleep(time::Duration::from_millis(100));
+    }
+}
+
 fn close_disable(stctrl_setup: &StCtrlSetup) {
     // Close friends:
     // ---------------
@@ -722,5 +776,6 @@ fn basic_cli() {
     send_funds(&stctrl_setup);
     pay_invoice(&stctrl_setup);
     export_token(&stctrl_setup);
+    index_admin_stats(&stctrl_setup);
     close_disable(&stctrl_setup);
 }
@@ -36,6 +36,7 @@ pub struct StCtrlSetup {
     pub node1_addr: String,
     pub index0_client_addr: String,
     pub index0_server_addr: String,
+    pub index0_admin_addr: String,
     pub index1_client_addr: String,
     pub index1_server_addr: String,
     pub relay0_addr: String,
@@ -82,7 +83,7 @@ pub fn create_stctrl_setup(temp_dir_path: &Path) -> StCtrlSetup {
     */
 
     // Assign listening addresses for all services:
-    let ports = get_available_ports(8);
+    let ports = get_available_ports(9);
     // TODO: Is there a more generic way to express localhost than 127.0.0.1?
     // We can't use "localhost" because it requires resolving.
     let node0_addr = format!("127.0.0.1:{}", ports[0]);
@@ -93,6 +94,7 @@ pub fn create_stctrl_setup(temp_dir_path: &Path) -> StCtrlSetup {
     let index1_server_addr = format!("127.0.0.1:{}", ports[5]);
     let relay0_addr = format!("127.0.0.1:{}", ports[6]);
     let relay1_addr = format!("127.0.0.1:{}", ports[7]);
+    let index0_admin_addr = format!("127.0.0.1:{}", ports[8]);
 
     // Prepare directories for all entities in the test:
     fs::create_dir(temp_dir_path.join("app0")).unwrap();
@@ -229,6 +231,7 @@ pub fn create_stctrl_setup(temp_dir_path: &Path) -> StCtrlSetup {
         node1_addr,
         index0_client_addr,
         index0_server_addr,
+        index0_admin_addr,
         index1_client_addr,
         index1_server_addr,
         relay0_addr,
@@ -5,7 +5,7 @@ use futures::channel::{mpsc, oneshot};
 use futures::task::{Spawn, SpawnExt};
 use futures::{SinkExt, StreamExt};
 
-use common::conn::{BoxFuture, ConnPairVec, FutTransform};
+use common::conn::{BoxFuture, ConnPairVec, FutTransform, Listener};
 use proto::net::messages::NetAddress;
 
 /// Length of a connection channel.
@@ -123,6 +123,56 @@ impl FutTransform for SimNetworkClient {
     }
 }
 
+/// Listen for connections on the simulated network.
+#[derive(Clone)]
+pub struct SimListener<S> {
+    sim_network_client: SimNetworkClient,
+    spawner: S,
+}
+
+impl<S> SimListener<S> {
+    pub fn new(sim_network_client: SimNetworkClient, spawner: S) -> Self {
+        SimListener {
+            sim_network_client,
+            spawner,
+        }
+    }
+}
+
+impl<S> Listener for SimListener<S>
+where
+    S: Spawn,
+{
+    type Connection = ConnPairVec;
+    type Config = ();
+    type Arg = NetAddress;
+
+    fn listen(
+        mut self,
+        net_address: Self::Arg,
+    ) -> (mpsc::Sender<Self::Config>, mpsc::Receiver<Self::Connection>) {
+        let (config_sender, _config_receiver) = mpsc::channel(0);
+        let (mut conn_sender, conn_receiver) = mpsc::channel(0);
+
+        let mut sim_network_client = self.sim_network_client;
+        let _ = self.spawner.spawn(
+            async move {
+                let mut incoming_conns = match await!(sim_network_client.listen(net_address)) {
+                    Ok(incoming_conns) => incoming_conns,
+                    Err(_) => return,
+                };
+                while let Some(conn_pair) = await!(incoming_conns.next()) {
+                    if await!(conn_sender.send(conn_pair)).is_err() {
+                        return;
+                    }
+                }
+            },
+        );
+
+        (config_sender, conn_receiver)
+    }
+}
+
 #[allow(unused)]
 /// A test util, simulating a network.
 /// Allows clients to listen on certain addresses and try to connect to certain addresses.
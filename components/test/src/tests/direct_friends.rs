@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use futures::channel::mpsc;
+
+use tempfile::tempdir;
+
+use common::test_executor::TestExecutor;
+
+use proto::app_server::messages::AppPermissions;
+use proto::funder::messages::{Currency, CurrencyBalance};
+use timer::create_timer_incoming;
+
+use crate::utils::{
+    advance_time, create_app, create_node, direct_address, is_friend_online, listen_direct_address,
+    node_public_key, SimDb,
+};
+
+use crate::sim_network::create_sim_network;
+
+const TIMER_CHANNEL_LEN: usize = 0;
+
+async fn task_direct_friends(mut test_executor: TestExecutor) {
+    let currency = Currency::try_from("FST".to_owned()).unwrap();
+
+    // Create timer_client:
+    let (mut tick_sender, tick_receiver) = mpsc::channel(TIMER_CHANNEL_LEN);
+    let timer_client = create_timer_incoming(tick_receiver, test_executor.clone()).unwrap();
+
+    // Create a temporary directory.
+    // Should be deleted when gets out of scope:
+    let temp_dir = tempdir().unwrap();
+
+    // Create a database manager at the temporary directory:
+    let sim_db = SimDb::new(temp_dir.path().to_path_buf());
+
+    // A network simulator:
+    let sim_net_client = create_sim_network(&mut test_executor);
+
+    // Create initial database for node 0:
+    sim_db.init_db(0);
+
+    let mut trusted_apps = HashMap::new();
+    trusted_apps.insert(
+        0,
+        AppPermissions {
+            routes: true,
+            send_funds: true,
+            config: true,
+        },
+    );
+
+    let _node0_handle = await!(create_node(
+        0,
+        sim_db.clone(),
+        timer_client.clone(),
+        sim_net_client.clone(),
+        trusted_apps,
+        test_executor.clone()
+    ));
+
+    let mut app0 = await!(create_app(
+        0,
+        sim_net_client.clone(),
+        timer_client.clone(),
+        0,
+        test_executor.clone()
+    ))
+    .unwrap();
+
+    // Create initial database for node 1:
+    sim_db.init_db(1);
+
+    let mut trusted_apps = HashMap::new();
+    trusted_apps.insert(
+        1,
+        AppPermissions {
+            routes: true,
+            send_funds: true,
+            config: true,
+        },
+    );
+    let _node1_handle = await!(create_node(
+        1,
+        sim_db.clone(),
+        timer_client.clone(),
+        sim_net_client.clone(),
+        trusted_apps,
+        test_executor.clone()
+    ));
+
+    let mut app1 = await!(create_app(
+        1,
+        sim_net_client.clone(),
+        timer_client.clone(),
+        1,
+        test_executor.clone()
+    ))
+    .unwrap();
+
+    let mut config0 = app0.config().unwrap().clone();
+    let mut config1 = app1.config().unwrap().clone();
+
+    let mut report0 = app0.report().clone();
+    let mut report1 = app1.report().clone();
+
+    // No relays are used. Every node advertises a direct address:
+    await!(config0.set_direct_address(Some(listen_direct_address(0)))).unwrap();
+    await!(config1.set_direct_address(Some(listen_direct_address(1)))).unwrap();
+
+    // Wait some time:
+    await!(advance_time(40, &mut tick_sender, &test_executor));
+
+    // Node0: Add node1 as a friend:
+    await!(config0.add_friend(
+        node_public_key(1),
+        vec![direct_address(1)],
+        String::from("node1"),
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 100
+        }]
+    ))
+    .unwrap();
+
+    // Node1: Add node0 as a friend:
+    await!(config1.add_friend(
+        node_public_key(0),
+        vec![direct_address(0)],
+        String::from("node0"),
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: -100
+        }]
+    ))
+    .unwrap();
+
+    await!(config0.enable_friend(node_public_key(1))).unwrap();
+    await!(config1.enable_friend(node_public_key(0))).unwrap();
+
+    await!(advance_time(40, &mut tick_sender, &test_executor));
+
+    // The nodes are connected directly:
+    assert!(await!(is_friend_online(&mut report0, 1)));
+    assert!(await!(is_friend_online(&mut report1, 0)));
+}
+
+#[test]
+fn test_direct_friends() {
+    // let _ = env_logger::init();
+    let test_executor = TestExecutor::new();
+    let res = test_executor.run(task_direct_friends(test_executor.clone()));
+    assert!(res.is_output());
+}
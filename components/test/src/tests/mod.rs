@@ -1,3 +1,4 @@
+mod direct_friends;
 mod nodes_chain;
 mod relay_migration;
 mod resolve_inconsistency;
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
 use futures::channel::mpsc;
 
@@ -7,6 +8,7 @@ use tempfile::tempdir;
 use common::test_executor::TestExecutor;
 
 use proto::app_server::messages::AppPermissions;
+use proto::funder::messages::{Currency, CurrencyBalance};
 use timer::create_timer_incoming;
 
 use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
@@ -21,6 +23,8 @@ use crate::utils::{
 const TIMER_CHANNEL_LEN: usize = 0;
 
 async fn task_nodes_chain(mut test_executor: TestExecutor) {
+    let currency = Currency::try_from("FST".to_owned()).unwrap();
+
     // Create a temporary directory.
     // Should be deleted when gets out of scope:
     let temp_dir = tempdir().unwrap();
@@ -196,15 +200,19 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         node_public_key(1),
         vec![relay_address(1)],
         String::from("node1"),
-        0
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 0
+        }]
     ))
     .unwrap();
     await!(apps[0].config().unwrap().enable_friend(node_public_key(1))).unwrap();
     await!(apps[0].config().unwrap().open_friend(node_public_key(1))).unwrap();
-    await!(apps[0]
-        .config()
-        .unwrap()
-        .set_friend_remote_max_debt(node_public_key(1), 100))
+    await!(apps[0].config().unwrap().set_friend_remote_max_debt(
+        node_public_key(1),
+        currency.clone(),
+        100
+    ))
     .unwrap();
 
     // 1 --> 0
@@ -212,15 +220,19 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         node_public_key(0),
         vec![relay_address(1)],
         String::from("node0"),
-        0
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 0
+        }]
     ))
     .unwrap();
     await!(apps[1].config().unwrap().enable_friend(node_public_key(0))).unwrap();
     await!(apps[1].config().unwrap().open_friend(node_public_key(0))).unwrap();
-    await!(apps[1]
-        .config()
-        .unwrap()
-        .set_friend_remote_max_debt(node_public_key(0), 100))
+    await!(apps[1].config().unwrap().set_friend_remote_max_debt(
+        node_public_key(0),
+        currency.clone(),
+        100
+    ))
     .unwrap();
 
     // 1 --> 2
@@ -228,15 +240,19 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         node_public_key(2),
         vec![relay_address(0)],
         String::from("node2"),
-        0
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 0
+        }]
     ))
     .unwrap();
     await!(apps[1].config().unwrap().enable_friend(node_public_key(2))).unwrap();
     await!(apps[1].config().unwrap().open_friend(node_public_key(2))).unwrap();
-    await!(apps[1]
-        .config()
-        .unwrap()
-        .set_friend_remote_max_debt(node_public_key(2), 100))
+    await!(apps[1].config().unwrap().set_friend_remote_max_debt(
+        node_public_key(2),
+        currency.clone(),
+        100
+    ))
     .unwrap();
 
     // 2 --> 1
@@ -244,15 +260,19 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         node_public_key(1),
         vec![relay_address(1)],
         String::from("node1"),
-        0
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 0
+        }]
     ))
     .unwrap();
     await!(apps[2].config().unwrap().enable_friend(node_public_key(1))).unwrap();
     await!(apps[2].config().unwrap().open_friend(node_public_key(1))).unwrap();
-    await!(apps[2]
-        .config()
-        .unwrap()
-        .set_friend_remote_max_debt(node_public_key(1), 100))
+    await!(apps[2].config().unwrap().set_friend_remote_max_debt(
+        node_public_key(1),
+        currency.clone(),
+        100
+    ))
     .unwrap();
 
     // 1 --> 3
@@ -260,15 +280,19 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         node_public_key(3),
         vec![relay_address(0)],
         String::from("node3"),
-        0
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 0
+        }]
     ))
     .unwrap();
     await!(apps[1].config().unwrap().enable_friend(node_public_key(3))).unwrap();
     await!(apps[1].config().unwrap().open_friend(node_public_key(3))).unwrap();
-    await!(apps[1]
-        .config()
-        .unwrap()
-        .set_friend_remote_max_debt(node_public_key(3), 100))
+    await!(apps[1].config().unwrap().set_friend_remote_max_debt(
+        node_public_key(3),
+        currency.clone(),
+        100
+    ))
     .unwrap();
 
     // 3 --> 1
@@ -276,15 +300,19 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         node_public_key(1),
         vec![relay_address(1)],
         String::from("node1"),
-        0
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 0
+        }]
     ))
     .unwrap();
     await!(apps[3].config().unwrap().enable_friend(node_public_key(1))).unwrap();
     await!(apps[3].config().unwrap().open_friend(node_public_key(1))).unwrap();
-    await!(apps[3]
-        .config()
-        .unwrap()
-        .set_friend_remote_max_debt(node_public_key(1), 100))
+    await!(apps[3].config().unwrap().set_friend_remote_max_debt(
+        node_public_key(1),
+        currency.clone(),
+        100
+    ))
     .unwrap();
 
     // 2 --> 5
@@ -292,15 +320,19 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         node_public_key(5),
         vec![relay_address(1)],
         String::from("node5"),
-        0
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 0
+        }]
     ))
     .unwrap();
     await!(apps[2].config().unwrap().enable_friend(node_public_key(5))).unwrap();
     await!(apps[2].config().unwrap().open_friend(node_public_key(5))).unwrap();
-    await!(apps[2]
-        .config()
-        .unwrap()
-        .set_friend_remote_max_debt(node_public_key(5), 100))
+    await!(apps[2].config().unwrap().set_friend_remote_max_debt(
+        node_public_key(5),
+        currency.clone(),
+        100
+    ))
     .unwrap();
 
     // 5 --> 2
@@ -308,15 +340,19 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         node_public_key(2),
         vec![relay_address(0)],
         String::from("node2"),
-        0
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 0
+        }]
     ))
     .unwrap();
     await!(apps[5].config().unwrap().enable_friend(node_public_key(2))).unwrap();
     await!(apps[5].config().unwrap().open_friend(node_public_key(2))).unwrap();
-    await!(apps[5]
-        .config()
-        .unwrap()
-        .set_friend_remote_max_debt(node_public_key(2), 100))
+    await!(apps[5].config().unwrap().set_friend_remote_max_debt(
+        node_public_key(2),
+        currency.clone(),
+        100
+    ))
     .unwrap();
 
     // 2 --> 4
@@ -324,15 +360,19 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         node_public_key(4),
         vec![relay_address(0)],
         String::from("node4"),
-        0
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 0
+        }]
     ))
     .unwrap();
     await!(apps[2].config().unwrap().enable_friend(node_public_key(4))).unwrap();
     await!(apps[2].config().unwrap().open_friend(node_public_key(4))).unwrap();
-    await!(apps[2]
-        .config()
-        .unwrap()
-        .set_friend_remote_max_debt(node_public_key(4), 100))
+    await!(apps[2].config().unwrap().set_friend_remote_max_debt(
+        node_public_key(4),
+        currency.clone(),
+        100
+    ))
     .unwrap();
 
     // 4 --> 2
@@ -342,15 +382,19 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         node_public_key(2),
         vec![relay_address(0), relay_address(1)],
         String::from("node2"),
-        0
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 0
+        }]
     ))
     .unwrap();
     await!(apps[4].config().unwrap().enable_friend(node_public_key(2))).unwrap();
     await!(apps[4].config().unwrap().open_friend(node_public_key(2))).unwrap();
-    await!(apps[4]
-        .config()
-        .unwrap()
-        .set_friend_remote_max_debt(node_public_key(2), 100))
+    await!(apps[4].config().unwrap().set_friend_remote_max_debt(
+        node_public_key(2),
+        currency.clone(),
+        100
+    ))
     .unwrap();
 
     // Wait some time:
@@ -370,7 +414,9 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         20,
         node_public_key(0),
         node_public_key(4),
-        None
+        None,
+        currency.clone(),
+        1
     ))
     .unwrap();
 
@@ -379,7 +425,10 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
     // Node0: Send 10 credits to Node1:
     let chosen_route_with_capacity = routes_0_4.pop().unwrap();
     assert_eq!(chosen_route_with_capacity.capacity, 100);
+    let fees = chosen_route_with_capacity.fees;
     let chosen_route = chosen_route_with_capacity.route;
+    // Every mediator on the route takes a fee of 1 credit:
+    assert_eq!(fees, (chosen_route.len() - 2) as u128);
 
     let request_id = Uid::from(&[0x0; UID_LEN]);
     let invoice_id = InvoiceId::from(&[0; INVOICE_ID_LEN]);
@@ -387,8 +436,10 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
     let receipt = await!(apps[0].send_funds().unwrap().request_send_funds(
         request_id.clone(),
         chosen_route,
+        currency.clone(),
         invoice_id,
-        dest_payment
+        dest_payment,
+        fees
     ))
     .unwrap();
     await!(apps[0]
@@ -405,7 +456,9 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         20,
         node_public_key(5),
         node_public_key(3),
-        None
+        None,
+        currency.clone(),
+        1
     ))
     .unwrap();
 
@@ -413,6 +466,7 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
 
     // Node5: Send 10 credits to Node3:
     let chosen_route_with_capacity = routes_5_3.pop().unwrap();
+    let fees = chosen_route_with_capacity.fees;
     let chosen_route = chosen_route_with_capacity.route;
 
     let request_id = Uid::from(&[0x1; UID_LEN]);
@@ -421,8 +475,10 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
     let receipt = await!(apps[5].send_funds().unwrap().request_send_funds(
         request_id.clone(),
         chosen_route,
+        currency.clone(),
         invoice_id,
-        dest_payment
+        dest_payment,
+        fees
     ))
     .unwrap();
     await!(apps[5]
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
 use futures::channel::mpsc;
 
@@ -7,37 +8,21 @@ use tempfile::tempdir;
 use common::test_executor::TestExecutor;
 
 use proto::app_server::messages::AppPermissions;
+use proto::funder::messages::{Currency, CurrencyBalance};
 use timer::create_timer_incoming;
 
 use crate::utils::{
-    advance_time, create_app, create_node, create_relay, named_relay_address, node_public_key,
-    relay_address, relay_public_key, SimDb,
+    advance_time, create_app, create_node, create_relay, is_friend_online, named_relay_address,
+    node_public_key, relay_address, relay_public_key, SimDb,
 };
 
-use node::connect::AppReport;
-
 use crate::sim_network::create_sim_network;
 
 const TIMER_CHANNEL_LEN: usize = 0;
 
-/// Checks if a friend is online
-/// panics if the friend does not exist.
-async fn is_friend_online(report: &mut AppReport, index: u8) -> bool {
-    let (node_report, mutations_receiver) = await!(report.incoming_reports()).unwrap();
-    drop(mutations_receiver);
-
-    let friend_report = match node_report
-        .funder_report
-        .friends
-        .get(&node_public_key(index))
-    {
-        None => unreachable!(),
-        Some(friend_report) => friend_report,
-    };
-    friend_report.liveness.is_online()
-}
-
 async fn task_relay_migration(mut test_executor: TestExecutor) {
+    let currency = Currency::try_from("FST".to_owned()).unwrap();
+
     // Create timer_client:
     let (mut tick_sender, tick_receiver) = mpsc::channel(TIMER_CHANNEL_LEN);
     let timer_client = create_timer_incoming(tick_receiver, test_executor.clone()).unwrap();
@@ -167,7 +152,10 @@ async fn task_relay_migration(mut test_executor: TestExecutor) {
         node_public_key(1),
         vec![relay_address(1)],
         String::from("node1"),
-        100
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 100
+        }]
     ))
     .unwrap();
 
@@ -176,7 +164,10 @@ async fn task_relay_migration(mut test_executor: TestExecutor) {
         node_public_key(0),
         vec![relay_address(0)],
         String::from("node0"),
-        -100
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: -100
+        }]
     ))
     .unwrap();
 
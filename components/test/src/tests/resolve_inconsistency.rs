@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
 use futures::channel::mpsc;
 
@@ -7,6 +8,7 @@ use tempfile::tempdir;
 use common::test_executor::TestExecutor;
 
 use proto::app_server::messages::AppPermissions;
+use proto::funder::messages::{Currency, CurrencyBalance};
 use proto::report::messages::ChannelStatusReport;
 use timer::create_timer_incoming;
 
@@ -20,6 +22,8 @@ use crate::sim_network::create_sim_network;
 const TIMER_CHANNEL_LEN: usize = 0;
 
 async fn task_resolve_inconsistency(mut test_executor: TestExecutor) {
+    let currency = Currency::try_from("FST".to_owned()).unwrap();
+
     // Create timer_client:
     let (mut tick_sender, tick_receiver) = mpsc::channel(TIMER_CHANNEL_LEN);
     let timer_client = create_timer_incoming(tick_receiver, test_executor.clone()).unwrap();
@@ -130,7 +134,10 @@ async fn task_resolve_inconsistency(mut test_executor: TestExecutor) {
         node_public_key(1),
         vec![relay_address(1)],
         String::from("node1"),
-        100
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 100
+        }]
     ))
     .unwrap();
 
@@ -139,7 +146,10 @@ async fn task_resolve_inconsistency(mut test_executor: TestExecutor) {
         node_public_key(0),
         vec![relay_address(0)],
         String::from("node0"),
-        -100
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: -100
+        }]
     ))
     .unwrap();
 
@@ -163,7 +173,10 @@ async fn task_resolve_inconsistency(mut test_executor: TestExecutor) {
         node_public_key(1),
         vec![relay_address(1)],
         String::from("node1"),
-        50
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 50
+        }]
     ))
     .unwrap();
 
@@ -188,9 +201,21 @@ async fn task_resolve_inconsistency(mut test_executor: TestExecutor) {
             }
         };
 
-        assert_eq!(incon_report.local_reset_terms_balance, -100);
+        assert_eq!(
+            incon_report.local_reset_terms_balance,
+            vec![CurrencyBalance {
+                currency: currency.clone(),
+                balance: -100
+            }]
+        );
         let remote_reset_terms = incon_report.opt_remote_reset_terms.clone().unwrap();
-        assert_eq!(remote_reset_terms.balance_for_reset, 50);
+        assert_eq!(
+            remote_reset_terms.balance_for_reset,
+            vec![CurrencyBalance {
+                currency: currency.clone(),
+                balance: 50
+            }]
+        );
     }
 
     let (node_report, _) = await!(report0.incoming_reports()).unwrap();
@@ -207,9 +232,21 @@ async fn task_resolve_inconsistency(mut test_executor: TestExecutor) {
         }
     };
 
-    assert_eq!(incon_report.local_reset_terms_balance, 50);
+    assert_eq!(
+        incon_report.local_reset_terms_balance,
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 50
+        }]
+    );
     let remote_reset_terms = incon_report.opt_remote_reset_terms.clone().unwrap();
-    assert_eq!(remote_reset_terms.balance_for_reset, -100);
+    assert_eq!(
+        remote_reset_terms.balance_for_reset,
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: -100
+        }]
+    );
 
     let reset_token = remote_reset_terms.reset_token.clone();
 
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
 use futures::channel::mpsc;
 use futures::StreamExt;
@@ -8,6 +9,7 @@ use tempfile::tempdir;
 use common::test_executor::TestExecutor;
 
 use proto::app_server::messages::AppPermissions;
+use proto::funder::messages::{Currency, CurrencyBalance};
 use timer::create_timer_incoming;
 
 use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
@@ -22,6 +24,8 @@ use crate::utils::{
 const TIMER_CHANNEL_LEN: usize = 0;
 
 async fn task_two_nodes_payment(mut test_executor: TestExecutor) {
+    let currency = Currency::try_from("FST".to_owned()).unwrap();
+
     // Create timer_client:
     let (mut tick_sender, tick_receiver) = mpsc::channel(TIMER_CHANNEL_LEN);
     let timer_client = create_timer_incoming(tick_receiver, test_executor.clone()).unwrap();
@@ -180,7 +184,10 @@ async fn task_two_nodes_payment(mut test_executor: TestExecutor) {
         node_public_key(1),
         vec![relay_address(1)],
         String::from("node1"),
-        100
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: 100
+        }]
     ))
     .unwrap();
 
@@ -189,7 +196,10 @@ async fn task_two_nodes_payment(mut test_executor: TestExecutor) {
         node_public_key(0),
         vec![relay_address(0)],
         String::from("node0"),
-        -100
+        vec![CurrencyBalance {
+            currency: currency.clone(),
+            balance: -100
+        }]
     ))
     .unwrap();
 
@@ -252,8 +262,15 @@ async fn task_two_nodes_payment(mut test_executor: TestExecutor) {
 
     // Node0: Send 10 credits to Node1:
     // Node0: Request routes:
-    let mut routes_0_1 =
-        await!(routes0.request_routes(20, node_public_key(0), node_public_key(1), None)).unwrap();
+    let mut routes_0_1 = await!(routes0.request_routes(
+        20,
+        node_public_key(0),
+        node_public_key(1),
+        None,
+        currency.clone(),
+        1
+    ))
+    .unwrap();
 
     assert_eq!(routes_0_1.len(), 1);
     let chosen_route_with_capacity = routes_0_1.pop().unwrap();
@@ -266,22 +283,31 @@ async fn task_two_nodes_payment(mut test_executor: TestExecutor) {
     let receipt = await!(send_funds0.request_send_funds(
         request_id.clone(),
         chosen_route,
+        currency.clone(),
         invoice_id,
-        dest_payment
+        dest_payment,
+        0 // No fees: There are no mediators on a route of two nodes
     ))
     .unwrap();
     await!(send_funds0.receipt_ack(request_id, receipt.clone())).unwrap();
 
     // Node0 allows node1 to have maximum debt of 100
     // (This should allow to node1 to pay back).
-    await!(config0.set_friend_remote_max_debt(node_public_key(1), 100)).unwrap();
+    await!(config0.set_friend_remote_max_debt(node_public_key(1), currency.clone(), 100)).unwrap();
 
     // Allow some time for the index servers to be updated about the new state:
     await!(advance_time(40, &mut tick_sender, &test_executor));
 
     // Node1: Send 5 credits to Node0:
-    let mut routes_1_0 =
-        await!(routes1.request_routes(10, node_public_key(1), node_public_key(0), None)).unwrap();
+    let mut routes_1_0 = await!(routes1.request_routes(
+        10,
+        node_public_key(1),
+        node_public_key(0),
+        None,
+        currency.clone(),
+        1
+    ))
+    .unwrap();
 
     assert_eq!(routes_1_0.len(), 1);
     let chosen_route_with_capacity = routes_1_0.pop().unwrap();
@@ -294,8 +320,10 @@ async fn task_two_nodes_payment(mut test_executor: TestExecutor) {
     let receipt = await!(send_funds1.request_send_funds(
         request_id,
         chosen_route.clone(),
+        currency.clone(),
         invoice_id.clone(),
-        dest_payment
+        dest_payment,
+        0
     ))
     .unwrap();
     await!(send_funds1.receipt_ack(request_id, receipt.clone())).unwrap();
@@ -308,8 +336,10 @@ async fn task_two_nodes_payment(mut test_executor: TestExecutor) {
     let res = await!(send_funds1.request_send_funds(
         request_id,
         chosen_route.clone(),
+        currency.clone(),
         invoice_id,
-        dest_payment
+        dest_payment,
+        0
     ));
     assert!(res.is_err());
 }
@@ -4,7 +4,7 @@ use std::path::PathBuf;
 use futures::channel::mpsc;
 use futures::future::RemoteHandle;
 use futures::task::{Spawn, SpawnExt};
-use futures::{future, FutureExt, SinkExt, TryFutureExt};
+use futures::{future, stream, FutureExt, SinkExt, TryFutureExt};
 
 use crypto::identity::{generate_pkcs8_key_pair, Identity, PublicKey, SoftwareEd25519Identity};
 
@@ -13,24 +13,26 @@ use crypto::test_utils::DummyRandom;
 
 use common::test_executor::TestExecutor;
 
-use proto::app_server::messages::{AppPermissions, NamedRelayAddress, RelayAddress};
+use proto::app_server::messages::{
+    AppPermissions, ChannelAddress, NamedRelayAddress, RelayAddress,
+};
 use proto::consts::{KEEPALIVE_TICKS, MAX_NODE_RELAYS, MAX_OPERATIONS_IN_BATCH, TICKS_TO_REKEY};
 use proto::index_server::messages::NamedIndexServerAddress;
 use proto::net::messages::NetAddress;
 
 use identity::{create_identity, IdentityClient};
 
-use node::connect::{node_connect, NodeConnection};
+use node::connect::{node_connect, AppReport, NodeConnection};
 use node::{net_node, NodeConfig, NodeState};
 
 use database::file_db::FileDb;
 
-use index_server::net_index_server;
-use relay::net_relay_server;
+use index_server::{net_index_server, ClientLimits, GraphBackend};
+use relay::{net_relay_server, RelayLimits};
 
 use timer::TimerClient;
 
-use crate::sim_network::{net_address, SimNetworkClient};
+use crate::sim_network::{net_address, SimListener, SimNetworkClient};
 
 /// Memory allocated to a channel in memory (Used to connect two components)
 const CHANNEL_LEN: usize = 0x20;
@@ -138,6 +140,8 @@ fn default_node_config() -> NodeConfig {
         max_operations_in_batch: MAX_OPERATIONS_IN_BATCH,
         /// The size we allocate for the user send funds requests queue.
         max_pending_user_requests: MAX_PENDING_USER_REQUESTS,
+        /// Keep all the records of the payment history.
+        opt_max_payment_history: None,
         /// Maximum amount of concurrent index client requests:
         max_open_index_client_requests: MAX_OPEN_INDEX_CLIENT_REQUESTS,
         /// Maximum amount of relays a node may use.
@@ -202,11 +206,19 @@ pub fn named_relay_address(index: u8) -> NamedRelayAddress {
     }
 }
 
-pub fn relay_address(index: u8) -> RelayAddress {
-    RelayAddress {
+pub fn relay_address(index: u8) -> ChannelAddress {
+    ChannelAddress::Relay(RelayAddress {
         public_key: get_relay_identity(index).get_public_key(),
         address: listen_relay_address(index),
-    }
+    })
+}
+
+pub fn listen_direct_address(index: u8) -> NetAddress {
+    net_address(&format!("node_direct_{}", index))
+}
+
+pub fn direct_address(index: u8) -> ChannelAddress {
+    ChannelAddress::Direct(listen_direct_address(index))
 }
 
 pub fn named_index_server_address(index: u8) -> NamedIndexServerAddress {
@@ -279,9 +291,11 @@ where
     let rng = DummyRandom::new(&[0xff, 0x13, 0x37, index]);
     // Note: we use the same spawner for testing purposes.
     // Simulating the passage of time becomes more difficult if our code uses a few different executors.
+    let sim_listener = SimListener::new(sim_network_client.clone(), spawner.clone());
     let net_node_fut = net_node(
         incoming_app_raw_conns,
         sim_network_client,
+        sim_listener,
         timer_client,
         identity_client,
         rng,
@@ -328,19 +342,33 @@ pub async fn create_index_server<S>(
         })
         .collect::<HashMap<_, _>>();
 
+    // Clients in the tests never send enough requests to be limited:
+    let client_limits = ClientLimits {
+        max_request_tokens: 0x100,
+        request_refill_tokens: 0x100,
+        max_mutations_tokens: 0x100,
+        mutations_refill_tokens: 0x100,
+        max_open_requests: 0x100,
+    };
+
     let rng = DummyRandom::new(&[0xff, 0x13, 0x38, index]);
     // We use the same spawner for both required spawners.
     // We do this to make it easier to simulate the passage of time in tests.
     let net_index_server_fut = net_index_server(
         incoming_client_raw_conns,
         incoming_server_raw_conns,
+        stream::empty(), // No admin connections
         sim_network_client,
         identity_client,
         timer_client,
         rng,
         trusted_servers,
+        stream::empty(), // Trusted servers never change
         MAX_CONCURRENT_ENCRYPT,
         BACKOFF_TICKS,
+        client_limits,
+        None, // No graph snapshots
+        GraphBackend::Simple,
         spawner.clone(),
         spawner.clone(),
     )
@@ -364,13 +392,29 @@ pub async fn create_relay<S>(
     let listen_address = listen_relay_address(index);
     let incoming_raw_conns = await!(sim_network_client.listen(listen_address)).unwrap();
 
+    // Nodes in the tests never use enough resources to be limited:
+    let relay_limits = RelayLimits {
+        max_conns: 0x100,
+        max_conns_per_public_key: 0x100,
+        max_half_tunnels: 0x100,
+        max_tunnel_bytes_per_tick: usize::max_value(),
+    };
+
     let rng = DummyRandom::new(&[0xff, 0x13, 0x39, index]);
     let net_relay_server_fut = net_relay_server(
         incoming_raw_conns,
+        sim_network_client,
+        None,            // All nodes may use the relay
+        stream::empty(), // Allowed nodes never change
+        HashMap::new(),  // No peer relays
         identity_client,
         timer_client,
         rng,
         MAX_CONCURRENT_ENCRYPT,
+        relay_limits,
+        BACKOFF_TICKS,
+        0x10,
+        mpsc::channel(0).0, // Statistics reports are discarded
         spawner.clone(),
     )
     .map_err(|e| error!("net_relay_server() error: {:?}", e))
@@ -390,3 +434,20 @@ pub async fn advance_time<'a>(
         await!(test_executor.wait());
     }
 }
+
+/// Checks if a friend is online
+/// panics if the friend does not exist.
+pub async fn is_friend_online(report: &mut AppReport, index: u8) -> bool {
+    let (node_report, mutations_receiver) = await!(report.incoming_reports()).unwrap();
+    drop(mutations_receiver);
+
+    let friend_report = match node_report
+        .funder_report
+        .friends
+        .get(&node_public_key(index))
+    {
+        None => unreachable!(),
+        Some(friend_report) => friend_report,
+    };
+    friend_report.liveness.is_online()
+}